@@ -24,16 +24,18 @@ use std::fs::OpenOptions;
 use std::io::Write as IoWrite;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-/// Action types for logging
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Action types for logging (also used as webhook event types)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ActionType {
     CommentCreated,
     CommentEdited,
     CommentDeleted,
     CommentVoted,
+    CommentReacted,
     ReportCreated,
     UserBanned,
     UserUnbanned,
@@ -43,6 +45,35 @@ pub enum ActionType {
     MediaUploaded,
     UserRegistered,
     OauthLogin,
+    QuotaWarning,
+    QuotaExceeded,
+    /// An import gave comments to an account whose email was never verified
+    ImportUnverifiedMatch,
+}
+
+impl ActionType {
+    /// Event name as serialized (e.g. "comment_created"), used in webhook headers
+    pub fn event_name(&self) -> &'static str {
+        match self {
+            ActionType::CommentCreated => "comment_created",
+            ActionType::CommentEdited => "comment_edited",
+            ActionType::CommentDeleted => "comment_deleted",
+            ActionType::CommentVoted => "comment_voted",
+            ActionType::CommentReacted => "comment_reacted",
+            ActionType::ReportCreated => "report_created",
+            ActionType::UserBanned => "user_banned",
+            ActionType::UserUnbanned => "user_unbanned",
+            ActionType::UserShadowbanned => "user_shadowbanned",
+            ActionType::CommentApproved => "comment_approved",
+            ActionType::CommentRejected => "comment_rejected",
+            ActionType::MediaUploaded => "media_uploaded",
+            ActionType::UserRegistered => "user_registered",
+            ActionType::OauthLogin => "oauth_login",
+            ActionType::QuotaWarning => "quota_warning",
+            ActionType::QuotaExceeded => "quota_exceeded",
+            ActionType::ImportUnverifiedMatch => "import_unverified_match",
+        }
+    }
 }
 
 impl std::fmt::Display for ActionType {
@@ -52,6 +83,7 @@ impl std::fmt::Display for ActionType {
             ActionType::CommentEdited => write!(f, "EDIT"),
             ActionType::CommentDeleted => write!(f, "DELETE"),
             ActionType::CommentVoted => write!(f, "VOTE"),
+            ActionType::CommentReacted => write!(f, "REACT"),
             ActionType::ReportCreated => write!(f, "REPORT"),
             ActionType::UserBanned => write!(f, "BAN"),
             ActionType::UserUnbanned => write!(f, "UNBAN"),
@@ -61,6 +93,9 @@ impl std::fmt::Display for ActionType {
             ActionType::MediaUploaded => write!(f, "MEDIA"),
             ActionType::UserRegistered => write!(f, "REGISTER"),
             ActionType::OauthLogin => write!(f, "OAUTH"),
+            ActionType::QuotaWarning => write!(f, "QUOTA_WARNING"),
+            ActionType::QuotaExceeded => write!(f, "QUOTA_EXCEEDED"),
+            ActionType::ImportUnverifiedMatch => write!(f, "IMPORT_UNVERIFIED"),
         }
     }
 }
@@ -79,6 +114,9 @@ pub struct ActionLog {
     pub content_preview: Option<String>,
     pub ip: Option<String>,
     pub user_agent: Option<String>,
+    /// Scoped API token that performed the action (instead of a signed-in user)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_token_id: Option<Uuid>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<serde_json::Value>,
 }
@@ -129,10 +167,11 @@ impl ActionLogger {
     /// Write concise debug log in format:
     /// [ACTION] [user_id] [comment_id] | content or details
     fn log_debug(&self, entry: &ActionLog) {
-        let user_id = entry
-            .user_id
-            .map(|id| id.to_string())
-            .unwrap_or_else(|| "anonymous".to_string());
+        let user_id = match (entry.user_id, entry.api_token_id) {
+            (Some(id), _) => id.to_string(),
+            (None, Some(token_id)) => format!("token:{}", token_id),
+            (None, None) => "anonymous".to_string(),
+        };
 
         let comment_id = entry
             .comment_id
@@ -154,6 +193,14 @@ impl ActionLogger {
                     .unwrap_or("?")
                     .to_string()
             }
+            ActionType::CommentReacted => {
+                entry.metadata
+                    .as_ref()
+                    .and_then(|m| m.get("emoji"))
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("?")
+                    .to_string()
+            }
             ActionType::CommentDeleted => {
                 // For deletes, just show the comment_id (already in the format)
                 "deleted".to_string()
@@ -174,6 +221,14 @@ impl ActionLogger {
                     .unwrap_or("?")
                     .to_string()
             }
+            ActionType::QuotaWarning | ActionType::QuotaExceeded => {
+                entry.metadata
+                    .as_ref()
+                    .and_then(|m| m.get("metric"))
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("?")
+                    .to_string()
+            }
             _ => "-".to_string(),
         };
 
@@ -208,6 +263,7 @@ pub struct ActionLogBuilder {
     content_preview: Option<String>,
     ip: Option<String>,
     user_agent: Option<String>,
+    api_token_id: Option<Uuid>,
     metadata: Option<serde_json::Value>,
 }
 
@@ -224,6 +280,7 @@ impl ActionLogBuilder {
             content_preview: None,
             ip: None,
             user_agent: None,
+            api_token_id: None,
             metadata: None,
         }
     }
@@ -274,6 +331,11 @@ impl ActionLogBuilder {
         self
     }
 
+    pub fn api_token_id(mut self, id: Uuid) -> Self {
+        self.api_token_id = Some(id);
+        self
+    }
+
     pub fn metadata(mut self, meta: serde_json::Value) -> Self {
         self.metadata = Some(meta);
         self
@@ -292,6 +354,7 @@ impl ActionLogBuilder {
             content_preview: self.content_preview,
             ip: self.ip,
             user_agent: self.user_agent,
+            api_token_id: self.api_token_id,
             metadata: self.metadata,
         }
     }
@@ -0,0 +1,112 @@
+//! Scoped API tokens for server-to-server integrations
+//!
+//! Tokens (`tk_tok_...`) are shown once when created and stored as their SHA-256 hash,
+//! so a Redis dump doesn't leak usable credentials. They're 32 random alphanumerics, so
+//! an unsalted hash is enough. A token can be limited to client IPs and CIDR ranges.
+
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+
+/// Storage key of a token: hex SHA-256
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// An IP allowlist entry: a single address or a CIDR range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpRange {
+    /// Parse `203.0.113.7`, `203.0.113.0/24` or `2001:db8::/32`
+    pub fn parse(entry: &str) -> Option<Self> {
+        let (addr, prefix_len) = match entry.trim().split_once('/') {
+            Some((addr, len)) => (addr.parse::<IpAddr>().ok()?, Some(len.parse::<u8>().ok()?)),
+            None => (entry.trim().parse::<IpAddr>().ok()?, None),
+        };
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = prefix_len.unwrap_or(max_len);
+        (prefix_len <= max_len).then_some(Self { network: addr, prefix_len })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 clients can show up as IPv4-mapped IPv6 addresses
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            ip => ip,
+        };
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                prefix_matches(u32::from(network) as u128, u32::from(ip) as u128, 32, self.prefix_len)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                prefix_matches(u128::from(network), u128::from(ip), 128, self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(network: u128, ip: u128, bits: u8, prefix_len: u8) -> bool {
+    if prefix_len == 0 {
+        return true;
+    }
+    let shift = bits - prefix_len;
+    network >> shift == ip >> shift
+}
+
+/// Whether `ip` may use a token with this allowlist (an empty list allows any IP)
+///
+/// An unknown client IP is only allowed without an allowlist.
+pub fn ip_allowed(allowed_ips: &[String], ip: Option<&str>) -> bool {
+    if allowed_ips.is_empty() {
+        return true;
+    }
+    let Some(ip) = ip.and_then(|ip| ip.trim().parse::<IpAddr>().ok()) else {
+        return false;
+    };
+    allowed_ips
+        .iter()
+        .filter_map(|entry| IpRange::parse(entry))
+        .any(|range| range.contains(ip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_token() {
+        let hash = hash_token("tk_tok_abc");
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, hash_token("tk_tok_abc"));
+        assert_ne!(hash, hash_token("tk_tok_abd"));
+    }
+
+    #[test]
+    fn test_ip_range_parse() {
+        assert!(IpRange::parse("203.0.113.7").is_some());
+        assert!(IpRange::parse("203.0.113.0/24").is_some());
+        assert!(IpRange::parse("2001:db8::/32").is_some());
+        assert!(IpRange::parse("203.0.113.0/33").is_none());
+        assert!(IpRange::parse("example.com").is_none());
+        assert!(IpRange::parse("203.0.113.0/").is_none());
+    }
+
+    #[test]
+    fn test_ip_allowed() {
+        let allowlist = vec!["203.0.113.0/24".to_string(), "2001:db8::1".to_string()];
+        assert!(ip_allowed(&allowlist, Some("203.0.113.42")));
+        assert!(ip_allowed(&allowlist, Some("::ffff:203.0.113.42")));
+        assert!(!ip_allowed(&allowlist, Some("198.51.100.1")));
+        assert!(ip_allowed(&allowlist, Some("2001:db8::1")));
+        assert!(!ip_allowed(&allowlist, Some("2001:db8::2")));
+        assert!(!ip_allowed(&allowlist, None));
+
+        // No allowlist: any client, even an unknown one
+        assert!(ip_allowed(&[], None));
+        assert!(ip_allowed(&["0.0.0.0/0".to_string()], Some("198.51.100.1")));
+    }
+}
@@ -5,6 +5,16 @@ use uuid::Uuid;
 
 use crate::{Error, Result};
 
+/// Kind of JWT
+///
+/// Only access tokens are JWTs (refresh tokens are opaque, see [`generate_refresh_token`]).
+/// Tokens without the claim are rejected, so the old JWT refresh tokens stop working.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenType {
+    Access,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: Uuid,        // user_id
@@ -12,6 +22,7 @@ pub struct Claims {
     pub exp: i64,         // expiry timestamp
     pub iat: i64,         // issued at
     pub session_id: Uuid, // for token revocation
+    pub typ: TokenType,
 }
 
 pub fn create_token(
@@ -30,6 +41,7 @@ pub fn create_token(
         exp: expiry.timestamp(),
         iat: now.timestamp(),
         session_id,
+        typ: TokenType::Access,
     };
 
     encode(
@@ -56,6 +68,50 @@ pub fn generate_verification_code() -> String {
     format!("{:06}", rng.gen_range(0..1_000_000))
 }
 
+/// `len` random lowercase alphanumerics from the OS RNG, for secrets
+pub fn random_alphanumeric(len: usize) -> String {
+    use rand::{rngs::OsRng, Rng};
+    const CHARSET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    (0..len)
+        .map(|_| CHARSET[OsRng.gen_range(0..CHARSET.len())] as char)
+        .collect()
+}
+
+/// Generate an opaque refresh token for a session: `{session_id}.{32 random alphanumerics}`
+///
+/// Only its hash is stored, and it's replaced on every use.
+pub fn generate_refresh_token(session_id: Uuid) -> String {
+    format!("{}.{}", session_id, random_alphanumeric(32))
+}
+
+/// Generate an opaque single-use ticket for opening an event stream (32 random alphanumerics)
+pub fn generate_stream_ticket() -> String {
+    random_alphanumeric(32)
+}
+
+/// Session a refresh token belongs to, if it's well-formed
+pub fn parse_refresh_token(token: &str) -> Option<Uuid> {
+    let (session_id, secret) = token.split_once('.')?;
+    if secret.len() != 32 {
+        return None;
+    }
+    session_id.parse().ok()
+}
+
+/// Prefix of public API keys (safe to embed in pages)
+pub const PUBLIC_KEY_PREFIX: &str = "tk_pub_";
+
+/// Prefix of secret API keys (server-side only)
+pub const SECRET_KEY_PREFIX: &str = "tk_sec_";
+
+/// Prefix of scoped API tokens (server-side only)
+pub const API_TOKEN_PREFIX: &str = "tk_tok_";
+
+/// Generate an API key: the prefix followed by 32 random lowercase alphanumerics
+pub fn generate_api_key(prefix: &str) -> String {
+    format!("{}{}", prefix, random_alphanumeric(32))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -73,5 +129,54 @@ mod tests {
         assert_eq!(claims.sub, user_id);
         assert_eq!(claims.site_id, site_id);
         assert_eq!(claims.session_id, session_id);
+        assert_eq!(claims.typ, TokenType::Access);
+    }
+
+    #[test]
+    fn test_token_without_type_rejected() {
+        #[derive(Serialize)]
+        struct UntypedClaims {
+            sub: Uuid,
+            site_id: Uuid,
+            exp: i64,
+            iat: i64,
+            session_id: Uuid,
+        }
+
+        let secret = "test_secret";
+        let now = Utc::now();
+        let claims = UntypedClaims {
+            sub: Uuid::now_v7(),
+            site_id: Uuid::now_v7(),
+            exp: (now + Duration::hours(1)).timestamp(),
+            iat: now.timestamp(),
+            session_id: Uuid::now_v7(),
+        };
+        let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_bytes())).unwrap();
+
+        assert!(verify_token(&token, secret).is_err());
+    }
+
+    #[test]
+    fn test_refresh_token_format() {
+        let session_id = Uuid::now_v7();
+        let token = generate_refresh_token(session_id);
+
+        assert_eq!(parse_refresh_token(&token), Some(session_id));
+        assert_ne!(token, generate_refresh_token(session_id));
+        assert!(parse_refresh_token("invalid_token").is_none());
+        assert!(parse_refresh_token(&format!("{}.short", session_id)).is_none());
+        // A JWT is not a refresh token
+        let jwt = create_token(Uuid::now_v7(), Uuid::now_v7(), session_id, "secret", 1).unwrap();
+        assert!(parse_refresh_token(&jwt).is_none());
+    }
+
+    #[test]
+    fn test_generate_api_key() {
+        let key = generate_api_key(SECRET_KEY_PREFIX);
+        assert!(key.starts_with("tk_sec_"));
+        assert_eq!(key.len(), SECRET_KEY_PREFIX.len() + 32);
+        assert!(key[SECRET_KEY_PREFIX.len()..].chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()));
+        assert_ne!(key, generate_api_key(SECRET_KEY_PREFIX));
     }
 }
@@ -0,0 +1,1042 @@
+//! Comment write operations shared by the HTTP API and the WebSocket server
+//!
+//! Posting, editing, deleting and voting go through [`CommentService`] so both transports
+//! apply the same validation, moderation, Turnstile and shadowban rules, write the page tree
+//! the same way, and publish the same events. Callers translate [`CommentError`] into their
+//! own error format (an HTTP status, or a JSON-RPC error object).
+
+use chrono::Utc;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::action_log::{ActionLogBuilder, ActionLogger, ActionType};
+use crate::config::Config;
+use crate::markdown::render_markdown_with_mentions;
+use crate::mentions::{resolve_mentions, Mention};
+use crate::moderation::{ModerationCheckResult, ModerationClient};
+use crate::notification_emails;
+use crate::quotas::{QuotaExceeded, QuotaMetric, Quotas, COMMENTS_FIELD, MODERATION_CHECKS_FIELD};
+use crate::redis::RedisClient;
+use crate::store::Storage;
+use crate::turnstile::verify_with_cloudflare;
+use crate::types::{
+    CommentRevision, CommentStatus, CreateCommentRequest, DeleteRequest, ModerationAction,
+    ModerationMode, Notification, NotificationType, ProjectIdInfo, Role, TreeComment,
+    TurnstileEnforcement, UpdateCommentRequest, VoteDirection, VoteRequest, VoteResponse,
+    ANONYMOUS_USER_ID, DELETED_USER_ID,
+};
+use crate::webhooks::WebhookDispatcher;
+
+/// Why a comment operation was refused
+#[derive(Debug, Error)]
+pub enum CommentError {
+    #[error("{0}")]
+    BadRequest(String),
+
+    #[error("{0}")]
+    Unauthorized(String),
+
+    #[error("{0}")]
+    Forbidden(String),
+
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("{0}")]
+    PaymentRequired(String),
+
+    #[error("{0}")]
+    TooManyRequests(String),
+
+    #[error("{0}")]
+    Unavailable(String),
+
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl CommentError {
+    /// HTTP status code for this error
+    pub fn status_code(&self) -> u16 {
+        match self {
+            CommentError::BadRequest(_) => 400,
+            CommentError::Unauthorized(_) => 401,
+            CommentError::Forbidden(_) => 403,
+            CommentError::NotFound(_) => 404,
+            CommentError::PaymentRequired(_) => 402,
+            CommentError::TooManyRequests(_) => 429,
+            CommentError::Unavailable(_) => 503,
+            CommentError::Internal(_) => 500,
+        }
+    }
+}
+
+impl From<QuotaExceeded> for CommentError {
+    fn from(e: QuotaExceeded) -> Self {
+        match e.status_code() {
+            429 => CommentError::TooManyRequests(e.to_string()),
+            _ => CommentError::PaymentRequired(e.to_string()),
+        }
+    }
+}
+
+impl From<crate::Error> for CommentError {
+    fn from(e: crate::Error) -> Self {
+        CommentError::Internal(e.to_string())
+    }
+}
+
+/// The user (or anonymous visitor) making a request, and where it came from
+#[derive(Debug, Clone)]
+pub struct CommentActor {
+    /// None for anonymous visitors
+    pub user_id: Option<Uuid>,
+    pub role: Role,
+    pub username_set: bool,
+    /// Client IP (`X-Forwarded-For`), used for Turnstile and the action log
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// Turnstile token, required when the site enforces Turnstile for this actor
+    pub turnstile_token: Option<String>,
+}
+
+impl CommentActor {
+    fn require_user(&self) -> Result<Uuid, CommentError> {
+        let user_id = self
+            .user_id
+            .ok_or_else(|| CommentError::Unauthorized("Authentication required".into()))?;
+
+        if !self.username_set {
+            return Err(CommentError::Forbidden(
+                "Username must be set before performing this action".into(),
+            ));
+        }
+
+        Ok(user_id)
+    }
+}
+
+/// Result of a write, with what callers need to refresh their caches
+#[derive(Debug, Clone)]
+pub struct CommentWrite<T> {
+    pub page_id: Uuid,
+    /// The page tree's new `updated_at`, None if nothing was written (shadowbanned author)
+    pub updated_at: Option<i64>,
+    pub value: T,
+}
+
+/// Comment writes shared by the HTTP and WebSocket servers
+pub struct CommentService {
+    config: Arc<Config>,
+    redis: Arc<RedisClient>,
+    store: Arc<dyn Storage>,
+    moderation: Arc<ModerationClient>,
+    action_logger: Arc<ActionLogger>,
+    webhooks: Arc<WebhookDispatcher>,
+    quotas: Arc<Quotas>,
+}
+
+impl CommentService {
+    pub fn new(
+        config: Arc<Config>,
+        redis: Arc<RedisClient>,
+        store: Arc<dyn Storage>,
+        moderation: Arc<ModerationClient>,
+        action_logger: Arc<ActionLogger>,
+        webhooks: Arc<WebhookDispatcher>,
+        quotas: Arc<Quotas>,
+    ) -> Self {
+        Self {
+            config,
+            redis,
+            store,
+            moderation,
+            action_logger,
+            webhooks,
+            quotas,
+        }
+    }
+
+    /// Post a new comment or reply
+    pub async fn create(
+        &self,
+        site: &ProjectIdInfo,
+        actor: &CommentActor,
+        req: CreateCommentRequest,
+    ) -> Result<CommentWrite<TreeComment>, CommentError> {
+        // Check if anonymous comments are allowed
+        let is_anonymous = actor.user_id.is_none();
+        if is_anonymous && !site.settings.auth.anonymous {
+            return Err(CommentError::Unauthorized(
+                "Authentication required. Anonymous comments are not enabled.".into(),
+            ));
+        }
+
+        // Anonymous comments require author_name
+        if is_anonymous && req.author_name.as_ref().is_none_or(|n| n.trim().is_empty()) {
+            return Err(CommentError::BadRequest(
+                "author_name is required for anonymous comments".into(),
+            ));
+        }
+
+        // Check if user is blocked (only for authenticated users)
+        if actor.role == Role::Blocked {
+            return Err(CommentError::Forbidden("User is blocked".into()));
+        }
+
+        // Check if username is set (authenticated users must have username set)
+        if !is_anonymous && !actor.username_set {
+            return Err(CommentError::Forbidden(
+                "Username must be set before performing this action".into(),
+            ));
+        }
+
+        // Check if site-wide posting is disabled
+        if site.settings.posting_disabled {
+            return Err(CommentError::Forbidden("Posting is currently disabled".into()));
+        }
+
+        // Check the plan's monthly comment limit
+        self.quotas.check(site, QuotaMetric::Comments, 1).await?;
+
+        let page_id = RedisClient::generate_page_id(site.site_id, &req.page_url);
+
+        tracing::debug!(
+            "Create comment: page_url={}, site_id={}, page_id={}",
+            req.page_url,
+            site.site_id,
+            page_id
+        );
+
+        // Check if page-level posting is disabled
+        let page_locked = self
+            .redis
+            .is_page_locked(site.site_id, page_id)
+            .await
+            .unwrap_or(false);
+
+        if page_locked {
+            return Err(CommentError::Forbidden("Posting is disabled on this page".into()));
+        }
+
+        // Turnstile verification (if configured)
+        self.verify_turnstile(site, actor, is_anonymous).await?;
+
+        // Validate comment length
+        let max_length = self.config.max_comment_length;
+        if req.content.chars().count() > max_length {
+            return Err(CommentError::BadRequest(format!(
+                "Comment exceeds maximum length of {} characters",
+                max_length
+            )));
+        }
+
+        // Check if shadow banned (only for authenticated users)
+        let is_shadowbanned = if let Some(user_id) = actor.user_id {
+            self.store
+                .is_shadowbanned(site.site_id, user_id)
+                .await
+                .unwrap_or(false)
+        } else {
+            false
+        };
+
+        // Content moderation check
+        //
+        // DESIGN: Fail-open policy - if the moderation service is unavailable (network error,
+        // timeout, API outage), the comment is allowed through. This prioritizes availability
+        // over strict moderation. The alternative (fail-closed) would reject all comments when
+        // moderation is down, which provides worse UX for legitimate users.
+        let content_moderation_settings = &site.settings.content_moderation;
+        let moderated = self.moderation.is_enabled() && content_moderation_settings.enabled;
+        if moderated {
+            self.quotas.check(site, QuotaMetric::ModerationChecks, 1).await?;
+        }
+
+        let moderation_result = self
+            .moderation
+            .check(&req.content, content_moderation_settings)
+            .await;
+
+        // Count checks the moderation service answered against the plan
+        if moderated && moderation_result.is_ok() {
+            let redis = self.redis.clone();
+            let site_id = site.site_id;
+            tokio::spawn(async move {
+                let _ = redis.increment_usage(site_id, MODERATION_CHECKS_FIELD, 1).await;
+            });
+        }
+
+        // Log moderation failures so operators can monitor service health
+        if let Err(ref e) = moderation_result {
+            tracing::warn!(
+                error = %e,
+                "Content moderation check failed - allowing comment through (fail-open policy)"
+            );
+        }
+
+        // Category the moderation service flagged the comment for, if any
+        let mut flagged_category = None;
+        if let Ok(ModerationCheckResult::Blocked { category, result }) = moderation_result {
+            match content_moderation_settings.action {
+                ModerationAction::Reject => {
+                    tracing::info!(
+                        category = %category,
+                        reason = ?result.reason,
+                        "Comment rejected by content moderation"
+                    );
+                    return Err(CommentError::Forbidden(format!(
+                        "Content rejected: {}",
+                        result.reason.unwrap_or(category)
+                    )));
+                }
+                ModerationAction::Queue | ModerationAction::Flag => {
+                    flagged_category = Some(category);
+                }
+            }
+        }
+
+        // Determine status
+        let status = if flagged_category.is_some()
+            && content_moderation_settings.action == ModerationAction::Queue
+        {
+            Some(CommentStatus::Pending)
+        } else {
+            match site.settings.moderation_mode {
+                ModerationMode::Pre => Some(CommentStatus::Pending),
+                _ => None, // None means approved (default)
+            }
+        };
+
+        // Get author info - either from authenticated user or anonymous
+        let (author_id, author_name, author_avatar, author_karma) =
+            if let Some(user_id) = actor.user_id {
+                let author = self
+                    .store
+                    .get_user(user_id)
+                    .await?
+                    .ok_or_else(|| CommentError::Internal("User not found".into()))?;
+                (user_id, author.name, author.avatar_url, author.karma)
+            } else {
+                (
+                    ANONYMOUS_USER_ID,
+                    req.author_name.clone().unwrap_or_else(|| "Anonymous".to_string()),
+                    None,
+                    0,
+                )
+            };
+
+        // Users mentioned with @username, linked in the HTML
+        let mentions =
+            resolve_mentions(self.store.as_ref(), &req.content, &self.config.email.public_api_url)
+                .await?;
+
+        let now = Utc::now();
+        let now_ts = now.timestamp();
+        let comment_id = Uuid::now_v7();
+
+        let tree_comment = TreeComment {
+            id: comment_id,
+            author_id,
+            name: author_name,
+            avatar: author_avatar,
+            karma: author_karma,
+            text: req.content.clone(),
+            html: render_markdown_with_mentions(&req.content, &site.settings.display, &mentions),
+            upvotes: 0,
+            downvotes: 0,
+            reactions: Default::default(),
+            created_at: now_ts,
+            modified_at: now_ts,
+            edited: false,
+            replies: Vec::new(),
+            status: status.clone(),
+            parent_id: req.parent_path.last().copied(),
+            more_replies: None,
+        };
+
+        // If shadow banned, return success but don't actually save
+        if is_shadowbanned {
+            return Ok(CommentWrite {
+                page_id,
+                updated_at: None,
+                value: tree_comment,
+            });
+        }
+
+        // Atomically add the comment to the page tree
+        let (tree, ()) = self
+            .store
+            .update_page_tree(page_id, |tree| {
+                if req.parent_path.is_empty() {
+                    tree.add_root(tree_comment.clone());
+                } else if !tree.add_reply(&req.parent_path, tree_comment.clone()) {
+                    // Reply - parent must exist
+                    return Err(CommentError::NotFound("Parent comment not found".into()));
+                }
+                Ok(())
+            })
+            .await??;
+
+        // Find parent author for notification (if this is a reply)
+        let notify_user_id = if !req.parent_path.is_empty() {
+            tree.find_by_path(&req.parent_path)
+                .and_then(|parent| reply_recipient(parent, author_id))
+        } else {
+            None
+        };
+
+        // Held comments notify nobody yet: pending ones notify when approved, flagged ones
+        // are left to the moderators
+        let notify_now = status != Some(CommentStatus::Pending) && flagged_category.is_none();
+        let mentions = if notify_now { mentions } else { Vec::new() };
+
+        // Fire-and-forget: update indexes, usage, notifications, and publish in background
+        // These don't block the response - the comment is already saved
+        {
+            let redis = self.redis.clone();
+            let store = self.store.clone();
+            let config = self.config.clone();
+            let site_id = site.site_id;
+            let user_id = actor.user_id;
+            let is_pending = status == Some(CommentStatus::Pending);
+            let page_url = req.page_url.clone();
+            let tree_comment = tree_comment.clone();
+
+            tokio::spawn(async move {
+                // Run all index updates concurrently
+                let mut futures: Vec<Pin<Box<dyn Future<Output = ()> + Send>>> =
+                    Vec::with_capacity(8);
+
+                // User comment indexes (if authenticated)
+                if let Some(user_id) = user_id {
+                    let redis1 = redis.clone();
+                    let redis2 = redis.clone();
+                    futures.push(Box::pin(async move {
+                        let _ = redis1.add_user_comment_index(user_id, page_id, comment_id).await;
+                    }));
+                    futures.push(Box::pin(async move {
+                        let _ = redis2
+                            .add_user_site_comment_index(user_id, site_id, page_id, comment_id)
+                            .await;
+                    }));
+                }
+
+                // Site comment index
+                {
+                    let redis = redis.clone();
+                    futures.push(Box::pin(async move {
+                        let _ = redis.add_site_comment_index(site_id, page_id, comment_id).await;
+                    }));
+                }
+
+                // Page URL (page IDs are hashes, exports need the URL to move pages between sites)
+                {
+                    let redis = redis.clone();
+                    let page_url = page_url.clone();
+                    futures.push(Box::pin(async move {
+                        let _ = redis.set_page_url(site_id, page_id, &page_url).await;
+                    }));
+                }
+
+                // Modqueue (if pending), and tell moderators watching the site
+                if is_pending {
+                    let redis = redis.clone();
+                    let tree_comment = tree_comment.clone();
+                    futures.push(Box::pin(async move {
+                        let _ = redis.add_to_modqueue(site_id, page_id, comment_id).await;
+                        let _ = redis
+                            .publish_moderation_event(
+                                site_id,
+                                "modqueue_added",
+                                serde_json::json!({ "page_id": page_id, "comment": tree_comment }),
+                            )
+                            .await;
+                    }));
+                }
+
+                // Flagged by content moderation
+                if let Some(category) = flagged_category {
+                    let redis = redis.clone();
+                    let tree_comment = tree_comment.clone();
+                    futures.push(Box::pin(async move {
+                        let _ = redis
+                            .publish_moderation_event(
+                                site_id,
+                                "comment_flagged_by_ai",
+                                serde_json::json!({
+                                    "page_id": page_id,
+                                    "comment": tree_comment,
+                                    "category": category,
+                                }),
+                            )
+                            .await;
+                    }));
+                }
+
+                // Usage increment
+                {
+                    let redis = redis.clone();
+                    futures.push(Box::pin(async move {
+                        let _ = redis.increment_usage(site_id, COMMENTS_FIELD, 1).await;
+                    }));
+                }
+
+                // Site analytics
+                {
+                    let redis = redis.clone();
+                    futures.push(Box::pin(async move {
+                        let _ = redis.record_comment_analytics(site_id, page_id, user_id).await;
+                    }));
+                }
+
+                // Increment user comment count (if authenticated)
+                if let Some(user_id) = user_id {
+                    let store = store.clone();
+                    futures.push(Box::pin(async move {
+                        let _ = store.increment_user_comment_count(user_id).await;
+                    }));
+                }
+
+                // Notification (if reply to another user), emailed if they opted in
+                if let Some(parent_author_id) = notify_user_id.filter(|_| notify_now) {
+                    let redis = redis.clone();
+                    let store = store.clone();
+                    let config = config.clone();
+                    let page_url = page_url.clone();
+                    let tree_comment = tree_comment.clone();
+                    futures.push(Box::pin(async move {
+                        let notification =
+                            comment_notification(site_id, page_url, &tree_comment, NotificationType::Reply);
+                        let _ = notification_emails::notify(
+                            &redis,
+                            store.as_ref(),
+                            &config,
+                            parent_author_id,
+                            &notification,
+                        )
+                        .await;
+                    }));
+                }
+
+                // Mention notifications, emailed if they opted in
+                if !mentions.is_empty() {
+                    let redis = redis.clone();
+                    let store = store.clone();
+                    let config = config.clone();
+                    let page_url = page_url.clone();
+                    let tree_comment = tree_comment.clone();
+                    futures.push(Box::pin(async move {
+                        let notification =
+                            comment_notification(site_id, page_url, &tree_comment, NotificationType::Mention);
+                        notify_mentions(
+                            &redis,
+                            store.as_ref(),
+                            &config,
+                            &notification,
+                            &mentions,
+                            notify_user_id,
+                        )
+                        .await;
+                    }));
+                }
+
+                // Publish for WebSocket subscribers
+                {
+                    let redis = redis.clone();
+                    futures.push(Box::pin(async move {
+                        let _ = redis
+                            .publish_page_event(
+                                page_id,
+                                "new_comment",
+                                serde_json::json!({ "comment": tree_comment }),
+                            )
+                            .await;
+                    }));
+                }
+
+                futures_util::future::join_all(futures).await;
+            });
+        }
+
+        let log_entry = ActionLogBuilder::new(ActionType::CommentCreated, site.site_id)
+            .user_id(author_id)
+            .page_url(req.page_url)
+            .page_id(page_id)
+            .comment_id(comment_id)
+            .content_preview(req.content);
+        self.log_action(log_entry, actor).await;
+
+        Ok(CommentWrite {
+            page_id,
+            updated_at: Some(tree.updated_at),
+            value: tree_comment,
+        })
+    }
+
+    /// Edit a comment
+    ///
+    /// Authors can edit their own comments within the site's edit window, moderators
+    /// can edit any comment. The previous text is kept as a revision.
+    pub async fn edit(
+        &self,
+        site: &ProjectIdInfo,
+        actor: &CommentActor,
+        comment_id: Uuid,
+        req: UpdateCommentRequest,
+    ) -> Result<CommentWrite<TreeComment>, CommentError> {
+        let user_id = actor.require_user()?;
+        validate_path(&req.path, comment_id)?;
+
+        let page_id = RedisClient::generate_page_id(site.site_id, &req.page_url);
+
+        // Mentions are linked, but only notified when the comment is posted
+        let mentions =
+            resolve_mentions(self.store.as_ref(), &req.content, &self.config.email.public_api_url)
+                .await?;
+        let html = render_markdown_with_mentions(&req.content, &site.settings.display, &mentions);
+        let is_moderator = actor.role >= Role::Moderator;
+        let edit_window = site.settings.editing.edit_window_minutes;
+
+        // Atomically update the comment in the page tree
+        let (tree, (updated_comment, previous_text)) = self
+            .store
+            .update_page_tree(page_id, |tree| {
+                let comment = tree
+                    .find_by_path_mut(&req.path)
+                    .ok_or_else(|| CommentError::NotFound("Comment not found".into()))?;
+
+                // Authors can edit their own comments, moderators can edit any
+                if comment.author_id != user_id && !is_moderator {
+                    return Err(CommentError::Forbidden("Not your comment".into()));
+                }
+
+                // Authors can only edit within the site's edit window
+                if let Some(minutes) = edit_window
+                    && !is_moderator
+                    && Utc::now().timestamp() - comment.created_at > i64::from(minutes) * 60
+                {
+                    return Err(CommentError::Forbidden("Edit window has passed".into()));
+                }
+
+                let previous_text = std::mem::replace(&mut comment.text, req.content.clone());
+                comment.html = html.clone();
+                comment.modified_at = Utc::now().timestamp();
+                comment.edited = true;
+
+                Ok((comment.clone(), previous_text))
+            })
+            .await??;
+
+        // Keep the previous version for moderators. The edit is already saved, so a
+        // failure fails the request rather than losing the history quietly
+        let revision = CommentRevision {
+            text: previous_text,
+            edited_at: updated_comment.modified_at,
+            editor_id: user_id,
+        };
+        if let Err(e) = self.store.add_comment_revision(comment_id, &revision).await {
+            tracing::error!("Failed to record revision for comment {}: {}", comment_id, e);
+            return Err(e.into());
+        }
+
+        // Publish update for WebSocket subscribers
+        self.publish_event(page_id, "edit_comment", serde_json::json!({
+            "comment_id": comment_id,
+            "author_id": updated_comment.author_id,
+            "status": updated_comment.effective_status(),
+            "content": updated_comment.text.clone(),
+            "content_html": updated_comment.html.clone()
+        }))
+        .await;
+
+        let log_entry = ActionLogBuilder::new(ActionType::CommentEdited, site.site_id)
+            .user_id(user_id)
+            .page_url(req.page_url)
+            .page_id(page_id)
+            .comment_id(comment_id)
+            .content_preview(req.content);
+        self.log_action(log_entry, actor).await;
+
+        Ok(CommentWrite {
+            page_id,
+            updated_at: Some(tree.updated_at),
+            value: updated_comment,
+        })
+    }
+
+    /// Delete a comment (marks as deleted, preserves replies)
+    pub async fn delete(
+        &self,
+        site: &ProjectIdInfo,
+        actor: &CommentActor,
+        comment_id: Uuid,
+        req: DeleteRequest,
+    ) -> Result<CommentWrite<()>, CommentError> {
+        let user_id = actor.require_user()?;
+        validate_path(&req.path, comment_id)?;
+
+        let page_id = RedisClient::generate_page_id(site.site_id, &req.page_url);
+
+        // Atomically mark the comment as deleted
+        let (tree, (deleted_by_author, author_id, previous_status)) = self
+            .store
+            .update_page_tree(page_id, |tree| {
+                let comment = tree
+                    .find_by_path_mut(&req.path)
+                    .ok_or_else(|| CommentError::NotFound("Comment not found".into()))?;
+
+                // Check ownership or moderator
+                let is_author = comment.author_id == user_id;
+                if !is_author && actor.role < Role::Moderator {
+                    return Err(CommentError::Forbidden("Not authorized".into()));
+                }
+
+                // Mark as deleted (preserves replies)
+                let author_id = comment.author_id;
+                let previous_status = comment.effective_status();
+                comment.mark_deleted();
+                Ok((is_author, author_id, previous_status))
+            })
+            .await??;
+
+        // An author deleting their comment removes its content, including earlier versions.
+        // Revisions are kept when a moderator removes it.
+        if deleted_by_author
+            && let Err(e) = self.store.delete_comment_revisions(comment_id).await
+        {
+            tracing::warn!("Failed to delete revisions for comment {}: {}", comment_id, e);
+        }
+
+        // Publish deletion for WebSocket subscribers
+        // (with the status it had, so viewers who never saw a pending comment skip this)
+        self.publish_event(page_id, "delete_comment", serde_json::json!({
+            "comment_id": comment_id,
+            "author_id": author_id,
+            "status": previous_status
+        }))
+        .await;
+
+        let log_entry = ActionLogBuilder::new(ActionType::CommentDeleted, site.site_id)
+            .user_id(user_id)
+            .page_url(req.page_url)
+            .page_id(page_id)
+            .comment_id(comment_id);
+        self.log_action(log_entry, actor).await;
+
+        Ok(CommentWrite {
+            page_id,
+            updated_at: Some(tree.updated_at),
+            value: (),
+        })
+    }
+
+    /// Vote on a comment (voting the same direction again removes the vote)
+    pub async fn vote(
+        &self,
+        site: &ProjectIdInfo,
+        actor: &CommentActor,
+        comment_id: Uuid,
+        req: VoteRequest,
+    ) -> Result<CommentWrite<VoteResponse>, CommentError> {
+        let user_id = actor.require_user()?;
+        validate_path(&req.path, comment_id)?;
+
+        let page_id = RedisClient::generate_page_id(site.site_id, &req.page_url);
+
+        tracing::debug!(
+            "Vote request: page_url={}, site_id={}, page_id={}, comment_id={}, path={:?}",
+            req.page_url,
+            site.site_id,
+            page_id,
+            comment_id,
+            req.path
+        );
+
+        // Use atomic vote operation to prevent race conditions
+        let (new_vote, new_upvotes, new_downvotes, upvote_delta, downvote_delta) = self
+            .store
+            .atomic_vote(user_id, page_id, comment_id, &req.path, req.direction)
+            .await
+            .map_err(|e| {
+                tracing::error!("Vote error: {}", e);
+                CommentError::from(e)
+            })?;
+
+        // Get author_id for karma update (we still need to fetch the tree for this)
+        let tree = self
+            .store
+            .get_page_tree(page_id)
+            .await?
+            .ok_or_else(|| CommentError::NotFound("Page not found".into()))?;
+
+        let comment = tree
+            .find_by_path(&req.path)
+            .ok_or_else(|| CommentError::NotFound("Comment not found".into()))?;
+        let author_id = comment.author_id;
+        let status = comment.effective_status();
+
+        // Fire-and-forget: karma update and WebSocket publish
+        // Note: Vote storage is handled atomically by `atomic_vote`
+        {
+            let redis = self.redis.clone();
+            let store = self.store.clone();
+            let karma_delta = upvote_delta - downvote_delta;
+
+            tokio::spawn(async move {
+                tokio::join!(
+                    // Update author karma (only if voting on someone else's comment)
+                    async {
+                        if author_id != user_id && author_id != DELETED_USER_ID && karma_delta != 0 {
+                            let _ = store.update_user_karma(author_id, karma_delta).await;
+                        }
+                    },
+                    // Publish vote update for WebSocket subscribers
+                    async {
+                        let _ = redis
+                            .publish_page_event(
+                                page_id,
+                                "vote_update",
+                                serde_json::json!({
+                                    "comment_id": comment_id,
+                                    "author_id": author_id,
+                                    "status": status,
+                                    "upvotes": new_upvotes,
+                                    "downvotes": new_downvotes,
+                                }),
+                            )
+                            .await;
+                    },
+                );
+            });
+        }
+
+        let log_entry = ActionLogBuilder::new(ActionType::CommentVoted, site.site_id)
+            .user_id(user_id)
+            .page_url(req.page_url)
+            .page_id(page_id)
+            .comment_id(comment_id)
+            .metadata(serde_json::json!({
+                "vote_type": match req.direction {
+                    VoteDirection::Up => "up",
+                    VoteDirection::Down => "down",
+                }
+            }));
+        self.log_action(log_entry, actor).await;
+
+        Ok(CommentWrite {
+            page_id,
+            updated_at: Some(tree.updated_at),
+            value: VoteResponse {
+                upvotes: new_upvotes,
+                downvotes: new_downvotes,
+                user_vote: new_vote,
+            },
+        })
+    }
+
+    /// Verify Turnstile if required for the actor
+    async fn verify_turnstile(
+        &self,
+        site: &ProjectIdInfo,
+        actor: &CommentActor,
+        is_anonymous: bool,
+    ) -> Result<(), CommentError> {
+        let turnstile_settings = &site.settings.turnstile;
+        if !turnstile_settings.enabled || turnstile_settings.enforce_on == TurnstileEnforcement::None {
+            return Ok(());
+        }
+
+        let requires_verification = match turnstile_settings.enforce_on {
+            TurnstileEnforcement::All => true,
+            TurnstileEnforcement::Anonymous => is_anonymous,
+            TurnstileEnforcement::Unverified => is_anonymous, // Anonymous users are "unverified"
+            TurnstileEnforcement::None => false,
+        };
+
+        if !requires_verification {
+            return Ok(());
+        }
+
+        let turnstile_token = actor
+            .turnstile_token
+            .as_deref()
+            .ok_or_else(|| CommentError::Forbidden("Turnstile verification required".into()))?;
+
+        let secret_key = self
+            .config
+            .turnstile
+            .secret_key
+            .as_ref()
+            .ok_or_else(|| CommentError::Unavailable("Turnstile not configured".into()))?;
+
+        let client_ip = actor
+            .ip
+            .as_deref()
+            .and_then(|s| s.split(',').next())
+            .map(|s| s.trim());
+
+        let result = verify_with_cloudflare(secret_key, turnstile_token, client_ip)
+            .await
+            .map_err(|e| CommentError::Internal(format!("Turnstile error: {}", e)))?;
+
+        if !result.success {
+            return Err(CommentError::Forbidden(format!(
+                "Turnstile failed: {}",
+                result.error_codes.join(", ")
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Send the notifications a comment held for moderation didn't send when it was posted
+    ///
+    /// Call once, when a pending comment is approved.
+    pub async fn notify_approved(&self, site_id: Uuid, page_id: Uuid, path: &[Uuid]) -> Result<(), CommentError> {
+        let Some(tree) = self.store.get_page_tree(page_id).await? else {
+            return Ok(());
+        };
+        let Some(comment) = tree.find_by_path(path) else {
+            return Ok(());
+        };
+        let Some(page_url) = self.redis.get_page_url(site_id, page_id).await? else {
+            return Ok(());
+        };
+
+        let reply_to = match path.split_last() {
+            Some((_, parent_path)) if !parent_path.is_empty() => tree
+                .find_by_path(parent_path)
+                .and_then(|parent| reply_recipient(parent, comment.author_id)),
+            _ => None,
+        };
+        if let Some(parent_author_id) = reply_to {
+            let notification =
+                comment_notification(site_id, page_url.clone(), comment, NotificationType::Reply);
+            let _ = notification_emails::notify(
+                &self.redis,
+                self.store.as_ref(),
+                &self.config,
+                parent_author_id,
+                &notification,
+            )
+            .await;
+        }
+
+        let mentions =
+            resolve_mentions(self.store.as_ref(), &comment.text, &self.config.email.public_api_url)
+                .await?;
+        let notification = comment_notification(site_id, page_url, comment, NotificationType::Mention);
+        notify_mentions(
+            &self.redis,
+            self.store.as_ref(),
+            &self.config,
+            &notification,
+            &mentions,
+            reply_to,
+        )
+        .await;
+        Ok(())
+    }
+
+    async fn publish_event(&self, page_id: Uuid, event_type: &str, data: serde_json::Value) {
+        if let Err(e) = self.redis.publish_page_event(page_id, event_type, data).await {
+            tracing::warn!("Failed to publish event to Redis: {}", e);
+        }
+    }
+
+    /// Fill in the actor's details, then log the action and queue its webhooks
+    async fn log_action(&self, mut log_entry: ActionLogBuilder, actor: &CommentActor) {
+        if let Some(user_id) = actor.user_id
+            && let Some(email) = self
+                .store
+                .get_user(user_id)
+                .await
+                .ok()
+                .flatten()
+                .and_then(|u| u.email)
+        {
+            log_entry = log_entry.user_email(email);
+        }
+        if let Some(ip) = &actor.ip {
+            log_entry = log_entry.ip(ip.clone());
+        }
+        if let Some(ua) = &actor.user_agent {
+            log_entry = log_entry.user_agent(ua.clone());
+        }
+
+        let entry = log_entry.build();
+        self.action_logger.log(entry.clone());
+        self.webhooks.enqueue_in_background(entry);
+    }
+}
+
+/// The author to notify about a reply to `parent`: anyone but the replier themselves,
+/// deleted users and anonymous authors
+fn reply_recipient(parent: &TreeComment, author_id: Uuid) -> Option<Uuid> {
+    let author = parent.author_id;
+    (author != author_id && author != DELETED_USER_ID && author != ANONYMOUS_USER_ID).then_some(author)
+}
+
+/// Notification about a comment replying to or mentioning someone
+fn comment_notification(
+    site_id: Uuid,
+    page_url: String,
+    comment: &TreeComment,
+    notification_type: NotificationType,
+) -> Notification {
+    Notification {
+        id: Uuid::now_v7(),
+        notification_type,
+        comment_id: comment.id,
+        from_user_id: comment.author_id,
+        read: false,
+        created_at: Utc::now(),
+        site_id: Some(site_id),
+        page_url: Some(page_url),
+        excerpt: Some(notification_emails::excerpt(&comment.text)),
+    }
+}
+
+/// Tell the users a comment mentions about it, emailed if they opted in
+///
+/// Only registered authors' mentions notify. The author, the replied-to author (who gets
+/// the reply notification) and users who blocked the author are skipped.
+async fn notify_mentions(
+    redis: &RedisClient,
+    store: &dyn Storage,
+    config: &Config,
+    notification: &Notification,
+    mentions: &[Mention],
+    reply_to: Option<Uuid>,
+) {
+    let author_id = notification.from_user_id;
+    if author_id == ANONYMOUS_USER_ID {
+        return;
+    }
+
+    for user_id in mentions.iter().map(|m| m.user_id) {
+        if user_id == author_id || Some(user_id) == reply_to {
+            continue;
+        }
+        if store.is_blocked_by_user(user_id, author_id).await.unwrap_or(false) {
+            continue;
+        }
+        let notification = Notification {
+            id: Uuid::now_v7(),
+            ..notification.clone()
+        };
+        let _ = notification_emails::notify(redis, store, config, user_id, &notification).await;
+    }
+}
+
+/// The path to a comment must end with the comment's ID
+fn validate_path(path: &[Uuid], comment_id: Uuid) -> Result<(), CommentError> {
+    if path.last() != Some(&comment_id) {
+        return Err(CommentError::BadRequest("Path must end with comment ID".into()));
+    }
+    Ok(())
+}
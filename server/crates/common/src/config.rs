@@ -5,24 +5,55 @@ use std::env;
 pub struct Config {
     pub mode: Mode,
     pub redis_url: String,
+    /// Where users, page trees, votes, roles, notifications, sessions and rate limits live
+    pub storage: StorageBackend,
     pub http_host: String,
     pub http_port: u16,
     pub ws_host: String,
     pub ws_port: u16,
     pub jwt_secret: String,
     pub jwt_expiry_hours: u64,
+    /// How long a refresh token stays valid; each refresh issues a new one
+    pub refresh_token_expiry_days: u64,
+    /// How long a just-exchanged refresh token is answered with a conflict instead of
+    /// revoking its session, so concurrent refreshes from one client aren't taken as theft
+    pub refresh_reuse_grace_secs: u64,
     pub oauth: OAuthConfig,
     pub rate_limit: RateLimitConfig,
     pub content_moderation: ContentModerationConfig,
     pub email: EmailConfig,
     pub turnstile: TurnstileConfig,
     pub s3: Option<S3Config>,
+    pub webhooks: WebhookConfig,
     /// Maximum comment length in characters
     pub max_comment_length: usize,
     /// Allow localhost/127.0.0.1/::1 origins for API requests (development only)
     pub allow_localhost_origin: bool,
 }
 
+/// Backend for durable data (`STORAGE_BACKEND`)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum StorageBackend {
+    /// Keep everything in Redis (default)
+    #[default]
+    Redis,
+    /// Embedded SQLite database at `SQLITE_PATH`
+    Sqlite { path: String },
+}
+
+impl StorageBackend {
+    /// Read `STORAGE_BACKEND` / `SQLITE_PATH` (also used by the CLI tools)
+    pub fn from_env() -> anyhow::Result<Self> {
+        match env::var("STORAGE_BACKEND").unwrap_or_default().to_lowercase().as_str() {
+            "" | "redis" => Ok(StorageBackend::Redis),
+            "sqlite" => Ok(StorageBackend::Sqlite {
+                path: env::var("SQLITE_PATH").unwrap_or_else(|_| "threadkit.db".to_string()),
+            }),
+            other => anyhow::bail!("Unknown STORAGE_BACKEND '{}' (expected redis or sqlite)", other),
+        }
+    }
+}
+
 /// Configuration for Cloudflare Turnstile bot protection
 #[derive(Debug, Clone, Default)]
 pub struct TurnstileConfig {
@@ -30,6 +61,36 @@ pub struct TurnstileConfig {
     pub secret_key: Option<String>,
 }
 
+/// Configuration for outbound webhook delivery
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// Enable webhook delivery globally
+    pub enabled: bool,
+    /// Attempts before a delivery is marked as failed
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on every further attempt
+    pub base_backoff_seconds: u64,
+    /// Request timeout in seconds
+    pub timeout_seconds: u64,
+    /// How often the worker polls the retry queue
+    pub poll_interval_ms: u64,
+    /// Allow endpoints on loopback, private and link-local addresses (tests and local development)
+    pub allow_private_targets: bool,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_attempts: 8,
+            base_backoff_seconds: 30,
+            timeout_seconds: 10,
+            poll_interval_ms: 1000,
+            allow_private_targets: false,
+        }
+    }
+}
+
 /// Configuration for S3-compatible storage (e.g., Backblaze B2)
 #[derive(Debug, Clone)]
 pub struct S3Config {
@@ -42,24 +103,79 @@ pub struct S3Config {
 }
 
 /// Configuration for email sending
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct EmailConfig {
-    /// Email provider (currently only "resend" is supported)
+    /// Email provider (`EMAIL_PROVIDER`); emails are only logged when unset
     pub provider: Option<EmailProvider>,
+    /// Sender address (`EMAIL_FROM_ADDRESS`, or `noreply@` + `EMAIL_FROM_DOMAIN`)
+    pub from_address: String,
+    /// Public URL of the HTTP server, for links in emails (`PUBLIC_API_URL`)
+    pub public_api_url: String,
+    /// Wait before sending an immediate notification email, so a burst of replies
+    /// shares one email (`EMAIL_NOTIFICATION_DELAY_SECONDS`)
+    pub notification_delay_seconds: u64,
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        Self {
+            provider: None,
+            from_address: "noreply@localhost".to_string(),
+            public_api_url: "http://localhost:8080".to_string(),
+            notification_delay_seconds: 60,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum EmailProvider {
+    Smtp(SmtpConfig),
     Resend(ResendConfig),
+    /// Write emails to a directory (or the log) instead of sending them, for development and tests
+    Outbox(OutboxConfig),
+}
+
+#[derive(Debug, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub security: SmtpSecurity,
+}
+
+/// How the SMTP connection is encrypted (`SMTP_SECURITY`)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SmtpSecurity {
+    /// Upgrade a plain connection with STARTTLS (port 587)
+    #[default]
+    StartTls,
+    /// Implicit TLS (port 465)
+    Tls,
+    /// Unencrypted, for local relays only (port 25)
+    None,
+}
+
+impl SmtpSecurity {
+    pub fn default_port(self) -> u16 {
+        match self {
+            SmtpSecurity::StartTls => 587,
+            SmtpSecurity::Tls => 465,
+            SmtpSecurity::None => 25,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct ResendConfig {
     pub api_key: String,
-    /// Domain for sending emails (e.g., mail.usethreadkit.com)
-    pub from_domain: String,
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct OutboxConfig {
+    /// Directory emails are written to as JSON files (`EMAIL_OUTBOX_DIR`); logged when unset
+    pub dir: Option<String>,
+}
 
 /// Configuration for AI-powered content moderation (OpenAI-compatible API)
 #[derive(Debug, Clone, Default)]
@@ -126,7 +242,7 @@ impl Default for RateLimitConfig {
 #[derive(Debug, Clone)]
 pub enum Mode {
     Standalone(StandaloneConfig),
-    Saas,
+    Saas(SaasConfig),
 }
 
 #[derive(Debug, Clone)]
@@ -138,6 +254,12 @@ pub struct StandaloneConfig {
     pub allowed_origins: Vec<String>,
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct SaasConfig {
+    /// Bearer token for the site management API (`ADMIN_API_KEY`); the API is disabled when unset
+    pub admin_api_key: Option<String>,
+}
+
 #[derive(Debug, Clone, Default, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum ModerationMode {
@@ -176,7 +298,9 @@ impl Config {
     fn load_from_env() -> anyhow::Result<Self> {
 
         let mode = match env::var("MODE").unwrap_or_else(|_| "standalone".to_string()).as_str() {
-            "saas" => Mode::Saas,
+            "saas" => Mode::Saas(SaasConfig {
+                admin_api_key: env::var("ADMIN_API_KEY").ok().filter(|s| !s.is_empty()),
+            }),
             _ => {
                 // In standalone mode, only require THREADKIT_SECRET_KEY (site_id is looked up from Redis)
                 // PROJECT_ID_PUBLIC is optional and defaults to the placeholder from --create-site output
@@ -284,7 +408,26 @@ impl Config {
         };
 
         let email = EmailConfig {
-            provider: Self::load_email_provider(),
+            provider: Self::load_email_provider()?,
+            from_address: env::var("EMAIL_FROM_ADDRESS")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .or_else(|| {
+                    env::var("EMAIL_FROM_DOMAIN")
+                        .ok()
+                        .filter(|s| !s.is_empty())
+                        .map(|domain| format!("noreply@{}", domain))
+                })
+                .unwrap_or_else(|| "noreply@localhost".to_string()),
+            public_api_url: env::var("PUBLIC_API_URL")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| s.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| "http://localhost:8080".to_string()),
+            notification_delay_seconds: env::var("EMAIL_NOTIFICATION_DELAY_SECONDS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(60),
         };
 
         let turnstile = TurnstileConfig {
@@ -293,9 +436,37 @@ impl Config {
 
         let s3 = Self::load_s3_config();
 
+        let webhooks = WebhookConfig {
+            enabled: env::var("WEBHOOKS_ENABLED")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            max_attempts: env::var("WEBHOOK_MAX_ATTEMPTS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(8),
+            base_backoff_seconds: env::var("WEBHOOK_BACKOFF_SECONDS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(30),
+            timeout_seconds: env::var("WEBHOOK_TIMEOUT_SECONDS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(10),
+            poll_interval_ms: env::var("WEBHOOK_POLL_INTERVAL_MS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1000),
+            allow_private_targets: env::var("WEBHOOK_ALLOW_PRIVATE_TARGETS")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+        };
+
+        let storage = StorageBackend::from_env()?;
+
         Ok(Config {
             mode,
             redis_url: env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".to_string()),
+            storage,
             http_host: env::var("HTTP_HOST").unwrap_or_else(|_| "127.0.0.1".to_string()),
             http_port: env::var("HTTP_PORT")
                 .ok()
@@ -322,12 +493,21 @@ impl Config {
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(24 * 7), // 1 week default
+            refresh_token_expiry_days: env::var("REFRESH_TOKEN_EXPIRY_DAYS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(30),
+            refresh_reuse_grace_secs: env::var("REFRESH_TOKEN_REUSE_GRACE_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(10),
             oauth,
             rate_limit,
             content_moderation,
             email,
             turnstile,
             s3,
+            webhooks,
             max_comment_length: env::var("MAX_COMMENT_LENGTH")
                 .ok()
                 .and_then(|s| s.parse().ok())
@@ -350,20 +530,44 @@ impl Config {
         })
     }
 
-    fn load_email_provider() -> Option<EmailProvider> {
+    fn load_email_provider() -> anyhow::Result<Option<EmailProvider>> {
         let provider = env::var("EMAIL_PROVIDER").unwrap_or_default();
 
         match provider.to_lowercase().as_str() {
+            "smtp" => {
+                let Some(host) = env::var("SMTP_HOST").ok().filter(|s| !s.is_empty()) else {
+                    anyhow::bail!("EMAIL_PROVIDER=smtp requires SMTP_HOST");
+                };
+                let security = match env::var("SMTP_SECURITY").unwrap_or_default().to_lowercase().as_str() {
+                    "" | "starttls" => SmtpSecurity::StartTls,
+                    "tls" | "ssl" => SmtpSecurity::Tls,
+                    "none" => SmtpSecurity::None,
+                    other => anyhow::bail!("Unknown SMTP_SECURITY '{}' (expected starttls, tls or none)", other),
+                };
+
+                Ok(Some(EmailProvider::Smtp(SmtpConfig {
+                    host,
+                    port: env::var("SMTP_PORT")
+                        .ok()
+                        .and_then(|s| s.parse().ok())
+                        .unwrap_or_else(|| security.default_port()),
+                    username: env::var("SMTP_USERNAME").ok().filter(|s| !s.is_empty()),
+                    password: env::var("SMTP_PASSWORD").ok().filter(|s| !s.is_empty()),
+                    security,
+                })))
+            }
             "resend" => {
-                let api_key = env::var("RESEND_API_KEY").ok().filter(|s| !s.is_empty())?;
-                let from_domain = env::var("EMAIL_FROM_DOMAIN").ok().filter(|s| !s.is_empty())?;
-
-                Some(EmailProvider::Resend(ResendConfig {
-                    api_key,
-                    from_domain,
-                }))
+                // Keep the old behaviour of only logging codes when the key is missing
+                Ok(env::var("RESEND_API_KEY")
+                    .ok()
+                    .filter(|s| !s.is_empty())
+                    .map(|api_key| EmailProvider::Resend(ResendConfig { api_key })))
             }
-            _ => None,
+            "outbox" => Ok(Some(EmailProvider::Outbox(OutboxConfig {
+                dir: env::var("EMAIL_OUTBOX_DIR").ok().filter(|s| !s.is_empty()),
+            }))),
+            "" => Ok(None),
+            other => anyhow::bail!("Unknown EMAIL_PROVIDER '{}' (expected smtp, resend or outbox)", other),
         }
     }
 
@@ -396,4 +600,11 @@ impl Config {
             _ => None,
         }
     }
+
+    pub fn saas(&self) -> Option<&SaasConfig> {
+        match &self.mode {
+            Mode::Saas(c) => Some(c),
+            _ => None,
+        }
+    }
 }
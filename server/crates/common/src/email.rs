@@ -0,0 +1,554 @@
+//! Outgoing email
+//!
+//! `EmailSender` is implemented for SMTP, Resend and a development outbox; `Mailer`
+//! picks one from `EmailConfig` and renders the templates in `templates/email`.
+
+use crate::config::{EmailConfig, EmailProvider, OutboxConfig, ResendConfig, SmtpConfig, SmtpSecurity};
+use crate::types::{NotificationType, SiteConfig};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use lettre::{
+    message::{
+        header::{HeaderName, HeaderValue},
+        Mailbox, MultiPart,
+    },
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const LAYOUT_HTML: &str = include_str!("../templates/email/layout.html");
+const OTP_HTML: &str = include_str!("../templates/email/otp.html");
+const OTP_TEXT: &str = include_str!("../templates/email/otp.txt");
+const NOTIFICATIONS_HTML: &str = include_str!("../templates/email/notifications.html");
+const NOTIFICATIONS_TEXT: &str = include_str!("../templates/email/notifications.txt");
+const NOTIFICATION_ITEM_HTML: &str = include_str!("../templates/email/notification_item.html");
+const NOTIFICATION_ITEM_TEXT: &str = include_str!("../templates/email/notification_item.txt");
+
+/// A rendered email ready to send
+#[derive(Debug, Clone, Serialize)]
+pub struct Email {
+    /// Sender mailbox, e.g. `My Blog <noreply@example.com>`
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+    /// One-click unsubscribe link, sent as the `List-Unsubscribe` header
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unsubscribe_url: Option<String>,
+}
+
+/// One notification in a notification email
+#[derive(Debug, Clone)]
+pub struct NotificationItem {
+    pub notification_type: NotificationType,
+    /// Name of the user who replied or mentioned
+    pub from_name: String,
+    pub excerpt: String,
+    pub page_url: String,
+}
+
+impl NotificationItem {
+    fn summary(&self) -> String {
+        match self.notification_type {
+            NotificationType::Mention => format!("{} mentioned you", self.from_name),
+            _ => format!("{} replied to your comment", self.from_name),
+        }
+    }
+}
+
+/// Delivers rendered emails
+#[async_trait]
+pub trait EmailSender: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<()>;
+}
+
+/// Sends email through an SMTP server
+pub struct SmtpSender {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpSender {
+    pub fn new(config: &SmtpConfig) -> Result<Self> {
+        let builder = match config.security {
+            SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?,
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?,
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+        };
+        let mut builder = builder
+            .port(config.port)
+            .timeout(Some(Duration::from_secs(10)));
+        if let Some(username) = &config.username {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                config.password.clone().unwrap_or_default(),
+            ));
+        }
+
+        Ok(Self { transport: builder.build() })
+    }
+}
+
+#[async_trait]
+impl EmailSender for SmtpSender {
+    async fn send(&self, email: &Email) -> Result<()> {
+        let mut builder = Message::builder()
+            .from(email.from.parse()?)
+            .to(email.to.parse()?)
+            .subject(&email.subject);
+        if let Some(url) = &email.unsubscribe_url {
+            builder = builder
+                .raw_header(HeaderValue::new(
+                    HeaderName::new_from_ascii_str("List-Unsubscribe"),
+                    format!("<{}>", url),
+                ))
+                .raw_header(HeaderValue::new(
+                    HeaderName::new_from_ascii_str("List-Unsubscribe-Post"),
+                    "List-Unsubscribe=One-Click".to_string(),
+                ));
+        }
+        let message = builder
+            .multipart(MultiPart::alternative_plain_html(email.text.clone(), email.html.clone()))?;
+
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
+
+/// Sends email through the Resend API
+pub struct ResendSender {
+    client: reqwest::Client,
+    api_key: String,
+}
+
+impl ResendSender {
+    pub fn new(config: &ResendConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+
+        Ok(Self {
+            client,
+            api_key: config.api_key.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl EmailSender for ResendSender {
+    async fn send(&self, email: &Email) -> Result<()> {
+        let mut body = serde_json::json!({
+            "from": email.from,
+            "to": [email.to],
+            "subject": email.subject,
+            "text": email.text,
+            "html": email.html,
+        });
+        if let Some(url) = &email.unsubscribe_url {
+            body["headers"] = serde_json::json!({
+                "List-Unsubscribe": format!("<{}>", url),
+                "List-Unsubscribe-Post": "List-Unsubscribe=One-Click",
+            });
+        }
+
+        let response = self
+            .client
+            .post("https://api.resend.com/emails")
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .json(&body)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error = response.text().await.unwrap_or_default();
+            tracing::error!(status = %status, body = %error, "Resend API error");
+            return Err(anyhow!("Resend API error: {} - {}", status, error));
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps emails instead of sending them, for development and tests
+///
+/// Each email is written to `dir` as a JSON file, or logged when there is no directory.
+pub struct OutboxSender {
+    dir: Option<PathBuf>,
+}
+
+impl OutboxSender {
+    pub fn new(config: &OutboxConfig) -> Result<Self> {
+        let dir = config.dir.as_ref().map(PathBuf::from);
+        if let Some(dir) = &dir {
+            std::fs::create_dir_all(dir)?;
+        }
+        Ok(Self { dir })
+    }
+}
+
+#[async_trait]
+impl EmailSender for OutboxSender {
+    async fn send(&self, email: &Email) -> Result<()> {
+        match &self.dir {
+            Some(dir) => {
+                // v7 UUIDs keep the files in the order they were sent
+                let path = dir.join(format!("{}.json", uuid::Uuid::now_v7()));
+                tokio::fs::write(&path, serde_json::to_vec_pretty(email)?).await?;
+                tracing::debug!(to = %email.to, path = %path.display(), "Email written to outbox");
+            }
+            None => {
+                tracing::info!(
+                    to = %email.to,
+                    subject = %email.subject,
+                    "Email not sent (email provider not configured):\n{}",
+                    email.text
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Renders and sends the emails the server sends
+pub struct Mailer {
+    sender: Box<dyn EmailSender>,
+    from_address: String,
+}
+
+impl Mailer {
+    /// Create a mailer for the configured provider, logging emails when there is none
+    pub fn new(config: &EmailConfig) -> Result<Self> {
+        let sender: Box<dyn EmailSender> = match &config.provider {
+            Some(EmailProvider::Smtp(smtp)) => Box::new(SmtpSender::new(smtp)?),
+            Some(EmailProvider::Resend(resend)) => Box::new(ResendSender::new(resend)?),
+            Some(EmailProvider::Outbox(outbox)) => Box::new(OutboxSender::new(outbox)?),
+            None => Box::new(OutboxSender::new(&OutboxConfig::default())?),
+        };
+
+        Ok(Self::with_sender(sender, config.from_address.clone()))
+    }
+
+    /// Create a mailer around any sender
+    pub fn with_sender(sender: Box<dyn EmailSender>, from_address: String) -> Self {
+        Self { sender, from_address }
+    }
+
+    /// Send a sign in code
+    pub async fn send_otp(&self, site: &SiteConfig, to: &str, code: &str) -> Result<()> {
+        let text = render_template(OTP_TEXT, &[("site_name", &site.name), ("code", code)]);
+        let html = render_template(OTP_HTML, &[("code", &escape_html(code))]);
+        let email = self.compose(site, to, format!("{} - Sign in to {}", code, site.name), text, &html, None)?;
+        self.sender.send(&email).await
+    }
+
+    /// Send replies and mentions, as one email from the site
+    pub async fn send_notifications(
+        &self,
+        site: &SiteConfig,
+        to: &str,
+        items: &[NotificationItem],
+        unsubscribe_url: &str,
+    ) -> Result<()> {
+        let (subject, heading) = match items {
+            [item] => (
+                format!("{} on {}", item.summary(), site.name),
+                format!("{} on {}:", item.summary(), site.name),
+            ),
+            _ => (
+                format!("{} new replies and mentions on {}", items.len(), site.name),
+                format!("You have {} new replies and mentions on {}:", items.len(), site.name),
+            ),
+        };
+
+        let mut text_items = String::new();
+        let mut html_items = String::new();
+        for item in items {
+            let summary = item.summary();
+            text_items.push_str(&render_template(
+                NOTIFICATION_ITEM_TEXT,
+                &[("summary", &summary), ("excerpt", &item.excerpt), ("page_url", &item.page_url)],
+            ));
+            html_items.push_str(&render_template(
+                NOTIFICATION_ITEM_HTML,
+                &[
+                    ("summary", &escape_html(&summary)),
+                    ("excerpt", &escape_html(&item.excerpt)),
+                    ("page_url", &escape_html(&item.page_url)),
+                ],
+            ));
+        }
+
+        let text = render_template(
+            NOTIFICATIONS_TEXT,
+            &[("heading", &heading), ("items", &text_items), ("unsubscribe_url", unsubscribe_url)],
+        );
+        let html = render_template(
+            NOTIFICATIONS_HTML,
+            &[
+                ("heading", &escape_html(&heading)),
+                ("items", &html_items),
+                ("unsubscribe_url", &escape_html(unsubscribe_url)),
+            ],
+        );
+
+        let email = self.compose(site, to, subject, text, &html, Some(unsubscribe_url.to_string()))?;
+        self.sender.send(&email).await
+    }
+
+    /// Build an email from the site, wrapping the HTML content in the layout
+    fn compose(
+        &self,
+        site: &SiteConfig,
+        to: &str,
+        subject: String,
+        text: String,
+        content_html: &str,
+        unsubscribe_url: Option<String>,
+    ) -> Result<Email> {
+        let html = render_template(
+            LAYOUT_HTML,
+            &[("site_name", &escape_html(&site.name)), ("content", content_html)],
+        );
+
+        let from = Mailbox::new(
+            Some(site.email_sender_name().to_string()),
+            self.from_address
+                .parse()
+                .map_err(|e| anyhow!("Invalid sender address '{}': {}", self.from_address, e))?,
+        );
+
+        Ok(Email {
+            from: from.to_string(),
+            to: to.to_string(),
+            subject,
+            text,
+            html,
+            unsubscribe_url,
+        })
+    }
+}
+
+/// Replace `{{name}}` placeholders in a template
+///
+/// Values are inserted as-is; escape them first for HTML templates. The template is
+/// scanned once, so placeholders inside inserted values are left alone.
+pub fn render_template(template: &str, vars: &[(&str, &str)]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let value = after.find("}}").and_then(|end| {
+            vars.iter()
+                .find(|(name, _)| *name == &after[..end])
+                .map(|(_, value)| (*value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                rendered.push_str(value);
+                rest = &after[end + 2..];
+            }
+            None => {
+                rendered.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Escape text for use in HTML
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{EmailSettings, SiteSettings};
+    use std::sync::{Arc, Mutex};
+    use uuid::Uuid;
+
+    /// Keeps sent emails in memory
+    struct Captured(Arc<Mutex<Vec<Email>>>);
+
+    #[async_trait]
+    impl EmailSender for Captured {
+        async fn send(&self, email: &Email) -> Result<()> {
+            self.0.lock().unwrap().push(email.clone());
+            Ok(())
+        }
+    }
+
+    fn site(name: &str, sender_name: Option<&str>) -> SiteConfig {
+        SiteConfig {
+            id: Uuid::now_v7(),
+            name: name.to_string(),
+            domain: "example.com".to_string(),
+            project_id_public: "tk_pub_test".to_string(),
+            project_id_secret: "tk_sec_test".to_string(),
+            settings: SiteSettings {
+                email: EmailSettings {
+                    sender_name: sender_name.map(String::from),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            plan: Default::default(),
+        }
+    }
+
+    fn mailer() -> (Mailer, Arc<Mutex<Vec<Email>>>) {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let mailer = Mailer::with_sender(
+            Box::new(Captured(sent.clone())),
+            "noreply@example.com".to_string(),
+        );
+        (mailer, sent)
+    }
+
+    #[test]
+    fn test_render_template() {
+        assert_eq!(
+            render_template("Hi {{name}}, {{name}}! {{missing}}", &[("name", "Ann")]),
+            "Hi Ann, Ann! {{missing}}"
+        );
+    }
+
+    #[test]
+    fn test_render_template_ignores_placeholders_in_values() {
+        let rendered = render_template(
+            "{{excerpt}} | {{unsubscribe_url}}",
+            &[
+                ("excerpt", "click {{unsubscribe_url}} or {{items}}"),
+                ("unsubscribe_url", "https://example.com/unsubscribe"),
+                ("items", "secret"),
+            ],
+        );
+        assert_eq!(
+            rendered,
+            "click {{unsubscribe_url}} or {{items}} | https://example.com/unsubscribe"
+        );
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(escape_html(r#"<a href="x">&'"#), "&lt;a href=&quot;x&quot;&gt;&amp;&#39;");
+    }
+
+    #[tokio::test]
+    async fn test_otp_email() {
+        let (mailer, sent) = mailer();
+        mailer
+            .send_otp(&site("My <Blog>", None), "user@example.com", "123456")
+            .await
+            .unwrap();
+
+        let sent = sent.lock().unwrap();
+        let email = &sent[0];
+        assert_eq!(email.to, "user@example.com");
+        assert_eq!(email.subject, "123456 - Sign in to My <Blog>");
+        assert!(email.from.ends_with("<noreply@example.com>"));
+        assert!(email.from.contains("My <Blog>"));
+        assert!(email.text.contains("Your sign in code for My <Blog>:"));
+        assert!(email.text.contains("123456"));
+        // Site name is escaped in the HTML body
+        assert!(email.html.contains("My &lt;Blog&gt;"));
+        assert!(!email.html.contains("My <Blog>"));
+        assert!(email.html.contains("123456"));
+        assert!(!email.html.contains("{{"));
+    }
+
+    #[tokio::test]
+    async fn test_sender_name_override() {
+        let (mailer, sent) = mailer();
+        mailer
+            .send_otp(&site("My Blog", Some("Blog Team")), "user@example.com", "123456")
+            .await
+            .unwrap();
+
+        let from: Mailbox = sent.lock().unwrap()[0].from.parse().unwrap();
+        assert_eq!(from.name.as_deref(), Some("Blog Team"));
+        assert_eq!(from.email.to_string(), "noreply@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_notification_email() {
+        let (mailer, sent) = mailer();
+        let reply = NotificationItem {
+            notification_type: NotificationType::Reply,
+            from_name: "alice".to_string(),
+            excerpt: "I <3 this".to_string(),
+            page_url: "https://example.com/post?a=1&b=2".to_string(),
+        };
+        let mention = NotificationItem {
+            notification_type: NotificationType::Mention,
+            from_name: "bob".to_string(),
+            ..reply.clone()
+        };
+
+        mailer
+            .send_notifications(&site("My Blog", None), "user@example.com", std::slice::from_ref(&reply), "https://api/unsub")
+            .await
+            .unwrap();
+        mailer
+            .send_notifications(&site("My Blog", None), "user@example.com", &[reply, mention], "https://api/unsub")
+            .await
+            .unwrap();
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent[0].subject, "alice replied to your comment on My Blog");
+        assert_eq!(sent[0].unsubscribe_url.as_deref(), Some("https://api/unsub"));
+        assert!(sent[0].text.contains("I <3 this"));
+        assert!(sent[0].html.contains("I &lt;3 this"));
+        assert!(sent[0].html.contains("https://example.com/post?a=1&amp;b=2"));
+
+        assert_eq!(sent[1].subject, "2 new replies and mentions on My Blog");
+        assert!(sent[1].text.contains("bob mentioned you"));
+        assert!(sent[1].text.contains("https://api/unsub"));
+        assert!(!sent[1].html.contains("{{"));
+    }
+
+    #[tokio::test]
+    async fn test_outbox_writes_json_files() {
+        let dir = std::env::temp_dir().join(format!("threadkit-outbox-{}", Uuid::now_v7()));
+        let mailer = Mailer::new(&EmailConfig {
+            provider: Some(EmailProvider::Outbox(OutboxConfig {
+                dir: Some(dir.to_string_lossy().into_owned()),
+            })),
+            from_address: "noreply@example.com".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        mailer
+            .send_otp(&site("My Blog", None), "user@example.com", "654321")
+            .await
+            .unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        let email: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(files[0].as_ref().unwrap().path()).unwrap()).unwrap();
+        assert_eq!(email["to"], "user@example.com");
+        assert!(email["text"].as_str().unwrap().contains("654321"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -7,6 +7,9 @@ pub enum Error {
     #[error("Redis error: {0}")]
     Redis(#[from] fred::error::Error),
 
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
 
@@ -1,6 +1,7 @@
 pub mod config;
 pub mod types;
 pub mod redis;
+pub mod store;
 pub mod auth;
 pub mod error;
 pub mod moderation;
@@ -9,6 +10,19 @@ pub mod web3;
 pub mod storage;
 pub mod image_processing;
 pub mod action_log;
+pub mod markdown;
+pub mod mentions;
+pub mod webhooks;
+pub mod turnstile;
+pub mod comments;
+pub mod email;
+pub mod notification_emails;
+pub mod quotas;
+pub mod api_tokens;
+pub mod rate_limit;
+pub mod messages;
+pub mod pubsub;
+pub mod visibility;
 
 #[cfg(test)]
 mod web3_tests;
@@ -17,5 +31,11 @@ pub use config::{Config, Mode, ModerationMode};
 pub use error::{Error, Result};
 pub use moderation::ModerationClient;
 pub use storage::StorageClient;
+pub use store::Storage;
 pub use username::{normalize_username, validate_username, MAX_USERNAME_LENGTH, MIN_USERNAME_LENGTH};
 pub use action_log::{ActionLogger, ActionLog, ActionLogBuilder, ActionType};
+pub use webhooks::WebhookDispatcher;
+pub use comments::CommentService;
+pub use email::Mailer;
+pub use notification_emails::NotificationEmailer;
+pub use quotas::Quotas;
@@ -0,0 +1,343 @@
+//! Markdown rendering for comment content.
+//!
+//! Comments are parsed as CommonMark (plus strikethrough and bare URL autolinks),
+//! rendered to HTML, and then passed through a strict sanitizer allowlist.
+//! Raw HTML in the source is never trusted - it is rendered as escaped text.
+
+use std::collections::{HashMap, HashSet};
+
+use ammonia::{Builder, UrlRelative};
+use pulldown_cmark::{CowStr, Event, LinkType, Options, Parser, Tag, TagEnd, TextMergeStream};
+
+use crate::mentions::{mention_spans, Mention, MENTION_CLASS};
+use crate::types::DisplaySettings;
+
+/// `rel` attribute added to every link in rendered comments
+pub const LINK_REL: &str = "nofollow ugc noopener";
+
+/// Tags that are always allowed in rendered comments
+const BASE_TAGS: &[&str] = &[
+    "p", "br", "hr", "strong", "em", "del", "code", "pre", "blockquote", "ul", "ol", "li", "h1",
+    "h2", "h3", "h4", "h5", "h6",
+];
+
+/// Render comment markdown to sanitized HTML using the site's display settings.
+pub fn render_markdown(content: &str, display: &DisplaySettings) -> String {
+    render_markdown_with_mentions(content, display, &[])
+}
+
+/// Render comment markdown, linking `@username` mentions of the given users to their
+/// profiles (when the site allows links)
+pub fn render_markdown_with_mentions(
+    content: &str,
+    display: &DisplaySettings,
+    mentions: &[Mention],
+) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+
+    let events = TextMergeStream::new(Parser::new_ext(content, options));
+    let events = filter_events(events, display, mentions);
+
+    let mut html = String::with_capacity(content.len() * 3 / 2);
+    pulldown_cmark::html::push_html(&mut html, events.into_iter());
+
+    sanitizer(display).clean(&html).to_string()
+}
+
+/// Sanitize already-rendered HTML (e.g. imported comments) with the same allowlist
+pub fn sanitize_html(html: &str, display: &DisplaySettings) -> String {
+    sanitizer(display).clean(html).to_string()
+}
+
+/// Build the sanitizer allowlist for the given display settings
+fn sanitizer(display: &DisplaySettings) -> Builder<'static> {
+    let mut tags: HashSet<&'static str> = BASE_TAGS.iter().copied().collect();
+    let mut tag_attributes: HashMap<&'static str, HashSet<&'static str>> = HashMap::new();
+    tag_attributes.insert("ol", ["start"].into_iter().collect());
+
+    let mut builder = Builder::empty();
+    if display.allow_links {
+        tags.insert("a");
+        tag_attributes.insert("a", ["href", "title"].into_iter().collect());
+        builder.add_allowed_classes("a", &[MENTION_CLASS]);
+    }
+    if display.allow_images {
+        tags.insert("img");
+        tag_attributes.insert("img", ["src", "alt", "title"].into_iter().collect());
+    }
+
+    builder
+        .tags(tags)
+        .tag_attributes(tag_attributes)
+        .url_schemes(["http", "https", "mailto"].into_iter().collect())
+        .url_relative(UrlRelative::Deny)
+        .link_rel(Some(LINK_REL));
+    builder
+}
+
+/// Apply per-site restrictions and autolinking to the parsed event stream.
+///
+/// - Raw HTML is turned into plain text so it shows up escaped
+/// - Links/images are unwrapped to their text when disabled for the site
+/// - Bare `http(s)://` URLs in text are turned into links when links are allowed
+/// - Mentions of the given users are turned into profile links when links are allowed
+fn filter_events<'a>(
+    parser: impl Iterator<Item = Event<'a>>,
+    display: &DisplaySettings,
+    mentions: &[Mention],
+) -> Vec<Event<'a>> {
+    let mut events = Vec::new();
+    let mut link_depth = 0usize;
+    let mut code_depth = 0usize;
+
+    for event in parser {
+        match event {
+            Event::Html(html) | Event::InlineHtml(html) => events.push(Event::Text(html)),
+            Event::Start(Tag::Link { .. }) if !display.allow_links => {}
+            Event::End(TagEnd::Link) if !display.allow_links => {}
+            Event::Start(Tag::Image { .. }) if !display.allow_images => {}
+            Event::End(TagEnd::Image) if !display.allow_images => {}
+            Event::Start(tag @ Tag::Link { .. }) => {
+                link_depth += 1;
+                events.push(Event::Start(tag));
+            }
+            Event::End(TagEnd::Link) => {
+                link_depth = link_depth.saturating_sub(1);
+                events.push(Event::End(TagEnd::Link));
+            }
+            Event::Start(tag @ Tag::CodeBlock(_)) => {
+                code_depth += 1;
+                events.push(Event::Start(tag));
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                code_depth = code_depth.saturating_sub(1);
+                events.push(Event::End(TagEnd::CodeBlock));
+            }
+            Event::Text(text) if display.allow_links && link_depth == 0 && code_depth == 0 => {
+                link_mentions(text, mentions, &mut events);
+            }
+            other => events.push(other),
+        }
+    }
+
+    events
+}
+
+/// Split a text event on mentions of known users, emitting a profile link for each and
+/// autolinking the text around them
+fn link_mentions<'a>(text: CowStr<'a>, mentions: &[Mention], events: &mut Vec<Event<'a>>) {
+    let mut last = 0;
+    for span in mention_spans(&text) {
+        let username = text[span.start + 1..span.end].to_lowercase();
+        let Some(mention) = mentions.iter().find(|m| m.username == username) else {
+            continue;
+        };
+
+        if span.start > last {
+            autolink_text(CowStr::from(text[last..span.start].to_string()), events);
+        }
+        // Raw HTML so the link can carry its class, the sanitizer still checks it
+        events.push(Event::InlineHtml(CowStr::from(format!(
+            r#"<a href="{}" class="{}">"#,
+            ammonia::clean_text(&mention.profile_url),
+            MENTION_CLASS
+        ))));
+        events.push(Event::Text(CowStr::from(text[span.clone()].to_string())));
+        events.push(Event::InlineHtml(CowStr::Borrowed("</a>")));
+        last = span.end;
+    }
+
+    if last == 0 {
+        autolink_text(text, events);
+    } else if last < text.len() {
+        autolink_text(CowStr::from(text[last..].to_string()), events);
+    }
+}
+
+/// Split a text event on bare URLs, emitting link events for each URL found
+fn autolink_text<'a>(text: CowStr<'a>, events: &mut Vec<Event<'a>>) {
+    let mut rest: &str = &text;
+    let mut found = false;
+
+    while let Some(start) = find_url_start(rest) {
+        let end = start + url_len(&rest[start..]);
+        if end == start {
+            break;
+        }
+        found = true;
+
+        if start > 0 {
+            events.push(Event::Text(CowStr::from(rest[..start].to_string())));
+        }
+        let url = rest[start..end].to_string();
+        events.push(Event::Start(Tag::Link {
+            link_type: LinkType::Autolink,
+            dest_url: CowStr::from(url.clone()),
+            title: CowStr::Borrowed(""),
+            id: CowStr::Borrowed(""),
+        }));
+        events.push(Event::Text(CowStr::from(url)));
+        events.push(Event::End(TagEnd::Link));
+        rest = &rest[end..];
+    }
+
+    if !found {
+        events.push(Event::Text(text));
+    } else if !rest.is_empty() {
+        events.push(Event::Text(CowStr::from(rest.to_string())));
+    }
+}
+
+/// Find the byte offset of the next `http://` or `https://` URL at a word boundary
+fn find_url_start(text: &str) -> Option<usize> {
+    let mut offset = 0;
+    while let Some(pos) = text[offset..].find("http") {
+        let start = offset + pos;
+        let tail = &text[start..];
+        let at_boundary = text[..start]
+            .chars()
+            .next_back()
+            .is_none_or(|c| c.is_whitespace() || "([{<\"'".contains(c));
+        if at_boundary && (tail.starts_with("http://") || tail.starts_with("https://")) {
+            return Some(start);
+        }
+        offset = start + "http".len();
+    }
+    None
+}
+
+/// Length of the URL at the start of `text`, excluding trailing punctuation
+fn url_len(text: &str) -> usize {
+    let end = text
+        .find(|c: char| c.is_whitespace() || "<>\"'`".contains(c))
+        .unwrap_or(text.len());
+    let url = text[..end].trim_end_matches(['.', ',', ':', ';', '!', '?', ')', ']', '}']);
+
+    // Require something after the scheme
+    let scheme_len = if url.starts_with("https://") { 8 } else { 7 };
+    if url.len() <= scheme_len { 0 } else { url.len() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(content: &str) -> String {
+        render_markdown(content, &DisplaySettings::default())
+    }
+
+    #[test]
+    fn test_basic_formatting() {
+        assert_eq!(render("Hello **world**"), "<p>Hello <strong>world</strong></p>\n");
+        assert!(render("*em* and ~~gone~~").contains("<em>em</em> and <del>gone</del>"));
+        assert!(render("> quoted").contains("<blockquote>"));
+        assert!(render("- one\n- two").contains("<ul>\n<li>one</li>\n<li>two</li>\n</ul>"));
+    }
+
+    #[test]
+    fn test_fenced_code_is_escaped() {
+        let html = render("```rust\nlet x = a < b && c;\n```");
+        assert!(html.contains("<pre><code>let x = a &lt; b &amp;&amp; c;\n</code></pre>"));
+    }
+
+    #[test]
+    fn test_raw_html_is_escaped() {
+        let html = render("<script>alert(1)</script> <b>hi</b>");
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("<b>"));
+        assert!(html.contains("&lt;script&gt;"));
+    }
+
+    #[test]
+    fn test_links_get_rel() {
+        let html = render("[site](https://example.com)");
+        assert!(html.contains(r#"href="https://example.com""#));
+        assert!(html.contains(r#"rel="nofollow ugc noopener""#));
+    }
+
+    #[test]
+    fn test_bare_urls_are_autolinked() {
+        let html = render("see https://example.com/page.");
+        assert!(html.contains(r#"<a href="https://example.com/page" rel="nofollow ugc noopener">https://example.com/page</a>."#));
+
+        let html = render("<https://example.com>");
+        assert!(html.contains(r#"<a href="https://example.com""#));
+
+        // Not inside code
+        let html = render("`https://example.com`");
+        assert!(!html.contains("<a "));
+    }
+
+    #[test]
+    fn test_dangerous_urls_are_stripped() {
+        let html = render("[click](javascript:alert(1))");
+        assert!(!html.contains("javascript:"));
+
+        let html = render("![x](data:image/png;base64,AAAA)");
+        assert!(!html.contains("data:"));
+    }
+
+    #[test]
+    fn test_links_disabled() {
+        let display = DisplaySettings {
+            allow_links: false,
+            ..DisplaySettings::default()
+        };
+        let html = render_markdown("[site](https://example.com) and https://other.com", &display);
+        assert!(!html.contains("<a"));
+        assert!(html.contains("site and https://other.com"));
+    }
+
+    #[test]
+    fn test_sanitize_html() {
+        let html = sanitize_html(
+            r#"<p onclick="x()">Hi <a href="https://example.com" target="_blank">there</a><script>alert(1)</script></p>"#,
+            &DisplaySettings::default(),
+        );
+        assert_eq!(
+            html,
+            r#"<p>Hi <a href="https://example.com" rel="nofollow ugc noopener">there</a></p>"#
+        );
+    }
+
+    #[test]
+    fn test_images_disabled() {
+        let html = render("![alt text](https://example.com/a.png)");
+        assert!(html.contains(r#"<img src="https://example.com/a.png" alt="alt text">"#));
+
+        let display = DisplaySettings {
+            allow_images: false,
+            ..DisplaySettings::default()
+        };
+        let html = render_markdown("![alt text](https://example.com/a.png)", &display);
+        assert!(!html.contains("<img"));
+        assert!(html.contains("alt text"));
+    }
+
+    #[test]
+    fn test_mentions_are_linked() {
+        let alice = Mention {
+            username: "alice".to_string(),
+            user_id: uuid::Uuid::nil(),
+            profile_url: "https://api.example.com/v1/users/1".to_string(),
+        };
+        let display = DisplaySettings::default();
+
+        let html = render_markdown_with_mentions("hi @Alice and @bob, see https://x.com", &display, std::slice::from_ref(&alice));
+        assert!(html.contains(r#"<a href="https://api.example.com/v1/users/1" class="threadkit-mention" rel="nofollow ugc noopener">@Alice</a>"#));
+        assert!(html.contains("and @bob, see <a href=\"https://x.com\""));
+
+        // Not in code
+        let html = render_markdown_with_mentions("`@alice`", &display, std::slice::from_ref(&alice));
+        assert!(!html.contains("<a "));
+
+        // Plain text when links are off
+        let display = DisplaySettings {
+            allow_links: false,
+            ..DisplaySettings::default()
+        };
+        let html = render_markdown_with_mentions("hi @alice", &display, &[alice]);
+        assert_eq!(html, "<p>hi @alice</p>\n");
+    }
+}
@@ -0,0 +1,161 @@
+//! `@username` mentions in comment content
+//!
+//! Mentions are found in the comment's text outside of code and links, resolved to users
+//! through the username index, rendered as links to the user's profile and delivered to
+//! the mentioned users as `mention` notifications.
+
+use std::ops::Range;
+
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd, TextMergeStream};
+use uuid::Uuid;
+
+use crate::store::Storage;
+use crate::username::{normalize_username, MAX_USERNAME_LENGTH};
+use crate::Result;
+
+/// Most users a single comment can mention (later mentions are left as plain text)
+pub const MAX_MENTIONS_PER_COMMENT: usize = 5;
+
+/// Class on the links mentions are rendered as
+pub const MENTION_CLASS: &str = "threadkit-mention";
+
+/// A mention resolved to a user
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mention {
+    /// The username as written after `@`, lowercased
+    pub username: String,
+    pub user_id: Uuid,
+    /// Where the rendered mention links to
+    pub profile_url: String,
+}
+
+/// Byte ranges of `@username` tokens in plain text, including the `@`
+///
+/// A mention starts at a word boundary (so email addresses don't count) and runs over
+/// username characters, without trailing separators.
+pub fn mention_spans(text: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut offset = 0;
+
+    while let Some(pos) = text[offset..].find('@') {
+        let start = offset + pos;
+        let name_start = start + 1;
+        let at_boundary = text[..start]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !(c.is_alphanumeric() || "@_-.".contains(c)));
+
+        let rest = &text[name_start..];
+        let name_len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+            .unwrap_or(rest.len());
+        let name = rest[..name_len].trim_end_matches(['-', '_']);
+
+        let starts_ok = name.starts_with(|c: char| c.is_ascii_alphanumeric());
+        if at_boundary && starts_ok && name.len() <= MAX_USERNAME_LENGTH {
+            spans.push(start..name_start + name.len());
+        }
+        offset = name_start + name_len;
+    }
+
+    spans
+}
+
+/// Usernames mentioned in comment markdown, lowercased, in order and without duplicates
+pub fn parse_mentions(content: &str) -> Vec<String> {
+    let mut usernames: Vec<String> = Vec::new();
+    let mut link_depth = 0usize;
+    let mut code_depth = 0usize;
+
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+
+    for event in TextMergeStream::new(Parser::new_ext(content, options)) {
+        match event {
+            Event::Start(Tag::Link { .. }) => link_depth += 1,
+            Event::End(TagEnd::Link) => link_depth = link_depth.saturating_sub(1),
+            Event::Start(Tag::CodeBlock(_)) => code_depth += 1,
+            Event::End(TagEnd::CodeBlock) => code_depth = code_depth.saturating_sub(1),
+            Event::Text(text) if link_depth == 0 && code_depth == 0 => {
+                for span in mention_spans(&text) {
+                    let username = text[span.start + 1..span.end].to_lowercase();
+                    if !usernames.contains(&username) {
+                        usernames.push(username);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    usernames
+}
+
+/// Resolve the users mentioned in comment markdown, up to `MAX_MENTIONS_PER_COMMENT`
+///
+/// Names are looked up as written, then normalized (`@John_Smith` finds `john-smith`).
+/// Only the first `MAX_MENTIONS_PER_COMMENT` names are looked up, so a comment can't
+/// trigger unbounded lookups; names that don't belong to a user still count.
+pub async fn resolve_mentions(
+    store: &dyn Storage,
+    content: &str,
+    profile_base_url: &str,
+) -> Result<Vec<Mention>> {
+    let mut mentions: Vec<Mention> = Vec::new();
+
+    for username in parse_mentions(content).into_iter().take(MAX_MENTIONS_PER_COMMENT) {
+        let mut user_id = store.get_user_by_username(&username).await?;
+        let normalized = normalize_username(&username);
+        if user_id.is_none() && normalized != username {
+            user_id = store.get_user_by_username(&normalized).await?;
+        }
+
+        if let Some(user_id) = user_id {
+            mentions.push(Mention {
+                username,
+                user_id,
+                profile_url: format!("{}/v1/users/{}", profile_base_url, user_id),
+            });
+        }
+    }
+
+    Ok(mentions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(text: &str) -> Vec<&str> {
+        mention_spans(text).into_iter().map(|r| &text[r]).collect()
+    }
+
+    #[test]
+    fn test_mention_spans() {
+        assert_eq!(spans("@alice hi"), vec!["@alice"]);
+        assert_eq!(spans("hi @alice, and (@bob-smith)."), vec!["@alice", "@bob-smith"]);
+        assert_eq!(spans("thanks @carol_!"), vec!["@carol"]);
+
+        // Not mentions
+        assert!(spans("mail me at alice@example.com").is_empty());
+        assert!(spans("@ alone, @@double, @-dash").is_empty());
+        assert!(spans(&format!("@{}", "a".repeat(MAX_USERNAME_LENGTH + 1))).is_empty());
+    }
+
+    #[test]
+    fn test_parse_mentions() {
+        assert_eq!(
+            parse_mentions("@Alice and @bob, again @alice"),
+            vec!["alice".to_string(), "bob".to_string()]
+        );
+
+        // Not in code or links
+        assert!(parse_mentions("`@alice`").is_empty());
+        assert!(parse_mentions("```\n@alice\n```").is_empty());
+        assert!(parse_mentions("[@alice](https://example.com)").is_empty());
+
+        // But in other formatting
+        assert_eq!(parse_mentions("**@alice**"), vec!["alice".to_string()]);
+        assert_eq!(parse_mentions("@john_smith_ hi"), vec!["john_smith".to_string()]);
+    }
+}
@@ -0,0 +1,659 @@
+//! JSON-RPC 2.0 message types for real-time (WebSocket and SSE) communication.
+//!
+//! Subscriptions, typing and pings are notifications (no `id` field, no response expected).
+//! Comment writes (`post_comment`, `edit_comment`, `delete_comment`, `vote`) are requests:
+//! they carry an `id` and are answered with a response holding either a `result` or an
+//! `error`. Error codes follow JSON-RPC for protocol errors (-32600 to -32602) and use the
+//! matching HTTP status for everything else (e.g. 403 when posting is disabled).
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::comments::CommentError;
+use crate::types::{
+    CommentStatus, CreateCommentRequest, DeleteRequest, TreeComment, UpdateCommentRequest,
+    UserPublic, VoteRequest,
+};
+
+// ============================================================================
+// Client -> Server Messages
+// ============================================================================
+
+/// Raw JSON-RPC message from client
+#[derive(Debug, Deserialize)]
+pub struct ClientRpcMessage {
+    pub jsonrpc: String,
+    /// Present on requests that expect a response
+    #[serde(default)]
+    pub id: Option<serde_json::Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: serde_json::Value,
+}
+
+/// Parsed client message
+#[derive(Debug)]
+pub enum ClientMessage {
+    /// Subscribe to a page's real-time events, replaying any events after
+    /// `since_seq` first
+    Subscribe { page_id: Uuid, since_seq: Option<u64> },
+    /// Unsubscribe from a page
+    Unsubscribe { page_id: Uuid },
+    /// Subscribe to the site's moderation events (moderators only)
+    SubscribeModeration,
+    /// Unsubscribe from the site's moderation events
+    UnsubscribeModeration,
+    /// Typing indicator (should be sent every ~1s while typing)
+    Typing { page_id: Uuid, reply_to: Option<Uuid> },
+    /// Heartbeat
+    Ping,
+    /// Request expecting a response with the same `id`
+    Request { id: serde_json::Value, request: ClientRequest },
+}
+
+/// Comment writes made over the socket, handled by the shared comment service
+#[derive(Debug)]
+pub enum ClientRequest {
+    PostComment {
+        req: CreateCommentRequest,
+        turnstile_token: Option<String>,
+    },
+    EditComment {
+        comment_id: Uuid,
+        req: UpdateCommentRequest,
+    },
+    DeleteComment {
+        comment_id: Uuid,
+        req: DeleteRequest,
+    },
+    Vote {
+        comment_id: Uuid,
+        req: VoteRequest,
+    },
+}
+
+impl ClientMessage {
+    /// Parse a JSON-RPC message into a ClientMessage
+    pub fn from_rpc(rpc: ClientRpcMessage) -> Result<Self, RpcError> {
+        if rpc.jsonrpc != "2.0" {
+            return Err(RpcError::new(RpcError::INVALID_REQUEST, "Invalid JSON-RPC version"));
+        }
+
+        match rpc.method.as_str() {
+            "subscribe" => {
+                let page_id = rpc.params.get("page_id")
+                    .and_then(|v| v.as_str())
+                    .and_then(|s| s.parse().ok())
+                    .ok_or_else(|| RpcError::invalid_params("Missing or invalid page_id"))?;
+                let since_seq = match rpc.params.get("since_seq") {
+                    None | Some(serde_json::Value::Null) => None,
+                    Some(v) => Some(v.as_u64()
+                        .ok_or_else(|| RpcError::invalid_params("Invalid since_seq"))?),
+                };
+                Ok(ClientMessage::Subscribe { page_id, since_seq })
+            }
+            "unsubscribe" => {
+                let page_id = rpc.params.get("page_id")
+                    .and_then(|v| v.as_str())
+                    .and_then(|s| s.parse().ok())
+                    .ok_or_else(|| RpcError::invalid_params("Missing or invalid page_id"))?;
+                Ok(ClientMessage::Unsubscribe { page_id })
+            }
+            "subscribe_moderation" => Ok(ClientMessage::SubscribeModeration),
+            "unsubscribe_moderation" => Ok(ClientMessage::UnsubscribeModeration),
+            "typing" => {
+                let page_id = rpc.params.get("page_id")
+                    .and_then(|v| v.as_str())
+                    .and_then(|s| s.parse().ok())
+                    .ok_or_else(|| RpcError::invalid_params("Missing or invalid page_id"))?;
+                let reply_to = rpc.params.get("reply_to")
+                    .and_then(|v| v.as_str())
+                    .and_then(|s| s.parse().ok());
+                Ok(ClientMessage::Typing { page_id, reply_to })
+            }
+            "ping" => Ok(ClientMessage::Ping),
+            "post_comment" | "edit_comment" | "delete_comment" | "vote" => {
+                let id = rpc.id.clone()
+                    .ok_or_else(|| RpcError::new(RpcError::INVALID_REQUEST, "Missing id"))?;
+                let request = ClientRequest::from_rpc(&rpc.method, rpc.params)?;
+                Ok(ClientMessage::Request { id, request })
+            }
+            _ => Err(RpcError::new(RpcError::METHOD_NOT_FOUND, "Unknown method")),
+        }
+    }
+}
+
+impl ClientRequest {
+    fn from_rpc(method: &str, params: serde_json::Value) -> Result<Self, RpcError> {
+        fn parse<T: serde::de::DeserializeOwned>(params: &serde_json::Value) -> Result<T, RpcError> {
+            serde_json::from_value(params.clone())
+                .map_err(|e| RpcError::invalid_params(format!("Invalid params: {}", e)))
+        }
+
+        if method == "post_comment" {
+            let turnstile_token = params.get("turnstile_token")
+                .and_then(|v| v.as_str())
+                .map(String::from);
+            return Ok(ClientRequest::PostComment {
+                req: parse(&params)?,
+                turnstile_token,
+            });
+        }
+
+        let comment_id = params.get("comment_id")
+            .and_then(|v| v.as_str())
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| RpcError::invalid_params("Missing or invalid comment_id"))?;
+
+        match method {
+            "edit_comment" => Ok(ClientRequest::EditComment { comment_id, req: parse(&params)? }),
+            "delete_comment" => Ok(ClientRequest::DeleteComment { comment_id, req: parse(&params)? }),
+            "vote" => Ok(ClientRequest::Vote { comment_id, req: parse(&params)? }),
+            _ => Err(RpcError::new(RpcError::METHOD_NOT_FOUND, "Unknown method")),
+        }
+    }
+}
+
+/// JSON-RPC 2.0 error object
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(Self::INVALID_PARAMS, message)
+    }
+}
+
+impl From<CommentError> for RpcError {
+    fn from(e: CommentError) -> Self {
+        Self::new(i64::from(e.status_code()), e.to_string())
+    }
+}
+
+// ============================================================================
+// Server -> Client Messages
+// ============================================================================
+
+/// What a page event is about, used to decide which viewers receive it
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventSubject {
+    /// A comment by `author_id` with this status
+    Comment { author_id: Uuid, status: CommentStatus },
+    /// Something a user did on the page (typing, joining, leaving)
+    Activity { user_id: Uuid },
+}
+
+/// JSON-RPC 2.0 notification message (no id, no response expected)
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerMessage {
+    jsonrpc: &'static str,
+    method: &'static str,
+    params: serde_json::Value,
+    /// Server-side only, not sent to clients
+    #[serde(skip)]
+    subject: Option<EventSubject>,
+}
+
+impl ServerMessage {
+    fn new(method: &'static str, params: serde_json::Value) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            method,
+            params,
+            subject: None,
+        }
+    }
+
+    // === Connection Events ===
+
+    /// Connection established
+    pub fn connected(user_id: Option<Uuid>) -> Self {
+        Self::new("connected", serde_json::json!({
+            "user_id": user_id
+        }))
+    }
+
+    /// Error message
+    pub fn error(code: &str, message: &str) -> Self {
+        Self::new("error", serde_json::json!({
+            "code": code,
+            "message": message
+        }))
+    }
+
+    /// The connection's user was logged out (`session_revoked`, the socket is then
+    /// closed) or banned (`banned`, the socket stays open as anonymous)
+    pub fn signed_out(reason: &str) -> Self {
+        Self::new("signed_out", serde_json::json!({
+            "reason": reason
+        }))
+    }
+
+    /// Heartbeat response
+    pub fn pong() -> Self {
+        Self::new("pong", serde_json::json!({}))
+    }
+
+    /// Missed events could not be replayed; the client should refetch the page
+    pub fn resync_required(page_id: Uuid, seq: u64) -> Self {
+        Self::new("resync_required", serde_json::json!({
+            "page_id": page_id,
+            "seq": seq
+        }))
+    }
+
+    // === Presence Events ===
+
+    /// Current users on page (sent on subscribe)
+    pub fn presence(page_id: Uuid, users: Vec<UserPublic>) -> Self {
+        Self::new("presence", serde_json::json!({
+            "page_id": page_id,
+            "users": users
+        }))
+    }
+
+    /// User joined page
+    pub fn user_joined(page_id: Uuid, user: UserPublic) -> Self {
+        let subject = EventSubject::Activity { user_id: user.id };
+        Self::new("user_joined", serde_json::json!({
+            "page_id": page_id,
+            "user": user
+        }))
+        .with_subject(subject)
+    }
+
+    /// User left page
+    pub fn user_left(page_id: Uuid, user_id: Uuid) -> Self {
+        Self::new("user_left", serde_json::json!({
+            "page_id": page_id,
+            "user_id": user_id
+        }))
+        .with_subject(EventSubject::Activity { user_id })
+    }
+
+    // === Typing Events ===
+
+    /// Someone is typing (expires after 3s of no refresh)
+    pub fn typing(page_id: Uuid, user: UserPublic, reply_to: Option<Uuid>) -> Self {
+        let subject = EventSubject::Activity { user_id: user.id };
+        Self::new("typing", serde_json::json!({
+            "page_id": page_id,
+            "user": user,
+            "reply_to": reply_to
+        }))
+        .with_subject(subject)
+    }
+
+    // === Comment Events ===
+
+    /// New comment posted
+    pub fn new_comment(page_id: Uuid, comment: TreeComment) -> Self {
+        let subject = EventSubject::Comment {
+            author_id: comment.author_id,
+            status: comment.effective_status(),
+        };
+        Self::new("new_comment", serde_json::json!({
+            "page_id": page_id,
+            "comment": comment
+        }))
+        .with_subject(subject)
+    }
+
+    /// Comment edited
+    pub fn edit_comment(page_id: Uuid, comment_id: Uuid, content: String, content_html: String) -> Self {
+        Self::new("edit_comment", serde_json::json!({
+            "page_id": page_id,
+            "comment_id": comment_id,
+            "content": content,
+            "content_html": content_html
+        }))
+    }
+
+    /// Comment deleted
+    pub fn delete_comment(page_id: Uuid, comment_id: Uuid) -> Self {
+        Self::new("delete_comment", serde_json::json!({
+            "page_id": page_id,
+            "comment_id": comment_id
+        }))
+    }
+
+    /// Vote counts changed
+    pub fn vote_update(page_id: Uuid, comment_id: Uuid, upvotes: i64, downvotes: i64) -> Self {
+        Self::new("vote_update", serde_json::json!({
+            "page_id": page_id,
+            "comment_id": comment_id,
+            "upvotes": upvotes,
+            "downvotes": downvotes
+        }))
+    }
+
+    /// Reaction counts changed
+    pub fn reaction_update(
+        page_id: Uuid,
+        comment_id: Uuid,
+        reactions: BTreeMap<String, i64>,
+    ) -> Self {
+        Self::new("reaction_update", serde_json::json!({
+            "page_id": page_id,
+            "comment_id": comment_id,
+            "reactions": reactions
+        }))
+    }
+
+    // === Moderation Events ===
+
+    /// Subscribed to the site's moderation events
+    pub fn moderation_subscribed(site_id: Uuid) -> Self {
+        Self::new("moderation_subscribed", serde_json::json!({
+            "site_id": site_id
+        }))
+    }
+
+    /// Site-wide moderation event, `data` fields become the params
+    pub fn moderation_event(method: &'static str, site_id: Uuid, data: serde_json::Value) -> Self {
+        let mut params = match data {
+            serde_json::Value::Object(map) => map,
+            _ => serde_json::Map::new(),
+        };
+        params.insert("site_id".to_string(), site_id.to_string().into());
+        Self::new(method, serde_json::Value::Object(params))
+    }
+
+    // === Notification Events ===
+
+    /// User notification (reply, mention)
+    pub fn notification(
+        notification_type: &str,
+        comment_id: Uuid,
+        from_user: UserPublic,
+        page_url: Option<String>,
+    ) -> Self {
+        Self::new("notification", serde_json::json!({
+            "type": notification_type,
+            "comment_id": comment_id,
+            "from_user": from_user,
+            "page_url": page_url
+        }))
+    }
+
+    /// Attach the page event sequence number
+    pub fn with_seq(mut self, seq: u64) -> Self {
+        if let Some(params) = self.params.as_object_mut() {
+            params.insert("seq".to_string(), seq.into());
+        }
+        self
+    }
+
+    /// Attach what the event is about, for per-viewer filtering
+    pub fn with_subject(mut self, subject: EventSubject) -> Self {
+        self.subject = Some(subject);
+        self
+    }
+
+    /// What the event is about, if known
+    pub fn subject(&self) -> Option<&EventSubject> {
+        self.subject.as_ref()
+    }
+
+    /// Page event sequence number, if this message carries one
+    pub fn seq(&self) -> Option<u64> {
+        self.params.get("seq").and_then(|v| v.as_u64())
+    }
+
+    /// Serialize to JSON string
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+}
+
+/// JSON-RPC 2.0 response to a client request
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerResponse {
+    jsonrpc: &'static str,
+    id: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+impl ServerResponse {
+    /// Successful response
+    pub fn result(id: serde_json::Value, result: serde_json::Value) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    /// Error response
+    pub fn error(id: serde_json::Value, error: RpcError) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result: None,
+            error: Some(error),
+        }
+    }
+
+    /// Serialize to JSON string
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_subscribe() {
+        let rpc = ClientRpcMessage {
+            jsonrpc: "2.0".to_string(),
+            id: None,
+            method: "subscribe".to_string(),
+            params: serde_json::json!({
+                "page_id": "550e8400-e29b-41d4-a716-446655440000"
+            }),
+        };
+
+        let msg = ClientMessage::from_rpc(rpc).unwrap();
+        match msg {
+            ClientMessage::Subscribe { page_id, since_seq } => {
+                assert_eq!(page_id.to_string(), "550e8400-e29b-41d4-a716-446655440000");
+                assert_eq!(since_seq, None);
+            }
+            _ => panic!("Expected Subscribe"),
+        }
+    }
+
+    #[test]
+    fn test_parse_subscribe_since_seq() {
+        let rpc = ClientRpcMessage {
+            jsonrpc: "2.0".to_string(),
+            id: None,
+            method: "subscribe".to_string(),
+            params: serde_json::json!({
+                "page_id": "550e8400-e29b-41d4-a716-446655440000",
+                "since_seq": 42
+            }),
+        };
+
+        match ClientMessage::from_rpc(rpc).unwrap() {
+            ClientMessage::Subscribe { since_seq, .. } => assert_eq!(since_seq, Some(42)),
+            _ => panic!("Expected Subscribe"),
+        }
+
+        let rpc = ClientRpcMessage {
+            jsonrpc: "2.0".to_string(),
+            id: None,
+            method: "subscribe".to_string(),
+            params: serde_json::json!({
+                "page_id": "550e8400-e29b-41d4-a716-446655440000",
+                "since_seq": "latest"
+            }),
+        };
+        let err = ClientMessage::from_rpc(rpc).unwrap_err();
+        assert_eq!(err.code, RpcError::INVALID_PARAMS);
+    }
+
+    #[test]
+    fn test_parse_subscribe_moderation() {
+        let rpc = ClientRpcMessage {
+            jsonrpc: "2.0".to_string(),
+            id: None,
+            method: "subscribe_moderation".to_string(),
+            params: serde_json::Value::Null,
+        };
+        assert!(matches!(ClientMessage::from_rpc(rpc).unwrap(), ClientMessage::SubscribeModeration));
+    }
+
+    #[test]
+    fn test_moderation_event_params() {
+        let site_id = Uuid::now_v7();
+        let user_id = Uuid::now_v7();
+        let msg = ServerMessage::moderation_event(
+            "user_banned",
+            site_id,
+            serde_json::json!({ "user_id": user_id, "shadowban": false }),
+        );
+        let json: serde_json::Value = serde_json::from_str(&msg.to_json().unwrap()).unwrap();
+        assert_eq!(json["method"], "user_banned");
+        assert_eq!(json["params"]["site_id"], site_id.to_string());
+        assert_eq!(json["params"]["user_id"], user_id.to_string());
+        assert_eq!(json["params"]["shadowban"], false);
+    }
+
+    #[test]
+    fn test_with_seq() {
+        let page_id = Uuid::now_v7();
+        let msg = ServerMessage::delete_comment(page_id, Uuid::now_v7());
+        assert_eq!(msg.seq(), None);
+
+        let msg = msg.with_seq(7);
+        assert_eq!(msg.seq(), Some(7));
+        let json: serde_json::Value = serde_json::from_str(&msg.to_json().unwrap()).unwrap();
+        assert_eq!(json["params"]["seq"], 7);
+        assert_eq!(json["method"], "delete_comment");
+    }
+
+    #[test]
+    fn test_parse_typing() {
+        let rpc = ClientRpcMessage {
+            jsonrpc: "2.0".to_string(),
+            id: None,
+            method: "typing".to_string(),
+            params: serde_json::json!({
+                "page_id": "550e8400-e29b-41d4-a716-446655440000",
+                "reply_to": "660e8400-e29b-41d4-a716-446655440001"
+            }),
+        };
+
+        let msg = ClientMessage::from_rpc(rpc).unwrap();
+        match msg {
+            ClientMessage::Typing { page_id, reply_to } => {
+                assert_eq!(page_id.to_string(), "550e8400-e29b-41d4-a716-446655440000");
+                assert!(reply_to.is_some());
+            }
+            _ => panic!("Expected Typing"),
+        }
+    }
+
+    #[test]
+    fn test_server_message_json() {
+        let msg = ServerMessage::connected(Some(Uuid::nil()));
+        let json = msg.to_json().unwrap();
+        assert!(json.contains("\"jsonrpc\":\"2.0\""));
+        assert!(json.contains("\"method\":\"connected\""));
+    }
+
+    #[test]
+    fn test_error_message() {
+        let msg = ServerMessage::error("rate_limit", "Too many requests");
+        let json = msg.to_json().unwrap();
+        assert!(json.contains("\"code\":\"rate_limit\""));
+        assert!(json.contains("\"message\":\"Too many requests\""));
+    }
+
+    #[test]
+    fn test_parse_vote_request() {
+        let rpc = ClientRpcMessage {
+            jsonrpc: "2.0".to_string(),
+            id: Some(serde_json::json!(7)),
+            method: "vote".to_string(),
+            params: serde_json::json!({
+                "comment_id": "550e8400-e29b-41d4-a716-446655440000",
+                "page_url": "https://example.com/post",
+                "direction": "up",
+                "path": ["550e8400-e29b-41d4-a716-446655440000"]
+            }),
+        };
+
+        match ClientMessage::from_rpc(rpc).unwrap() {
+            ClientMessage::Request { id, request: ClientRequest::Vote { comment_id, req } } => {
+                assert_eq!(id, serde_json::json!(7));
+                assert_eq!(comment_id.to_string(), "550e8400-e29b-41d4-a716-446655440000");
+                assert_eq!(req.path, vec![comment_id]);
+            }
+            other => panic!("Expected Vote request, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_request_requires_id() {
+        let rpc = ClientRpcMessage {
+            jsonrpc: "2.0".to_string(),
+            id: None,
+            method: "post_comment".to_string(),
+            params: serde_json::json!({
+                "page_url": "https://example.com/post",
+                "content": "Hello"
+            }),
+        };
+
+        let err = ClientMessage::from_rpc(rpc).unwrap_err();
+        assert_eq!(err.code, RpcError::INVALID_REQUEST);
+    }
+
+    #[test]
+    fn test_request_invalid_params() {
+        let rpc = ClientRpcMessage {
+            jsonrpc: "2.0".to_string(),
+            id: Some(serde_json::json!("a")),
+            method: "edit_comment".to_string(),
+            params: serde_json::json!({
+                "comment_id": "550e8400-e29b-41d4-a716-446655440000"
+            }),
+        };
+
+        let err = ClientMessage::from_rpc(rpc).unwrap_err();
+        assert_eq!(err.code, RpcError::INVALID_PARAMS);
+    }
+
+    #[test]
+    fn test_error_response_json() {
+        let err = RpcError::from(CommentError::Forbidden("Posting is currently disabled".into()));
+        let json = ServerResponse::error(serde_json::json!(1), err).to_json().unwrap();
+        assert!(json.contains("\"id\":1"));
+        assert!(json.contains("\"code\":403"));
+        assert!(!json.contains("\"result\""));
+    }
+}
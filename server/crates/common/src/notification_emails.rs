@@ -0,0 +1,281 @@
+//! Delivery of reply and mention notifications, including email
+//!
+//! Notifications always go to the in-app list in the store first, and to the user's open
+//! WebSocket connections to the site the comment is on. Users who opted in to emails are
+//! also put on a Redis queue, scored by when their next email is due: shortly after the
+//! notification for `immediate`, a day later for `daily`. The worker emails everything
+//! unread since the user's last email (one email per site, so each comes from the site's
+//! sender name) and moves the user's cursor past it.
+//!
+//! Every email links to a signed unsubscribe URL that works without logging in.
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::email::{Mailer, NotificationItem};
+use crate::redis::RedisClient;
+use crate::store::Storage;
+use crate::types::{EmailNotificationFrequency, Notification, User, UserPublic};
+use crate::Result;
+
+/// Users claimed from the queue per poll
+const CLAIM_BATCH_SIZE: usize = 50;
+/// How often the worker polls the queue
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a claimed user is held before another worker may retry them
+const LEASE_MS: i64 = 5 * 60 * 1000;
+/// Time between daily digests
+const DIGEST_INTERVAL_MS: i64 = 24 * 3600 * 1000;
+/// Most notifications listed in one email
+const MAX_EMAILED: i64 = 50;
+/// Notification excerpts are cut to this many characters
+pub const EXCERPT_LENGTH: usize = 200;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Store a notification, push it to the user's WebSocket connections and queue an email
+/// about it if the user wants one
+pub async fn notify(
+    redis: &RedisClient,
+    store: &dyn Storage,
+    config: &Config,
+    user_id: Uuid,
+    notification: &Notification,
+) -> Result<()> {
+    store.add_notification(user_id, notification).await?;
+
+    if let Some(site_id) = notification.site_id
+        && let Some(from_user) = store.get_user(notification.from_user_id).await?
+    {
+        let data = serde_json::json!({
+            "type": notification.notification_type,
+            "comment_id": notification.comment_id,
+            "from_user": UserPublic::from(from_user),
+            "page_url": notification.page_url,
+        });
+        if let Err(e) = redis.publish_notification_event(user_id, site_id, data).await {
+            tracing::warn!(user_id = %user_id, "Failed to publish notification event: {}", e);
+        }
+    }
+
+    let Some(user) = store.get_user(user_id).await? else {
+        return Ok(());
+    };
+    if !wants_emails(&user) {
+        return Ok(());
+    }
+
+    let now_ms = Utc::now().timestamp_millis();
+    let due_at_ms = match user.email_notifications {
+        EmailNotificationFrequency::Daily => now_ms + DIGEST_INTERVAL_MS,
+        _ => now_ms + config.email.notification_delay_seconds as i64 * 1000,
+    };
+    redis.schedule_notification_email(user_id, due_at_ms).await
+}
+
+/// Whether notifications should be emailed to the user at all
+fn wants_emails(user: &User) -> bool {
+    user.email_notifications != EmailNotificationFrequency::Off
+        && user.email_verified
+        && user.email.is_some()
+}
+
+/// Cut text to `EXCERPT_LENGTH` characters for a notification
+pub fn excerpt(text: &str) -> String {
+    let text = text.trim();
+    if text.chars().count() <= EXCERPT_LENGTH {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(EXCERPT_LENGTH).collect();
+    format!("{}…", cut.trim_end())
+}
+
+/// Hex-encoded HMAC-SHA256 proving an unsubscribe link was issued for this user
+pub fn unsubscribe_token(secret: &str, user_id: Uuid) -> String {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(b"unsubscribe:");
+    mac.update(user_id.to_string().as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Check an unsubscribe link's token in constant time
+pub fn verify_unsubscribe_token(secret: &str, user_id: Uuid, token: &str) -> bool {
+    let Ok(token) = hex::decode(token) else {
+        return false;
+    };
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(b"unsubscribe:");
+    mac.update(user_id.to_string().as_bytes());
+    mac.verify_slice(&token).is_ok()
+}
+
+/// Sends queued notification emails
+pub struct NotificationEmailer {
+    redis: Arc<RedisClient>,
+    store: Arc<dyn Storage>,
+    mailer: Arc<Mailer>,
+    config: Arc<Config>,
+}
+
+impl NotificationEmailer {
+    pub fn new(
+        redis: Arc<RedisClient>,
+        store: Arc<dyn Storage>,
+        mailer: Arc<Mailer>,
+        config: Arc<Config>,
+    ) -> Self {
+        Self { redis, store, mailer, config }
+    }
+
+    /// Link that turns off notification emails for a user
+    pub fn unsubscribe_url(&self, user_id: Uuid) -> String {
+        format!(
+            "{}/v1/notifications/unsubscribe?user_id={}&token={}",
+            self.config.email.public_api_url,
+            user_id,
+            unsubscribe_token(&self.config.jwt_secret, user_id)
+        )
+    }
+
+    /// Send emails for every user that is due. Returns the number of users processed.
+    pub async fn process_due(&self) -> Result<usize> {
+        let now_ms = Utc::now().timestamp_millis();
+        let user_ids = self
+            .redis
+            .claim_notification_emails(now_ms, now_ms + LEASE_MS, CLAIM_BATCH_SIZE)
+            .await?;
+
+        let sends = user_ids.iter().map(|&user_id| async move {
+            if let Err(e) = self.send_for_user(user_id).await {
+                tracing::warn!(user_id = %user_id, "Failed to send notification email: {}", e);
+            }
+        });
+        futures_util::future::join_all(sends).await;
+
+        Ok(user_ids.len())
+    }
+
+    /// Spawn the background worker that drains the queue
+    pub fn start(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.process_due().await {
+                    tracing::warn!("Notification email worker error: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Email a claimed user everything unread since their last email
+    async fn send_for_user(&self, user_id: Uuid) -> anyhow::Result<()> {
+        // Take the user off the queue first: a notification arriving while this runs
+        // queues them again, and is picked up by the next run
+        self.redis.remove_notification_email(user_id).await?;
+
+        let Some(user) = self.store.get_user(user_id).await? else {
+            return Ok(());
+        };
+        let Some(email) = user.email.as_deref().filter(|_| wants_emails(&user)) else {
+            return Ok(());
+        };
+
+        let cursor = self.redis.get_notification_email_cursor(user_id).await?;
+        let unread = self.store.get_unread_count(user_id).await?.clamp(0, MAX_EMAILED) as usize;
+        if unread == 0 {
+            return Ok(());
+        }
+        let pending: Vec<Notification> = self
+            .store
+            .get_notifications(user_id, 0, unread)
+            .await?
+            .into_iter()
+            .filter(|n| n.created_at.timestamp_millis() > cursor)
+            .collect();
+        let Some(newest) = pending.iter().map(|n| n.created_at.timestamp_millis()).max() else {
+            return Ok(());
+        };
+
+        // Oldest first, grouped by the site the comment is on
+        let mut by_site: BTreeMap<Uuid, Vec<Notification>> = BTreeMap::new();
+        for notification in pending.into_iter().rev() {
+            if let Some(site_id) = notification.site_id {
+                by_site.entry(site_id).or_default().push(notification);
+            }
+        }
+
+        let unsubscribe_url = self.unsubscribe_url(user_id);
+        for (site_id, notifications) in by_site {
+            let Some(site) = self.redis.get_site_config(site_id).await? else {
+                continue;
+            };
+
+            let mut items = Vec::new();
+            for notification in notifications {
+                if !site.settings.email.emails(&notification.notification_type) {
+                    continue;
+                }
+                let from_name = self
+                    .store
+                    .get_user(notification.from_user_id)
+                    .await?
+                    .map(|u| u.name)
+                    .unwrap_or_else(|| "Someone".to_string());
+                items.push(NotificationItem {
+                    notification_type: notification.notification_type,
+                    from_name,
+                    excerpt: notification.excerpt.unwrap_or_default(),
+                    page_url: notification.page_url.unwrap_or_else(|| format!("https://{}", site.domain)),
+                });
+            }
+
+            if items.is_empty() {
+                continue;
+            }
+            if let Err(e) = self.mailer.send_notifications(&site, email, &items, &unsubscribe_url).await {
+                // Try again later (sites already emailed in this run are emailed again)
+                let retry_at = Utc::now().timestamp_millis() + LEASE_MS;
+                self.redis.schedule_notification_email(user_id, retry_at).await?;
+                return Err(e);
+            }
+        }
+
+        self.redis.set_notification_email_cursor(user_id, newest).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unsubscribe_token() {
+        let user_id = Uuid::now_v7();
+        let token = unsubscribe_token("secret", user_id);
+
+        assert!(verify_unsubscribe_token("secret", user_id, &token));
+        assert!(!verify_unsubscribe_token("other-secret", user_id, &token));
+        assert!(!verify_unsubscribe_token("secret", Uuid::now_v7(), &token));
+        assert!(!verify_unsubscribe_token("secret", user_id, "not-hex"));
+    }
+
+    #[test]
+    fn test_excerpt() {
+        assert_eq!(excerpt("  short  "), "short");
+
+        let long = "word ".repeat(100);
+        let cut = excerpt(&long);
+        assert!(cut.ends_with('…'));
+        assert!(cut.chars().count() <= EXCERPT_LENGTH + 1);
+    }
+}
@@ -0,0 +1,497 @@
+//! Redis Pub/Sub subscriber for receiving events from the HTTP server and
+//! WebSocket nodes, used by both the WebSocket server and SSE streams.
+//!
+//! Subscribes to `threadkit:page:*:events`, `threadkit:site:*:moderation`,
+//! `threadkit:user:*:notifications` and `threadkit:auth:events` and relays messages to
+//! in-memory broadcast channels.
+
+use dashmap::DashMap;
+use fred::prelude::*;
+use fred::types::Message as RedisMessage;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::{
+    redis::{PageEventReplay, RedisClient},
+    types::{AuthEvent, CommentStatus, UserPublic},
+};
+
+use crate::messages::{EventSubject, ServerMessage};
+
+/// Event payload from Redis Pub/Sub
+/// Note: page_id is extracted from the Redis channel name (threadkit:page:{page_id}:events)
+/// rather than from the event payload, so it's not included in this struct.
+#[derive(Debug, serde::Deserialize)]
+struct PubSubEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    /// Per-page sequence number (absent for events published before sequencing)
+    #[serde(default)]
+    seq: Option<u64>,
+    data: serde_json::Value,
+}
+
+/// Redis Pub/Sub subscriber
+///
+/// Subscribes to Redis pub/sub channels and relays events to in-memory broadcast channels.
+/// Uses fred's SubscriberClient for efficient pub/sub handling.
+pub struct PubSubSubscriber {
+    redis_url: String,
+    page_channels: Arc<DashMap<Uuid, broadcast::Sender<ServerMessage>>>,
+    moderation_channels: Arc<DashMap<Uuid, broadcast::Sender<ServerMessage>>>,
+    notification_channels: Arc<DashMap<(Uuid, Uuid), broadcast::Sender<ServerMessage>>>,
+    auth_events: broadcast::Sender<AuthEvent>,
+    /// Called with the page of every page event (see `on_page_event`)
+    page_event_hook: Option<Arc<dyn Fn(Uuid) + Send + Sync>>,
+}
+
+/// Site moderation event payload from Redis Pub/Sub (site_id comes from the channel name)
+#[derive(Debug, serde::Deserialize)]
+struct ModerationEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    data: serde_json::Value,
+}
+
+/// User notification payload from Redis Pub/Sub (user_id comes from the channel name)
+#[derive(Debug, serde::Deserialize)]
+struct NotificationEvent {
+    site_id: Uuid,
+    data: NotificationData,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct NotificationData {
+    #[serde(rename = "type")]
+    notification_type: String,
+    comment_id: Uuid,
+    from_user: UserPublic,
+    #[serde(default)]
+    page_url: Option<String>,
+}
+
+impl PubSubSubscriber {
+    /// Create a new subscriber
+    pub fn new(
+        redis_url: String,
+        page_channels: Arc<DashMap<Uuid, broadcast::Sender<ServerMessage>>>,
+        moderation_channels: Arc<DashMap<Uuid, broadcast::Sender<ServerMessage>>>,
+        notification_channels: Arc<DashMap<(Uuid, Uuid), broadcast::Sender<ServerMessage>>>,
+        auth_events: broadcast::Sender<AuthEvent>,
+    ) -> Self {
+        Self {
+            redis_url,
+            page_channels,
+            moderation_channels,
+            notification_channels,
+            auth_events,
+            page_event_hook: None,
+        }
+    }
+
+    /// Also call `hook` with the page of every page event, subscribed or not
+    ///
+    /// Lets a server drop state it keeps per page when any node changes the page.
+    pub fn on_page_event(mut self, hook: impl Fn(Uuid) + Send + Sync + 'static) -> Self {
+        self.page_event_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Start the subscriber loop
+    ///
+    /// This subscribes to `threadkit:page:*:events` using pattern subscription
+    /// and relays received messages to the appropriate broadcast channels.
+    pub fn start(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = self.run().await {
+                    tracing::error!("PubSub subscriber error: {}, reconnecting in 5s...", e);
+                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                }
+            }
+        })
+    }
+
+    async fn run(&self) -> anyhow::Result<()> {
+        // Create a dedicated subscriber client
+        let config = Config::from_url(&self.redis_url)?;
+        let subscriber = Builder::from_config(config).build_subscriber_client()?;
+
+        // Initialize the connection
+        subscriber.init().await?;
+        tracing::info!("PubSub subscriber connected to Redis");
+
+        // Get the message receiver before subscribing
+        let mut message_rx = subscriber.message_rx();
+
+        // Subscribe to all page events using pattern matching
+        // Pattern: threadkit:page:*:events
+        subscriber.psubscribe("threadkit:page:*:events").await?;
+        tracing::info!("Subscribed to pattern: threadkit:page:*:events");
+
+        // Site-wide moderation events for moderator dashboards
+        subscriber.psubscribe("threadkit:site:*:moderation").await?;
+        tracing::info!("Subscribed to pattern: threadkit:site:*:moderation");
+
+        // Notifications for users connected to this node
+        subscriber.psubscribe("threadkit:user:*:notifications").await?;
+        tracing::info!("Subscribed to pattern: threadkit:user:*:notifications");
+
+        // Logouts and bans, applied to open connections
+        subscriber.subscribe("threadkit:auth:events").await?;
+        tracing::info!("Subscribed to channel: threadkit:auth:events");
+
+        // Spawn a task to manage re-subscriptions after reconnects
+        subscriber.manage_subscriptions();
+
+        // Process incoming messages
+        while let Ok(message) = message_rx.recv().await {
+            self.handle_message(&message);
+        }
+
+        // If we reach here, the message receiver closed - this means Redis connection was lost
+        tracing::warn!("PubSub message receiver closed, triggering reconnection");
+        Err(anyhow::anyhow!("Message receiver closed, Redis connection lost"))
+    }
+
+    /// Handle a received pub/sub message
+    fn handle_message(&self, message: &RedisMessage) {
+        // Get the channel name (for pattern subscriptions, this is the actual channel that matched)
+        let channel = message.channel.to_string();
+
+        if channel == "threadkit:auth:events" {
+            self.handle_auth_message(message);
+            return;
+        }
+
+        // Parse channel to extract page_id: "threadkit:page:{page_id}:events"
+        let parts: Vec<&str> = channel.split(':').collect();
+        if parts.len() == 4 && parts[0] == "threadkit" && parts[1] == "site" && parts[3] == "moderation" {
+            self.handle_moderation_message(parts[2], message);
+            return;
+        }
+        if parts.len() == 4 && parts[0] == "threadkit" && parts[1] == "user" && parts[3] == "notifications" {
+            self.handle_notification_message(parts[2], message);
+            return;
+        }
+        if parts.len() != 4 || parts[0] != "threadkit" || parts[1] != "page" || parts[3] != "events" {
+            tracing::debug!("Ignoring message from unexpected channel: {}", channel);
+            return;
+        }
+
+        let page_id = match parts[2].parse::<Uuid>() {
+            Ok(id) => id,
+            Err(_) => {
+                tracing::debug!("Failed to parse page_id from channel: {}", channel);
+                return;
+            }
+        };
+
+        if let Some(hook) = &self.page_event_hook {
+            hook(page_id);
+        }
+
+        // Get the message payload - convert Value to string
+        let payload: String = match message.value.clone().convert() {
+            Ok(s) => s,
+            Err(_) => {
+                tracing::debug!("Message value is not a string");
+                return;
+            }
+        };
+
+        if let Some(msg) = parse_event(page_id, &payload) {
+            self.broadcast(page_id, msg);
+        }
+    }
+
+    /// Handle a site moderation event: "threadkit:site:{site_id}:moderation"
+    fn handle_moderation_message(&self, site_id: &str, message: &RedisMessage) {
+        let Ok(site_id) = site_id.parse::<Uuid>() else {
+            tracing::debug!("Failed to parse site_id from moderation channel: {}", site_id);
+            return;
+        };
+
+        let payload: String = match message.value.clone().convert() {
+            Ok(s) => s,
+            Err(_) => {
+                tracing::debug!("Message value is not a string");
+                return;
+            }
+        };
+
+        let event: ModerationEvent = match serde_json::from_str(&payload) {
+            Ok(e) => e,
+            Err(e) => {
+                tracing::warn!("Failed to parse moderation event: {} - payload: {}", e, payload);
+                return;
+            }
+        };
+
+        let method = match event.event_type.as_str() {
+            "modqueue_added" => "modqueue_added",
+            "report_created" => "report_created",
+            "comment_flagged_by_ai" => "comment_flagged_by_ai",
+            "user_banned" => "user_banned",
+            _ => {
+                tracing::debug!("Unknown moderation event type: {}", event.event_type);
+                return;
+            }
+        };
+
+        // No receivers - no moderator is watching this site, that's ok
+        if let Some(tx) = self.moderation_channels.get(&site_id) {
+            let _ = tx.send(ServerMessage::moderation_event(method, site_id, event.data));
+        }
+    }
+
+    /// Handle a user notification: "threadkit:user:{user_id}:notifications"
+    fn handle_notification_message(&self, user_id: &str, message: &RedisMessage) {
+        let Ok(user_id) = user_id.parse::<Uuid>() else {
+            tracing::debug!("Failed to parse user_id from notification channel: {}", user_id);
+            return;
+        };
+
+        let payload: String = match message.value.clone().convert() {
+            Ok(s) => s,
+            Err(_) => {
+                tracing::debug!("Message value is not a string");
+                return;
+            }
+        };
+
+        let event: NotificationEvent = match serde_json::from_str(&payload) {
+            Ok(e) => e,
+            Err(e) => {
+                tracing::warn!("Failed to parse notification event: {} - payload: {}", e, payload);
+                return;
+            }
+        };
+
+        // No receivers - the user isn't connected to this site here, that's ok
+        if let Some(tx) = self.notification_channels.get(&(event.site_id, user_id)) {
+            let data = event.data;
+            let _ = tx.send(ServerMessage::notification(
+                &data.notification_type,
+                data.comment_id,
+                data.from_user,
+                data.page_url,
+            ));
+        }
+    }
+
+    /// Handle a logout or ban published by the HTTP server
+    fn handle_auth_message(&self, message: &RedisMessage) {
+        let payload: String = match message.value.clone().convert() {
+            Ok(s) => s,
+            Err(_) => {
+                tracing::debug!("Message value is not a string");
+                return;
+            }
+        };
+
+        match serde_json::from_str::<AuthEvent>(&payload) {
+            // No receivers - no connections open, that's ok
+            Ok(event) => {
+                let _ = self.auth_events.send(event);
+            }
+            Err(e) => {
+                tracing::warn!("Failed to parse auth event: {} - payload: {}", e, payload);
+            }
+        }
+    }
+
+    /// Broadcast a message to a page's subscribers
+    fn broadcast(&self, page_id: Uuid, message: ServerMessage) {
+        if let Some(tx) = self.page_channels.get(&page_id) {
+            match tx.send(message) {
+                Ok(n) => {
+                    tracing::trace!("Broadcast to {} receivers on page {}", n, page_id);
+                }
+                Err(_) => {
+                    // No receivers - channel might be empty, that's ok
+                }
+            }
+        } else {
+            // No channel for this page - no one is subscribed, that's ok
+            tracing::trace!("No broadcast channel for page {}", page_id);
+        }
+    }
+}
+
+/// Convert a published page event payload into a `ServerMessage`
+///
+/// Shared by live pub/sub relaying and missed-event replay. The event's
+/// sequence number, if any, is carried over as the message's `seq` param.
+pub fn parse_event(page_id: Uuid, payload: &str) -> Option<ServerMessage> {
+    // Parse event payload
+    let event: PubSubEvent = match serde_json::from_str(payload) {
+        Ok(e) => e,
+        Err(e) => {
+            tracing::warn!("Failed to parse pub/sub event: {} - payload: {}", e, payload);
+            return None;
+        }
+    };
+
+    // Convert to ServerMessage
+    let message = match event.event_type.as_str() {
+        "new_comment" => {
+            if let Ok(comment) = serde_json::from_value(event.data.get("comment").cloned().unwrap_or_default()) {
+                Some(ServerMessage::new_comment(page_id, comment))
+            } else {
+                tracing::debug!("Failed to parse new_comment data");
+                None
+            }
+        }
+        "edit_comment" => {
+            let comment_id = event.data.get("comment_id")
+                .and_then(|v| v.as_str())
+                .and_then(|s| s.parse().ok());
+            let content = event.data.get("content")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+            let content_html = event.data.get("content_html")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+
+            match (comment_id, content, content_html) {
+                (Some(cid), Some(c), Some(ch)) => {
+                    Some(ServerMessage::edit_comment(page_id, cid, c, ch))
+                }
+                _ => {
+                    tracing::debug!("Failed to parse edit_comment data");
+                    None
+                }
+            }
+        }
+        "delete_comment" => {
+            event.data.get("comment_id")
+                .and_then(|v| v.as_str())
+                .and_then(|s| s.parse().ok())
+                .map(|cid| ServerMessage::delete_comment(page_id, cid))
+        }
+        "vote_update" => {
+            let comment_id = event.data.get("comment_id")
+                .and_then(|v| v.as_str())
+                .and_then(|s| s.parse().ok());
+            let upvotes = event.data.get("upvotes")
+                .and_then(|v| v.as_i64());
+            let downvotes = event.data.get("downvotes")
+                .and_then(|v| v.as_i64());
+
+            match (comment_id, upvotes, downvotes) {
+                (Some(cid), Some(u), Some(d)) => {
+                    Some(ServerMessage::vote_update(page_id, cid, u, d))
+                }
+                _ => {
+                    tracing::debug!("Failed to parse vote_update data");
+                    None
+                }
+            }
+        }
+        "reaction_update" => {
+            let comment_id = event.data.get("comment_id")
+                .and_then(|v| v.as_str())
+                .and_then(|s| s.parse().ok());
+            let reactions = event.data.get("reactions")
+                .and_then(|v| serde_json::from_value(v.clone()).ok());
+
+            match (comment_id, reactions) {
+                (Some(cid), Some(r)) => {
+                    Some(ServerMessage::reaction_update(page_id, cid, r))
+                }
+                _ => {
+                    tracing::debug!("Failed to parse reaction_update data");
+                    None
+                }
+            }
+        }
+        // Presence and typing activity, published by WebSocket nodes
+        "user_joined" => {
+            match serde_json::from_value(event.data.get("user").cloned().unwrap_or_default()) {
+                Ok(user) => Some(ServerMessage::user_joined(page_id, user)),
+                Err(_) => {
+                    tracing::debug!("Failed to parse user_joined data");
+                    None
+                }
+            }
+        }
+        "user_left" => {
+            event.data.get("user_id")
+                .and_then(|v| v.as_str())
+                .and_then(|s| s.parse().ok())
+                .map(|uid| ServerMessage::user_left(page_id, uid))
+        }
+        "typing" => {
+            let user = serde_json::from_value(event.data.get("user").cloned().unwrap_or_default()).ok();
+            let reply_to = event.data.get("reply_to")
+                .and_then(|v| v.as_str())
+                .and_then(|s| s.parse().ok());
+
+            match user {
+                Some(user) => Some(ServerMessage::typing(page_id, user, reply_to)),
+                None => {
+                    tracing::debug!("Failed to parse typing data");
+                    None
+                }
+            }
+        }
+        _ => {
+            tracing::debug!("Unknown event type: {}", event.event_type);
+            None
+        }
+    };
+
+    // Events about an existing comment name its author and status for per-viewer filtering
+    let message = message.map(|m| match comment_subject(&event.data) {
+        Some(subject) if m.subject().is_none() => m.with_subject(subject),
+        _ => m,
+    });
+
+    match event.seq {
+        Some(seq) => message.map(|m| m.with_seq(seq)),
+        None => message,
+    }
+}
+
+/// Load a page's events published after `since_seq` for a reconnecting client
+///
+/// Returns the messages to send and the highest replayed sequence number.
+/// When the missed events are no longer in the page's event log this is a
+/// single `resync_required` message and nothing counts as replayed.
+pub async fn replay_events(
+    redis: &RedisClient,
+    page_id: Uuid,
+    since_seq: u64,
+) -> (Vec<ServerMessage>, u64) {
+    match redis.get_page_events_since(page_id, since_seq).await {
+        Ok(PageEventReplay::Events(events)) => {
+            let messages: Vec<ServerMessage> = events
+                .iter()
+                .filter_map(|payload| parse_event(page_id, payload))
+                .collect();
+            let through = messages.iter().filter_map(|m| m.seq()).max().unwrap_or(since_seq);
+            (messages, through)
+        }
+        Ok(PageEventReplay::Gap { latest_seq }) => {
+            (vec![ServerMessage::resync_required(page_id, latest_seq)], 0)
+        }
+        Err(e) => {
+            tracing::warn!("Failed to load events for replay on page {}: {}", page_id, e);
+            let latest_seq = redis.get_page_event_seq(page_id).await.unwrap_or(0);
+            (vec![ServerMessage::resync_required(page_id, latest_seq)], 0)
+        }
+    }
+}
+
+/// The comment author and status named in an event's data, if any
+fn comment_subject(data: &serde_json::Value) -> Option<EventSubject> {
+    let author_id = data.get("author_id")?.as_str()?.parse().ok()?;
+    let status = data
+        .get("status")
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or(CommentStatus::Approved);
+    Some(EventSubject::Comment { author_id, status })
+}
@@ -0,0 +1,297 @@
+//! Plan quotas, enforced from the usage counters
+//!
+//! Comments and AI moderation checks are counted per calendar month in
+//! `site:{id}:usage:{YYYY-MM}`, stored media in `site:{id}:media_bytes`. A write that would
+//! go over the site's plan limit is refused. The first time in a month a counter passes
+//! [`SOFT_LIMIT_PERCENT`] of its limit a `quota_warning` action is logged (and sent to the
+//! site's webhooks), and the first refused write logs a `quota_exceeded`.
+//!
+//! Usage is read before the write and counted after it, so concurrent writes can overshoot
+//! a limit slightly. If the counters can't be read the write is allowed (fail-open, like
+//! content moderation).
+
+use serde::Serialize;
+use std::sync::Arc;
+use thiserror::Error;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::action_log::{ActionLogBuilder, ActionLogger, ActionType};
+use crate::redis::RedisClient;
+use crate::types::{PlanLimits, ProjectIdInfo};
+use crate::webhooks::WebhookDispatcher;
+
+/// Share of a limit at which the soft-limit warning is logged
+pub const SOFT_LIMIT_PERCENT: u64 = 80;
+
+/// Usage counter field for comments
+pub const COMMENTS_FIELD: &str = "comments";
+
+/// Usage counter field for AI moderation checks
+pub const MODERATION_CHECKS_FIELD: &str = "moderation_checks";
+
+/// What a plan limit applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaMetric {
+    /// Comments posted this month
+    Comments,
+    /// AI moderation checks this month
+    ModerationChecks,
+    /// Bytes of uploaded media currently stored
+    MediaStorageBytes,
+    /// Sites of the owning account
+    Sites,
+}
+
+impl QuotaMetric {
+    /// Name as serialized, used in action log metadata
+    pub fn name(self) -> &'static str {
+        match self {
+            QuotaMetric::Comments => "comments",
+            QuotaMetric::ModerationChecks => "moderation_checks",
+            QuotaMetric::MediaStorageBytes => "media_storage_bytes",
+            QuotaMetric::Sites => "sites",
+        }
+    }
+
+    /// The limit on this metric (None = unlimited)
+    pub fn limit(self, limits: &PlanLimits) -> Option<u64> {
+        match self {
+            QuotaMetric::Comments => limits.monthly_comments,
+            QuotaMetric::ModerationChecks => limits.monthly_moderation_checks,
+            QuotaMetric::MediaStorageBytes => limits.media_storage_bytes,
+            QuotaMetric::Sites => limits.max_sites.map(u64::from),
+        }
+    }
+}
+
+/// How close a counter is to its limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaStatus {
+    Ok,
+    /// At or above the soft limit
+    Warning,
+    /// Further writes are refused
+    LimitReached,
+}
+
+impl QuotaStatus {
+    pub fn of(used: u64, limit: Option<u64>) -> Self {
+        match limit {
+            Some(limit) if used >= limit => QuotaStatus::LimitReached,
+            Some(limit) if past_soft_limit(used, limit) => QuotaStatus::Warning,
+            _ => QuotaStatus::Ok,
+        }
+    }
+}
+
+/// A counter and its limit, as reported by the usage API
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct QuotaUsage {
+    pub metric: QuotaMetric,
+    pub used: u64,
+    /// None if the plan doesn't limit this metric
+    pub limit: Option<u64>,
+    pub status: QuotaStatus,
+}
+
+impl QuotaUsage {
+    pub fn new(metric: QuotaMetric, used: u64, limits: &PlanLimits) -> Self {
+        let limit = metric.limit(limits);
+        Self {
+            metric,
+            used,
+            limit,
+            status: QuotaStatus::of(used, limit),
+        }
+    }
+}
+
+/// A write refused because the site's plan limit was reached
+#[derive(Debug, Error)]
+#[error("{}", self.message())]
+pub struct QuotaExceeded {
+    pub metric: QuotaMetric,
+    pub limit: u64,
+}
+
+impl QuotaExceeded {
+    /// HTTP status code: 429 for moderation checks (the site can turn AI moderation off to
+    /// keep posting), 402 for limits that need a plan upgrade
+    pub fn status_code(&self) -> u16 {
+        match self.metric {
+            QuotaMetric::ModerationChecks => 429,
+            _ => 402,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self.metric {
+            QuotaMetric::Comments => format!(
+                "This site has reached its plan's limit of {} comments this month",
+                self.limit
+            ),
+            QuotaMetric::ModerationChecks => format!(
+                "This site has used all {} AI moderation checks in its plan this month",
+                self.limit
+            ),
+            QuotaMetric::MediaStorageBytes => format!(
+                "This site has reached its plan's media storage limit of {} MB",
+                self.limit / (1024 * 1024)
+            ),
+            QuotaMetric::Sites => {
+                format!("This account has reached its plan's limit of {} sites", self.limit)
+            }
+        }
+    }
+}
+
+fn past_soft_limit(used: u64, limit: u64) -> bool {
+    used.saturating_mul(100) >= limit.saturating_mul(SOFT_LIMIT_PERCENT)
+}
+
+/// Checks writes against the site's plan and logs quota notices
+pub struct Quotas {
+    redis: Arc<RedisClient>,
+    action_logger: Arc<ActionLogger>,
+    webhooks: Arc<WebhookDispatcher>,
+}
+
+impl Quotas {
+    pub fn new(
+        redis: Arc<RedisClient>,
+        action_logger: Arc<ActionLogger>,
+        webhooks: Arc<WebhookDispatcher>,
+    ) -> Self {
+        Self {
+            redis,
+            action_logger,
+            webhooks,
+        }
+    }
+
+    /// Check that `amount` more of `metric` fits in the site's plan
+    pub async fn check(
+        &self,
+        site: &ProjectIdInfo,
+        metric: QuotaMetric,
+        amount: u64,
+    ) -> Result<(), QuotaExceeded> {
+        let Some(limit) = metric.limit(&site.limits) else {
+            return Ok(());
+        };
+
+        let used = match self.used(site.site_id, metric).await {
+            Ok(used) => used,
+            Err(e) => {
+                tracing::warn!(error = %e, metric = metric.name(), "Failed to read usage - allowing write");
+                return Ok(());
+            }
+        };
+
+        let total = used.saturating_add(amount);
+        if total > limit {
+            self.notify(site.site_id, ActionType::QuotaExceeded, metric, used, limit).await;
+            return Err(QuotaExceeded { metric, limit });
+        }
+        if past_soft_limit(total, limit) {
+            self.notify(site.site_id, ActionType::QuotaWarning, metric, total, limit).await;
+        }
+        Ok(())
+    }
+
+    /// Current value of a site's counter
+    async fn used(&self, site_id: Uuid, metric: QuotaMetric) -> crate::Result<u64> {
+        let month = RedisClient::usage_month();
+        match metric {
+            QuotaMetric::Comments => self.redis.get_usage(site_id, &month, COMMENTS_FIELD).await,
+            QuotaMetric::ModerationChecks => {
+                self.redis.get_usage(site_id, &month, MODERATION_CHECKS_FIELD).await
+            }
+            QuotaMetric::MediaStorageBytes => self.redis.get_media_storage(site_id).await,
+            // Counted per owner by the site management API
+            QuotaMetric::Sites => Ok(0),
+        }
+    }
+
+    /// Log a quota notice (and queue its webhooks), once per site, metric and month
+    async fn notify(&self, site_id: Uuid, action: ActionType, metric: QuotaMetric, used: u64, limit: u64) {
+        let month = RedisClient::usage_month();
+        match self
+            .redis
+            .claim_quota_notice(site_id, action.event_name(), metric.name(), &month)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                tracing::warn!("Failed to record quota notice: {}", e);
+                return;
+            }
+        }
+
+        let entry = ActionLogBuilder::new(action, site_id)
+            .metadata(serde_json::json!({
+                "metric": metric.name(),
+                "used": used,
+                "limit": limit,
+                "month": month,
+            }))
+            .build();
+        self.action_logger.log(entry.clone());
+        self.webhooks.enqueue_in_background(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_status() {
+        assert_eq!(QuotaStatus::of(5, None), QuotaStatus::Ok);
+        assert_eq!(QuotaStatus::of(79, Some(100)), QuotaStatus::Ok);
+        assert_eq!(QuotaStatus::of(80, Some(100)), QuotaStatus::Warning);
+        assert_eq!(QuotaStatus::of(100, Some(100)), QuotaStatus::LimitReached);
+        assert_eq!(QuotaStatus::of(0, Some(0)), QuotaStatus::LimitReached);
+    }
+
+    #[test]
+    fn test_quota_exceeded_status_codes() {
+        let comments = QuotaExceeded { metric: QuotaMetric::Comments, limit: 1000 };
+        assert_eq!(comments.status_code(), 402);
+        assert!(comments.to_string().contains("1000 comments"));
+
+        let checks = QuotaExceeded { metric: QuotaMetric::ModerationChecks, limit: 10 };
+        assert_eq!(checks.status_code(), 429);
+
+        let storage = QuotaExceeded {
+            metric: QuotaMetric::MediaStorageBytes,
+            limit: 100 * 1024 * 1024,
+        };
+        assert!(storage.to_string().contains("100 MB"));
+    }
+
+    #[test]
+    fn test_effective_limits() {
+        use crate::types::{PlanTier, SitePlan};
+
+        assert_eq!(SitePlan::default().effective_limits(), PlanLimits::default());
+
+        let mut plan = SitePlan {
+            tier: Some(PlanTier::Free),
+            ..Default::default()
+        };
+        assert_eq!(plan.effective_limits().monthly_comments, Some(1_000));
+
+        // Custom limits replace the tier's
+        plan.limits = Some(PlanLimits {
+            monthly_comments: Some(5),
+            ..Default::default()
+        });
+        assert_eq!(plan.effective_limits().monthly_comments, Some(5));
+        assert_eq!(plan.effective_limits().max_sites, None);
+    }
+}
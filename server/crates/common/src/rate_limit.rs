@@ -0,0 +1,166 @@
+//! Client identification and rate limit buckets shared by the HTTP and WebSocket servers
+//!
+//! Writes count against the same keys whichever transport they arrive on, so a client
+//! can't double its allowance by posting over the socket as well.
+
+use reqwest::header::HeaderMap;
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use uuid::Uuid;
+
+use crate::config::RateLimitConfig;
+use crate::redis::RateLimitResult;
+use crate::store::Storage;
+use crate::types::SiteRateLimitSettings;
+use crate::Result;
+
+/// Window for write limits
+pub const WRITE_WINDOW_SECS: u64 = 60;
+
+/// Client IP: the TCP peer, or the address forwarded by a trusted proxy
+///
+/// X-Forwarded-For and X-Real-IP are only read when the peer is one of
+/// `trusted_proxies`, so clients connecting directly can't spoof them.
+pub fn client_ip(headers: &HeaderMap, peer: Option<IpAddr>, trusted_proxies: &[String]) -> Option<String> {
+    let peer = peer?.to_string();
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer);
+    }
+
+    // Try to get X-Forwarded-For header
+    if let Some(forwarded_for) = headers
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok())
+    {
+        // X-Forwarded-For is a comma-separated list: client, proxy1, proxy2, ...
+        // We want the first non-trusted IP from the right
+        let ips: Vec<&str> = forwarded_for.split(',').map(|s| s.trim()).collect();
+
+        // Find the rightmost IP that isn't from a trusted proxy
+        for ip in ips.iter().rev() {
+            if !trusted_proxies.iter().any(|p| p == *ip) {
+                return Some(ip.to_string());
+            }
+        }
+
+        // If all IPs are trusted, use the leftmost (client)
+        if let Some(first) = ips.first() {
+            return Some(first.to_string());
+        }
+    }
+
+    // Try X-Real-IP header, then the proxy itself
+    headers
+        .get("x-real-ip")
+        .and_then(|v| v.to_str().ok())
+        .map(|ip| ip.to_string())
+        .or(Some(peer))
+}
+
+/// Hash an IP address for storage (privacy)
+pub fn hash_ip(ip: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(ip.as_bytes());
+    let result = hasher.finalize();
+    format!("{:x}", result)[..16].to_string()
+}
+
+/// Bucket for a client IP (`route` is `auth`, `write` or `read`)
+pub fn ip_key(ip: &str, route: &str) -> String {
+    format!("ratelimit:ip:{}:{}", hash_ip(ip), route)
+}
+
+/// Bucket for a site's API key
+pub fn project_id_key(site_id: Uuid, route: &str) -> String {
+    format!("ratelimit:apikey:{}:{}", site_id, route)
+}
+
+/// Bucket for a user on a site
+pub fn user_key(site_id: Uuid, user_id: Uuid, route: &str) -> String {
+    format!("ratelimit:user:{}:{}:{}", site_id, user_id, route)
+}
+
+/// Count one write against the IP, site and user limits, like the HTTP middleware does
+///
+/// Returns the layer that refused it (`IP`, `project_id` or `user`) and its result.
+pub async fn check_write_limits(
+    store: &dyn Storage,
+    config: &RateLimitConfig,
+    overrides: &SiteRateLimitSettings,
+    site_id: Uuid,
+    ip: &str,
+    user_id: Option<Uuid>,
+) -> Result<Option<(&'static str, RateLimitResult)>> {
+    let ip_limit = overrides.ip_writes_per_minute.unwrap_or(config.ip_writes_per_minute);
+    let result = store
+        .check_rate_limit(&ip_key(ip, "write"), ip_limit, WRITE_WINDOW_SECS)
+        .await?;
+    if !result.allowed {
+        return Ok(Some(("IP", result)));
+    }
+
+    let result = store
+        .check_rate_limit(
+            &project_id_key(site_id, "write"),
+            config.project_id_writes_per_minute,
+            WRITE_WINDOW_SECS,
+        )
+        .await?;
+    if !result.allowed {
+        return Ok(Some(("project_id", result)));
+    }
+
+    if let Some(uid) = user_id {
+        let user_limit = overrides.user_writes_per_minute.unwrap_or(config.user_writes_per_minute);
+        let result = store
+            .check_rate_limit(&user_key(site_id, uid, "write"), user_limit, WRITE_WINDOW_SECS)
+            .await?;
+        if !result.allowed {
+            return Ok(Some(("user", result)));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forwarded(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_client_ip_trusts_headers_only_from_proxies() {
+        let trusted = vec!["10.0.0.1".to_string()];
+        let headers = forwarded("203.0.113.7, 10.0.0.1");
+
+        // Behind the proxy: the client it forwarded
+        assert_eq!(
+            client_ip(&headers, Some("10.0.0.1".parse().unwrap()), &trusted).as_deref(),
+            Some("203.0.113.7")
+        );
+        // Direct connection: the header is ignored
+        assert_eq!(
+            client_ip(&headers, Some("198.51.100.1".parse().unwrap()), &trusted).as_deref(),
+            Some("198.51.100.1")
+        );
+        // Unknown peer: nothing to go on
+        assert_eq!(client_ip(&headers, None, &trusted), None);
+        // The proxy itself when it doesn't forward anything
+        assert_eq!(
+            client_ip(&HeaderMap::new(), Some("10.0.0.1".parse().unwrap()), &trusted).as_deref(),
+            Some("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn test_ip_key_hides_the_address() {
+        let key = ip_key("203.0.113.7", "write");
+        assert!(!key.contains("203.0.113.7"));
+        assert_eq!(key, format!("ratelimit:ip:{}:write", hash_ip("203.0.113.7")));
+    }
+}
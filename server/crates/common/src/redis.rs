@@ -1,4 +1,4 @@
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use fred::prelude::*;
 use fred::types::{CustomCommand, ClusterHash, Resp3Frame};
 use serde::{de::DeserializeOwned, Serialize};
@@ -7,6 +7,7 @@ use std::fs;
 use std::path::Path;
 use uuid::Uuid;
 
+use crate::store::session_expiry;
 use crate::types::*;
 use crate::{Error, Result};
 
@@ -30,6 +31,30 @@ const VERIFICATION_TTL: i64 = 600; // 10 minutes
 const PROJECT_ID_CACHE_TTL: i64 = 300; // 5 minutes
 const TYPING_TTL: i64 = 5; // 5 seconds
 const WEB3_NONCE_TTL: i64 = 600; // 10 minutes
+pub const STREAM_TICKET_TTL: i64 = 60; // 1 minute
+const WEBHOOK_DELIVERY_TTL: i64 = 86400 * 7; // 7 days
+const QUOTA_NOTICE_TTL: i64 = 86400 * 32; // outlives the month it was sent in
+const ANALYTICS_TTL_SLACK: i64 = 86400; // buckets outlive their retention by a day
+const MAX_SITE_WEBHOOK_DELIVERIES: i64 = 500;
+const WEBHOOK_QUEUE_KEY: &str = "webhooks:queue";
+const PAGE_EVENT_LOG_LEN: i64 = 1000;
+const PAGE_EVENT_LOG_TTL: i64 = 86400; // 24 hours
+const WS_NODES_KEY: &str = "ws:nodes";
+const NOTIFICATION_EMAIL_QUEUE_KEY: &str = "notification_emails:queue";
+/// A WebSocket node is considered dead after this long without a heartbeat
+pub const WS_NODE_TTL_MS: i64 = 30_000;
+
+/// Result of looking up a page's events for replay
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageEventReplay {
+    /// Events after the requested sequence number, oldest first, as published
+    Events(Vec<String>),
+    /// Some events after the requested sequence number are no longer in the log
+    Gap {
+        /// The page's current sequence number
+        latest_seq: u64,
+    },
+}
 
 pub struct RedisClient {
     client: Client,
@@ -48,6 +73,41 @@ impl RedisClient {
         Ok(RedisClient { client, script_shas })
     }
 
+    /// Run a loaded Lua script that returns an array of strings
+    ///
+    /// `args` are the EVALSHA arguments after the SHA (key count, keys, then argv).
+    async fn eval_strings(&self, script: &str, args: Vec<Value>) -> Result<Vec<String>> {
+        let sha = self.script_shas.get(script)
+            .ok_or_else(|| Error::Internal(format!("{} script not loaded", script)))?;
+
+        let mut full_args: Vec<Value> = vec![sha.clone().into()];
+        full_args.extend(args);
+
+        let cmd = CustomCommand::new("EVALSHA", ClusterHash::FirstKey, false);
+        let frame = self.client.custom_raw::<Value>(cmd, full_args).await?;
+
+        let result: Vec<Value> = match frame {
+            Resp3Frame::Array { data, .. } => {
+                data.into_iter()
+                    .map(|f| f.try_into())
+                    .collect::<std::result::Result<Vec<Value>, _>>()
+                    .map_err(|e: fred::error::Error| Error::Redis(e))?
+            }
+            Resp3Frame::SimpleError { data, .. } => {
+                return Err(Error::Internal(format!("Lua script error: {}", data)));
+            }
+            Resp3Frame::BlobError { data, .. } => {
+                let err_msg = String::from_utf8_lossy(&data);
+                return Err(Error::Internal(format!("Lua script error: {}", err_msg)));
+            }
+            other => {
+                return Err(Error::Internal(format!("Unexpected response type from EVALSHA: {:?}", other)));
+            }
+        };
+
+        Ok(result.into_iter().filter_map(|v| v.as_string()).collect())
+    }
+
     /// Load Lua scripts from the lua/ directory and return their SHA1 hashes
     async fn load_lua_scripts(client: &Client) -> Result<HashMap<String, String>> {
         let mut shas = HashMap::new();
@@ -254,21 +314,132 @@ impl RedisClient {
         Uuid::from_u64_pair(hash, hash.rotate_left(32))
     }
 
+    /// Remember the URL a page ID was generated from (page IDs can't be reversed)
+    pub async fn set_page_url(&self, site_id: Uuid, page_id: Uuid, page_url: &str) -> Result<()> {
+        self.client
+            .hset::<(), _, _>(format!("site:{}:pages", site_id), (page_id.to_string(), page_url))
+            .await?;
+        Ok(())
+    }
+
+    /// Record the URL of a page that was read, unless it is already known
+    ///
+    /// Reads can name any URL, so at most about `limit` pages per site are added this
+    /// way (concurrent first reads of one page may each count). Writes record their
+    /// page with `set_page_url` regardless.
+    pub async fn register_viewed_page(&self, site_id: Uuid, page_id: Uuid, page_url: &str, limit: u64) -> Result<()> {
+        let key = format!("site:{}:pages", site_id);
+        if self.client.hexists::<bool, _, _>(&key, page_id.to_string()).await? {
+            return Ok(());
+        }
+        let count: u64 = self.client.incr(format!("site:{}:viewed_pages", site_id)).await?;
+        if count <= limit {
+            self.client
+                .hsetnx::<(), _, _, _>(&key, page_id.to_string(), page_url)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Get the URL a page ID was generated from, if recorded
+    pub async fn get_page_url(&self, site_id: Uuid, page_id: Uuid) -> Result<Option<String>> {
+        Ok(self
+            .client
+            .hget(format!("site:{}:pages", site_id), page_id.to_string())
+            .await?)
+    }
+
+    /// Get all recorded page URLs for a site
+    /// Returns Vec<(page_id, page_url)>
+    pub async fn get_site_pages(&self, site_id: Uuid) -> Result<Vec<(Uuid, String)>> {
+        let pages: HashMap<String, String> = self.client.hgetall(format!("site:{}:pages", site_id)).await?;
+        Ok(pages
+            .into_iter()
+            .filter_map(|(page_id, url)| Some((page_id.parse().ok()?, url)))
+            .collect())
+    }
+
     /// Get the entire page tree (single Redis GET)
     pub async fn get_page_tree(&self, page_id: Uuid) -> Result<Option<PageTree>> {
         let value: Option<String> = self.client.get(format!("page:{}:tree", page_id)).await?;
         Ok(value.and_then(|v| serde_json::from_str(&v).ok()))
     }
 
-    /// Set the entire page tree (single Redis SET)
+    /// Set the entire page tree unconditionally (single Redis SET + version bump)
+    ///
+    /// Prefer `update_page_tree` for read-modify-write, this overwrites concurrent changes.
     pub async fn set_page_tree(&self, page_id: Uuid, tree: &PageTree) -> Result<()> {
         let json = serde_json::to_string(tree)?;
-        self.client
-            .set::<(), _, _>(format!("page:{}:tree", page_id), json, None, None, false)
+        let trx = self.client.multi();
+        trx.set::<(), _, _>(format!("page:{}:tree", page_id), json, None, None, false)
             .await?;
+        trx.incr::<(), _>(format!("page:{}:tree:version", page_id)).await?;
+        trx.exec::<()>(true).await?;
         Ok(())
     }
 
+    /// Atomically read-modify-write a page tree (see `Storage::update_page_tree`)
+    pub async fn update_page_tree<T, E, F>(
+        &self,
+        page_id: Uuid,
+        f: F,
+    ) -> Result<std::result::Result<(PageTree, T), E>>
+    where
+        F: FnMut(&mut PageTree) -> std::result::Result<T, E>,
+    {
+        (self as &dyn crate::store::Storage).update_page_tree(page_id, f).await
+    }
+
+    /// Get a page tree together with its version counter (single MGET)
+    pub async fn get_page_tree_versioned(&self, page_id: Uuid) -> Result<(Option<PageTree>, u64)> {
+        let values: Vec<Option<String>> = self
+            .client
+            .mget(vec![format!("page:{}:tree", page_id), format!("page:{}:tree:version", page_id)])
+            .await?;
+        let mut values = values.into_iter();
+        let tree_json = values.next().flatten();
+        let version = values.next().flatten().and_then(|v| v.parse().ok()).unwrap_or(0);
+
+        // Don't fall back to an empty tree on parse errors, that would wipe the page
+        let tree = match tree_json {
+            Some(json) => Some(serde_json::from_str(&json)?),
+            None => None,
+        };
+        Ok((tree, version))
+    }
+
+    /// Write a page tree if its version counter still matches (compare-and-set script)
+    pub async fn compare_and_set_page_tree(&self, page_id: Uuid, expected_version: u64, tree: &PageTree) -> Result<bool> {
+        let sha = self.script_shas.get("update_page_tree")
+            .ok_or_else(|| Error::Internal("update_page_tree script not loaded".to_string()))?;
+
+        let args: Vec<Value> = vec![
+            sha.clone().into(),
+            "2".into(), // 2 keys
+            format!("page:{}:tree", page_id).into(),
+            format!("page:{}:tree:version", page_id).into(),
+            expected_version.to_string().into(),
+            serde_json::to_string(tree)?.into(),
+        ];
+
+        let cmd = CustomCommand::new("EVALSHA", ClusterHash::FirstKey, false);
+        let frame = self.client.custom_raw::<Value>(cmd, args).await?;
+
+        match frame {
+            Resp3Frame::Number { data, .. } => Ok(data >= 0),
+            Resp3Frame::SimpleError { data, .. } => {
+                Err(Error::Internal(format!("Lua script error: {}", data)))
+            }
+            Resp3Frame::BlobError { data, .. } => {
+                let err_msg = String::from_utf8_lossy(&data);
+                Err(Error::Internal(format!("Lua script error: {}", err_msg)))
+            }
+            other => {
+                Err(Error::Internal(format!("Unexpected response type from EVALSHA: {:?}", other)))
+            }
+        }
+    }
+
     /// Get or create a page tree
     pub async fn get_or_create_page_tree(&self, page_id: Uuid) -> Result<PageTree> {
         match self.get_page_tree(page_id).await? {
@@ -277,6 +448,56 @@ impl RedisClient {
         }
     }
 
+    /// Record the previous version of an edited comment
+    pub async fn add_comment_revision(&self, comment_id: Uuid, revision: &CommentRevision) -> Result<()> {
+        let json = serde_json::to_string(revision)?;
+        self.client
+            .rpush::<(), _, _>(format!("comment:{}:revisions", comment_id), json)
+            .await?;
+        Ok(())
+    }
+
+    /// Get a comment's previous versions, oldest first
+    pub async fn get_comment_revisions(&self, comment_id: Uuid) -> Result<Vec<CommentRevision>> {
+        let items: Vec<String> = self
+            .client
+            .lrange(format!("comment:{}:revisions", comment_id), 0, -1)
+            .await?;
+        Ok(items
+            .iter()
+            .filter_map(|s| serde_json::from_str(s).ok())
+            .collect())
+    }
+
+    pub async fn delete_comment_revisions(&self, comment_id: Uuid) -> Result<()> {
+        self.client
+            .del::<(), _>(format!("comment:{}:revisions", comment_id))
+            .await?;
+        Ok(())
+    }
+
+    /// Attribute the revisions of a comment made by `from` to `to`
+    ///
+    /// Entries are rewritten in place by index, which is safe against concurrent
+    /// edits since revisions are only ever appended.
+    pub async fn reassign_comment_revisions(&self, comment_id: Uuid, from: Uuid, to: Uuid) -> Result<()> {
+        let key = format!("comment:{}:revisions", comment_id);
+        let items: Vec<String> = self.client.lrange(&key, 0, -1).await?;
+        for (index, item) in items.iter().enumerate() {
+            let Ok(mut revision) = serde_json::from_str::<CommentRevision>(item) else {
+                continue;
+            };
+            if revision.editor_id != from {
+                continue;
+            }
+            revision.editor_id = to;
+            self.client
+                .lset::<(), _, _>(&key, index as i64, serde_json::to_string(&revision)?)
+                .await?;
+        }
+        Ok(())
+    }
+
     /// Add a comment to user's comment index (for profile/history)
     pub async fn add_user_comment_index(&self, user_id: Uuid, page_id: Uuid, comment_id: Uuid) -> Result<()> {
         let score = Utc::now().timestamp_millis() as f64;
@@ -328,6 +549,55 @@ impl RedisClient {
         Ok(())
     }
 
+    /// Add an imported comment to the site and user indexes, scored by its original timestamp
+    pub async fn add_imported_comment_indexes(
+        &self,
+        site_id: Uuid,
+        user_id: Option<Uuid>,
+        page_id: Uuid,
+        comment_id: Uuid,
+        created_at: i64,
+    ) -> Result<()> {
+        let score = (created_at * 1000) as f64;
+        let value = format!("{}:{}", page_id, comment_id);
+
+        let pipeline = self.client.pipeline();
+        pipeline
+            .zadd::<(), _, _>(
+                format!("site:{}:comments", site_id),
+                None,
+                None,
+                false,
+                false,
+                (score, value.clone()),
+            )
+            .await?;
+        if let Some(user_id) = user_id {
+            pipeline
+                .zadd::<(), _, _>(
+                    format!("user:{}:comments", user_id),
+                    None,
+                    None,
+                    false,
+                    false,
+                    (score, value.clone()),
+                )
+                .await?;
+            pipeline
+                .zadd::<(), _, _>(
+                    format!("user:{}:{}:comments", user_id, site_id),
+                    None,
+                    None,
+                    false,
+                    false,
+                    (score, value),
+                )
+                .await?;
+        }
+        let _: Vec<i64> = pipeline.all().await?;
+        Ok(())
+    }
+
     /// Get user's comments across all sites (for profile)
     /// Returns Vec<(page_id, comment_id)>
     pub async fn get_user_comment_index(&self, user_id: Uuid, offset: usize, limit: usize) -> Result<Vec<(Uuid, Uuid)>> {
@@ -388,6 +658,22 @@ impl RedisClient {
             .collect())
     }
 
+    /// Get the IDs of all pages in the site's comment index
+    pub async fn get_site_comment_page_ids(&self, site_id: Uuid) -> Result<Vec<Uuid>> {
+        let items: Vec<String> = self
+            .client
+            .zrange(format!("site:{}:comments", site_id), 0, -1, None, false, None, false)
+            .await?;
+
+        let mut page_ids: Vec<Uuid> = items
+            .iter()
+            .filter_map(|s| s.split(':').next()?.parse().ok())
+            .collect();
+        page_ids.sort();
+        page_ids.dedup();
+        Ok(page_ids)
+    }
+
     /// Get user's comments on a specific site (for admin bulk delete)
     /// Returns Vec<(page_id, comment_id)>
     pub async fn get_user_site_comments(&self, user_id: Uuid, site_id: Uuid, offset: usize, limit: usize) -> Result<Vec<(Uuid, Uuid)>> {
@@ -470,6 +756,53 @@ impl RedisClient {
             .collect())
     }
 
+    /// Add to moderation queue with an explicit queued time (unix ms), used by restores
+    pub async fn add_to_modqueue_at(&self, site_id: Uuid, page_id: Uuid, comment_id: Uuid, queued_at: i64) -> Result<()> {
+        self.add_page_comment_entry(&format!("site:{}:modqueue", site_id), page_id, comment_id, queued_at)
+            .await
+    }
+
+    /// Get the whole moderation queue
+    /// Returns Vec<(page_id, comment_id, queued_at)> with queued_at in unix ms
+    pub async fn get_modqueue_entries(&self, site_id: Uuid) -> Result<Vec<(Uuid, Uuid, i64)>> {
+        self.get_page_comment_entries(&format!("site:{}:modqueue", site_id)).await
+    }
+
+    /// Add a report entry with an explicit report time (unix ms), used by restores
+    pub async fn add_report_entry(&self, site_id: Uuid, page_id: Uuid, comment_id: Uuid, reported_at: i64) -> Result<()> {
+        self.add_page_comment_entry(&format!("site:{}:reports", site_id), page_id, comment_id, reported_at)
+            .await
+    }
+
+    /// Get all reports
+    /// Returns Vec<(page_id, comment_id, reported_at)> with reported_at in unix ms
+    pub async fn get_report_entries(&self, site_id: Uuid) -> Result<Vec<(Uuid, Uuid, i64)>> {
+        self.get_page_comment_entries(&format!("site:{}:reports", site_id)).await
+    }
+
+    async fn add_page_comment_entry(&self, key: &str, page_id: Uuid, comment_id: Uuid, score: i64) -> Result<()> {
+        let value = format!("{}:{}", page_id, comment_id);
+        self.client
+            .zadd::<(), _, _>(key, None, None, false, false, (score as f64, value))
+            .await?;
+        Ok(())
+    }
+
+    async fn get_page_comment_entries(&self, key: &str) -> Result<Vec<(Uuid, Uuid, i64)>> {
+        let items: Vec<(String, f64)> = self
+            .client
+            .zrange(key, 0, -1, None, false, None, true)
+            .await?;
+
+        Ok(items
+            .into_iter()
+            .filter_map(|(s, score)| {
+                let (page_id, comment_id) = s.split_once(':')?;
+                Some((page_id.parse().ok()?, comment_id.parse().ok()?, score as i64))
+            })
+            .collect())
+    }
+
     /// Add report (uses page_id:comment_id format as value, report JSON as score member)
     pub async fn add_report_v2(&self, site_id: Uuid, page_id: Uuid, report: &Report) -> Result<()> {
         let score = report.created_at.timestamp_millis() as f64;
@@ -756,6 +1089,7 @@ impl RedisClient {
     // ========================================================================
     // Per-Page Vote Operations (for efficient loading)
     // Key: votes:{user_id}:{page_id} -> hash of comment_id -> direction (1 or -1)
+    // Key: user:{user_id}:vote_pages -> set of page IDs the user has voted on
     // ========================================================================
 
     /// Set a user's vote for a comment on a page
@@ -770,6 +1104,9 @@ impl RedisClient {
                 (comment_id.to_string(), value),
             )
             .await?;
+        self.client
+            .sadd::<(), _, _>(format!("user:{}:vote_pages", user_id), page_id.to_string())
+            .await?;
         Ok(())
     }
 
@@ -784,6 +1121,22 @@ impl RedisClient {
         Ok(())
     }
 
+    /// Get a user's votes across all pages as (page_id, comment_id, direction)
+    pub async fn get_all_page_votes(&self, user_id: Uuid) -> Result<Vec<(Uuid, Uuid, VoteDirection)>> {
+        let page_ids: Vec<String> = self
+            .client
+            .smembers(format!("user:{}:vote_pages", user_id))
+            .await?;
+
+        let mut votes = Vec::new();
+        for page_id in page_ids.iter().filter_map(|p| p.parse::<Uuid>().ok()) {
+            for (comment_id, direction) in self.get_page_votes(user_id, page_id).await? {
+                votes.push((page_id, comment_id, direction));
+            }
+        }
+        Ok(votes)
+    }
+
     /// Get all of a user's votes for a page
     /// Returns a map of comment_id -> direction
     pub async fn get_page_votes(&self, user_id: Uuid, page_id: Uuid) -> Result<std::collections::HashMap<Uuid, VoteDirection>> {
@@ -820,6 +1173,8 @@ impl RedisClient {
     ) -> Result<(Option<VoteDirection>, i64, i64, i64, i64)> {
         let vote_key = format!("votes:{}:{}", user_id, page_id);
         let tree_key = format!("page:{}:tree", page_id);
+        let version_key = format!("page:{}:tree:version", page_id);
+        let vote_pages_key = format!("user:{}:vote_pages", user_id);
         let direction_str = match direction {
             VoteDirection::Up => "1",
             VoteDirection::Down => "-1",
@@ -831,12 +1186,15 @@ impl RedisClient {
             .ok_or_else(|| Error::Internal("atomic_vote script not loaded".to_string()))?;
 
         // Use EVALSHA with custom_raw
-        let mut args: Vec<Value> = vec![sha.clone().into(), "2".into()]; // 2 keys
+        let mut args: Vec<Value> = vec![sha.clone().into(), "4".into()]; // 4 keys
         args.push(vote_key.into());
         args.push(tree_key.into());
+        args.push(version_key.into());
+        args.push(vote_pages_key.into());
         args.push(comment_id.to_string().into());
         args.push(direction_str.to_string().into());
         args.push(path_json.into());
+        args.push(page_id.to_string().into());
 
         let cmd = CustomCommand::new("EVALSHA", ClusterHash::FirstKey, false);
         let frame = self
@@ -893,6 +1251,128 @@ impl RedisClient {
         Ok((final_vote, upvotes, downvotes, upvote_delta, downvote_delta))
     }
 
+    // ========================================================================
+    // Reaction Operations
+    // ========================================================================
+
+    /// Atomically toggle an emoji reaction using a Lua script
+    /// Returns (added, reaction counts for the comment), or `NotFound` if the page or comment is gone
+    pub async fn toggle_reaction(
+        &self,
+        user_id: Uuid,
+        page_id: Uuid,
+        comment_id: Uuid,
+        path: &[Uuid],
+        emoji: &str,
+    ) -> Result<(bool, std::collections::BTreeMap<String, i64>)> {
+        let path_json = serde_json::to_string(path)
+            .map_err(|e| Error::Internal(format!("Failed to serialize path: {}", e)))?;
+
+        let sha = self.script_shas.get("toggle_reaction")
+            .ok_or_else(|| Error::Internal("toggle_reaction script not loaded".to_string()))?;
+
+        let args: Vec<Value> = vec![
+            sha.clone().into(),
+            "4".into(), // 4 keys
+            format!("reactions:{}:{}", user_id, page_id).into(),
+            format!("page:{}:tree", page_id).into(),
+            format!("page:{}:tree:version", page_id).into(),
+            format!("user:{}:reaction_pages", user_id).into(),
+            comment_id.to_string().into(),
+            emoji.into(),
+            path_json.into(),
+            page_id.to_string().into(),
+        ];
+
+        let cmd = CustomCommand::new("EVALSHA", ClusterHash::FirstKey, false);
+        let frame = self.client.custom_raw::<Value>(cmd, args).await?;
+
+        let result: Vec<Value> = match frame {
+            Resp3Frame::Array { data, .. } => {
+                data.into_iter()
+                    .map(|f| f.try_into())
+                    .collect::<std::result::Result<Vec<Value>, _>>()
+                    .map_err(|e: fred::error::Error| Error::Redis(e))?
+            }
+            // The script's only error replies are "Page not found" and "Comment not found..."
+            Resp3Frame::SimpleError { data, .. } => {
+                return Err(Error::NotFound(data.to_string()));
+            }
+            Resp3Frame::BlobError { data, .. } => {
+                return Err(Error::NotFound(String::from_utf8_lossy(&data).into_owned()));
+            }
+            other => {
+                return Err(Error::Internal(format!("Unexpected response type from EVALSHA: {:?}", other)));
+            }
+        };
+
+        let added = result.first().and_then(|v| v.as_i64()) == Some(1);
+        let reactions = result
+            .get(1)
+            .and_then(|v| v.as_str())
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+
+        Ok((added, reactions))
+    }
+
+    /// Get all of a user's reactions for a page
+    /// Returns a map of comment_id -> emojis
+    pub async fn get_page_reactions(&self, user_id: Uuid, page_id: Uuid) -> Result<std::collections::HashMap<Uuid, Vec<String>>> {
+        let fields: Vec<String> = self
+            .client
+            .hkeys(format!("reactions:{}:{}", user_id, page_id))
+            .await?;
+
+        let mut result: std::collections::HashMap<Uuid, Vec<String>> = std::collections::HashMap::new();
+        for field in fields {
+            // "{comment_id}:{emoji}"
+            if let Some((comment_id, emoji)) = field.split_once(':')
+                && let Ok(comment_id) = comment_id.parse::<Uuid>()
+            {
+                result.entry(comment_id).or_default().push(emoji.to_string());
+            }
+        }
+        for emojis in result.values_mut() {
+            emojis.sort();
+        }
+        Ok(result)
+    }
+
+    /// Get a user's reactions across all pages as (page_id, comment_id, emoji)
+    pub async fn get_all_page_reactions(&self, user_id: Uuid) -> Result<Vec<(Uuid, Uuid, String)>> {
+        let page_ids: Vec<String> = self
+            .client
+            .smembers(format!("user:{}:reaction_pages", user_id))
+            .await?;
+
+        let mut reactions = Vec::new();
+        for page_id in page_ids.iter().filter_map(|p| p.parse::<Uuid>().ok()) {
+            for (comment_id, emojis) in self.get_page_reactions(user_id, page_id).await? {
+                for emoji in emojis {
+                    reactions.push((page_id, comment_id, emoji));
+                }
+            }
+        }
+        Ok(reactions)
+    }
+
+    /// Forget all of a user's reactions (counts on comments are kept)
+    pub async fn delete_user_reactions(&self, user_id: Uuid) -> Result<()> {
+        use futures_util::TryStreamExt;
+
+        let keys: Vec<Key> = self
+            .client
+            .scan_buffered(format!("reactions:{}:*", user_id), Some(100), None)
+            .try_collect()
+            .await?;
+        for key in keys {
+            self.client.del::<(), _>(key).await?;
+        }
+        self.client.del::<(), _>(format!("user:{}:reaction_pages", user_id)).await?;
+        Ok(())
+    }
+
     // ========================================================================
     // Role Operations
     // ========================================================================
@@ -1001,6 +1481,22 @@ impl RedisClient {
         Ok(banned)
     }
 
+    pub async fn get_site_blocked_users(&self, site_id: Uuid) -> Result<Vec<Uuid>> {
+        let ids: Vec<String> = self
+            .client
+            .smembers(format!("site:{}:blocked", site_id))
+            .await?;
+        Ok(ids.into_iter().filter_map(|s| s.parse().ok()).collect())
+    }
+
+    pub async fn get_site_shadowbanned_users(&self, site_id: Uuid) -> Result<Vec<Uuid>> {
+        let ids: Vec<String> = self
+            .client
+            .smembers(format!("site:{}:shadowbanned", site_id))
+            .await?;
+        Ok(ids.into_iter().filter_map(|s| s.parse().ok()).collect())
+    }
+
     // ========================================================================
     // User Comment Tracking
     // ========================================================================
@@ -1052,6 +1548,15 @@ impl RedisClient {
         Ok(new_count)
     }
 
+    /// Increment user's total comment count by `count` (bulk restores)
+    pub async fn increment_user_comment_count_by(&self, user_id: Uuid, count: i64) -> Result<i64> {
+        let new_count: i64 = self
+            .client
+            .hincrby(format!("user:{}", user_id), "total_comments", count)
+            .await?;
+        Ok(new_count)
+    }
+
     /// Decrement user's total comment count
     pub async fn decrement_user_comment_count(&self, user_id: Uuid) -> Result<i64> {
         let new_count: i64 = self
@@ -1143,12 +1648,19 @@ impl RedisClient {
     }
 
     pub async fn get_notifications(&self, user_id: Uuid, offset: usize, limit: usize) -> Result<Vec<Notification>> {
+        // usize::MAX means "get all", same as get_user_comment_index
+        let end_index = if limit == usize::MAX || offset.checked_add(limit).is_none() {
+            -1
+        } else {
+            (offset + limit - 1) as i64
+        };
+
         let items: Vec<String> = self
             .client
             .zrevrange(
                 format!("user:{}:notifications", user_id),
                 offset as i64,
-                (offset + limit - 1) as i64,
+                end_index,
                 false,
             )
             .await?;
@@ -1177,9 +1689,78 @@ impl RedisClient {
         Ok(())
     }
 
+    /// Queue a user's notification email, keeping an earlier due time if one is already set
+    pub async fn schedule_notification_email(&self, user_id: Uuid, due_at_ms: i64) -> Result<()> {
+        self.client
+            .zadd::<(), _, _>(
+                NOTIFICATION_EMAIL_QUEUE_KEY,
+                None,
+                Some(fred::types::sorted_sets::Ordering::LessThan),
+                false,
+                false,
+                (due_at_ms as f64, user_id.to_string()),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Atomically claim up to `limit` users whose notification email is due
+    ///
+    /// Claimed users stay queued until `lease_until_ms` (same script as the webhook queue),
+    /// so another worker picks them up again if this one dies before finishing.
+    pub async fn claim_notification_emails(
+        &self,
+        now_ms: i64,
+        lease_until_ms: i64,
+        limit: usize,
+    ) -> Result<Vec<Uuid>> {
+        let ids = self
+            .eval_strings(
+                "claim_webhooks",
+                vec![
+                    "1".into(),
+                    NOTIFICATION_EMAIL_QUEUE_KEY.into(),
+                    now_ms.to_string().into(),
+                    lease_until_ms.to_string().into(),
+                    limit.to_string().into(),
+                ],
+            )
+            .await?;
+        Ok(ids.into_iter().filter_map(|s| s.parse().ok()).collect())
+    }
+
+    pub async fn remove_notification_email(&self, user_id: Uuid) -> Result<()> {
+        self.client
+            .zrem::<(), _, _>(NOTIFICATION_EMAIL_QUEUE_KEY, user_id.to_string())
+            .await?;
+        Ok(())
+    }
+
+    /// Creation time (unix millis) of the newest notification already emailed to the user
+    pub async fn get_notification_email_cursor(&self, user_id: Uuid) -> Result<i64> {
+        let cursor: Option<i64> = self
+            .client
+            .get(format!("user:{}:notifications:emailed", user_id))
+            .await?;
+        Ok(cursor.unwrap_or(0))
+    }
+
+    pub async fn set_notification_email_cursor(&self, user_id: Uuid, cursor_ms: i64) -> Result<()> {
+        self.client
+            .set::<(), _, _>(
+                format!("user:{}:notifications:emailed", user_id),
+                cursor_ms,
+                None,
+                None,
+                false,
+            )
+            .await?;
+        Ok(())
+    }
+
+    // ========================================================================
+    // Verification Operations
     // ========================================================================
-    // Verification Operations
-    // ========================================================================
 
     pub async fn set_verification_code(&self, key: &str, code: &VerificationCode) -> Result<()> {
         let value = serde_json::to_string(code)?;
@@ -1240,6 +1821,33 @@ impl RedisClient {
         Ok(())
     }
 
+    // ========================================================================
+    // Event Stream Tickets
+    // ========================================================================
+
+    /// Store a single-use ticket that opens an event stream as a session
+    pub async fn set_stream_ticket(&self, ticket: &str, site_id: Uuid, user_id: Uuid, session_id: Uuid) -> Result<()> {
+        self.client
+            .set::<(), _, _>(
+                format!("stream_ticket:{}", ticket),
+                format!("{}:{}:{}", site_id, user_id, session_id),
+                Some(Expiration::EX(STREAM_TICKET_TTL)),
+                None,
+                false,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Redeem a stream ticket, returning (site_id, user_id, session_id) the first time only
+    pub async fn take_stream_ticket(&self, ticket: &str) -> Result<Option<(Uuid, Uuid, Uuid)>> {
+        let value: Option<String> = self.client.getdel(format!("stream_ticket:{}", ticket)).await?;
+        Ok(value.and_then(|v| {
+            let mut ids = v.split(':').map(|id| id.parse::<Uuid>().ok());
+            Some((ids.next()??, ids.next()??, ids.next()??))
+        }))
+    }
+
     /// Get user by wallet address
     pub async fn get_user_by_wallet(&self, chain: &str, address: &str) -> Result<Option<Uuid>> {
         let id: Option<String> = self
@@ -1308,11 +1916,20 @@ impl RedisClient {
     // Session Operations
     // ========================================================================
 
-    pub async fn create_session(&self, session_id: Uuid, user_id: Uuid, user_agent: &str, ip: &str) -> Result<()> {
+    /// Start a session that lasts until its first refresh token, `expires_at`, has expired
+    pub async fn create_session(
+        &self,
+        session_id: Uuid,
+        user_id: Uuid,
+        user_agent: &str,
+        ip: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
         let now = Utc::now();
+        let key = format!("session:{}", session_id);
         self.client
             .hset::<(), _, _>(
-                format!("session:{}", session_id),
+                &key,
                 [
                     ("user_id", user_id.to_string()),
                     ("created_at", now.to_rfc3339()),
@@ -1322,7 +1939,13 @@ impl RedisClient {
                 ],
             )
             .await?;
-        // Sessions are persistent (no TTL) for better UX
+        // Refreshing pushes the expiry back, so active sessions never lapse
+        self.client
+            .expire_at::<(), _>(&key, session_expiry(expires_at).timestamp(), None)
+            .await?;
+        self.client
+            .sadd::<(), _, _>(format!("user:{}:sessions", user_id), session_id.to_string())
+            .await?;
         Ok(())
     }
 
@@ -1335,12 +1958,119 @@ impl RedisClient {
     }
 
     pub async fn delete_session(&self, session_id: Uuid) -> Result<()> {
+        let user_id = self.get_session_user(session_id).await?;
         self.client
-            .del::<(), _>(format!("session:{}", session_id))
+            .del::<(), _>(vec![
+                format!("session:{}", session_id),
+                format!("session:{}:refresh_used", session_id),
+            ])
             .await?;
+        if let Some(user_id) = user_id {
+            self.client
+                .srem::<(), _, _>(format!("user:{}:sessions", user_id), session_id.to_string())
+                .await?;
+        }
         Ok(())
     }
 
+    /// Set a session's refresh token (by hash), replacing the current one and extending the session
+    pub async fn set_refresh_token(&self, session_id: Uuid, token_hash: &str, expires_at: DateTime<Utc>) -> Result<()> {
+        let key = format!("session:{}", session_id);
+        self.client
+            .hset::<(), _, _>(
+                &key,
+                [
+                    ("refresh_hash", token_hash.to_string()),
+                    ("refresh_expires_at", expires_at.timestamp().to_string()),
+                ],
+            )
+            .await?;
+        self.client
+            .expire_at::<(), _>(&key, session_expiry(expires_at).timestamp(), None)
+            .await?;
+        Ok(())
+    }
+
+    /// Atomically replace the session's current refresh token, remembering the old one as used
+    ///
+    /// The session and its used tokens are extended to the new token's `session_expiry`.
+    pub async fn rotate_refresh_token(
+        &self,
+        session_id: Uuid,
+        token_hash: &str,
+        new_token_hash: &str,
+        expires_at: DateTime<Utc>,
+        reuse_grace: chrono::Duration,
+    ) -> Result<RefreshRotation> {
+        let now = Utc::now();
+        let session_expires_at = session_expiry(expires_at);
+        let args: Vec<Value> = vec![
+            "2".into(),
+            format!("session:{}", session_id).into(),
+            format!("session:{}:refresh_used", session_id).into(),
+            token_hash.into(),
+            new_token_hash.into(),
+            now.timestamp().to_string().into(),
+            expires_at.timestamp().to_string().into(),
+            now.to_rfc3339().into(),
+            reuse_grace.num_seconds().to_string().into(),
+            session_expires_at.timestamp().to_string().into(),
+        ];
+        let result = self.eval_strings("rotate_refresh_token", args).await?;
+
+        let user_id = || {
+            result.get(1)
+                .and_then(|id| id.parse().ok())
+                .ok_or_else(|| Error::Internal("rotate_refresh_token returned no user".into()))
+        };
+        Ok(match result.first().map(String::as_str) {
+            Some("rotated") => RefreshRotation::Rotated { user_id: user_id()? },
+            Some("superseded") => RefreshRotation::Superseded { user_id: user_id()? },
+            Some("reused") => RefreshRotation::Reused { user_id: user_id()? },
+            Some("expired") => RefreshRotation::Expired,
+            _ => RefreshRotation::Invalid,
+        })
+    }
+
+    /// Get a user's active sessions, oldest first
+    /// Only sessions created since the per-user index was added are listed
+    pub async fn get_user_sessions(&self, user_id: Uuid) -> Result<Vec<SessionInfo>> {
+        let key = format!("user:{}:sessions", user_id);
+        let ids: Vec<String> = self.client.smembers(&key).await?;
+
+        let mut sessions = Vec::new();
+        for id in ids {
+            let Ok(session_id) = id.parse::<Uuid>() else {
+                continue;
+            };
+            let fields: HashMap<String, String> = self
+                .client
+                .hgetall(format!("session:{}", session_id))
+                .await?;
+            let parse_time = |field: &str| {
+                fields
+                    .get(field)
+                    .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+                    .map(|t| t.with_timezone(&Utc))
+            };
+            let (Some(created_at), Some(last_used)) = (parse_time("created_at"), parse_time("last_used")) else {
+                // Session hash is gone - drop the stale index entry
+                self.client.srem::<(), _, _>(&key, id).await?;
+                continue;
+            };
+            sessions.push(SessionInfo {
+                id: session_id,
+                created_at,
+                last_used,
+                user_agent: fields.get("user_agent").cloned().unwrap_or_default(),
+                ip: fields.get("ip").cloned().unwrap_or_default(),
+            });
+        }
+
+        sessions.sort_by_key(|s| s.created_at);
+        Ok(sessions)
+    }
+
     // ========================================================================
     // Typing & Presence
     // ========================================================================
@@ -1377,28 +2107,164 @@ impl RedisClient {
         Ok(ids.into_iter().filter_map(|s| s.parse().ok()).collect())
     }
 
-    pub async fn add_presence(&self, page_id: Uuid, user_id: Uuid) -> Result<()> {
-        self.client
-            .sadd::<(), _, _>(format!("page:{}:presence", page_id), user_id.to_string())
+    /// Mark a user as present on a page through a WebSocket node
+    ///
+    /// Entries are keyed by node (`{user_id}:{node_id}`) and indexed under the node,
+    /// so a node's entries can be removed when it dies (see `reap_ws_nodes`).
+    pub async fn add_presence(&self, page_id: Uuid, user_id: Uuid, node_id: Uuid) -> Result<()> {
+        let trx = self.client.multi();
+        trx.sadd::<(), _, _>(format!("page:{}:presence", page_id), format!("{}:{}", user_id, node_id))
+            .await?;
+        trx.sadd::<(), _, _>(format!("ws:node:{}:presence", node_id), format!("{}:{}", page_id, user_id))
             .await?;
+        trx.exec::<()>(true).await?;
         Ok(())
     }
 
-    pub async fn remove_presence(&self, page_id: Uuid, user_id: Uuid) -> Result<()> {
-        self.client
-            .srem::<(), _, _>(format!("page:{}:presence", page_id), user_id.to_string())
+    pub async fn remove_presence(&self, page_id: Uuid, user_id: Uuid, node_id: Uuid) -> Result<()> {
+        let trx = self.client.multi();
+        trx.srem::<(), _, _>(format!("page:{}:presence", page_id), format!("{}:{}", user_id, node_id))
             .await?;
+        trx.srem::<(), _, _>(format!("ws:node:{}:presence", node_id), format!("{}:{}", page_id, user_id))
+            .await?;
+        trx.exec::<()>(true).await?;
         Ok(())
     }
 
+    /// Users present on a page through any live WebSocket node
     pub async fn get_presence(&self, page_id: Uuid) -> Result<Vec<Uuid>> {
+        let args: Vec<Value> = vec![
+            "2".into(), // 2 keys
+            format!("page:{}:presence", page_id).into(),
+            WS_NODES_KEY.into(),
+            Self::ws_node_cutoff().to_string().into(),
+        ];
+        let ids = self.eval_strings("get_page_presence", args).await?;
+        Ok(ids.into_iter().filter_map(|s| s.parse().ok()).collect())
+    }
+
+    // ========================================================================
+    // WebSocket Cluster
+    // Key: ws:nodes -> sorted set of node_id by last heartbeat (unix millis)
+    // Key: ws:node:{node_id}:presence -> set of "{page_id}:{user_id}"
+    // Key: ws:node:{node_id}:connections -> hash of site_id -> open connections
+    // Key: ws:node:{node_id}:metrics -> hash of metric -> value
+    // ========================================================================
+
+    /// Record a WebSocket node's heartbeat along with its connection counts and metrics
+    ///
+    /// Returns false if the node was not registered, either because this is its first
+    /// heartbeat or because it missed enough of them to be reaped.
+    pub async fn ws_node_heartbeat(
+        &self,
+        node_id: Uuid,
+        connections: &HashMap<Uuid, u64>,
+        metrics: &HashMap<&str, u64>,
+    ) -> Result<bool> {
+        let now = Utc::now().timestamp_millis() as f64;
+        let added: i64 = self
+            .client
+            .zadd(WS_NODES_KEY, None, None, false, false, (now, node_id.to_string()))
+            .await?;
+
+        let connections_key = format!("ws:node:{}:connections", node_id);
+        let metrics_key = format!("ws:node:{}:metrics", node_id);
+
+        let trx = self.client.multi();
+        trx.del::<(), _>(vec![connections_key.clone(), metrics_key.clone()]).await?;
+        if !connections.is_empty() {
+            let values: Vec<(String, u64)> = connections
+                .iter()
+                .map(|(site_id, count)| (site_id.to_string(), *count))
+                .collect();
+            trx.hset::<(), _, _>(&connections_key, values).await?;
+            trx.pexpire::<(), _>(&connections_key, WS_NODE_TTL_MS, None).await?;
+        }
+        if !metrics.is_empty() {
+            let values: Vec<(String, u64)> = metrics
+                .iter()
+                .map(|(name, value)| (name.to_string(), *value))
+                .collect();
+            trx.hset::<(), _, _>(&metrics_key, values).await?;
+            trx.pexpire::<(), _>(&metrics_key, WS_NODE_TTL_MS, None).await?;
+        }
+        trx.exec::<()>(true).await?;
+
+        Ok(added == 0)
+    }
+
+    /// Remove a WebSocket node that is shutting down, along with its presence entries
+    pub async fn remove_ws_node(&self, node_id: Uuid) -> Result<()> {
+        // Backdating the heartbeat lets the reaper do the cleanup
+        self.client
+            .zadd::<(), _, _>(WS_NODES_KEY, None, None, false, false, (0.0, node_id.to_string()))
+            .await?;
+        self.reap_ws_nodes().await?;
+        Ok(())
+    }
+
+    /// Clean up after WebSocket nodes that stopped sending heartbeats
+    ///
+    /// Their presence entries are removed (publishing `user_left` for users that
+    /// aren't on the page through another node), along with their connection
+    /// counts and metrics. Returns the reaped node IDs.
+    pub async fn reap_ws_nodes(&self) -> Result<Vec<Uuid>> {
+        let args: Vec<Value> = vec![
+            "1".into(), // 1 key
+            WS_NODES_KEY.into(),
+            Self::ws_node_cutoff().to_string().into(),
+        ];
+        let ids = self.eval_strings("reap_ws_nodes", args).await?;
+        Ok(ids.into_iter().filter_map(|s| s.parse().ok()).collect())
+    }
+
+    /// WebSocket nodes that sent a heartbeat recently
+    pub async fn get_live_ws_nodes(&self) -> Result<Vec<Uuid>> {
         let ids: Vec<String> = self
             .client
-            .smembers(format!("page:{}:presence", page_id))
+            .zrangebyscore(WS_NODES_KEY, Self::ws_node_cutoff() as f64, f64::INFINITY, false, None)
             .await?;
         Ok(ids.into_iter().filter_map(|s| s.parse().ok()).collect())
     }
 
+    /// Open WebSocket connections per site, summed across live nodes
+    pub async fn get_cluster_connections(&self) -> Result<HashMap<Uuid, u64>> {
+        let mut totals: HashMap<Uuid, u64> = HashMap::new();
+        for node_id in self.get_live_ws_nodes().await? {
+            let counts: HashMap<String, u64> = self
+                .client
+                .hgetall(format!("ws:node:{}:connections", node_id))
+                .await?;
+            for (site_id, count) in counts {
+                if let Ok(site_id) = site_id.parse() {
+                    *totals.entry(site_id).or_default() += count;
+                }
+            }
+        }
+        Ok(totals)
+    }
+
+    /// Metrics summed across live WebSocket nodes, with the number of live nodes
+    pub async fn get_cluster_metrics(&self) -> Result<(usize, HashMap<String, u64>)> {
+        let nodes = self.get_live_ws_nodes().await?;
+        let mut totals: HashMap<String, u64> = HashMap::new();
+        for node_id in &nodes {
+            let metrics: HashMap<String, u64> = self
+                .client
+                .hgetall(format!("ws:node:{}:metrics", node_id))
+                .await?;
+            for (name, value) in metrics {
+                *totals.entry(name).or_default() += value;
+            }
+        }
+        Ok((nodes.len(), totals))
+    }
+
+    /// Heartbeats older than this (unix millis) belong to dead nodes
+    fn ws_node_cutoff() -> i64 {
+        Utc::now().timestamp_millis() - WS_NODE_TTL_MS
+    }
+
     // ========================================================================
     // Pageview Counter
     // ========================================================================
@@ -1411,17 +2277,35 @@ impl RedisClient {
         Ok(count)
     }
 
-    /// Increment both pageview and usage counters in a single pipeline (one round trip)
-    pub async fn increment_pageview_with_usage(&self, page_id: Uuid, site_id: Uuid) -> Result<()> {
-        let month = Utc::now().format("%Y-%m").to_string();
+    /// Count a comment page load: the page's views, the site's monthly usage, its
+    /// analytics buckets and, if known, the visitor (one round trip)
+    pub async fn record_pageview(&self, page_id: Uuid, site_id: Uuid, visitor_id: Option<&str>) -> Result<()> {
+        let now = Utc::now();
         let pipeline = self.client.pipeline();
 
-        // Queue both commands to the pipeline
-        pipeline.incr::<i64, _>(format!("page:{}:views", page_id)).await?;
-        pipeline.hincrby::<i64, _, _>(format!("site:{}:usage:{}", site_id, month), "pageviews", 1).await?;
+        pipeline.incr::<(), _>(format!("page:{}:views", page_id)).await?;
+        pipeline
+            .hincrby::<(), _, _>(format!("site:{}:usage:{}", site_id, Self::usage_month()), "pageviews", 1)
+            .await?;
+        for granularity in [AnalyticsGranularity::Hour, AnalyticsGranularity::Day] {
+            let bucket = granularity.bucket_key(granularity.truncate(now));
+            let ttl = granularity.retention().num_seconds() + ANALYTICS_TTL_SLACK;
+            let stats_key = format!("site:{}:stats:{}", site_id, bucket);
+            pipeline.hincrby::<(), _, _>(&stats_key, "pageviews", 1).await?;
+            pipeline.expire::<(), _>(&stats_key, ttl, None).await?;
+            if let Some(visitor_id) = visitor_id {
+                let visitors_key = format!("site:{}:visitors:{}", site_id, bucket);
+                pipeline.pfadd::<(), _, _>(&visitors_key, visitor_id).await?;
+                pipeline.expire::<(), _>(&visitors_key, ttl, None).await?;
+            }
+        }
+        let page_views_key = format!("site:{}:page_views:{}", site_id, now.format("%Y%m%d"));
+        pipeline.zincrby::<(), _, _>(&page_views_key, 1.0, page_id.to_string()).await?;
+        pipeline
+            .expire::<(), _>(&page_views_key, AnalyticsGranularity::Day.retention().num_seconds() + ANALYTICS_TTL_SLACK, None)
+            .await?;
 
-        // Execute both commands in a single round trip
-        let _: Vec<i64> = pipeline.all().await?;
+        pipeline.all::<Vec<Value>>().await?;
         Ok(())
     }
 
@@ -1438,27 +2322,319 @@ impl RedisClient {
     // Usage Metering
     // ========================================================================
 
+    /// Current usage month (`YYYY-MM`, UTC)
+    pub fn usage_month() -> String {
+        Utc::now().format("%Y-%m").to_string()
+    }
+
     pub async fn increment_usage(&self, site_id: Uuid, field: &str, amount: i64) -> Result<()> {
-        let month = Utc::now().format("%Y-%m").to_string();
+        let month = Self::usage_month();
         self.client
             .hincrby::<(), _, _>(format!("site:{}:usage:{}", site_id, month), field, amount)
             .await?;
         Ok(())
     }
 
+    /// A usage counter for the given month (`YYYY-MM`), 0 if nothing was counted
+    pub async fn get_usage(&self, site_id: Uuid, month: &str, field: &str) -> Result<u64> {
+        let value: Option<i64> = self
+            .client
+            .hget(format!("site:{}:usage:{}", site_id, month), field)
+            .await?;
+        Ok(value.unwrap_or(0).max(0) as u64)
+    }
+
+    /// Add (or with a negative amount, remove) bytes of stored media for a site
+    pub async fn increment_media_storage(&self, site_id: Uuid, bytes: i64) -> Result<()> {
+        self.client
+            .incr_by::<(), _>(format!("site:{}:media_bytes", site_id), bytes)
+            .await?;
+        Ok(())
+    }
+
+    /// Bytes of media currently stored for a site
+    pub async fn get_media_storage(&self, site_id: Uuid) -> Result<u64> {
+        let value: Option<i64> = self.client.get(format!("site:{}:media_bytes", site_id)).await?;
+        Ok(value.unwrap_or(0).max(0) as u64)
+    }
+
+    /// Record that a quota notice (`event` for `metric`) went out this month
+    ///
+    /// Returns false if it already had, so each notice is sent once per month.
+    pub async fn claim_quota_notice(
+        &self,
+        site_id: Uuid,
+        event: &str,
+        metric: &str,
+        month: &str,
+    ) -> Result<bool> {
+        let set: Option<String> = self
+            .client
+            .set(
+                format!("site:{}:quota_notice:{}:{}:{}", site_id, month, event, metric),
+                "1",
+                Some(Expiration::EX(QUOTA_NOTICE_TTL)),
+                Some(SetOptions::NX),
+                false,
+            )
+            .await?;
+        Ok(set.is_some())
+    }
+
+    // ========================================================================
+    // Site Analytics
+    // Key: site:{site_id}:stats:{hour:YYYYMMDDHH|day:YYYYMMDD} -> hash of counters
+    // Key: site:{site_id}:visitors:{bucket} -> HyperLogLog of visitor IDs
+    // Key: site:{site_id}:commenters:{bucket} -> HyperLogLog of comment author IDs
+    // Key: site:{site_id}:page_views:{YYYYMMDD} -> zset of page_id by views
+    // Key: site:{site_id}:page_comments:{YYYYMMDD} -> zset of page_id by comments
+    // ========================================================================
+
+    /// Count a visitor (a user ID, or a hash identifying an anonymous visitor) in the
+    /// site's current hour and day
     pub async fn add_unique_visitor(&self, site_id: Uuid, visitor_id: &str) -> Result<()> {
-        // HyperLogLog for unique visitor counting
-        // Using PFADD command via custom command since fred may not expose it directly
-        let month = Utc::now().format("%Y-%m").to_string();
-        let key = format!("site:{}:visitors:{}", site_id, month);
-        // Use set with NX for simple unique tracking instead of HyperLogLog
-        // In production, consider using HyperLogLog via raw command
+        self.add_to_analytics_hll(site_id, "visitors", visitor_id).await
+    }
+
+    /// Delete the monthly visitor sets (`site:{site_id}:visitors:{YYYY-MM}`) kept before
+    /// visitors were counted in HyperLogLogs; they have no TTL and are no longer read
+    pub async fn delete_legacy_visitor_sets(&self) -> Result<usize> {
+        use futures_util::TryStreamExt;
+
+        let keys: Vec<Key> = self
+            .client
+            .scan_buffered("site:*:visitors:[0-9][0-9][0-9][0-9]-[0-9][0-9]", Some(100), None)
+            .try_collect()
+            .await?;
+        let deleted = keys.len();
+        for key in keys {
+            self.client.del::<(), _>(key).await?;
+        }
+        Ok(deleted)
+    }
+
+    /// Count a posted comment in the site's analytics
+    ///
+    /// `author_id` is None for anonymous comments, which don't count as active commenters.
+    pub async fn record_comment_analytics(&self, site_id: Uuid, page_id: Uuid, author_id: Option<Uuid>) -> Result<()> {
+        self.increment_analytics(site_id, "comments", 1).await?;
+        if let Some(author_id) = author_id {
+            self.add_to_analytics_hll(site_id, "commenters", &author_id.to_string()).await?;
+        }
+
+        let key = format!("site:{}:page_comments:{}", site_id, Utc::now().format("%Y%m%d"));
+        self.client.zincrby::<(), _, _>(&key, 1.0, page_id.to_string()).await?;
         self.client
-            .sadd::<(), _, _>(&key, visitor_id)
+            .expire::<(), _>(&key, AnalyticsGranularity::Day.retention().num_seconds() + ANALYTICS_TTL_SLACK, None)
             .await?;
         Ok(())
     }
 
+    /// Add to a counter in the site's current hour and day buckets
+    pub async fn increment_analytics(&self, site_id: Uuid, field: &str, amount: i64) -> Result<()> {
+        let now = Utc::now();
+        let pipeline = self.client.pipeline();
+        for granularity in [AnalyticsGranularity::Hour, AnalyticsGranularity::Day] {
+            let key = format!("site:{}:stats:{}", site_id, granularity.bucket_key(granularity.truncate(now)));
+            pipeline.hincrby::<(), _, _>(&key, field, amount).await?;
+            pipeline
+                .expire::<(), _>(&key, granularity.retention().num_seconds() + ANALYTICS_TTL_SLACK, None)
+                .await?;
+        }
+        pipeline.all::<Vec<Value>>().await?;
+        Ok(())
+    }
+
+    async fn add_to_analytics_hll(&self, site_id: Uuid, kind: &str, member: &str) -> Result<()> {
+        let now = Utc::now();
+        let pipeline = self.client.pipeline();
+        for granularity in [AnalyticsGranularity::Hour, AnalyticsGranularity::Day] {
+            let key = format!("site:{}:{}:{}", site_id, kind, granularity.bucket_key(granularity.truncate(now)));
+            pipeline.pfadd::<(), _, _>(&key, member).await?;
+            pipeline
+                .expire::<(), _>(&key, granularity.retention().num_seconds() + ANALYTICS_TTL_SLACK, None)
+                .await?;
+        }
+        pipeline.all::<Vec<Value>>().await?;
+        Ok(())
+    }
+
+    /// Raise the sites' peak WebSocket connections for the current hour and day to
+    /// `connections` (cluster-wide open connections per site) where it's higher
+    pub async fn record_peak_connections(&self, connections: &HashMap<Uuid, u64>) -> Result<()> {
+        if connections.is_empty() {
+            return Ok(());
+        }
+
+        let now = Utc::now();
+        let mut keys: Vec<Value> = Vec::with_capacity(connections.len() * 2);
+        let mut counts: Vec<Value> = Vec::with_capacity(connections.len());
+        for (site_id, count) in connections {
+            for granularity in [AnalyticsGranularity::Hour, AnalyticsGranularity::Day] {
+                keys.push(format!("site:{}:stats:{}", site_id, granularity.bucket_key(granularity.truncate(now))).into());
+            }
+            counts.push(count.to_string().into());
+        }
+
+        let mut args: Vec<Value> = vec![keys.len().to_string().into()];
+        args.extend(keys);
+        args.push((AnalyticsGranularity::Hour.retention().num_seconds() + ANALYTICS_TTL_SLACK).to_string().into());
+        args.push((AnalyticsGranularity::Day.retention().num_seconds() + ANALYTICS_TTL_SLACK).to_string().into());
+        args.extend(counts);
+        self.eval_strings("record_peak_connections", args).await?;
+        Ok(())
+    }
+
+    /// Activity per bucket, for buckets starting at each of `starts`
+    pub async fn get_analytics(
+        &self,
+        site_id: Uuid,
+        granularity: AnalyticsGranularity,
+        starts: &[DateTime<Utc>],
+    ) -> Result<Vec<AnalyticsPoint>> {
+        if starts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let buckets: Vec<String> = starts.iter().map(|start| granularity.bucket_key(*start)).collect();
+
+        let pipeline = self.client.pipeline();
+        for bucket in &buckets {
+            pipeline
+                .hmget::<(), _, _>(
+                    format!("site:{}:stats:{}", site_id, bucket),
+                    vec!["pageviews", "comments", "peak_connections", "websocket_messages"],
+                )
+                .await?;
+        }
+        let stats: Vec<Vec<Option<i64>>> = pipeline.all().await?;
+
+        let visitors = self.count_analytics_hlls(site_id, "visitors", &buckets).await?;
+        let commenters = self.count_analytics_hlls(site_id, "commenters", &buckets).await?;
+
+        let count = |v: Option<&Option<i64>>| v.copied().flatten().unwrap_or(0).max(0) as u64;
+        Ok(starts
+            .iter()
+            .zip(stats)
+            .zip(visitors.into_iter().zip(commenters))
+            .map(|((start, stats), (unique_visitors, active_commenters))| AnalyticsPoint {
+                start: *start,
+                pageviews: count(stats.first()),
+                comments: count(stats.get(1)),
+                peak_connections: count(stats.get(2)),
+                websocket_messages: count(stats.get(3)),
+                unique_visitors,
+                active_commenters,
+            })
+            .collect())
+    }
+
+    /// Approximate count of each bucket's HyperLogLog
+    async fn count_analytics_hlls(&self, site_id: Uuid, kind: &str, buckets: &[String]) -> Result<Vec<u64>> {
+        let pipeline = self.client.pipeline();
+        for bucket in buckets {
+            pipeline
+                .pfcount::<(), _>(format!("site:{}:{}:{}", site_id, kind, bucket))
+                .await?;
+        }
+        let counts: Vec<i64> = pipeline.all().await?;
+        Ok(counts.into_iter().map(|c| c.max(0) as u64).collect())
+    }
+
+    /// Approximate distinct visitors and commenters across all of the buckets
+    pub async fn count_analytics_uniques(
+        &self,
+        site_id: Uuid,
+        granularity: AnalyticsGranularity,
+        starts: &[DateTime<Utc>],
+    ) -> Result<(u64, u64)> {
+        if starts.is_empty() {
+            return Ok((0, 0));
+        }
+        let keys = |kind: &str| -> Vec<String> {
+            starts
+                .iter()
+                .map(|start| format!("site:{}:{}:{}", site_id, kind, granularity.bucket_key(*start)))
+                .collect()
+        };
+        let visitors: i64 = self.client.pfcount(keys("visitors")).await?;
+        let commenters: i64 = self.client.pfcount(keys("commenters")).await?;
+        Ok((visitors.max(0) as u64, commenters.max(0) as u64))
+    }
+
+    /// Pages with the most engagement over the given days (UTC day starts)
+    ///
+    /// Pages are ranked by views plus `comment_weight` per comment.
+    pub async fn get_top_pages(
+        &self,
+        site_id: Uuid,
+        days: &[DateTime<Utc>],
+        comment_weight: f64,
+        limit: usize,
+    ) -> Result<Vec<TopPage>> {
+        if days.is_empty() || limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        let day_keys = |kind: &str| -> Vec<String> {
+            days.iter()
+                .map(|day| format!("site:{}:{}:{}", site_id, kind, day.format("%Y%m%d")))
+                .collect()
+        };
+        let scratch = Uuid::now_v7();
+        let views_key = format!("site:{}:top_pages:{}:views", site_id, scratch);
+        let comments_key = format!("site:{}:top_pages:{}:comments", site_id, scratch);
+        let ranked_key = format!("site:{}:top_pages:{}:ranked", site_id, scratch);
+
+        let trx = self.client.multi();
+        trx.zunionstore::<(), _, _, _>(&views_key, day_keys("page_views"), Vec::<f64>::new(), None)
+            .await?;
+        trx.zunionstore::<(), _, _, _>(&comments_key, day_keys("page_comments"), Vec::<f64>::new(), None)
+            .await?;
+        trx.zunionstore::<(), _, _, _>(
+            &ranked_key,
+            vec![views_key.clone(), comments_key.clone()],
+            vec![1.0, comment_weight],
+            None,
+        )
+        .await?;
+        trx.zrevrange::<(), _>(&ranked_key, 0, limit as i64 - 1, false).await?;
+        trx.del::<(), _>(vec![ranked_key.clone()]).await?;
+        trx.expire::<(), _>(&views_key, 60, None).await?;
+        trx.expire::<(), _>(&comments_key, 60, None).await?;
+        let results: Vec<Value> = trx.exec(true).await?;
+
+        let page_ids: Vec<String> = results
+            .get(3)
+            .cloned()
+            .map(|v| v.convert())
+            .transpose()?
+            .unwrap_or_default();
+
+        let mut pages = Vec::with_capacity(page_ids.len());
+        if !page_ids.is_empty() {
+            let views: Vec<Option<f64>> = self.client.zmscore(&views_key, page_ids.clone()).await?;
+            let comments: Vec<Option<f64>> = self.client.zmscore(&comments_key, page_ids.clone()).await?;
+            let urls: Vec<Option<String>> = self
+                .client
+                .hmget(format!("site:{}:pages", site_id), page_ids.clone())
+                .await?;
+
+            for (i, page_id) in page_ids.iter().enumerate() {
+                let Ok(page_id) = page_id.parse() else { continue };
+                pages.push(TopPage {
+                    page_id,
+                    page_url: urls.get(i).cloned().flatten(),
+                    pageviews: views.get(i).copied().flatten().unwrap_or(0.0) as u64,
+                    comments: comments.get(i).copied().flatten().unwrap_or(0.0) as u64,
+                });
+            }
+        }
+
+        self.client.del::<(), _>(vec![views_key, comments_key]).await?;
+        Ok(pages)
+    }
+
     // ========================================================================
     // API Key Cache
     // ========================================================================
@@ -1488,6 +2664,109 @@ impl RedisClient {
         Ok(())
     }
 
+    // ========================================================================
+    // Scoped API Tokens
+    // Key: api_token:{sha256 of token} -> ApiToken JSON (expires with the token)
+    // Key: site:{site_id}:api_tokens -> hash of token_id -> token hash
+    // Key: site:{site_id}:api_tokens_used -> hash of token_id -> last use (RFC 3339)
+    // ========================================================================
+
+    /// Store a new token under the hash of its secret
+    pub async fn create_api_token(&self, token: &ApiToken, token_hash: &str) -> Result<()> {
+        let key = format!("api_token:{}", token_hash);
+        let value = serde_json::to_string(token)?;
+        let expiration = token
+            .expires_at
+            .map(|expires_at| Expiration::EXAT(expires_at.timestamp()));
+
+        let trx = self.client.multi();
+        trx.set::<(), _, _>(&key, value, expiration, None, false).await?;
+        trx.hset::<(), _, _>(
+            format!("site:{}:api_tokens", token.site_id),
+            (token.id.to_string(), token_hash),
+        )
+        .await?;
+        trx.exec::<()>(true).await?;
+        Ok(())
+    }
+
+    /// Look up a token by the hash of its secret (None if unknown, revoked or expired)
+    pub async fn get_api_token(&self, token_hash: &str) -> Result<Option<ApiToken>> {
+        let value: Option<String> = self.client.get(format!("api_token:{}", token_hash)).await?;
+        Ok(value.and_then(|v| serde_json::from_str(&v).ok()))
+    }
+
+    /// A site's tokens, oldest first, with when they were last used
+    ///
+    /// Tokens that expired are dropped from the index.
+    pub async fn list_api_tokens(&self, site_id: Uuid) -> Result<Vec<ApiToken>> {
+        let index_key = format!("site:{}:api_tokens", site_id);
+        let index: HashMap<String, String> = self.client.hgetall(&index_key).await?;
+        if index.is_empty() {
+            return Ok(Vec::new());
+        }
+        let last_used: HashMap<String, String> = self
+            .client
+            .hgetall(format!("site:{}:api_tokens_used", site_id))
+            .await?;
+
+        let (ids, hashes): (Vec<String>, Vec<String>) = index.into_iter().unzip();
+        let keys: Vec<String> = hashes.iter().map(|hash| format!("api_token:{}", hash)).collect();
+        let values: Vec<Option<String>> = self.client.mget(keys).await?;
+
+        let mut tokens = Vec::with_capacity(values.len());
+        let mut gone = Vec::new();
+        for (id, value) in ids.into_iter().zip(values) {
+            match value.and_then(|v| serde_json::from_str::<ApiToken>(&v).ok()) {
+                Some(mut token) => {
+                    token.last_used_at = last_used
+                        .get(&id)
+                        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+                        .map(|t| t.with_timezone(&Utc));
+                    tokens.push(token);
+                }
+                None => gone.push(id),
+            }
+        }
+        if !gone.is_empty() {
+            self.client.hdel::<(), _, _>(&index_key, gone.clone()).await?;
+            self.client
+                .hdel::<(), _, _>(format!("site:{}:api_tokens_used", site_id), gone)
+                .await?;
+        }
+
+        tokens.sort_by_key(|token| token.created_at);
+        Ok(tokens)
+    }
+
+    /// Delete a token, returns false if the site has no such token
+    pub async fn revoke_api_token(&self, site_id: Uuid, token_id: Uuid) -> Result<bool> {
+        let index_key = format!("site:{}:api_tokens", site_id);
+        let token_hash: Option<String> = self.client.hget(&index_key, token_id.to_string()).await?;
+        let Some(token_hash) = token_hash else {
+            return Ok(false);
+        };
+
+        let trx = self.client.multi();
+        trx.del::<(), _>(format!("api_token:{}", token_hash)).await?;
+        trx.hdel::<(), _, _>(&index_key, token_id.to_string()).await?;
+        trx.hdel::<(), _, _>(format!("site:{}:api_tokens_used", site_id), token_id.to_string())
+            .await?;
+        trx.exec::<()>(true).await?;
+        Ok(true)
+    }
+
+    /// Record that a token was just used
+    pub async fn touch_api_token(&self, site_id: Uuid, token_id: Uuid) -> Result<()> {
+        self.client
+            .hset::<(), _, _>(
+                format!("site:{}:api_tokens_used", site_id),
+                (token_id.to_string(), Utc::now().to_rfc3339()),
+            )
+            .await?;
+        Ok(())
+    }
+
     // ========================================================================
     // Site Config (for standalone mode)
     // ========================================================================
@@ -1520,6 +2799,20 @@ impl RedisClient {
         Ok(result.len())
     }
 
+    /// IDs of all site configs in Redis, oldest first (site IDs are time-ordered)
+    /// Note: Uses custom to execute KEYS - only use for admin operations
+    pub async fn list_site_ids(&self) -> Result<Vec<Uuid>> {
+        let keys: Vec<String> = self.client
+            .custom(fred::cmd!("KEYS"), vec!["site:*:config"])
+            .await?;
+        let mut ids: Vec<Uuid> = keys
+            .iter()
+            .filter_map(|k| k.strip_prefix("site:")?.strip_suffix(":config")?.parse().ok())
+            .collect();
+        ids.sort();
+        Ok(ids)
+    }
+
     pub async fn set_site_config(&self, config: &SiteConfig) -> Result<()> {
         let json = serde_json::to_string(config)?;
         self.client
@@ -1549,6 +2842,44 @@ impl RedisClient {
         Ok(())
     }
 
+    /// Delete a site config and its API key indexes, so its keys stop working
+    ///
+    /// Comments, roles and other site data are left in place.
+    pub async fn delete_site_config(&self, config: &SiteConfig) -> Result<()> {
+        self.client
+            .del::<(), _>(vec![
+                format!("site:{}:config", config.id),
+                format!("apikey:{}:site", config.project_id_public),
+                format!("apikey:{}:site", config.project_id_secret),
+                format!("apikey:{}", config.project_id_public),
+                format!("apikey:{}", config.project_id_secret),
+            ])
+            .await?;
+        Ok(())
+    }
+
+    /// Keep a replaced API key working for `grace_seconds`, then let its index expire
+    ///
+    /// The key's cached lookup is dropped so requests pick up the rotated config. A grace
+    /// period of 0 revokes the key right away.
+    pub async fn retire_project_id(&self, project_id: &str, site_id: Uuid, grace_seconds: u64) -> Result<()> {
+        let key = format!("apikey:{}:site", project_id);
+        if grace_seconds == 0 {
+            self.client.del::<(), _>(&key).await?;
+        } else {
+            self.client
+                .set::<(), _, _>(
+                    &key,
+                    site_id.to_string(),
+                    Some(Expiration::EX(grace_seconds as i64)),
+                    None,
+                    false,
+                )
+                .await?;
+        }
+        self.invalidate_project_id_cache(project_id).await
+    }
+
     /// Look up site by API key (for SaaS mode)
     pub async fn get_site_by_project_id(&self, project_id: &str) -> Result<Option<(Uuid, SiteConfig)>> {
         // Get site_id from API key index
@@ -1634,103 +2965,364 @@ impl RedisClient {
     }
 
     // ========================================================================
-    // Pub/Sub for real-time updates
+    // Webhook Deliveries
     // ========================================================================
 
-    pub async fn publish(&self, channel: &str, message: &str) -> Result<()> {
-        self.client.publish::<(), _, _>(channel, message).await?;
+    /// Store a new delivery, add it to the site's recent deliveries and queue it for sending
+    pub async fn enqueue_webhook_delivery(&self, delivery: &WebhookDelivery, due_at_ms: i64) -> Result<()> {
+        let json = serde_json::to_string(delivery)?;
+        let site_key = format!("site:{}:webhook_deliveries", delivery.site_id);
+
+        let trx = self.client.multi();
+        trx.set::<(), _, _>(
+            format!("webhook:delivery:{}", delivery.id),
+            json,
+            Some(Expiration::EX(WEBHOOK_DELIVERY_TTL)),
+            None,
+            false,
+        )
+        .await?;
+        trx.zadd::<(), _, _>(
+            site_key.clone(),
+            None,
+            None,
+            false,
+            false,
+            (delivery.created_at.timestamp_millis() as f64, delivery.id.to_string()),
+        )
+        .await?;
+        // Only keep the most recent deliveries per site
+        trx.zremrangebyrank::<(), _>(site_key, 0, -(MAX_SITE_WEBHOOK_DELIVERIES + 1))
+            .await?;
+        trx.zadd::<(), _, _>(
+            WEBHOOK_QUEUE_KEY,
+            None,
+            None,
+            false,
+            false,
+            (due_at_ms as f64, delivery.id.to_string()),
+        )
+        .await?;
+        trx.exec::<()>(true).await?;
+        Ok(())
+    }
+
+    pub async fn get_webhook_delivery(&self, delivery_id: Uuid) -> Result<Option<WebhookDelivery>> {
+        let value: Option<String> = self
+            .client
+            .get(format!("webhook:delivery:{}", delivery_id))
+            .await?;
+        Ok(value.and_then(|v| serde_json::from_str(&v).ok()))
+    }
+
+    /// Update a stored delivery (keeps the original expiry window)
+    pub async fn save_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<()> {
+        let json = serde_json::to_string(delivery)?;
+        self.client
+            .set::<(), _, _>(
+                format!("webhook:delivery:{}", delivery.id),
+                json,
+                Some(Expiration::KEEPTTL),
+                None,
+                false,
+            )
+            .await?;
         Ok(())
     }
 
+    /// Recent deliveries for a site, newest first
+    pub async fn get_site_webhook_deliveries(
+        &self,
+        site_id: Uuid,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<WebhookDelivery>> {
+        let ids: Vec<String> = self
+            .client
+            .zrevrange(
+                format!("site:{}:webhook_deliveries", site_id),
+                offset as i64,
+                (offset + limit - 1) as i64,
+                false,
+            )
+            .await?;
+
+        if ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let keys: Vec<String> = ids.iter().map(|id| format!("webhook:delivery:{}", id)).collect();
+        let values: Vec<Option<String>> = self.client.mget(keys).await?;
+
+        Ok(values
+            .into_iter()
+            .flatten()
+            .filter_map(|v| serde_json::from_str(&v).ok())
+            .collect())
+    }
+
+    /// (Re)schedule a delivery in the retry queue
+    pub async fn schedule_webhook_delivery(&self, delivery_id: Uuid, due_at_ms: i64) -> Result<()> {
+        self.client
+            .zadd::<(), _, _>(
+                WEBHOOK_QUEUE_KEY,
+                None,
+                None,
+                false,
+                false,
+                (due_at_ms as f64, delivery_id.to_string()),
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_webhook_from_queue(&self, delivery_id: Uuid) -> Result<()> {
+        self.client
+            .zrem::<(), _, _>(WEBHOOK_QUEUE_KEY, delivery_id.to_string())
+            .await?;
+        Ok(())
+    }
+
+    /// Atomically claim up to `limit` due deliveries.
+    ///
+    /// Claimed deliveries stay queued until `lease_until_ms`, so another worker picks them
+    /// up again if this one never reports back.
+    pub async fn claim_webhook_deliveries(
+        &self,
+        now_ms: i64,
+        lease_until_ms: i64,
+        limit: usize,
+    ) -> Result<Vec<Uuid>> {
+        let sha = self.script_shas.get("claim_webhooks")
+            .ok_or_else(|| Error::Internal("claim_webhooks script not loaded".to_string()))?;
+
+        let args: Vec<Value> = vec![
+            sha.clone().into(),
+            "1".into(), // 1 key
+            WEBHOOK_QUEUE_KEY.into(),
+            now_ms.to_string().into(),
+            lease_until_ms.to_string().into(),
+            limit.to_string().into(),
+        ];
+
+        let cmd = CustomCommand::new("EVALSHA", ClusterHash::FirstKey, false);
+        let frame = self.client.custom_raw::<Value>(cmd, args).await?;
+
+        let result: Vec<Value> = match frame {
+            Resp3Frame::Array { data, .. } => {
+                data.into_iter()
+                    .map(|f| f.try_into())
+                    .collect::<std::result::Result<Vec<Value>, _>>()
+                    .map_err(|e: fred::error::Error| Error::Redis(e))?
+            }
+            Resp3Frame::SimpleError { data, .. } => {
+                return Err(Error::Internal(format!("Lua script error: {}", data)));
+            }
+            Resp3Frame::BlobError { data, .. } => {
+                let err_msg = String::from_utf8_lossy(&data);
+                return Err(Error::Internal(format!("Lua script error: {}", err_msg)));
+            }
+            other => {
+                return Err(Error::Internal(format!("Unexpected response type from EVALSHA: {:?}", other)));
+            }
+        };
+
+        Ok(result
+            .into_iter()
+            .filter_map(|v| v.as_string())
+            .filter_map(|s| s.parse().ok())
+            .collect())
+    }
+
     // ========================================================================
-    // Account Deletion (GDPR)
+    // Pub/Sub for real-time updates
     // ========================================================================
 
-    /// Delete all user data for GDPR compliance
-    /// Anonymizes comments (keeps content but removes personal data) to preserve conversation threading
-    pub async fn delete_user_account(&self, user_id: Uuid) -> Result<DeletedAccountStats> {
-        let mut stats = DeletedAccountStats::default();
+    pub async fn publish(&self, channel: &str, message: &str) -> Result<()> {
+        self.client.publish::<(), _, _>(channel, message).await?;
+        Ok(())
+    }
 
-        // Get user data first for index cleanup
-        let user = self.get_user(user_id).await?;
+    /// Publish a page event for WebSocket servers to relay
+    ///
+    /// Each event gets the page's next sequence number and is appended to a
+    /// capped per-page log (`page:{page_id}:event_log`) so reconnecting
+    /// clients can replay what they missed. Events are published to
+    /// `threadkit:page:{page_id}:events`. Returns the event's sequence number.
+    pub async fn publish_page_event(
+        &self,
+        page_id: Uuid,
+        event_type: &str,
+        data: serde_json::Value,
+    ) -> Result<u64> {
+        let sha = self.script_shas.get("publish_page_event")
+            .ok_or_else(|| Error::Internal("publish_page_event script not loaded".to_string()))?;
+
+        let args: Vec<Value> = vec![
+            sha.clone().into(),
+            "2".into(), // 2 keys
+            format!("page:{}:event_seq", page_id).into(),
+            format!("page:{}:event_log", page_id).into(),
+            format!("threadkit:page:{}:events", page_id).into(),
+            event_type.into(),
+            page_id.to_string().into(),
+            data.to_string().into(),
+            PAGE_EVENT_LOG_LEN.into(),
+            PAGE_EVENT_LOG_TTL.into(),
+        ];
 
-        // Anonymize all user's comments (GDPR-compliant: keep content, remove personal data)
-        // Use get_user_comment_index which returns ALL comments (no pagination)
-        let comment_refs = self.get_user_comment_index(user_id, 0, usize::MAX).await.unwrap_or_default();
+        let cmd = CustomCommand::new("EVALSHA", ClusterHash::FirstKey, false);
+        let frame = self.client.custom_raw::<Value>(cmd, args).await?;
 
-        // Group comments by page_id to minimize page tree loads
-        let mut comments_by_page: std::collections::HashMap<Uuid, Vec<Uuid>> = std::collections::HashMap::new();
-        for (page_id, comment_id) in comment_refs {
-            comments_by_page.entry(page_id).or_default().push(comment_id);
+        match frame {
+            Resp3Frame::Number { data, .. } => Ok(data.max(0) as u64),
+            Resp3Frame::SimpleError { data, .. } => {
+                Err(Error::Internal(format!("Lua script error: {}", data)))
+            }
+            Resp3Frame::BlobError { data, .. } => {
+                let err_msg = String::from_utf8_lossy(&data);
+                Err(Error::Internal(format!("Lua script error: {}", err_msg)))
+            }
+            other => {
+                Err(Error::Internal(format!("Unexpected response type from EVALSHA: {:?}", other)))
+            }
         }
+    }
 
-        // Anonymize comments in each affected page tree
-        for (page_id, comment_ids) in comments_by_page {
-            // Load the page tree
-            let mut tree = self.get_or_create_page_tree(page_id).await?;
+    /// Publish a site-wide moderation event for moderator dashboards
+    ///
+    /// Events are published to `threadkit:site:{site_id}:moderation`
+    pub async fn publish_moderation_event(
+        &self,
+        site_id: Uuid,
+        event_type: &str,
+        data: serde_json::Value,
+    ) -> Result<()> {
+        let message = serde_json::json!({
+            "type": event_type,
+            "site_id": site_id,
+            "data": data
+        });
+        self.publish(&format!("threadkit:site:{}:moderation", site_id), &message.to_string())
+            .await
+    }
 
-            // Anonymize each comment in this page
-            for comment_id in comment_ids {
-                tree.anonymize_comment(comment_id);
-                stats.comments_deleted += 1;
-            }
+    /// Publish a session or ban change for WebSocket servers to apply
+    ///
+    /// Events are published to `threadkit:auth:events`
+    pub async fn publish_auth_event(&self, event: &AuthEvent) -> Result<()> {
+        self.publish("threadkit:auth:events", &serde_json::to_string(event)?)
+            .await
+    }
 
-            // Save the modified tree back to Redis
-            self.set_page_tree(page_id, &tree).await?;
-        }
+    /// Publish a notification for the user's open WebSocket connections to the site
+    ///
+    /// Events are published to `threadkit:user:{user_id}:notifications`
+    pub async fn publish_notification_event(
+        &self,
+        user_id: Uuid,
+        site_id: Uuid,
+        data: serde_json::Value,
+    ) -> Result<()> {
+        let message = serde_json::json!({
+            "site_id": site_id,
+            "data": data
+        });
+        self.publish(&format!("threadkit:user:{}:notifications", user_id), &message.to_string())
+            .await
+    }
 
-        // Keep user comments set - it maps to anonymized comments now
-        // (Deleting it would break the "get user comments" functionality for the deleted user ID)
+    /// Get the sequence number of a page's latest event (0 if none)
+    pub async fn get_page_event_seq(&self, page_id: Uuid) -> Result<u64> {
+        let seq: Option<u64> = self.client.get(format!("page:{}:event_seq", page_id)).await?;
+        Ok(seq.unwrap_or(0))
+    }
 
-        // Delete user's votes (personal data)
-        // NOTE: We keep the vote COUNTS on comments but remove the user's specific votes
-        let vote_ids = self.get_user_votes(user_id).await.unwrap_or_default();
-        for comment_id in &vote_ids {
-            // Delete the vote record (don't reverse vote counts - preserves comment karma)
-            self.client.del::<(), _>(format!("vote:{}:{}", user_id, comment_id)).await?;
-            stats.votes_deleted += 1;
+    /// Get a page's events published after `since_seq`
+    ///
+    /// Returns `PageEventReplay::Gap` when the oldest missed event has already
+    /// been trimmed from the log or expired, or when `since_seq` is ahead of the
+    /// page's sequence (e.g. the counter expired and restarted), since the client's
+    /// view can't be reconciled with the log.
+    pub async fn get_page_events_since(&self, page_id: Uuid, since_seq: u64) -> Result<PageEventReplay> {
+        let latest_seq = self.get_page_event_seq(page_id).await?;
+        if since_seq > latest_seq {
+            return Ok(PageEventReplay::Gap { latest_seq });
+        }
+        if since_seq == latest_seq {
+            return Ok(PageEventReplay::Events(Vec::new()));
         }
-        self.client.del::<(), _>(format!("user:{}:votes", user_id)).await?;
 
-        // Delete notifications
-        self.client.del::<(), _>(format!("user:{}:notifications", user_id)).await?;
-        self.client.del::<(), _>(format!("user:{}:unread", user_id)).await?;
+        let entries: Vec<(String, HashMap<String, String>)> = self
+            .client
+            .xrange(
+                format!("page:{}:event_log", page_id),
+                format!("{}-0", since_seq + 1),
+                "+",
+                None,
+            )
+            .await?;
 
-        // Clean up blocking relationships
-        // Users this account blocked
-        let blocked = self.get_blocked_users(user_id).await.unwrap_or_default();
-        for blocked_id in blocked {
-            self.client
-                .srem::<(), _, _>(format!("user:{}:blocked_by", blocked_id), user_id.to_string())
-                .await?;
+        let first_id = format!("{}-0", since_seq + 1);
+        if entries.first().map(|(id, _)| id) != Some(&first_id) {
+            return Ok(PageEventReplay::Gap { latest_seq });
         }
-        self.client.del::<(), _>(format!("user:{}:blocked", user_id)).await?;
 
-        // Users who blocked this account
-        let blocked_by = self.get_blocked_by(user_id).await.unwrap_or_default();
-        for blocker_id in blocked_by {
-            self.client
-                .srem::<(), _, _>(format!("user:{}:blocked", blocker_id), user_id.to_string())
-                .await?;
+        Ok(PageEventReplay::Events(
+            entries
+                .into_iter()
+                .filter_map(|(_, mut fields)| fields.remove("event"))
+                .collect(),
+        ))
+    }
+
+    // ========================================================================
+    // Account Deletion (GDPR)
+    // ========================================================================
+
+    /// Delete the user hash, password and email/username indexes
+    pub async fn delete_user(&self, user: &User) -> Result<()> {
+        if let Some(ref email) = user.email {
+            self.client.del::<(), _>(format!("email:{}", email.to_lowercase())).await?;
         }
-        self.client.del::<(), _>(format!("user:{}:blocked_by", user_id)).await?;
+        self.client.del::<(), _>(format!("username:{}", user.name.to_lowercase())).await?;
+        self.client.del::<(), _>(format!("user:{}:password", user.id)).await?;
+        self.client.del::<(), _>(format!("user:{}", user.id)).await?;
+        Ok(())
+    }
 
-        // Delete email/username/provider indexes
-        if let Some(ref user) = user {
-            if let Some(ref email) = user.email {
-                self.client.del::<(), _>(format!("email:{}", email.to_lowercase())).await?;
-            }
-            // Delete username index
-            self.client.del::<(), _>(format!("username:{}", user.name.to_lowercase())).await?;
+    /// Delete a user's vote records (per-page hashes and the legacy per-comment keys)
+    /// Vote counts on comments are kept. Returns the number of votes removed.
+    pub async fn delete_user_votes(&self, user_id: Uuid) -> Result<i64> {
+        let mut comment_ids: std::collections::HashSet<Uuid> = std::collections::HashSet::new();
+
+        let page_votes = self.get_all_page_votes(user_id).await?;
+        let mut page_ids: std::collections::HashSet<Uuid> = std::collections::HashSet::new();
+        for (page_id, comment_id, _) in page_votes {
+            page_ids.insert(page_id);
+            comment_ids.insert(comment_id);
+        }
+        for page_id in page_ids {
+            self.client.del::<(), _>(format!("votes:{}:{}", user_id, page_id)).await?;
         }
 
-        // Note: Provider indexes would need to be tracked to delete them
-        // For now, they will be orphaned but harmless
+        for comment_id in self.get_user_votes(user_id).await? {
+            self.client.del::<(), _>(format!("vote:{}:{}", user_id, comment_id)).await?;
+            comment_ids.insert(comment_id);
+        }
+        self.client.del::<(), _>(format!("user:{}:votes", user_id)).await?;
+        self.client.del::<(), _>(format!("user:{}:vote_pages", user_id)).await?;
 
-        // Delete user hash
-        self.client.del::<(), _>(format!("user:{}", user_id)).await?;
+        Ok(comment_ids.len() as i64)
+    }
 
-        Ok(stats)
+    /// Delete all of a user's notifications and the unread counter
+    pub async fn delete_notifications(&self, user_id: Uuid) -> Result<()> {
+        self.client.del::<(), _>(format!("user:{}:notifications", user_id)).await?;
+        self.client.del::<(), _>(format!("user:{}:unread", user_id)).await?;
+        self.client.del::<(), _>(format!("user:{}:notifications:emailed", user_id)).await?;
+        self.remove_notification_email(user_id).await?;
+        Ok(())
     }
 
     /// Track a vote for later cleanup during account deletion
@@ -0,0 +1,412 @@
+//! Pluggable persistence for durable data.
+//!
+//! The [`Storage`] trait covers users, page trees and comment revisions, votes,
+//! roles, notifications, sessions and rate limits. [`RedisClient`] implements it
+//! on the existing key layout and [`SqliteStore`] keeps the same data in an
+//! embedded database. The backend is picked by `STORAGE_BACKEND`; see [`connect`].
+//!
+//! Redis is still required with every backend: site configs, comment indexes,
+//! queues, caches and pub/sub stay on [`RedisClient`].
+
+mod redis;
+mod sqlite;
+
+pub use sqlite::SqliteStore;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::config::StorageBackend;
+use crate::redis::{RateLimitResult, RedisClient};
+use crate::types::{
+    CommentRevision, DeletedAccountStats, Notification, PageTree, RefreshRotation, Role,
+    SessionInfo, User, VoteDirection, DELETED_USER_ID,
+};
+use crate::{Error, Result};
+
+const TREE_UPDATE_MAX_ATTEMPTS: u32 = 50;
+const TREE_UPDATE_BACKOFF_MS: u64 = 2;
+
+/// When a session ends, given its current refresh token's expiry
+///
+/// Sessions outlive their refresh token by a day, so an expired token is still told apart
+/// from an unknown one for a while.
+pub(crate) fn session_expiry(refresh_expires_at: DateTime<Utc>) -> DateTime<Utc> {
+    refresh_expires_at + Duration::days(1)
+}
+
+/// Result of a vote: (new_vote, upvotes, downvotes, upvote_delta, downvote_delta)
+pub type VoteOutcome = (Option<VoteDirection>, i64, i64, i64, i64);
+
+/// Result of toggling a reaction: (added, reaction counts for the comment)
+pub type ReactionOutcome = (bool, BTreeMap<String, i64>);
+
+#[async_trait]
+pub trait Storage: Send + Sync {
+    // ========================================================================
+    // Users
+    // ========================================================================
+
+    async fn get_user(&self, user_id: Uuid) -> Result<Option<User>>;
+    async fn set_user(&self, user: &User) -> Result<()>;
+    /// Delete the user record, password and lookup indexes
+    async fn delete_user(&self, user: &User) -> Result<()>;
+
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<Uuid>>;
+    async fn set_user_email_index(&self, email: &str, user_id: Uuid) -> Result<()>;
+    async fn get_user_by_username(&self, username: &str) -> Result<Option<Uuid>>;
+    async fn set_user_username_index(&self, username: &str, user_id: Uuid) -> Result<()>;
+    async fn delete_user_username_index(&self, username: &str) -> Result<()>;
+    async fn get_user_by_phone(&self, phone: &str) -> Result<Option<Uuid>>;
+    async fn set_user_phone_index(&self, phone: &str, user_id: Uuid) -> Result<()>;
+    async fn get_user_by_provider(&self, provider: &str, provider_id: &str)
+    -> Result<Option<Uuid>>;
+    async fn set_user_provider_index(
+        &self,
+        provider: &str,
+        provider_id: &str,
+        user_id: Uuid,
+    ) -> Result<()>;
+    async fn get_user_by_wallet(&self, chain: &str, address: &str) -> Result<Option<Uuid>>;
+    async fn set_user_wallet_index(&self, chain: &str, address: &str, user_id: Uuid) -> Result<()>;
+
+    async fn set_user_password(&self, user_id: Uuid, password_hash: &str) -> Result<()>;
+    async fn get_user_password(&self, user_id: Uuid) -> Result<Option<String>>;
+
+    /// Update user karma by delta, returns the new karma
+    async fn update_user_karma(&self, user_id: Uuid, delta: i64) -> Result<i64>;
+    /// Change user's total comment count by `count`, returns the new count
+    async fn increment_user_comment_count_by(&self, user_id: Uuid, count: i64) -> Result<i64>;
+
+    async fn block_user_by_user(&self, blocker_id: Uuid, blocked_id: Uuid) -> Result<()>;
+    async fn unblock_user_by_user(&self, blocker_id: Uuid, blocked_id: Uuid) -> Result<()>;
+    async fn is_blocked_by_user(&self, blocker_id: Uuid, blocked_id: Uuid) -> Result<bool>;
+    /// Get all users that this user has blocked
+    async fn get_blocked_users(&self, user_id: Uuid) -> Result<Vec<Uuid>>;
+    /// Get all users who have blocked this user
+    async fn get_blocked_by(&self, user_id: Uuid) -> Result<Vec<Uuid>>;
+
+    /// Check if username is available (not taken by another user)
+    async fn is_username_available(
+        &self,
+        username: &str,
+        exclude_user_id: Option<Uuid>,
+    ) -> Result<bool> {
+        match self.get_user_by_username(username).await? {
+            None => Ok(true),
+            Some(existing_id) => Ok(exclude_user_id == Some(existing_id)),
+        }
+    }
+
+    async fn increment_user_comment_count(&self, user_id: Uuid) -> Result<i64> {
+        self.increment_user_comment_count_by(user_id, 1).await
+    }
+
+    // ========================================================================
+    // Page Trees
+    // ========================================================================
+
+    async fn get_page_tree(&self, page_id: Uuid) -> Result<Option<PageTree>>;
+    /// Get a page tree together with its write version (0 if never written)
+    async fn get_page_tree_versioned(&self, page_id: Uuid) -> Result<(Option<PageTree>, u64)>;
+    /// Write a page tree only if its version is still `expected_version`
+    /// Returns false if another writer got there first
+    async fn compare_and_set_page_tree(
+        &self,
+        page_id: Uuid,
+        expected_version: u64,
+        tree: &PageTree,
+    ) -> Result<bool>;
+    /// Set the entire page tree unconditionally
+    ///
+    /// Prefer `update_page_tree` for read-modify-write, this overwrites concurrent changes.
+    async fn set_page_tree(&self, page_id: Uuid, tree: &PageTree) -> Result<()>;
+
+    async fn get_or_create_page_tree(&self, page_id: Uuid) -> Result<PageTree> {
+        Ok(self
+            .get_page_tree(page_id)
+            .await?
+            .unwrap_or_else(PageTree::new))
+    }
+
+    // ========================================================================
+    // Comment Revisions
+    // ========================================================================
+
+    /// Record the previous version of an edited comment
+    async fn add_comment_revision(&self, comment_id: Uuid, revision: &CommentRevision) -> Result<()>;
+    /// Get a comment's previous versions, oldest first
+    async fn get_comment_revisions(&self, comment_id: Uuid) -> Result<Vec<CommentRevision>>;
+    async fn delete_comment_revisions(&self, comment_id: Uuid) -> Result<()>;
+    /// Attribute the revisions of a comment made by `from` to `to`
+    async fn reassign_comment_revisions(&self, comment_id: Uuid, from: Uuid, to: Uuid) -> Result<()>;
+
+    // ========================================================================
+    // Votes
+    // ========================================================================
+
+    /// Apply a vote and update the comment's counts in the tree atomically
+    /// Voting the same direction twice removes the vote
+    async fn atomic_vote(
+        &self,
+        user_id: Uuid,
+        page_id: Uuid,
+        comment_id: Uuid,
+        path: &[Uuid],
+        direction: VoteDirection,
+    ) -> Result<VoteOutcome>;
+    /// Get all of a user's votes for a page
+    async fn get_page_votes(
+        &self,
+        user_id: Uuid,
+        page_id: Uuid,
+    ) -> Result<std::collections::HashMap<Uuid, VoteDirection>>;
+    /// Get a user's votes across all pages as (page_id, comment_id, direction)
+    async fn get_all_page_votes(&self, user_id: Uuid) -> Result<Vec<(Uuid, Uuid, VoteDirection)>>;
+    /// Forget all of a user's votes (counts on comments are kept), returns how many were removed
+    async fn delete_user_votes(&self, user_id: Uuid) -> Result<i64>;
+
+    // ========================================================================
+    // Reactions
+    // ========================================================================
+
+    /// Toggle a user's emoji reaction and update the comment's counts in the tree atomically
+    async fn toggle_reaction(
+        &self,
+        user_id: Uuid,
+        page_id: Uuid,
+        comment_id: Uuid,
+        path: &[Uuid],
+        emoji: &str,
+    ) -> Result<ReactionOutcome>;
+    /// Get all of a user's reactions for a page as comment_id -> emojis
+    async fn get_page_reactions(
+        &self,
+        user_id: Uuid,
+        page_id: Uuid,
+    ) -> Result<std::collections::HashMap<Uuid, Vec<String>>>;
+    /// Get a user's reactions across all pages as (page_id, comment_id, emoji)
+    async fn get_all_page_reactions(&self, user_id: Uuid) -> Result<Vec<(Uuid, Uuid, String)>>;
+    /// Forget all of a user's reactions (counts on comments are kept)
+    async fn delete_user_reactions(&self, user_id: Uuid) -> Result<()>;
+
+    // ========================================================================
+    // Roles
+    // ========================================================================
+
+    async fn get_user_role(&self, site_id: Uuid, user_id: Uuid) -> Result<Role>;
+    async fn add_admin(&self, site_id: Uuid, user_id: Uuid) -> Result<()>;
+    async fn remove_admin(&self, site_id: Uuid, user_id: Uuid) -> Result<()>;
+    async fn get_admins(&self, site_id: Uuid) -> Result<Vec<Uuid>>;
+    async fn add_moderator(&self, site_id: Uuid, user_id: Uuid) -> Result<()>;
+    async fn remove_moderator(&self, site_id: Uuid, user_id: Uuid) -> Result<()>;
+    async fn get_moderators(&self, site_id: Uuid) -> Result<Vec<Uuid>>;
+    async fn block_user(&self, site_id: Uuid, user_id: Uuid) -> Result<()>;
+    async fn unblock_user(&self, site_id: Uuid, user_id: Uuid) -> Result<()>;
+    async fn get_site_blocked_users(&self, site_id: Uuid) -> Result<Vec<Uuid>>;
+    async fn shadowban_user(&self, site_id: Uuid, user_id: Uuid) -> Result<()>;
+    async fn is_shadowbanned(&self, site_id: Uuid, user_id: Uuid) -> Result<bool>;
+    async fn get_site_shadowbanned_users(&self, site_id: Uuid) -> Result<Vec<Uuid>>;
+
+    // ========================================================================
+    // Notifications
+    // ========================================================================
+
+    async fn add_notification(&self, user_id: Uuid, notification: &Notification) -> Result<()>;
+    /// Newest first, `limit` of usize::MAX means all
+    async fn get_notifications(
+        &self,
+        user_id: Uuid,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<Notification>>;
+    async fn get_unread_count(&self, user_id: Uuid) -> Result<i64>;
+    async fn mark_notifications_read(&self, user_id: Uuid) -> Result<()>;
+    async fn delete_notifications(&self, user_id: Uuid) -> Result<()>;
+
+    // ========================================================================
+    // Sessions
+    // ========================================================================
+
+    /// Start a session that lasts until its first refresh token, `expires_at`, has expired
+    async fn create_session(
+        &self,
+        session_id: Uuid,
+        user_id: Uuid,
+        user_agent: &str,
+        ip: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()>;
+    /// The session's user, unless the session was deleted or has expired
+    async fn get_session_user(&self, session_id: Uuid) -> Result<Option<Uuid>>;
+    /// Also deletes the session's refresh tokens
+    async fn delete_session(&self, session_id: Uuid) -> Result<()>;
+    /// Set a session's refresh token (by hash), replacing the current one and extending the session
+    async fn set_refresh_token(
+        &self,
+        session_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()>;
+    /// Atomically replace the session's current refresh token, remembering the old one as used
+    ///
+    /// The token it replaced, presented again within `reuse_grace`, is `Superseded` rather than
+    /// `Reused`; older used tokens are always `Reused`.
+    async fn rotate_refresh_token(
+        &self,
+        session_id: Uuid,
+        token_hash: &str,
+        new_token_hash: &str,
+        expires_at: DateTime<Utc>,
+        reuse_grace: Duration,
+    ) -> Result<RefreshRotation>;
+    /// Get a user's active sessions, oldest first
+    async fn get_user_sessions(&self, user_id: Uuid) -> Result<Vec<SessionInfo>>;
+
+    // ========================================================================
+    // Rate Limiting
+    // ========================================================================
+
+    /// Check rate limit using a sliding window, recording the request if allowed
+    async fn check_rate_limit(
+        &self,
+        key: &str,
+        max_requests: u32,
+        window_secs: u64,
+    ) -> Result<RateLimitResult>;
+}
+
+impl dyn Storage + '_ {
+    /// Atomically read-modify-write a page tree.
+    ///
+    /// Reads the tree together with its version, applies `f`, and writes the result back
+    /// with `compare_and_set_page_tree`. If another writer changed the tree in between,
+    /// the tree is re-read and `f` runs again, so `f` must be safe to call more than once.
+    ///
+    /// If `f` returns `Err`, nothing is written and the error is returned as the inner result.
+    /// A missing tree is passed to `f` as an empty `PageTree`.
+    pub async fn update_page_tree<T, E, F>(
+        &self,
+        page_id: Uuid,
+        mut f: F,
+    ) -> Result<std::result::Result<(PageTree, T), E>>
+    where
+        F: FnMut(&mut PageTree) -> std::result::Result<T, E>,
+    {
+        for attempt in 0..TREE_UPDATE_MAX_ATTEMPTS {
+            let (tree, version) = self.get_page_tree_versioned(page_id).await?;
+            let mut tree = tree.unwrap_or_else(PageTree::new);
+
+            let value = match f(&mut tree) {
+                Ok(value) => value,
+                Err(e) => return Ok(Err(e)),
+            };
+
+            if self
+                .compare_and_set_page_tree(page_id, version, &tree)
+                .await?
+            {
+                return Ok(Ok((tree, value)));
+            }
+
+            // Lost the race - back off briefly (with jitter) before retrying
+            let max_delay = TREE_UPDATE_BACKOFF_MS * (attempt as u64 + 1);
+            let delay = rand::random::<u64>() % max_delay + 1;
+            tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+        }
+
+        Err(Error::Internal(format!(
+            "Page tree update for {} conflicted {} times",
+            page_id, TREE_UPDATE_MAX_ATTEMPTS
+        )))
+    }
+}
+
+/// Open the configured storage backend
+///
+/// The Redis backend shares the client that the rest of the server already uses.
+pub async fn connect(
+    backend: &StorageBackend,
+    redis: Arc<RedisClient>,
+) -> Result<Arc<dyn Storage>> {
+    match backend {
+        StorageBackend::Redis => Ok(redis),
+        StorageBackend::Sqlite { path } => Ok(Arc::new(SqliteStore::open(path).await?)),
+    }
+}
+
+/// Delete all user data for GDPR compliance
+/// Anonymizes comments (keeps content but removes personal data) to preserve conversation threading
+pub async fn delete_user_account(
+    store: &dyn Storage,
+    redis: &RedisClient,
+    user_id: Uuid,
+) -> Result<DeletedAccountStats> {
+    let mut stats = DeletedAccountStats::default();
+
+    let user = store.get_user(user_id).await?;
+
+    // Anonymize all user's comments (GDPR-compliant: keep content, remove personal data)
+    // Use get_user_comment_index which returns ALL comments (no pagination)
+    let comment_refs = redis
+        .get_user_comment_index(user_id, 0, usize::MAX)
+        .await
+        .unwrap_or_default();
+
+    // Group comments by page_id to minimize page tree loads
+    let mut comments_by_page: std::collections::HashMap<Uuid, Vec<Uuid>> =
+        std::collections::HashMap::new();
+    for (page_id, comment_id) in comment_refs {
+        comments_by_page
+            .entry(page_id)
+            .or_default()
+            .push(comment_id);
+    }
+
+    for (page_id, comment_ids) in comments_by_page {
+        let _ = store
+            .update_page_tree(page_id, |tree| {
+                for comment_id in &comment_ids {
+                    tree.anonymize_comment(*comment_id);
+                }
+                Ok::<_, Error>(())
+            })
+            .await??;
+        // Revision history would otherwise link the anonymized comments back to the account
+        for comment_id in &comment_ids {
+            store
+                .reassign_comment_revisions(*comment_id, user_id, DELETED_USER_ID)
+                .await?;
+        }
+        stats.comments_deleted += comment_ids.len() as i64;
+    }
+
+    // Keep user comments index - it maps to anonymized comments now
+    // (Deleting it would break the "get user comments" functionality for the deleted user ID)
+
+    // Delete user's votes (personal data)
+    // NOTE: We keep the vote COUNTS on comments but remove the user's specific votes
+    stats.votes_deleted = store.delete_user_votes(user_id).await?;
+    store.delete_user_reactions(user_id).await?;
+
+    store.delete_notifications(user_id).await?;
+
+    // Clean up blocking relationships in both directions
+    for blocked_id in store.get_blocked_users(user_id).await.unwrap_or_default() {
+        store.unblock_user_by_user(user_id, blocked_id).await?;
+    }
+    for blocker_id in store.get_blocked_by(user_id).await.unwrap_or_default() {
+        store.unblock_user_by_user(blocker_id, user_id).await?;
+    }
+
+    // Note: Provider indexes would need to be tracked to delete them
+    // For now, they will be orphaned but harmless
+    if let Some(user) = user {
+        store.delete_user(&user).await?;
+    }
+
+    Ok(stats)
+}
@@ -0,0 +1,338 @@
+//! `Storage` on the Redis key layout - every method delegates to `RedisClient`
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use super::{ReactionOutcome, Storage, VoteOutcome};
+use crate::Result;
+use crate::redis::{RateLimitResult, RedisClient};
+use crate::types::{
+    CommentRevision, Notification, PageTree, RefreshRotation, Role, SessionInfo, User,
+    VoteDirection,
+};
+
+#[async_trait]
+impl Storage for RedisClient {
+    async fn get_user(&self, user_id: Uuid) -> Result<Option<User>> {
+        RedisClient::get_user(self, user_id).await
+    }
+
+    async fn set_user(&self, user: &User) -> Result<()> {
+        RedisClient::set_user(self, user).await
+    }
+
+    async fn delete_user(&self, user: &User) -> Result<()> {
+        RedisClient::delete_user(self, user).await
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<Uuid>> {
+        RedisClient::get_user_by_email(self, email).await
+    }
+
+    async fn set_user_email_index(&self, email: &str, user_id: Uuid) -> Result<()> {
+        RedisClient::set_user_email_index(self, email, user_id).await
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> Result<Option<Uuid>> {
+        RedisClient::get_user_by_username(self, username).await
+    }
+
+    async fn set_user_username_index(&self, username: &str, user_id: Uuid) -> Result<()> {
+        RedisClient::set_user_username_index(self, username, user_id).await
+    }
+
+    async fn delete_user_username_index(&self, username: &str) -> Result<()> {
+        RedisClient::delete_user_username_index(self, username).await
+    }
+
+    async fn get_user_by_phone(&self, phone: &str) -> Result<Option<Uuid>> {
+        RedisClient::get_user_by_phone(self, phone).await
+    }
+
+    async fn set_user_phone_index(&self, phone: &str, user_id: Uuid) -> Result<()> {
+        RedisClient::set_user_phone_index(self, phone, user_id).await
+    }
+
+    async fn get_user_by_provider(
+        &self,
+        provider: &str,
+        provider_id: &str,
+    ) -> Result<Option<Uuid>> {
+        RedisClient::get_user_by_provider(self, provider, provider_id).await
+    }
+
+    async fn set_user_provider_index(
+        &self,
+        provider: &str,
+        provider_id: &str,
+        user_id: Uuid,
+    ) -> Result<()> {
+        RedisClient::set_user_provider_index(self, provider, provider_id, user_id).await
+    }
+
+    async fn get_user_by_wallet(&self, chain: &str, address: &str) -> Result<Option<Uuid>> {
+        RedisClient::get_user_by_wallet(self, chain, address).await
+    }
+
+    async fn set_user_wallet_index(&self, chain: &str, address: &str, user_id: Uuid) -> Result<()> {
+        RedisClient::set_user_wallet_index(self, chain, address, user_id).await
+    }
+
+    async fn set_user_password(&self, user_id: Uuid, password_hash: &str) -> Result<()> {
+        RedisClient::set_user_password(self, user_id, password_hash).await
+    }
+
+    async fn get_user_password(&self, user_id: Uuid) -> Result<Option<String>> {
+        RedisClient::get_user_password(self, user_id).await
+    }
+
+    async fn update_user_karma(&self, user_id: Uuid, delta: i64) -> Result<i64> {
+        RedisClient::update_user_karma(self, user_id, delta).await
+    }
+
+    async fn increment_user_comment_count_by(&self, user_id: Uuid, count: i64) -> Result<i64> {
+        RedisClient::increment_user_comment_count_by(self, user_id, count).await
+    }
+
+    async fn block_user_by_user(&self, blocker_id: Uuid, blocked_id: Uuid) -> Result<()> {
+        RedisClient::block_user_by_user(self, blocker_id, blocked_id).await
+    }
+
+    async fn unblock_user_by_user(&self, blocker_id: Uuid, blocked_id: Uuid) -> Result<()> {
+        RedisClient::unblock_user_by_user(self, blocker_id, blocked_id).await
+    }
+
+    async fn is_blocked_by_user(&self, blocker_id: Uuid, blocked_id: Uuid) -> Result<bool> {
+        RedisClient::is_blocked_by_user(self, blocker_id, blocked_id).await
+    }
+
+    async fn get_blocked_users(&self, user_id: Uuid) -> Result<Vec<Uuid>> {
+        RedisClient::get_blocked_users(self, user_id).await
+    }
+
+    async fn get_blocked_by(&self, user_id: Uuid) -> Result<Vec<Uuid>> {
+        RedisClient::get_blocked_by(self, user_id).await
+    }
+
+    async fn get_page_tree(&self, page_id: Uuid) -> Result<Option<PageTree>> {
+        RedisClient::get_page_tree(self, page_id).await
+    }
+
+    async fn get_page_tree_versioned(&self, page_id: Uuid) -> Result<(Option<PageTree>, u64)> {
+        RedisClient::get_page_tree_versioned(self, page_id).await
+    }
+
+    async fn compare_and_set_page_tree(
+        &self,
+        page_id: Uuid,
+        expected_version: u64,
+        tree: &PageTree,
+    ) -> Result<bool> {
+        RedisClient::compare_and_set_page_tree(self, page_id, expected_version, tree).await
+    }
+
+    async fn set_page_tree(&self, page_id: Uuid, tree: &PageTree) -> Result<()> {
+        RedisClient::set_page_tree(self, page_id, tree).await
+    }
+
+    async fn add_comment_revision(&self, comment_id: Uuid, revision: &CommentRevision) -> Result<()> {
+        RedisClient::add_comment_revision(self, comment_id, revision).await
+    }
+
+    async fn get_comment_revisions(&self, comment_id: Uuid) -> Result<Vec<CommentRevision>> {
+        RedisClient::get_comment_revisions(self, comment_id).await
+    }
+
+    async fn delete_comment_revisions(&self, comment_id: Uuid) -> Result<()> {
+        RedisClient::delete_comment_revisions(self, comment_id).await
+    }
+
+    async fn reassign_comment_revisions(&self, comment_id: Uuid, from: Uuid, to: Uuid) -> Result<()> {
+        RedisClient::reassign_comment_revisions(self, comment_id, from, to).await
+    }
+
+    async fn atomic_vote(
+        &self,
+        user_id: Uuid,
+        page_id: Uuid,
+        comment_id: Uuid,
+        path: &[Uuid],
+        direction: VoteDirection,
+    ) -> Result<VoteOutcome> {
+        RedisClient::atomic_vote(self, user_id, page_id, comment_id, path, direction).await
+    }
+
+    async fn get_page_votes(
+        &self,
+        user_id: Uuid,
+        page_id: Uuid,
+    ) -> Result<std::collections::HashMap<Uuid, VoteDirection>> {
+        RedisClient::get_page_votes(self, user_id, page_id).await
+    }
+
+    async fn get_all_page_votes(&self, user_id: Uuid) -> Result<Vec<(Uuid, Uuid, VoteDirection)>> {
+        RedisClient::get_all_page_votes(self, user_id).await
+    }
+
+    async fn delete_user_votes(&self, user_id: Uuid) -> Result<i64> {
+        RedisClient::delete_user_votes(self, user_id).await
+    }
+
+    async fn toggle_reaction(
+        &self,
+        user_id: Uuid,
+        page_id: Uuid,
+        comment_id: Uuid,
+        path: &[Uuid],
+        emoji: &str,
+    ) -> Result<ReactionOutcome> {
+        RedisClient::toggle_reaction(self, user_id, page_id, comment_id, path, emoji).await
+    }
+
+    async fn get_page_reactions(
+        &self,
+        user_id: Uuid,
+        page_id: Uuid,
+    ) -> Result<std::collections::HashMap<Uuid, Vec<String>>> {
+        RedisClient::get_page_reactions(self, user_id, page_id).await
+    }
+
+    async fn get_all_page_reactions(&self, user_id: Uuid) -> Result<Vec<(Uuid, Uuid, String)>> {
+        RedisClient::get_all_page_reactions(self, user_id).await
+    }
+
+    async fn delete_user_reactions(&self, user_id: Uuid) -> Result<()> {
+        RedisClient::delete_user_reactions(self, user_id).await
+    }
+
+    async fn get_user_role(&self, site_id: Uuid, user_id: Uuid) -> Result<Role> {
+        RedisClient::get_user_role(self, site_id, user_id).await
+    }
+
+    async fn add_admin(&self, site_id: Uuid, user_id: Uuid) -> Result<()> {
+        RedisClient::add_admin(self, site_id, user_id).await
+    }
+
+    async fn remove_admin(&self, site_id: Uuid, user_id: Uuid) -> Result<()> {
+        RedisClient::remove_admin(self, site_id, user_id).await
+    }
+
+    async fn get_admins(&self, site_id: Uuid) -> Result<Vec<Uuid>> {
+        RedisClient::get_admins(self, site_id).await
+    }
+
+    async fn add_moderator(&self, site_id: Uuid, user_id: Uuid) -> Result<()> {
+        RedisClient::add_moderator(self, site_id, user_id).await
+    }
+
+    async fn remove_moderator(&self, site_id: Uuid, user_id: Uuid) -> Result<()> {
+        RedisClient::remove_moderator(self, site_id, user_id).await
+    }
+
+    async fn get_moderators(&self, site_id: Uuid) -> Result<Vec<Uuid>> {
+        RedisClient::get_moderators(self, site_id).await
+    }
+
+    async fn block_user(&self, site_id: Uuid, user_id: Uuid) -> Result<()> {
+        RedisClient::block_user(self, site_id, user_id).await
+    }
+
+    async fn unblock_user(&self, site_id: Uuid, user_id: Uuid) -> Result<()> {
+        RedisClient::unblock_user(self, site_id, user_id).await
+    }
+
+    async fn get_site_blocked_users(&self, site_id: Uuid) -> Result<Vec<Uuid>> {
+        RedisClient::get_site_blocked_users(self, site_id).await
+    }
+
+    async fn shadowban_user(&self, site_id: Uuid, user_id: Uuid) -> Result<()> {
+        RedisClient::shadowban_user(self, site_id, user_id).await
+    }
+
+    async fn is_shadowbanned(&self, site_id: Uuid, user_id: Uuid) -> Result<bool> {
+        RedisClient::is_shadowbanned(self, site_id, user_id).await
+    }
+
+    async fn get_site_shadowbanned_users(&self, site_id: Uuid) -> Result<Vec<Uuid>> {
+        RedisClient::get_site_shadowbanned_users(self, site_id).await
+    }
+
+    async fn add_notification(&self, user_id: Uuid, notification: &Notification) -> Result<()> {
+        RedisClient::add_notification(self, user_id, notification).await
+    }
+
+    async fn get_notifications(
+        &self,
+        user_id: Uuid,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<Notification>> {
+        RedisClient::get_notifications(self, user_id, offset, limit).await
+    }
+
+    async fn get_unread_count(&self, user_id: Uuid) -> Result<i64> {
+        RedisClient::get_unread_count(self, user_id).await
+    }
+
+    async fn mark_notifications_read(&self, user_id: Uuid) -> Result<()> {
+        RedisClient::mark_notifications_read(self, user_id).await
+    }
+
+    async fn delete_notifications(&self, user_id: Uuid) -> Result<()> {
+        RedisClient::delete_notifications(self, user_id).await
+    }
+
+    async fn create_session(
+        &self,
+        session_id: Uuid,
+        user_id: Uuid,
+        user_agent: &str,
+        ip: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        RedisClient::create_session(self, session_id, user_id, user_agent, ip, expires_at).await
+    }
+
+    async fn get_session_user(&self, session_id: Uuid) -> Result<Option<Uuid>> {
+        RedisClient::get_session_user(self, session_id).await
+    }
+
+    async fn delete_session(&self, session_id: Uuid) -> Result<()> {
+        RedisClient::delete_session(self, session_id).await
+    }
+
+    async fn set_refresh_token(
+        &self,
+        session_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        RedisClient::set_refresh_token(self, session_id, token_hash, expires_at).await
+    }
+
+    async fn rotate_refresh_token(
+        &self,
+        session_id: Uuid,
+        token_hash: &str,
+        new_token_hash: &str,
+        expires_at: DateTime<Utc>,
+        reuse_grace: Duration,
+    ) -> Result<RefreshRotation> {
+        RedisClient::rotate_refresh_token(self, session_id, token_hash, new_token_hash, expires_at, reuse_grace)
+            .await
+    }
+
+    async fn get_user_sessions(&self, user_id: Uuid) -> Result<Vec<SessionInfo>> {
+        RedisClient::get_user_sessions(self, user_id).await
+    }
+
+    async fn check_rate_limit(
+        &self,
+        key: &str,
+        max_requests: u32,
+        window_secs: u64,
+    ) -> Result<RateLimitResult> {
+        RedisClient::check_rate_limit(self, key, max_requests, window_secs).await
+    }
+}
@@ -0,0 +1,1359 @@
+//! Embedded SQLite backend
+//!
+//! Everything goes through one connection behind a mutex, so each method runs as a
+//! single serialized unit and the multi-statement ones use a transaction to stay atomic.
+//! Users, trees and notifications are stored as the same JSON the Redis backend uses.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{Connection, OptionalExtension, params};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use super::{ReactionOutcome, Storage, VoteOutcome, session_expiry};
+use crate::redis::RateLimitResult;
+use crate::types::{
+    CommentRevision, Notification, PageTree, RefreshRotation, Role, SessionInfo, User,
+    VoteDirection,
+};
+use crate::{Error, Result};
+
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS users (
+    id TEXT PRIMARY KEY,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS user_passwords (
+    user_id TEXT PRIMARY KEY,
+    hash TEXT NOT NULL
+);
+-- email / username / phone / provider / wallet -> user_id
+CREATE TABLE IF NOT EXISTS user_lookup (
+    kind TEXT NOT NULL,
+    key TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    PRIMARY KEY (kind, key)
+);
+CREATE TABLE IF NOT EXISTS user_blocks (
+    blocker_id TEXT NOT NULL,
+    blocked_id TEXT NOT NULL,
+    PRIMARY KEY (blocker_id, blocked_id)
+);
+CREATE INDEX IF NOT EXISTS user_blocks_blocked ON user_blocks (blocked_id);
+CREATE TABLE IF NOT EXISTS page_trees (
+    page_id TEXT PRIMARY KEY,
+    tree TEXT NOT NULL,
+    version INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS comment_revisions (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    comment_id TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS comment_revisions_comment ON comment_revisions (comment_id);
+CREATE TABLE IF NOT EXISTS reactions (
+    user_id TEXT NOT NULL,
+    page_id TEXT NOT NULL,
+    comment_id TEXT NOT NULL,
+    emoji TEXT NOT NULL,
+    PRIMARY KEY (user_id, page_id, comment_id, emoji)
+);
+CREATE TABLE IF NOT EXISTS votes (
+    user_id TEXT NOT NULL,
+    page_id TEXT NOT NULL,
+    comment_id TEXT NOT NULL,
+    direction INTEGER NOT NULL,
+    PRIMARY KEY (user_id, page_id, comment_id)
+);
+-- admin / moderator / blocked / shadowbanned
+CREATE TABLE IF NOT EXISTS site_roles (
+    site_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    role TEXT NOT NULL,
+    PRIMARY KEY (site_id, role, user_id)
+);
+CREATE TABLE IF NOT EXISTS notifications (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS notifications_user ON notifications (user_id, created_at);
+CREATE TABLE IF NOT EXISTS unread_counts (
+    user_id TEXT PRIMARY KEY,
+    count INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    last_used TEXT NOT NULL,
+    user_agent TEXT NOT NULL,
+    ip TEXT NOT NULL,
+    expires_at INTEGER NOT NULL,
+    previous_hash TEXT,
+    rotated_at INTEGER
+);
+CREATE INDEX IF NOT EXISTS sessions_user ON sessions (user_id);
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+    token_hash TEXT PRIMARY KEY,
+    session_id TEXT NOT NULL,
+    expires_at INTEGER NOT NULL,
+    used INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS refresh_tokens_session ON refresh_tokens (session_id);
+CREATE TABLE IF NOT EXISTS rate_limit_hits (
+    key TEXT NOT NULL,
+    at_ms INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS rate_limit_hits_key ON rate_limit_hits (key, at_ms);
+"#;
+
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    /// Open (or create) the database at `path` and apply the schema
+    /// Use `:memory:` for a throwaway database
+    pub async fn open(path: &str) -> Result<Self> {
+        let path = path.to_string();
+        let conn = tokio::task::spawn_blocking(move || -> Result<Connection> {
+            let conn = if path == ":memory:" {
+                Connection::open_in_memory()?
+            } else {
+                Connection::open(Path::new(&path))?
+            };
+            conn.pragma_update(None, "journal_mode", "WAL")?;
+            conn.pragma_update(None, "busy_timeout", 5000)?;
+            conn.execute_batch(SCHEMA)?;
+            Ok(conn)
+        })
+        .await
+        .map_err(|e| Error::Internal(e.to_string()))??;
+
+        Ok(SqliteStore {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Run `f` against the connection on the blocking thread pool
+    async fn call<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn
+                .lock()
+                .map_err(|_| Error::Internal("SQLite connection lock poisoned".to_string()))?;
+            f(&mut conn)
+        })
+        .await
+        .map_err(|e| Error::Internal(e.to_string()))?
+    }
+
+    async fn get_lookup(&self, kind: &'static str, key: String) -> Result<Option<Uuid>> {
+        self.call(move |conn| {
+            let id: Option<String> = conn
+                .query_row(
+                    "SELECT user_id FROM user_lookup WHERE kind = ?1 AND key = ?2",
+                    params![kind, key],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(id.and_then(|s| s.parse().ok()))
+        })
+        .await
+    }
+
+    async fn set_lookup(&self, kind: &'static str, key: String, user_id: Uuid) -> Result<()> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO user_lookup (kind, key, user_id) VALUES (?1, ?2, ?3)",
+                params![kind, key, user_id.to_string()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Add a numeric delta to a field of the user's JSON, returns the new value
+    async fn add_to_user_field(
+        &self,
+        user_id: Uuid,
+        field: &'static str,
+        delta: i64,
+    ) -> Result<i64> {
+        self.call(move |conn| {
+            let path = format!("$.{}", field);
+            let value: Option<i64> = conn
+                .query_row(
+                    "UPDATE users SET data = json_set(data, ?1, coalesce(json_extract(data, ?1), 0) + ?2)
+                     WHERE id = ?3 RETURNING json_extract(data, ?1)",
+                    params![path, delta, user_id.to_string()],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(value.unwrap_or(0))
+        })
+        .await
+    }
+
+    async fn add_role(&self, site_id: Uuid, user_id: Uuid, role: &'static str) -> Result<()> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO site_roles (site_id, user_id, role) VALUES (?1, ?2, ?3)",
+                params![site_id.to_string(), user_id.to_string(), role],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn remove_role(&self, site_id: Uuid, user_id: Uuid, role: &'static str) -> Result<()> {
+        self.call(move |conn| {
+            conn.execute(
+                "DELETE FROM site_roles WHERE site_id = ?1 AND user_id = ?2 AND role = ?3",
+                params![site_id.to_string(), user_id.to_string(), role],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn has_role(&self, site_id: Uuid, user_id: Uuid, role: &'static str) -> Result<bool> {
+        self.call(move |conn| {
+            let found: Option<i64> = conn
+                .query_row(
+                    "SELECT 1 FROM site_roles WHERE site_id = ?1 AND user_id = ?2 AND role = ?3",
+                    params![site_id.to_string(), user_id.to_string(), role],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(found.is_some())
+        })
+        .await
+    }
+
+    async fn get_role_members(&self, site_id: Uuid, role: &'static str) -> Result<Vec<Uuid>> {
+        self.call(move |conn| {
+            let mut stmt =
+                conn.prepare("SELECT user_id FROM site_roles WHERE site_id = ?1 AND role = ?2")?;
+            let ids = stmt
+                .query_map(params![site_id.to_string(), role], |row| {
+                    row.get::<_, String>(0)
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(parse_ids(ids))
+        })
+        .await
+    }
+
+    async fn get_block_ids(&self, sql: &'static str, user_id: Uuid) -> Result<Vec<Uuid>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(sql)?;
+            let ids = stmt
+                .query_map(params![user_id.to_string()], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(parse_ids(ids))
+        })
+        .await
+    }
+}
+
+fn parse_ids(ids: Vec<String>) -> Vec<Uuid> {
+    ids.into_iter().filter_map(|s| s.parse().ok()).collect()
+}
+
+fn direction_to_i64(direction: VoteDirection) -> i64 {
+    match direction {
+        VoteDirection::Up => 1,
+        VoteDirection::Down => -1,
+    }
+}
+
+fn direction_from_i64(value: i64) -> Option<VoteDirection> {
+    match value {
+        1 => Some(VoteDirection::Up),
+        -1 => Some(VoteDirection::Down),
+        _ => None,
+    }
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+#[async_trait]
+impl Storage for SqliteStore {
+    // ========================================================================
+    // Users
+    // ========================================================================
+
+    async fn get_user(&self, user_id: Uuid) -> Result<Option<User>> {
+        self.call(move |conn| {
+            let data: Option<String> = conn
+                .query_row(
+                    "SELECT data FROM users WHERE id = ?1",
+                    params![user_id.to_string()],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(match data {
+                Some(data) => Some(serde_json::from_str(&data)?),
+                None => None,
+            })
+        })
+        .await
+    }
+
+    async fn set_user(&self, user: &User) -> Result<()> {
+        let id = user.id.to_string();
+        let data = serde_json::to_string(user)?;
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO users (id, data) VALUES (?1, ?2)",
+                params![id, data],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_user(&self, user: &User) -> Result<()> {
+        let id = user.id.to_string();
+        let email = user.email.as_ref().map(|e| e.to_lowercase());
+        let username = user.name.to_lowercase();
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            if let Some(email) = email {
+                tx.execute(
+                    "DELETE FROM user_lookup WHERE kind = 'email' AND key = ?1",
+                    params![email],
+                )?;
+            }
+            tx.execute(
+                "DELETE FROM user_lookup WHERE kind = 'username' AND key = ?1",
+                params![username],
+            )?;
+            tx.execute("DELETE FROM user_passwords WHERE user_id = ?1", params![id])?;
+            tx.execute("DELETE FROM users WHERE id = ?1", params![id])?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<Uuid>> {
+        self.get_lookup("email", email.to_lowercase()).await
+    }
+
+    async fn set_user_email_index(&self, email: &str, user_id: Uuid) -> Result<()> {
+        self.set_lookup("email", email.to_lowercase(), user_id)
+            .await
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> Result<Option<Uuid>> {
+        self.get_lookup("username", username.to_lowercase()).await
+    }
+
+    async fn set_user_username_index(&self, username: &str, user_id: Uuid) -> Result<()> {
+        self.set_lookup("username", username.to_lowercase(), user_id)
+            .await
+    }
+
+    async fn delete_user_username_index(&self, username: &str) -> Result<()> {
+        let key = username.to_lowercase();
+        self.call(move |conn| {
+            conn.execute(
+                "DELETE FROM user_lookup WHERE kind = 'username' AND key = ?1",
+                params![key],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_user_by_phone(&self, phone: &str) -> Result<Option<Uuid>> {
+        self.get_lookup("phone", phone.to_string()).await
+    }
+
+    async fn set_user_phone_index(&self, phone: &str, user_id: Uuid) -> Result<()> {
+        self.set_lookup("phone", phone.to_string(), user_id).await
+    }
+
+    async fn get_user_by_provider(
+        &self,
+        provider: &str,
+        provider_id: &str,
+    ) -> Result<Option<Uuid>> {
+        self.get_lookup("provider", format!("{}:{}", provider, provider_id))
+            .await
+    }
+
+    async fn set_user_provider_index(
+        &self,
+        provider: &str,
+        provider_id: &str,
+        user_id: Uuid,
+    ) -> Result<()> {
+        self.set_lookup("provider", format!("{}:{}", provider, provider_id), user_id)
+            .await
+    }
+
+    async fn get_user_by_wallet(&self, chain: &str, address: &str) -> Result<Option<Uuid>> {
+        self.get_lookup("wallet", format!("{}:{}", chain, address.to_lowercase()))
+            .await
+    }
+
+    async fn set_user_wallet_index(&self, chain: &str, address: &str, user_id: Uuid) -> Result<()> {
+        self.set_lookup(
+            "wallet",
+            format!("{}:{}", chain, address.to_lowercase()),
+            user_id,
+        )
+        .await
+    }
+
+    async fn set_user_password(&self, user_id: Uuid, password_hash: &str) -> Result<()> {
+        let hash = password_hash.to_string();
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO user_passwords (user_id, hash) VALUES (?1, ?2)",
+                params![user_id.to_string(), hash],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_user_password(&self, user_id: Uuid) -> Result<Option<String>> {
+        self.call(move |conn| {
+            Ok(conn
+                .query_row(
+                    "SELECT hash FROM user_passwords WHERE user_id = ?1",
+                    params![user_id.to_string()],
+                    |row| row.get(0),
+                )
+                .optional()?)
+        })
+        .await
+    }
+
+    async fn update_user_karma(&self, user_id: Uuid, delta: i64) -> Result<i64> {
+        self.add_to_user_field(user_id, "karma", delta).await
+    }
+
+    async fn increment_user_comment_count_by(&self, user_id: Uuid, count: i64) -> Result<i64> {
+        self.add_to_user_field(user_id, "total_comments", count)
+            .await
+    }
+
+    async fn block_user_by_user(&self, blocker_id: Uuid, blocked_id: Uuid) -> Result<()> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO user_blocks (blocker_id, blocked_id) VALUES (?1, ?2)",
+                params![blocker_id.to_string(), blocked_id.to_string()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn unblock_user_by_user(&self, blocker_id: Uuid, blocked_id: Uuid) -> Result<()> {
+        self.call(move |conn| {
+            conn.execute(
+                "DELETE FROM user_blocks WHERE blocker_id = ?1 AND blocked_id = ?2",
+                params![blocker_id.to_string(), blocked_id.to_string()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn is_blocked_by_user(&self, blocker_id: Uuid, blocked_id: Uuid) -> Result<bool> {
+        self.call(move |conn| {
+            let found: Option<i64> = conn
+                .query_row(
+                    "SELECT 1 FROM user_blocks WHERE blocker_id = ?1 AND blocked_id = ?2",
+                    params![blocker_id.to_string(), blocked_id.to_string()],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(found.is_some())
+        })
+        .await
+    }
+
+    async fn get_blocked_users(&self, user_id: Uuid) -> Result<Vec<Uuid>> {
+        self.get_block_ids(
+            "SELECT blocked_id FROM user_blocks WHERE blocker_id = ?1",
+            user_id,
+        )
+        .await
+    }
+
+    async fn get_blocked_by(&self, user_id: Uuid) -> Result<Vec<Uuid>> {
+        self.get_block_ids(
+            "SELECT blocker_id FROM user_blocks WHERE blocked_id = ?1",
+            user_id,
+        )
+        .await
+    }
+
+    // ========================================================================
+    // Page Trees
+    // ========================================================================
+
+    async fn get_page_tree(&self, page_id: Uuid) -> Result<Option<PageTree>> {
+        self.call(move |conn| {
+            let tree: Option<String> = conn
+                .query_row(
+                    "SELECT tree FROM page_trees WHERE page_id = ?1",
+                    params![page_id.to_string()],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            // Same as Redis: an unreadable tree reads as missing
+            Ok(tree.and_then(|t| serde_json::from_str(&t).ok()))
+        })
+        .await
+    }
+
+    async fn get_page_tree_versioned(&self, page_id: Uuid) -> Result<(Option<PageTree>, u64)> {
+        self.call(move |conn| {
+            let row: Option<(String, i64)> = conn
+                .query_row(
+                    "SELECT tree, version FROM page_trees WHERE page_id = ?1",
+                    params![page_id.to_string()],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?;
+            match row {
+                Some((tree, version)) => Ok((Some(serde_json::from_str(&tree)?), version as u64)),
+                None => Ok((None, 0)),
+            }
+        })
+        .await
+    }
+
+    async fn compare_and_set_page_tree(
+        &self,
+        page_id: Uuid,
+        expected_version: u64,
+        tree: &PageTree,
+    ) -> Result<bool> {
+        let json = serde_json::to_string(tree)?;
+        self.call(move |conn| {
+            let changed = if expected_version == 0 {
+                conn.execute(
+                    "INSERT OR IGNORE INTO page_trees (page_id, tree, version) VALUES (?1, ?2, 1)",
+                    params![page_id.to_string(), json],
+                )?
+            } else {
+                conn.execute(
+                    "UPDATE page_trees SET tree = ?2, version = version + 1 WHERE page_id = ?1 AND version = ?3",
+                    params![page_id.to_string(), json, expected_version as i64],
+                )?
+            };
+            Ok(changed == 1)
+        })
+        .await
+    }
+
+    async fn set_page_tree(&self, page_id: Uuid, tree: &PageTree) -> Result<()> {
+        let json = serde_json::to_string(tree)?;
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO page_trees (page_id, tree, version) VALUES (?1, ?2, 1)
+                 ON CONFLICT (page_id) DO UPDATE SET tree = excluded.tree, version = version + 1",
+                params![page_id.to_string(), json],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    // ========================================================================
+    // Comment Revisions
+    // ========================================================================
+
+    async fn add_comment_revision(&self, comment_id: Uuid, revision: &CommentRevision) -> Result<()> {
+        let json = serde_json::to_string(revision)?;
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO comment_revisions (comment_id, data) VALUES (?1, ?2)",
+                params![comment_id.to_string(), json],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_comment_revisions(&self, comment_id: Uuid) -> Result<Vec<CommentRevision>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT data FROM comment_revisions WHERE comment_id = ?1 ORDER BY seq",
+            )?;
+            let rows = stmt
+                .query_map(params![comment_id.to_string()], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(rows
+                .iter()
+                .filter_map(|s| serde_json::from_str(s).ok())
+                .collect())
+        })
+        .await
+    }
+
+    async fn delete_comment_revisions(&self, comment_id: Uuid) -> Result<()> {
+        self.call(move |conn| {
+            conn.execute(
+                "DELETE FROM comment_revisions WHERE comment_id = ?1",
+                params![comment_id.to_string()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn reassign_comment_revisions(&self, comment_id: Uuid, from: Uuid, to: Uuid) -> Result<()> {
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            let rows = {
+                let mut stmt =
+                    tx.prepare("SELECT seq, data FROM comment_revisions WHERE comment_id = ?1")?;
+                stmt.query_map(params![comment_id.to_string()], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?
+            };
+            for (seq, data) in rows {
+                let Ok(mut revision) = serde_json::from_str::<CommentRevision>(&data) else {
+                    continue;
+                };
+                if revision.editor_id != from {
+                    continue;
+                }
+                revision.editor_id = to;
+                tx.execute(
+                    "UPDATE comment_revisions SET data = ?1 WHERE seq = ?2",
+                    params![serde_json::to_string(&revision)?, seq],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    // ========================================================================
+    // Votes
+    // ========================================================================
+
+    async fn atomic_vote(
+        &self,
+        user_id: Uuid,
+        page_id: Uuid,
+        comment_id: Uuid,
+        path: &[Uuid],
+        direction: VoteDirection,
+    ) -> Result<VoteOutcome> {
+        let path = path.to_vec();
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+
+            let tree_json: Option<String> = tx
+                .query_row(
+                    "SELECT tree FROM page_trees WHERE page_id = ?1",
+                    params![page_id.to_string()],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let mut tree: PageTree = match tree_json {
+                Some(json) => serde_json::from_str(&json)?,
+                None => return Err(Error::NotFound("Page not found".to_string())),
+            };
+
+            let existing: Option<i64> = tx
+                .query_row(
+                    "SELECT direction FROM votes WHERE user_id = ?1 AND page_id = ?2 AND comment_id = ?3",
+                    params![user_id.to_string(), page_id.to_string(), comment_id.to_string()],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let existing = existing.and_then(direction_from_i64);
+
+            // Same transitions as atomic_vote.lua
+            let (final_vote, upvote_delta, downvote_delta) = match (existing, direction) {
+                (None, VoteDirection::Up) => (Some(VoteDirection::Up), 1, 0),
+                (None, VoteDirection::Down) => (Some(VoteDirection::Down), 0, 1),
+                (Some(VoteDirection::Up), VoteDirection::Up) => (None, -1, 0),
+                (Some(VoteDirection::Down), VoteDirection::Down) => (None, 0, -1),
+                (Some(VoteDirection::Up), VoteDirection::Down) => (Some(VoteDirection::Down), -1, 1),
+                (Some(VoteDirection::Down), VoteDirection::Up) => (Some(VoteDirection::Up), 1, -1),
+            };
+
+            let comment = tree
+                .find_by_path_mut(&path)
+                .ok_or_else(|| Error::NotFound("Comment not found in path".to_string()))?;
+            comment.upvotes = (comment.upvotes + upvote_delta).max(0);
+            comment.downvotes = (comment.downvotes + downvote_delta).max(0);
+            let (upvotes, downvotes) = (comment.upvotes, comment.downvotes);
+            tree.updated_at = Utc::now().timestamp();
+
+            tx.execute(
+                "UPDATE page_trees SET tree = ?2, version = version + 1 WHERE page_id = ?1",
+                params![page_id.to_string(), serde_json::to_string(&tree)?],
+            )?;
+
+            match final_vote {
+                Some(dir) => tx.execute(
+                    "INSERT OR REPLACE INTO votes (user_id, page_id, comment_id, direction) VALUES (?1, ?2, ?3, ?4)",
+                    params![user_id.to_string(), page_id.to_string(), comment_id.to_string(), direction_to_i64(dir)],
+                )?,
+                None => tx.execute(
+                    "DELETE FROM votes WHERE user_id = ?1 AND page_id = ?2 AND comment_id = ?3",
+                    params![user_id.to_string(), page_id.to_string(), comment_id.to_string()],
+                )?,
+            };
+
+            tx.commit()?;
+            Ok((final_vote, upvotes, downvotes, upvote_delta, downvote_delta))
+        })
+        .await
+    }
+
+    async fn get_page_votes(
+        &self,
+        user_id: Uuid,
+        page_id: Uuid,
+    ) -> Result<HashMap<Uuid, VoteDirection>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT comment_id, direction FROM votes WHERE user_id = ?1 AND page_id = ?2",
+            )?;
+            let rows = stmt
+                .query_map(params![user_id.to_string(), page_id.to_string()], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(rows
+                .into_iter()
+                .filter_map(|(comment_id, dir)| {
+                    Some((comment_id.parse().ok()?, direction_from_i64(dir)?))
+                })
+                .collect())
+        })
+        .await
+    }
+
+    async fn get_all_page_votes(&self, user_id: Uuid) -> Result<Vec<(Uuid, Uuid, VoteDirection)>> {
+        self.call(move |conn| {
+            let mut stmt = conn
+                .prepare("SELECT page_id, comment_id, direction FROM votes WHERE user_id = ?1")?;
+            let rows = stmt
+                .query_map(params![user_id.to_string()], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, i64>(2)?,
+                    ))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(rows
+                .into_iter()
+                .filter_map(|(page_id, comment_id, dir)| {
+                    Some((
+                        page_id.parse().ok()?,
+                        comment_id.parse().ok()?,
+                        direction_from_i64(dir)?,
+                    ))
+                })
+                .collect())
+        })
+        .await
+    }
+
+    async fn delete_user_votes(&self, user_id: Uuid) -> Result<i64> {
+        self.call(move |conn| {
+            let deleted = conn.execute(
+                "DELETE FROM votes WHERE user_id = ?1",
+                params![user_id.to_string()],
+            )?;
+            Ok(deleted as i64)
+        })
+        .await
+    }
+
+    // ========================================================================
+    // Reactions
+    // ========================================================================
+
+    async fn toggle_reaction(
+        &self,
+        user_id: Uuid,
+        page_id: Uuid,
+        comment_id: Uuid,
+        path: &[Uuid],
+        emoji: &str,
+    ) -> Result<ReactionOutcome> {
+        let path = path.to_vec();
+        let emoji = emoji.to_string();
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+
+            let tree_json: Option<String> = tx
+                .query_row(
+                    "SELECT tree FROM page_trees WHERE page_id = ?1",
+                    params![page_id.to_string()],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let mut tree: PageTree = match tree_json {
+                Some(json) => serde_json::from_str(&json)?,
+                None => return Err(Error::NotFound("Page not found".to_string())),
+            };
+
+            let key = params![user_id.to_string(), page_id.to_string(), comment_id.to_string(), emoji];
+            let existing = tx
+                .query_row(
+                    "SELECT 1 FROM reactions WHERE user_id = ?1 AND page_id = ?2 AND comment_id = ?3 AND emoji = ?4",
+                    key,
+                    |_| Ok(()),
+                )
+                .optional()?
+                .is_some();
+
+            // Same transitions as toggle_reaction.lua
+            let comment = tree
+                .find_by_path_mut(&path)
+                .ok_or_else(|| Error::NotFound("Comment not found in path".to_string()))?;
+            let count = comment.reactions.get(&emoji).copied().unwrap_or(0);
+            let count = if existing { (count - 1).max(0) } else { count + 1 };
+            if count > 0 {
+                comment.reactions.insert(emoji.clone(), count);
+            } else {
+                comment.reactions.remove(&emoji);
+            }
+            let reactions = comment.reactions.clone();
+            tree.updated_at = Utc::now().timestamp();
+
+            tx.execute(
+                "UPDATE page_trees SET tree = ?2, version = version + 1 WHERE page_id = ?1",
+                params![page_id.to_string(), serde_json::to_string(&tree)?],
+            )?;
+
+            if existing {
+                tx.execute(
+                    "DELETE FROM reactions WHERE user_id = ?1 AND page_id = ?2 AND comment_id = ?3 AND emoji = ?4",
+                    key,
+                )?;
+            } else {
+                tx.execute(
+                    "INSERT INTO reactions (user_id, page_id, comment_id, emoji) VALUES (?1, ?2, ?3, ?4)",
+                    key,
+                )?;
+            }
+
+            tx.commit()?;
+            Ok((!existing, reactions))
+        })
+        .await
+    }
+
+    async fn get_page_reactions(
+        &self,
+        user_id: Uuid,
+        page_id: Uuid,
+    ) -> Result<HashMap<Uuid, Vec<String>>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT comment_id, emoji FROM reactions WHERE user_id = ?1 AND page_id = ?2
+                 ORDER BY emoji",
+            )?;
+            let rows = stmt
+                .query_map(params![user_id.to_string(), page_id.to_string()], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            let mut result: HashMap<Uuid, Vec<String>> = HashMap::new();
+            for (comment_id, emoji) in rows {
+                if let Ok(comment_id) = comment_id.parse() {
+                    result.entry(comment_id).or_default().push(emoji);
+                }
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    async fn get_all_page_reactions(&self, user_id: Uuid) -> Result<Vec<(Uuid, Uuid, String)>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT page_id, comment_id, emoji FROM reactions WHERE user_id = ?1 ORDER BY emoji",
+            )?;
+            let rows = stmt
+                .query_map(params![user_id.to_string()], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                    ))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(rows
+                .into_iter()
+                .filter_map(|(page_id, comment_id, emoji)| {
+                    Some((page_id.parse().ok()?, comment_id.parse().ok()?, emoji))
+                })
+                .collect())
+        })
+        .await
+    }
+
+    async fn delete_user_reactions(&self, user_id: Uuid) -> Result<()> {
+        self.call(move |conn| {
+            conn.execute(
+                "DELETE FROM reactions WHERE user_id = ?1",
+                params![user_id.to_string()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    // ========================================================================
+    // Roles
+    // ========================================================================
+
+    async fn get_user_role(&self, site_id: Uuid, user_id: Uuid) -> Result<Role> {
+        if self.has_role(site_id, user_id, "blocked").await? {
+            return Ok(Role::Blocked);
+        }
+        if self.has_role(site_id, user_id, "admin").await? {
+            return Ok(Role::Admin);
+        }
+        if self.has_role(site_id, user_id, "moderator").await? {
+            return Ok(Role::Moderator);
+        }
+        Ok(Role::User)
+    }
+
+    async fn add_admin(&self, site_id: Uuid, user_id: Uuid) -> Result<()> {
+        self.add_role(site_id, user_id, "admin").await
+    }
+
+    async fn remove_admin(&self, site_id: Uuid, user_id: Uuid) -> Result<()> {
+        self.remove_role(site_id, user_id, "admin").await
+    }
+
+    async fn get_admins(&self, site_id: Uuid) -> Result<Vec<Uuid>> {
+        self.get_role_members(site_id, "admin").await
+    }
+
+    async fn add_moderator(&self, site_id: Uuid, user_id: Uuid) -> Result<()> {
+        self.add_role(site_id, user_id, "moderator").await
+    }
+
+    async fn remove_moderator(&self, site_id: Uuid, user_id: Uuid) -> Result<()> {
+        self.remove_role(site_id, user_id, "moderator").await
+    }
+
+    async fn get_moderators(&self, site_id: Uuid) -> Result<Vec<Uuid>> {
+        self.get_role_members(site_id, "moderator").await
+    }
+
+    async fn block_user(&self, site_id: Uuid, user_id: Uuid) -> Result<()> {
+        self.add_role(site_id, user_id, "blocked").await
+    }
+
+    async fn unblock_user(&self, site_id: Uuid, user_id: Uuid) -> Result<()> {
+        self.remove_role(site_id, user_id, "blocked").await
+    }
+
+    async fn get_site_blocked_users(&self, site_id: Uuid) -> Result<Vec<Uuid>> {
+        self.get_role_members(site_id, "blocked").await
+    }
+
+    async fn shadowban_user(&self, site_id: Uuid, user_id: Uuid) -> Result<()> {
+        self.add_role(site_id, user_id, "shadowbanned").await
+    }
+
+    async fn is_shadowbanned(&self, site_id: Uuid, user_id: Uuid) -> Result<bool> {
+        self.has_role(site_id, user_id, "shadowbanned").await
+    }
+
+    async fn get_site_shadowbanned_users(&self, site_id: Uuid) -> Result<Vec<Uuid>> {
+        self.get_role_members(site_id, "shadowbanned").await
+    }
+
+    // ========================================================================
+    // Notifications
+    // ========================================================================
+
+    async fn add_notification(&self, user_id: Uuid, notification: &Notification) -> Result<()> {
+        let id = notification.id.to_string();
+        let created_at = notification.created_at.timestamp_millis();
+        let data = serde_json::to_string(notification)?;
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT OR REPLACE INTO notifications (id, user_id, created_at, data) VALUES (?1, ?2, ?3, ?4)",
+                params![id, user_id.to_string(), created_at, data],
+            )?;
+            tx.execute(
+                "INSERT INTO unread_counts (user_id, count) VALUES (?1, 1)
+                 ON CONFLICT (user_id) DO UPDATE SET count = count + 1",
+                params![user_id.to_string()],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_notifications(
+        &self,
+        user_id: Uuid,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<Notification>> {
+        // SQLite LIMIT is a signed 64-bit integer, -1 means no limit
+        let limit = i64::try_from(limit).unwrap_or(-1);
+        let offset = i64::try_from(offset).unwrap_or(i64::MAX);
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT data FROM notifications WHERE user_id = ?1
+                 ORDER BY created_at DESC LIMIT ?2 OFFSET ?3",
+            )?;
+            let rows = stmt
+                .query_map(params![user_id.to_string(), limit, offset], |row| {
+                    row.get::<_, String>(0)
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(rows
+                .iter()
+                .filter_map(|s| serde_json::from_str(s).ok())
+                .collect())
+        })
+        .await
+    }
+
+    async fn get_unread_count(&self, user_id: Uuid) -> Result<i64> {
+        self.call(move |conn| {
+            let count: Option<i64> = conn
+                .query_row(
+                    "SELECT count FROM unread_counts WHERE user_id = ?1",
+                    params![user_id.to_string()],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(count.unwrap_or(0))
+        })
+        .await
+    }
+
+    async fn mark_notifications_read(&self, user_id: Uuid) -> Result<()> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO unread_counts (user_id, count) VALUES (?1, 0)",
+                params![user_id.to_string()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_notifications(&self, user_id: Uuid) -> Result<()> {
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "DELETE FROM notifications WHERE user_id = ?1",
+                params![user_id.to_string()],
+            )?;
+            tx.execute(
+                "DELETE FROM unread_counts WHERE user_id = ?1",
+                params![user_id.to_string()],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    // ========================================================================
+    // Sessions
+    // ========================================================================
+
+    async fn create_session(
+        &self,
+        session_id: Uuid,
+        user_id: Uuid,
+        user_agent: &str,
+        ip: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let now = Utc::now();
+        let user_agent = user_agent.to_string();
+        let ip = ip.to_string();
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            // Nothing expires rows on its own, so clear out the user's lapsed sessions here
+            tx.execute(
+                "DELETE FROM refresh_tokens WHERE session_id IN
+                 (SELECT id FROM sessions WHERE user_id = ?1 AND expires_at <= ?2)",
+                params![user_id.to_string(), now.timestamp()],
+            )?;
+            tx.execute(
+                "DELETE FROM sessions WHERE user_id = ?1 AND expires_at <= ?2",
+                params![user_id.to_string(), now.timestamp()],
+            )?;
+            tx.execute(
+                "INSERT OR REPLACE INTO sessions (id, user_id, created_at, last_used, user_agent, ip, expires_at)
+                 VALUES (?1, ?2, ?3, ?3, ?4, ?5, ?6)",
+                params![
+                    session_id.to_string(),
+                    user_id.to_string(),
+                    now.to_rfc3339(),
+                    user_agent,
+                    ip,
+                    session_expiry(expires_at).timestamp()
+                ],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_session_user(&self, session_id: Uuid) -> Result<Option<Uuid>> {
+        self.call(move |conn| {
+            let id: Option<String> = conn
+                .query_row(
+                    "SELECT user_id FROM sessions WHERE id = ?1 AND expires_at > ?2",
+                    params![session_id.to_string(), Utc::now().timestamp()],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(id.and_then(|s| s.parse().ok()))
+        })
+        .await
+    }
+
+    async fn delete_session(&self, session_id: Uuid) -> Result<()> {
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "DELETE FROM sessions WHERE id = ?1",
+                params![session_id.to_string()],
+            )?;
+            tx.execute(
+                "DELETE FROM refresh_tokens WHERE session_id = ?1",
+                params![session_id.to_string()],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn set_refresh_token(
+        &self,
+        session_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let token_hash = token_hash.to_string();
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "DELETE FROM refresh_tokens WHERE session_id = ?1 AND used = 0",
+                params![session_id.to_string()],
+            )?;
+            tx.execute(
+                "INSERT OR REPLACE INTO refresh_tokens (token_hash, session_id, expires_at, used)
+                 VALUES (?1, ?2, ?3, 0)",
+                params![token_hash, session_id.to_string(), expires_at.timestamp()],
+            )?;
+            tx.execute(
+                "UPDATE sessions SET expires_at = ?2 WHERE id = ?1",
+                params![session_id.to_string(), session_expiry(expires_at).timestamp()],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn rotate_refresh_token(
+        &self,
+        session_id: Uuid,
+        token_hash: &str,
+        new_token_hash: &str,
+        expires_at: DateTime<Utc>,
+        reuse_grace: Duration,
+    ) -> Result<RefreshRotation> {
+        let token_hash = token_hash.to_string();
+        let new_token_hash = new_token_hash.to_string();
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            let now = Utc::now();
+            let session: Option<(String, Option<String>, Option<i64>)> = tx
+                .query_row(
+                    "SELECT user_id, previous_hash, rotated_at FROM sessions WHERE id = ?1 AND expires_at > ?2",
+                    params![session_id.to_string(), now.timestamp()],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .optional()?;
+            let Some((user_id, previous_hash, rotated_at)) = session else {
+                return Ok(RefreshRotation::Invalid);
+            };
+            let Ok(user_id) = user_id.parse::<Uuid>() else {
+                return Ok(RefreshRotation::Invalid);
+            };
+            let token: Option<(i64, bool)> = tx
+                .query_row(
+                    "SELECT expires_at, used FROM refresh_tokens WHERE token_hash = ?1 AND session_id = ?2",
+                    params![token_hash, session_id.to_string()],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?;
+            let outcome = match token {
+                None => RefreshRotation::Invalid,
+                // The token was exchanged moments ago, most likely by a concurrent refresh from the same client
+                Some((_, true))
+                    if previous_hash.as_deref() == Some(token_hash.as_str())
+                        && now.timestamp() - rotated_at.unwrap_or(0) < reuse_grace.num_seconds() =>
+                {
+                    RefreshRotation::Superseded { user_id }
+                }
+                // A rotated-out token coming back means two parties hold the session's tokens
+                Some((_, true)) => RefreshRotation::Reused { user_id },
+                Some((token_expires_at, false)) if token_expires_at <= now.timestamp() => {
+                    RefreshRotation::Expired
+                }
+                Some(_) => {
+                    tx.execute(
+                        "UPDATE refresh_tokens SET used = 1 WHERE token_hash = ?1",
+                        params![token_hash],
+                    )?;
+                    tx.execute(
+                        "INSERT OR REPLACE INTO refresh_tokens (token_hash, session_id, expires_at, used)
+                         VALUES (?1, ?2, ?3, 0)",
+                        params![new_token_hash, session_id.to_string(), expires_at.timestamp()],
+                    )?;
+                    tx.execute(
+                        "UPDATE sessions SET last_used = ?1, expires_at = ?2, previous_hash = ?3, rotated_at = ?4
+                         WHERE id = ?5",
+                        params![
+                            now.to_rfc3339(),
+                            session_expiry(expires_at).timestamp(),
+                            token_hash,
+                            now.timestamp(),
+                            session_id.to_string()
+                        ],
+                    )?;
+                    RefreshRotation::Rotated { user_id }
+                }
+            };
+            tx.commit()?;
+            Ok(outcome)
+        })
+        .await
+    }
+
+    async fn get_user_sessions(&self, user_id: Uuid) -> Result<Vec<SessionInfo>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, created_at, last_used, user_agent, ip FROM sessions
+                 WHERE user_id = ?1 AND expires_at > ?2",
+            )?;
+            let rows = stmt
+                .query_map(params![user_id.to_string(), Utc::now().timestamp()], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, String>(4)?,
+                    ))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            let mut sessions: Vec<SessionInfo> = rows
+                .into_iter()
+                .filter_map(|(id, created_at, last_used, user_agent, ip)| {
+                    Some(SessionInfo {
+                        id: id.parse().ok()?,
+                        created_at: parse_time(&created_at)?,
+                        last_used: parse_time(&last_used)?,
+                        user_agent,
+                        ip,
+                    })
+                })
+                .collect();
+            sessions.sort_by_key(|s| s.created_at);
+            Ok(sessions)
+        })
+        .await
+    }
+
+    // ========================================================================
+    // Rate Limiting
+    // ========================================================================
+
+    async fn check_rate_limit(
+        &self,
+        key: &str,
+        max_requests: u32,
+        window_secs: u64,
+    ) -> Result<RateLimitResult> {
+        let key = key.to_string();
+        self.call(move |conn| {
+            let now_ms = Utc::now().timestamp_millis();
+            let window_ms = (window_secs * 1000) as i64;
+            let reset_at = (now_ms + window_ms) / 1000;
+
+            let tx = conn.transaction()?;
+            tx.execute(
+                "DELETE FROM rate_limit_hits WHERE key = ?1 AND at_ms <= ?2",
+                params![key, now_ms - window_ms],
+            )?;
+            let count: i64 = tx.query_row(
+                "SELECT COUNT(*) FROM rate_limit_hits WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )?;
+
+            let result = if count < max_requests as i64 {
+                tx.execute(
+                    "INSERT INTO rate_limit_hits (key, at_ms) VALUES (?1, ?2)",
+                    params![key, now_ms],
+                )?;
+                RateLimitResult {
+                    allowed: true,
+                    remaining: (max_requests as i64 - count - 1).max(0) as u32,
+                    reset_at,
+                    limit: max_requests,
+                }
+            } else {
+                RateLimitResult {
+                    allowed: false,
+                    remaining: 0,
+                    reset_at,
+                    limit: max_requests,
+                }
+            };
+            tx.commit()?;
+            Ok(result)
+        })
+        .await
+    }
+}
@@ -0,0 +1,49 @@
+//! Cloudflare Turnstile token verification
+
+/// Outcome of a siteverify call
+#[derive(Debug, Clone)]
+pub struct TurnstileVerification {
+    /// Whether the token was accepted
+    pub success: bool,
+    /// Cloudflare error codes if it wasn't
+    pub error_codes: Vec<String>,
+}
+
+/// Verify a token with Cloudflare's siteverify API
+pub async fn verify_with_cloudflare(
+    secret_key: &str,
+    token: &str,
+    remote_ip: Option<&str>,
+) -> Result<TurnstileVerification, String> {
+    let client = reqwest::Client::new();
+
+    let mut form = vec![
+        ("secret", secret_key),
+        ("response", token),
+    ];
+
+    if let Some(ip) = remote_ip {
+        form.push(("remoteip", ip));
+    }
+
+    let response = client
+        .post("https://challenges.cloudflare.com/turnstile/v0/siteverify")
+        .form(&form)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to contact Cloudflare: {}", e))?;
+
+    let body: serde_json::Value = response.json().await
+        .map_err(|e| format!("Failed to parse Cloudflare response: {}", e))?;
+
+    let success = body["success"].as_bool().unwrap_or(false);
+    let error_codes = body["error-codes"]
+        .as_array()
+        .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+        .unwrap_or_default();
+
+    Ok(TurnstileVerification {
+        success,
+        error_codes,
+    })
+}
@@ -1,8 +1,11 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use utoipa::ToSchema;
 use uuid::Uuid;
 
+use crate::action_log::ActionType;
+
 // ============================================================================
 // Helper functions
 // ============================================================================
@@ -62,6 +65,22 @@ pub struct User {
     /// Total number of comments posted by this user
     #[serde(default)]
     pub total_comments: i64,
+    /// How reply and mention notifications are emailed
+    #[serde(default)]
+    pub email_notifications: EmailNotificationFrequency,
+}
+
+/// How often a user is emailed about new notifications
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailNotificationFrequency {
+    /// No emails (default)
+    #[default]
+    Off,
+    /// Shortly after each notification, with bursts combined into one email
+    Immediate,
+    /// At most one digest email a day
+    Daily,
 }
 
 fn default_username_set() -> bool {
@@ -216,6 +235,9 @@ pub struct TreeComment {
     /// downvotes count
     #[serde(rename = "d")]
     pub downvotes: i64,
+    /// reaction counts by emoji
+    #[serde(rename = "z", default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub reactions: BTreeMap<String, i64>,
     /// created_at (unix timestamp)
     #[serde(rename = "x")]
     pub created_at: i64,
@@ -235,6 +257,10 @@ pub struct TreeComment {
     /// Not used in GET /comments (uses nested replies instead)
     #[serde(rename = "q", default, skip_serializing_if = "Option::is_none")]
     pub parent_id: Option<Uuid>,
+    /// omitted replies - only set in truncated responses, number of replies (including nested)
+    /// left out of `r`. Fetch them via GET /comments/subtree. Never stored.
+    #[serde(rename = "o", default, skip_serializing_if = "Option::is_none")]
+    pub more_replies: Option<i64>,
 }
 
 impl TreeComment {
@@ -463,6 +489,17 @@ pub struct FlatComment {
     pub depth: u32,
 }
 
+/// A previous version of an edited comment
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CommentRevision {
+    /// Text (markdown) before the edit
+    pub text: String,
+    /// When the edit was made (unix seconds)
+    pub edited_at: i64,
+    /// Who made the edit (the author or a moderator)
+    pub editor_id: Uuid,
+}
+
 // ============================================================================
 // Vote Types
 // ============================================================================
@@ -478,7 +515,7 @@ pub enum VoteDirection {
 // Site Types
 // ============================================================================
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct SiteConfig {
     pub id: Uuid,
     pub name: String,
@@ -486,9 +523,12 @@ pub struct SiteConfig {
     pub project_id_public: String,
     pub project_id_secret: String,
     pub settings: SiteSettings,
+    /// Billing plan and usage limits (SaaS)
+    #[serde(default)]
+    pub plan: SitePlan,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
 pub struct SiteSettings {
     pub moderation_mode: ModerationMode,
     pub auth: AuthSettings,
@@ -504,10 +544,172 @@ pub struct SiteSettings {
     /// When true, new comments are disabled site-wide
     #[serde(default)]
     pub posting_disabled: bool,
+    /// Outbound webhook endpoints notified about site activity
+    #[serde(default)]
+    pub webhooks: Vec<WebhookEndpoint>,
+    /// Comment editing rules
+    #[serde(default)]
+    pub editing: EditSettings,
+    /// Emoji reactions allowed on comments
+    #[serde(default)]
+    pub reactions: ReactionSettings,
+    /// Outgoing email settings
+    #[serde(default)]
+    pub email: EmailSettings,
+}
+
+/// Per-site outgoing email settings
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct EmailSettings {
+    /// Name emails are sent from (defaults to the site name)
+    pub sender_name: Option<String>,
+    /// Email users who opted in about replies to their comments
+    pub reply_notifications: bool,
+    /// Email users who opted in about mentions
+    pub mention_notifications: bool,
+}
+
+impl Default for EmailSettings {
+    fn default() -> Self {
+        Self {
+            sender_name: None,
+            reply_notifications: true,
+            mention_notifications: true,
+        }
+    }
+}
+
+impl EmailSettings {
+    /// Whether notifications of this type are emailed from the site
+    pub fn emails(&self, notification_type: &NotificationType) -> bool {
+        match notification_type {
+            NotificationType::Reply => self.reply_notifications,
+            NotificationType::Mention => self.mention_notifications,
+            NotificationType::Upvote | NotificationType::ModAction => false,
+        }
+    }
+}
+
+impl SiteConfig {
+    /// Name this site's emails are sent from
+    pub fn email_sender_name(&self) -> &str {
+        self.settings
+            .email
+            .sender_name
+            .as_deref()
+            .filter(|name| !name.trim().is_empty())
+            .unwrap_or(&self.name)
+    }
+}
+
+/// Hosted plan tiers, see docs/dashboard.md for pricing
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanTier {
+    Free,
+    Pro,
+    Business,
+    Enterprise,
+}
+
+impl PlanTier {
+    /// Default limits of this tier
+    pub fn limits(self) -> PlanLimits {
+        const MB: u64 = 1024 * 1024;
+        match self {
+            PlanTier::Free => PlanLimits {
+                monthly_comments: Some(1_000),
+                monthly_moderation_checks: Some(1_000),
+                media_storage_bytes: Some(100 * MB),
+                max_sites: Some(1),
+            },
+            PlanTier::Pro => PlanLimits {
+                monthly_comments: Some(50_000),
+                monthly_moderation_checks: Some(50_000),
+                media_storage_bytes: Some(5 * 1024 * MB),
+                max_sites: Some(5),
+            },
+            PlanTier::Business => PlanLimits {
+                monthly_comments: Some(500_000),
+                monthly_moderation_checks: Some(500_000),
+                media_storage_bytes: Some(50 * 1024 * MB),
+                max_sites: Some(20),
+            },
+            PlanTier::Enterprise => PlanLimits::default(),
+        }
+    }
+}
+
+/// Usage caps for a site (None = unlimited)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq, ToSchema)]
+#[serde(default)]
+pub struct PlanLimits {
+    /// Comments posted per calendar month, over HTTP or WebSocket
+    pub monthly_comments: Option<u64>,
+    /// AI content moderation checks per calendar month
+    pub monthly_moderation_checks: Option<u64>,
+    /// Total size of uploaded images and avatars
+    pub media_storage_bytes: Option<u64>,
+    /// Sites the owning account can have
+    pub max_sites: Option<u32>,
+}
+
+/// The plan a site is on
+///
+/// Sites without a tier (self-hosted, or created before plans existed) have no limits.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
+#[serde(default)]
+pub struct SitePlan {
+    pub tier: Option<PlanTier>,
+    /// Replaces the tier's limits, for custom deals
+    pub limits: Option<PlanLimits>,
+    /// Dashboard account the site belongs to; its sites count towards `max_sites`
+    pub owner_id: Option<String>,
+}
+
+impl SitePlan {
+    /// Limits in effect: the custom limits if set, otherwise the tier's
+    pub fn effective_limits(&self) -> PlanLimits {
+        self.limits
+            .or_else(|| self.tier.map(PlanTier::limits))
+            .unwrap_or_default()
+    }
+}
+
+/// Per-site emoji reaction settings
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct ReactionSettings {
+    /// Emojis users can react with (empty = reactions disabled)
+    pub emojis: Vec<String>,
+}
+
+impl Default for ReactionSettings {
+    fn default() -> Self {
+        Self {
+            emojis: ["👍", "❤️", "😂", "😮", "😢", "🎉"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        }
+    }
+}
+
+/// Per-site comment editing settings
+#[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
+pub struct EditSettings {
+    /// Minutes after posting during which authors can edit (None = no limit).
+    /// Moderators can always edit.
+    #[serde(default)]
+    pub edit_window_minutes: Option<u32>,
+    /// Let everyone see a comment's revisions (otherwise moderators only)
+    #[serde(default)]
+    pub public_revisions: bool,
 }
 
 /// Per-site Cloudflare Turnstile bot protection settings
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct TurnstileSettings {
     /// Enable Turnstile protection for this site
     pub enabled: bool,
@@ -529,7 +731,7 @@ impl Default for TurnstileSettings {
 }
 
 /// When to require Turnstile verification
-#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, ToSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum TurnstileEnforcement {
     /// Require for all comment submissions
@@ -544,7 +746,7 @@ pub enum TurnstileEnforcement {
 }
 
 /// Per-site AI content moderation settings
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ContentModerationSettings {
     /// Enable AI content moderation for this site
     pub enabled: bool,
@@ -568,7 +770,7 @@ impl Default for ContentModerationSettings {
 }
 
 /// Categories that can be blocked by content moderation
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct BlockedCategories {
     pub hate_speech: bool,
     pub harassment: bool,
@@ -594,7 +796,7 @@ impl Default for BlockedCategories {
 }
 
 /// Action to take when content is flagged
-#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, ToSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ModerationAction {
     /// Reject the content outright
@@ -607,7 +809,7 @@ pub enum ModerationAction {
 }
 
 /// Per-site rate limit overrides (None = use global defaults)
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
 pub struct SiteRateLimitSettings {
     /// Override IP writes per minute
     pub ip_writes_per_minute: Option<u32>,
@@ -621,7 +823,7 @@ pub struct SiteRateLimitSettings {
     pub auth_attempts_per_hour: Option<u32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum ModerationMode {
     #[default]
@@ -630,7 +832,7 @@ pub enum ModerationMode {
     Post,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
 pub struct AuthSettings {
     pub google: bool,
     pub github: bool,
@@ -640,11 +842,21 @@ pub struct AuthSettings {
     pub solana: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct DisplaySettings {
     pub show_pageviews: bool,
     pub show_vote_counts: bool,
     pub default_sort: SortOrder,
+    /// Render markdown links (and bare URLs) as clickable links
+    #[serde(default = "default_true")]
+    pub allow_links: bool,
+    /// Render markdown images inline
+    #[serde(default = "default_true")]
+    pub allow_images: bool,
+}
+
+fn default_true() -> bool {
+    true
 }
 
 impl Default for DisplaySettings {
@@ -653,10 +865,67 @@ impl Default for DisplaySettings {
             show_pageviews: true,
             show_vote_counts: true,
             default_sort: SortOrder::New,
+            allow_links: true,
+            allow_images: true,
         }
     }
 }
 
+// ============================================================================
+// Webhook Types
+// ============================================================================
+
+/// A per-site outbound webhook endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WebhookEndpoint {
+    pub id: Uuid,
+    /// URL that receives signed POST requests
+    pub url: String,
+    /// Event types delivered to this endpoint (empty = all events)
+    #[serde(default)]
+    pub events: Vec<ActionType>,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+impl WebhookEndpoint {
+    /// Whether this endpoint should receive the given event
+    pub fn wants(&self, action: ActionType) -> bool {
+        self.enabled && (self.events.is_empty() || self.events.contains(&action))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookDeliveryStatus {
+    /// Waiting for its first attempt or a retry
+    Pending,
+    /// Endpoint responded with a 2xx status
+    Succeeded,
+    /// Gave up after the maximum number of attempts (can be replayed)
+    Failed,
+}
+
+/// A single webhook delivery and its attempt history
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub site_id: Uuid,
+    pub endpoint_id: Uuid,
+    pub url: String,
+    pub event: ActionType,
+    /// JSON body sent to the endpoint (signed as-is)
+    pub payload: String,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: u32,
+    /// HTTP status of the last attempt (None if the request itself failed)
+    pub last_status_code: Option<u16>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum SortOrder {
@@ -692,6 +961,15 @@ pub struct Notification {
     pub from_user_id: Uuid,
     pub read: bool,
     pub created_at: DateTime<Utc>,
+    /// Site the comment is on (absent on older notifications)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site_id: Option<Uuid>,
+    /// Page the comment is on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_url: Option<String>,
+    /// Start of the comment's text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub excerpt: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -756,6 +1034,12 @@ pub struct ProjectIdInfo {
     pub settings: SiteSettings,
     /// Primary domain for this site (used for origin validation)
     pub domain: String,
+    /// Usage caps from the site's plan
+    #[serde(default)]
+    pub limits: PlanLimits,
+    /// The scoped API token used (`key_type` Token), never cached
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_token: Option<ApiToken>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -763,6 +1047,186 @@ pub struct ProjectIdInfo {
 pub enum ProjectIdType {
     Public,
     Secret,
+    /// A scoped API token, see [`ApiToken`]
+    Token,
+}
+
+/// What a scoped API token may do
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiTokenScope {
+    /// Site comment feeds (`/admin/sites/{id}/comments`, `/owner/comments`)
+    ReadComments,
+    /// Moderation queue, reports, approving, rejecting and banning
+    Moderate,
+    /// Creating users and managing admins and moderators
+    ManageUsers,
+    /// Site analytics
+    ReadAnalytics,
+    /// Site settings and webhooks
+    ManageSite,
+}
+
+impl ApiTokenScope {
+    /// Name as serialized
+    pub fn name(self) -> &'static str {
+        match self {
+            ApiTokenScope::ReadComments => "read_comments",
+            ApiTokenScope::Moderate => "moderate",
+            ApiTokenScope::ManageUsers => "manage_users",
+            ApiTokenScope::ReadAnalytics => "read_analytics",
+            ApiTokenScope::ManageSite => "manage_site",
+        }
+    }
+}
+
+/// A named API token for server-to-server integrations, stored in
+/// `api_token:{sha256 of the token}`
+///
+/// Used in the `projectid` header in place of the secret key. It acts with admin rights,
+/// limited to its scopes, so it can't add or remove admins or manage tokens.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub site_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<ApiTokenScope>,
+    /// Last 4 characters of the token, to tell tokens apart
+    pub hint: String,
+    pub created_at: DateTime<Utc>,
+    /// None = never expires
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Client IPs or CIDR ranges the token may be used from (empty = any)
+    #[serde(default)]
+    pub allowed_ips: Vec<String>,
+    /// Filled in when listing tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+    pub fn has_scope(&self, scope: ApiTokenScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= Utc::now())
+    }
+}
+
+// ============================================================================
+// Analytics Types
+// ============================================================================
+
+/// Time bucket size of site analytics
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AnalyticsGranularity {
+    /// Kept for 7 days
+    Hour,
+    /// Kept for 90 days
+    #[default]
+    Day,
+}
+
+impl AnalyticsGranularity {
+    /// Length of one bucket
+    pub fn step(self) -> chrono::Duration {
+        match self {
+            AnalyticsGranularity::Hour => chrono::Duration::hours(1),
+            AnalyticsGranularity::Day => chrono::Duration::days(1),
+        }
+    }
+
+    /// How long buckets are kept
+    pub fn retention(self) -> chrono::Duration {
+        match self {
+            AnalyticsGranularity::Hour => chrono::Duration::days(7),
+            AnalyticsGranularity::Day => chrono::Duration::days(90),
+        }
+    }
+
+    /// Start of the bucket containing `time`
+    pub fn truncate(self, time: DateTime<Utc>) -> DateTime<Utc> {
+        use chrono::DurationRound;
+        time.duration_trunc(self.step()).unwrap_or(time)
+    }
+
+    /// Redis key suffix of the bucket starting at `start` (`hour:YYYYMMDDHH` or `day:YYYYMMDD`)
+    pub fn bucket_key(self, start: DateTime<Utc>) -> String {
+        match self {
+            AnalyticsGranularity::Hour => format!("hour:{}", start.format("%Y%m%d%H")),
+            AnalyticsGranularity::Day => format!("day:{}", start.format("%Y%m%d")),
+        }
+    }
+}
+
+/// Site activity during one analytics bucket
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct AnalyticsPoint {
+    /// Start of the bucket
+    pub start: DateTime<Utc>,
+    /// Comment page loads (`GET /comments`)
+    pub pageviews: u64,
+    pub comments: u64,
+    /// Distinct visitors (signed-in users, or IP and user agent), approximate
+    pub unique_visitors: u64,
+    /// Distinct signed-in users who commented, approximate
+    pub active_commenters: u64,
+    /// Most WebSocket connections open at once
+    pub peak_connections: u64,
+    /// Messages received from WebSocket clients
+    pub websocket_messages: u64,
+}
+
+/// A page ranked by engagement
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopPage {
+    pub page_id: Uuid,
+    /// None for pages whose URL was never recorded
+    pub page_url: Option<String>,
+    pub pageviews: u64,
+    pub comments: u64,
+}
+
+// ============================================================================
+// Session Types
+// ============================================================================
+
+/// A login session as stored in `session:{session_id}`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SessionInfo {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_used: DateTime<Utc>,
+    pub user_agent: String,
+    pub ip: String,
+}
+
+/// Result of exchanging a session's refresh token for a new one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshRotation {
+    /// The token was current and has been replaced
+    Rotated { user_id: Uuid },
+    /// The token was replaced within the grace period, most likely by a concurrent refresh
+    /// from the same client, which should use the new token instead
+    Superseded { user_id: Uuid },
+    /// The token was already used - it may have been stolen, so the session should be revoked
+    Reused { user_id: Uuid },
+    /// The token was current but has expired
+    Expired,
+    /// Unknown token, or the session is gone
+    Invalid,
+}
+
+/// Session and ban changes, published for WebSocket servers to apply to open connections
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthEvent {
+    /// A session was logged out
+    SessionRevoked { session_id: Uuid, user_id: Uuid },
+    /// A user was banned from a site
+    UserBlocked { site_id: Uuid, user_id: Uuid },
 }
 
 // ============================================================================
@@ -880,6 +1344,45 @@ pub struct CreateCommentResponse {
     pub comment: TreeComment,
 }
 
+/// Request to edit a comment
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UpdateCommentRequest {
+    /// Page URL where the comment exists
+    pub page_url: String,
+    /// New comment content (markdown)
+    pub content: String,
+    /// Path to the comment (array of UUIDs from root to target)
+    pub path: Vec<Uuid>,
+}
+
+/// Request to delete a comment
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DeleteRequest {
+    /// Page URL where the comment exists
+    pub page_url: String,
+    /// Path to the comment (array of UUIDs from root to target)
+    pub path: Vec<Uuid>,
+}
+
+/// Request to vote on a comment
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct VoteRequest {
+    /// Page URL where the comment exists
+    pub page_url: String,
+    /// Vote direction (up or down)
+    pub direction: VoteDirection,
+    /// Path to the comment (array of UUIDs from root to target)
+    pub path: Vec<Uuid>,
+}
+
+/// Vote counts after a vote
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct VoteResponse {
+    pub upvotes: i64,
+    pub downvotes: i64,
+    pub user_vote: Option<VoteDirection>,
+}
+
 /// Response for GET /comments - uses compact tree format
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct GetCommentsResponse {
@@ -895,4 +1398,7 @@ pub struct GetCommentsResponse {
     /// List of pinned comment IDs with their pinned timestamps (sorted newest first)
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub pinned: Vec<(Uuid, i64)>,
+    /// Cursor for the next page of top-level comments (paginated mode only, absent on the last page)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
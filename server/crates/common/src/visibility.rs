@@ -0,0 +1,209 @@
+//! Per-connection filtering of page events.
+//!
+//! Applies the same rules as the HTTP API's page tree filter, so a WebSocket or SSE
+//! client never sees a comment the REST API would hide from it:
+//! - comments from users the viewer blocked are hidden (except the viewer's own)
+//! - pending comments are only shown to their author, plus moderators so they can
+//!   act on them as they arrive
+//! - rejected comments are hidden
+//!
+//! Typing and presence from blocked users are hidden as well, and shadowbanned
+//! users' activity is only shown to themselves and moderators.
+
+use std::collections::HashSet;
+
+use uuid::Uuid;
+
+use crate::{
+    types::{CommentStatus, Role, UserPublic},
+    Storage,
+};
+
+use crate::messages::{EventSubject, ServerMessage};
+
+/// The user behind a connection, as far as event visibility is concerned
+#[derive(Debug, Clone)]
+pub struct Viewer {
+    user_id: Option<Uuid>,
+    role: Role,
+    /// Users the viewer blocked
+    blocked_users: HashSet<Uuid>,
+    /// Users shadowbanned on the site
+    shadowbanned_users: HashSet<Uuid>,
+}
+
+impl Viewer {
+    /// Load the viewer's role, block list and the site's shadowbans
+    ///
+    /// Authenticated connections reload it on their periodic session check (every minute).
+    pub async fn load(store: &dyn Storage, site_id: Uuid, user_id: Option<Uuid>) -> Self {
+        let (role, blocked_users) = match user_id {
+            Some(uid) => {
+                let (role, blocked) = tokio::join!(
+                    store.get_user_role(site_id, uid),
+                    store.get_blocked_users(uid),
+                );
+                (
+                    role.unwrap_or(Role::User),
+                    blocked.unwrap_or_default().into_iter().collect(),
+                )
+            }
+            None => (Role::User, HashSet::new()),
+        };
+
+        let shadowbanned_users = store
+            .get_site_shadowbanned_users(site_id)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+
+        Self::new(user_id, role, blocked_users, shadowbanned_users)
+    }
+
+    pub fn new(
+        user_id: Option<Uuid>,
+        role: Role,
+        blocked_users: HashSet<Uuid>,
+        shadowbanned_users: HashSet<Uuid>,
+    ) -> Self {
+        Self {
+            user_id,
+            role,
+            blocked_users,
+            shadowbanned_users,
+        }
+    }
+
+    /// Whether this viewer should receive a page event
+    pub fn can_see(&self, message: &ServerMessage) -> bool {
+        match message.subject() {
+            None => true,
+            Some(EventSubject::Comment { author_id, status }) => {
+                self.can_see_comment(*author_id, status)
+            }
+            Some(EventSubject::Activity { user_id }) => self.can_see_user(*user_id),
+        }
+    }
+
+    /// Whether this viewer should see a user in presence lists and typing indicators
+    pub fn can_see_user(&self, user_id: Uuid) -> bool {
+        if self.is_self(user_id) {
+            return true;
+        }
+        if self.blocked_users.contains(&user_id) {
+            return false;
+        }
+        !self.shadowbanned_users.contains(&user_id) || self.is_moderator()
+    }
+
+    /// Drop users this viewer shouldn't see from a presence list
+    pub fn filter_users(&self, users: Vec<UserPublic>) -> Vec<UserPublic> {
+        users.into_iter().filter(|u| self.can_see_user(u.id)).collect()
+    }
+
+    fn can_see_comment(&self, author_id: Uuid, status: &CommentStatus) -> bool {
+        if self.blocked_users.contains(&author_id) && !self.is_self(author_id) {
+            return false;
+        }
+
+        match status {
+            CommentStatus::Approved | CommentStatus::Deleted => true,
+            CommentStatus::Pending => self.is_self(author_id) || self.is_moderator(),
+            CommentStatus::Rejected => false,
+        }
+    }
+
+    fn is_self(&self, user_id: Uuid) -> bool {
+        self.user_id == Some(user_id)
+    }
+
+    /// Whether the viewer moderates the site
+    pub fn is_moderator(&self) -> bool {
+        self.role >= Role::Moderator
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TreeComment;
+
+    fn comment(author_id: Uuid, status: Option<CommentStatus>) -> TreeComment {
+        TreeComment {
+            id: Uuid::now_v7(),
+            author_id,
+            name: "Author".to_string(),
+            avatar: None,
+            karma: 0,
+            text: "Hello".to_string(),
+            html: "<p>Hello</p>".to_string(),
+            upvotes: 0,
+            downvotes: 0,
+            reactions: Default::default(),
+            created_at: 0,
+            modified_at: 0,
+            edited: false,
+            replies: Vec::new(),
+            status,
+            parent_id: None,
+            more_replies: None,
+        }
+    }
+
+    fn reader(user_id: Uuid) -> Viewer {
+        Viewer::new(Some(user_id), Role::User, HashSet::new(), HashSet::new())
+    }
+
+    #[test]
+    fn test_pending_comment_visibility() {
+        let page_id = Uuid::now_v7();
+        let author = Uuid::now_v7();
+        let msg = ServerMessage::new_comment(page_id, comment(author, Some(CommentStatus::Pending)));
+
+        assert!(reader(author).can_see(&msg));
+        assert!(!reader(Uuid::now_v7()).can_see(&msg));
+        assert!(!Viewer::new(None, Role::User, HashSet::new(), HashSet::new()).can_see(&msg));
+
+        let moderator = Viewer::new(Some(Uuid::now_v7()), Role::Moderator, HashSet::new(), HashSet::new());
+        assert!(moderator.can_see(&msg));
+    }
+
+    #[test]
+    fn test_blocked_author_hidden() {
+        let page_id = Uuid::now_v7();
+        let author = Uuid::now_v7();
+        let viewer = Viewer::new(
+            Some(Uuid::now_v7()),
+            Role::Moderator,
+            HashSet::from([author]),
+            HashSet::new(),
+        );
+
+        assert!(!viewer.can_see(&ServerMessage::new_comment(page_id, comment(author, None))));
+        assert!(viewer.can_see(&ServerMessage::new_comment(page_id, comment(Uuid::now_v7(), None))));
+        assert!(!viewer.can_see(&ServerMessage::user_left(page_id, author)));
+    }
+
+    #[test]
+    fn test_shadowbanned_activity() {
+        let page_id = Uuid::now_v7();
+        let banned = Uuid::now_v7();
+        let shadowbanned = HashSet::from([banned]);
+        let msg = ServerMessage::user_left(page_id, banned);
+
+        let reader = Viewer::new(Some(Uuid::now_v7()), Role::User, HashSet::new(), shadowbanned.clone());
+        let moderator = Viewer::new(Some(Uuid::now_v7()), Role::Moderator, HashSet::new(), shadowbanned.clone());
+        let themselves = Viewer::new(Some(banned), Role::User, HashSet::new(), shadowbanned);
+
+        assert!(!reader.can_see(&msg));
+        assert!(moderator.can_see(&msg));
+        assert!(themselves.can_see(&msg));
+    }
+
+    #[test]
+    fn test_events_without_subject_are_visible() {
+        let viewer = Viewer::new(None, Role::User, HashSet::new(), HashSet::new());
+        assert!(viewer.can_see(&ServerMessage::pong()));
+    }
+}
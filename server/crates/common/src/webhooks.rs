@@ -0,0 +1,535 @@
+//! Outbound webhooks
+//!
+//! Action log entries are turned into signed JSON POSTs to the endpoints configured in a
+//! site's settings. Deliveries are stored in Redis and sent from a retry queue with
+//! exponential backoff, so they survive restarts and can be replayed from the admin API.
+//!
+//! Every request carries:
+//! - `X-ThreadKit-Event`: the event type (e.g. `comment_created`)
+//! - `X-ThreadKit-Delivery`: the delivery ID (stable across retries)
+//! - `X-ThreadKit-Signature`: `t={unix_seconds},v1={hex}` where `v1` is
+//!   HMAC-SHA256 of `"{t}.{body}"` keyed with the site's secret key
+//!
+//! Endpoints must be on public addresses: loopback, private and link-local targets are
+//! refused when they're saved and again after DNS resolution at delivery time, unless
+//! `allow_private_targets` is set.
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{Client, Url};
+use serde_json::json;
+use sha2::Sha256;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::action_log::ActionLog;
+use crate::config::WebhookConfig;
+use crate::redis::RedisClient;
+use crate::types::{SiteConfig, WebhookDelivery, WebhookDeliveryStatus};
+use crate::{Error, Result};
+
+pub const SIGNATURE_HEADER: &str = "X-ThreadKit-Signature";
+pub const EVENT_HEADER: &str = "X-ThreadKit-Event";
+pub const DELIVERY_HEADER: &str = "X-ThreadKit-Delivery";
+
+/// Deliveries claimed from the queue per poll
+const CLAIM_BATCH_SIZE: usize = 50;
+/// Upper bound for the retry delay
+const MAX_BACKOFF_SECONDS: u64 = 6 * 3600;
+/// Extra time on top of the request timeout before a claimed delivery is retried by another worker
+const LEASE_GRACE_SECONDS: u64 = 30;
+/// Truncate stored error messages / response bodies to this many characters
+const MAX_ERROR_LENGTH: usize = 500;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Queues and sends webhook deliveries
+pub struct WebhookDispatcher {
+    redis: Arc<RedisClient>,
+    client: Client,
+    config: WebhookConfig,
+}
+
+impl WebhookDispatcher {
+    pub fn new(redis: Arc<RedisClient>, config: WebhookConfig) -> anyhow::Result<Self> {
+        let mut builder = Client::builder()
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .redirect(reqwest::redirect::Policy::none());
+        if !config.allow_private_targets {
+            // Check the addresses actually connected to, so DNS can't point a saved host inward later
+            builder = builder.dns_resolver(Arc::new(PublicOnlyResolver));
+        }
+        let client = builder.build()?;
+
+        Ok(Self { redis, client, config })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Queue a delivery of `entry` to every enabled endpoint of the site that wants this event.
+    ///
+    /// Returns the number of deliveries queued.
+    pub async fn enqueue(&self, site: &SiteConfig, entry: &ActionLog) -> Result<usize> {
+        if !self.is_enabled() {
+            return Ok(0);
+        }
+
+        let now = Utc::now();
+        let mut queued = 0;
+
+        for endpoint in site.settings.webhooks.iter().filter(|e| e.wants(entry.action)) {
+            let id = Uuid::now_v7();
+            let delivery = WebhookDelivery {
+                id,
+                site_id: site.id,
+                endpoint_id: endpoint.id,
+                url: endpoint.url.clone(),
+                event: entry.action,
+                payload: build_payload(id, entry).to_string(),
+                status: WebhookDeliveryStatus::Pending,
+                attempts: 0,
+                last_status_code: None,
+                last_error: None,
+                created_at: now,
+                next_attempt_at: Some(now),
+                delivered_at: None,
+            };
+
+            self.redis
+                .enqueue_webhook_delivery(&delivery, now.timestamp_millis())
+                .await?;
+            queued += 1;
+        }
+
+        Ok(queued)
+    }
+
+    /// Queue deliveries for `entry` from a background task, so the caller isn't slowed down
+    pub fn enqueue_in_background(self: &Arc<Self>, entry: ActionLog) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let webhooks = Arc::clone(self);
+        tokio::spawn(async move {
+            let site = match webhooks.redis.get_site_config(entry.site_id).await {
+                Ok(Some(site)) => site,
+                Ok(None) => return,
+                Err(e) => {
+                    tracing::warn!("Failed to load site config for webhooks: {}", e);
+                    return;
+                }
+            };
+
+            if let Err(e) = webhooks.enqueue(&site, &entry).await {
+                tracing::warn!("Failed to queue webhook deliveries: {}", e);
+            }
+        });
+    }
+
+    /// Reset a finished delivery and queue it to be sent again right away
+    pub async fn replay(&self, delivery_id: Uuid) -> Result<WebhookDelivery> {
+        let mut delivery = self
+            .redis
+            .get_webhook_delivery(delivery_id)
+            .await?
+            .ok_or_else(|| Error::NotFound("Delivery not found".into()))?;
+
+        if delivery.status == WebhookDeliveryStatus::Pending {
+            return Err(Error::BadRequest("Delivery is already queued".into()));
+        }
+
+        let now = Utc::now();
+        delivery.status = WebhookDeliveryStatus::Pending;
+        delivery.attempts = 0;
+        delivery.next_attempt_at = Some(now);
+
+        self.redis.save_webhook_delivery(&delivery).await?;
+        self.redis
+            .schedule_webhook_delivery(delivery.id, now.timestamp_millis())
+            .await?;
+
+        Ok(delivery)
+    }
+
+    /// Claim due deliveries and attempt each of them once.
+    ///
+    /// Returns the number of deliveries attempted.
+    pub async fn process_due(&self) -> Result<usize> {
+        let now_ms = Utc::now().timestamp_millis();
+        let lease_ms = ((self.config.timeout_seconds + LEASE_GRACE_SECONDS) * 1000) as i64;
+
+        let ids = self
+            .redis
+            .claim_webhook_deliveries(now_ms, now_ms + lease_ms, CLAIM_BATCH_SIZE)
+            .await?;
+
+        let attempts = ids.into_iter().map(|id| self.process_one(id));
+        let results = futures_util::future::join_all(attempts).await;
+
+        let mut attempted = 0;
+        for result in results {
+            match result {
+                Ok(true) => attempted += 1,
+                Ok(false) => {}
+                Err(e) => tracing::warn!("Webhook delivery failed to process: {}", e),
+            }
+        }
+
+        Ok(attempted)
+    }
+
+    /// Spawn the background worker that drains the retry queue
+    pub fn start(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_millis(self.config.poll_interval_ms.max(1)));
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.process_due().await {
+                    tracing::warn!("Webhook worker error: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Attempt a single claimed delivery. Returns false if there was nothing to send.
+    async fn process_one(&self, delivery_id: Uuid) -> Result<bool> {
+        let delivery = match self.redis.get_webhook_delivery(delivery_id).await? {
+            Some(d) if d.status == WebhookDeliveryStatus::Pending => d,
+            // Expired or already finished - nothing left to do
+            _ => {
+                self.redis.remove_webhook_from_queue(delivery_id).await?;
+                return Ok(false);
+            }
+        };
+
+        self.attempt(delivery).await?;
+        Ok(true)
+    }
+
+    /// Send a delivery once and record the outcome (success, retry or give up)
+    async fn attempt(&self, mut delivery: WebhookDelivery) -> Result<()> {
+        delivery.attempts += 1;
+
+        // Look up the secret at send time so key rotation applies to pending retries
+        let (status_code, error) = match self.redis.get_site_config(delivery.site_id).await? {
+            Some(site) => self.send(&delivery, &site.project_id_secret).await,
+            None => (None, Some("Site not found".to_string())),
+        };
+
+        let now = Utc::now();
+        delivery.last_status_code = status_code;
+        delivery.last_error = error;
+
+        if delivery.last_error.is_none() {
+            delivery.status = WebhookDeliveryStatus::Succeeded;
+            delivery.delivered_at = Some(now);
+            delivery.next_attempt_at = None;
+        } else if delivery.attempts >= self.config.max_attempts {
+            delivery.status = WebhookDeliveryStatus::Failed;
+            delivery.next_attempt_at = None;
+        } else {
+            let next = now + self.backoff(delivery.attempts);
+            delivery.next_attempt_at = Some(next);
+        }
+
+        self.redis.save_webhook_delivery(&delivery).await?;
+
+        match delivery.next_attempt_at {
+            Some(next) => {
+                self.redis
+                    .schedule_webhook_delivery(delivery.id, next.timestamp_millis())
+                    .await?
+            }
+            None => self.redis.remove_webhook_from_queue(delivery.id).await?,
+        }
+
+        Ok(())
+    }
+
+    /// POST the payload. Returns the response status and an error if the attempt failed.
+    async fn send(&self, delivery: &WebhookDelivery, secret: &str) -> (Option<u16>, Option<String>) {
+        // IP literals skip the resolver, so check them here
+        if !self.config.allow_private_targets {
+            let private_ip = Url::parse(&delivery.url)
+                .ok()
+                .and_then(|url| url_ip(&url))
+                .is_some_and(|ip| !is_public_ip(ip));
+            if private_ip {
+                return (None, Some("Webhook URL points to a private address".to_string()));
+            }
+        }
+
+        let timestamp = Utc::now().timestamp();
+
+        let response = self
+            .client
+            .post(&delivery.url)
+            .header("Content-Type", "application/json")
+            .header("User-Agent", "ThreadKit-Webhooks/1.0")
+            .header(EVENT_HEADER, delivery.event.event_name())
+            .header(DELIVERY_HEADER, delivery.id.to_string())
+            .header(SIGNATURE_HEADER, signature_header(secret, timestamp, &delivery.payload))
+            .body(delivery.payload.clone())
+            .send()
+            .await;
+
+        match response {
+            Ok(response) if response.status().is_success() => {
+                (Some(response.status().as_u16()), None)
+            }
+            Ok(response) => {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                let error = format!("HTTP {}: {}", status, body);
+                (Some(status.as_u16()), Some(truncate(&error)))
+            }
+            Err(e) => (None, Some(truncate(&e.to_string()))),
+        }
+    }
+
+    /// Delay before the next attempt: base * 2^(attempts - 1), capped
+    fn backoff(&self, attempts: u32) -> chrono::Duration {
+        let factor = 1u64 << attempts.saturating_sub(1).min(20);
+        let seconds = self
+            .config
+            .base_backoff_seconds
+            .saturating_mul(factor)
+            .min(MAX_BACKOFF_SECONDS);
+        chrono::Duration::seconds(seconds as i64)
+    }
+}
+
+/// Resolves endpoint hosts and refuses any that point at a non-public address
+struct PublicOnlyResolver;
+
+impl Resolve for PublicOnlyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            if addrs.iter().any(|addr| !is_public_ip(addr.ip())) {
+                return Err(format!("{} resolves to a private address", name.as_str()).into());
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Check a webhook URL before it's saved: http(s), with a host that isn't (and doesn't
+/// resolve to) a loopback, private or link-local address.
+///
+/// Hosts that don't resolve yet are accepted; delivery checks them again.
+pub async fn validate_endpoint_url(url: &str, allow_private_targets: bool) -> std::result::Result<(), String> {
+    let parsed = Url::parse(url).map_err(|_| format!("Invalid webhook URL: {}", url))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("Invalid webhook URL: {}", url));
+    }
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| format!("Invalid webhook URL: {}", url))?
+        .to_ascii_lowercase();
+
+    if allow_private_targets {
+        return Ok(());
+    }
+
+    let private = format!("Webhook URL must not point to a private address: {}", url);
+    if host == "localhost" || host.ends_with(".localhost") {
+        return Err(private);
+    }
+    match url_ip(&parsed) {
+        Some(ip) if !is_public_ip(ip) => return Err(private),
+        Some(_) => return Ok(()),
+        None => {}
+    }
+    if let Ok(addrs) = tokio::net::lookup_host((host.as_str(), 0)).await
+        && addrs.into_iter().any(|addr| !is_public_ip(addr.ip()))
+    {
+        return Err(private);
+    }
+    Ok(())
+}
+
+/// The URL's host if it's an IP literal
+fn url_ip(url: &Url) -> Option<IpAddr> {
+    let host = url.host_str()?;
+    host.strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host)
+        .parse()
+        .ok()
+}
+
+/// Whether `ip` is a publicly routable address webhooks may be sent to
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || a == 0
+                // Carrier-grade NAT (100.64.0.0/10)
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local (fc00::/7) and link-local (fe80::/10)
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// Build the JSON body for an action.
+///
+/// Client IP, user agent and emails are left out - endpoints get IDs and content only.
+pub fn build_payload(delivery_id: Uuid, entry: &ActionLog) -> serde_json::Value {
+    let metadata = entry.metadata.clone().map(|mut meta| {
+        if let Some(map) = meta.as_object_mut() {
+            map.retain(|key, _| !key.ends_with("email"));
+        }
+        meta
+    });
+
+    json!({
+        "id": delivery_id,
+        "event": entry.action,
+        "site_id": entry.site_id,
+        "created_at": entry.timestamp,
+        "data": {
+            "user_id": entry.user_id,
+            "page_id": entry.page_id,
+            "page_url": entry.page_url,
+            "comment_id": entry.comment_id,
+            "content_preview": entry.content_preview,
+            "metadata": metadata,
+        }
+    })
+}
+
+/// Hex-encoded HMAC-SHA256 of `"{timestamp}.{body}"`
+pub fn sign_payload(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Value of the `X-ThreadKit-Signature` header
+pub fn signature_header(secret: &str, timestamp: i64, body: &str) -> String {
+    format!("t={},v1={}", timestamp, sign_payload(secret, timestamp, body))
+}
+
+fn truncate(s: &str) -> String {
+    s.chars().take(MAX_ERROR_LENGTH).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action_log::{ActionLogBuilder, ActionType};
+    use crate::types::WebhookEndpoint;
+
+    #[test]
+    fn test_signature_is_stable_and_keyed() {
+        let sig = sign_payload("tk_sec_test", 1_700_000_000, r#"{"a":1}"#);
+        assert_eq!(sig.len(), 64);
+        assert_eq!(sig, sign_payload("tk_sec_test", 1_700_000_000, r#"{"a":1}"#));
+        assert_ne!(sig, sign_payload("tk_sec_other", 1_700_000_000, r#"{"a":1}"#));
+        assert_ne!(sig, sign_payload("tk_sec_test", 1_700_000_001, r#"{"a":1}"#));
+
+        let header = signature_header("tk_sec_test", 1_700_000_000, r#"{"a":1}"#);
+        assert_eq!(header, format!("t=1700000000,v1={}", sig));
+    }
+
+    #[test]
+    fn test_private_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "0.0.0.0",
+            "100.64.0.1", "::1", "::", "fd00::1", "fe80::1", "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{} should be private", ip);
+        }
+        for ip in ["93.184.216.34", "1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{} should be public", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_endpoint_url() {
+        assert!(validate_endpoint_url("https://93.184.216.34/hook", false).await.is_ok());
+        for url in [
+            "ftp://example.com/hook",
+            "not a url",
+            "http://127.0.0.1:8080/hook",
+            "http://[::1]/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://10.0.0.5/hook",
+            "http://localhost/hook",
+            "http://api.localhost/hook",
+        ] {
+            assert!(validate_endpoint_url(url, false).await.is_err(), "{} should be rejected", url);
+        }
+
+        // Allowed for local development, but the URL still has to be valid
+        assert!(validate_endpoint_url("http://127.0.0.1:8080/hook", true).await.is_ok());
+        assert!(validate_endpoint_url("ftp://127.0.0.1/hook", true).await.is_err());
+    }
+
+    #[test]
+    fn test_endpoint_event_filter() {
+        let mut endpoint = WebhookEndpoint {
+            id: Uuid::now_v7(),
+            url: "https://example.com/hook".into(),
+            events: vec![],
+            enabled: true,
+        };
+        assert!(endpoint.wants(ActionType::CommentCreated));
+
+        endpoint.events = vec![ActionType::UserBanned, ActionType::ReportCreated];
+        assert!(endpoint.wants(ActionType::UserBanned));
+        assert!(!endpoint.wants(ActionType::CommentCreated));
+
+        endpoint.enabled = false;
+        assert!(!endpoint.wants(ActionType::UserBanned));
+    }
+
+    #[test]
+    fn test_payload_omits_private_fields() {
+        let entry = ActionLogBuilder::new(ActionType::CommentCreated, Uuid::now_v7())
+            .user_id(Uuid::now_v7())
+            .user_email("user@example.com".into())
+            .ip("203.0.113.1".into())
+            .user_agent("Mozilla/5.0".into())
+            .content_preview("Hello".into())
+            .metadata(json!({ "banned_user_email": "target@example.com", "reason": "spam" }))
+            .build();
+
+        let payload = build_payload(Uuid::now_v7(), &entry);
+        assert_eq!(payload["event"], "comment_created");
+        assert_eq!(payload["data"]["content_preview"], "Hello");
+        assert_eq!(payload["data"]["metadata"]["reason"], "spam");
+
+        let body = payload.to_string();
+        assert!(!body.contains("user@example.com"));
+        assert!(!body.contains("target@example.com"));
+        assert!(!body.contains("203.0.113.1"));
+        assert!(!body.contains("Mozilla"));
+    }
+}
@@ -54,12 +54,14 @@ impl TestContext {
             html: "<p>Test comment</p>".to_string(),
             upvotes: 0,
             downvotes: 0,
+            reactions: Default::default(),
             created_at: now,
             modified_at: now,
             edited: false,
             replies: vec![],
             status: None,
             parent_id: None,
+            more_replies: None,
         };
 
         let tree = PageTree {
@@ -93,12 +95,14 @@ impl TestContext {
             html: "<p>Child comment</p>".to_string(),
             upvotes: 0,
             downvotes: 0,
+            reactions: Default::default(),
             created_at: now,
             modified_at: now,
             edited: false,
             replies: vec![],
             status: None,
             parent_id: Some(root_id),
+            more_replies: None,
         };
 
         let root = TreeComment {
@@ -111,12 +115,14 @@ impl TestContext {
             html: "<p>Root comment</p>".to_string(),
             upvotes: 0,
             downvotes: 0,
+            reactions: Default::default(),
             created_at: now,
             modified_at: now,
             edited: false,
             replies: vec![child],
             status: None,
             parent_id: None,
+            more_replies: None,
         };
 
         let tree = PageTree {
@@ -0,0 +1,288 @@
+use std::sync::Arc;
+use testcontainers::{runners::AsyncRunner, ContainerAsync, ImageExt};
+use testcontainers_modules::redis::Redis;
+use uuid::Uuid;
+
+use threadkit_common::{
+    redis::RedisClient,
+    types::{PageTree, TreeComment, VoteDirection},
+};
+
+struct TestContext {
+    redis: Arc<RedisClient>,
+    #[allow(dead_code)]
+    redis_container: ContainerAsync<Redis>,
+}
+
+impl TestContext {
+    async fn new() -> Self {
+        let redis_container = Redis::default()
+            .with_tag("7-alpine")
+            .start()
+            .await
+            .expect("Failed to start Redis");
+
+        let host = redis_container.get_host().await.expect("Failed to get host");
+        let port = redis_container
+            .get_host_port_ipv4(6379)
+            .await
+            .expect("Failed to get port");
+
+        let redis_url = format!("redis://{}:{}", host, port);
+        let redis = Arc::new(
+            RedisClient::new(&redis_url)
+                .await
+                .expect("Failed to create Redis client"),
+        );
+
+        TestContext {
+            redis,
+            redis_container,
+        }
+    }
+}
+
+fn make_comment(id: Uuid, parent_id: Option<Uuid>) -> TreeComment {
+    let now = chrono::Utc::now().timestamp_millis();
+    TreeComment {
+        id,
+        author_id: Uuid::now_v7(),
+        name: "Test User".to_string(),
+        avatar: None,
+        karma: 0,
+        text: "Test comment".to_string(),
+        html: "<p>Test comment</p>".to_string(),
+        upvotes: 0,
+        downvotes: 0,
+        reactions: Default::default(),
+        created_at: now,
+        modified_at: now,
+        edited: false,
+        replies: vec![],
+        status: None,
+        parent_id,
+        more_replies: None,
+    }
+}
+
+#[tokio::test]
+async fn test_update_creates_missing_tree() {
+    let ctx = TestContext::new().await;
+
+    let page_id = Uuid::now_v7();
+    let comment_id = Uuid::now_v7();
+
+    let (tree, ()) = ctx
+        .redis
+        .update_page_tree(page_id, |tree| {
+            tree.add_root(make_comment(comment_id, None));
+            Ok::<_, ()>(())
+        })
+        .await
+        .expect("Redis error")
+        .expect("Update aborted");
+
+    assert_eq!(tree.comments.len(), 1);
+
+    let stored = ctx
+        .redis
+        .get_page_tree(page_id)
+        .await
+        .expect("Failed to get tree")
+        .expect("Tree not found");
+    assert_eq!(stored.comments[0].id, comment_id);
+}
+
+#[tokio::test]
+async fn test_aborted_update_writes_nothing() {
+    let ctx = TestContext::new().await;
+
+    let page_id = Uuid::now_v7();
+    let root_id = Uuid::now_v7();
+
+    ctx.redis
+        .set_page_tree(
+            page_id,
+            &PageTree {
+                comments: vec![make_comment(root_id, None)],
+                updated_at: 0,
+            },
+        )
+        .await
+        .expect("Failed to set page tree");
+
+    let result = ctx
+        .redis
+        .update_page_tree(page_id, |tree| {
+            tree.comments.clear();
+            Err::<(), _>("nope")
+        })
+        .await
+        .expect("Redis error");
+    assert_eq!(result.err(), Some("nope"));
+
+    let stored = ctx
+        .redis
+        .get_page_tree(page_id)
+        .await
+        .expect("Failed to get tree")
+        .expect("Tree not found");
+    assert_eq!(stored.comments.len(), 1);
+}
+
+#[tokio::test]
+async fn test_concurrent_root_inserts() {
+    let ctx = TestContext::new().await;
+
+    let page_id = Uuid::now_v7();
+    let num_writers = 50;
+
+    // Every writer adds its own root comment at the same time
+    let mut handles = vec![];
+    for _ in 0..num_writers {
+        let redis = ctx.redis.clone();
+        handles.push(tokio::spawn(async move {
+            let comment_id = Uuid::now_v7();
+            redis
+                .update_page_tree(page_id, |tree| {
+                    tree.add_root(make_comment(comment_id, None));
+                    Ok::<_, ()>(())
+                })
+                .await
+                .expect("Redis error")
+                .expect("Update aborted");
+            comment_id
+        }));
+    }
+
+    let mut ids = vec![];
+    for handle in handles {
+        ids.push(handle.await.unwrap());
+    }
+
+    let tree = ctx
+        .redis
+        .get_page_tree(page_id)
+        .await
+        .expect("Failed to get tree")
+        .expect("Tree not found");
+
+    // No insert should have been lost
+    assert_eq!(tree.comments.len(), num_writers);
+    for id in ids {
+        assert!(tree.find_root(id).is_some(), "Comment {} was lost", id);
+    }
+}
+
+#[tokio::test]
+async fn test_concurrent_replies_to_same_parent() {
+    let ctx = TestContext::new().await;
+
+    let page_id = Uuid::now_v7();
+    let root_id = Uuid::now_v7();
+    let num_writers = 30;
+
+    ctx.redis
+        .set_page_tree(
+            page_id,
+            &PageTree {
+                comments: vec![make_comment(root_id, None)],
+                updated_at: 0,
+            },
+        )
+        .await
+        .expect("Failed to set page tree");
+
+    let mut handles = vec![];
+    for _ in 0..num_writers {
+        let redis = ctx.redis.clone();
+        handles.push(tokio::spawn(async move {
+            redis
+                .update_page_tree(page_id, |tree| {
+                    if tree.add_reply(&[root_id], make_comment(Uuid::now_v7(), Some(root_id))) {
+                        Ok(())
+                    } else {
+                        Err(())
+                    }
+                })
+                .await
+                .expect("Redis error")
+                .expect("Parent not found");
+        }));
+    }
+
+    for handle in handles {
+        handle.await.unwrap();
+    }
+
+    let tree = ctx
+        .redis
+        .get_page_tree(page_id)
+        .await
+        .expect("Failed to get tree")
+        .expect("Tree not found");
+
+    assert_eq!(tree.comments[0].replies.len(), num_writers);
+    assert_eq!(tree.total_count(), num_writers as i64 + 1);
+}
+
+#[tokio::test]
+async fn test_concurrent_inserts_and_votes() {
+    let ctx = TestContext::new().await;
+
+    let page_id = Uuid::now_v7();
+    let root_id = Uuid::now_v7();
+    let num_writers = 20;
+    let num_voters = 20;
+
+    ctx.redis
+        .set_page_tree(
+            page_id,
+            &PageTree {
+                comments: vec![make_comment(root_id, None)],
+                updated_at: 0,
+            },
+        )
+        .await
+        .expect("Failed to set page tree");
+
+    // Votes go through the Lua script and must not be overwritten by tree updates
+    let mut handles = vec![];
+    for i in 0..(num_writers + num_voters) {
+        let redis = ctx.redis.clone();
+        handles.push(tokio::spawn(async move {
+            if i % 2 == 0 {
+                redis
+                    .update_page_tree(page_id, |tree| {
+                        tree.add_root(make_comment(Uuid::now_v7(), None));
+                        Ok::<_, ()>(())
+                    })
+                    .await
+                    .expect("Redis error")
+                    .expect("Update aborted");
+            } else {
+                redis
+                    .atomic_vote(Uuid::now_v7(), page_id, root_id, &[root_id], VoteDirection::Up)
+                    .await
+                    .expect("Failed to vote");
+            }
+        }));
+    }
+
+    for handle in handles {
+        handle.await.unwrap();
+    }
+
+    let tree = ctx
+        .redis
+        .get_page_tree(page_id)
+        .await
+        .expect("Failed to get tree")
+        .expect("Tree not found");
+
+    assert_eq!(tree.comments.len(), num_writers + 1);
+    assert_eq!(
+        tree.find_root(root_id).unwrap().upvotes,
+        num_voters as i64
+    );
+}
@@ -0,0 +1,735 @@
+//! Behaviour shared by every `Storage` backend.
+//!
+//! Each check runs against SQLite (in memory) and Redis (testcontainers).
+
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use testcontainers::{ContainerAsync, ImageExt, runners::AsyncRunner};
+use testcontainers_modules::redis::Redis;
+use uuid::Uuid;
+
+use threadkit_common::{
+    Storage,
+    redis::RedisClient,
+    store::SqliteStore,
+    types::{
+        AuthProvider, CommentRevision, Notification, NotificationType, PageTree, RefreshRotation,
+        Role, SocialLinks, TreeComment, User, VoteDirection, DELETED_USER_ID,
+    },
+};
+
+async fn sqlite_store() -> SqliteStore {
+    SqliteStore::open(":memory:")
+        .await
+        .expect("Failed to open SQLite store")
+}
+
+async fn redis_store() -> (Arc<RedisClient>, ContainerAsync<Redis>) {
+    let container = Redis::default()
+        .with_tag("7-alpine")
+        .start()
+        .await
+        .expect("Failed to start Redis");
+
+    let host = container.get_host().await.expect("Failed to get host");
+    let port = container
+        .get_host_port_ipv4(6379)
+        .await
+        .expect("Failed to get port");
+
+    let redis = RedisClient::new(&format!("redis://{}:{}", host, port))
+        .await
+        .expect("Failed to create Redis client");
+
+    (Arc::new(redis), container)
+}
+
+/// Run a check against both backends
+macro_rules! backend_tests {
+    ($($check:ident),* $(,)?) => {
+        mod sqlite {
+            $(
+                #[tokio::test]
+                async fn $check() {
+                    let store = super::sqlite_store().await;
+                    super::$check(&store).await;
+                }
+            )*
+        }
+
+        mod redis {
+            $(
+                #[tokio::test]
+                async fn $check() {
+                    let (store, _container) = super::redis_store().await;
+                    super::$check(store.as_ref()).await;
+                }
+            )*
+        }
+    };
+}
+
+backend_tests!(
+    users_and_indexes,
+    karma_and_comment_counts,
+    user_blocks,
+    page_tree_compare_and_set,
+    page_tree_concurrent_updates,
+    comment_revisions,
+    votes,
+    reactions,
+    site_roles,
+    notifications,
+    sessions,
+    refresh_tokens,
+    refresh_token_grace_period,
+    rate_limits,
+);
+
+fn test_user(name: &str) -> User {
+    User {
+        id: Uuid::now_v7(),
+        name: name.to_string(),
+        email: Some(format!("{}@example.com", name)),
+        avatar_url: None,
+        provider: AuthProvider::Email,
+        provider_id: None,
+        email_verified: true,
+        karma: 0,
+        global_banned: false,
+        shadow_banned: false,
+        created_at: chrono::Utc::now(),
+        username_set: true,
+        social_links: SocialLinks::default(),
+        total_comments: 0,
+        email_notifications: Default::default(),
+    }
+}
+
+fn test_comment(id: Uuid) -> TreeComment {
+    let now = chrono::Utc::now().timestamp_millis();
+    TreeComment {
+        id,
+        author_id: Uuid::now_v7(),
+        name: "Test User".to_string(),
+        avatar: None,
+        karma: 0,
+        text: "Test comment".to_string(),
+        html: "<p>Test comment</p>".to_string(),
+        upvotes: 0,
+        downvotes: 0,
+        reactions: Default::default(),
+        created_at: now,
+        modified_at: now,
+        edited: false,
+        replies: vec![],
+        status: None,
+        parent_id: None,
+        more_replies: None,
+    }
+}
+
+async fn users_and_indexes(store: &dyn Storage) {
+    let user = test_user("alice");
+    store.set_user(&user).await.unwrap();
+    store
+        .set_user_email_index("alice@example.com", user.id)
+        .await
+        .unwrap();
+    store
+        .set_user_username_index("alice", user.id)
+        .await
+        .unwrap();
+    store
+        .set_user_provider_index("github", "123", user.id)
+        .await
+        .unwrap();
+    store
+        .set_user_wallet_index("ethereum", "0xabc", user.id)
+        .await
+        .unwrap();
+    store.set_user_password(user.id, "hash").await.unwrap();
+
+    let loaded = store.get_user(user.id).await.unwrap().expect("user exists");
+    assert_eq!(loaded.name, "alice");
+    assert_eq!(
+        store.get_user_by_email("alice@example.com").await.unwrap(),
+        Some(user.id)
+    );
+    assert_eq!(
+        store.get_user_by_username("alice").await.unwrap(),
+        Some(user.id)
+    );
+    assert_eq!(
+        store.get_user_by_provider("github", "123").await.unwrap(),
+        Some(user.id)
+    );
+    assert_eq!(
+        store.get_user_by_wallet("ethereum", "0xabc").await.unwrap(),
+        Some(user.id)
+    );
+    assert_eq!(
+        store.get_user_password(user.id).await.unwrap().as_deref(),
+        Some("hash")
+    );
+
+    assert!(!store.is_username_available("alice", None).await.unwrap());
+    assert!(
+        store
+            .is_username_available("alice", Some(user.id))
+            .await
+            .unwrap()
+    );
+
+    store.delete_user(&user).await.unwrap();
+    assert!(store.get_user(user.id).await.unwrap().is_none());
+    assert!(
+        store
+            .get_user_by_email("alice@example.com")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(store.get_user_password(user.id).await.unwrap().is_none());
+    assert!(store.is_username_available("alice", None).await.unwrap());
+}
+
+async fn karma_and_comment_counts(store: &dyn Storage) {
+    let user = test_user("bob");
+    store.set_user(&user).await.unwrap();
+
+    assert_eq!(store.update_user_karma(user.id, 5).await.unwrap(), 5);
+    assert_eq!(store.update_user_karma(user.id, -2).await.unwrap(), 3);
+    assert_eq!(
+        store.increment_user_comment_count(user.id).await.unwrap(),
+        1
+    );
+    assert_eq!(
+        store
+            .increment_user_comment_count_by(user.id, 4)
+            .await
+            .unwrap(),
+        5
+    );
+
+    let loaded = store.get_user(user.id).await.unwrap().unwrap();
+    assert_eq!(loaded.karma, 3);
+    assert_eq!(loaded.total_comments, 5);
+}
+
+async fn user_blocks(store: &dyn Storage) {
+    let (a, b) = (Uuid::now_v7(), Uuid::now_v7());
+
+    store.block_user_by_user(a, b).await.unwrap();
+    assert!(store.is_blocked_by_user(a, b).await.unwrap());
+    assert!(!store.is_blocked_by_user(b, a).await.unwrap());
+    assert_eq!(store.get_blocked_users(a).await.unwrap(), vec![b]);
+    assert_eq!(store.get_blocked_by(b).await.unwrap(), vec![a]);
+
+    store.unblock_user_by_user(a, b).await.unwrap();
+    assert!(!store.is_blocked_by_user(a, b).await.unwrap());
+    assert!(store.get_blocked_by(b).await.unwrap().is_empty());
+}
+
+async fn page_tree_compare_and_set(store: &dyn Storage) {
+    let page_id = Uuid::now_v7();
+
+    let (tree, version) = store.get_page_tree_versioned(page_id).await.unwrap();
+    assert!(tree.is_none());
+    assert_eq!(version, 0);
+
+    let mut tree = PageTree::default();
+    tree.comments.push(test_comment(Uuid::now_v7()));
+    assert!(
+        store
+            .compare_and_set_page_tree(page_id, 0, &tree)
+            .await
+            .unwrap()
+    );
+
+    // A second writer holding the old version loses
+    assert!(
+        !store
+            .compare_and_set_page_tree(page_id, 0, &tree)
+            .await
+            .unwrap()
+    );
+
+    let (loaded, version) = store.get_page_tree_versioned(page_id).await.unwrap();
+    assert_eq!(loaded.unwrap().comments.len(), 1);
+    assert!(
+        store
+            .compare_and_set_page_tree(page_id, version, &tree)
+            .await
+            .unwrap()
+    );
+}
+
+async fn page_tree_concurrent_updates(store: &dyn Storage) {
+    let page_id = Uuid::now_v7();
+    store
+        .set_page_tree(page_id, &PageTree::default())
+        .await
+        .unwrap();
+
+    let updates = (0..10).map(|_| {
+        store.update_page_tree(page_id, |tree| {
+            tree.comments.push(test_comment(Uuid::now_v7()));
+            Ok::<_, ()>(())
+        })
+    });
+    for result in futures_util::future::join_all(updates).await {
+        result.unwrap().unwrap();
+    }
+
+    let tree = store.get_page_tree(page_id).await.unwrap().unwrap();
+    assert_eq!(tree.comments.len(), 10);
+}
+
+async fn comment_revisions(store: &dyn Storage) {
+    let (comment_id, editor_id) = (Uuid::now_v7(), Uuid::now_v7());
+
+    for (i, text) in ["first", "second"].into_iter().enumerate() {
+        let revision = CommentRevision {
+            text: text.to_string(),
+            edited_at: 1_700_000_000 + i as i64,
+            editor_id,
+        };
+        store
+            .add_comment_revision(comment_id, &revision)
+            .await
+            .unwrap();
+    }
+
+    // Oldest first
+    let revisions = store.get_comment_revisions(comment_id).await.unwrap();
+    let texts: Vec<_> = revisions.iter().map(|r| r.text.as_str()).collect();
+    assert_eq!(texts, ["first", "second"]);
+    assert_eq!(revisions[0].editor_id, editor_id);
+
+    // Only the given editor's revisions are reassigned
+    let moderator_id = Uuid::now_v7();
+    let revision = CommentRevision {
+        text: "third".to_string(),
+        edited_at: 1_700_000_002,
+        editor_id: moderator_id,
+    };
+    store.add_comment_revision(comment_id, &revision).await.unwrap();
+    store
+        .reassign_comment_revisions(comment_id, editor_id, DELETED_USER_ID)
+        .await
+        .unwrap();
+    let revisions = store.get_comment_revisions(comment_id).await.unwrap();
+    let editors: Vec<_> = revisions.iter().map(|r| r.editor_id).collect();
+    assert_eq!(editors, [DELETED_USER_ID, DELETED_USER_ID, moderator_id]);
+    assert_eq!(revisions[2].text, "third");
+
+    store.delete_comment_revisions(comment_id).await.unwrap();
+    assert!(
+        store
+            .get_comment_revisions(comment_id)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+async fn votes(store: &dyn Storage) {
+    let (user_id, page_id, comment_id) = (Uuid::now_v7(), Uuid::now_v7(), Uuid::now_v7());
+    let mut tree = PageTree::default();
+    tree.comments.push(test_comment(comment_id));
+    store.set_page_tree(page_id, &tree).await.unwrap();
+
+    let (vote, up, down, _, _) = store
+        .atomic_vote(
+            user_id,
+            page_id,
+            comment_id,
+            &[comment_id],
+            VoteDirection::Up,
+        )
+        .await
+        .unwrap();
+    assert_eq!(vote, Some(VoteDirection::Up));
+    assert_eq!((up, down), (1, 0));
+
+    // Switching direction moves the vote
+    let (vote, up, down, up_delta, down_delta) = store
+        .atomic_vote(
+            user_id,
+            page_id,
+            comment_id,
+            &[comment_id],
+            VoteDirection::Down,
+        )
+        .await
+        .unwrap();
+    assert_eq!(vote, Some(VoteDirection::Down));
+    assert_eq!((up, down, up_delta, down_delta), (0, 1, -1, 1));
+
+    let page_votes = store.get_page_votes(user_id, page_id).await.unwrap();
+    assert_eq!(page_votes.get(&comment_id), Some(&VoteDirection::Down));
+    assert_eq!(store.get_all_page_votes(user_id).await.unwrap().len(), 1);
+
+    // Voting the same direction again removes the vote
+    let (vote, up, down, _, _) = store
+        .atomic_vote(
+            user_id,
+            page_id,
+            comment_id,
+            &[comment_id],
+            VoteDirection::Down,
+        )
+        .await
+        .unwrap();
+    assert_eq!(vote, None);
+    assert_eq!((up, down), (0, 0));
+
+    store
+        .atomic_vote(
+            user_id,
+            page_id,
+            comment_id,
+            &[comment_id],
+            VoteDirection::Up,
+        )
+        .await
+        .unwrap();
+    assert_eq!(store.delete_user_votes(user_id).await.unwrap(), 1);
+    assert!(
+        store
+            .get_page_votes(user_id, page_id)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    // Counts on the comment are kept
+    let tree = store.get_page_tree(page_id).await.unwrap().unwrap();
+    assert_eq!(tree.comments[0].upvotes, 1);
+}
+
+async fn reactions(store: &dyn Storage) {
+    let (user_id, other_id, page_id, comment_id) =
+        (Uuid::now_v7(), Uuid::now_v7(), Uuid::now_v7(), Uuid::now_v7());
+    let mut tree = PageTree::default();
+    tree.comments.push(test_comment(comment_id));
+    store.set_page_tree(page_id, &tree).await.unwrap();
+
+    let path = [comment_id];
+    let (added, counts) = store
+        .toggle_reaction(user_id, page_id, comment_id, &path, "👍")
+        .await
+        .unwrap();
+    assert!(added);
+    assert_eq!(counts.get("👍"), Some(&1));
+
+    store
+        .toggle_reaction(other_id, page_id, comment_id, &path, "👍")
+        .await
+        .unwrap();
+    let (_, counts) = store
+        .toggle_reaction(user_id, page_id, comment_id, &path, "🎉")
+        .await
+        .unwrap();
+    assert_eq!(counts.get("👍"), Some(&2));
+    assert_eq!(counts.get("🎉"), Some(&1));
+
+    let mine = store.get_page_reactions(user_id, page_id).await.unwrap();
+    assert_eq!(mine.get(&comment_id).map(Vec::len), Some(2));
+    assert_eq!(store.get_all_page_reactions(user_id).await.unwrap().len(), 2);
+
+    // Toggling again removes the reaction, and empty counts are dropped
+    let (added, counts) = store
+        .toggle_reaction(user_id, page_id, comment_id, &path, "🎉")
+        .await
+        .unwrap();
+    assert!(!added);
+    assert!(!counts.contains_key("🎉"));
+
+    let tree = store.get_page_tree(page_id).await.unwrap().unwrap();
+    assert_eq!(tree.comments[0].reactions.get("👍"), Some(&2));
+
+    assert_eq!(
+        store.get_all_page_reactions(user_id).await.unwrap(),
+        vec![(page_id, comment_id, "👍".to_string())]
+    );
+
+    store.delete_user_reactions(user_id).await.unwrap();
+    assert!(
+        store
+            .get_page_reactions(user_id, page_id)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(store.get_all_page_reactions(user_id).await.unwrap().is_empty());
+}
+
+async fn site_roles(store: &dyn Storage) {
+    let site_id = Uuid::now_v7();
+    let (admin, moderator, user) = (Uuid::now_v7(), Uuid::now_v7(), Uuid::now_v7());
+
+    store.add_admin(site_id, admin).await.unwrap();
+    store.add_moderator(site_id, moderator).await.unwrap();
+    assert_eq!(
+        store.get_user_role(site_id, admin).await.unwrap(),
+        Role::Admin
+    );
+    assert_eq!(
+        store.get_user_role(site_id, moderator).await.unwrap(),
+        Role::Moderator
+    );
+    assert_eq!(
+        store.get_user_role(site_id, user).await.unwrap(),
+        Role::User
+    );
+    assert_eq!(store.get_admins(site_id).await.unwrap(), vec![admin]);
+    assert_eq!(
+        store.get_moderators(site_id).await.unwrap(),
+        vec![moderator]
+    );
+
+    store.block_user(site_id, user).await.unwrap();
+    assert_eq!(
+        store.get_user_role(site_id, user).await.unwrap(),
+        Role::Blocked
+    );
+    assert_eq!(
+        store.get_site_blocked_users(site_id).await.unwrap(),
+        vec![user]
+    );
+    store.unblock_user(site_id, user).await.unwrap();
+    assert_eq!(
+        store.get_user_role(site_id, user).await.unwrap(),
+        Role::User
+    );
+
+    store.shadowban_user(site_id, user).await.unwrap();
+    assert!(store.is_shadowbanned(site_id, user).await.unwrap());
+    assert_eq!(
+        store.get_site_shadowbanned_users(site_id).await.unwrap(),
+        vec![user]
+    );
+
+    store.remove_admin(site_id, admin).await.unwrap();
+    store.remove_moderator(site_id, moderator).await.unwrap();
+    assert!(store.get_admins(site_id).await.unwrap().is_empty());
+    assert!(store.get_moderators(site_id).await.unwrap().is_empty());
+}
+
+async fn notifications(store: &dyn Storage) {
+    let user_id = Uuid::now_v7();
+    let base = chrono::Utc::now();
+
+    for i in 0..3 {
+        let notification = Notification {
+            id: Uuid::now_v7(),
+            notification_type: NotificationType::Reply,
+            comment_id: Uuid::now_v7(),
+            from_user_id: Uuid::now_v7(),
+            read: false,
+            site_id: None,
+            page_url: None,
+            excerpt: None,
+            created_at: base + chrono::Duration::seconds(i),
+        };
+        store
+            .add_notification(user_id, &notification)
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(store.get_unread_count(user_id).await.unwrap(), 3);
+
+    // Newest first
+    let page = store.get_notifications(user_id, 0, 2).await.unwrap();
+    assert_eq!(page.len(), 2);
+    assert!(page[0].created_at > page[1].created_at);
+    assert_eq!(
+        store
+            .get_notifications(user_id, 0, usize::MAX)
+            .await
+            .unwrap()
+            .len(),
+        3
+    );
+
+    store.mark_notifications_read(user_id).await.unwrap();
+    assert_eq!(store.get_unread_count(user_id).await.unwrap(), 0);
+
+    store.delete_notifications(user_id).await.unwrap();
+    assert!(
+        store
+            .get_notifications(user_id, 0, 10)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+async fn sessions(store: &dyn Storage) {
+    let user_id = Uuid::now_v7();
+    let (first, second) = (Uuid::now_v7(), Uuid::now_v7());
+    let expires_at = Utc::now() + Duration::days(1);
+
+    store
+        .create_session(first, user_id, "curl", "127.0.0.1", expires_at)
+        .await
+        .unwrap();
+    store
+        .create_session(second, user_id, "firefox", "127.0.0.2", expires_at)
+        .await
+        .unwrap();
+    assert_eq!(store.get_session_user(first).await.unwrap(), Some(user_id));
+
+    let sessions = store.get_user_sessions(user_id).await.unwrap();
+    assert_eq!(sessions.len(), 2);
+
+    store.delete_session(first).await.unwrap();
+    assert!(store.get_session_user(first).await.unwrap().is_none());
+    let sessions = store.get_user_sessions(user_id).await.unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].id, second);
+
+    // A session whose refresh token lapsed more than a day ago is gone
+    let lapsed = Uuid::now_v7();
+    store
+        .create_session(lapsed, user_id, "curl", "127.0.0.1", Utc::now() - Duration::days(2))
+        .await
+        .unwrap();
+    assert!(store.get_session_user(lapsed).await.unwrap().is_none());
+    let sessions = store.get_user_sessions(user_id).await.unwrap();
+    assert_eq!(sessions.len(), 1);
+}
+
+async fn refresh_tokens(store: &dyn Storage) {
+    let user_id = Uuid::now_v7();
+    let session_id = Uuid::now_v7();
+    let expires_at = Utc::now() + Duration::days(1);
+
+    store
+        .create_session(session_id, user_id, "curl", "127.0.0.1", expires_at)
+        .await
+        .unwrap();
+    store
+        .set_refresh_token(session_id, "first", expires_at)
+        .await
+        .unwrap();
+
+    let grace = Duration::seconds(10);
+    assert_eq!(
+        store
+            .rotate_refresh_token(session_id, "first", "second", expires_at, grace)
+            .await
+            .unwrap(),
+        RefreshRotation::Rotated { user_id }
+    );
+    // A concurrent refresh within the grace period isn't taken as reuse
+    assert_eq!(
+        store
+            .rotate_refresh_token(session_id, "first", "third", expires_at, grace)
+            .await
+            .unwrap(),
+        RefreshRotation::Superseded { user_id }
+    );
+    assert_eq!(
+        store
+            .rotate_refresh_token(session_id, "first", "third", expires_at, Duration::zero())
+            .await
+            .unwrap(),
+        RefreshRotation::Reused { user_id }
+    );
+    assert_eq!(
+        store
+            .rotate_refresh_token(session_id, "unknown", "third", expires_at, grace)
+            .await
+            .unwrap(),
+        RefreshRotation::Invalid
+    );
+
+    // The current token, but past its expiry
+    store
+        .set_refresh_token(session_id, "expired", Utc::now() - Duration::seconds(1))
+        .await
+        .unwrap();
+    assert_eq!(
+        store
+            .rotate_refresh_token(session_id, "expired", "third", expires_at, grace)
+            .await
+            .unwrap(),
+        RefreshRotation::Expired
+    );
+
+    store.delete_session(session_id).await.unwrap();
+    assert_eq!(
+        store
+            .rotate_refresh_token(session_id, "first", "third", expires_at, grace)
+            .await
+            .unwrap(),
+        RefreshRotation::Invalid
+    );
+}
+
+async fn refresh_token_grace_period(store: &dyn Storage) {
+    let user_id = Uuid::now_v7();
+    let session_id = Uuid::now_v7();
+    let expires_at = Utc::now() + Duration::days(1);
+    let grace = Duration::seconds(10);
+
+    store
+        .create_session(session_id, user_id, "curl", "127.0.0.1", expires_at)
+        .await
+        .unwrap();
+    store
+        .set_refresh_token(session_id, "first", expires_at)
+        .await
+        .unwrap();
+    for (token, next) in [("first", "second"), ("second", "third")] {
+        assert_eq!(
+            store
+                .rotate_refresh_token(session_id, token, next, expires_at, grace)
+                .await
+                .unwrap(),
+            RefreshRotation::Rotated { user_id }
+        );
+    }
+
+    // Only the token the current one replaced gets the grace period
+    assert_eq!(
+        store
+            .rotate_refresh_token(session_id, "second", "fourth", expires_at, grace)
+            .await
+            .unwrap(),
+        RefreshRotation::Superseded { user_id }
+    );
+    assert_eq!(
+        store
+            .rotate_refresh_token(session_id, "first", "fourth", expires_at, grace)
+            .await
+            .unwrap(),
+        RefreshRotation::Reused { user_id }
+    );
+    // Superseded doesn't consume the current token
+    assert_eq!(
+        store
+            .rotate_refresh_token(session_id, "third", "fourth", expires_at, grace)
+            .await
+            .unwrap(),
+        RefreshRotation::Rotated { user_id }
+    );
+}
+
+async fn rate_limits(store: &dyn Storage) {
+    let key = format!("test:{}", Uuid::now_v7());
+
+    for remaining in (0..3).rev() {
+        let result = store.check_rate_limit(&key, 3, 60).await.unwrap();
+        assert!(result.allowed);
+        assert_eq!(result.remaining, remaining);
+    }
+
+    let result = store.check_rate_limit(&key, 3, 60).await.unwrap();
+    assert!(!result.allowed);
+    assert_eq!(result.limit, 3);
+}
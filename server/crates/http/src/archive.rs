@@ -0,0 +1,661 @@
+//! Site export and restore.
+//!
+//! An archive is a JSON-lines file: a `header` record followed by one record per
+//! site config, role list, user, page and media item. Restoring loads it into the
+//! same site ID (e.g. an empty Redis) or merges it into another site, remapping
+//! page IDs from the recorded page URLs.
+//!
+//! Archives don't contain credentials (the site's secret key, password hashes, sessions)
+//! or derived data (votes, usage, pageviews, comment indexes - the indexes are rebuilt
+//! on restore). A restored site gets a new secret key.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::io::{BufRead, Write};
+use uuid::Uuid;
+
+use threadkit_common::{
+    auth::{generate_api_key, PUBLIC_KEY_PREFIX, SECRET_KEY_PREFIX},
+    redis::RedisClient,
+    types::{
+        AuthProvider, MediaInfo, PageTree, SiteConfig, TreeComment, User, ANONYMOUS_USER_ID,
+        DELETED_USER_ID,
+    },
+    Storage,
+};
+
+/// Current archive format version
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// One line of an archive
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ArchiveRecord {
+    Header(ArchiveHeader),
+    Site(SiteConfig),
+    Roles(ArchivedRoles),
+    User(User),
+    Page(ArchivedPage),
+    Media(MediaInfo),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveHeader {
+    pub version: u32,
+    pub site_id: Uuid,
+    pub exported_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ArchivedRoles {
+    pub admins: Vec<Uuid>,
+    pub moderators: Vec<Uuid>,
+    pub blocked: Vec<Uuid>,
+    pub shadowbanned: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchivedPage {
+    pub page_id: Uuid,
+    /// None for pages created before page URLs were recorded
+    pub page_url: Option<String>,
+    #[serde(default)]
+    pub locked: bool,
+    pub tree: PageTree,
+    #[serde(default)]
+    pub pinned: Vec<PageEntry>,
+    #[serde(default)]
+    pub modqueue: Vec<PageEntry>,
+    #[serde(default)]
+    pub reports: Vec<PageEntry>,
+}
+
+/// A comment in a per-page list (pins, moderation queue, reports)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageEntry {
+    pub comment_id: Uuid,
+    /// When the comment was pinned (unix seconds), queued or reported (unix ms)
+    pub at: i64,
+}
+
+// ============================================================================
+// Export
+// ============================================================================
+
+/// Counts for an export
+#[derive(Debug, Clone, Default)]
+pub struct ExportSummary {
+    pub pages: usize,
+    /// Pages without a recorded URL, which can only be restored into the same site ID
+    pub pages_without_url: usize,
+    pub comments: usize,
+    pub users: usize,
+    pub media: usize,
+}
+
+impl fmt::Display for ExportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Pages:             {}", self.pages)?;
+        writeln!(f, "  Without URL:     {}", self.pages_without_url)?;
+        writeln!(f, "Comments:          {}", self.comments)?;
+        writeln!(f, "Users:             {}", self.users)?;
+        writeln!(f, "Media:             {}", self.media)
+    }
+}
+
+/// Write a site archive to `out`
+pub async fn export_site(
+    redis: &RedisClient,
+    store: &dyn Storage,
+    site_id: Uuid,
+    out: &mut impl Write,
+) -> Result<ExportSummary> {
+    let mut config = redis
+        .get_site_config(site_id)
+        .await?
+        .with_context(|| format!("Site {} not found", site_id))?;
+    config.project_id_secret.clear();
+
+    let mut summary = ExportSummary::default();
+
+    write_record(
+        out,
+        &ArchiveRecord::Header(ArchiveHeader {
+            version: ARCHIVE_VERSION,
+            site_id,
+            exported_at: Utc::now(),
+        }),
+    )?;
+    write_record(out, &ArchiveRecord::Site(config))?;
+
+    let roles = ArchivedRoles {
+        admins: store.get_admins(site_id).await?,
+        moderators: store.get_moderators(site_id).await?,
+        blocked: store.get_site_blocked_users(site_id).await?,
+        shadowbanned: store.get_site_shadowbanned_users(site_id).await?,
+    };
+
+    // Pages are found through every per-site structure that references them
+    let page_urls: HashMap<Uuid, String> = redis.get_site_pages(site_id).await?.into_iter().collect();
+    let locked: HashSet<Uuid> = redis.get_locked_pages(site_id).await?.into_iter().collect();
+    let modqueue = group_by_page(redis.get_modqueue_entries(site_id).await?);
+    let reports = group_by_page(redis.get_report_entries(site_id).await?);
+
+    let mut page_ids: BTreeSet<Uuid> = redis.get_site_comment_page_ids(site_id).await?.into_iter().collect();
+    page_ids.extend(page_urls.keys());
+    page_ids.extend(&locked);
+    page_ids.extend(modqueue.keys());
+    page_ids.extend(reports.keys());
+
+    let mut user_ids: BTreeSet<Uuid> = roles
+        .admins
+        .iter()
+        .chain(&roles.moderators)
+        .chain(&roles.blocked)
+        .chain(&roles.shadowbanned)
+        .copied()
+        .collect();
+
+    let mut pages = Vec::new();
+    for page_id in page_ids {
+        let tree = store.get_page_tree(page_id).await?;
+        if tree.is_none() && !locked.contains(&page_id) {
+            continue;
+        }
+        let tree = tree.unwrap_or_default();
+
+        visit_comments(&tree.comments, &mut |c| {
+            summary.comments += 1;
+            user_ids.insert(c.author_id);
+        });
+
+        let pinned = redis
+            .get_pinned_comments(page_id)
+            .await?
+            .into_iter()
+            .map(|(comment_id, at)| PageEntry { comment_id, at })
+            .collect();
+
+        let page_url = page_urls.get(&page_id).cloned();
+        if page_url.is_none() {
+            summary.pages_without_url += 1;
+        }
+
+        pages.push(ArchivedPage {
+            page_id,
+            page_url,
+            locked: locked.contains(&page_id),
+            tree,
+            pinned,
+            modqueue: modqueue.get(&page_id).cloned().unwrap_or_default(),
+            reports: reports.get(&page_id).cloned().unwrap_or_default(),
+        });
+    }
+
+    write_record(out, &ArchiveRecord::Roles(roles))?;
+
+    user_ids.remove(&ANONYMOUS_USER_ID);
+    user_ids.remove(&DELETED_USER_ID);
+    let mut media = Vec::new();
+    for user_id in user_ids {
+        let Some(user) = store.get_user(user_id).await? else {
+            continue;
+        };
+        write_record(out, &ArchiveRecord::User(user))?;
+        summary.users += 1;
+
+        for media_id in redis.get_user_media(user_id).await? {
+            if let Some(info) = redis.get_media_info(media_id).await?
+                && info.site_id == site_id
+            {
+                media.push(info);
+            }
+        }
+    }
+
+    // Pages after users so a restore can remap authors as it goes
+    summary.pages = pages.len();
+    for page in pages {
+        write_record(out, &ArchiveRecord::Page(page))?;
+    }
+
+    summary.media = media.len();
+    for info in media {
+        write_record(out, &ArchiveRecord::Media(info))?;
+    }
+
+    out.flush()?;
+    Ok(summary)
+}
+
+fn write_record(out: &mut impl Write, record: &ArchiveRecord) -> Result<()> {
+    serde_json::to_writer(&mut *out, record)?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+fn group_by_page(entries: Vec<(Uuid, Uuid, i64)>) -> HashMap<Uuid, Vec<PageEntry>> {
+    let mut grouped: HashMap<Uuid, Vec<PageEntry>> = HashMap::new();
+    for (page_id, comment_id, at) in entries {
+        grouped.entry(page_id).or_default().push(PageEntry { comment_id, at });
+    }
+    grouped
+}
+
+fn visit_comments<'a>(comments: &'a [TreeComment], f: &mut impl FnMut(&'a TreeComment)) {
+    for comment in comments {
+        f(comment);
+        visit_comments(&comment.replies, f);
+    }
+}
+
+// ============================================================================
+// Restore
+// ============================================================================
+
+/// A parsed archive
+#[derive(Debug)]
+pub struct Archive {
+    pub header: ArchiveHeader,
+    pub site: SiteConfig,
+    pub roles: ArchivedRoles,
+    pub users: Vec<User>,
+    pub pages: Vec<ArchivedPage>,
+    pub media: Vec<MediaInfo>,
+}
+
+impl Archive {
+    /// Read and validate an archive
+    pub fn read(input: impl BufRead) -> Result<Self> {
+        let mut lines = input.lines().enumerate().filter(|(_, line)| {
+            line.as_ref().map(|l| !l.trim().is_empty()).unwrap_or(true)
+        });
+
+        let header = match lines.next() {
+            Some((_, line)) => match serde_json::from_str(&line?).context("Invalid archive header")? {
+                ArchiveRecord::Header(header) => header,
+                _ => bail!("Archive must start with a header record"),
+            },
+            None => bail!("Archive is empty"),
+        };
+        if header.version > ARCHIVE_VERSION {
+            bail!(
+                "Archive version {} is newer than supported version {}",
+                header.version,
+                ARCHIVE_VERSION
+            );
+        }
+
+        let mut site = None;
+        let mut roles = ArchivedRoles::default();
+        let mut users = Vec::new();
+        let mut pages = Vec::new();
+        let mut media = Vec::new();
+
+        for (i, line) in lines {
+            let record: ArchiveRecord = serde_json::from_str(&line?)
+                .with_context(|| format!("Invalid record on line {}", i + 1))?;
+            match record {
+                ArchiveRecord::Header(_) => bail!("Unexpected header on line {}", i + 1),
+                ArchiveRecord::Site(config) => site = Some(config),
+                ArchiveRecord::Roles(r) => roles = r,
+                ArchiveRecord::User(user) => users.push(user),
+                ArchiveRecord::Page(page) => pages.push(page),
+                ArchiveRecord::Media(info) => media.push(info),
+            }
+        }
+
+        Ok(Archive {
+            site: site.context("Archive has no site record")?,
+            header,
+            roles,
+            users,
+            pages,
+            media,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RestoreOptions {
+    /// Restore into this site instead of the archived site ID
+    pub site_id: Option<Uuid>,
+}
+
+/// Counts for a restore
+#[derive(Debug, Clone, Default)]
+pub struct RestoreReport {
+    pub site_id: Uuid,
+    /// False when merging into an existing site (its config is kept)
+    pub site_created: bool,
+    /// Project keys (public, secret) when any changed: the secret key is always new,
+    /// and the public key is new when the archived one belongs to another site
+    pub new_project_keys: Option<(String, String)>,
+    pub pages: usize,
+    pub comments: usize,
+    /// Comments skipped because they already exist in the target page
+    pub already_present: usize,
+    /// Pages skipped because they have no URL and the site ID changed
+    pub unmapped_pages: Vec<Uuid>,
+    pub users_created: usize,
+    pub users_existing: usize,
+    /// Archived users merged into an existing account with the same verified email
+    pub users_matched_by_email: usize,
+    pub media: usize,
+}
+
+impl fmt::Display for RestoreReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Site:              {}", self.site_id)?;
+        writeln!(
+            f,
+            "  Config:          {}",
+            if self.site_created { "restored" } else { "kept existing" }
+        )?;
+        if let Some((public, secret)) = &self.new_project_keys {
+            writeln!(f, "  New public key:  {}", public)?;
+            writeln!(f, "  New secret key:  {}", secret)?;
+        }
+        writeln!(f, "Pages:             {}", self.pages)?;
+        writeln!(f, "Comments:          {}", self.comments)?;
+        writeln!(f, "Already present:   {}", self.already_present)?;
+        writeln!(f, "Users created:     {}", self.users_created)?;
+        writeln!(f, "Users existing:    {}", self.users_existing)?;
+        writeln!(f, "Users by email:    {}", self.users_matched_by_email)?;
+        writeln!(f, "Media:             {}", self.media)?;
+
+        if !self.unmapped_pages.is_empty() {
+            writeln!(f, "\nPages without a URL (not restored):")?;
+            for page_id in &self.unmapped_pages {
+                writeln!(f, "  {}", page_id)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Load an archive into Redis. Safe to re-run: existing comments, users and
+/// role memberships are left as they are.
+pub async fn restore_site(
+    redis: &RedisClient,
+    store: &dyn Storage,
+    archive: &Archive,
+    options: &RestoreOptions,
+) -> Result<RestoreReport> {
+    let source_id = archive.header.site_id;
+    let target_id = options.site_id.unwrap_or(source_id);
+    let mut report = RestoreReport {
+        site_id: target_id,
+        ..Default::default()
+    };
+
+    // Site config - an existing target site keeps its own settings and keys
+    if redis.get_site_config(target_id).await?.is_none() {
+        let mut config = archive.site.clone();
+        config.id = target_id;
+        if project_key_taken(redis, &config.project_id_public, target_id).await? {
+            config.project_id_public = generate_api_key(PUBLIC_KEY_PREFIX);
+        }
+        // The secret key isn't exported (older archives may still have one)
+        if config.project_id_secret.is_empty()
+            || project_key_taken(redis, &config.project_id_secret, target_id).await?
+        {
+            config.project_id_secret = generate_api_key(SECRET_KEY_PREFIX);
+        }
+        if config.project_id_public != archive.site.project_id_public
+            || config.project_id_secret != archive.site.project_id_secret
+        {
+            report.new_project_keys =
+                Some((config.project_id_public.clone(), config.project_id_secret.clone()));
+        }
+        redis.set_site_config(&config).await?;
+        report.site_created = true;
+    }
+
+    // Users - archived ID -> ID in this Redis
+    let mut user_map: HashMap<Uuid, Uuid> = HashMap::new();
+    let mut new_comment_counts: HashMap<Uuid, i64> = HashMap::new();
+    for user in &archive.users {
+        let user_id = restore_user(store, user, &mut report).await?;
+        user_map.insert(user.id, user_id);
+    }
+    let map_user = |id: Uuid| user_map.get(&id).copied().unwrap_or(id);
+
+    for user_id in &archive.roles.admins {
+        store.add_admin(target_id, map_user(*user_id)).await?;
+    }
+    for user_id in &archive.roles.moderators {
+        store.add_moderator(target_id, map_user(*user_id)).await?;
+    }
+    for user_id in &archive.roles.blocked {
+        store.block_user(target_id, map_user(*user_id)).await?;
+    }
+    for user_id in &archive.roles.shadowbanned {
+        store.shadowban_user(target_id, map_user(*user_id)).await?;
+    }
+
+    for page in &archive.pages {
+        // Page IDs are derived from the site ID, so moving sites needs the URL
+        let page_id = if target_id == source_id {
+            page.page_id
+        } else if let Some(page_url) = &page.page_url {
+            RedisClient::generate_page_id(target_id, page_url)
+        } else {
+            report.unmapped_pages.push(page.page_id);
+            continue;
+        };
+
+        let mut roots = page.tree.comments.clone();
+        remap_authors(&mut roots, &map_user);
+
+        let (_, added) = store
+            .update_page_tree(page_id, |tree| {
+                let added: Vec<usize> = roots
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, root)| tree.find_root(root.id).is_none())
+                    .map(|(i, _)| i)
+                    .collect();
+                for &i in &added {
+                    tree.add_root(roots[i].clone());
+                }
+                Ok::<_, std::convert::Infallible>(added)
+            })
+            .await?
+            .unwrap_or_else(|e| match e {});
+
+        for (i, root) in roots.iter().enumerate() {
+            let mut comments = Vec::new();
+            visit_comments(std::slice::from_ref(root), &mut |c| comments.push(c));
+
+            if !added.contains(&i) {
+                report.already_present += comments.len();
+                continue;
+            }
+
+            for c in comments {
+                let user_id = Some(c.author_id)
+                    .filter(|id| *id != ANONYMOUS_USER_ID && *id != DELETED_USER_ID);
+                redis
+                    .add_imported_comment_indexes(target_id, user_id, page_id, c.id, c.created_at)
+                    .await?;
+                if let Some(user_id) = user_id {
+                    *new_comment_counts.entry(user_id).or_default() += 1;
+                }
+                report.comments += 1;
+            }
+        }
+
+        if let Some(page_url) = &page.page_url {
+            redis.set_page_url(target_id, page_id, page_url).await?;
+        }
+        if page.locked {
+            redis.lock_page(target_id, page_id).await?;
+        }
+        for entry in &page.pinned {
+            redis.pin_comment(page_id, entry.comment_id, entry.at).await?;
+        }
+        for entry in &page.modqueue {
+            redis
+                .add_to_modqueue_at(target_id, page_id, entry.comment_id, entry.at)
+                .await?;
+        }
+        for entry in &page.reports {
+            redis
+                .add_report_entry(target_id, page_id, entry.comment_id, entry.at)
+                .await?;
+        }
+        report.pages += 1;
+    }
+
+    for (user_id, count) in new_comment_counts {
+        store.increment_user_comment_count_by(user_id, count).await?;
+    }
+
+    for info in &archive.media {
+        if info.site_id != source_id {
+            continue;
+        }
+        let mut info = info.clone();
+        info.site_id = target_id;
+        info.uploader_user_id = map_user(info.uploader_user_id);
+        redis.set_media_info(&info).await?;
+        redis.add_user_media(info.uploader_user_id, info.id).await?;
+        report.media += 1;
+    }
+
+    Ok(report)
+}
+
+/// Whether a project key already maps to a different site
+async fn project_key_taken(redis: &RedisClient, key: &str, site_id: Uuid) -> Result<bool> {
+    Ok(redis
+        .get_site_by_project_id(key)
+        .await?
+        .is_some_and(|(existing, _)| existing != site_id))
+}
+
+/// Find or create the user for an archived user record
+///
+/// Only accounts that verified the email are matched, so signing up with someone
+/// else's address before a restore doesn't hand over their comments.
+async fn restore_user(store: &dyn Storage, user: &User, report: &mut RestoreReport) -> Result<Uuid> {
+    if store.get_user(user.id).await?.is_some() {
+        report.users_existing += 1;
+        return Ok(user.id);
+    }
+
+    let existing = match &user.email {
+        Some(email) => match store.get_user_by_email(email).await? {
+            Some(user_id) => store.get_user(user_id).await?,
+            None => None,
+        },
+        None => None,
+    };
+    let email_taken = existing.is_some();
+    if let Some(existing) = existing.filter(|u| u.email_verified) {
+        report.users_matched_by_email += 1;
+        return Ok(existing.id);
+    }
+
+    let mut user = user.clone();
+    if email_taken {
+        // An unverified account holds the email: the restored user keeps its
+        // comments together but can't be claimed through that address
+        user.email = None;
+        user.email_verified = false;
+    }
+    // Counted again from the restored comments
+    user.total_comments = 0;
+    if !store.is_username_available(&user.name, None).await? {
+        user.name = format!("{}-{}", user.name, &user.id.to_string()[..8]);
+    }
+
+    store.set_user(&user).await?;
+    store.set_user_username_index(&user.name, user.id).await?;
+    if let Some(email) = &user.email {
+        store.set_user_email_index(email, user.id).await?;
+    }
+    if let Some(provider_id) = &user.provider_id {
+        match user.provider {
+            AuthProvider::Google => store.set_user_provider_index("google", provider_id, user.id).await?,
+            AuthProvider::Github => store.set_user_provider_index("github", provider_id, user.id).await?,
+            AuthProvider::Ethereum => store.set_user_wallet_index("ethereum", provider_id, user.id).await?,
+            AuthProvider::Solana => store.set_user_wallet_index("solana", provider_id, user.id).await?,
+            AuthProvider::Email | AuthProvider::Anonymous => {}
+        }
+    }
+
+    report.users_created += 1;
+    Ok(user.id)
+}
+
+fn remap_authors(comments: &mut [TreeComment], map_user: &impl Fn(Uuid) -> Uuid) {
+    for comment in comments {
+        comment.author_id = map_user(comment.author_id);
+        remap_authors(&mut comment.replies, map_user);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use threadkit_common::types::SiteSettings;
+
+    fn site_record() -> String {
+        serde_json::to_string(&ArchiveRecord::Site(SiteConfig {
+            id: Uuid::nil(),
+            name: "Test".to_string(),
+            domain: "example.com".to_string(),
+            project_id_public: "tk_pub_test".to_string(),
+            project_id_secret: "tk_sec_test".to_string(),
+            settings: SiteSettings::default(),
+            plan: Default::default(),
+        }))
+        .unwrap()
+    }
+
+    fn header_record(version: u32) -> String {
+        serde_json::to_string(&ArchiveRecord::Header(ArchiveHeader {
+            version,
+            site_id: Uuid::nil(),
+            exported_at: Utc::now(),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_read_archive() {
+        let page = r#"{"type":"page","page_id":"00000000-0000-0000-0000-000000000001","page_url":"/a","tree":{"c":[],"u":0}}"#;
+        let input = format!("{}\n{}\n\n{}\n", header_record(1), site_record(), page);
+
+        let archive = Archive::read(input.as_bytes()).unwrap();
+        assert_eq!(archive.site.domain, "example.com");
+        assert_eq!(archive.pages.len(), 1);
+        assert_eq!(archive.pages[0].page_url.as_deref(), Some("/a"));
+        assert!(!archive.pages[0].locked);
+        assert!(archive.users.is_empty());
+    }
+
+    #[test]
+    fn test_read_archive_requires_header() {
+        let err = Archive::read(site_record().as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("header"));
+    }
+
+    #[test]
+    fn test_read_archive_rejects_newer_version() {
+        let input = format!("{}\n{}\n", header_record(ARCHIVE_VERSION + 1), site_record());
+        let err = Archive::read(input.as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("newer"));
+    }
+
+    #[test]
+    fn test_record_tag() {
+        let line = serde_json::to_string(&ArchiveRecord::Roles(ArchivedRoles::default())).unwrap();
+        assert!(line.starts_with(r#"{"type":"roles""#));
+    }
+}
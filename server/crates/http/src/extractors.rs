@@ -2,14 +2,18 @@ use axum::{
     extract::{FromRef, FromRequestParts},
     http::{header::AUTHORIZATION, request::Parts, HeaderMap, StatusCode},
 };
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
 use threadkit_common::{
-    auth,
-    types::{ProjectIdInfo, ProjectIdType, Role},
+    api_tokens, auth,
+    rate_limit::client_ip,
+    types::{ApiToken, ApiTokenScope, ProjectIdInfo, ProjectIdType, Role, SiteSettings},
+    ActionLogBuilder, ActionType,
 };
 use url::Url;
 use uuid::Uuid;
 
-use crate::state::AppState;
+use crate::{middleware::peer_ip, state::AppState};
 
 /// Extract the origin from Referer or Origin headers
 fn extract_request_origin(headers: &HeaderMap) -> Option<String> {
@@ -74,6 +78,9 @@ fn is_origin_allowed(
 
 /// Extracts and validates API key from projectid header
 /// Also validates that the request origin matches the site's allowed domains
+///
+/// API tokens are rejected: they only reach endpoints taking `OwnerAccess` or `StaffAccess`,
+/// which check their scopes.
 pub struct ProjectId(pub ProjectIdInfo);
 
 impl<S> FromRequestParts<S> for ProjectId
@@ -84,85 +91,202 @@ where
     type Rejection = (StatusCode, String);
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let state = AppState::from_ref(state);
+        let info = request_key(parts, state).await?;
+        reject_api_token(&info)?;
+        Ok(ProjectId(info))
+    }
+}
 
-        let project_id = parts
-            .headers
-            .get("projectid")
-            .and_then(|v| v.to_str().ok())
-            .ok_or((StatusCode::UNAUTHORIZED, "Missing projectid header".to_string()))?;
+/// Look up the `projectid` header's key, API tokens included
+async fn request_key<S>(parts: &mut Parts, state: &S) -> Result<ProjectIdInfo, (StatusCode, String)>
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    let state = AppState::from_ref(state);
 
-        let allow_localhost = state.config.allow_localhost_origin;
+    // Several extractors of one request need the key, look it up once
+    if let Some(info) = parts.extensions.get::<ProjectIdInfo>() {
+        return Ok(info.clone());
+    }
 
-        // Check cache first
-        if let Ok(Some(info)) = state.redis.get_cached_project_id(project_id).await {
-            // Validate origin for cached API keys too
-            validate_origin(&parts.headers, &info, allow_localhost)?;
-            return Ok(ProjectId(info));
-        }
+    let project_id = parts
+        .headers
+        .get("projectid")
+        .and_then(|v| v.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing projectid header".to_string()))?;
 
-        // In standalone mode, validate against config
-        if let Some(standalone) = state.config.standalone() {
-            let key_type = if project_id == standalone.project_id_public {
-                ProjectIdType::Public
-            } else if project_id == standalone.project_id_secret {
-                ProjectIdType::Secret
-            } else {
-                return Err((StatusCode::UNAUTHORIZED, "Invalid API key".to_string()));
-            };
-
-            let site_config = state
-                .redis
-                .get_site_config_by_api_key(project_id)
-                .await
-                .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get site config".to_string()))?
-                .ok_or((StatusCode::INTERNAL_SERVER_ERROR, "Site config not found".to_string()))?;
-
-            let info = ProjectIdInfo {
-                site_id: site_config.id,
-                key_type,
-                settings: site_config.settings,
-                domain: site_config.domain,
-            };
-
-            // Validate origin before caching
-            validate_origin(&parts.headers, &info, allow_localhost)?;
-
-            // Cache for future requests
-            let _ = state.redis.cache_project_id(project_id, &info).await;
-
-            return Ok(ProjectId(info));
-        }
+    let peer = peer_ip(&parts.extensions);
+    let info = resolve_key(&state, project_id, &parts.headers, peer).await?;
+    parts.extensions.insert(info.clone());
+    Ok(info)
+}
 
-        // SaaS mode: look up site by API key in Redis
-        let (site_id, site_config) = state
-            .redis
-            .get_site_by_project_id(project_id)
-            .await
-            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to validate API key".to_string()))?
-            .ok_or((StatusCode::UNAUTHORIZED, "Invalid API key".to_string()))?;
+fn reject_api_token(info: &ProjectIdInfo) -> Result<(), (StatusCode, String)> {
+    if info.key_type == ProjectIdType::Token {
+        Err((StatusCode::FORBIDDEN, "API tokens can't be used on this endpoint".to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Look up a public or secret key and validate the request origin against the site's allowed domains
+///
+/// For endpoints that also accept the key outside the `projectid` header (`EventSource`
+/// can't set headers). Like the `ProjectId` extractor, API tokens are rejected.
+pub async fn resolve_project_id(
+    state: &AppState,
+    project_id: &str,
+    headers: &HeaderMap,
+    peer: Option<IpAddr>,
+) -> Result<ProjectIdInfo, (StatusCode, String)> {
+    let info = resolve_key(state, project_id, headers, peer).await?;
+    reject_api_token(&info)?;
+    Ok(info)
+}
+
+/// Look up an API key or token and validate the request origin against the site's allowed domains
+async fn resolve_key(
+    state: &AppState,
+    project_id: &str,
+    headers: &HeaderMap,
+    peer: Option<IpAddr>,
+) -> Result<ProjectIdInfo, (StatusCode, String)> {
+    let allow_localhost = state.config.allow_localhost_origin;
+
+    if project_id.starts_with(auth::API_TOKEN_PREFIX) {
+        return resolve_api_token(state, project_id, headers, peer).await;
+    }
+
+    // Check cache first
+    if let Ok(Some(info)) = state.redis.get_cached_project_id(project_id).await {
+        // Validate origin for cached API keys too
+        validate_origin(headers, &info, allow_localhost)?;
+        return Ok(info);
+    }
 
-        let key_type = if project_id == site_config.project_id_public {
+    // In standalone mode, validate against config
+    if let Some(standalone) = state.config.standalone() {
+        let key_type = if project_id == standalone.project_id_public {
             ProjectIdType::Public
-        } else {
+        } else if project_id == standalone.project_id_secret {
             ProjectIdType::Secret
+        } else {
+            return Err((StatusCode::UNAUTHORIZED, "Invalid API key".to_string()));
         };
 
+        let site_config = state
+            .redis
+            .get_site_config_by_api_key(project_id)
+            .await
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get site config".to_string()))?
+            .ok_or((StatusCode::INTERNAL_SERVER_ERROR, "Site config not found".to_string()))?;
+
         let info = ProjectIdInfo {
-            site_id,
+            site_id: site_config.id,
             key_type,
             settings: site_config.settings,
             domain: site_config.domain,
+            limits: site_config.plan.effective_limits(),
+            api_token: None,
         };
 
         // Validate origin before caching
-        validate_origin(&parts.headers, &info, allow_localhost)?;
+        validate_origin(headers, &info, allow_localhost)?;
 
         // Cache for future requests
         let _ = state.redis.cache_project_id(project_id, &info).await;
 
-        Ok(ProjectId(info))
+        return Ok(info);
+    }
+
+    // SaaS mode: look up site by API key in Redis
+    let (site_id, site_config) = state
+        .redis
+        .get_site_by_project_id(project_id)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to validate API key".to_string()))?
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid API key".to_string()))?;
+
+    // Keys replaced by a rotation keep working until their grace period ends
+    let current = project_id == site_config.project_id_public || project_id == site_config.project_id_secret;
+    let key_type = if project_id == site_config.project_id_secret
+        || (!current && project_id.starts_with(auth::SECRET_KEY_PREFIX))
+    {
+        ProjectIdType::Secret
+    } else {
+        ProjectIdType::Public
+    };
+
+    let info = ProjectIdInfo {
+        site_id,
+        key_type,
+        settings: site_config.settings,
+        domain: site_config.domain,
+        limits: site_config.plan.effective_limits(),
+        api_token: None,
+    };
+
+    // Validate origin before caching
+    validate_origin(headers, &info, allow_localhost)?;
+
+    // Cache for future requests (replaced keys aren't, so they stop working when their index expires)
+    if current {
+        let _ = state.redis.cache_project_id(project_id, &info).await;
     }
+
+    Ok(info)
+}
+
+/// Look up a scoped API token and check its expiry and IP allowlist
+///
+/// Tokens aren't cached, so revoking one takes effect immediately.
+async fn resolve_api_token(
+    state: &AppState,
+    token: &str,
+    headers: &HeaderMap,
+    peer: Option<IpAddr>,
+) -> Result<ProjectIdInfo, (StatusCode, String)> {
+    let api_token = state
+        .redis
+        .get_api_token(&api_tokens::hash_token(token))
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to validate API token".to_string()))?
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid API key".to_string()))?;
+
+    if api_token.is_expired() {
+        return Err((StatusCode::UNAUTHORIZED, "API token expired".to_string()));
+    }
+
+    let client_ip = client_ip(headers, peer, &state.config.rate_limit.trusted_proxies);
+    if !api_tokens::ip_allowed(&api_token.allowed_ips, client_ip.as_deref()) {
+        return Err((StatusCode::FORBIDDEN, "API token can't be used from this IP address".to_string()));
+    }
+
+    let site_config = state
+        .redis
+        .get_site_config(api_token.site_id)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get site config".to_string()))?
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid API key".to_string()))?;
+
+    {
+        let redis = state.redis.clone();
+        let (site_id, token_id) = (api_token.site_id, api_token.id);
+        tokio::spawn(async move {
+            let _ = redis.touch_api_token(site_id, token_id).await;
+        });
+    }
+
+    // Server-to-server like the secret key, so the origin isn't checked
+    Ok(ProjectIdInfo {
+        site_id: site_config.id,
+        key_type: ProjectIdType::Token,
+        settings: site_config.settings,
+        domain: site_config.domain,
+        limits: site_config.plan.effective_limits(),
+        api_token: Some(api_token),
+    })
 }
 
 /// Validate that the request origin is allowed for this API key
@@ -171,8 +295,8 @@ fn validate_origin(
     info: &ProjectIdInfo,
     allow_localhost: bool,
 ) -> Result<(), (StatusCode, String)> {
-    // Secret keys skip origin validation (server-to-server)
-    if info.key_type == ProjectIdType::Secret {
+    // Secret keys and API tokens skip origin validation (server-to-server)
+    if info.key_type != ProjectIdType::Public {
         return Ok(());
     }
 
@@ -230,7 +354,7 @@ where
 
         // Verify session still exists
         let session_user = state
-            .redis
+            .store
             .get_session_user(claims.session_id)
             .await
             .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to verify session".to_string()))?;
@@ -311,7 +435,7 @@ where
         let auth_user = AuthUser::from_request_parts(parts, state).await?;
 
         let role = app_state
-            .redis
+            .store
             .get_user_role(auth_user.site_id, auth_user.user_id)
             .await
             .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get user role".to_string()))?;
@@ -322,7 +446,7 @@ where
 
         // Get username_set from user record
         let username_set = app_state
-            .redis
+            .store
             .get_user(auth_user.user_id)
             .await
             .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get user".to_string()))?
@@ -396,9 +520,31 @@ where
     }
 }
 
-/// Owner access via secret API key
+/// Owner access via secret API key, or an API token with the endpoint's scope
+///
+/// Handlers must call [`OwnerAccess::require_scope`] (or `require_secret_key`).
 pub struct OwnerAccess {
     pub site_id: Uuid,
+    /// None for the secret key
+    pub api_token: Option<ApiToken>,
+}
+
+impl OwnerAccess {
+    /// Allow the secret key, and API tokens with `scope`
+    pub fn require_scope(&self, scope: ApiTokenScope) -> Result<(), (StatusCode, String)> {
+        match &self.api_token {
+            Some(token) => require_token_scope(token, scope),
+            None => Ok(()),
+        }
+    }
+
+    /// Allow only the secret key
+    pub fn require_secret_key(&self) -> Result<(), (StatusCode, String)> {
+        match self.api_token {
+            Some(_) => Err((StatusCode::FORBIDDEN, "Secret API key required".to_string())),
+            None => Ok(()),
+        }
+    }
 }
 
 impl<S> FromRequestParts<S> for OwnerAccess
@@ -409,18 +555,176 @@ where
     type Rejection = (StatusCode, String);
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let project_id = ProjectId::from_request_parts(parts, state).await?;
+        let project_id = request_key(parts, state).await?;
 
-        if project_id.0.key_type != ProjectIdType::Secret {
+        if project_id.key_type == ProjectIdType::Public {
             return Err((StatusCode::FORBIDDEN, "Secret API key required".to_string()));
         }
 
         Ok(OwnerAccess {
-            site_id: project_id.0.site_id,
+            site_id: project_id.site_id,
+            api_token: project_id.api_token,
         })
     }
 }
 
+/// Staff access to the site: a signed-in moderator or admin, the secret key (acting as
+/// owner) or an API token (acting as admin, limited to its scopes)
+///
+/// A signed-in user always acts as themselves, whatever key the request carries.
+/// Handlers take the site from here rather than from `ProjectId`, which refuses API tokens.
+pub struct StaffAccess {
+    pub site_id: Uuid,
+    pub settings: SiteSettings,
+    /// None for the secret key and API tokens
+    pub user_id: Option<Uuid>,
+    pub role: Role,
+    pub api_token: Option<ApiToken>,
+}
+
+impl StaffAccess {
+    pub fn require_moderator(&self, scope: ApiTokenScope) -> Result<(), (StatusCode, String)> {
+        self.require_scope(scope)?;
+        if self.role >= Role::Moderator {
+            Ok(())
+        } else {
+            Err((StatusCode::FORBIDDEN, "Moderator access required".to_string()))
+        }
+    }
+
+    pub fn require_admin(&self, scope: ApiTokenScope) -> Result<(), (StatusCode, String)> {
+        self.require_scope(scope)?;
+        if self.role >= Role::Admin {
+            Ok(())
+        } else {
+            Err((StatusCode::FORBIDDEN, "Admin access required".to_string()))
+        }
+    }
+
+    fn require_scope(&self, scope: ApiTokenScope) -> Result<(), (StatusCode, String)> {
+        match &self.api_token {
+            Some(token) => require_token_scope(token, scope),
+            None => Ok(()),
+        }
+    }
+
+    /// Start an action log entry attributed to the user or API token
+    pub fn action(&self, action: ActionType) -> ActionLogBuilder {
+        let mut builder = ActionLogBuilder::new(action, self.site_id);
+        if let Some(user_id) = self.user_id {
+            builder = builder.user_id(user_id);
+        }
+        if let Some(token) = &self.api_token {
+            builder = builder.api_token_id(token.id);
+        }
+        builder
+    }
+}
+
+impl<S> FromRequestParts<S> for StaffAccess
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let project_id = request_key(parts, state).await?;
+
+        if project_id.key_type == ProjectIdType::Public || parts.headers.contains_key(AUTHORIZATION) {
+            let auth = AuthUserWithRole::from_request_parts(parts, state).await?;
+            return Ok(StaffAccess {
+                site_id: project_id.site_id,
+                settings: project_id.settings,
+                user_id: Some(auth.user_id),
+                role: auth.role,
+                api_token: None,
+            });
+        }
+
+        let role = match project_id.key_type {
+            ProjectIdType::Token => Role::Admin,
+            _ => Role::Owner,
+        };
+        Ok(StaffAccess {
+            site_id: project_id.site_id,
+            settings: project_id.settings,
+            user_id: None,
+            role,
+            api_token: project_id.api_token,
+        })
+    }
+}
+
+fn require_token_scope(token: &ApiToken, scope: ApiTokenScope) -> Result<(), (StatusCode, String)> {
+    if token.has_scope(scope) {
+        Ok(())
+    } else {
+        Err((StatusCode::FORBIDDEN, format!("API token lacks the {} scope", scope.name())))
+    }
+}
+
+/// Client IP address: the TCP peer, or the address forwarded by a trusted proxy
+///
+/// Used for the action log, Turnstile and visitor counting, so it can't be spoofed
+/// with forwarding headers.
+pub struct ClientIp(pub Option<String>);
+
+impl<S> FromRequestParts<S> for ClientIp
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = AppState::from_ref(state);
+        Ok(ClientIp(client_ip(
+            &parts.headers,
+            peer_ip(&parts.extensions),
+            &state.config.rate_limit.trusted_proxies,
+        )))
+    }
+}
+
+/// Platform operator access via `ADMIN_API_KEY` (SaaS mode only)
+///
+/// Manages every site, so it's checked before anything site-specific.
+pub struct PlatformAdmin;
+
+impl<S> FromRequestParts<S> for PlatformAdmin
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = AppState::from_ref(state);
+
+        let Some(saas) = state.config.saas() else {
+            return Err((StatusCode::FORBIDDEN, "Site management is only available in SaaS mode".to_string()));
+        };
+        let Some(admin_api_key) = saas.admin_api_key.as_deref() else {
+            return Err((StatusCode::FORBIDDEN, "Site management is disabled (ADMIN_API_KEY is not set)".to_string()));
+        };
+
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .ok_or((StatusCode::UNAUTHORIZED, "Missing admin API key".to_string()))?;
+
+        // Compare digests so the comparison time doesn't depend on how much of the key matches
+        if Sha256::digest(token.as_bytes()) != Sha256::digest(admin_api_key.as_bytes()) {
+            return Err((StatusCode::UNAUTHORIZED, "Invalid admin API key".to_string()));
+        }
+
+        Ok(PlatformAdmin)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
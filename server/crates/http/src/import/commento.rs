@@ -0,0 +1,91 @@
+//! Commento JSON export (`{"version": 1, "comments": [...], "commenters": [...]}`)
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+use super::{ContentFormat, ImportComment, ImportData, ImportState, ImportThread};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Export {
+    comments: Vec<Comment>,
+    #[serde(default)]
+    commenters: Vec<Commenter>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Comment {
+    comment_hex: String,
+    domain: String,
+    path: String,
+    commenter_hex: String,
+    markdown: String,
+    parent_hex: String,
+    #[serde(default)]
+    state: String,
+    creation_date: DateTime<Utc>,
+    #[serde(default)]
+    deleted: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Commenter {
+    commenter_hex: String,
+    email: String,
+    name: String,
+}
+
+pub(super) fn parse(input: &str) -> Result<ImportData> {
+    let export: Export = serde_json::from_str(input).context("Not a Commento export")?;
+
+    let commenters: HashMap<&str, &Commenter> = export
+        .commenters
+        .iter()
+        .map(|c| (c.commenter_hex.as_str(), c))
+        .collect();
+
+    let mut data = ImportData::default();
+    let mut seen_threads = HashSet::new();
+
+    for comment in &export.comments {
+        // Commento has no thread IDs, pages are identified by domain + path
+        let thread_key = format!("{}{}", comment.domain, comment.path);
+        if seen_threads.insert(thread_key.clone()) {
+            data.threads.push(ImportThread {
+                key: thread_key.clone(),
+                url: Some(format!("https://{}", thread_key)),
+                title: None,
+            });
+        }
+
+        let state = if comment.deleted {
+            ImportState::Deleted
+        } else {
+            match comment.state.as_str() {
+                "unapproved" | "flagged" => ImportState::Pending,
+                _ => ImportState::Approved,
+            }
+        };
+
+        // Anonymous comments use the commenter hex "anonymous"
+        let commenter = commenters.get(comment.commenter_hex.as_str());
+
+        data.comments.push(ImportComment {
+            key: comment.comment_hex.clone(),
+            thread_key,
+            parent_key: Some(comment.parent_hex.clone()).filter(|p| p != "root"),
+            author_name: commenter.map(|c| c.name.clone()),
+            author_email: commenter.map(|c| c.email.clone()).filter(|e| !e.is_empty()),
+            content: comment.markdown.clone(),
+            format: ContentFormat::Markdown,
+            created_at: comment.creation_date,
+            state,
+        });
+    }
+
+    Ok(data)
+}
@@ -0,0 +1,63 @@
+//! Disqus XML export (`<disqus>` with `<thread>` and `<post>` elements)
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+
+use super::{xml, ContentFormat, ImportComment, ImportData, ImportState, ImportThread};
+
+pub(super) fn parse(input: &str) -> Result<ImportData> {
+    let root = xml::parse(input)?;
+    if root.name != "disqus" {
+        bail!("Not a Disqus export (root element is <{}>)", root.name);
+    }
+
+    let mut data = ImportData::default();
+
+    for thread in root.children_named("thread") {
+        let Some(key) = thread.attr("id") else {
+            continue;
+        };
+        data.threads.push(ImportThread {
+            key: key.to_string(),
+            url: thread.child_text("link").map(str::to_string),
+            title: thread.child_text("title").map(str::to_string),
+        });
+    }
+
+    for post in root.children_named("post") {
+        let key = post.attr("id").context("Disqus post without dsq:id")?;
+        let thread_key = post
+            .child("thread")
+            .and_then(|t| t.attr("id"))
+            .with_context(|| format!("Disqus post {} has no thread", key))?;
+
+        let state = if post.child_text("isSpam") == Some("true") {
+            ImportState::Spam
+        } else if post.child_text("isDeleted") == Some("true") {
+            ImportState::Deleted
+        } else {
+            ImportState::Approved
+        };
+
+        let author = post.child("author");
+        let created_at = post
+            .child_text("createdAt")
+            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+            .map(|d| d.with_timezone(&Utc))
+            .with_context(|| format!("Disqus post {} has no valid createdAt", key))?;
+
+        data.comments.push(ImportComment {
+            key: key.to_string(),
+            thread_key: thread_key.to_string(),
+            parent_key: post.child("parent").and_then(|p| p.attr("id")).map(str::to_string),
+            author_name: author.and_then(|a| a.child_text("name")).map(str::to_string),
+            author_email: author.and_then(|a| a.child_text("email")).map(str::to_string),
+            content: post.child_text("message").unwrap_or_default().to_string(),
+            format: ContentFormat::Html,
+            created_at,
+            state,
+        });
+    }
+
+    Ok(data)
+}
@@ -0,0 +1,74 @@
+//! Isso JSON dump of the `threads` and `comments` tables
+//! (`{"threads": [...], "comments": [...]}`)
+
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use serde::Deserialize;
+
+use super::{ContentFormat, ImportComment, ImportData, ImportState, ImportThread};
+
+#[derive(Deserialize)]
+struct Export {
+    threads: Vec<Thread>,
+    comments: Vec<Comment>,
+}
+
+#[derive(Deserialize)]
+struct Thread {
+    id: i64,
+    uri: String,
+    title: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Comment {
+    id: i64,
+    tid: i64,
+    parent: Option<i64>,
+    /// Unix timestamp with fractional seconds
+    created: f64,
+    /// 1 = accepted, 2 = in moderation queue, 4 = deleted (kept for its replies)
+    mode: i64,
+    text: String,
+    author: Option<String>,
+    email: Option<String>,
+}
+
+pub(super) fn parse(input: &str) -> Result<ImportData> {
+    let export: Export = serde_json::from_str(input).context("Not an Isso export")?;
+
+    let mut data = ImportData::default();
+
+    for thread in export.threads {
+        data.threads.push(ImportThread {
+            key: thread.id.to_string(),
+            url: Some(thread.uri).filter(|u| !u.is_empty()),
+            title: thread.title,
+        });
+    }
+
+    for comment in export.comments {
+        let state = match comment.mode {
+            1 => ImportState::Approved,
+            2 => ImportState::Pending,
+            _ => ImportState::Deleted,
+        };
+
+        let created_at = DateTime::from_timestamp_millis((comment.created * 1000.0) as i64)
+            .with_context(|| format!("Isso comment {} has an invalid timestamp", comment.id))?;
+
+        data.comments.push(ImportComment {
+            key: comment.id.to_string(),
+            thread_key: comment.tid.to_string(),
+            parent_key: comment.parent.map(|p| p.to_string()),
+            author_name: comment.author.filter(|a| !a.is_empty()),
+            author_email: comment.email.filter(|e| !e.is_empty()),
+            content: comment.text,
+            format: ContentFormat::Markdown,
+            created_at,
+            state,
+        });
+    }
+
+    Ok(data)
+}
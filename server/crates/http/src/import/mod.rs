@@ -0,0 +1,1217 @@
+//! Comment import from other commenting systems.
+//!
+//! Exports are parsed into a common [`ImportData`] model, planned into page trees
+//! (pure, see [`plan_import`]) and then written to Redis by [`run_import`].
+//!
+//! Imports are idempotent: comment IDs are derived from the site, format and the
+//! source system's comment ID, so re-running an import skips threads already present.
+//! Placeholder users for unknown authors are derived the same way from their email.
+
+mod commento;
+mod disqus;
+mod isso;
+mod wordpress;
+mod xml;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use threadkit_common::{
+    action_log::{ActionLogBuilder, ActionLogger, ActionType},
+    markdown::{render_markdown, sanitize_html},
+    normalize_username,
+    redis::RedisClient,
+    types::{
+        AuthProvider, CommentStatus, PageTree, SiteConfig, SocialLinks, TreeComment, User,
+        ANONYMOUS_USER_ID,
+    },
+    Storage,
+};
+
+// ============================================================================
+// Import Model
+// ============================================================================
+
+/// Supported export formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// Disqus XML export
+    Disqus,
+    /// WordPress WXR export
+    Wordpress,
+    /// Commento JSON export
+    Commento,
+    /// Isso JSON dump
+    Isso,
+}
+
+impl ImportFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportFormat::Disqus => "disqus",
+            ImportFormat::Wordpress => "wordpress",
+            ImportFormat::Commento => "commento",
+            ImportFormat::Isso => "isso",
+        }
+    }
+
+    /// Parse an export file
+    pub fn parse(&self, input: &str) -> Result<ImportData> {
+        match self {
+            ImportFormat::Disqus => disqus::parse(input),
+            ImportFormat::Wordpress => wordpress::parse(input),
+            ImportFormat::Commento => commento::parse(input),
+            ImportFormat::Isso => isso::parse(input),
+        }
+    }
+}
+
+impl FromStr for ImportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "disqus" => Ok(ImportFormat::Disqus),
+            "wordpress" | "wxr" => Ok(ImportFormat::Wordpress),
+            "commento" => Ok(ImportFormat::Commento),
+            "isso" => Ok(ImportFormat::Isso),
+            _ => Err(format!(
+                "Unknown import format '{}' (expected disqus, wordpress, commento or isso)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ImportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Threads and comments parsed from an export
+#[derive(Debug, Default)]
+pub struct ImportData {
+    pub threads: Vec<ImportThread>,
+    pub comments: Vec<ImportComment>,
+    /// Entries that aren't comments (pingbacks, trackbacks)
+    pub skipped: usize,
+}
+
+/// A page in the source system
+#[derive(Debug, Clone)]
+pub struct ImportThread {
+    /// Thread ID in the source system
+    pub key: String,
+    pub url: Option<String>,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentFormat {
+    Html,
+    Markdown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportState {
+    Approved,
+    Pending,
+    Deleted,
+    Spam,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportComment {
+    /// Comment ID in the source system
+    pub key: String,
+    pub thread_key: String,
+    pub parent_key: Option<String>,
+    pub author_name: Option<String>,
+    pub author_email: Option<String>,
+    pub content: String,
+    pub format: ContentFormat,
+    pub created_at: DateTime<Utc>,
+    pub state: ImportState,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    /// Use the full thread URL as page_url instead of just its path.
+    /// Must match what the embed passes as page_url (the default templates use the path).
+    pub keep_full_urls: bool,
+    /// Only count what would be imported
+    pub dry_run: bool,
+    /// Also give comments to accounts whose email matches but was never verified.
+    /// Anyone can sign up with an address they don't own, so each such match is
+    /// written to the action log.
+    pub match_unverified_emails: bool,
+}
+
+/// A thread whose comments can't be imported because it has no usable URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnmappedThread {
+    pub key: String,
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub comments: usize,
+}
+
+/// Counts for an import run
+#[derive(Debug, Clone, Default)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    /// Threads in the export
+    pub threads: usize,
+    /// Pages with at least one imported comment
+    pub pages: usize,
+    /// Comments imported (excluding deleted placeholders)
+    pub comments: usize,
+    /// Of which replies
+    pub replies: usize,
+    /// Of which pending moderation
+    pub pending: usize,
+    /// Deleted comments kept as "[deleted]" so their replies stay threaded
+    pub deleted_placeholders: usize,
+    pub skipped_spam: usize,
+    pub skipped_deleted: usize,
+    /// Pingbacks, trackbacks and comments whose parent chain never reaches a root
+    pub skipped_other: usize,
+    /// Replies imported as root comments because their parent was missing or skipped
+    pub orphaned_replies: usize,
+    /// Comments skipped because a previous run already imported them
+    pub already_imported: usize,
+    pub users_matched: usize,
+    /// Of which accounts with an unverified email (`match_unverified_emails`)
+    pub unverified_matched: usize,
+    pub users_created: usize,
+    pub anonymous_comments: usize,
+    /// Comments not imported because their thread couldn't be mapped to a page
+    pub unmapped_comments: usize,
+    pub unmapped_threads: Vec<UnmappedThread>,
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.dry_run {
+            writeln!(f, "Dry run - nothing was written")?;
+        }
+        writeln!(f, "Threads:              {}", self.threads)?;
+        writeln!(f, "Pages:                {}", self.pages)?;
+        writeln!(f, "Comments:             {}", self.comments)?;
+        writeln!(f, "  Replies:            {}", self.replies)?;
+        writeln!(f, "  Pending:            {}", self.pending)?;
+        writeln!(f, "  Anonymous:          {}", self.anonymous_comments)?;
+        writeln!(f, "  Orphaned replies:   {}", self.orphaned_replies)?;
+        writeln!(f, "Deleted placeholders: {}", self.deleted_placeholders)?;
+        writeln!(f, "Already imported:     {}", self.already_imported)?;
+        writeln!(f, "Skipped spam:         {}", self.skipped_spam)?;
+        writeln!(f, "Skipped deleted:      {}", self.skipped_deleted)?;
+        writeln!(f, "Skipped other:        {}", self.skipped_other)?;
+        writeln!(f, "Users matched:        {}", self.users_matched)?;
+        writeln!(f, "  Unverified email:   {}", self.unverified_matched)?;
+        writeln!(f, "Users created:        {}", self.users_created)?;
+        writeln!(f, "Unmapped comments:    {}", self.unmapped_comments)?;
+
+        if !self.unmapped_threads.is_empty() {
+            writeln!(f, "\nUnmapped threads:")?;
+            for thread in &self.unmapped_threads {
+                writeln!(
+                    f,
+                    "  {} ({} comments) title={:?} url={:?}",
+                    thread.key, thread.comments, thread.title, thread.url
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Planning
+// ============================================================================
+
+/// Comments for one page, ready to be written
+#[derive(Debug)]
+pub struct PlannedPage {
+    pub page_url: String,
+    pub comments: Vec<PlannedComment>,
+}
+
+#[derive(Debug)]
+pub struct PlannedComment {
+    pub id: Uuid,
+    /// Index into `ImportData::comments`
+    pub source: usize,
+    /// Deleted comment kept only for its replies
+    pub placeholder: bool,
+    pub replies: Vec<PlannedComment>,
+}
+
+impl PlannedComment {
+    fn visit<'a>(&'a self, f: &mut impl FnMut(&'a PlannedComment)) {
+        f(self);
+        for reply in &self.replies {
+            reply.visit(f);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ImportPlan {
+    pub pages: Vec<PlannedPage>,
+    pub report: ImportReport,
+}
+
+/// Derive a stable comment ID so re-running an import finds the comments it wrote
+pub fn import_comment_id(site_id: Uuid, format: ImportFormat, key: &str) -> Uuid {
+    let hash = Sha256::digest(format!("{}:{}:{}", site_id, format, key).as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    uuid::Builder::from_custom_bytes(bytes).into_uuid()
+}
+
+/// Deterministic ID for the placeholder user of an imported author, so re-runs
+/// reuse it even when the email can't be used to find it again
+fn import_author_id(site_id: Uuid, format: ImportFormat, email: &str) -> Uuid {
+    import_comment_id(site_id, format, &format!("author:{}", email))
+}
+
+/// Map a thread URL to the page_url the embed uses for that page
+fn map_page_url(url: &str, site_domain: &str, keep_full_urls: bool) -> Option<String> {
+    let url = url.trim();
+
+    if url.starts_with('/') {
+        return Some(if keep_full_urls {
+            format!("https://{}{}", site_domain, url)
+        } else {
+            url.to_string()
+        });
+    }
+
+    let parsed = url::Url::parse(url).ok()?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return None;
+    }
+    if keep_full_urls {
+        Some(url.to_string())
+    } else {
+        Some(parsed.path().to_string())
+    }
+}
+
+/// Group comments into pages and rebuild reply trees
+pub fn plan_import(
+    site: &SiteConfig,
+    data: &ImportData,
+    format: ImportFormat,
+    options: &ImportOptions,
+) -> ImportPlan {
+    let mut report = ImportReport {
+        dry_run: options.dry_run,
+        threads: data.threads.len(),
+        skipped_other: data.skipped,
+        ..Default::default()
+    };
+
+    // thread key -> page_url
+    let mut page_urls: HashMap<&str, String> = HashMap::new();
+    let mut unmapped: HashMap<&str, UnmappedThread> = HashMap::new();
+    for thread in &data.threads {
+        match thread
+            .url
+            .as_deref()
+            .and_then(|url| map_page_url(url, &site.domain, options.keep_full_urls))
+        {
+            Some(page_url) => {
+                page_urls.insert(&thread.key, page_url);
+            }
+            None => {
+                unmapped.insert(
+                    &thread.key,
+                    UnmappedThread {
+                        key: thread.key.clone(),
+                        title: thread.title.clone(),
+                        url: thread.url.clone(),
+                        comments: 0,
+                    },
+                );
+            }
+        }
+    }
+
+    // Several threads can map to the same page (e.g. http and https variants)
+    let mut by_page: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, comment) in data.comments.iter().enumerate() {
+        match page_urls.get(comment.thread_key.as_str()) {
+            Some(page_url) => by_page.entry(page_url.as_str()).or_default().push(i),
+            None => {
+                report.unmapped_comments += 1;
+                unmapped
+                    .entry(&comment.thread_key)
+                    .or_insert_with(|| UnmappedThread {
+                        key: comment.thread_key.clone(),
+                        title: None,
+                        url: None,
+                        comments: 0,
+                    })
+                    .comments += 1;
+            }
+        }
+    }
+
+    let mut unmapped_threads: Vec<UnmappedThread> =
+        unmapped.into_values().filter(|t| t.comments > 0).collect();
+    unmapped_threads.sort_by(|a, b| a.key.cmp(&b.key));
+    report.unmapped_threads = unmapped_threads;
+
+    let mut page_list: Vec<(&str, Vec<usize>)> = by_page.into_iter().collect();
+    page_list.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut pages = Vec::new();
+    for (page_url, indices) in page_list {
+        let comments = plan_page(site.id, data, format, &indices, &mut report);
+        if comments.is_empty() {
+            continue;
+        }
+
+        for root in &comments {
+            root.visit(&mut |c| {
+                if c.placeholder {
+                    report.deleted_placeholders += 1;
+                    return;
+                }
+                report.comments += 1;
+                if data.comments[c.source].state == ImportState::Pending {
+                    report.pending += 1;
+                }
+            });
+            report.replies += root_reply_count(root);
+        }
+
+        pages.push(PlannedPage {
+            page_url: page_url.to_string(),
+            comments,
+        });
+    }
+    report.pages = pages.len();
+
+    ImportPlan { pages, report }
+}
+
+/// Non-placeholder comments below a root
+fn root_reply_count(root: &PlannedComment) -> usize {
+    let mut count = 0;
+    for reply in &root.replies {
+        reply.visit(&mut |c| {
+            if !c.placeholder {
+                count += 1;
+            }
+        });
+    }
+    count
+}
+
+fn plan_page(
+    site_id: Uuid,
+    data: &ImportData,
+    format: ImportFormat,
+    indices: &[usize],
+    report: &mut ImportReport,
+) -> Vec<PlannedComment> {
+    let by_key: HashMap<&str, usize> = indices
+        .iter()
+        .map(|&i| (data.comments[i].key.as_str(), i))
+        .collect();
+
+    let mut roots = Vec::new();
+    let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+    for &i in indices {
+        match data.comments[i]
+            .parent_key
+            .as_deref()
+            .and_then(|p| by_key.get(p))
+        {
+            Some(&parent) => children.entry(parent).or_default().push(i),
+            None => roots.push(i),
+        }
+    }
+
+    let mut visited = HashSet::new();
+    let mut planned = Vec::new();
+    for root in sorted(data, roots) {
+        planned.extend(plan_comment(site_id, data, format, root, &children, &mut visited, report));
+    }
+
+    // Anything not reached sits in a parent cycle
+    report.skipped_other += indices.len() - visited.len();
+
+    // Replies whose parent was missing or skipped end up as roots
+    report.orphaned_replies += planned
+        .iter()
+        .filter(|c| data.comments[c.source].parent_key.is_some())
+        .count();
+
+    planned.sort_by_key(|c| data.comments[c.source].created_at);
+    planned
+}
+
+/// Plan a comment and its replies. Returns the nodes that take its place:
+/// itself, nothing, or - for skipped spam - its replies, moved up a level.
+fn plan_comment(
+    site_id: Uuid,
+    data: &ImportData,
+    format: ImportFormat,
+    index: usize,
+    children: &HashMap<usize, Vec<usize>>,
+    visited: &mut HashSet<usize>,
+    report: &mut ImportReport,
+) -> Vec<PlannedComment> {
+    visited.insert(index);
+
+    let mut replies = Vec::new();
+    if let Some(child_indices) = children.get(&index) {
+        for &child in child_indices {
+            if !visited.contains(&child) {
+                replies.extend(plan_comment(site_id, data, format, child, children, visited, report));
+            }
+        }
+    }
+    replies.sort_by_key(|c| data.comments[c.source].created_at);
+
+    let comment = &data.comments[index];
+    let planned = |placeholder, replies| PlannedComment {
+        id: import_comment_id(site_id, format, &comment.key),
+        source: index,
+        placeholder,
+        replies,
+    };
+
+    match comment.state {
+        ImportState::Approved | ImportState::Pending => vec![planned(false, replies)],
+        ImportState::Deleted if !replies.is_empty() => vec![planned(true, replies)],
+        ImportState::Deleted => {
+            report.skipped_deleted += 1;
+            vec![]
+        }
+        ImportState::Spam => {
+            report.skipped_spam += 1;
+            replies
+        }
+    }
+}
+
+fn sorted(data: &ImportData, mut indices: Vec<usize>) -> Vec<usize> {
+    indices.sort_by_key(|&i| data.comments[i].created_at);
+    indices
+}
+
+// ============================================================================
+// Apply
+// ============================================================================
+
+/// Resolved author for a comment
+#[derive(Clone)]
+struct Author {
+    /// None for anonymous comments
+    user_id: Option<Uuid>,
+    name: String,
+    avatar: Option<String>,
+    karma: i64,
+}
+
+/// Parameters shared by every step of one import run
+struct ImportContext<'a> {
+    store: &'a dyn Storage,
+    site_id: Uuid,
+    format: ImportFormat,
+    options: &'a ImportOptions,
+    action_logger: &'a ActionLogger,
+}
+
+/// Plan and write an import. With `dry_run` only reads are made.
+pub async fn run_import(
+    redis: &RedisClient,
+    store: &dyn Storage,
+    site: &SiteConfig,
+    data: &ImportData,
+    format: ImportFormat,
+    options: &ImportOptions,
+    action_logger: &ActionLogger,
+) -> Result<ImportReport> {
+    let ImportPlan { pages, mut report } = plan_import(site, data, format, options);
+
+    let ctx = ImportContext { store, site_id: site.id, format, options, action_logger };
+    let mut authors: HashMap<String, Author> = HashMap::new();
+
+    for page in &pages {
+        let page_id = RedisClient::generate_page_id(site.id, &page.page_url);
+
+        // Resolve authors before touching the tree
+        let mut comment_authors: HashMap<usize, Author> = HashMap::new();
+        for root in &page.comments {
+            let mut sources = Vec::new();
+            root.visit(&mut |c| {
+                if !c.placeholder {
+                    sources.push(c.source);
+                }
+            });
+            for source in sources {
+                let author =
+                    resolve_author(&ctx, &data.comments[source], &mut authors, &mut report).await?;
+                comment_authors.insert(source, author);
+            }
+        }
+
+        let roots: Vec<TreeComment> = page
+            .comments
+            .iter()
+            .map(|c| build_tree_comment(c, data, &comment_authors, site, None))
+            .collect();
+
+        let added: HashSet<Uuid> = if options.dry_run {
+            let mut existing = store.get_page_tree(page_id).await?.unwrap_or_default();
+            merge_into_tree(&mut existing, &roots)
+        } else {
+            let (_, added) = store
+                .update_page_tree(page_id, |tree| {
+                    Ok::<_, std::convert::Infallible>(merge_into_tree(tree, &roots))
+                })
+                .await?
+                .unwrap_or_else(|e| match e {});
+            added
+        };
+
+        let mut written = Vec::new();
+        for root in &page.comments {
+            root.visit(&mut |c| {
+                if c.placeholder {
+                    return;
+                }
+                if added.contains(&c.id) {
+                    written.push(c);
+                } else {
+                    report.already_imported += 1;
+                }
+            });
+        }
+
+        if options.dry_run {
+            continue;
+        }
+
+        redis.set_page_url(site.id, page_id, &page.page_url).await?;
+
+        for c in written {
+            let source = &data.comments[c.source];
+            let user_id = comment_authors[&c.source].user_id;
+
+            redis
+                .add_imported_comment_indexes(site.id, user_id, page_id, c.id, source.created_at.timestamp())
+                .await?;
+            if source.state == ImportState::Pending {
+                redis.add_to_modqueue(site.id, page_id, c.id).await?;
+            }
+            if let Some(user_id) = user_id {
+                store.increment_user_comment_count(user_id).await?;
+            }
+        }
+    }
+
+    // Counts above include comments skipped as already imported
+    report.comments -= report.already_imported;
+
+    Ok(report)
+}
+
+/// Merge imported comments into a page tree by ID, returning the IDs added
+///
+/// Comments already in the tree are left as they are, but new replies below them are
+/// added, so importing a newer export picks up replies to threads imported before.
+fn merge_into_tree(tree: &mut PageTree, roots: &[TreeComment]) -> HashSet<Uuid> {
+    let mut existing = HashSet::new();
+    for root in &tree.comments {
+        collect_ids(root, &mut existing);
+    }
+
+    let mut added = HashSet::new();
+    merge_comments(&mut tree.comments, roots, &existing, &mut added);
+    if !added.is_empty() {
+        tree.updated_at = Utc::now().timestamp();
+    }
+    added
+}
+
+fn merge_comments(
+    siblings: &mut Vec<TreeComment>,
+    incoming: &[TreeComment],
+    existing: &HashSet<Uuid>,
+    added: &mut HashSet<Uuid>,
+) {
+    for comment in incoming {
+        if let Some(current) = siblings.iter_mut().find(|c| c.id == comment.id) {
+            merge_comments(&mut current.replies, &comment.replies, existing, added);
+        } else if !existing.contains(&comment.id) {
+            // Drop anything already elsewhere in the tree, e.g. a reply first imported
+            // as a root while its parent was missing
+            let mut comment = comment.clone();
+            remove_existing(&mut comment, existing);
+            collect_ids(&comment, added);
+            siblings.push(comment);
+        }
+    }
+}
+
+fn remove_existing(comment: &mut TreeComment, existing: &HashSet<Uuid>) {
+    comment.replies.retain(|r| !existing.contains(&r.id));
+    for reply in &mut comment.replies {
+        remove_existing(reply, existing);
+    }
+}
+
+fn collect_ids(comment: &TreeComment, ids: &mut HashSet<Uuid>) {
+    ids.insert(comment.id);
+    for reply in &comment.replies {
+        collect_ids(reply, ids);
+    }
+}
+
+/// Match a comment's author to a user by email, creating a placeholder user if needed
+///
+/// Only accounts that proved they own the email (and placeholders from earlier
+/// imports) are matched, so signing up with someone else's address doesn't hand
+/// over their imported comments.
+async fn resolve_author(
+    ctx: &ImportContext<'_>,
+    comment: &ImportComment,
+    authors: &mut HashMap<String, Author>,
+    report: &mut ImportReport,
+) -> Result<Author> {
+    let ImportContext { store, site_id, format, options, action_logger } = *ctx;
+    let Some(email) = comment.author_email.as_deref().map(|e| e.trim().to_lowercase()) else {
+        report.anonymous_comments += 1;
+        return Ok(Author {
+            user_id: None,
+            name: comment.author_name.clone().unwrap_or_else(|| "Anonymous".to_string()),
+            avatar: None,
+            karma: 0,
+        });
+    };
+
+    if let Some(author) = authors.get(&email) {
+        return Ok(author.clone());
+    }
+
+    let existing = match store.get_user_by_email(&email).await? {
+        Some(user_id) => store.get_user(user_id).await?,
+        None => None,
+    };
+    let email_taken = existing.is_some();
+
+    let matched = match existing {
+        Some(user) if user.email_verified || is_import_placeholder(&user) => Some(user),
+        Some(user) if options.match_unverified_emails => {
+            report.unverified_matched += 1;
+            if !options.dry_run {
+                action_logger.log(
+                    ActionLogBuilder::new(ActionType::ImportUnverifiedMatch, site_id)
+                        .user_id(user.id)
+                        .user_email(email.clone())
+                        .build(),
+                );
+            }
+            Some(user)
+        }
+        _ => None,
+    };
+
+    // Placeholders left without the email are found again by their ID
+    let user_id = import_author_id(site_id, format, &email);
+    let matched = match matched {
+        Some(user) => Some(user),
+        None => store.get_user(user_id).await?,
+    };
+
+    let author = match matched {
+        Some(user) => {
+            report.users_matched += 1;
+            Author {
+                user_id: Some(user.id),
+                name: user.name,
+                avatar: user.avatar_url,
+                karma: user.karma,
+            }
+        }
+        None => {
+            report.users_created += 1;
+            let name = comment
+                .author_name
+                .as_deref()
+                .unwrap_or_else(|| email.split('@').next().unwrap_or_default());
+            // An unverified account already holds the email, so this placeholder
+            // can't be claimed through it
+            let email = (!email_taken).then_some(email.as_str());
+            let name = if options.dry_run {
+                normalize_username(name)
+            } else {
+                create_placeholder_user(store, user_id, name, email).await?
+            };
+            Author {
+                user_id: Some(user_id),
+                name,
+                avatar: None,
+                karma: 0,
+            }
+        }
+    };
+
+    authors.insert(email, author.clone());
+    Ok(author)
+}
+
+/// Whether a user is a placeholder created by an earlier import (nobody has
+/// logged into it yet: logging in by email verifies it)
+fn is_import_placeholder(user: &User) -> bool {
+    user.provider == AuthProvider::Email && !user.email_verified && !user.username_set
+}
+
+/// Create an unverified user for an imported author. Signing up with the same
+/// email later logs into this account, which then owns the imported comments.
+/// Without an email the placeholder only keeps the author's comments together.
+async fn create_placeholder_user(
+    store: &dyn Storage,
+    user_id: Uuid,
+    name: &str,
+    email: Option<&str>,
+) -> Result<String> {
+    let normalized_name = normalize_username(name);
+    let name = if normalized_name.is_empty() {
+        format!("user-{}", &user_id.to_string()[..8])
+    } else if store.is_username_available(&normalized_name, None).await? {
+        normalized_name
+    } else {
+        format!("{}-{}", normalized_name, &user_id.to_string()[..8])
+    };
+
+    let user = User {
+        id: user_id,
+        name: name.clone(),
+        email: email.map(str::to_string),
+        avatar_url: None,
+        provider: AuthProvider::Email,
+        provider_id: None,
+        email_verified: false,
+        karma: 0,
+        global_banned: false,
+        shadow_banned: false,
+        created_at: Utc::now(),
+        username_set: false,
+        social_links: SocialLinks::default(),
+        total_comments: 0,
+        email_notifications: Default::default(),
+    };
+
+    store.set_user(&user).await?;
+    store.set_user_username_index(&name, user_id).await?;
+    if let Some(email) = email {
+        store.set_user_email_index(email, user_id).await?;
+    }
+
+    Ok(name)
+}
+
+fn build_tree_comment(
+    planned: &PlannedComment,
+    data: &ImportData,
+    authors: &HashMap<usize, Author>,
+    site: &SiteConfig,
+    parent_id: Option<Uuid>,
+) -> TreeComment {
+    let source = &data.comments[planned.source];
+    let created_at = source.created_at.timestamp();
+
+    let replies = planned
+        .replies
+        .iter()
+        .map(|r| build_tree_comment(r, data, authors, site, Some(planned.id)))
+        .collect();
+
+    let (text, html) = match source.format {
+        ContentFormat::Html => {
+            let html = sanitize_html(&source.content, &site.settings.display);
+            (html_to_text(&html), html)
+        }
+        ContentFormat::Markdown => (
+            source.content.clone(),
+            render_markdown(&source.content, &site.settings.display),
+        ),
+    };
+
+    let mut comment = TreeComment {
+        id: planned.id,
+        author_id: ANONYMOUS_USER_ID,
+        name: String::new(),
+        avatar: None,
+        karma: 0,
+        text,
+        html,
+        upvotes: 0,
+        downvotes: 0,
+        reactions: Default::default(),
+        created_at,
+        modified_at: created_at,
+        edited: false,
+        replies,
+        status: None,
+        parent_id,
+        more_replies: None,
+    };
+
+    if planned.placeholder {
+        comment.mark_deleted();
+        return comment;
+    }
+
+    let author = &authors[&planned.source];
+    comment.author_id = author.user_id.unwrap_or(ANONYMOUS_USER_ID);
+    comment.name = author.name.clone();
+    comment.avatar = author.avatar.clone();
+    comment.karma = author.karma;
+    if source.state == ImportState::Pending {
+        comment.status = Some(CommentStatus::Pending);
+    }
+    comment
+}
+
+/// Plain-text version of imported HTML, used as the comment's source text
+fn html_to_text(html: &str) -> String {
+    let html = html
+        .replace("<br>", "\n")
+        .replace("<br />", "\n")
+        .replace("</p>", "\n\n");
+
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use threadkit_common::types::SiteSettings;
+
+    fn site() -> SiteConfig {
+        SiteConfig {
+            id: Uuid::nil(),
+            name: "Test".to_string(),
+            domain: "example.com".to_string(),
+            project_id_public: "tk_pub_test".to_string(),
+            project_id_secret: "tk_sec_test".to_string(),
+            settings: SiteSettings::default(),
+            plan: Default::default(),
+        }
+    }
+
+    const DISQUS: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<disqus xmlns="http://disqus.com" xmlns:dsq="http://disqus.com/disqus-internals">
+  <category dsq:id="1"><title>General</title></category>
+  <thread dsq:id="100">
+    <link>https://example.com/blog/hello?utm=x</link>
+    <title>Hello</title>
+  </thread>
+  <thread dsq:id="200">
+    <link></link>
+    <title>Lost page</title>
+  </thread>
+  <post dsq:id="1">
+    <message><![CDATA[<p>First &amp; best</p>]]></message>
+    <createdAt>2019-01-01T10:00:00Z</createdAt>
+    <isDeleted>false</isDeleted>
+    <isSpam>false</isSpam>
+    <author><email>alice@example.com</email><name>Alice</name></author>
+    <thread dsq:id="100" />
+  </post>
+  <post dsq:id="2">
+    <message><![CDATA[<p>Reply</p>]]></message>
+    <createdAt>2019-01-02T10:00:00Z</createdAt>
+    <isDeleted>false</isDeleted>
+    <isSpam>false</isSpam>
+    <author><name>Guest</name></author>
+    <thread dsq:id="100" />
+    <parent dsq:id="1" />
+  </post>
+  <post dsq:id="3">
+    <message><![CDATA[<p>Buy now</p>]]></message>
+    <createdAt>2019-01-03T10:00:00Z</createdAt>
+    <isDeleted>false</isDeleted>
+    <isSpam>true</isSpam>
+    <author><name>Spammer</name></author>
+    <thread dsq:id="100" />
+  </post>
+  <post dsq:id="4">
+    <message><![CDATA[<p>Nobody sees this</p>]]></message>
+    <createdAt>2019-01-04T10:00:00Z</createdAt>
+    <isDeleted>false</isDeleted>
+    <isSpam>false</isSpam>
+    <author><name>Bob</name></author>
+    <thread dsq:id="200" />
+  </post>
+</disqus>"#;
+
+    #[test]
+    fn test_parse_disqus() {
+        let data = ImportFormat::Disqus.parse(DISQUS).unwrap();
+        assert_eq!(data.threads.len(), 2);
+        assert_eq!(data.comments.len(), 4);
+
+        let first = &data.comments[0];
+        assert_eq!(first.key, "1");
+        assert_eq!(first.thread_key, "100");
+        assert_eq!(first.content, "<p>First &amp; best</p>");
+        assert_eq!(first.author_email.as_deref(), Some("alice@example.com"));
+        assert_eq!(first.created_at.to_rfc3339(), "2019-01-01T10:00:00+00:00");
+
+        assert_eq!(data.comments[1].parent_key.as_deref(), Some("1"));
+        assert_eq!(data.comments[1].author_email, None);
+        assert_eq!(data.comments[2].state, ImportState::Spam);
+    }
+
+    #[test]
+    fn test_plan_disqus() {
+        let data = ImportFormat::Disqus.parse(DISQUS).unwrap();
+        let plan = plan_import(&site(), &data, ImportFormat::Disqus, &ImportOptions::default());
+
+        assert_eq!(plan.pages.len(), 1);
+        // Query string is dropped, page_url is the path
+        assert_eq!(plan.pages[0].page_url, "/blog/hello");
+        assert_eq!(plan.pages[0].comments.len(), 1);
+        assert_eq!(plan.pages[0].comments[0].replies.len(), 1);
+
+        let report = &plan.report;
+        assert_eq!(report.comments, 2);
+        assert_eq!(report.replies, 1);
+        assert_eq!(report.skipped_spam, 1);
+        assert_eq!(report.unmapped_comments, 1);
+        assert_eq!(
+            report.unmapped_threads,
+            vec![UnmappedThread {
+                key: "200".to_string(),
+                title: Some("Lost page".to_string()),
+                url: None,
+                comments: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_plan_keep_full_urls() {
+        let data = ImportFormat::Disqus.parse(DISQUS).unwrap();
+        let options = ImportOptions {
+            keep_full_urls: true,
+            ..Default::default()
+        };
+        let plan = plan_import(&site(), &data, ImportFormat::Disqus, &options);
+        assert_eq!(plan.pages[0].page_url, "https://example.com/blog/hello?utm=x");
+    }
+
+    #[test]
+    fn test_parse_wordpress() {
+        let wxr = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:wp="http://wordpress.org/export/1.2/" xmlns:content="http://purl.org/rss/1.0/modules/content/">
+<channel>
+  <item>
+    <title>Post</title>
+    <link>https://blog.example.com/2020/post/</link>
+    <wp:post_id>7</wp:post_id>
+    <wp:comment>
+      <wp:comment_id>11</wp:comment_id>
+      <wp:comment_author><![CDATA[Carol]]></wp:comment_author>
+      <wp:comment_author_email><![CDATA[carol@example.com]]></wp:comment_author_email>
+      <wp:comment_date>2020-05-01 12:00:00</wp:comment_date>
+      <wp:comment_date_gmt>2020-05-01 10:00:00</wp:comment_date_gmt>
+      <wp:comment_content><![CDATA[Line one
+line two
+
+Second paragraph]]></wp:comment_content>
+      <wp:comment_approved><![CDATA[1]]></wp:comment_approved>
+      <wp:comment_type><![CDATA[comment]]></wp:comment_type>
+      <wp:comment_parent>0</wp:comment_parent>
+    </wp:comment>
+    <wp:comment>
+      <wp:comment_id>12</wp:comment_id>
+      <wp:comment_author><![CDATA[Dave]]></wp:comment_author>
+      <wp:comment_date_gmt>0000-00-00 00:00:00</wp:comment_date_gmt>
+      <wp:comment_date>2020-05-02 08:00:00</wp:comment_date>
+      <wp:comment_content><![CDATA[Agreed]]></wp:comment_content>
+      <wp:comment_approved><![CDATA[0]]></wp:comment_approved>
+      <wp:comment_parent>11</wp:comment_parent>
+    </wp:comment>
+    <wp:comment>
+      <wp:comment_id>13</wp:comment_id>
+      <wp:comment_date_gmt>2020-05-03 08:00:00</wp:comment_date_gmt>
+      <wp:comment_content><![CDATA[Linked]]></wp:comment_content>
+      <wp:comment_approved><![CDATA[1]]></wp:comment_approved>
+      <wp:comment_type><![CDATA[pingback]]></wp:comment_type>
+      <wp:comment_parent>0</wp:comment_parent>
+    </wp:comment>
+  </item>
+</channel>
+</rss>"#;
+
+        let data = ImportFormat::Wordpress.parse(wxr).unwrap();
+        assert_eq!(data.threads.len(), 1);
+        assert_eq!(data.comments.len(), 2);
+        assert_eq!(data.skipped, 1);
+
+        let first = &data.comments[0];
+        assert_eq!(first.created_at.to_rfc3339(), "2020-05-01T10:00:00+00:00");
+        assert_eq!(first.content, "<p>Line one<br>\nline two</p>\n<p>Second paragraph</p>");
+        assert_eq!(first.parent_key, None);
+
+        // Zeroed GMT date falls back to the local date
+        let second = &data.comments[1];
+        assert_eq!(second.created_at.to_rfc3339(), "2020-05-02T08:00:00+00:00");
+        assert_eq!(second.state, ImportState::Pending);
+        assert_eq!(second.parent_key.as_deref(), Some("11"));
+    }
+
+    #[test]
+    fn test_parse_commento() {
+        let json = r#"{
+            "version": 1,
+            "comments": [
+                {"commentHex": "aa", "domain": "example.com", "path": "/post", "commenterHex": "c1",
+                 "markdown": "**hi**", "html": "", "parentHex": "root", "score": 0,
+                 "state": "approved", "creationDate": "2021-03-04T05:06:07Z", "deleted": false},
+                {"commentHex": "bb", "domain": "example.com", "path": "/post", "commenterHex": "anonymous",
+                 "markdown": "reply", "html": "", "parentHex": "aa", "score": 0,
+                 "state": "unapproved", "creationDate": "2021-03-05T05:06:07Z", "deleted": false}
+            ],
+            "commenters": [
+                {"commenterHex": "c1", "email": "erin@example.com", "name": "Erin", "link": "", "photo": "",
+                 "provider": "commento", "joinDate": "2021-01-01T00:00:00Z", "isModerator": false}
+            ]
+        }"#;
+
+        let data = ImportFormat::Commento.parse(json).unwrap();
+        assert_eq!(data.threads.len(), 1);
+        assert_eq!(data.threads[0].url.as_deref(), Some("https://example.com/post"));
+        assert_eq!(data.comments[0].author_email.as_deref(), Some("erin@example.com"));
+        assert_eq!(data.comments[0].parent_key, None);
+        assert_eq!(data.comments[1].author_email, None);
+        assert_eq!(data.comments[1].parent_key.as_deref(), Some("aa"));
+        assert_eq!(data.comments[1].state, ImportState::Pending);
+    }
+
+    #[test]
+    fn test_parse_isso() {
+        let json = r#"{
+            "threads": [{"id": 1, "uri": "/about/", "title": "About"}],
+            "comments": [
+                {"id": 1, "tid": 1, "parent": null, "created": 1600000000.5, "mode": 4,
+                 "text": "", "author": null, "email": null},
+                {"id": 2, "tid": 1, "parent": 1, "created": 1600000100.0, "mode": 1,
+                 "text": "Still here", "author": "Frank", "email": "frank@example.com"}
+            ]
+        }"#;
+
+        let data = ImportFormat::Isso.parse(json).unwrap();
+        assert_eq!(data.comments[0].state, ImportState::Deleted);
+        assert_eq!(data.comments[0].created_at.timestamp_millis(), 1600000000500);
+
+        // Deleted parent is kept as a placeholder for its live reply
+        let plan = plan_import(&site(), &data, ImportFormat::Isso, &ImportOptions::default());
+        let root = &plan.pages[0].comments[0];
+        assert!(root.placeholder);
+        assert_eq!(root.replies.len(), 1);
+        assert_eq!(plan.report.comments, 1);
+        assert_eq!(plan.report.deleted_placeholders, 1);
+        assert_eq!(plan.pages[0].page_url, "/about/");
+    }
+
+    #[test]
+    fn test_plan_reparents_spam_replies() {
+        let comment = |key: &str, parent: Option<&str>, state, minute: i64| ImportComment {
+            key: key.to_string(),
+            thread_key: "t".to_string(),
+            parent_key: parent.map(str::to_string),
+            author_name: None,
+            author_email: None,
+            content: key.to_string(),
+            format: ContentFormat::Markdown,
+            created_at: DateTime::from_timestamp(minute * 60, 0).unwrap(),
+            state,
+        };
+        let data = ImportData {
+            threads: vec![ImportThread {
+                key: "t".to_string(),
+                url: Some("/t".to_string()),
+                title: None,
+            }],
+            comments: vec![
+                comment("root", None, ImportState::Approved, 1),
+                comment("spam", Some("root"), ImportState::Spam, 2),
+                comment("under-spam", Some("spam"), ImportState::Approved, 3),
+                comment("spam-root", None, ImportState::Spam, 4),
+                comment("under-spam-root", Some("spam-root"), ImportState::Approved, 5),
+                comment("missing-parent", Some("gone"), ImportState::Approved, 6),
+                comment("cycle-a", Some("cycle-b"), ImportState::Approved, 7),
+                comment("cycle-b", Some("cycle-a"), ImportState::Approved, 8),
+            ],
+            skipped: 0,
+        };
+
+        let plan = plan_import(&site(), &data, ImportFormat::Isso, &ImportOptions::default());
+        let roots = &plan.pages[0].comments;
+        let keys: Vec<&str> = roots.iter().map(|c| data.comments[c.source].key.as_str()).collect();
+        assert_eq!(keys, vec!["root", "under-spam-root", "missing-parent"]);
+        assert_eq!(data.comments[roots[0].replies[0].source].key, "under-spam");
+
+        assert_eq!(plan.report.skipped_spam, 2);
+        assert_eq!(plan.report.orphaned_replies, 2);
+        assert_eq!(plan.report.skipped_other, 2);
+        assert_eq!(plan.report.comments, 4);
+    }
+
+    #[test]
+    fn test_import_comment_id_is_stable() {
+        let site_id = Uuid::now_v7();
+        assert_eq!(
+            import_comment_id(site_id, ImportFormat::Disqus, "1"),
+            import_comment_id(site_id, ImportFormat::Disqus, "1")
+        );
+        assert_ne!(
+            import_comment_id(site_id, ImportFormat::Disqus, "1"),
+            import_comment_id(site_id, ImportFormat::Wordpress, "1")
+        );
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        assert_eq!(
+            html_to_text("<p>One &amp; two<br>three</p><p><a href=\"x\">link</a></p>"),
+            "One & two\nthree\n\nlink"
+        );
+    }
+}
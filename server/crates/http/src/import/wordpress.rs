@@ -0,0 +1,92 @@
+//! WordPress WXR export (`<rss><channel><item>` with nested `<wp:comment>` elements)
+
+use anyhow::{bail, Context, Result};
+use chrono::{NaiveDateTime, Utc};
+
+use super::{xml, ContentFormat, ImportComment, ImportData, ImportState, ImportThread};
+
+const WP_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+pub(super) fn parse(input: &str) -> Result<ImportData> {
+    let root = xml::parse(input)?;
+    let channel = root
+        .child("channel")
+        .filter(|_| root.name == "rss")
+        .context("Not a WordPress export (expected <rss><channel>)")?;
+
+    let mut data = ImportData::default();
+
+    for item in channel.children_named("item") {
+        let Some(key) = item.child_text("post_id") else {
+            continue;
+        };
+
+        data.threads.push(ImportThread {
+            key: key.to_string(),
+            url: item.child_text("link").map(str::to_string),
+            title: item.child_text("title").map(str::to_string),
+        });
+
+        for comment in item.children_named("comment") {
+            // Pingbacks and trackbacks aren't comments
+            if matches!(comment.child_text("comment_type"), Some("pingback" | "trackback")) {
+                data.skipped += 1;
+                continue;
+            }
+
+            let comment_key = comment
+                .child_text("comment_id")
+                .context("WordPress comment without comment_id")?;
+
+            let state = match comment.child_text("comment_approved") {
+                Some("1") => ImportState::Approved,
+                Some("spam") => ImportState::Spam,
+                Some("trash") => ImportState::Deleted,
+                _ => ImportState::Pending,
+            };
+
+            // comment_date_gmt is zeroed for some comments, fall back to the local date
+            let created_at = ["comment_date_gmt", "comment_date"]
+                .iter()
+                .filter_map(|field| comment.child_text(field))
+                .find_map(|s| NaiveDateTime::parse_from_str(s, WP_DATE_FORMAT).ok())
+                .map(|d| d.and_utc())
+                .with_context(|| format!("WordPress comment {} has no valid date", comment_key))?;
+
+            data.comments.push(ImportComment {
+                key: comment_key.to_string(),
+                thread_key: key.to_string(),
+                parent_key: comment
+                    .child_text("comment_parent")
+                    .filter(|p| *p != "0")
+                    .map(str::to_string),
+                author_name: comment.child_text("comment_author").map(str::to_string),
+                author_email: comment.child_text("comment_author_email").map(str::to_string),
+                content: autop(comment.child_text("comment_content").unwrap_or_default()),
+                format: ContentFormat::Html,
+                created_at: created_at.with_timezone(&Utc),
+                state,
+            });
+        }
+    }
+
+    if data.threads.is_empty() && !input.contains("wordpress.org/export") {
+        bail!("Not a WordPress export (no items found)");
+    }
+
+    Ok(data)
+}
+
+/// Turn WordPress comment text into HTML paragraphs (simplified `wpautop`).
+///
+/// Comments are stored as text with blank lines between paragraphs and may contain
+/// inline HTML, which is left for the sanitizer.
+fn autop(text: &str) -> String {
+    text.replace("\r\n", "\n")
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| format!("<p>{}</p>", p.replace('\n', "<br>\n")))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,113 @@
+//! Minimal XML tree for the Disqus and WordPress exports.
+//!
+//! Element and attribute names are stored without their namespace prefix
+//! (`wp:comment` -> `comment`, `dsq:id` -> `id`).
+
+use anyhow::{Context, Result};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+#[derive(Debug, Default)]
+pub(super) struct Element {
+    pub name: String,
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    /// Concatenated text and CDATA content
+    pub text: String,
+}
+
+impl Element {
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    /// Trimmed text of a child element, None if missing or empty
+    pub fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name)
+            .map(|c| c.text.trim())
+            .filter(|t| !t.is_empty())
+    }
+
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Parse a document and return its root element
+pub(super) fn parse(input: &str) -> Result<Element> {
+    let mut reader = Reader::from_str(input);
+    // Stack of open elements, the bottom one collects the document root
+    let mut stack = vec![Element::default()];
+
+    loop {
+        let event = reader
+            .read_event()
+            .with_context(|| format!("Invalid XML at byte {}", reader.buffer_position()))?;
+
+        match event {
+            Event::Start(e) => stack.push(start_element(&e)?),
+            Event::Empty(e) => {
+                let element = start_element(&e)?;
+                stack.last_mut().unwrap().children.push(element);
+            }
+            Event::End(_) => {
+                let element = stack.pop().unwrap();
+                let parent = stack
+                    .last_mut()
+                    .context("Unbalanced closing tag in XML")?;
+                parent.children.push(element);
+            }
+            Event::Text(t) => {
+                // Fall back to the raw text for entities XML doesn't know (e.g. &nbsp;)
+                let text = match t.unescape() {
+                    Ok(text) => text.into_owned(),
+                    Err(_) => String::from_utf8_lossy(&t).into_owned(),
+                };
+                stack.last_mut().unwrap().text.push_str(&text);
+            }
+            Event::CData(c) => {
+                stack
+                    .last_mut()
+                    .unwrap()
+                    .text
+                    .push_str(&String::from_utf8_lossy(&c));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    let mut document = stack.pop().context("Empty XML document")?;
+    if !stack.is_empty() {
+        anyhow::bail!("Unexpected end of XML document");
+    }
+    document
+        .children
+        .pop()
+        .context("XML document has no root element")
+}
+
+fn start_element(e: &quick_xml::events::BytesStart) -> Result<Element> {
+    let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+
+    let mut attrs = Vec::new();
+    for attr in e.attributes() {
+        let attr = attr?;
+        let key = String::from_utf8_lossy(attr.key.local_name().as_ref()).into_owned();
+        let value = attr.unescape_value()?.into_owned();
+        attrs.push((key, value));
+    }
+
+    Ok(Element {
+        name,
+        attrs,
+        ..Default::default()
+    })
+}
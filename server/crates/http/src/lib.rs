@@ -3,3 +3,5 @@ pub mod state;
 pub mod middleware;
 pub mod extractors;
 pub mod openapi;
+pub mod import;
+pub mod archive;
@@ -69,6 +69,33 @@ struct Args {
     #[arg(long, value_names = ["SITE_ID", "KEY", "VALUE"], num_args = 3)]
     edit_site: Option<Vec<String>>,
 
+    /// Import comments from another system: --import SITE_ID FORMAT FILE
+    /// Formats: disqus (XML), wordpress (WXR), commento (JSON), isso (JSON)
+    #[arg(long, value_names = ["SITE_ID", "FORMAT", "FILE"], num_args = 3)]
+    import: Option<Vec<String>>,
+
+    /// With --import: report counts and unmapped threads without writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// With --import: use full thread URLs as page_url instead of just the path
+    #[arg(long)]
+    import_full_urls: bool,
+
+    /// With --import: also match authors to accounts whose email was never verified
+    /// (each match is written to the action log)
+    #[arg(long)]
+    import_match_unverified: bool,
+
+    /// Export a site to a JSON-lines archive: --export SITE_ID FILE
+    #[arg(long, value_names = ["SITE_ID", "FILE"], num_args = 2)]
+    export: Option<Vec<String>>,
+
+    /// Restore a site archive: --restore FILE [SITE_ID]
+    /// Restores under the archived site ID, or merges into SITE_ID if given.
+    #[arg(long, value_names = ["FILE", "SITE_ID"], num_args = 1..=2)]
+    restore: Option<Vec<String>>,
+
     /// Enable auth methods (comma-separated): google,github,email,anonymous,ethereum,solana
     /// Example: --enable-auth email,anonymous
     #[arg(long, value_delimiter = ',')]
@@ -103,7 +130,7 @@ async fn main() -> Result<()> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    // Load .env file early so that --create-site and --edit-site can access env vars
+    // Load .env file early so that the site management commands can access env vars
     if let Some(path) = &args.env {
         dotenvy::from_filename(path).ok();
     } else {
@@ -120,6 +147,19 @@ async fn main() -> Result<()> {
         return edit_site(&args, edit_args).await;
     }
 
+    // Handle --import mode - import comments and exit
+    if let Some(ref import_args) = args.import {
+        return import_comments(&args, import_args).await;
+    }
+
+    // Handle --export / --restore mode - move a site archive and exit
+    if let Some(ref export_args) = args.export {
+        return export_site(&args, export_args).await;
+    }
+    if let Some(ref restore_args) = args.restore {
+        return restore_site(&args, restore_args).await;
+    }
+
     // Load config
     let mut config = match &args.env {
         Some(path) => {
@@ -172,6 +212,20 @@ async fn main() -> Result<()> {
     // Initialize state
     let state = AppState::new(config.clone(), action_logger).await?;
 
+    // Start webhook delivery worker
+    if state.webhooks.is_enabled() {
+        state.webhooks.clone().start();
+        tracing::info!("Webhook delivery worker started");
+    }
+
+    // Start reply and mention email worker
+    state.notification_emails.clone().start();
+    tracing::info!("Notification email worker started");
+
+    // Relay page events from Redis pub/sub to SSE streams
+    let _pubsub_handle = state.pubsub_subscriber().start();
+    tracing::info!("Redis pub/sub subscriber started");
+
     // Build router
     let app = Router::new()
         // Easter egg
@@ -221,7 +275,8 @@ async fn main() -> Result<()> {
     tracing::info!("Listening on {}", addr);
 
     let listener = TcpListener::bind(addr).await?;
-    axum::serve(listener, app)
+    // The peer address decides whether X-Forwarded-For comes from a trusted proxy
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
         .with_graceful_shutdown(shutdown_signal())
         .await?;
 
@@ -468,7 +523,7 @@ async fn create_site(args: &Args, site_args: &[String]) -> Result<()> {
             .or_else(|_| env::var("PROJECT_ID_SECRET"))
             .ok()
             .filter(|s| !s.is_empty() && s != "tk_sec_your_secret_key"))
-        .unwrap_or_else(|| format!("tk_sec_{}", generate_key()));
+        .unwrap_or_else(|| threadkit_common::auth::generate_api_key(threadkit_common::auth::SECRET_KEY_PREFIX));
 
     // Validate moderation mode
     let moderation = match moderation_mode.to_lowercase().as_str() {
@@ -549,7 +604,12 @@ async fn create_site(args: &Args, site_args: &[String]) -> Result<()> {
             turnstile: TurnstileSettings::default(),
             allowed_origins: vec![],
             posting_disabled: false,
+            webhooks: vec![],
+            editing: Default::default(),
+            reactions: Default::default(),
+            email: Default::default(),
         },
+        plan: Default::default(),
     };
 
     // Store in Redis
@@ -669,8 +729,58 @@ async fn edit_site(args: &Args, edit_args: &[String]) -> Result<()> {
             config.settings.auth.ethereum = methods.iter().any(|m| *m == "ethereum" || *m == "eth");
             config.settings.auth.solana = methods.iter().any(|m| *m == "solana" || *m == "sol");
         }
+        "edit_window_minutes" => {
+            // "0" or "none" removes the limit
+            config.settings.editing.edit_window_minutes = match value.to_lowercase().as_str() {
+                "0" | "none" => None,
+                v => match v.parse() {
+                    Ok(minutes) => Some(minutes),
+                    Err(_) => {
+                        eprintln!("error: invalid edit window '{}' (must be minutes or 'none')", value);
+                        std::process::exit(1);
+                    }
+                },
+            };
+        }
+        "public_revisions" => {
+            config.settings.editing.public_revisions = match value.to_lowercase().as_str() {
+                "true" | "yes" | "1" => true,
+                "false" | "no" | "0" => false,
+                _ => {
+                    eprintln!("error: invalid value '{}' (must be: true, false)", value);
+                    std::process::exit(1);
+                }
+            };
+        }
+        "reactions" => {
+            // Value is comma-separated list of emojis, empty disables reactions
+            config.settings.reactions.emojis = value
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        "email_sender_name" => {
+            // Empty value falls back to the site name
+            config.settings.email.sender_name = Some(value.trim().to_string()).filter(|s| !s.is_empty());
+        }
+        "reply_notification_emails" | "mention_notification_emails" => {
+            let enabled = match value.to_lowercase().as_str() {
+                "true" | "yes" | "1" => true,
+                "false" | "no" | "0" => false,
+                _ => {
+                    eprintln!("error: invalid value '{}' (must be: true, false)", value);
+                    std::process::exit(1);
+                }
+            };
+            if key == "reply_notification_emails" {
+                config.settings.email.reply_notifications = enabled;
+            } else {
+                config.settings.email.mention_notifications = enabled;
+            }
+        }
         _ => {
-            eprintln!("error: unknown key '{}' (valid keys: name, domain, moderation_mode, project_id_public, project_id_secret, auth)", key);
+            eprintln!("error: unknown key '{}' (valid keys: name, domain, moderation_mode, project_id_public, project_id_secret, auth, edit_window_minutes, public_revisions, reactions, email_sender_name, reply_notification_emails, mention_notification_emails)", key);
             std::process::exit(1);
         }
     }
@@ -685,14 +795,229 @@ async fn edit_site(args: &Args, edit_args: &[String]) -> Result<()> {
     Ok(())
 }
 
-/// Generate a cryptographically secure random alphanumeric key
-fn generate_key() -> String {
-    use rand::{Rng, rngs::OsRng};
-    const CHARSET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
-    (0..32)
-        .map(|_| {
-            let idx = OsRng.gen_range(0..CHARSET.len());
-            CHARSET[idx] as char
-        })
-        .collect()
+async fn import_comments(args: &Args, import_args: &[String]) -> Result<()> {
+    use threadkit_common::redis::RedisClient;
+    use threadkit_http::import::{run_import, ImportFormat, ImportOptions};
+    use uuid::Uuid;
+
+    let site_id_str = &import_args[0];
+    let format_str = &import_args[1];
+    let path = &import_args[2];
+
+    // Parse site ID
+    let site_id: Uuid = match site_id_str.parse() {
+        Ok(id) => id,
+        Err(_) => {
+            eprintln!("error: invalid site ID '{}'", site_id_str);
+            std::process::exit(1);
+        }
+    };
+
+    let format: ImportFormat = match format_str.parse() {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let input = match std::fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("error: failed to read '{}': {}", path, e);
+            std::process::exit(1);
+        }
+    };
+
+    let data = match format.parse(&input) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("error: failed to parse {} export: {:#}", format, e);
+            std::process::exit(1);
+        }
+    };
+
+    // Get Redis URL
+    let redis_url = args.redis_url.clone()
+        .or_else(|| std::env::var("REDIS_URL").ok())
+        .unwrap_or_else(|| "redis://localhost:6379".to_string());
+
+    // Connect to Redis
+    let redis = match RedisClient::new(&redis_url).await {
+        Ok(r) => Arc::new(r),
+        Err(e) => {
+            eprintln!("error: failed to connect to redis: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let store = connect_store(&redis).await;
+
+    let site = match redis.get_site_config(site_id).await {
+        Ok(Some(c)) => c,
+        Ok(None) => {
+            eprintln!("error: site '{}' not found", site_id);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("error: failed to get site config: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let options = ImportOptions {
+        keep_full_urls: args.import_full_urls,
+        dry_run: args.dry_run,
+        match_unverified_emails: args.import_match_unverified,
+    };
+
+    let action_log_path = args.action_log.clone()
+        .or_else(|| std::env::var("THREADKIT_ACTION_LOG").ok())
+        .map(std::path::PathBuf::from);
+    let action_logger = ActionLogger::new(action_log_path)?;
+
+    match run_import(&redis, store.as_ref(), &site, &data, format, &options, &action_logger).await {
+        Ok(report) => {
+            print!("{}", report);
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("error: import failed: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn export_site(args: &Args, export_args: &[String]) -> Result<()> {
+    use threadkit_common::redis::RedisClient;
+    use uuid::Uuid;
+
+    let site_id_str = &export_args[0];
+    let path = &export_args[1];
+
+    // Parse site ID
+    let site_id: Uuid = match site_id_str.parse() {
+        Ok(id) => id,
+        Err(_) => {
+            eprintln!("error: invalid site ID '{}'", site_id_str);
+            std::process::exit(1);
+        }
+    };
+
+    // Get Redis URL
+    let redis_url = args.redis_url.clone()
+        .or_else(|| std::env::var("REDIS_URL").ok())
+        .unwrap_or_else(|| "redis://localhost:6379".to_string());
+
+    // Connect to Redis
+    let redis = match RedisClient::new(&redis_url).await {
+        Ok(r) => Arc::new(r),
+        Err(e) => {
+            eprintln!("error: failed to connect to redis: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let store = connect_store(&redis).await;
+
+    let file = match std::fs::File::create(path) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("error: failed to create '{}': {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    let mut out = std::io::BufWriter::new(file);
+
+    match threadkit_http::archive::export_site(&redis, store.as_ref(), site_id, &mut out).await {
+        Ok(summary) => {
+            print!("{}", summary);
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("error: export failed: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn restore_site(args: &Args, restore_args: &[String]) -> Result<()> {
+    use threadkit_common::redis::RedisClient;
+    use threadkit_http::archive::{restore_site, Archive, RestoreOptions};
+    use uuid::Uuid;
+
+    let path = &restore_args[0];
+
+    // Optional target site ID
+    let site_id: Option<Uuid> = match restore_args.get(1) {
+        Some(s) => match s.parse() {
+            Ok(id) => Some(id),
+            Err(_) => {
+                eprintln!("error: invalid site ID '{}'", s);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let file = match std::fs::File::open(path) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("error: failed to read '{}': {}", path, e);
+            std::process::exit(1);
+        }
+    };
+
+    let archive = match Archive::read(std::io::BufReader::new(file)) {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("error: invalid archive: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Get Redis URL
+    let redis_url = args.redis_url.clone()
+        .or_else(|| std::env::var("REDIS_URL").ok())
+        .unwrap_or_else(|| "redis://localhost:6379".to_string());
+
+    // Connect to Redis
+    let redis = match RedisClient::new(&redis_url).await {
+        Ok(r) => Arc::new(r),
+        Err(e) => {
+            eprintln!("error: failed to connect to redis: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let store = connect_store(&redis).await;
+
+    match restore_site(&redis, store.as_ref(), &archive, &RestoreOptions { site_id }).await {
+        Ok(report) => {
+            print!("{}", report);
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("error: restore failed: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Open the configured storage backend (`STORAGE_BACKEND`) for the CLI tools
+async fn connect_store(
+    redis: &Arc<threadkit_common::redis::RedisClient>,
+) -> Arc<dyn threadkit_common::Storage> {
+    let backend = match threadkit_common::config::StorageBackend::from_env() {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    match threadkit_common::store::connect(&backend, redis.clone()).await {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("error: failed to open storage backend: {}", e);
+            std::process::exit(1);
+        }
+    }
 }
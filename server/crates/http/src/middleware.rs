@@ -1,12 +1,16 @@
 use axum::{
     body::Body,
-    extract::State,
-    http::{header::AUTHORIZATION, Method, Request, StatusCode},
+    extract::{ConnectInfo, State},
+    http::{header::AUTHORIZATION, Extensions, Method, Request, StatusCode},
     middleware::Next,
     response::{IntoResponse, Response},
 };
-use sha2::{Digest, Sha256};
-use threadkit_common::{auth, redis::RateLimitResult};
+use std::net::{IpAddr, SocketAddr};
+use threadkit_common::{
+    auth,
+    rate_limit::{self, client_ip},
+    redis::RateLimitResult,
+};
 
 use crate::state::AppState;
 
@@ -57,53 +61,15 @@ impl RouteType {
 
 /// Extract client IP from request, handling X-Forwarded-For for proxies
 fn extract_client_ip(request: &Request<Body>, trusted_proxies: &[String]) -> String {
-    // Try to get X-Forwarded-For header
-    if let Some(forwarded_for) = request
-        .headers()
-        .get("x-forwarded-for")
-        .and_then(|v| v.to_str().ok())
-    {
-        // X-Forwarded-For is a comma-separated list: client, proxy1, proxy2, ...
-        // We want the first non-trusted IP from the right
-        let ips: Vec<&str> = forwarded_for.split(',').map(|s| s.trim()).collect();
-
-        // Find the rightmost IP that isn't from a trusted proxy
-        for ip in ips.iter().rev() {
-            if !trusted_proxies.iter().any(|p| p == *ip) {
-                return ip.to_string();
-            }
-        }
-
-        // If all IPs are trusted, use the leftmost (client)
-        if let Some(first) = ips.first() {
-            return first.to_string();
-        }
-    }
-
-    // Try X-Real-IP header
-    if let Some(real_ip) = request
-        .headers()
-        .get("x-real-ip")
-        .and_then(|v| v.to_str().ok())
-    {
-        return real_ip.to_string();
-    }
-
-    // Fall back to peer address from extensions (if set by server)
-    if let Some(addr) = request.extensions().get::<std::net::SocketAddr>() {
-        return addr.ip().to_string();
-    }
-
-    // Last resort - use a placeholder
-    "unknown".to_string()
+    client_ip(request.headers(), peer_ip(request.extensions()), trusted_proxies)
+        .unwrap_or_else(|| "unknown".to_string())
 }
 
-/// Hash an IP address for storage (privacy)
-fn hash_ip(ip: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(ip.as_bytes());
-    let result = hasher.finalize();
-    format!("{:x}", result)[..16].to_string()
+/// Address of the TCP peer: the client, or a proxy in front of the server
+pub(crate) fn peer_ip(extensions: &Extensions) -> Option<IpAddr> {
+    extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_canonical())
 }
 
 /// Rate limiting middleware
@@ -124,7 +90,6 @@ pub async fn rate_limit(
 
     // Extract identifiers
     let client_ip = extract_client_ip(&request, &state.config.rate_limit.trusted_proxies);
-    let ip_hash = hash_ip(&client_ip);
 
     // Get API key info if present
     let project_id_header = request
@@ -204,9 +169,9 @@ pub async fn rate_limit(
     };
 
     // Check IP rate limit
-    let ip_key = format!("ratelimit:ip:{}:{}", ip_hash, route_suffix);
+    let ip_key = rate_limit::ip_key(&client_ip, route_suffix);
     let ip_result = state
-        .redis
+        .store
         .check_rate_limit(&ip_key, ip_limit, window_secs)
         .await
         .map_err(|e| {
@@ -221,9 +186,9 @@ pub async fn rate_limit(
     // Check API key rate limit (if present and for write operations)
     if let (Some(sid), RouteType::Write) = (site_id, route_type) {
         let project_id_limit = global.project_id_writes_per_minute;
-        let project_id_key = format!("ratelimit:apikey:{}:{}", sid, route_suffix);
+        let project_id_key = rate_limit::project_id_key(sid, route_suffix);
         let api_result = state
-            .redis
+            .store
             .check_rate_limit(&project_id_key, project_id_limit, 60)
             .await
             .map_err(|e| {
@@ -238,9 +203,9 @@ pub async fn rate_limit(
 
     // Check user rate limit (if authenticated)
     let final_result = if let (Some(uid), Some(sid)) = (user_id, site_id) {
-        let user_key = format!("ratelimit:user:{}:{}:{}", sid, uid, route_suffix);
+        let user_key = rate_limit::user_key(sid, uid, route_suffix);
         let user_result = state
-            .redis
+            .store
             .check_rate_limit(&user_key, user_limit, window_secs)
             .await
             .map_err(|e| {
@@ -1,6 +1,6 @@
 use utoipa::OpenApi;
 
-use crate::routes::{admin, auth, comments, media, moderation, turnstile, users};
+use crate::routes::{admin, analytics, api_tokens, auth, comments, events, media, moderation, sites, turnstile, users};
 
 const API_DESCRIPTION: &str = r#"
 ThreadKit is an open-source, self-hostable comment system for websites and applications.
@@ -30,27 +30,40 @@ ws://server:8081/ws?project_id=tk_pub_xxx&token=<jwt>
 
 ### Protocol
 
-All messages use JSON-RPC 2.0 notification format:
+Events and the methods below use JSON-RPC 2.0 notification format:
 
 ```json
 {"jsonrpc": "2.0", "method": "<method>", "params": {...}}
 ```
 
+Comment writes are JSON-RPC requests: send an `id` and the server answers with `{"jsonrpc": "2.0", "id": ..., "result": {...}}` or `{"jsonrpc": "2.0", "id": ..., "error": {"code": ..., "message": "..."}}`. They run the same checks as the HTTP endpoints; error codes are the HTTP status the endpoint would return (e.g. `403`), or `-32600`/`-32601`/`-32602` for malformed requests.
+
 ### Client → Server
 
 | Method | Params | Description |
 |--------|--------|-------------|
-| `subscribe` | `page_id: UUID` | Subscribe to page events |
+| `subscribe` | `page_id: UUID, since_seq?: u64` | Subscribe to page events, replaying events after `since_seq` |
 | `unsubscribe` | `page_id: UUID` | Unsubscribe from page |
 | `typing` | `page_id: UUID, reply_to?: UUID` | Send typing indicator |
 | `ping` | `{}` | Heartbeat |
+| `subscribe_moderation` | `{}` | Site-wide moderation events (moderator+) |
+| `unsubscribe_moderation` | `{}` | Stop moderation events |
+
+### Client → Server Requests
+
+| Method | Params | Result |
+|--------|--------|--------|
+| `post_comment` | `page_url, content, parent_path?, author_name?, turnstile_token?` | `{comment}` |
+| `edit_comment` | `comment_id, page_url, content, path` | `{comment}` |
+| `delete_comment` | `comment_id, page_url, path` | `{comment_id, deleted}` |
+| `vote` | `comment_id, page_url, direction, path` | `{upvotes, downvotes, user_vote}` |
 
 ### Server → Client
 
 | Method | Params | Description |
 |--------|--------|-------------|
 | `connected` | `user_id?: UUID` | Connection established |
-| `presence` | `page_id, users[]` | Current users on page |
+| `presence` | `page_id, users[], seq` | Current users on page and latest event seq |
 | `user_joined` | `page_id, user` | User joined page |
 | `user_left` | `page_id, user_id` | User left page |
 | `typing` | `page_id, user, reply_to?` | User is typing |
@@ -58,10 +71,21 @@ All messages use JSON-RPC 2.0 notification format:
 | `edit_comment` | `page_id, comment_id, content, content_html` | Comment edited |
 | `delete_comment` | `page_id, comment_id` | Comment deleted |
 | `vote_update` | `page_id, comment_id, upvotes, downvotes` | Votes changed |
-| `notification` | `type, comment_id, from_user` | User notification |
+| `notification` | `type, comment_id, from_user, page_url` | Reply or mention of the signed in user |
+| `moderation_subscribed` | `site_id` | Moderation events will follow |
+| `modqueue_added` | `site_id, page_id, comment` | Comment held for review (moderators) |
+| `comment_flagged_by_ai` | `site_id, page_id, comment, category` | Content moderation flagged a comment (moderators) |
+| `report_created` | `site_id, page_id, report` | Comment reported (moderators) |
+| `user_banned` | `site_id, user_id, moderator_id, shadowban, comments_deleted` | User banned or shadowbanned (moderators) |
+| `signed_out` | `reason` | Session logged out (`session_revoked`, connection closes) or user banned (`banned`, connection continues as anonymous) |
+| `resync_required` | `page_id, seq` | `since_seq` is too old to replay; refetch the page |
 | `pong` | `{}` | Heartbeat response |
 | `error` | `code, message` | Error occurred |
 
+Comment events also carry `seq`, which increases by one with each event on the page. Resubscribe with the last `seq` you processed as `since_seq` to replay what you missed (roughly the last 1000 events per page, kept for 24 hours).
+
+Events are filtered per connection with the same rules as `GET /comments`: comments from users you blocked and other users' pending comments are not sent (moderators do receive pending comments), and typing/presence from blocked or shadowbanned users is hidden.
+
 ### Limits
 
 | Limit | Value |
@@ -81,6 +105,16 @@ All auth methods can be enabled/disabled in site configuration.
 
 Authenticated endpoints require `Authorization: Bearer <token>` header.
 
+### API Tokens
+
+Site owners can mint named API tokens (`tk_tok_...`) for server-to-server integrations
+instead of sharing the secret key. A token is sent in the `projectid` header and acts
+with admin rights limited to its scopes: `read_comments`, `moderate`, `manage_users`,
+`read_analytics` and `manage_site`. Tokens can expire and be limited to client IPs or
+CIDR ranges. They are stored hashed, shown once on creation, and managed with the
+secret key under `/admin/sites/{id}/tokens`. Actions taken with a token are logged
+with its `api_token_id`.
+
 ## Comment Tree
 
 Comments are returned as a compact tree with single-letter keys:
@@ -93,6 +127,14 @@ Comments are returned as a compact tree with single-letter keys:
 - Each comment has `r` (replies) for nested threading
 - See `TreeComment` schema for full structure
 
+## Comment Edits
+
+Editing a comment keeps the previous text as a revision (`GET /comments/{id}/revisions`). Revisions are visible to moderators, or to everyone when the site sets `editing.public_revisions`. With `editing.edit_window_minutes` set, authors can only edit for that long after posting; moderators can always edit. Configure both via `/admin/sites/{id}/editing`.
+
+## Reactions
+
+`POST /comments/{id}/reactions` with an `emoji` toggles the signed-in user's reaction. Counts are carried on each comment as `z` (emoji -> count) and pushed to WebSocket subscribers as `reaction_update`; the user's own reactions come back from `/pages/my_votes`. Sites choose their emoji set with `PUT /admin/sites/{id}/reactions` (an empty set disables reactions).
+
 ## Caching
 
 The `/comments` endpoint supports ETag caching:
@@ -101,6 +143,53 @@ The `/comments` endpoint supports ETag caching:
 - Send `If-None-Match: "<etag>"` to get `304 Not Modified`
 - Works with Cloudflare and CDNs for instant responses
 
+## Webhooks
+
+Admins can register endpoints with `PUT /admin/sites/{id}/webhooks`. Each endpoint receives a `POST` with a JSON body for every matching site event (`comment_created`, `comment_approved`, `report_created`, `user_banned`, ...; an empty `events` list means all events). Endpoints must be on public addresses: URLs pointing at loopback, private or link-local addresses are rejected when saved, and deliveries whose host resolves to one fail.
+
+| Header | Description |
+|--------|-------------|
+| `X-ThreadKit-Event` | Event type |
+| `X-ThreadKit-Delivery` | Delivery ID (same for every retry) |
+| `X-ThreadKit-Signature` | `t=<unix seconds>,v1=<hex>` |
+
+`v1` is HMAC-SHA256 of `<t>.<raw body>` keyed with the site's secret key. Verify it and reject old timestamps to prevent replays.
+
+Non-2xx responses and timeouts are retried with exponential backoff. Recent deliveries are listed at `GET /admin/sites/{id}/webhooks/deliveries` and failed ones can be re-sent with `POST .../deliveries/{delivery_id}/replay`.
+
+## Site Export
+
+`GET /admin/sites/{id}/owner/export` (secret key) downloads the site as a JSON-lines archive: a `header` record with the format version, then `site`, `roles`, `user`, `page` and `media` records. The site's secret key, password hashes, sessions and votes are not included.
+
+Restore with `threadkit-http --restore FILE [SITE_ID]`. Without `SITE_ID` the archive is loaded under its original site ID (e.g. into an empty Redis); with it, comments are merged into that site and page IDs are recomputed from page URLs. Users are matched by ID, then by email. A restored site gets a new secret key, printed in the restore report.
+
+## Site Management (SaaS)
+
+With `MODE=saas`, platform operators manage tenants with `Authorization: Bearer <ADMIN_API_KEY>` (the endpoints are disabled when the key isn't set): `GET`/`POST /admin/sites`, `GET`/`PATCH`/`DELETE /admin/sites/{id}`. `PATCH` replaces the settings sections it's given (`auth`, `display`, `rate_limits`, `content_moderation`, `turnstile`, `allowed_origins`, ...) and leaves the rest unchanged.
+
+`POST /admin/sites/{id}/rotate-keys` issues new keys; the replaced ones keep working for `grace_period_seconds` (default one day, `0` revokes them immediately).
+
+### Plans and Quotas
+
+A site's `plan` sets its limits: a `tier` (`free`, `pro`, `business`, `enterprise`), optional custom `limits` that replace the tier's, and the `owner_id` of the account it belongs to. Sites without a tier have no limits.
+
+| Limit | Enforced on | Error |
+|-------|-------------|-------|
+| `monthly_comments` | Posting a comment (HTTP or WebSocket) | `402` |
+| `monthly_moderation_checks` | Posting a comment while AI moderation is on | `429` |
+| `media_storage_bytes` | `POST /upload/image` (avatars count but aren't refused) | `402` |
+| `max_sites` | Creating a site, or moving one to another owner | `402` |
+
+Monthly counters reset with the calendar month (UTC). The first time in a month a counter passes 80% of its limit a `quota_warning` action is logged and sent to the site's webhooks; the first refused write sends `quota_exceeded`. `GET /admin/sites/{id}/usage?month=YYYY-MM` reports usage against each limit.
+
+## Personal Data Export
+
+`GET /users/me/export` downloads everything stored about the signed-in user as one JSON document: the user record, comments across all sites (without other users' replies), votes, notifications, block lists, uploaded media and active sessions.
+
+## Storage Backends
+
+`STORAGE_BACKEND=redis` (default) keeps everything in Redis and the schema below applies as-is. With `STORAGE_BACKEND=sqlite` users, page trees and comment revisions, votes, site roles, notifications, sessions and rate limits are stored in the SQLite database at `SQLITE_PATH` instead; Redis is still required for site configs, comment indexes, the moderation queue, webhooks, caches, presence and pub/sub.
+
 ## Rate Limits
 
 - Read: 100/second per API key
@@ -118,10 +207,16 @@ The `/comments` endpoint supports ETag caching:
 | `user:{user_id}:password` | String | Argon2 password hash |
 | `user:{user_id}:comments` | ZSet | User's comments across all sites. Values: `page_id:comment_id` |
 | `user:{user_id}:votes` | ZSet | Comments user has voted on. Values: `page_id:comment_id` |
+| `user:{user_id}:vote_pages` | Set | Page IDs with a `votes:{user_id}:{page_id}` hash |
+| `user:{user_id}:reaction_pages` | Set | Page IDs with a `reactions:{user_id}:{page_id}` hash |
 | `user:{user_id}:notifications` | ZSet | User notifications (score = timestamp) |
 | `user:{user_id}:unread` | String | Count of unread notifications |
 | `user:{user_id}:blocked` | Set | User IDs this user has blocked |
 | `user:{user_id}:blocked_by` | Set | User IDs who have blocked this user |
+| `user:{user_id}:sessions` | Set | Session IDs for this user |
+| `user:{user_id}:media` | Set | Media IDs uploaded by this user |
+| `votes:{user_id}:{page_id}` | Hash | User's votes on a page. Field: comment_id, value: `1` or `-1` |
+| `reactions:{user_id}:{page_id}` | Hash | User's reactions on a page. Field: `{comment_id}:{emoji}` |
 | `email:{email}` | String | Maps email to user_id |
 | `phone:{phone}` | String | Maps phone to user_id |
 | `username:{username}` | String | Maps username to user_id |
@@ -132,9 +227,11 @@ The `/comments` endpoint supports ETag caching:
 
 | Key | Type | TTL | Description |
 |-----|------|-----|-------------|
-| `session:{session_id}` | Hash | - | Session data (user_id, created_at, user_agent, ip) |
+| `session:{session_id}` | Hash | A day after the refresh token | Session data (user_id, created_at, user_agent, ip, current and previous refresh token hashes, expiry and rotation time) |
+| `session:{session_id}:refresh_used` | Set | A day after the refresh token | Hashes of refresh tokens already exchanged (reuse revokes the session) |
 | `verify:{key}` | String | 10m | Email/phone verification code |
 | `web3nonce:{chain}:{address}` | String | 10m | Web3 signature nonce |
+| `stream_ticket:{ticket}` | String | 1m | Single-use ticket for opening an event stream (`{site_id}:{user_id}:{session_id}`) |
 
 ### Pages
 
@@ -143,7 +240,12 @@ The `/comments` endpoint supports ETag caching:
 | Key | Type | Description |
 |-----|------|-------------|
 | `page:{page_id}:tree` | JSON | Full page tree (comments, votes, authors) |
+| `page:{page_id}:tree:version` | String | Tree write counter (compare-and-set for concurrent writers) |
+| `page:{page_id}:event_seq` | String | Sequence number of the page's latest real-time event |
+| `page:{page_id}:event_log` | Stream | Recent page events for WebSocket replay (entry ID `{seq}-0`, ~1000 kept, 24h TTL) |
 | `page:{page_id}:views` | String | Pageview counter |
+| `page:{page_id}:presence` | Set | Users viewing the page, as `{user_id}:{node_id}` (entries of dead WebSocket nodes are ignored and reaped) |
+| `comment:{comment_id}:revisions` | List | Previous versions of an edited comment (JSON `CommentRevision`, oldest first) |
 
 ### Page Tree Structure
 
@@ -161,6 +263,7 @@ Single-letter keys for efficiency:
     "h": "<p>html</p>",
     "u": 5,
     "d": 1,
+    "z": {"👍": 3},
     "x": 1704067200,
     "m": 1704067200,
     "r": [...]
@@ -187,11 +290,15 @@ Single-letter keys for efficiency:
 | `h` | html content |
 | `u` | upvotes (count) |
 | `d` | downvotes (count) |
+| `z` | reactions (emoji -> count, omitted when empty) |
 | `x` | created_at |
 | `m` | modified_at |
 | `r` | replies (nested comments) |
+| `o` | omitted reply count (truncated responses only) |
+
+**Large pages:** Pass `limit` to `GET /comments` to page through top-level comments (follow `next_cursor`), and `max_depth` / `max_replies` to cut reply chains short. Truncated comments carry `o`; load the rest with `GET /comments/subtree?page_url=...&parent_path=root_id,...,parent_id`.
 
-**User votes:** Fetch via `GET /pages/my_votes?page_url=...` - returns `{comment_id: "up"|"down"}` map
+**User votes:** Fetch via `GET /pages/my_votes?page_url=...` - returns `{comment_id: "up"|"down"}` map, plus the user's reactions as `{comment_id: [emoji, ...]}`
 
 **Deleted comments**: `a` = `d0000000-0000-0000-0000-000000000000`, `n` = `[deleted]`, content cleared, replies preserved.
 
@@ -209,19 +316,48 @@ Single-letter keys for efficiency:
 | `site:{site_id}:modqueue` | ZSet | Pending comments (`page_id:comment_id`) |
 | `site:{site_id}:reports` | ZSet | Reported comments |
 | `site:{site_id}:locked_pages` | Set | Pages where posting is disabled |
-| `site:{site_id}:usage:{YYYY-MM}` | Hash | Monthly usage stats |
+| `site:{site_id}:pages` | Hash | page_id → page_url (page IDs are hashes, needed to move pages between sites) |
+| `site:{site_id}:usage:{YYYY-MM}` | Hash | Monthly usage stats (`comments`, `pageviews`, `moderation_checks`) |
+| `site:{site_id}:media_bytes` | String | Bytes of uploaded media stored for the site |
+| `site:{site_id}:quota_notice:{YYYY-MM}:{event}:{metric}` | String | Quota warning already sent this month (32d TTL) |
+| `site:{site_id}:webhook_deliveries` | ZSet | Recent webhook delivery IDs (capped) |
+| `site:{site_id}:stats:{hour\|day}:{bucket}` | Hash | Analytics counters (`pageviews`, `comments`, `websocket_messages`, `peak_connections`) |
+| `site:{site_id}:visitors:{hour\|day}:{bucket}` | HyperLogLog | Unique visitors |
+| `site:{site_id}:commenters:{hour\|day}:{bucket}` | HyperLogLog | Active commenters |
+| `site:{site_id}:page_views:{YYYYMMDD}` | ZSet | Pageviews per page that day |
+| `site:{site_id}:page_comments:{YYYYMMDD}` | ZSet | Comments per page that day |
+
+### Webhooks
+
+| Key | Type | TTL | Description |
+|-----|------|-----|-------------|
+| `webhook:delivery:{delivery_id}` | JSON | 7d | Delivery payload, status and last attempt |
+| `webhooks:queue` | ZSet | - | Pending delivery IDs (score = next attempt, unix ms) |
+
+### WebSocket Nodes
+
+| Key | Type | TTL | Description |
+|-----|------|-----|-------------|
+| `ws:nodes` | ZSet | - | WebSocket node IDs (score = last heartbeat, unix ms); nodes silent for 30s are reaped |
+| `ws:node:{node_id}:presence` | Set | - | The node's presence entries (`{page_id}:{user_id}`) |
+| `ws:node:{node_id}:connections` | Hash | 30s | Open connections on the node by site_id |
+| `ws:node:{node_id}:metrics` | Hash | 30s | The node's `/metrics` counters |
 
 ### API Keys
 
 | Key | Type | Description |
 |-----|------|-------------|
-| `apikey:{key}:site` | String | Maps API key to site_id |
+| `apikey:{key}:site` | String | Maps API key to site_id (expires at the end of the grace period once the key is rotated out) |
+| `apikey:{key}` | JSON | Cached key lookup (5 minutes) |
+| `api_token:{sha256}` | JSON | Scoped API token by the hash of the token (expires with it) |
+| `site:{site_id}:api_tokens` | Hash | token_id → token hash |
+| `site:{site_id}:api_tokens_used` | Hash | token_id → last use |
 
 ### Design
 
 **Read path**: Single `GET page:{id}:tree` returns all comments. Server filters, sorts, strips vote arrays.
 
-**Write path**: Update page tree JSON + user/site indexes.
+**Write path**: Update page tree JSON + user/site indexes. Tree writes are compare-and-set against `page:{id}:tree:version` (retried on conflict), votes update the tree inside a Lua script.
 
 **Performance**: O(1) page load (~1-5ms for 1000 comments) instead of N+1 queries.
 "#;
@@ -245,6 +381,7 @@ Single-letter keys for efficiency:
         (name = "notifications", description = "User notifications"),
         (name = "moderation", description = "Content moderation (moderator+)"),
         (name = "admin", description = "Site administration (admin+)"),
+        (name = "sites", description = "Site management for platform operators (SaaS mode)"),
         (name = "turnstile", description = "Cloudflare Turnstile CAPTCHA")
     ),
     paths(
@@ -267,12 +404,17 @@ Single-letter keys for efficiency:
         turnstile::verify_token,
         // Comments
         comments::get_comments,
+        comments::get_comment_subtree,
         comments::create_comment,
         comments::update_comment,
+        comments::get_comment_revisions,
         comments::delete_comment,
         comments::vote_comment,
+        comments::react_to_comment,
         comments::report_comment,
         comments::get_my_votes,
+        events::stream_page_events,
+        events::create_stream_ticket,
         // Media
         media::upload_avatar,
         media::upload_image,
@@ -281,6 +423,7 @@ Single-letter keys for efficiency:
         users::get_me,
         users::update_me,
         users::delete_account,
+        users::export_my_data,
         users::get_user,
         users::check_username,
         users::get_blocked_users,
@@ -291,6 +434,8 @@ Single-letter keys for efficiency:
         // Notifications
         users::get_notifications,
         users::mark_read,
+        users::unsubscribe_page,
+        users::unsubscribe,
         // Moderation
         moderation::get_queue,
         moderation::get_reports,
@@ -309,13 +454,37 @@ Single-letter keys for efficiency:
         admin::get_site_comments,
         admin::get_posting_status,
         admin::set_site_posting,
+        admin::get_edit_settings,
+        admin::set_edit_settings,
+        admin::get_reaction_settings,
+        admin::set_reaction_settings,
         admin::get_page_posting_status,
         admin::set_page_posting,
+        admin::get_webhooks,
+        admin::set_webhooks,
+        admin::get_webhook_deliveries,
+        admin::replay_webhook_delivery,
+        admin::export_site,
+        // Analytics
+        analytics::get_site_analytics,
+        // API tokens
+        api_tokens::list_tokens,
+        api_tokens::create_token,
+        api_tokens::revoke_token,
+        // Site management
+        sites::list_sites,
+        sites::create_site,
+        sites::get_site,
+        sites::update_site,
+        sites::delete_site,
+        sites::rotate_keys,
+        sites::get_site_usage,
     ),
     components(
         schemas(
             // Common types
             threadkit_common::types::SocialLinks,
+            threadkit_common::types::EmailNotificationFrequency,
             threadkit_common::types::UserPublic,
             threadkit_common::types::Comment,
             threadkit_common::types::CommentStatus,
@@ -327,6 +496,7 @@ Single-letter keys for efficiency:
             threadkit_common::types::Report,
             threadkit_common::types::ReportReason,
             threadkit_common::types::DeletedAccountStats,
+            threadkit_common::types::SessionInfo,
             threadkit_common::types::PageTree,
             threadkit_common::types::TreeComment,
             // Auth types
@@ -342,14 +512,20 @@ Single-letter keys for efficiency:
             auth::Web3VerifyRequest,
             // Comment types
             comments::GetCommentsResponse,
+            comments::GetSubtreeResponse,
             comments::CreateCommentRequest,
             comments::CreateCommentResponse,
             comments::UpdateCommentRequest,
+            comments::GetRevisionsResponse,
+            threadkit_common::types::CommentRevision,
             comments::DeleteRequest,
             comments::VoteRequest,
             comments::VoteResponse,
+            comments::ReactionRequest,
+            comments::ReactionResponse,
             comments::ReportRequest,
             comments::GetVotesResponse,
+            events::StreamTicketResponse,
             // Media types
             media::UploadResponse,
             // User types
@@ -362,6 +538,9 @@ Single-letter keys for efficiency:
             users::BlockedUsersResponse,
             users::UserCommentsResponse,
             users::CommentItem,
+            users::UserDataExport,
+            users::ExportedVote,
+            users::ExportedReaction,
             // Moderation types
             moderation::QueueResponse,
             moderation::QueueItem,
@@ -381,12 +560,63 @@ Single-letter keys for efficiency:
             admin::SiteCommentItem,
             admin::PostingStatusResponse,
             admin::SetPostingRequest,
+            threadkit_common::types::EditSettings,
+            threadkit_common::types::ReactionSettings,
+            admin::WebhooksResponse,
+            admin::WebhookEndpointInput,
+            admin::SetWebhooksRequest,
+            admin::WebhookDeliveriesResponse,
+            // Webhook types
+            threadkit_common::types::WebhookEndpoint,
+            threadkit_common::types::WebhookDelivery,
+            threadkit_common::types::WebhookDeliveryStatus,
+            threadkit_common::ActionType,
+            // API token types
+            api_tokens::CreateTokenRequest,
+            api_tokens::CreateTokenResponse,
+            api_tokens::TokensResponse,
+            threadkit_common::types::ApiToken,
+            threadkit_common::types::ApiTokenScope,
+            // Analytics types
+            analytics::AnalyticsResponse,
+            analytics::AnalyticsTotals,
+            threadkit_common::types::AnalyticsGranularity,
+            threadkit_common::types::AnalyticsPoint,
+            threadkit_common::types::TopPage,
+            // Site management types
+            sites::SitesResponse,
+            sites::SiteSettingsUpdate,
+            sites::CreateSiteRequest,
+            sites::UpdateSiteRequest,
+            sites::RotatedKeys,
+            sites::RotateKeysRequest,
+            sites::RotateKeysResponse,
+            sites::SiteUsageResponse,
+            threadkit_common::types::SiteConfig,
+            threadkit_common::types::SitePlan,
+            threadkit_common::types::PlanTier,
+            threadkit_common::types::PlanLimits,
+            threadkit_common::quotas::QuotaUsage,
+            threadkit_common::quotas::QuotaMetric,
+            threadkit_common::quotas::QuotaStatus,
+            threadkit_common::types::SiteSettings,
+            threadkit_common::types::ModerationMode,
+            threadkit_common::types::AuthSettings,
+            threadkit_common::types::DisplaySettings,
+            threadkit_common::types::SiteRateLimitSettings,
+            threadkit_common::types::ContentModerationSettings,
+            threadkit_common::types::BlockedCategories,
+            threadkit_common::types::ModerationAction,
+            threadkit_common::types::TurnstileSettings,
+            threadkit_common::types::TurnstileEnforcement,
+            threadkit_common::types::EmailSettings,
         )
     ),
     security(
         ("project_id" = []),
         ("secret_key" = []),
-        ("bearer" = [])
+        ("bearer" = []),
+        ("admin_api_key" = [])
     )
 )]
 pub struct ApiDoc;
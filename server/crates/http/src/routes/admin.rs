@@ -1,6 +1,7 @@
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{header, StatusCode},
+    response::IntoResponse,
     routing::{delete, get},
     Json, Router,
 };
@@ -11,12 +12,18 @@ use uuid::Uuid;
 
 use threadkit_common::{
     auth,
-    types::{AuthProvider, Role, SocialLinks, TreeComment, User, UserPublic},
+    types::{
+        ApiTokenScope, AuthProvider, EditSettings, ReactionSettings, Role, SocialLinks, TreeComment, User, UserPublic,
+        WebhookDelivery, WebhookEndpoint,
+    },
+    webhooks::validate_endpoint_url,
+    ActionType, Error,
 };
 
 use crate::{
-    extractors::{ProjectId, AuthUserWithRole, OwnerAccess},
-    routes::users::find_comment_in_tree,
+    archive,
+    extractors::{OwnerAccess, StaffAccess},
+    routes::{auth::refresh_token_expiry, users::find_comment_in_tree},
     state::AppState,
 };
 
@@ -34,9 +41,22 @@ pub fn router() -> Router<AppState> {
         .route("/admin/sites/{id}/comments", get(get_site_comments))
         // Site comment feed for owners (owner only via secret key)
         .route("/admin/sites/{id}/owner/comments", get(get_site_comments_owner))
+        // Site export (owner only via secret key)
+        .route("/admin/sites/{id}/owner/export", get(export_site))
         // Posting controls (admin+)
         .route("/admin/sites/{id}/posting", get(get_posting_status).put(set_site_posting))
         .route("/admin/pages/{page_id}/posting", get(get_page_posting_status).put(set_page_posting))
+        // Comment editing rules (admin+)
+        .route("/admin/sites/{id}/editing", get(get_edit_settings).put(set_edit_settings))
+        // Reaction emoji set (admin+)
+        .route("/admin/sites/{id}/reactions", get(get_reaction_settings).put(set_reaction_settings))
+        // Webhooks (admin+)
+        .route("/admin/sites/{id}/webhooks", get(get_webhooks).put(set_webhooks))
+        .route("/admin/sites/{id}/webhooks/deliveries", get(get_webhook_deliveries))
+        .route(
+            "/admin/sites/{id}/webhooks/deliveries/{delivery_id}/replay",
+            axum::routing::post(replay_webhook_delivery),
+        )
 }
 
 // ============================================================================
@@ -119,6 +139,8 @@ pub async fn create_user(
     owner: OwnerAccess,
     Json(req): Json<CreateUserRequest>,
 ) -> Result<Json<CreateUserResponse>, (StatusCode, String)> {
+    owner.require_scope(ApiTokenScope::ManageUsers)?;
+
     // Only allow in standalone mode (not SaaS) to prevent username squatting
     use threadkit_common::config::Mode;
     if !matches!(state.config.mode, Mode::Standalone(_)) {
@@ -131,13 +153,13 @@ pub async fn create_user(
     }
 
     // Check if username is already taken
-    if let Ok(Some(_)) = state.redis.get_user_by_username(&req.name).await {
+    if let Ok(Some(_)) = state.store.get_user_by_username(&req.name).await {
         return Err((StatusCode::BAD_REQUEST, "Username already taken".into()));
     }
 
     // Check if email is already taken
     if let Some(ref email) = req.email {
-        if let Ok(Some(_)) = state.redis.get_user_by_email(email).await {
+        if let Ok(Some(_)) = state.store.get_user_by_email(email).await {
             return Err((StatusCode::BAD_REQUEST, "Email already registered".into()));
         }
     }
@@ -161,25 +183,26 @@ pub async fn create_user(
         username_set: true,
         social_links: SocialLinks::default(),
         total_comments: 0,
+        email_notifications: Default::default(),
     };
 
     // Save to Redis
-    state.redis.set_user(&user).await
+    state.store.set_user(&user).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // Index username
-    state.redis.set_user_username_index(&user.name, user_id).await
+    state.store.set_user_username_index(&user.name, user_id).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // Index email if provided
     if let Some(ref email) = req.email {
-        state.redis.set_user_email_index(email, user_id).await
+        state.store.set_user_email_index(email, user_id).await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     }
 
     // Create session
     let session_id = Uuid::now_v7();
-    state.redis.create_session(session_id, user_id, "", "").await
+    state.store.create_session(session_id, user_id, "", "", refresh_token_expiry(&state)).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // Generate JWT token
@@ -217,13 +240,15 @@ pub async fn get_admins(
     owner: OwnerAccess,
     Path(site_id): Path<Uuid>,
 ) -> Result<Json<RoleListResponse>, (StatusCode, String)> {
+    owner.require_scope(ApiTokenScope::ManageUsers)?;
+
     // Verify site_id matches
     if site_id != owner.site_id {
         return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
     }
 
     let admin_ids = state
-        .redis
+        .store
         .get_admins(site_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -231,7 +256,7 @@ pub async fn get_admins(
     // Fetch all admin users in parallel
     let user_futures: Vec<_> = admin_ids
         .iter()
-        .map(|&user_id| state.redis.get_user(user_id))
+        .map(|&user_id| state.store.get_user(user_id))
         .collect();
 
     let user_results = futures::future::join_all(user_futures).await;
@@ -266,26 +291,28 @@ pub async fn add_admin(
     Path(site_id): Path<Uuid>,
     Json(req): Json<AddUserRequest>,
 ) -> Result<StatusCode, (StatusCode, String)> {
+    owner.require_secret_key()?;
+
     if site_id != owner.site_id {
         return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
     }
 
     // Verify user exists
     let _ = state
-        .redis
+        .store
         .get_user(req.user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or((StatusCode::NOT_FOUND, "User not found".into()))?;
 
     state
-        .redis
+        .store
         .add_admin(site_id, req.user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // Remove from moderators if they were one (promotion)
-    let _ = state.redis.remove_moderator(site_id, req.user_id).await;
+    let _ = state.store.remove_moderator(site_id, req.user_id).await;
 
     Ok(StatusCode::OK)
 }
@@ -310,12 +337,14 @@ pub async fn remove_admin(
     owner: OwnerAccess,
     Path((site_id, user_id)): Path<(Uuid, Uuid)>,
 ) -> Result<StatusCode, (StatusCode, String)> {
+    owner.require_secret_key()?;
+
     if site_id != owner.site_id {
         return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
     }
 
     state
-        .redis
+        .store
         .remove_admin(site_id, user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -343,18 +372,17 @@ pub async fn remove_admin(
 )]
 pub async fn get_moderators(
     State(state): State<AppState>,
-    project_id: ProjectId,
-    auth: AuthUserWithRole,
+    auth: StaffAccess,
     Path(site_id): Path<Uuid>,
 ) -> Result<Json<RoleListResponse>, (StatusCode, String)> {
-    auth.require_admin()?;
+    auth.require_admin(ApiTokenScope::ManageUsers)?;
 
-    if site_id != project_id.0.site_id {
+    if site_id != auth.site_id {
         return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
     }
 
     let mod_ids = state
-        .redis
+        .store
         .get_moderators(site_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -362,7 +390,7 @@ pub async fn get_moderators(
     // Fetch all moderator users in parallel
     let user_futures: Vec<_> = mod_ids
         .iter()
-        .map(|&user_id| state.redis.get_user(user_id))
+        .map(|&user_id| state.store.get_user(user_id))
         .collect();
 
     let user_results = futures::future::join_all(user_futures).await;
@@ -394,20 +422,19 @@ pub async fn get_moderators(
 )]
 pub async fn add_moderator(
     State(state): State<AppState>,
-    project_id: ProjectId,
-    auth: AuthUserWithRole,
+    auth: StaffAccess,
     Path(site_id): Path<Uuid>,
     Json(req): Json<AddUserRequest>,
 ) -> Result<StatusCode, (StatusCode, String)> {
-    auth.require_admin()?;
+    auth.require_admin(ApiTokenScope::ManageUsers)?;
 
-    if site_id != project_id.0.site_id {
+    if site_id != auth.site_id {
         return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
     }
 
     // Verify user exists
     let _ = state
-        .redis
+        .store
         .get_user(req.user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
@@ -415,7 +442,7 @@ pub async fn add_moderator(
 
     // Check they're not already an admin
     let role = state
-        .redis
+        .store
         .get_user_role(site_id, req.user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -425,7 +452,7 @@ pub async fn add_moderator(
     }
 
     state
-        .redis
+        .store
         .add_moderator(site_id, req.user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -450,18 +477,17 @@ pub async fn add_moderator(
 )]
 pub async fn remove_moderator(
     State(state): State<AppState>,
-    project_id: ProjectId,
-    auth: AuthUserWithRole,
+    auth: StaffAccess,
     Path((site_id, user_id)): Path<(Uuid, Uuid)>,
 ) -> Result<StatusCode, (StatusCode, String)> {
-    auth.require_admin()?;
+    auth.require_admin(ApiTokenScope::ManageUsers)?;
 
-    if site_id != project_id.0.site_id {
+    if site_id != auth.site_id {
         return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
     }
 
     state
-        .redis
+        .store
         .remove_moderator(site_id, user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -489,14 +515,13 @@ pub async fn remove_moderator(
 )]
 pub async fn get_site_comments(
     State(state): State<AppState>,
-    project_id: ProjectId,
-    auth: AuthUserWithRole,
+    auth: StaffAccess,
     Path(site_id): Path<Uuid>,
     Query(query): Query<SiteCommentsQuery>,
 ) -> Result<Json<SiteCommentsResponse>, (StatusCode, String)> {
-    auth.require_moderator()?;
+    auth.require_moderator(ApiTokenScope::ReadComments)?;
 
-    if site_id != project_id.0.site_id {
+    if site_id != auth.site_id {
         return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
     }
 
@@ -521,7 +546,7 @@ pub async fn get_site_comments(
     // Fetch all needed page trees in parallel
     let tree_futures: Vec<_> = page_ids
         .iter()
-        .map(|&page_id| state.redis.get_page_tree(page_id))
+        .map(|&page_id| state.store.get_page_tree(page_id))
         .collect();
 
     let tree_results = futures::future::join_all(tree_futures).await;
@@ -571,6 +596,8 @@ pub async fn get_site_comments_owner(
     Path(site_id): Path<Uuid>,
     Query(query): Query<SiteCommentsQuery>,
 ) -> Result<Json<SiteCommentsResponse>, (StatusCode, String)> {
+    owner.require_scope(ApiTokenScope::ReadComments)?;
+
     // Verify site_id matches
     if site_id != owner.site_id {
         return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
@@ -597,7 +624,7 @@ pub async fn get_site_comments_owner(
     // Fetch all needed page trees in parallel
     let tree_futures: Vec<_> = page_ids
         .iter()
-        .map(|&page_id| state.redis.get_page_tree(page_id))
+        .map(|&page_id| state.store.get_page_tree(page_id))
         .collect();
 
     let tree_results = futures::future::join_all(tree_futures).await;
@@ -661,18 +688,17 @@ pub struct SetPostingRequest {
     security(("project_id" = []), ("bearer" = []))
 )]
 pub async fn get_posting_status(
-    project_id: ProjectId,
-    auth: AuthUserWithRole,
+    auth: StaffAccess,
     Path(site_id): Path<Uuid>,
 ) -> Result<Json<PostingStatusResponse>, (StatusCode, String)> {
-    auth.require_admin()?;
+    auth.require_admin(ApiTokenScope::ManageSite)?;
 
-    if site_id != project_id.0.site_id {
+    if site_id != auth.site_id {
         return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
     }
 
     Ok(Json(PostingStatusResponse {
-        disabled: project_id.0.settings.posting_disabled,
+        disabled: auth.settings.posting_disabled,
     }))
 }
 
@@ -693,19 +719,18 @@ pub async fn get_posting_status(
 )]
 pub async fn set_site_posting(
     State(state): State<AppState>,
-    project_id: ProjectId,
-    auth: AuthUserWithRole,
+    auth: StaffAccess,
     Path(site_id): Path<Uuid>,
     Json(req): Json<SetPostingRequest>,
 ) -> Result<Json<PostingStatusResponse>, (StatusCode, String)> {
-    auth.require_admin()?;
+    auth.require_admin(ApiTokenScope::ManageSite)?;
 
-    if site_id != project_id.0.site_id {
+    if site_id != auth.site_id {
         return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
     }
 
     // Update settings
-    let mut settings = project_id.0.settings.clone();
+    let mut settings = auth.settings.clone();
     settings.posting_disabled = req.disabled;
 
     state
@@ -722,6 +747,176 @@ pub async fn set_site_posting(
     }))
 }
 
+// ============================================================================
+// Comment Editing Settings Handlers (Admin+)
+// ============================================================================
+
+/// Get the site's comment editing rules (admin+)
+#[utoipa::path(
+    get,
+    path = "/sites/{id}/editing",
+    tag = "admin",
+    params(
+        ("id" = Uuid, Path, description = "Site ID")
+    ),
+    responses(
+        (status = 200, description = "Editing settings", body = EditSettings),
+        (status = 403, description = "Not an admin")
+    ),
+    security(("project_id" = []), ("bearer" = []))
+)]
+pub async fn get_edit_settings(
+    auth: StaffAccess,
+    Path(site_id): Path<Uuid>,
+) -> Result<Json<EditSettings>, (StatusCode, String)> {
+    auth.require_admin(ApiTokenScope::ManageSite)?;
+
+    if site_id != auth.site_id {
+        return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
+    }
+
+    Ok(Json(auth.settings.editing.clone()))
+}
+
+/// Set the edit window and revision visibility (admin+)
+#[utoipa::path(
+    put,
+    path = "/sites/{id}/editing",
+    tag = "admin",
+    params(
+        ("id" = Uuid, Path, description = "Site ID")
+    ),
+    request_body = EditSettings,
+    responses(
+        (status = 200, description = "Editing settings updated", body = EditSettings),
+        (status = 403, description = "Not an admin")
+    ),
+    security(("project_id" = []), ("bearer" = []))
+)]
+pub async fn set_edit_settings(
+    State(state): State<AppState>,
+    auth: StaffAccess,
+    Path(site_id): Path<Uuid>,
+    Json(req): Json<EditSettings>,
+) -> Result<Json<EditSettings>, (StatusCode, String)> {
+    auth.require_admin(ApiTokenScope::ManageSite)?;
+
+    if site_id != auth.site_id {
+        return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
+    }
+
+    let mut settings = auth.settings.clone();
+    settings.editing = req.clone();
+
+    state
+        .redis
+        .update_site_settings(site_id, &settings)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(req))
+}
+
+// ============================================================================
+// Reaction Settings Handlers (Admin+)
+// ============================================================================
+
+/// Maximum emojis in a site's reaction set
+const MAX_REACTION_EMOJIS: usize = 20;
+
+/// Trim and de-duplicate a reaction emoji set, refusing empty or oversized entries
+pub(crate) fn validate_reaction_settings(
+    reactions: ReactionSettings,
+) -> Result<ReactionSettings, (StatusCode, String)> {
+    if reactions.emojis.len() > MAX_REACTION_EMOJIS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("At most {} reaction emojis are allowed", MAX_REACTION_EMOJIS),
+        ));
+    }
+
+    let mut emojis: Vec<String> = Vec::with_capacity(reactions.emojis.len());
+    for emoji in reactions.emojis {
+        let emoji = emoji.trim().to_string();
+        if emoji.is_empty() || emoji.len() > 32 {
+            return Err((StatusCode::BAD_REQUEST, format!("Invalid emoji '{}'", emoji)));
+        }
+        if !emojis.contains(&emoji) {
+            emojis.push(emoji);
+        }
+    }
+    Ok(ReactionSettings { emojis })
+}
+
+/// Get the site's reaction emoji set (admin+)
+#[utoipa::path(
+    get,
+    path = "/sites/{id}/reactions",
+    tag = "admin",
+    params(
+        ("id" = Uuid, Path, description = "Site ID")
+    ),
+    responses(
+        (status = 200, description = "Reaction settings", body = ReactionSettings),
+        (status = 403, description = "Not an admin")
+    ),
+    security(("project_id" = []), ("bearer" = []))
+)]
+pub async fn get_reaction_settings(
+    auth: StaffAccess,
+    Path(site_id): Path<Uuid>,
+) -> Result<Json<ReactionSettings>, (StatusCode, String)> {
+    auth.require_admin(ApiTokenScope::ManageSite)?;
+
+    if site_id != auth.site_id {
+        return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
+    }
+
+    Ok(Json(auth.settings.reactions.clone()))
+}
+
+/// Replace the site's reaction emoji set, an empty list disables reactions (admin+)
+#[utoipa::path(
+    put,
+    path = "/sites/{id}/reactions",
+    tag = "admin",
+    params(
+        ("id" = Uuid, Path, description = "Site ID")
+    ),
+    request_body = ReactionSettings,
+    responses(
+        (status = 200, description = "Reaction settings updated", body = ReactionSettings),
+        (status = 400, description = "Invalid emoji set"),
+        (status = 403, description = "Not an admin")
+    ),
+    security(("project_id" = []), ("bearer" = []))
+)]
+pub async fn set_reaction_settings(
+    State(state): State<AppState>,
+    auth: StaffAccess,
+    Path(site_id): Path<Uuid>,
+    Json(req): Json<ReactionSettings>,
+) -> Result<Json<ReactionSettings>, (StatusCode, String)> {
+    auth.require_admin(ApiTokenScope::ManageSite)?;
+
+    if site_id != auth.site_id {
+        return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
+    }
+
+    let reactions = validate_reaction_settings(req)?;
+
+    let mut settings = auth.settings.clone();
+    settings.reactions = reactions.clone();
+
+    state
+        .redis
+        .update_site_settings(site_id, &settings)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(reactions))
+}
+
 // ============================================================================
 // Page Posting Control Handlers (Admin+)
 // ============================================================================
@@ -742,15 +937,14 @@ pub async fn set_site_posting(
 )]
 pub async fn get_page_posting_status(
     State(state): State<AppState>,
-    project_id: ProjectId,
-    auth: AuthUserWithRole,
+    auth: StaffAccess,
     Path(page_id): Path<Uuid>,
 ) -> Result<Json<PostingStatusResponse>, (StatusCode, String)> {
-    auth.require_admin()?;
+    auth.require_admin(ApiTokenScope::ManageSite)?;
 
     let is_locked = state
         .redis
-        .is_page_locked(project_id.0.site_id, page_id)
+        .is_page_locked(auth.site_id, page_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
@@ -774,23 +968,22 @@ pub async fn get_page_posting_status(
 )]
 pub async fn set_page_posting(
     State(state): State<AppState>,
-    project_id: ProjectId,
-    auth: AuthUserWithRole,
+    auth: StaffAccess,
     Path(page_id): Path<Uuid>,
     Json(req): Json<SetPostingRequest>,
 ) -> Result<Json<PostingStatusResponse>, (StatusCode, String)> {
-    auth.require_admin()?;
+    auth.require_admin(ApiTokenScope::ManageSite)?;
 
     if req.disabled {
         state
             .redis
-            .lock_page(project_id.0.site_id, page_id)
+            .lock_page(auth.site_id, page_id)
             .await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     } else {
         state
             .redis
-            .unlock_page(project_id.0.site_id, page_id)
+            .unlock_page(auth.site_id, page_id)
             .await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     }
@@ -799,3 +992,302 @@ pub async fn set_page_posting(
         disabled: req.disabled,
     }))
 }
+
+// ============================================================================
+// Webhook Types
+// ============================================================================
+
+/// Maximum webhook endpoints per site
+const MAX_WEBHOOK_ENDPOINTS: usize = 10;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhooksResponse {
+    /// Configured webhook endpoints
+    pub webhooks: Vec<WebhookEndpoint>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct WebhookEndpointInput {
+    /// Existing endpoint ID (omit to create a new endpoint)
+    pub id: Option<Uuid>,
+    /// URL that receives signed POST requests (http or https)
+    pub url: String,
+    /// Event types to deliver (empty = all events)
+    #[serde(default)]
+    pub events: Vec<ActionType>,
+    /// Whether the endpoint is active (default: true)
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetWebhooksRequest {
+    /// Full list of endpoints (replaces the current list)
+    pub webhooks: Vec<WebhookEndpointInput>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct WebhookDeliveriesQuery {
+    /// Number of deliveries to skip (default: 0)
+    #[param(default = 0)]
+    pub offset: Option<usize>,
+    /// Maximum number of deliveries to return (default: 50, max: 100)
+    #[param(default = 50)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookDeliveriesResponse {
+    /// Recent deliveries, newest first
+    pub deliveries: Vec<WebhookDelivery>,
+    /// Whether there are more deliveries available
+    pub has_more: bool,
+}
+
+// ============================================================================
+// Webhook Handlers (Admin+)
+// ============================================================================
+
+/// Get configured webhook endpoints (admin+)
+#[utoipa::path(
+    get,
+    path = "/sites/{id}/webhooks",
+    tag = "admin",
+    params(
+        ("id" = Uuid, Path, description = "Site ID")
+    ),
+    responses(
+        (status = 200, description = "Webhook endpoints", body = WebhooksResponse),
+        (status = 403, description = "Not an admin")
+    ),
+    security(("project_id" = []), ("bearer" = []))
+)]
+pub async fn get_webhooks(
+    auth: StaffAccess,
+    Path(site_id): Path<Uuid>,
+) -> Result<Json<WebhooksResponse>, (StatusCode, String)> {
+    auth.require_admin(ApiTokenScope::ManageSite)?;
+
+    if site_id != auth.site_id {
+        return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
+    }
+
+    Ok(Json(WebhooksResponse {
+        webhooks: auth.settings.webhooks.clone(),
+    }))
+}
+
+/// Replace the site's webhook endpoints (admin+)
+#[utoipa::path(
+    put,
+    path = "/sites/{id}/webhooks",
+    tag = "admin",
+    params(
+        ("id" = Uuid, Path, description = "Site ID")
+    ),
+    request_body = SetWebhooksRequest,
+    responses(
+        (status = 200, description = "Webhook endpoints updated", body = WebhooksResponse),
+        (status = 400, description = "Invalid endpoint"),
+        (status = 403, description = "Not an admin")
+    ),
+    security(("project_id" = []), ("bearer" = []))
+)]
+pub async fn set_webhooks(
+    State(state): State<AppState>,
+    auth: StaffAccess,
+    Path(site_id): Path<Uuid>,
+    Json(req): Json<SetWebhooksRequest>,
+) -> Result<Json<WebhooksResponse>, (StatusCode, String)> {
+    auth.require_admin(ApiTokenScope::ManageSite)?;
+
+    if site_id != auth.site_id {
+        return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
+    }
+
+    if req.webhooks.len() > MAX_WEBHOOK_ENDPOINTS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("At most {} webhook endpoints allowed", MAX_WEBHOOK_ENDPOINTS),
+        ));
+    }
+
+    let mut webhooks = Vec::with_capacity(req.webhooks.len());
+    for input in req.webhooks {
+        validate_endpoint_url(&input.url, state.config.webhooks.allow_private_targets)
+            .await
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+        webhooks.push(WebhookEndpoint {
+            id: input.id.unwrap_or_else(Uuid::now_v7),
+            url: input.url,
+            events: input.events,
+            enabled: input.enabled.unwrap_or(true),
+        });
+    }
+
+    // Start from the stored settings rather than the (possibly cached) request context
+    let mut config = state
+        .redis
+        .get_site_config(site_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Site not found".into()))?;
+    config.settings.webhooks = webhooks.clone();
+
+    state
+        .redis
+        .update_site_settings(site_id, &config.settings)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Drop cached API key lookups so the new settings are visible right away
+    let _ = state.redis.invalidate_project_id_cache(&config.project_id_public).await;
+    let _ = state.redis.invalidate_project_id_cache(&config.project_id_secret).await;
+
+    Ok(Json(WebhooksResponse { webhooks }))
+}
+
+/// List recent webhook deliveries (admin+)
+#[utoipa::path(
+    get,
+    path = "/sites/{id}/webhooks/deliveries",
+    tag = "admin",
+    params(
+        ("id" = Uuid, Path, description = "Site ID"),
+        WebhookDeliveriesQuery
+    ),
+    responses(
+        (status = 200, description = "Recent deliveries", body = WebhookDeliveriesResponse),
+        (status = 403, description = "Not an admin")
+    ),
+    security(("project_id" = []), ("bearer" = []))
+)]
+pub async fn get_webhook_deliveries(
+    State(state): State<AppState>,
+    auth: StaffAccess,
+    Path(site_id): Path<Uuid>,
+    Query(query): Query<WebhookDeliveriesQuery>,
+) -> Result<Json<WebhookDeliveriesResponse>, (StatusCode, String)> {
+    auth.require_admin(ApiTokenScope::ManageSite)?;
+
+    if site_id != auth.site_id {
+        return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
+    }
+
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+
+    let mut deliveries = state
+        .redis
+        .get_site_webhook_deliveries(site_id, offset, limit + 1)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let has_more = deliveries.len() > limit;
+    deliveries.truncate(limit);
+
+    Ok(Json(WebhookDeliveriesResponse { deliveries, has_more }))
+}
+
+/// Replay a finished webhook delivery (admin+)
+///
+/// Resets the attempt counter and queues the delivery to be sent again immediately.
+#[utoipa::path(
+    post,
+    path = "/sites/{id}/webhooks/deliveries/{delivery_id}/replay",
+    tag = "admin",
+    params(
+        ("id" = Uuid, Path, description = "Site ID"),
+        ("delivery_id" = Uuid, Path, description = "Delivery ID")
+    ),
+    responses(
+        (status = 200, description = "Delivery queued", body = WebhookDelivery),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "Delivery not found"),
+        (status = 409, description = "Delivery is already queued")
+    ),
+    security(("project_id" = []), ("bearer" = []))
+)]
+pub async fn replay_webhook_delivery(
+    State(state): State<AppState>,
+    auth: StaffAccess,
+    Path((site_id, delivery_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<WebhookDelivery>, (StatusCode, String)> {
+    auth.require_admin(ApiTokenScope::ManageSite)?;
+
+    if site_id != auth.site_id {
+        return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
+    }
+
+    // Deliveries of other sites are reported as missing
+    let delivery = state
+        .redis
+        .get_webhook_delivery(delivery_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if delivery.is_none_or(|d| d.site_id != site_id) {
+        return Err((StatusCode::NOT_FOUND, "Delivery not found".into()));
+    }
+
+    let delivery = state
+        .webhooks
+        .replay(delivery_id)
+        .await
+        .map_err(|e| match e {
+            Error::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
+            Error::BadRequest(msg) => (StatusCode::CONFLICT, msg),
+            e => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+
+    Ok(Json(delivery))
+}
+
+// ============================================================================
+// Site Export (Owner Only)
+// ============================================================================
+
+/// Export the site as a JSON-lines archive (owner only - requires secret API key)
+///
+/// Contains the site config, page trees, roles, pins, locked pages, reports, media
+/// metadata and the users who commented. Restore it with `threadkit-http --restore`.
+#[utoipa::path(
+    get,
+    path = "/sites/{id}/owner/export",
+    tag = "admin",
+    params(
+        ("id" = Uuid, Path, description = "Site ID")
+    ),
+    responses(
+        (status = 200, description = "Site archive, one JSON record per line", content_type = "application/x-ndjson", body = String),
+        (status = 403, description = "Not the owner")
+    ),
+    security(("secret_key" = []))
+)]
+pub async fn export_site(
+    State(state): State<AppState>,
+    owner: OwnerAccess,
+    Path(site_id): Path<Uuid>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    // The archive holds all users' personal data - not for scoped tokens
+    owner.require_secret_key()?;
+
+    if site_id != owner.site_id {
+        return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
+    }
+
+    let mut body = Vec::new();
+    archive::export_site(&state.redis, state.store.as_ref(), site_id, &mut body)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-ndjson".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"threadkit-{}.jsonl\"", site_id),
+            ),
+        ],
+        body,
+    ))
+}
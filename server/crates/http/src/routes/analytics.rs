@@ -0,0 +1,183 @@
+//! Site analytics for site owners and admins
+//!
+//! Time series of pageviews, comments, unique visitors, active commenters, WebSocket
+//! messages and peak concurrent connections, plus the pages with the most engagement.
+//! Visitors and commenters are counted with HyperLogLogs, so they're approximate
+//! (about 1% standard error) and totals over a range are unions, not sums.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use threadkit_common::types::{AnalyticsGranularity, AnalyticsPoint, ApiTokenScope, TopPage};
+
+use crate::{
+    extractors::StaffAccess,
+    state::AppState,
+};
+
+/// Default number of top pages returned
+const DEFAULT_TOP_PAGES: usize = 10;
+
+/// Maximum number of top pages returned
+const MAX_TOP_PAGES: usize = 50;
+
+/// A comment counts as much engagement as this many pageviews when ranking pages
+const COMMENT_ENGAGEMENT_WEIGHT: f64 = 10.0;
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/admin/sites/{id}/analytics", get(get_site_analytics))
+}
+
+// ============================================================================
+// Request/Response Types
+// ============================================================================
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct AnalyticsQuery {
+    /// Bucket size: `hour` (kept 7 days) or `day` (kept 90 days, default)
+    pub granularity: Option<AnalyticsGranularity>,
+    /// Start of the range (default: 24 hours or 30 days before `to`)
+    pub from: Option<DateTime<Utc>>,
+    /// End of the range (default: now)
+    pub to: Option<DateTime<Utc>>,
+    /// Number of top pages to return (default: 10, max: 50)
+    #[param(default = 10)]
+    pub top_pages: Option<usize>,
+}
+
+/// Activity over the whole range
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AnalyticsTotals {
+    pub pageviews: u64,
+    pub comments: u64,
+    /// Distinct visitors across the range, approximate
+    pub unique_visitors: u64,
+    /// Distinct signed-in commenters across the range, approximate
+    pub active_commenters: u64,
+    /// Highest peak of any bucket
+    pub peak_connections: u64,
+    pub websocket_messages: u64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AnalyticsResponse {
+    pub granularity: AnalyticsGranularity,
+    /// Start of the first bucket
+    pub from: DateTime<Utc>,
+    /// End of the range
+    pub to: DateTime<Utc>,
+    /// One point per bucket, oldest first (buckets without activity are zero)
+    pub series: Vec<AnalyticsPoint>,
+    pub totals: AnalyticsTotals,
+    /// Pages ranked by pageviews plus 10 per comment, over the days in the range
+    pub top_pages: Vec<TopPage>,
+}
+
+// ============================================================================
+// Handlers
+// ============================================================================
+
+/// Get a site's analytics (admin+, or an API token with `read_analytics`)
+#[utoipa::path(
+    get,
+    path = "/sites/{id}/analytics",
+    tag = "admin",
+    params(
+        ("id" = Uuid, Path, description = "Site ID"),
+        AnalyticsQuery
+    ),
+    responses(
+        (status = 200, description = "Time series, totals and top pages", body = AnalyticsResponse),
+        (status = 400, description = "Invalid range"),
+        (status = 403, description = "Not an admin")
+    ),
+    security(("project_id" = []), ("bearer" = []))
+)]
+pub async fn get_site_analytics(
+    State(state): State<AppState>,
+    auth: StaffAccess,
+    Path(site_id): Path<Uuid>,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<Json<AnalyticsResponse>, (StatusCode, String)> {
+    auth.require_admin(ApiTokenScope::ReadAnalytics)?;
+
+    if site_id != auth.site_id {
+        return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
+    }
+
+    let granularity = query.granularity.unwrap_or_default();
+    let now = Utc::now();
+    let to = query.to.unwrap_or(now).min(now);
+    let from = query.from.unwrap_or_else(|| {
+        to - match granularity {
+            AnalyticsGranularity::Hour => Duration::hours(24),
+            AnalyticsGranularity::Day => Duration::days(30),
+        }
+    });
+    if from > to {
+        return Err((StatusCode::BAD_REQUEST, "from must be before to".into()));
+    }
+    // Older buckets have expired
+    let from = granularity.truncate(from.max(now - granularity.retention()));
+    let top_pages = query.top_pages.unwrap_or(DEFAULT_TOP_PAGES).min(MAX_TOP_PAGES);
+
+    let internal = |e: threadkit_common::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    let starts = bucket_starts(granularity, from, to);
+    let series = state
+        .redis
+        .get_analytics(site_id, granularity, &starts)
+        .await
+        .map_err(internal)?;
+    let (unique_visitors, active_commenters) = state
+        .redis
+        .count_analytics_uniques(site_id, granularity, &starts)
+        .await
+        .map_err(internal)?;
+    let days = bucket_starts(AnalyticsGranularity::Day, AnalyticsGranularity::Day.truncate(from), to);
+    let top_pages = state
+        .redis
+        .get_top_pages(site_id, &days, COMMENT_ENGAGEMENT_WEIGHT, top_pages)
+        .await
+        .map_err(internal)?;
+
+    let totals = AnalyticsTotals {
+        pageviews: series.iter().map(|p| p.pageviews).sum(),
+        comments: series.iter().map(|p| p.comments).sum(),
+        unique_visitors,
+        active_commenters,
+        peak_connections: series.iter().map(|p| p.peak_connections).max().unwrap_or(0),
+        websocket_messages: series.iter().map(|p| p.websocket_messages).sum(),
+    };
+
+    Ok(Json(AnalyticsResponse {
+        granularity,
+        from,
+        to,
+        series,
+        totals,
+        top_pages,
+    }))
+}
+
+// ============================================================================
+// Helper Functions
+// ============================================================================
+
+/// Starts of the buckets from `from` (a bucket start) up to the one containing `to`
+fn bucket_starts(granularity: AnalyticsGranularity, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+    let mut starts = Vec::new();
+    let mut start = from;
+    while start <= to {
+        starts.push(start);
+        start += granularity.step();
+    }
+    starts
+}
@@ -0,0 +1,267 @@
+//! Scoped API tokens, minted and revoked by the site owner with the secret key
+//!
+//! The token itself is only returned when it's created; Redis keeps its SHA-256 hash.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use threadkit_common::{
+    api_tokens::{self, IpRange},
+    auth::{generate_api_key, API_TOKEN_PREFIX},
+    types::{ApiToken, ApiTokenScope},
+};
+
+use crate::{extractors::OwnerAccess, state::AppState};
+
+/// Maximum length of a token name
+const MAX_TOKEN_NAME_LENGTH: usize = 100;
+
+/// Maximum tokens per site
+const MAX_TOKENS_PER_SITE: usize = 50;
+
+/// Maximum IP allowlist entries per token
+const MAX_ALLOWED_IPS: usize = 20;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/admin/sites/{id}/tokens", get(list_tokens).post(create_token))
+        .route("/admin/sites/{id}/tokens/{token_id}", delete(revoke_token))
+}
+
+// ============================================================================
+// Request/Response Types
+// ============================================================================
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateTokenRequest {
+    /// What the token is for, e.g. "CMS backend"
+    pub name: String,
+    /// At least one scope
+    pub scopes: Vec<ApiTokenScope>,
+    /// When the token stops working (default: never)
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Client IPs or CIDR ranges the token may be used from (default: any)
+    #[serde(default)]
+    pub allowed_ips: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreateTokenResponse {
+    /// The token, use it in the `projectid` header. Shown only once.
+    pub token: String,
+    #[serde(flatten)]
+    pub details: ApiToken,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TokensResponse {
+    /// Oldest first
+    pub tokens: Vec<ApiToken>,
+}
+
+// ============================================================================
+// Handlers
+// ============================================================================
+
+/// List the site's API tokens (owner only - requires secret API key)
+#[utoipa::path(
+    get,
+    path = "/sites/{id}/tokens",
+    tag = "admin",
+    params(
+        ("id" = Uuid, Path, description = "Site ID")
+    ),
+    responses(
+        (status = 200, description = "API tokens (without the tokens themselves)", body = TokensResponse),
+        (status = 403, description = "Not the owner")
+    ),
+    security(("secret_key" = []))
+)]
+pub async fn list_tokens(
+    State(state): State<AppState>,
+    owner: OwnerAccess,
+    Path(site_id): Path<Uuid>,
+) -> Result<Json<TokensResponse>, (StatusCode, String)> {
+    owner.require_secret_key()?;
+
+    if site_id != owner.site_id {
+        return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
+    }
+
+    let tokens = state
+        .redis
+        .list_api_tokens(site_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(TokensResponse { tokens }))
+}
+
+/// Create an API token (owner only - requires secret API key)
+#[utoipa::path(
+    post,
+    path = "/sites/{id}/tokens",
+    tag = "admin",
+    params(
+        ("id" = Uuid, Path, description = "Site ID")
+    ),
+    request_body = CreateTokenRequest,
+    responses(
+        (status = 200, description = "Token created", body = CreateTokenResponse),
+        (status = 400, description = "Invalid name, scopes, expiry or IP allowlist, or too many tokens"),
+        (status = 403, description = "Not the owner")
+    ),
+    security(("secret_key" = []))
+)]
+pub async fn create_token(
+    State(state): State<AppState>,
+    owner: OwnerAccess,
+    Path(site_id): Path<Uuid>,
+    Json(req): Json<CreateTokenRequest>,
+) -> Result<Json<CreateTokenResponse>, (StatusCode, String)> {
+    owner.require_secret_key()?;
+
+    if site_id != owner.site_id {
+        return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
+    }
+
+    let name = validate_name(&req.name)?;
+    let scopes = validate_scopes(req.scopes)?;
+    let allowed_ips = validate_allowed_ips(req.allowed_ips)?;
+    if req.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        return Err((StatusCode::BAD_REQUEST, "expires_at must be in the future".into()));
+    }
+
+    let existing = state
+        .redis
+        .list_api_tokens(site_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if existing.len() >= MAX_TOKENS_PER_SITE {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("A site can have at most {} API tokens", MAX_TOKENS_PER_SITE),
+        ));
+    }
+
+    let token = generate_api_key(API_TOKEN_PREFIX);
+    let details = ApiToken {
+        id: Uuid::now_v7(),
+        site_id,
+        name,
+        scopes,
+        hint: token[token.len() - 4..].to_string(),
+        created_at: Utc::now(),
+        expires_at: req.expires_at,
+        allowed_ips,
+        last_used_at: None,
+    };
+
+    state
+        .redis
+        .create_api_token(&details, &api_tokens::hash_token(&token))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(CreateTokenResponse { token, details }))
+}
+
+/// Revoke an API token (owner only - requires secret API key)
+///
+/// Takes effect immediately.
+#[utoipa::path(
+    delete,
+    path = "/sites/{id}/tokens/{token_id}",
+    tag = "admin",
+    params(
+        ("id" = Uuid, Path, description = "Site ID"),
+        ("token_id" = Uuid, Path, description = "Token ID")
+    ),
+    responses(
+        (status = 204, description = "Token revoked"),
+        (status = 403, description = "Not the owner"),
+        (status = 404, description = "Token not found")
+    ),
+    security(("secret_key" = []))
+)]
+pub async fn revoke_token(
+    State(state): State<AppState>,
+    owner: OwnerAccess,
+    Path((site_id, token_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    owner.require_secret_key()?;
+
+    if site_id != owner.site_id {
+        return Err((StatusCode::FORBIDDEN, "Site ID mismatch".into()));
+    }
+
+    let revoked = state
+        .redis
+        .revoke_api_token(site_id, token_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if revoked {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err((StatusCode::NOT_FOUND, "Token not found".into()))
+    }
+}
+
+// ============================================================================
+// Validation
+// ============================================================================
+
+fn validate_name(name: &str) -> Result<String, (StatusCode, String)> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Name cannot be empty".into()));
+    }
+    if name.chars().count() > MAX_TOKEN_NAME_LENGTH {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Name must be at most {} characters", MAX_TOKEN_NAME_LENGTH),
+        ));
+    }
+    Ok(name.to_string())
+}
+
+fn validate_scopes(mut scopes: Vec<ApiTokenScope>) -> Result<Vec<ApiTokenScope>, (StatusCode, String)> {
+    let mut seen = std::collections::HashSet::new();
+    scopes.retain(|scope| seen.insert(*scope));
+    if scopes.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "A token needs at least one scope".into()));
+    }
+    Ok(scopes)
+}
+
+fn validate_allowed_ips(allowed_ips: Vec<String>) -> Result<Vec<String>, (StatusCode, String)> {
+    if allowed_ips.len() > MAX_ALLOWED_IPS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("At most {} allowed IPs", MAX_ALLOWED_IPS),
+        ));
+    }
+    allowed_ips
+        .into_iter()
+        .map(|entry| {
+            let entry = entry.trim().to_string();
+            match IpRange::parse(&entry) {
+                Some(_) => Ok(entry),
+                None => Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid allowed IP '{}' (expected an IP address or CIDR range)", entry),
+                )),
+            }
+        })
+        .collect()
+}
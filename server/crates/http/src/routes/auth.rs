@@ -5,18 +5,25 @@ use axum::{
     routing::{get, post},
     Json, Router,
 };
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
 use threadkit_common::{
+    api_tokens::hash_token,
     auth::{self, generate_verification_code},
-    types::{AuthProvider, SocialLinks, User, VerificationCode, VerificationType},
+    types::{
+        AuthEvent, AuthProvider, RefreshRotation, SocialLinks, User, VerificationCode,
+        VerificationType,
+    },
     web3,
 };
 
-use crate::{extractors::ProjectId, state::AppState};
+use crate::{
+    extractors::{ClientIp, ProjectId},
+    state::AppState,
+};
 
 /// API routes for auth (goes under /v1)
 pub fn router() -> Router<AppState> {
@@ -49,7 +56,7 @@ pub fn oauth_router() -> Router<AppState> {
 pub struct AuthResponse {
     /// JWT access token
     pub token: String,
-    /// JWT refresh token (longer lived)
+    /// Opaque refresh token, exchanged for new tokens at `/auth/refresh`. Single use.
     pub refresh_token: String,
     /// User details
     pub user: UserResponse,
@@ -85,7 +92,7 @@ impl From<User> for UserResponse {
 
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct RefreshRequest {
-    /// Refresh token from login/register
+    /// Refresh token from login/register or the previous refresh
     pub refresh_token: String,
 }
 
@@ -261,8 +268,8 @@ pub async fn auth_methods(
 )]
 pub async fn send_otp(
     State(state): State<AppState>,
-    _project_id: ProjectId,
-    headers: axum::http::HeaderMap,
+    project_id: ProjectId,
+    ClientIp(client_ip): ClientIp,
     Json(req): Json<SendOtpRequest>,
 ) -> Result<StatusCode, (StatusCode, String)> {
     let target = &req.email;
@@ -270,7 +277,7 @@ pub async fn send_otp(
     // OTP-specific rate limiting (more strict since it costs money)
     // 1. Rate limit per target (email)
     let target_key = format!("ratelimit:otp:target:{}", target);
-    let target_result = state.redis
+    let target_result = state.store
         .check_rate_limit(&target_key, state.config.rate_limit.otp_per_target_per_hour, 3600)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -283,14 +290,8 @@ pub async fn send_otp(
     }
 
     // 2. Rate limit per IP (prevent enumeration attacks)
-    let client_ip = headers
-        .get("x-forwarded-for")
-        .and_then(|v| v.to_str().ok())
-        .and_then(|s| s.split(',').next())
-        .unwrap_or("unknown")
-        .trim();
-    let ip_key = format!("ratelimit:otp:ip:{}", client_ip);
-    let ip_result = state.redis
+    let ip_key = format!("ratelimit:otp:ip:{}", client_ip.as_deref().unwrap_or("unknown"));
+    let ip_result = state.store
         .check_rate_limit(&ip_key, state.config.rate_limit.otp_per_ip_per_hour, 3600)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -315,17 +316,15 @@ pub async fn send_otp(
     state.redis.set_verification_code(target, &verification).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    // Send OTP via email
-    if let Some(ref provider) = state.config.email.provider {
-        match provider {
-            threadkit_common::config::EmailProvider::Resend(resend) => {
-                send_otp_email_resend(resend, &req.email, &code).await
-                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
-            }
-        }
-    } else {
-        tracing::info!("OTP for {}: {} (email provider not configured)", req.email, code);
-    }
+    // Send OTP via email (logged when no provider is configured)
+    let site = state.redis.get_site_config(project_id.0.site_id).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Site not found".to_string()))?;
+    state.mailer.send_otp(&site, &req.email, &code).await
+        .map_err(|e| {
+            tracing::error!("Failed to send OTP email: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to send email".to_string())
+        })?;
 
     Ok(StatusCode::OK)
 }
@@ -351,7 +350,7 @@ pub async fn verify_otp(
 
     // Rate limit verification attempts (5 attempts per 10 minutes)
     let verify_key = format!("ratelimit:otp:verify:{}", key);
-    state.redis.check_rate_limit(&verify_key, 5, 600).await
+    state.store.check_rate_limit(&verify_key, 5, 600).await
         .map_err(|_| (StatusCode::TOO_MANY_REQUESTS, "Too many verification attempts. Please request a new code.".into()))?;
 
     let verification = state.redis.get_verification_code(key).await
@@ -363,17 +362,17 @@ pub async fn verify_otp(
     }
 
     // Find or create user
-    let existing_user_id = state.redis.get_user_by_email(key).await
+    let existing_user_id = state.store.get_user_by_email(key).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     let user = if let Some(user_id) = existing_user_id {
-        let mut user = state.redis.get_user(user_id).await
+        let mut user = state.store.get_user(user_id).await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
             .ok_or((StatusCode::INTERNAL_SERVER_ERROR, "User not found".into()))?;
 
         // Mark as verified
         user.email_verified = true;
-        let _ = state.redis.set_user(&user).await;
+        let _ = state.store.set_user(&user).await;
         user
     } else {
         // Create new user - require name for new accounts
@@ -383,7 +382,7 @@ pub async fn verify_otp(
         threadkit_common::validate_username(&name)
             .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
 
-        if !state.redis.is_username_available(&name, None).await
+        if !state.store.is_username_available(&name, None).await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))? {
             return Err((StatusCode::CONFLICT, "Username already taken".into()));
         }
@@ -408,43 +407,26 @@ pub async fn verify_otp(
             username_set: true, // User explicitly chose this username
             social_links: SocialLinks::default(),
             total_comments: 0,
+            email_notifications: Default::default(),
         };
 
-        state.redis.set_user(&user).await
+        state.store.set_user(&user).await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-        state.redis.set_user_username_index(&name, user_id).await
+        state.store.set_user_username_index(&name, user_id).await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-        state.redis.set_user_email_index(&req.email, user_id).await
+        state.store.set_user_email_index(&req.email, user_id).await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
         user
     };
 
     // Create session and tokens
-    let session_id = Uuid::now_v7();
-    state.redis.create_session(session_id, user.id, "", "").await
+    let (token, refresh_token) = start_session(&state, user.id, project_id.0.site_id)
+        .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let token = auth::create_token(
-        user.id,
-        project_id.0.site_id,
-        session_id,
-        &state.config.jwt_secret,
-        state.config.jwt_expiry_hours,
-    )
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-    let refresh_token = auth::create_token(
-        user.id,
-        project_id.0.site_id,
-        session_id,
-        &state.config.jwt_secret,
-        24 * 365 * 100, // ~100 years
-    )
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
     // Delete used OTP code only after successful authentication
     let _ = state.redis.delete_verification_code(key).await;
 
@@ -455,6 +437,38 @@ pub async fn verify_otp(
     }))
 }
 
+/// Start a session for a user and issue its access and refresh tokens
+async fn start_session(
+    state: &AppState,
+    user_id: Uuid,
+    site_id: Uuid,
+) -> threadkit_common::Result<(String, String)> {
+    let session_id = Uuid::now_v7();
+    let expires_at = refresh_token_expiry(state);
+    state.store.create_session(session_id, user_id, "", "", expires_at).await?;
+
+    let token = auth::create_token(
+        user_id,
+        site_id,
+        session_id,
+        &state.config.jwt_secret,
+        state.config.jwt_expiry_hours,
+    )?;
+
+    let refresh_token = auth::generate_refresh_token(session_id);
+    state
+        .store
+        .set_refresh_token(session_id, &hash_token(&refresh_token), expires_at)
+        .await?;
+
+    Ok((token, refresh_token))
+}
+
+/// When a refresh token issued now expires
+pub(crate) fn refresh_token_expiry(state: &AppState) -> DateTime<Utc> {
+    Utc::now() + Duration::days(state.config.refresh_token_expiry_days as i64)
+}
+
 /// Generate random alphanumeric string
 fn generate_random_id(length: usize) -> String {
     use rand::Rng;
@@ -529,37 +543,20 @@ pub async fn anonymous_login(
         username_set: true, // Anonymous users don't need to set username
         social_links: SocialLinks::default(),
         total_comments: 0,
+        email_notifications: Default::default(),
     };
 
-    state.redis.set_user(&user).await
+    state.store.set_user(&user).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    state.redis.set_user_username_index(&username, user_id).await
+    state.store.set_user_username_index(&username, user_id).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // Create session and tokens
-    let session_id = Uuid::now_v7();
-    state.redis.create_session(session_id, user_id, "", "").await
+    let (token, refresh_token) = start_session(&state, user_id, project_id.0.site_id)
+        .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let token = auth::create_token(
-        user_id,
-        project_id.0.site_id,
-        session_id,
-        &state.config.jwt_secret,
-        state.config.jwt_expiry_hours,
-    )
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-    let refresh_token = auth::create_token(
-        user_id,
-        project_id.0.site_id,
-        session_id,
-        &state.config.jwt_secret,
-        24 * 365 * 100, // ~100 years
-    )
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
     Ok(Json(AuthResponse {
         token,
         refresh_token,
@@ -567,72 +564,11 @@ pub async fn anonymous_login(
     }))
 }
 
-/// Helper to send OTP via Resend
-async fn send_otp_email_resend(config: &threadkit_common::config::ResendConfig, email: &str, code: &str) -> Result<(), String> {
-    let client = reqwest::Client::new();
-    let from_email = format!("ThreadKit <noreply@{}>", config.from_domain);
-
-    let html = format!(r#"<!DOCTYPE html>
-<html>
-<head>
-  <meta charset="utf-8">
-  <meta name="viewport" content="width=device-width, initial-scale=1.0">
-</head>
-<body style="background-color: #f4f4f5; font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, 'Helvetica Neue', Ubuntu, sans-serif; margin: 0; padding: 0;">
-  <div style="background-color: #ffffff; border: 1px solid #e4e4e7; border-radius: 8px; margin: 40px auto; padding: 40px; max-width: 480px;">
-    <div style="text-align: center; margin: 0 0 32px;">
-      <img src="https://usethreadkit.com/email-logo.png" width="48" height="48" alt="ThreadKit" style="margin: 0 auto;">
-      <p style="color: #18181b; font-size: 20px; font-weight: 700; text-align: center; margin: 12px 0 0;">ThreadKit</p>
-    </div>
-
-    <p style="color: #3f3f46; font-size: 15px; line-height: 24px; text-align: center; margin: 0 0 16px;">
-      Your sign in code:
-    </p>
-
-    <p style="background-color: #f4f4f5; border: 1px solid #e4e4e7; border-radius: 8px; color: #18181b; font-size: 32px; font-weight: 700; letter-spacing: 4px; padding: 20px; text-align: center; font-family: monospace; margin: 24px 0;">
-      {code}
-    </p>
-
-    <p style="color: #71717a; font-size: 14px; text-align: center; margin: 0;">
-      This code expires in 10 minutes.
-    </p>
-
-    <hr style="border: none; border-top: 1px solid #e4e4e7; margin: 32px 0;">
-
-    <p style="color: #a1a1aa; font-size: 13px; text-align: center; margin: 0;">
-      <a href="https://usethreadkit.com" style="color: #71717a; text-decoration: underline;">ThreadKit</a>
-    </p>
-  </div>
-</body>
-</html>"#, code = code);
-
-    let body = serde_json::json!({
-        "from": from_email,
-        "to": [email],
-        "subject": format!("{} - Sign in to ThreadKit", code),
-        "html": html
-    });
-
-    let response = client
-        .post("https://api.resend.com/emails")
-        .header("Authorization", format!("Bearer {}", config.api_key))
-        .header("Content-Type", "application/json")
-        .json(&body)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
-
-    if !response.status().is_success() {
-        let error = response.text().await.unwrap_or_default();
-        tracing::error!("Resend API error: {}", error);
-        return Err(error);
-    }
-
-    Ok(())
-}
-
-
 /// Refresh access token
+///
+/// Refresh tokens are single-use: each call returns a new one. Presenting a refresh
+/// token that was already used revokes its session, unless it was exchanged within the
+/// last few seconds (e.g. by a concurrent refresh), which gets `409` instead.
 #[utoipa::path(
     post,
     path = "/auth/refresh",
@@ -640,7 +576,8 @@ async fn send_otp_email_resend(config: &threadkit_common::config::ResendConfig,
     request_body = RefreshRequest,
     responses(
         (status = 200, description = "New tokens issued", body = AuthResponse),
-        (status = 401, description = "Invalid or expired refresh token")
+        (status = 401, description = "Invalid, expired or reused refresh token"),
+        (status = 409, description = "Refresh token was just exchanged by another request")
     ),
     security(("project_id" = []))
 )]
@@ -649,38 +586,57 @@ pub async fn refresh_token(
     project_id: ProjectId,
     Json(req): Json<RefreshRequest>,
 ) -> Result<Json<AuthResponse>, (StatusCode, String)> {
-    let claims = auth::verify_token(&req.refresh_token, &state.config.jwt_secret)
-        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid refresh token".into()))?;
-
-    let session_user = state.redis.get_session_user(claims.session_id).await
+    let session_id = auth::parse_refresh_token(&req.refresh_token)
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid refresh token".into()))?;
+
+    let refresh_token = auth::generate_refresh_token(session_id);
+    let rotation = state
+        .store
+        .rotate_refresh_token(
+            session_id,
+            &hash_token(&req.refresh_token),
+            &hash_token(&refresh_token),
+            refresh_token_expiry(&state),
+            Duration::seconds(state.config.refresh_reuse_grace_secs as i64),
+        )
+        .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    if session_user != Some(claims.sub) {
-        return Err((StatusCode::UNAUTHORIZED, "Session expired".into()));
-    }
+    let user_id = match rotation {
+        RefreshRotation::Rotated { user_id } => user_id,
+        RefreshRotation::Superseded { .. } => {
+            // A concurrent refresh won the race - the client should use the token it got
+            return Err((StatusCode::CONFLICT, "Refresh token was just used, use the new one".into()));
+        }
+        RefreshRotation::Reused { user_id } => {
+            // Either the client or an attacker holds a stolen copy - end the session for both
+            tracing::warn!("Refresh token reused for session {}, revoking it", session_id);
+            state.store.delete_session(session_id).await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            state.publish_auth_event(AuthEvent::SessionRevoked { session_id, user_id }).await;
+            return Err((StatusCode::UNAUTHORIZED, "Refresh token already used, session revoked".into()));
+        }
+        RefreshRotation::Expired => {
+            return Err((StatusCode::UNAUTHORIZED, "Refresh token expired".into()));
+        }
+        RefreshRotation::Invalid => {
+            return Err((StatusCode::UNAUTHORIZED, "Invalid refresh token".into()));
+        }
+    };
 
-    let user = state.redis.get_user(claims.sub).await
+    let user = state.store.get_user(user_id).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or((StatusCode::UNAUTHORIZED, "User not found".into()))?;
 
     let token = auth::create_token(
-        claims.sub,
+        user_id,
         project_id.0.site_id,
-        claims.session_id,
+        session_id,
         &state.config.jwt_secret,
         state.config.jwt_expiry_hours,
     )
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let refresh_token = auth::create_token(
-        claims.sub,
-        project_id.0.site_id,
-        claims.session_id,
-        &state.config.jwt_secret,
-        24 * 365 * 100, // ~100 years - refresh tokens never expire
-    )
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
     Ok(Json(AuthResponse {
         token,
         refresh_token,
@@ -702,11 +658,13 @@ pub async fn refresh_token(
 pub async fn logout(
     State(state): State<AppState>,
     _project_id: ProjectId,
-    crate::extractors::AuthUser { session_id, .. }: crate::extractors::AuthUser,
+    crate::extractors::AuthUser { user_id, session_id, .. }: crate::extractors::AuthUser,
 ) -> Result<StatusCode, (StatusCode, String)> {
-    state.redis.delete_session(session_id).await
+    state.store.delete_session(session_id).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    state.publish_auth_event(AuthEvent::SessionRevoked { session_id, user_id }).await;
+
     Ok(StatusCode::OK)
 }
 
@@ -933,31 +891,32 @@ async fn create_new_oauth_user(
         username_set: false, // New users must confirm their username
         social_links: SocialLinks::default(),
         total_comments: 0,
+        email_notifications: Default::default(),
     };
 
-    state.redis.set_user(&user).await
+    state.store.set_user(&user).await
         .map_err(|e| e.to_string())?;
 
-    let final_name = if state.redis.is_username_available(&user.name, None).await.unwrap_or(false) {
+    let final_name = if state.store.is_username_available(&user.name, None).await.unwrap_or(false) {
         user.name.clone()
     } else {
         format!("{}-{}", user.name, &user_id.to_string()[..8])
     };
-    state.redis.set_user_username_index(&final_name, user_id).await
+    state.store.set_user_username_index(&final_name, user_id).await
         .map_err(|e| e.to_string())?;
 
-    state.redis.set_user_provider_index(&provider, &provider_id, user_id).await
+    state.store.set_user_provider_index(&provider, &provider_id, user_id).await
         .map_err(|e| e.to_string())?;
 
     if let Some(ref email) = email {
-        let _ = state.redis.set_user_email_index(email, user_id).await;
+        let _ = state.store.set_user_email_index(email, user_id).await;
     }
 
     // Update user with final name if it changed
     if final_name != user.name {
         let mut updated_user = user.clone();
         updated_user.name = final_name;
-        state.redis.set_user(&updated_user).await
+        state.store.set_user(&updated_user).await
             .map_err(|e| e.to_string())?;
         Ok(updated_user)
     } else {
@@ -1058,7 +1017,7 @@ async fn oauth_callback_inner(
         _ => return Err("Provider not supported".into()),
     };
 
-    let existing_user_id = state.redis.get_user_by_provider(&provider, &provider_id).await
+    let existing_user_id = state.store.get_user_by_provider(&provider, &provider_id).await
         .map_err(|e| e.to_string())?;
 
     let site_id: Uuid = if let Some(state_str) = query.state.as_ref() {
@@ -1074,14 +1033,14 @@ async fn oauth_callback_inner(
 
     let user = if let Some(user_id) = existing_user_id {
         // User already exists with this OAuth provider
-        state.redis.get_user(user_id).await
+        state.store.get_user(user_id).await
             .map_err(|e| e.to_string())?
             .ok_or("User not found")?
     } else if let Some(ref user_email) = email {
         // No existing OAuth account, but check if there's an account with this email
-        if let Some(existing_user_id) = state.redis.get_user_by_email(user_email).await.map_err(|e| e.to_string())? {
+        if let Some(existing_user_id) = state.store.get_user_by_email(user_email).await.map_err(|e| e.to_string())? {
             // Account exists with this email - link the OAuth provider to it
-            let mut existing_user = state.redis.get_user(existing_user_id).await
+            let mut existing_user = state.store.get_user(existing_user_id).await
                 .map_err(|e| e.to_string())?
                 .ok_or("User not found")?;
 
@@ -1095,11 +1054,11 @@ async fn oauth_callback_inner(
                 existing_user.avatar_url = avatar_url.clone();
             }
 
-            state.redis.set_user(&existing_user).await
+            state.store.set_user(&existing_user).await
                 .map_err(|e| e.to_string())?;
 
             // Create the OAuth provider index
-            state.redis.set_user_provider_index(&provider, &provider_id, existing_user_id).await
+            state.store.set_user_provider_index(&provider, &provider_id, existing_user_id).await
                 .map_err(|e| e.to_string())?;
 
             existing_user
@@ -1112,28 +1071,10 @@ async fn oauth_callback_inner(
         create_new_oauth_user(state.clone(), provider, provider_id, name, email, avatar_url, auth_provider).await?
     };
 
-    let session_id = Uuid::now_v7();
-    state.redis.create_session(session_id, user.id, "", "").await
+    let (token, refresh_token) = start_session(&state, user.id, site_id)
+        .await
         .map_err(|e| e.to_string())?;
 
-    let token = auth::create_token(
-        user.id,
-        site_id,
-        session_id,
-        &state.config.jwt_secret,
-        state.config.jwt_expiry_hours,
-    )
-    .map_err(|e| e.to_string())?;
-
-    let refresh_token = auth::create_token(
-        user.id,
-        site_id,
-        session_id,
-        &state.config.jwt_secret,
-        24 * 365 * 100, // ~100 years - refresh tokens never expire
-    )
-    .map_err(|e| e.to_string())?;
-
     // Build user JSON for postMessage
     let user_response = UserResponse::from(user);
     let user_json = serde_json::to_string(&user_response).map_err(|e| e.to_string())?;
@@ -1231,31 +1172,10 @@ pub async fn ethereum_verify(
     let user = get_or_create_web3_user(&state, "ethereum", &address, AuthProvider::Ethereum).await?;
 
     // Create session and tokens
-    let session_id = Uuid::now_v7();
-    state
-        .redis
-        .create_session(session_id, user.id, "", "")
+    let (token, refresh_token) = start_session(&state, user.id, project_id.0.site_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let token = auth::create_token(
-        user.id,
-        project_id.0.site_id,
-        session_id,
-        &state.config.jwt_secret,
-        state.config.jwt_expiry_hours,
-    )
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-    let refresh_token = auth::create_token(
-        user.id,
-        project_id.0.site_id,
-        session_id,
-        &state.config.jwt_secret,
-        24 * 365 * 100,
-    )
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
     Ok(Json(AuthResponse {
         token,
         refresh_token,
@@ -1352,31 +1272,10 @@ pub async fn solana_verify(
     let user = get_or_create_web3_user(&state, "solana", &address, AuthProvider::Solana).await?;
 
     // Create session and tokens
-    let session_id = Uuid::now_v7();
-    state
-        .redis
-        .create_session(session_id, user.id, "", "")
+    let (token, refresh_token) = start_session(&state, user.id, project_id.0.site_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let token = auth::create_token(
-        user.id,
-        project_id.0.site_id,
-        session_id,
-        &state.config.jwt_secret,
-        state.config.jwt_expiry_hours,
-    )
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-    let refresh_token = auth::create_token(
-        user.id,
-        project_id.0.site_id,
-        session_id,
-        &state.config.jwt_secret,
-        24 * 365 * 100,
-    )
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
     Ok(Json(AuthResponse {
         token,
         refresh_token,
@@ -1397,13 +1296,13 @@ async fn get_or_create_web3_user(
 ) -> Result<User, (StatusCode, String)> {
     // Check if user exists
     if let Some(user_id) = state
-        .redis
+        .store
         .get_user_by_wallet(chain, address)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
     {
         return state
-            .redis
+            .store
             .get_user(user_id)
             .await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
@@ -1432,24 +1331,25 @@ async fn get_or_create_web3_user(
         username_set: false, // Web3 users need to choose a proper username
         social_links: SocialLinks::default(),
         total_comments: 0,
+        email_notifications: Default::default(),
     };
 
     state
-        .redis
+        .store
         .set_user(&user)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // Index by wallet address
     state
-        .redis
+        .store
         .set_user_wallet_index(chain, address, user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // Try to set username index (may fail if not unique, but that's ok for web3 users)
     let _ = state
-        .redis
+        .store
         .set_user_username_index(&display_name, user_id)
         .await;
 
@@ -11,30 +11,31 @@ use uuid::Uuid;
 
 use threadkit_common::redis::RedisClient;
 use threadkit_common::types::{
-    CommentStatus, ModerationAction, ModerationMode, Notification, NotificationType, PageTree,
-    Report, ReportReason, SortOrder, TreeComment, TurnstileEnforcement, VoteDirection,
-    ANONYMOUS_USER_ID, DELETED_USER_ID,
+    CommentRevision, CommentStatus, PageTree, Report, ReportReason, Role, SortOrder, TreeComment,
+    VoteDirection,
 };
-use threadkit_common::moderation::ModerationCheckResult;
-use threadkit_common::{ActionLogBuilder, ActionType};
+use threadkit_common::comments::{CommentActor, CommentError};
+use threadkit_common::{ActionLogBuilder, ActionType, Error};
 
 // Re-export shared types for OpenAPI docs and external use
 pub use threadkit_common::types::{
-    CreateCommentRequest, CreateCommentResponse, GetCommentsResponse,
+    CreateCommentRequest, CreateCommentResponse, DeleteRequest, GetCommentsResponse,
+    UpdateCommentRequest, VoteRequest, VoteResponse,
 };
 
-use super::turnstile::verify_with_cloudflare;
-
 use crate::{
-    extractors::{ProjectId, AuthUser, AuthUserWithRole, MaybeAuthUser, MaybeAuthUserWithRole},
+    extractors::{ProjectId, AuthUser, AuthUserWithRole, ClientIp, MaybeAuthUser, MaybeAuthUserWithRole},
     state::AppState,
 };
 
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/comments", get(get_comments).post(create_comment))
+        .route("/comments/subtree", get(get_comment_subtree))
         .route("/comments/{id}", put(update_comment).delete(delete_comment))
+        .route("/comments/{id}/revisions", get(get_comment_revisions))
         .route("/comments/{id}/vote", post(vote_comment))
+        .route("/comments/{id}/reactions", post(react_to_comment))
         .route("/comments/{id}/pin", post(pin_comment))
         .route("/comments/{id}/report", post(report_comment))
         .route("/pages/my_votes", get(get_my_votes))
@@ -44,14 +45,6 @@ pub fn router() -> Router<AppState> {
 // Helper Functions
 // ============================================================================
 
-/// Extract IP address from headers (prioritizes X-Forwarded-For)
-fn extract_ip(headers: &axum::http::HeaderMap) -> Option<String> {
-    headers
-        .get("x-forwarded-for")
-        .and_then(|v| v.to_str().ok())
-        .map(String::from)
-}
-
 /// Extract user agent from headers
 fn extract_user_agent(headers: &axum::http::HeaderMap) -> Option<String> {
     headers
@@ -60,6 +53,52 @@ fn extract_user_agent(headers: &axum::http::HeaderMap) -> Option<String> {
         .map(String::from)
 }
 
+/// Identify a visitor for analytics: the user ID if signed in, otherwise a hash of the
+/// client IP and user agent (None if neither is known)
+fn visitor_id(user_id: Option<Uuid>, ip: Option<&str>, headers: &axum::http::HeaderMap) -> Option<String> {
+    use sha2::{Digest, Sha256};
+
+    if let Some(user_id) = user_id {
+        return Some(user_id.to_string());
+    }
+
+    let user_agent = extract_user_agent(headers);
+    if ip.is_none() && user_agent.is_none() {
+        return None;
+    }
+
+    let hash = Sha256::digest(format!("{}|{}", ip.unwrap_or_default(), user_agent.unwrap_or_default()).as_bytes());
+    Some(format!("{:x}", hash)[..16].to_string())
+}
+
+/// Describe the caller for the comment service
+fn comment_actor(
+    user_id: Option<Uuid>,
+    role: Role,
+    username_set: bool,
+    ip: Option<String>,
+    headers: &axum::http::HeaderMap,
+) -> CommentActor {
+    CommentActor {
+        user_id,
+        role,
+        username_set,
+        ip,
+        user_agent: extract_user_agent(headers),
+        turnstile_token: headers
+            .get("X-Turnstile-Token")
+            .and_then(|v| v.to_str().ok())
+            .map(String::from),
+    }
+}
+
+/// Map a comment service error to an HTTP error response
+fn service_error(e: CommentError) -> (StatusCode, String) {
+    let status =
+        StatusCode::from_u16(e.status_code()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    (status, e.to_string())
+}
+
 // ============================================================================
 // Request/Response Types
 // ============================================================================
@@ -71,33 +110,82 @@ pub struct GetCommentsQuery {
     pub page_url: String,
     /// Sort order (new, top, controversial, old)
     pub sort: Option<SortOrder>,
+    /// Page size for top-level comments. Enables paginated mode when set (max 200)
+    pub limit: Option<usize>,
+    /// Cursor from a previous response's `next_cursor` for the same page (or parent)
+    pub cursor: Option<String>,
+    /// Maximum reply depth to include (0 = top-level only); deeper replies become a stub with `o` set
+    pub max_depth: Option<usize>,
+    /// Maximum replies to include per comment; the rest become a stub with `o` set
+    pub max_replies: Option<usize>,
 }
 
-#[derive(Debug, Deserialize, ToSchema)]
-pub struct UpdateCommentRequest {
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GetSubtreeQuery {
+    /// URL of the page the comment is on
+    pub page_url: String,
+    /// Comma-separated path of comment IDs from root to the parent comment
+    pub parent_path: String,
+    /// Sort order for replies (new, top, controversial, old)
+    pub sort: Option<SortOrder>,
+    /// Page size for direct replies of the parent (max 200). All replies when not set
+    pub limit: Option<usize>,
+    /// Cursor from a previous response's `next_cursor` for the same page (or parent)
+    pub cursor: Option<String>,
+    /// Maximum reply depth to include below the direct replies
+    pub max_depth: Option<usize>,
+    /// Maximum replies to include per comment
+    pub max_replies: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GetSubtreeResponse {
+    /// Page ID for WebSocket subscription
+    pub page_id: Uuid,
+    /// Path of the parent comment
+    pub parent_path: Vec<Uuid>,
+    /// Direct replies of the parent, in compact tree format
+    pub replies: Vec<TreeComment>,
+    /// Total reply count below the parent (including nested)
+    pub total: i64,
+    /// Cursor for the next page of replies (absent on the last page)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GetRevisionsQuery {
     /// Page URL where the comment exists
     pub page_url: String,
-    /// New comment content (markdown)
-    pub content: String,
-    /// Path to the comment (array of UUIDs from root to target)
-    pub path: Vec<Uuid>,
+    /// Comma-separated path of comment IDs from root to the comment
+    pub path: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GetRevisionsResponse {
+    pub comment_id: Uuid,
+    /// Previous versions of the comment, oldest first
+    pub revisions: Vec<CommentRevision>,
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
-pub struct VoteRequest {
+pub struct ReactionRequest {
     /// Page URL where the comment exists
     pub page_url: String,
-    /// Vote direction (up or down)
-    pub direction: VoteDirection,
+    /// Emoji to toggle (must be in the site's reaction set)
+    pub emoji: String,
     /// Path to the comment (array of UUIDs from root to target)
     pub path: Vec<Uuid>,
 }
 
 #[derive(Debug, Serialize, ToSchema)]
-pub struct VoteResponse {
-    pub upvotes: i64,
-    pub downvotes: i64,
-    pub user_vote: Option<VoteDirection>,
+pub struct ReactionResponse {
+    /// Reaction counts for the comment by emoji
+    pub reactions: std::collections::BTreeMap<String, i64>,
+    /// Whether the reaction was added (false = removed)
+    pub reacted: bool,
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
@@ -114,14 +202,6 @@ pub struct PinResponse {
     pub pinned_at: Option<i64>,
 }
 
-#[derive(Debug, Deserialize, ToSchema)]
-pub struct DeleteRequest {
-    /// Page URL where the comment exists
-    pub page_url: String,
-    /// Path to the comment (array of UUIDs from root to target)
-    pub path: Vec<Uuid>,
-}
-
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct ReportRequest {
     /// Page URL where the comment exists
@@ -145,12 +225,17 @@ pub struct GetVotesQuery {
 pub struct GetVotesResponse {
     /// Map of comment ID to vote direction ("up" or "down")
     pub votes: std::collections::HashMap<String, String>,
+    /// Map of comment ID to the emojis the user reacted with
+    pub reactions: std::collections::HashMap<String, Vec<String>>,
 }
 
 // ============================================================================
 // Handlers
 // ============================================================================
 
+/// Most pages a site gets registered by reads alone (written pages aren't limited)
+const MAX_VIEWED_PAGES_PER_SITE: u64 = 10_000;
+
 /// Get comments for a page
 #[utoipa::path(
     get,
@@ -160,7 +245,7 @@ pub struct GetVotesResponse {
     responses(
         (status = 200, description = "Comment tree", body = GetCommentsResponse),
         (status = 304, description = "Not modified (ETag match)"),
-        (status = 400, description = "Invalid request")
+        (status = 400, description = "Invalid request, or a cursor not naming a comment on the page")
     ),
     security(("project_id" = []))
 )]
@@ -168,6 +253,7 @@ pub async fn get_comments(
     State(state): State<AppState>,
     project_id: ProjectId,
     maybe_auth: MaybeAuthUser,
+    ClientIp(client_ip): ClientIp,
     headers: axum::http::HeaderMap,
     Query(query): Query<GetCommentsQuery>,
 ) -> Result<axum::response::Response, (StatusCode, String)> {
@@ -180,30 +266,18 @@ pub async fn get_comments(
     let page_id = RedisClient::generate_page_id(project_id.0.site_id, &query.page_url);
 
     // Check in-memory ETag cache FIRST (avoids Redis entirely for unchanged pages)
-    if let Some(if_none_match) = headers.get("if-none-match").and_then(|v| v.to_str().ok()) {
-        if let Some(cached_ts) = state.etag_cache.get(&page_id).await {
-            let cached_etag = format!("\"{}\"", cached_ts);
-            if if_none_match == cached_etag || if_none_match == format!("W/{}", cached_etag) {
-                // Sub-millisecond 304 response - no Redis hit!
-                return Ok((
-                    StatusCode::NOT_MODIFIED,
-                    [
-                        ("ETag", cached_etag),
-                        ("Cache-Control", "public, max-age=0, must-revalidate".to_string()),
-                    ],
-                )
-                    .into_response());
-            }
-        }
+    if let Some(response) = cached_not_modified(&state, page_id, &headers).await {
+        return Ok(response);
     }
 
-    // Fire-and-forget pageview increments (spawned task, no await blocking response)
-    // Uses pipeline to batch both increments into a single Redis round trip
+    // Fire-and-forget pageview and analytics increments (spawned task, no await blocking response)
+    // Uses a pipeline to batch them into a single Redis round trip
     {
         let redis = state.redis.clone();
         let site_id = project_id.0.site_id;
+        let visitor_id = visitor_id(maybe_auth.0.as_ref().map(|u| u.user_id), client_ip.as_deref(), &headers);
         tokio::spawn(async move {
-            let _ = redis.increment_pageview_with_usage(page_id, site_id).await;
+            let _ = redis.record_pageview(page_id, site_id, visitor_id.as_deref()).await;
         });
     }
 
@@ -215,12 +289,12 @@ pub async fn get_comments(
     let redis_start = Instant::now();
     let (tree_result, blocked_users, pageviews, pinned) = tokio::join!(
         // Primary: get the page tree
-        state.redis.get_or_create_page_tree(page_id),
+        state.store.get_or_create_page_tree(page_id),
         // Get blocked users (if authenticated)
         async {
             if let Some(user_id) = user_id_for_blocked {
                 state
-                    .redis
+                    .store
                     .get_blocked_users(user_id)
                     .await
                     .unwrap_or_default()
@@ -251,6 +325,19 @@ pub async fn get_comments(
 
     let mut tree = tree_result.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    // Register the page as the site's so it can be subscribed to before it has comments.
+    // Anyone with the public key can read any URL, so reads only add a capped number.
+    {
+        let redis = state.redis.clone();
+        let site_id = project_id.0.site_id;
+        let page_url = query.page_url.clone();
+        tokio::spawn(async move {
+            let _ = redis
+                .register_viewed_page(site_id, page_id, &page_url, MAX_VIEWED_PAGES_PER_SITE)
+                .await;
+        });
+    }
+
     // Update in-memory cache with latest timestamp
     state.etag_cache.insert(page_id, tree.updated_at).await;
 
@@ -258,35 +345,44 @@ pub async fn get_comments(
     let etag = format!("\"{}\"", tree.updated_at);
 
     // Check If-None-Match header again (cache miss case - ETag from Redis)
-    if let Some(if_none_match) = headers.get("if-none-match").and_then(|v| v.to_str().ok()) {
-        if if_none_match == etag || if_none_match == format!("W/{}", etag) {
-            // Return 304 Not Modified
-            return Ok((
-                StatusCode::NOT_MODIFIED,
-                [
-                    ("ETag", etag),
-                    ("Cache-Control", "public, max-age=0, must-revalidate".to_string()),
-                ],
-            )
-                .into_response());
-        }
+    if let Some(response) = not_modified_response(&headers, &etag) {
+        return Ok(response);
     }
 
     let current_user_id = maybe_auth.0.as_ref().map(|u| u.user_id);
 
-    // Filter comments in place and strip vote lists
-    let filter_start = Instant::now();
-    filter_tree_for_response(&mut tree, current_user_id, &blocked_users);
-    let filter_elapsed = filter_start.elapsed();
-
-    // Sort if requested
+    // Sort before filtering so cursors can be resolved against every comment,
+    // including ones the filter hides from this viewer
     let sort_start = Instant::now();
     let sort = query.sort.unwrap_or_default();
     sort_tree(&mut tree, sort);
     let sort_elapsed = sort_start.elapsed();
+    let root_order = query.limit.map(|_| sort_positions(&tree.comments));
+
+    // Filter comments in place and strip vote lists
+    let filter_start = Instant::now();
+    filter_tree_for_response(&mut tree, current_user_id, &blocked_users);
+    let filter_elapsed = filter_start.elapsed();
 
     let total = tree.total_count();
 
+    // Paginated mode: one page of top-level comments
+    let mut next_cursor = None;
+    if let (Some(limit), Some(order)) = (query.limit, root_order) {
+        let cursor = parse_cursor(query.cursor.as_deref())?;
+        let comments = std::mem::take(&mut tree.comments);
+        let (page, cursor) = paginate_comments(comments, &order, cursor, limit)?;
+        tree.comments = page;
+        next_cursor = cursor;
+    }
+
+    // Truncate deep/wide reply chains into "more replies" stubs
+    if query.max_depth.is_some() || query.max_replies.is_some() {
+        for comment in &mut tree.comments {
+            truncate_replies(comment, 0, query.max_depth, query.max_replies);
+        }
+    }
+
     let serialize_start = Instant::now();
     let response = GetCommentsResponse {
         page_id,
@@ -294,6 +390,7 @@ pub async fn get_comments(
         total,
         pageviews,
         pinned,
+        next_cursor,
     };
 
     let json_response = Json(response);
@@ -324,384 +421,152 @@ pub async fn get_comments(
         .into_response())
 }
 
-/// Create a new comment
+/// Get the replies below a comment (for expanding truncated "more replies" stubs)
 #[utoipa::path(
-    post,
-    path = "/comments",
+    get,
+    path = "/comments/subtree",
     tag = "comments",
-    request_body = CreateCommentRequest,
+    params(GetSubtreeQuery),
     responses(
-        (status = 200, description = "Comment created", body = CreateCommentResponse),
-        (status = 400, description = "Invalid request"),
-        (status = 403, description = "User is blocked or Turnstile verification failed"),
-        (status = 404, description = "Parent comment not found")
+        (status = 200, description = "Replies below the parent comment", body = GetSubtreeResponse),
+        (status = 304, description = "Not modified (ETag match)"),
+        (status = 400, description = "Invalid path, or a cursor not naming a reply of the parent"),
+        (status = 404, description = "Comment not found")
     ),
-    security(("project_id" = []), ("bearer" = []))
+    security(("project_id" = []))
 )]
-pub async fn create_comment(
+pub async fn get_comment_subtree(
     State(state): State<AppState>,
     project_id: ProjectId,
-    auth: MaybeAuthUserWithRole,
+    maybe_auth: MaybeAuthUser,
     headers: axum::http::HeaderMap,
-    Json(req): Json<CreateCommentRequest>,
-) -> Result<Json<CreateCommentResponse>, (StatusCode, String)> {
-    // Check if anonymous comments are allowed
-    let is_anonymous = !auth.is_authenticated();
-    if is_anonymous && !project_id.0.settings.auth.anonymous {
-        return Err((
-            StatusCode::UNAUTHORIZED,
-            "Authentication required. Anonymous comments are not enabled.".into(),
-        ));
-    }
-
-    // Anonymous comments require author_name
-    if is_anonymous && req.author_name.as_ref().map_or(true, |n| n.trim().is_empty()) {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            "author_name is required for anonymous comments".into(),
-        ));
-    }
+    Query(query): Query<GetSubtreeQuery>,
+) -> Result<axum::response::Response, (StatusCode, String)> {
+    use axum::response::IntoResponse;
 
-    // Check if user is blocked (only for authenticated users)
-    if auth.role == threadkit_common::types::Role::Blocked {
-        return Err((StatusCode::FORBIDDEN, "User is blocked".into()));
+    let parent_path = query
+        .parent_path
+        .split(',')
+        .map(|id| id.trim().parse::<Uuid>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid parent_path".to_string()))?;
+    if parent_path.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "parent_path is required".into()));
     }
 
-    // Check if username is set (authenticated users must have username set)
-    auth.require_username_set()?;
+    let page_id = RedisClient::generate_page_id(project_id.0.site_id, &query.page_url);
 
-    // Check if site-wide posting is disabled
-    if project_id.0.settings.posting_disabled {
-        return Err((StatusCode::FORBIDDEN, "Posting is currently disabled".into()));
+    if let Some(response) = cached_not_modified(&state, page_id, &headers).await {
+        return Ok(response);
     }
 
-    // Generate page_id
-    let page_id = RedisClient::generate_page_id(project_id.0.site_id, &req.page_url);
-
-    // Debug logging for tests
-    tracing::debug!(
-        "Create comment: page_url={}, site_id={}, page_id={}",
-        req.page_url,
-        project_id.0.site_id,
-        page_id
+    let current_user_id = maybe_auth.0.as_ref().map(|u| u.user_id);
+    let (tree, blocked_users) = tokio::join!(
+        state.store.get_or_create_page_tree(page_id),
+        async {
+            if let Some(user_id) = current_user_id {
+                state
+                    .store
+                    .get_blocked_users(user_id)
+                    .await
+                    .unwrap_or_default()
+                    .into_iter()
+                    .collect::<std::collections::HashSet<Uuid>>()
+            } else {
+                std::collections::HashSet::new()
+            }
+        }
     );
+    let mut tree = tree.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    // Check if page-level posting is disabled
-    let page_locked = state
-        .redis
-        .is_page_locked(project_id.0.site_id, page_id)
-        .await
-        .unwrap_or(false);
-
-    if page_locked {
-        return Err((
-            StatusCode::FORBIDDEN,
-            "Posting is disabled on this page".into(),
-        ));
+    state.etag_cache.insert(page_id, tree.updated_at).await;
+    let etag = format!("\"{}\"", tree.updated_at);
+    if let Some(response) = not_modified_response(&headers, &etag) {
+        return Ok(response);
     }
 
-    // Turnstile verification (if configured)
-    verify_turnstile(&state, &project_id, is_anonymous, &headers).await?;
-
-    // Validate comment length
-    let max_length = state.config.max_comment_length;
-    if req.content.chars().count() > max_length {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            format!(
-                "Comment exceeds maximum length of {} characters",
-                max_length
-            ),
-        ));
-    }
+    let parent = tree
+        .find_by_path_mut(&parent_path)
+        .ok_or((StatusCode::NOT_FOUND, "Comment not found".into()))?;
 
-    // Check if shadow banned (only for authenticated users)
-    let is_shadowbanned = if let Some(user_id) = auth.user_id {
-        state
-            .redis
-            .is_shadowbanned(project_id.0.site_id, user_id)
-            .await
-            .unwrap_or(false)
-    } else {
-        false
-    };
+    // Sort before filtering so cursors resolve against hidden replies too
+    let sort = query.sort.unwrap_or_default();
+    sort_comments(&mut parent.replies, sort);
+    let order = sort_positions(&parent.replies);
 
-    // Content moderation check
-    //
-    // DESIGN: Fail-open policy - if the moderation service is unavailable (network error,
-    // timeout, API outage), the comment is allowed through. This prioritizes availability
-    // over strict moderation. The alternative (fail-closed) would reject all comments when
-    // moderation is down, which provides worse UX for legitimate users.
-    let content_moderation_settings = &project_id.0.settings.content_moderation;
-    let moderation_result = state
-        .moderation
-        .check(&req.content, content_moderation_settings)
-        .await;
-
-    // Log moderation failures so operators can monitor service health
-    if let Err(ref e) = moderation_result {
-        tracing::warn!(
-            error = %e,
-            "Content moderation check failed - allowing comment through (fail-open policy)"
-        );
+    if !filter_comment_recursive(parent, current_user_id, &blocked_users) {
+        return Err((StatusCode::NOT_FOUND, "Comment not found".into()));
     }
-
-    let mut moderation_flagged = false;
-    if let Ok(ModerationCheckResult::Blocked { category, result }) = moderation_result {
-        match content_moderation_settings.action {
-            ModerationAction::Reject => {
-                tracing::info!(
-                    category = %category,
-                    reason = ?result.reason,
-                    "Comment rejected by content moderation"
-                );
-                return Err((
-                    StatusCode::FORBIDDEN,
-                    format!(
-                        "Content rejected: {}",
-                        result.reason.unwrap_or_else(|| category)
-                    ),
-                ));
-            }
-            ModerationAction::Queue | ModerationAction::Flag => {
-                moderation_flagged = true;
-            }
-        }
+    let total = parent.reply_count();
+
+    let mut replies = std::mem::take(&mut parent.replies);
+    let mut next_cursor = None;
+    if let Some(limit) = query.limit {
+        let cursor = parse_cursor(query.cursor.as_deref())?;
+        let (page, cursor) = paginate_comments(replies, &order, cursor, limit)?;
+        replies = page;
+        next_cursor = cursor;
     }
 
-    // Determine status
-    let status =
-        if moderation_flagged && content_moderation_settings.action == ModerationAction::Queue {
-            Some(CommentStatus::Pending)
-        } else {
-            match project_id.0.settings.moderation_mode {
-                ModerationMode::Pre => Some(CommentStatus::Pending),
-                _ => None, // None means approved (default)
-            }
-        };
-
-    // Get author info - either from authenticated user or anonymous
-    let (author_id, author_name, author_avatar, author_karma) = if let Some(user_id) = auth.user_id {
-        let author = state
-            .redis
-            .get_user(user_id)
-            .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-            .ok_or((StatusCode::INTERNAL_SERVER_ERROR, "User not found".into()))?;
-        (user_id, author.name.clone(), author.avatar_url.clone(), author.karma)
-    } else {
-        // Anonymous user
-        (
-            ANONYMOUS_USER_ID,
-            req.author_name.clone().unwrap_or_else(|| "Anonymous".to_string()),
-            None,
-            0,
-        )
-    };
-
-    let now = Utc::now();
-    let now_ts = now.timestamp();
-    let comment_id = Uuid::now_v7();
-
-    // Create the tree comment
-    let tree_comment = TreeComment {
-        id: comment_id,
-        author_id,
-        name: author_name,
-        avatar: author_avatar,
-        karma: author_karma,
-        text: req.content.clone(),
-        html: markdown_to_html(&req.content),
-        upvotes: 0,
-        downvotes: 0,
-        created_at: now_ts,
-        modified_at: now_ts,
-        edited: false,
-        replies: Vec::new(),
-        status: status.clone(),
-        parent_id: req.parent_path.last().copied(),
-    };
-
-    // If shadow banned, return success but don't actually save
-    if is_shadowbanned {
-        return Ok(Json(CreateCommentResponse {
-            comment: tree_comment,
-        }));
+    for reply in &mut replies {
+        truncate_replies(reply, 0, query.max_depth, query.max_replies);
     }
 
-    // Get current tree and add comment
-    let mut tree = state
-        .redis
-        .get_or_create_page_tree(page_id)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-    if req.parent_path.is_empty() {
-        // Root comment
-        tree.add_root(tree_comment.clone());
-    } else {
-        // Reply - find parent and add
-        if !tree.add_reply(&req.parent_path, tree_comment.clone()) {
-            return Err((StatusCode::NOT_FOUND, "Parent comment not found".into()));
-        }
-    }
+    Ok((
+        StatusCode::OK,
+        [
+            ("ETag", etag),
+            ("Cache-Control", "public, max-age=0, must-revalidate".to_string()),
+        ],
+        Json(GetSubtreeResponse {
+            page_id,
+            parent_path,
+            replies,
+            total,
+            next_cursor,
+        }),
+    )
+        .into_response())
+}
 
-    // Save updated tree
-    state
-        .redis
-        .set_page_tree(page_id, &tree)
+/// Create a new comment
+#[utoipa::path(
+    post,
+    path = "/comments",
+    tag = "comments",
+    request_body = CreateCommentRequest,
+    responses(
+        (status = 200, description = "Comment created", body = CreateCommentResponse),
+        (status = 400, description = "Invalid request"),
+        (status = 402, description = "Site's monthly comment limit reached"),
+        (status = 403, description = "User is blocked or Turnstile verification failed"),
+        (status = 404, description = "Parent comment not found"),
+        (status = 429, description = "Site's monthly AI moderation checks used up")
+    ),
+    security(("project_id" = []), ("bearer" = []))
+)]
+pub async fn create_comment(
+    State(state): State<AppState>,
+    project_id: ProjectId,
+    auth: MaybeAuthUserWithRole,
+    ClientIp(client_ip): ClientIp,
+    headers: axum::http::HeaderMap,
+    Json(req): Json<CreateCommentRequest>,
+) -> Result<Json<CreateCommentResponse>, (StatusCode, String)> {
+    let actor = comment_actor(auth.user_id, auth.role, auth.username_set, client_ip, &headers);
+    let written = state
+        .comments
+        .create(&project_id.0, &actor, req)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .map_err(service_error)?;
 
     // Update ETag cache with new timestamp
-    state.etag_cache.insert(page_id, tree.updated_at).await;
-
-    // Clone for response before background tasks
-    let response_comment = tree_comment.clone();
-
-    // Fire-and-forget: update indexes, usage, notifications, and publish in background
-    // These don't block the response - the comment is already saved
-    {
-        let redis = state.redis.clone();
-        let site_id = project_id.0.site_id;
-        let is_pending = status == Some(CommentStatus::Pending);
-        let parent_path = req.parent_path.clone();
-
-        // Find parent author for notification (if this is a reply)
-        let notify_user_id = if !parent_path.is_empty() {
-            tree.find_by_path(&parent_path)
-                .filter(|parent| {
-                    parent.author_id != author_id
-                        && parent.author_id != DELETED_USER_ID
-                        && parent.author_id != ANONYMOUS_USER_ID
-                })
-                .map(|parent| parent.author_id)
-        } else {
-            None
-        };
-
-        tokio::spawn(async move {
-            // Run all index updates concurrently
-            let mut futures: Vec<std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>>> =
-                Vec::with_capacity(6);
-
-            // User comment indexes (if authenticated)
-            if let Some(user_id) = auth.user_id {
-                let redis1 = redis.clone();
-                let redis2 = redis.clone();
-                futures.push(Box::pin(async move {
-                    let _ = redis1.add_user_comment_index(user_id, page_id, comment_id).await;
-                }));
-                futures.push(Box::pin(async move {
-                    let _ = redis2
-                        .add_user_site_comment_index(user_id, site_id, page_id, comment_id)
-                        .await;
-                }));
-            }
-
-            // Site comment index
-            {
-                let redis = redis.clone();
-                futures.push(Box::pin(async move {
-                    let _ = redis.add_site_comment_index(site_id, page_id, comment_id).await;
-                }));
-            }
-
-            // Modqueue (if pending)
-            if is_pending {
-                let redis = redis.clone();
-                futures.push(Box::pin(async move {
-                    let _ = redis.add_to_modqueue(site_id, page_id, comment_id).await;
-                }));
-            }
-
-            // Usage increment
-            {
-                let redis = redis.clone();
-                futures.push(Box::pin(async move {
-                    let _ = redis.increment_usage(site_id, "comments", 1).await;
-                }));
-            }
-
-            // Increment user comment count (if authenticated)
-            if let Some(user_id) = auth.user_id {
-                let redis = redis.clone();
-                futures.push(Box::pin(async move {
-                    let _ = redis.increment_user_comment_count(user_id).await;
-                }));
-            }
-
-            // Notification (if reply to another user)
-            if let Some(parent_author_id) = notify_user_id {
-                let redis = redis.clone();
-                futures.push(Box::pin(async move {
-                    let notification = Notification {
-                        id: Uuid::now_v7(),
-                        notification_type: NotificationType::Reply,
-                        comment_id,
-                        from_user_id: author_id,
-                        read: false,
-                        created_at: now,
-                    };
-                    let _ = redis.add_notification(parent_author_id, &notification).await;
-                }));
-            }
-
-            // Publish for WebSocket subscribers
-            {
-                let redis = redis.clone();
-                futures.push(Box::pin(async move {
-                    let _ = redis
-                        .publish(
-                            &format!("threadkit:page:{}:events", page_id),
-                            &serde_json::json!({
-                                "type": "new_comment",
-                                "page_id": page_id,
-                                "data": {
-                                    "comment": tree_comment
-                                }
-                            })
-                            .to_string(),
-                        )
-                        .await;
-                }));
-            }
-
-            // Execute all concurrently
-            futures::future::join_all(futures).await;
-        });
-    }
-
-    // Log action
-    let user_email = if let Some(user_id) = auth.user_id {
-        state.redis.get_user(user_id).await.ok()
-            .and_then(|u| u)
-            .and_then(|u| u.email)
-    } else {
-        None
-    };
-
-    let mut log_entry = ActionLogBuilder::new(ActionType::CommentCreated, project_id.0.site_id)
-        .user_id(author_id)
-        .page_url(req.page_url.clone())
-        .page_id(page_id)
-        .comment_id(comment_id)
-        .content_preview(req.content.clone());
-
-    if let Some(email) = user_email {
-        log_entry = log_entry.user_email(email);
-    }
-    if let Some(ip) = extract_ip(&headers) {
-        log_entry = log_entry.ip(ip);
-    }
-    if let Some(ua) = extract_user_agent(&headers) {
-        log_entry = log_entry.user_agent(ua);
+    if let Some(updated_at) = written.updated_at {
+        state.etag_cache.insert(written.page_id, updated_at).await;
     }
 
-    state.action_logger.log(log_entry.build());
-
     Ok(Json(CreateCommentResponse {
-        comment: response_comment,
+        comment: written.value,
     }))
 }
 
@@ -716,7 +581,7 @@ pub async fn create_comment(
     request_body = UpdateCommentRequest,
     responses(
         (status = 200, description = "Comment updated", body = TreeComment),
-        (status = 403, description = "Not your comment"),
+        (status = 403, description = "Not your comment, or the edit window has passed"),
         (status = 404, description = "Comment not found")
     ),
     security(("project_id" = []), ("bearer" = []))
@@ -726,86 +591,85 @@ pub async fn update_comment(
     project_id: ProjectId,
     auth: AuthUserWithRole,
     Path(comment_id): Path<Uuid>,
+    ClientIp(client_ip): ClientIp,
     headers: axum::http::HeaderMap,
     Json(req): Json<UpdateCommentRequest>,
 ) -> Result<Json<TreeComment>, (StatusCode, String)> {
-    // Check if username is set
-    auth.require_username_set()?;
+    let actor = comment_actor(Some(auth.user_id), auth.role, auth.username_set, client_ip, &headers);
+    let written = state
+        .comments
+        .edit(&project_id.0, &actor, comment_id, req)
+        .await
+        .map_err(service_error)?;
 
-    // Validate path ends with the correct comment ID
-    if req.path.is_empty() || *req.path.last().unwrap() != comment_id {
+    // Update ETag cache with new timestamp
+    if let Some(updated_at) = written.updated_at {
+        state.etag_cache.insert(written.page_id, updated_at).await;
+    }
+
+    Ok(Json(written.value))
+}
+
+/// List previous versions of an edited comment
+///
+/// Visible to moderators, or to everyone when the site enables public revisions.
+#[utoipa::path(
+    get,
+    path = "/comments/{id}/revisions",
+    tag = "comments",
+    params(
+        ("id" = Uuid, Path, description = "Comment ID"),
+        GetRevisionsQuery
+    ),
+    responses(
+        (status = 200, description = "Comment revisions", body = GetRevisionsResponse),
+        (status = 403, description = "Revisions are only visible to moderators"),
+        (status = 404, description = "Comment not found")
+    ),
+    security(("project_id" = []), ("bearer" = []))
+)]
+pub async fn get_comment_revisions(
+    State(state): State<AppState>,
+    project_id: ProjectId,
+    auth: MaybeAuthUserWithRole,
+    Path(comment_id): Path<Uuid>,
+    Query(query): Query<GetRevisionsQuery>,
+) -> Result<Json<GetRevisionsResponse>, (StatusCode, String)> {
+    if !project_id.0.settings.editing.public_revisions
+        && auth.role < threadkit_common::types::Role::Moderator
+    {
+        return Err((StatusCode::FORBIDDEN, "Revisions are only visible to moderators".into()));
+    }
+
+    let path: Vec<Uuid> = query
+        .path
+        .split(',')
+        .map(|s| s.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid path".to_string()))?;
+    if path.last() != Some(&comment_id) {
         return Err((StatusCode::BAD_REQUEST, "Path must end with comment ID".into()));
     }
 
-    let page_id = RedisClient::generate_page_id(project_id.0.site_id, &req.page_url);
+    let page_id = RedisClient::generate_page_id(project_id.0.site_id, &query.page_url);
 
-    let mut tree = state
-        .redis
+    // Make sure the comment belongs to this site before exposing its history
+    let tree = state
+        .store
         .get_page_tree(page_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or((StatusCode::NOT_FOUND, "Page not found".into()))?;
-
-    let comment = tree
-        .find_by_path_mut(&req.path)
+    tree.find_by_path(&path)
         .ok_or((StatusCode::NOT_FOUND, "Comment not found".into()))?;
 
-    // Check ownership
-    if comment.author_id != auth.user_id {
-        return Err((StatusCode::FORBIDDEN, "Not your comment".into()));
-    }
-
-    // Update comment
-    comment.text = req.content.clone();
-    comment.html = markdown_to_html(&req.content);
-    comment.modified_at = Utc::now().timestamp();
-    comment.edited = true;
-
-    // Clone for response before saving
-    let updated_comment = comment.clone();
-
-    // Save tree
-    state
-        .redis
-        .set_page_tree(page_id, &tree)
+    let revisions = state
+        .store
+        .get_comment_revisions(comment_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    // Update ETag cache with new timestamp
-    state.etag_cache.insert(page_id, tree.updated_at).await;
-
-    // Publish update for WebSocket subscribers
-    state.publish_event(page_id, "edit_comment", serde_json::json!({
-        "comment_id": comment_id,
-        "content": updated_comment.text.clone(),
-        "content_html": updated_comment.html.clone()
-    })).await;
-
-    // Log action
-    let user_email = state.redis.get_user(auth.user_id).await.ok()
-        .and_then(|u| u)
-        .and_then(|u| u.email);
-
-    let mut log_entry = ActionLogBuilder::new(ActionType::CommentEdited, project_id.0.site_id)
-        .user_id(auth.user_id)
-        .page_url(req.page_url.clone())
-        .page_id(page_id)
-        .comment_id(comment_id)
-        .content_preview(req.content.clone());
-
-    if let Some(email) = user_email {
-        log_entry = log_entry.user_email(email);
-    }
-    if let Some(ip) = extract_ip(&headers) {
-        log_entry = log_entry.ip(ip);
-    }
-    if let Some(ua) = extract_user_agent(&headers) {
-        log_entry = log_entry.user_agent(ua);
-    }
-
-    state.action_logger.log(log_entry.build());
-
-    Ok(Json(updated_comment))
+    Ok(Json(GetRevisionsResponse { comment_id, revisions }))
 }
 
 /// Delete a comment (marks as deleted, preserves replies)
@@ -829,75 +693,21 @@ pub async fn delete_comment(
     project_id: ProjectId,
     auth: AuthUserWithRole,
     Path(comment_id): Path<Uuid>,
+    ClientIp(client_ip): ClientIp,
     headers: axum::http::HeaderMap,
     Json(req): Json<DeleteRequest>,
 ) -> Result<StatusCode, (StatusCode, String)> {
-    // Check if username is set
-    auth.require_username_set()?;
-
-    // Validate path
-    if req.path.is_empty() || *req.path.last().unwrap() != comment_id {
-        return Err((StatusCode::BAD_REQUEST, "Path must end with comment ID".into()));
-    }
-
-    let page_id = RedisClient::generate_page_id(project_id.0.site_id, &req.page_url);
-
-    let mut tree = state
-        .redis
-        .get_page_tree(page_id)
+    let actor = comment_actor(Some(auth.user_id), auth.role, auth.username_set, client_ip, &headers);
+    let written = state
+        .comments
+        .delete(&project_id.0, &actor, comment_id, req)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-        .ok_or((StatusCode::NOT_FOUND, "Page not found".into()))?;
-
-    let comment = tree
-        .find_by_path_mut(&req.path)
-        .ok_or((StatusCode::NOT_FOUND, "Comment not found".into()))?;
-
-    // Check ownership or moderator
-    if comment.author_id != auth.user_id && auth.role < threadkit_common::types::Role::Moderator {
-        return Err((StatusCode::FORBIDDEN, "Not authorized".into()));
-    }
-
-    // Mark as deleted (preserves replies)
-    comment.mark_deleted();
-
-    // Save tree
-    state
-        .redis
-        .set_page_tree(page_id, &tree)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .map_err(service_error)?;
 
     // Update ETag cache with new timestamp
-    state.etag_cache.insert(page_id, tree.updated_at).await;
-
-    // Publish deletion for WebSocket subscribers
-    state.publish_event(page_id, "delete_comment", serde_json::json!({
-        "comment_id": comment_id
-    })).await;
-
-    // Log action
-    let user_email = state.redis.get_user(auth.user_id).await.ok()
-        .and_then(|u| u)
-        .and_then(|u| u.email);
-
-    let mut log_entry = ActionLogBuilder::new(ActionType::CommentDeleted, project_id.0.site_id)
-        .user_id(auth.user_id)
-        .page_url(req.page_url.clone())
-        .page_id(page_id)
-        .comment_id(comment_id);
-
-    if let Some(email) = user_email {
-        log_entry = log_entry.user_email(email);
-    }
-    if let Some(ip) = extract_ip(&headers) {
-        log_entry = log_entry.ip(ip);
+    if let Some(updated_at) = written.updated_at {
+        state.etag_cache.insert(written.page_id, updated_at).await;
     }
-    if let Some(ua) = extract_user_agent(&headers) {
-        log_entry = log_entry.user_agent(ua);
-    }
-
-    state.action_logger.log(log_entry.build());
 
     Ok(StatusCode::NO_CONTENT)
 }
@@ -922,12 +732,61 @@ pub async fn vote_comment(
     project_id: ProjectId,
     auth: AuthUserWithRole,
     Path(comment_id): Path<Uuid>,
+    ClientIp(client_ip): ClientIp,
     headers: axum::http::HeaderMap,
     Json(req): Json<VoteRequest>,
 ) -> Result<Json<VoteResponse>, (StatusCode, String)> {
-    // Check if username is set
+    let actor = comment_actor(Some(auth.user_id), auth.role, auth.username_set, client_ip, &headers);
+    let written = state
+        .comments
+        .vote(&project_id.0, &actor, comment_id, req)
+        .await
+        .map_err(service_error)?;
+
+    // Update ETag cache with new timestamp (tree was updated by the atomic vote)
+    if let Some(updated_at) = written.updated_at {
+        state.etag_cache.insert(written.page_id, updated_at).await;
+    }
+
+    Ok(Json(written.value))
+}
+
+/// Toggle an emoji reaction on a comment
+#[utoipa::path(
+    post,
+    path = "/comments/{id}/reactions",
+    tag = "comments",
+    params(
+        ("id" = Uuid, Path, description = "Comment ID")
+    ),
+    request_body = ReactionRequest,
+    responses(
+        (status = 200, description = "Reaction toggled", body = ReactionResponse),
+        (status = 400, description = "Emoji not allowed on this site"),
+        (status = 403, description = "Reactions are disabled"),
+        (status = 404, description = "Comment not found")
+    ),
+    security(("project_id" = []), ("bearer" = []))
+)]
+pub async fn react_to_comment(
+    State(state): State<AppState>,
+    project_id: ProjectId,
+    auth: AuthUserWithRole,
+    Path(comment_id): Path<Uuid>,
+    ClientIp(client_ip): ClientIp,
+    headers: axum::http::HeaderMap,
+    Json(req): Json<ReactionRequest>,
+) -> Result<Json<ReactionResponse>, (StatusCode, String)> {
     auth.require_username_set()?;
 
+    let allowed = &project_id.0.settings.reactions.emojis;
+    if allowed.is_empty() {
+        return Err((StatusCode::FORBIDDEN, "Reactions are disabled".into()));
+    }
+    if !allowed.contains(&req.emoji) {
+        return Err((StatusCode::BAD_REQUEST, "Emoji not allowed on this site".into()));
+    }
+
     // Validate path
     if req.path.is_empty() || *req.path.last().unwrap() != comment_id {
         return Err((StatusCode::BAD_REQUEST, "Path must end with comment ID".into()));
@@ -935,115 +794,64 @@ pub async fn vote_comment(
 
     let page_id = RedisClient::generate_page_id(project_id.0.site_id, &req.page_url);
 
-    // Debug logging for tests
-    tracing::debug!(
-        "Vote request: page_url={}, site_id={}, page_id={}, comment_id={}, path={:?}",
-        req.page_url,
-        project_id.0.site_id,
-        page_id,
-        comment_id,
-        req.path
-    );
-
-    // Use atomic vote operation to prevent race conditions
-    let (new_vote, new_upvotes, new_downvotes, upvote_delta, downvote_delta) = state
-        .redis
-        .atomic_vote(auth.user_id, page_id, comment_id, &req.path, req.direction)
+    // Toggle and update the counts in the tree atomically (same guarantees as votes)
+    let (reacted, reactions) = state
+        .store
+        .toggle_reaction(auth.user_id, page_id, comment_id, &req.path, &req.emoji)
         .await
-        .map_err(|e| {
-            tracing::error!("Vote error: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        .map_err(|e| match e {
+            Error::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
+            e => {
+                tracing::error!("Reaction error: {}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+            }
         })?;
 
-    // Get author_id for karma update (we still need to fetch the tree for this)
-    let tree = state
-        .redis
-        .get_page_tree(page_id)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-        .ok_or((StatusCode::NOT_FOUND, "Page not found".into()))?;
-
-    let comment = tree
-        .find_by_path(&req.path)
-        .ok_or((StatusCode::NOT_FOUND, "Comment not found".into()))?;
-
-    let author_id = comment.author_id;
-
-    // Update ETag cache with new timestamp (tree was updated by Lua script)
-    state.etag_cache.insert(page_id, tree.updated_at).await;
-
-    // Fire-and-forget: karma update and WebSocket publish
-    // Note: Vote storage is now handled atomically in the Lua script
-    {
-        let redis = state.redis.clone();
-        let user_id = auth.user_id;
-        let karma_delta = upvote_delta - downvote_delta;
+    // Tree was updated in place, refresh the ETag
+    let mut data = serde_json::json!({
+        "comment_id": comment_id,
+        "reactions": reactions,
+    });
+    if let Ok(Some(tree)) = state.store.get_page_tree(page_id).await {
+        state.etag_cache.insert(page_id, tree.updated_at).await;
 
-        tokio::spawn(async move {
-            // Run all updates concurrently
-            tokio::join!(
-                // Update author karma (only if voting on someone else's comment)
-                async {
-                    if author_id != user_id && author_id != DELETED_USER_ID && karma_delta != 0 {
-                        let _ = redis.update_user_karma(author_id, karma_delta).await;
-                    }
-                },
-                // Publish vote update for WebSocket subscribers
-                async {
-                    let _ = redis
-                        .publish(
-                            &format!("threadkit:page:{}:events", page_id),
-                            &serde_json::json!({
-                                "type": "vote_update",
-                                "page_id": page_id,
-                                "data": {
-                                    "comment_id": comment_id,
-                                    "upvotes": new_upvotes,
-                                    "downvotes": new_downvotes,
-                                }
-                            })
-                            .to_string(),
-                        )
-                        .await;
-                },
-            );
-        });
+        // Name the comment's author and status so the WebSocket server can filter per viewer
+        if let Some(comment) = tree.find_by_path(&req.path) {
+            data["author_id"] = serde_json::json!(comment.author_id);
+            data["status"] = serde_json::json!(comment.effective_status());
+        }
     }
 
+    state.publish_event(page_id, "reaction_update", data).await;
+
     // Log action
-    let user_email = state.redis.get_user(auth.user_id).await.ok()
+    let user_email = state.store.get_user(auth.user_id).await.ok()
         .and_then(|u| u)
         .and_then(|u| u.email);
 
-    let mut log_entry = ActionLogBuilder::new(ActionType::CommentVoted, project_id.0.site_id)
+    let mut log_entry = ActionLogBuilder::new(ActionType::CommentReacted, project_id.0.site_id)
         .user_id(auth.user_id)
         .page_url(req.page_url.clone())
         .page_id(page_id)
         .comment_id(comment_id)
         .metadata(serde_json::json!({
-            "vote_type": match req.direction {
-                VoteDirection::Up => "up",
-                VoteDirection::Down => "down",
-            }
+            "emoji": req.emoji,
+            "added": reacted,
         }));
 
     if let Some(email) = user_email {
         log_entry = log_entry.user_email(email);
     }
-    if let Some(ip) = extract_ip(&headers) {
+    if let Some(ip) = client_ip {
         log_entry = log_entry.ip(ip);
     }
     if let Some(ua) = extract_user_agent(&headers) {
         log_entry = log_entry.user_agent(ua);
     }
 
-    state.action_logger.log(log_entry.build());
+    state.log_action(log_entry.build());
 
-    Ok(Json(VoteResponse {
-        upvotes: new_upvotes,
-        downvotes: new_downvotes,
-        user_vote: new_vote,
-    }))
+    Ok(Json(ReactionResponse { reactions, reacted }))
 }
 
 /// Pin or unpin a comment (moderator+)
@@ -1080,7 +888,7 @@ pub async fn pin_comment(
 
     // Verify the comment exists in the tree
     let tree = state
-        .redis
+        .store
         .get_page_tree(page_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
@@ -1121,18 +929,14 @@ pub async fn pin_comment(
         let redis = state.redis.clone();
         tokio::spawn(async move {
             let _ = redis
-                .publish(
-                    &format!("threadkit:page:{}:events", page_id),
-                    &serde_json::json!({
-                        "type": "pin_update",
-                        "page_id": page_id,
-                        "data": {
-                            "comment_id": comment_id,
-                            "pinned": new_pinned,
-                            "pinned_at": new_pinned_at,
-                        }
-                    })
-                    .to_string(),
+                .publish_page_event(
+                    page_id,
+                    "pin_update",
+                    serde_json::json!({
+                        "comment_id": comment_id,
+                        "pinned": new_pinned,
+                        "pinned_at": new_pinned_at,
+                    }),
                 )
                 .await;
         });
@@ -1175,7 +979,7 @@ pub async fn report_comment(
 
     // Verify comment exists
     let tree = state
-        .redis
+        .store
         .get_page_tree(page_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
@@ -1198,17 +1002,35 @@ pub async fn report_comment(
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    state.publish_moderation_event(project_id.0.site_id, "report_created", serde_json::json!({
+        "page_id": page_id,
+        "report": report,
+    })).await;
+
+    state.log_action(
+        ActionLogBuilder::new(ActionType::ReportCreated, project_id.0.site_id)
+            .user_id(auth.user_id)
+            .page_url(req.page_url)
+            .page_id(page_id)
+            .comment_id(comment_id)
+            .metadata(serde_json::json!({
+                "reason": report.reason,
+                "details": report.details
+            }))
+            .build(),
+    );
+
     Ok(StatusCode::NO_CONTENT)
 }
 
-/// Get the current user's votes for a page
+/// Get the current user's votes and reactions for a page
 #[utoipa::path(
     get,
     path = "/pages/my_votes",
     tag = "comments",
     params(GetVotesQuery),
     responses(
-        (status = 200, description = "User's votes for the page", body = GetVotesResponse),
+        (status = 200, description = "User's votes and reactions for the page", body = GetVotesResponse),
         (status = 401, description = "Authentication required")
     ),
     security(("project_id" = []), ("bearer" = []))
@@ -1222,11 +1044,17 @@ pub async fn get_my_votes(
     let page_id = RedisClient::generate_page_id(project_id.0.site_id, &query.page_url);
 
     let votes = state
-        .redis
+        .store
         .get_page_votes(auth.user_id, page_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    let reactions = state
+        .store
+        .get_page_reactions(auth.user_id, page_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
     // Convert to string keys and values for JSON
     let votes_map: std::collections::HashMap<String, String> = votes
         .into_iter()
@@ -1239,78 +1067,21 @@ pub async fn get_my_votes(
         })
         .collect();
 
-    Ok(Json(GetVotesResponse { votes: votes_map }))
+    let reactions_map = reactions
+        .into_iter()
+        .map(|(comment_id, emojis)| (comment_id.to_string(), emojis))
+        .collect();
+
+    Ok(Json(GetVotesResponse {
+        votes: votes_map,
+        reactions: reactions_map,
+    }))
 }
 
 // ============================================================================
 // Helpers
 // ============================================================================
 
-/// Verify Turnstile if required for current user type
-async fn verify_turnstile(
-    state: &AppState,
-    project_id: &ProjectId,
-    is_anonymous: bool,
-    headers: &axum::http::HeaderMap,
-) -> Result<(), (StatusCode, String)> {
-    let turnstile_settings = &project_id.0.settings.turnstile;
-    if !turnstile_settings.enabled || turnstile_settings.enforce_on == TurnstileEnforcement::None {
-        return Ok(());
-    }
-
-    // Check if anonymous users need verification
-    let requires_verification = match turnstile_settings.enforce_on {
-        TurnstileEnforcement::All => true,
-        TurnstileEnforcement::Anonymous => is_anonymous,
-        TurnstileEnforcement::Unverified => is_anonymous, // Anonymous users are "unverified"
-        TurnstileEnforcement::None => false,
-    };
-
-    if !requires_verification {
-        return Ok(());
-    }
-
-    // Get token and verify
-    let turnstile_token = headers
-        .get("X-Turnstile-Token")
-        .and_then(|v| v.to_str().ok())
-        .ok_or((StatusCode::FORBIDDEN, "Turnstile verification required".into()))?;
-
-    let secret_key = state
-        .config
-        .turnstile
-        .secret_key
-        .as_ref()
-        .ok_or((
-            StatusCode::SERVICE_UNAVAILABLE,
-            "Turnstile not configured".into(),
-        ))?;
-
-    let client_ip = headers
-        .get("x-forwarded-for")
-        .and_then(|v| v.to_str().ok())
-        .and_then(|s| s.split(',').next())
-        .map(|s| s.trim());
-
-    let result = verify_with_cloudflare(secret_key, turnstile_token, client_ip)
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Turnstile error: {}", e),
-            )
-        })?;
-
-    if !result.success {
-        return Err((
-            StatusCode::FORBIDDEN,
-            format!("Turnstile failed: {}", result.error_codes.join(", ")),
-        ));
-    }
-
-    Ok(())
-}
-
 /// Filter tree for API response:
 /// - Remove comments from blocked users
 /// - Only show approved or own pending comments
@@ -1351,6 +1122,135 @@ fn filter_comment_recursive(
     }
 }
 
+// ============================================================================
+// Pagination Helpers
+// ============================================================================
+
+/// Largest page size accepted for paginated comment requests
+const MAX_PAGE_LIMIT: usize = 200;
+
+/// Return a 304 from the in-memory ETag cache if the client's copy is current
+async fn cached_not_modified(
+    state: &AppState,
+    page_id: Uuid,
+    headers: &axum::http::HeaderMap,
+) -> Option<axum::response::Response> {
+    headers.get("if-none-match")?;
+    let cached_ts = state.etag_cache.get(&page_id).await?;
+    // Sub-millisecond 304 response - no Redis hit!
+    not_modified_response(headers, &format!("\"{}\"", cached_ts))
+}
+
+/// Return a 304 if If-None-Match matches the given ETag
+fn not_modified_response(
+    headers: &axum::http::HeaderMap,
+    etag: &str,
+) -> Option<axum::response::Response> {
+    use axum::response::IntoResponse;
+
+    let if_none_match = headers.get("if-none-match").and_then(|v| v.to_str().ok())?;
+    if if_none_match != etag && if_none_match != format!("W/{}", etag) {
+        return None;
+    }
+
+    Some(
+        (
+            StatusCode::NOT_MODIFIED,
+            [
+                ("ETag", etag.to_string()),
+                ("Cache-Control", "public, max-age=0, must-revalidate".to_string()),
+            ],
+        )
+            .into_response(),
+    )
+}
+
+fn parse_cursor(cursor: Option<&str>) -> Result<Option<Uuid>, (StatusCode, String)> {
+    cursor
+        .map(|c| c.parse::<Uuid>())
+        .transpose()
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid cursor".to_string()))
+}
+
+/// Map each comment ID to its position in an already sorted (unfiltered) list
+fn sort_positions(comments: &[TreeComment]) -> std::collections::HashMap<Uuid, usize> {
+    comments.iter().enumerate().map(|(i, c)| (c.id, i)).collect()
+}
+
+/// Take one page of filtered, sorted comments starting after `cursor`.
+///
+/// The cursor is the ID of the last comment of the previous page. It is resolved
+/// through `order` (positions before filtering), so it stays valid while that comment
+/// is in the tree, including after it's deleted (deletion keeps a placeholder) or
+/// hidden. A cursor naming a comment that isn't among `order`, e.g. from another
+/// page or parent, is rejected. Vote-based sorts can still shift between requests
+/// as votes come in.
+fn paginate_comments(
+    comments: Vec<TreeComment>,
+    order: &std::collections::HashMap<Uuid, usize>,
+    cursor: Option<Uuid>,
+    limit: usize,
+) -> Result<(Vec<TreeComment>, Option<String>), (StatusCode, String)> {
+    let limit = limit.clamp(1, MAX_PAGE_LIMIT);
+
+    let start = match cursor {
+        Some(cursor) => {
+            let position = *order
+                .get(&cursor)
+                .ok_or((StatusCode::BAD_REQUEST, "Invalid cursor".to_string()))?;
+            comments
+                .iter()
+                .position(|c| order.get(&c.id).is_some_and(|&p| p > position))
+                .unwrap_or(comments.len())
+        }
+        None => 0,
+    };
+
+    let has_more = comments.len() > start + limit;
+    let page: Vec<TreeComment> = comments.into_iter().skip(start).take(limit).collect();
+    let next_cursor = if has_more {
+        page.last().map(|c| c.id.to_string())
+    } else {
+        None
+    };
+
+    Ok((page, next_cursor))
+}
+
+/// Truncate replies below `max_depth` or beyond `max_replies` per comment,
+/// recording how many were left out in `more_replies`
+fn truncate_replies(
+    comment: &mut TreeComment,
+    depth: usize,
+    max_depth: Option<usize>,
+    max_replies: Option<usize>,
+) {
+    if comment.replies.is_empty() {
+        return;
+    }
+
+    if max_depth.is_some_and(|max| depth >= max) {
+        comment.more_replies = Some(comment.reply_count());
+        comment.replies.clear();
+        return;
+    }
+
+    if let Some(max) = max_replies
+        && comment.replies.len() > max
+    {
+        let omitted: i64 = comment.replies[max..]
+            .iter()
+            .map(|r| 1 + r.reply_count())
+            .sum();
+        comment.replies.truncate(max);
+        comment.more_replies = Some(omitted);
+    }
+
+    for reply in &mut comment.replies {
+        truncate_replies(reply, depth + 1, max_depth, max_replies);
+    }
+}
+
 /// Sort the tree by the specified order
 fn sort_tree(tree: &mut PageTree, sort: SortOrder) {
     sort_comments(&mut tree.comments, sort);
@@ -1411,13 +1311,3 @@ fn sort_comments(comments: &mut [TreeComment], sort: SortOrder) {
         sort_comments(&mut comment.replies, sort);
     }
 }
-
-fn markdown_to_html(content: &str) -> String {
-    // Simple markdown conversion
-    let escaped = content
-        .replace('&', "&amp;")
-        .replace('<', "&lt;")
-        .replace('>', "&gt;");
-
-    format!("<p>{}</p>", escaped.replace("\n\n", "</p><p>"))
-}
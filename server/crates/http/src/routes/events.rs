@@ -0,0 +1,430 @@
+//! Server-Sent Events fallback for real-time page updates.
+//!
+//! For clients behind proxies that break WebSocket upgrades. Streams the same
+//! JSON-RPC `ServerMessage` payloads as the WebSocket server, relayed from the
+//! same Redis pub/sub channels and filtered per viewer the same way. Events that
+//! carry a page sequence number use it as their SSE id, so a reconnecting
+//! `EventSource` resumes through `Last-Event-ID`.
+//!
+//! `EventSource` can't set headers, so signed-in browsers exchange their access
+//! token for a short-lived, single-use ticket and pass that in the URL instead.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use axum::{
+    extract::{ConnectInfo, Path, Query, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    response::sse::{Event, KeepAlive, Sse},
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use futures::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{interval_at, Instant, Interval, MissedTickBehavior};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use threadkit_common::{
+    auth,
+    messages::ServerMessage,
+    pubsub,
+    redis::STREAM_TICKET_TTL,
+    types::{AuthEvent, Role},
+    visibility::Viewer,
+};
+
+use crate::{
+    extractors::{resolve_project_id, AuthUser},
+    state::AppState,
+};
+
+/// How often a stream's session is re-checked, in case a logout or ban event was missed
+const SESSION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/pages/{page_id}/events", get(stream_page_events))
+        .route("/events/ticket", post(create_stream_ticket))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct PageEventsQuery {
+    /// API key, for clients that can't set the `projectid` header (`EventSource`)
+    pub project_id: Option<String>,
+    /// Resume after this event sequence number, for a new `EventSource` (which
+    /// can't set `Last-Event-ID`) reopening a stream with a fresh ticket
+    pub last_event_id: Option<u64>,
+    /// Single-use ticket from `POST /events/ticket`, for clients that can't set the
+    /// `Authorization` header
+    pub ticket: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct StreamTicketResponse {
+    /// Pass as the `ticket` query parameter when opening the event stream
+    pub ticket: String,
+    /// Seconds until the ticket expires
+    pub expires_in: i64,
+}
+
+/// The login session a stream was opened with
+#[derive(Debug, Clone, Copy)]
+struct Session {
+    user_id: Uuid,
+    session_id: Uuid,
+}
+
+/// A stream's live event sources, after any replay
+struct LiveEvents {
+    state: AppState,
+    site_id: Uuid,
+    page_id: Uuid,
+    rx: broadcast::Receiver<ServerMessage>,
+    /// Logouts and bans, while the stream has a session
+    auth_rx: Option<broadcast::Receiver<AuthEvent>>,
+    session: Option<Session>,
+    session_check: Interval,
+    viewer: Viewer,
+    /// Events up to this sequence number were already sent by a replay
+    replayed_through: u64,
+    /// The session was revoked, end the stream
+    done: bool,
+}
+
+/// Stream a page's real-time events
+///
+/// Server-Sent Events fallback for the WebSocket server. Each event's data is a
+/// JSON-RPC notification (`connected`, `new_comment`, `vote_update`, ...). Sequenced
+/// events carry their sequence number as the event id; reconnecting with
+/// `Last-Event-ID` replays what was missed, or sends `resync_required` when the
+/// missed events are no longer available.
+#[utoipa::path(
+    get,
+    path = "/pages/{page_id}/events",
+    tag = "comments",
+    params(
+        ("page_id" = Uuid, Path, description = "Page ID"),
+        ("Last-Event-ID" = Option<u64>, Header, description = "Resume after this event sequence number"),
+        PageEventsQuery
+    ),
+    responses(
+        (status = 200, description = "Event stream", content_type = "text/event-stream", body = String),
+        (status = 401, description = "Invalid API key, token or ticket"),
+        (status = 403, description = "Origin not allowed"),
+        (status = 404, description = "Page not found on this site")
+    ),
+    security(("project_id" = []), ("bearer" = []))
+)]
+pub async fn stream_page_events(
+    State(state): State<AppState>,
+    Path(page_id): Path<Uuid>,
+    Query(query): Query<PageEventsQuery>,
+    headers: HeaderMap,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    let project_id = headers
+        .get("projectid")
+        .and_then(|v| v.to_str().ok())
+        .or(query.project_id.as_deref())
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing projectid header".to_string()))?;
+    let peer = connect_info.map(|Extension(ConnectInfo(addr))| addr.ip().to_canonical());
+    let site = resolve_project_id(&state, project_id, &headers, peer).await?;
+
+    // The viewer's role is for this site, so only its pages may be watched
+    match state.redis.get_page_url(site.site_id, page_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Page not found".to_string())),
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let session = match (token, query.ticket.as_deref()) {
+        (Some(token), _) => verify_token(&state, site.site_id, token).await?,
+        (None, Some(ticket)) => redeem_ticket(&state, site.site_id, ticket).await?,
+        (None, None) => None,
+    };
+    let user_id = session.map(|s| s.user_id);
+
+    let viewer = Viewer::load(state.store.as_ref(), site.site_id, user_id).await;
+
+    // Subscribe before reading the event log so nothing published in between is missed
+    let rx = state.subscribe_page_events(page_id);
+
+    let mut initial = vec![ServerMessage::connected(user_id)];
+    let mut replayed_through = 0;
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .or(query.last_event_id);
+    if let Some(since_seq) = last_event_id {
+        let (messages, through) = pubsub::replay_events(&state.redis, page_id, since_seq).await;
+        initial.extend(messages.into_iter().filter(|m| viewer.can_see(m)));
+        replayed_through = through;
+    }
+
+    let mut session_check = interval_at(Instant::now() + SESSION_CHECK_INTERVAL, SESSION_CHECK_INTERVAL);
+    session_check.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let live = LiveEvents {
+        auth_rx: session.map(|_| state.auth_events.subscribe()),
+        state,
+        site_id: site.site_id,
+        page_id,
+        rx,
+        session,
+        session_check,
+        viewer,
+        replayed_through,
+        done: false,
+    };
+
+    let initial = stream::iter(initial.iter().filter_map(sse_event).map(Ok).collect::<Vec<_>>());
+    let live = stream::unfold(live, |mut live| async move {
+        loop {
+            let message = live.next().await?;
+            if let Some(event) = sse_event(&message) {
+                return Some((Ok(event), live));
+            }
+        }
+    });
+
+    Ok(Sse::new(initial.chain(live)).keep_alive(KeepAlive::default()))
+}
+
+impl LiveEvents {
+    /// Wait for the next message to send, or `None` when the stream should end
+    async fn next(&mut self) -> Option<ServerMessage> {
+        if self.done {
+            return None;
+        }
+
+        loop {
+            tokio::select! {
+                message = self.rx.recv() => match message {
+                    Ok(message) => {
+                        // Skip events the client already got from a replay
+                        if message.seq().is_some_and(|seq| seq <= self.replayed_through) {
+                            continue;
+                        }
+                        if self.viewer.can_see(&message) {
+                            return Some(message);
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        // The client missed events, have it refetch the page
+                        tracing::debug!("SSE stream lagged, skipped {} events", skipped);
+                        let latest_seq = self.state.redis.get_page_event_seq(self.page_id).await.unwrap_or(0);
+                        self.replayed_through = self.replayed_through.max(latest_seq);
+                        return Some(ServerMessage::resync_required(self.page_id, latest_seq));
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+                event = recv_auth_event(&mut self.auth_rx) => {
+                    if let Some(message) = self.apply_auth_event(event).await {
+                        return Some(message);
+                    }
+                }
+                _ = self.session_check.tick(), if self.session.is_some() => {
+                    if let Some(message) = self.check_session().await {
+                        return Some(message);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Re-check the stream's session, like the WebSocket server does periodically
+    ///
+    /// A live session also reloads the viewer, so role and block list changes apply.
+    /// Storage errors leave the session active, so a brief outage doesn't sign everyone out.
+    async fn check_session(&mut self) -> Option<ServerMessage> {
+        let current = self.session?;
+        match self.state.store.get_session_user(current.session_id).await {
+            Ok(Some(uid)) if uid == current.user_id => {}
+            Ok(_) => {
+                return self
+                    .apply_auth_event(AuthEvent::SessionRevoked {
+                        session_id: current.session_id,
+                        user_id: current.user_id,
+                    })
+                    .await;
+            }
+            Err(e) => {
+                tracing::warn!("Failed to check session {}: {}", current.session_id, e);
+                return None;
+            }
+        }
+
+        match self.state.store.get_user_role(self.site_id, current.user_id).await {
+            Ok(Role::Blocked) => {
+                self.apply_auth_event(AuthEvent::UserBlocked {
+                    site_id: self.site_id,
+                    user_id: current.user_id,
+                })
+                .await
+            }
+            _ => {
+                self.viewer = Viewer::load(self.state.store.as_ref(), self.site_id, Some(current.user_id)).await;
+                None
+            }
+        }
+    }
+
+    /// Apply a logout or ban to the stream's identity, returning the message to send if it applies
+    async fn apply_auth_event(&mut self, event: AuthEvent) -> Option<ServerMessage> {
+        let current = self.session?;
+        match event {
+            AuthEvent::SessionRevoked { session_id, .. } if session_id == current.session_id => {
+                // The token is dead, the client must reconnect
+                self.done = true;
+                Some(ServerMessage::signed_out("session_revoked"))
+            }
+            AuthEvent::UserBlocked { site_id, user_id }
+                if site_id == self.site_id && user_id == current.user_id =>
+            {
+                // Banned users can still read, but lose their identity on the stream
+                self.session = None;
+                self.auth_rx = None;
+                self.viewer = Viewer::load(self.state.store.as_ref(), self.site_id, None).await;
+                Some(ServerMessage::signed_out("banned"))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Drop for LiveEvents {
+    /// Drop the page's channel when this was the last stream on it
+    fn drop(&mut self) {
+        // `self.rx` is still alive here, so it counts as one receiver
+        self.state
+            .page_channels
+            .remove_if(&self.page_id, |_, tx| tx.receiver_count() <= 1);
+    }
+}
+
+/// Next logout or ban, waiting forever when the stream has no session
+async fn recv_auth_event(rx: &mut Option<broadcast::Receiver<AuthEvent>>) -> AuthEvent {
+    loop {
+        let Some(rx) = rx.as_mut() else {
+            return std::future::pending().await;
+        };
+        match rx.recv().await {
+            Ok(event) => return event,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return std::future::pending().await,
+        }
+    }
+}
+
+/// Issue a ticket for opening an event stream
+///
+/// `EventSource` can't send the `Authorization` header, and access tokens in URLs end
+/// up in logs and browser history. The ticket is good for one stream within a minute.
+#[utoipa::path(
+    post,
+    path = "/events/ticket",
+    tag = "comments",
+    responses(
+        (status = 200, description = "Stream ticket", body = StreamTicketResponse),
+        (status = 401, description = "Not authenticated")
+    ),
+    security(("bearer" = []))
+)]
+pub async fn create_stream_ticket(
+    State(state): State<AppState>,
+    auth: AuthUser,
+) -> Result<Json<StreamTicketResponse>, (StatusCode, String)> {
+    let ticket = auth::generate_stream_ticket();
+    state
+        .redis
+        .set_stream_ticket(&ticket, auth.site_id, auth.user_id, auth.session_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(StreamTicketResponse {
+        ticket,
+        expires_in: STREAM_TICKET_TTL,
+    }))
+}
+
+/// Check a stream's access token
+async fn verify_token(
+    state: &AppState,
+    site_id: Uuid,
+    token: &str,
+) -> Result<Option<Session>, (StatusCode, String)> {
+    let claims = auth::verify_token(token, &state.config.jwt_secret)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+    if claims.site_id != site_id {
+        return Err((StatusCode::UNAUTHORIZED, "Invalid token".to_string()));
+    }
+
+    verify_session(state, site_id, claims.sub, claims.session_id).await
+}
+
+/// Redeem a stream ticket, which only works once
+async fn redeem_ticket(
+    state: &AppState,
+    site_id: Uuid,
+    ticket: &str,
+) -> Result<Option<Session>, (StatusCode, String)> {
+    let (ticket_site, user_id, session_id) = state
+        .redis
+        .take_stream_ticket(ticket)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::UNAUTHORIZED, "Invalid ticket".to_string()))?;
+    if ticket_site != site_id {
+        return Err((StatusCode::UNAUTHORIZED, "Invalid ticket".to_string()));
+    }
+
+    verify_session(state, site_id, user_id, session_id).await
+}
+
+/// Check that a stream's session is still logged in
+///
+/// Banned users are let in anonymously, like on the WebSocket server.
+async fn verify_session(
+    state: &AppState,
+    site_id: Uuid,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<Option<Session>, (StatusCode, String)> {
+    let session_user = state
+        .store
+        .get_session_user(session_id)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to verify session".to_string()))?;
+    if session_user != Some(user_id) {
+        return Err((StatusCode::UNAUTHORIZED, "Session expired".to_string()));
+    }
+
+    let role = state
+        .store
+        .get_user_role(site_id, user_id)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get user role".to_string()))?;
+    if role == Role::Blocked {
+        return Ok(None);
+    }
+
+    Ok(Some(Session { user_id, session_id }))
+}
+
+/// An SSE event for a message, with its page sequence number as the event id
+fn sse_event(message: &ServerMessage) -> Option<Event> {
+    let json = message.to_json().ok()?;
+    let event = Event::default().data(json);
+    Some(match message.seq() {
+        Some(seq) => event.id(seq.to_string()),
+        None => event,
+    })
+}
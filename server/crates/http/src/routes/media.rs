@@ -7,12 +7,14 @@ use axum::{
 };
 use chrono::Utc;
 use serde::Serialize;
-use threadkit_common::{image_processing, types::MediaInfo, ActionLogBuilder, ActionType};
+use threadkit_common::{
+    image_processing, quotas::QuotaMetric, types::MediaInfo, ActionLogBuilder, ActionType,
+};
 use utoipa::ToSchema;
 use uuid::Uuid;
 
 use crate::{
-    extractors::{AuthUser, AuthUserWithRole, ProjectId},
+    extractors::{AuthUser, AuthUserWithRole, ClientIp, ProjectId},
     state::AppState,
 };
 
@@ -24,14 +26,6 @@ const AVATAR_SIZE_PX: u32 = 200;
 // Helper Functions
 // ============================================================================
 
-/// Extract IP address from headers (prioritizes X-Forwarded-For)
-fn extract_ip(headers: &axum::http::HeaderMap) -> Option<String> {
-    headers
-        .get("x-forwarded-for")
-        .and_then(|v| v.to_str().ok())
-        .map(String::from)
-}
-
 /// Extract user agent from headers
 fn extract_user_agent(headers: &axum::http::HeaderMap) -> Option<String> {
     headers
@@ -204,9 +198,16 @@ pub async fn upload_avatar(
         .await
         .ok();
 
+    // Avatars count towards the site's media storage, but aren't refused when it's full
+    state
+        .redis
+        .increment_media_storage(project_id.0.site_id, resized.len() as i64)
+        .await
+        .ok();
+
     // Update user avatar_url
     let mut user = state
-        .redis
+        .store
         .get_user(auth.user_id)
         .await
         .map_err(|_| {
@@ -219,7 +220,7 @@ pub async fn upload_avatar(
 
     user.avatar_url = Some(url.clone());
     state
-        .redis
+        .store
         .set_user(&user)
         .await
         .map_err(|_| {
@@ -254,6 +255,7 @@ pub async fn upload_avatar(
     responses(
         (status = 200, description = "Image uploaded successfully", body = UploadResponse),
         (status = 400, description = "Bad request - invalid image or unsupported format"),
+        (status = 402, description = "Site's media storage limit reached"),
         (status = 413, description = "File too large"),
         (status = 501, description = "File uploads not enabled"),
     ),
@@ -265,6 +267,7 @@ pub async fn upload_image(
     State(state): State<AppState>,
     project_id: ProjectId,
     auth: AuthUser,
+    ClientIp(client_ip): ClientIp,
     headers: axum::http::HeaderMap,
     mut multipart: Multipart,
 ) -> Result<Json<UploadResponse>, (StatusCode, String)> {
@@ -344,6 +347,18 @@ pub async fn upload_image(
         compression_stats.compression_ratio
     );
 
+    // Check the plan's media storage limit
+    state
+        .quotas
+        .check(&project_id.0, QuotaMetric::MediaStorageBytes, webp_data.len() as u64)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::from_u16(e.status_code()).unwrap_or(StatusCode::PAYMENT_REQUIRED),
+                e.to_string(),
+            )
+        })?;
+
     let media_id = Uuid::now_v7();
     let url = storage
         .upload_file(media_id, "image/webp", webp_data.clone(), "images")
@@ -385,6 +400,12 @@ pub async fn upload_image(
         .await
         .ok();
 
+    state
+        .redis
+        .increment_media_storage(project_id.0.site_id, webp_data.len() as i64)
+        .await
+        .ok();
+
     tracing::info!(
         "Image uploaded: media_id={} user_id={} size={} dimensions={}x{}",
         media_id,
@@ -395,7 +416,7 @@ pub async fn upload_image(
     );
 
     // Log action
-    let user_email = state.redis.get_user(auth.user_id).await.ok()
+    let user_email = state.store.get_user(auth.user_id).await.ok()
         .and_then(|u| u)
         .and_then(|u| u.email);
 
@@ -412,14 +433,14 @@ pub async fn upload_image(
     if let Some(email) = user_email {
         log_entry = log_entry.user_email(email);
     }
-    if let Some(ip) = extract_ip(&headers) {
+    if let Some(ip) = client_ip {
         log_entry = log_entry.ip(ip);
     }
     if let Some(ua) = extract_user_agent(&headers) {
         log_entry = log_entry.user_agent(ua);
     }
 
-    state.action_logger.log(log_entry.build());
+    state.log_action(log_entry.build());
 
     Ok(Json(UploadResponse {
         media_id,
@@ -512,6 +533,12 @@ pub async fn delete_media(
         .await
         .ok();
 
+    state
+        .redis
+        .increment_media_storage(info.site_id, -(info.size_bytes as i64))
+        .await
+        .ok();
+
     tracing::info!(
         "Media deleted: media_id={} by_user={}",
         media_id,
@@ -3,10 +3,14 @@ use axum::Router;
 use crate::state::AppState;
 
 pub mod admin;
+pub mod analytics;
+pub mod api_tokens;
 pub mod auth;
 pub mod comments;
+pub mod events;
 pub mod media;
 pub mod moderation;
+pub mod sites;
 pub mod turnstile;
 pub mod users;
 pub mod version;
@@ -15,9 +19,13 @@ pub fn router() -> Router<AppState> {
     Router::new()
         .merge(auth::router())
         .merge(comments::router())
+        .merge(events::router())
         .merge(media::router())
         .merge(moderation::router())
         .merge(users::router())
         .merge(admin::router())
+        .merge(analytics::router())
+        .merge(api_tokens::router())
+        .merge(sites::router())
         .merge(turnstile::router())
 }
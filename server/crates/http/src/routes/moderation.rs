@@ -8,11 +8,13 @@ use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
-use threadkit_common::types::{CommentStatus, TreeComment, UserPublic, DELETED_USER_ID};
-use threadkit_common::{ActionLogBuilder, ActionType};
+use threadkit_common::types::{
+    ApiTokenScope, AuthEvent, CommentStatus, TreeComment, UserPublic, DELETED_USER_ID,
+};
+use threadkit_common::ActionType;
 
 use crate::{
-    extractors::{ProjectId, AuthUserWithRole},
+    extractors::{ClientIp, StaffAccess},
     state::AppState,
 };
 
@@ -20,14 +22,6 @@ use crate::{
 // Helper Functions
 // ============================================================================
 
-/// Extract IP address from headers (prioritizes X-Forwarded-For)
-fn extract_ip(headers: &axum::http::HeaderMap) -> Option<String> {
-    headers
-        .get("x-forwarded-for")
-        .and_then(|v| v.to_str().ok())
-        .map(String::from)
-}
-
 /// Extract user agent from headers
 fn extract_user_agent(headers: &axum::http::HeaderMap) -> Option<String> {
     headers
@@ -135,11 +129,10 @@ pub struct BanUserResponse {
 )]
 pub async fn get_queue(
     State(state): State<AppState>,
-    project_id: ProjectId,
-    auth: AuthUserWithRole,
+    auth: StaffAccess,
     Query(query): Query<PaginationQuery>,
 ) -> Result<Json<QueueResponse>, (StatusCode, String)> {
-    auth.require_moderator()?;
+    auth.require_moderator(ApiTokenScope::Moderate)?;
 
     let offset = query.offset.unwrap_or(0);
     let limit = query.limit.unwrap_or(50).min(100);
@@ -147,14 +140,14 @@ pub async fn get_queue(
     // Get modqueue items (page_id:comment_id pairs)
     let queue_items = state
         .redis
-        .get_modqueue_v2(project_id.0.site_id, offset, limit)
+        .get_modqueue_v2(auth.site_id, offset, limit)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // Fetch all page trees in parallel
     let tree_futures: Vec<_> = queue_items
         .iter()
-        .map(|(page_id, _)| state.redis.get_page_tree(*page_id))
+        .map(|(page_id, _)| state.store.get_page_tree(*page_id))
         .collect();
 
     let tree_results = futures::future::join_all(tree_futures).await;
@@ -195,11 +188,10 @@ pub async fn get_queue(
 )]
 pub async fn get_reports(
     State(state): State<AppState>,
-    project_id: ProjectId,
-    auth: AuthUserWithRole,
+    auth: StaffAccess,
     Query(query): Query<PaginationQuery>,
 ) -> Result<Json<ReportsResponse>, (StatusCode, String)> {
-    auth.require_moderator()?;
+    auth.require_moderator(ApiTokenScope::Moderate)?;
 
     let offset = query.offset.unwrap_or(0);
     let limit = query.limit.unwrap_or(50).min(100);
@@ -207,14 +199,14 @@ pub async fn get_reports(
     // Get report items (page_id:comment_id pairs)
     let report_items = state
         .redis
-        .get_reports_v2(project_id.0.site_id, offset, limit)
+        .get_reports_v2(auth.site_id, offset, limit)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // Fetch all page trees in parallel
     let tree_futures: Vec<_> = report_items
         .iter()
-        .map(|(page_id, _)| state.redis.get_page_tree(*page_id))
+        .map(|(page_id, _)| state.store.get_page_tree(*page_id))
         .collect();
 
     let tree_results = futures::future::join_all(tree_futures).await;
@@ -263,45 +255,56 @@ pub async fn get_reports(
 )]
 pub async fn approve_comment(
     State(state): State<AppState>,
-    project_id: ProjectId,
-    auth: AuthUserWithRole,
+    auth: StaffAccess,
     Path(comment_id): Path<Uuid>,
     Json(req): Json<ModerateCommentRequest>,
 ) -> Result<StatusCode, (StatusCode, String)> {
-    auth.require_moderator()?;
+    auth.require_moderator(ApiTokenScope::Moderate)?;
 
     // Validate path ends with the correct comment ID
     if req.path.is_empty() || *req.path.last().unwrap() != comment_id {
         return Err((StatusCode::BAD_REQUEST, "Path must end with comment ID".into()));
     }
 
-    // Get the page tree
-    let mut tree = state
-        .redis
-        .get_page_tree(req.page_id)
+    // Atomically find and approve the comment
+    let (_, was_pending) = state
+        .store
+        .update_page_tree(req.page_id, |tree| {
+            let comment = tree
+                .find_by_path_mut(&req.path)
+                .ok_or((StatusCode::NOT_FOUND, "Comment not found".to_string()))?;
+
+            // Set status to approved (None in our schema means approved)
+            let was_pending = comment.status == Some(CommentStatus::Pending);
+            comment.status = None;
+            Ok(was_pending)
+        })
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-        .ok_or((StatusCode::NOT_FOUND, "Page not found".into()))?;
-
-    // Find and update the comment
-    let comment = tree
-        .find_by_path_mut(&req.path)
-        .ok_or((StatusCode::NOT_FOUND, "Comment not found".into()))?;
-
-    // Set status to approved (None in our schema means approved)
-    comment.status = None;
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))??;
+
+    // Reply notifications were held until now
+    if was_pending {
+        let comments = state.comments.clone();
+        let site_id = auth.site_id;
+        let (page_id, path) = (req.page_id, req.path.clone());
+        tokio::spawn(async move {
+            if let Err(e) = comments.notify_approved(site_id, page_id, &path).await {
+                tracing::warn!("Failed to send notifications for approved comment: {}", e);
+            }
+        });
+    }
 
-    // Save the updated tree
-    state
-        .redis
-        .set_page_tree(req.page_id, &tree)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    state.log_action(
+        auth.action(ActionType::CommentApproved)
+            .page_id(req.page_id)
+            .comment_id(comment_id)
+            .build(),
+    );
 
     // Remove from modqueue
     let _ = state
         .redis
-        .remove_from_modqueue_v2(project_id.0.site_id, req.page_id, comment_id)
+        .remove_from_modqueue_v2(auth.site_id, req.page_id, comment_id)
         .await;
 
     // Publish update for real-time clients
@@ -338,44 +341,42 @@ pub async fn approve_comment(
 )]
 pub async fn reject_comment(
     State(state): State<AppState>,
-    project_id: ProjectId,
-    auth: AuthUserWithRole,
+    auth: StaffAccess,
     Path(comment_id): Path<Uuid>,
     Json(req): Json<ModerateCommentRequest>,
 ) -> Result<StatusCode, (StatusCode, String)> {
-    auth.require_moderator()?;
+    auth.require_moderator(ApiTokenScope::Moderate)?;
 
     // Validate path
     if req.path.is_empty() || *req.path.last().unwrap() != comment_id {
         return Err((StatusCode::BAD_REQUEST, "Path must end with comment ID".into()));
     }
 
-    // Get the page tree
-    let mut tree = state
-        .redis
-        .get_page_tree(req.page_id)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-        .ok_or((StatusCode::NOT_FOUND, "Page not found".into()))?;
-
-    // Find and update the comment
-    let comment = tree
-        .find_by_path_mut(&req.path)
-        .ok_or((StatusCode::NOT_FOUND, "Comment not found".into()))?;
-
-    comment.status = Some(CommentStatus::Rejected);
-
-    // Save the updated tree
+    // Atomically find and reject the comment
     state
-        .redis
-        .set_page_tree(req.page_id, &tree)
+        .store
+        .update_page_tree(req.page_id, |tree| {
+            let comment = tree
+                .find_by_path_mut(&req.path)
+                .ok_or((StatusCode::NOT_FOUND, "Comment not found".to_string()))?;
+
+            comment.status = Some(CommentStatus::Rejected);
+            Ok(())
+        })
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))??;
+
+    state.log_action(
+        auth.action(ActionType::CommentRejected)
+            .page_id(req.page_id)
+            .comment_id(comment_id)
+            .build(),
+    );
 
     // Remove from modqueue
     let _ = state
         .redis
-        .remove_from_modqueue_v2(project_id.0.site_id, req.page_id, comment_id)
+        .remove_from_modqueue_v2(auth.site_id, req.page_id, comment_id)
         .await;
 
     Ok(StatusCode::OK)
@@ -400,23 +401,23 @@ pub async fn reject_comment(
 )]
 pub async fn ban_user(
     State(state): State<AppState>,
-    project_id: ProjectId,
-    auth: AuthUserWithRole,
+    auth: StaffAccess,
     Path(user_id): Path<Uuid>,
+    ClientIp(client_ip): ClientIp,
     headers: axum::http::HeaderMap,
     Json(req): Json<BanUserRequest>,
 ) -> Result<Json<BanUserResponse>, (StatusCode, String)> {
-    auth.require_moderator()?;
+    auth.require_moderator(ApiTokenScope::Moderate)?;
 
     // Can't ban yourself
-    if user_id == auth.user_id {
+    if Some(user_id) == auth.user_id {
         return Err((StatusCode::BAD_REQUEST, "Cannot ban yourself".into()));
     }
 
     // Check target's role - can't ban someone with higher role
     let target_role = state
-        .redis
-        .get_user_role(project_id.0.site_id, user_id)
+        .store
+        .get_user_role(auth.site_id, user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
@@ -426,18 +427,24 @@ pub async fn ban_user(
 
     // Block the user
     state
-        .redis
-        .block_user(project_id.0.site_id, user_id)
+        .store
+        .block_user(auth.site_id, user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    // Drop the user's identity on open WebSocket connections
+    state.publish_auth_event(AuthEvent::UserBlocked {
+        site_id: auth.site_id,
+        user_id,
+    }).await;
+
     // Optionally delete all their comments
     let mut comments_deleted = 0i64;
     if req.delete_comments {
         // Get user's comments on this site
         let comments = state
             .redis
-            .get_user_site_comments(user_id, project_id.0.site_id, 0, 10000) // Get all
+            .get_user_site_comments(user_id, auth.site_id, 0, 10000) // Get all
             .await
             .unwrap_or_default();
 
@@ -449,32 +456,38 @@ pub async fn ban_user(
 
         // Update each page tree
         for (page_id, comment_ids) in pages {
-            if let Ok(Some(mut tree)) = state.redis.get_page_tree(page_id).await {
-                let mut modified = false;
-                for comment_id in comment_ids {
-                    if mark_comment_deleted_by_admin(&mut tree.comments, comment_id) {
-                        comments_deleted += 1;
-                        modified = true;
-                    }
-                }
-                if modified {
-                    let _ = state.redis.set_page_tree(page_id, &tree).await;
-                }
+            let result = state
+                .store
+                .update_page_tree(page_id, |tree| {
+                    let deleted = comment_ids
+                        .iter()
+                        .filter(|id| mark_comment_deleted_by_admin(&mut tree.comments, **id))
+                        .count() as i64;
+                    // Skip the write if none of the comments are on this page anymore
+                    if deleted == 0 { Err(()) } else { Ok(deleted) }
+                })
+                .await;
+            if let Ok(Ok((tree, deleted))) = result {
+                comments_deleted += deleted;
+                state.etag_cache.insert(page_id, tree.updated_at).await;
             }
         }
     }
 
     // Log action
-    let moderator_email = state.redis.get_user(auth.user_id).await.ok()
+    let moderator_email = match auth.user_id {
+        Some(moderator_id) => state.store.get_user(moderator_id).await.ok()
+            .and_then(|u| u)
+            .and_then(|u| u.email),
+        None => None,
+    };
+
+    let target_email = state.store.get_user(user_id).await.ok()
         .and_then(|u| u)
         .and_then(|u| u.email);
 
-    let target_email = state.redis.get_user(user_id).await.ok()
-        .and_then(|u| u)
-        .and_then(|u| u.email);
-
-    let mut log_entry = ActionLogBuilder::new(ActionType::UserBanned, project_id.0.site_id)
-        .user_id(auth.user_id)
+    let mut log_entry = auth
+        .action(ActionType::UserBanned)
         .metadata(serde_json::json!({
             "banned_user_id": user_id,
             "banned_user_email": target_email,
@@ -485,14 +498,21 @@ pub async fn ban_user(
     if let Some(email) = moderator_email {
         log_entry = log_entry.user_email(email);
     }
-    if let Some(ip) = extract_ip(&headers) {
+    if let Some(ip) = client_ip {
         log_entry = log_entry.ip(ip);
     }
     if let Some(ua) = extract_user_agent(&headers) {
         log_entry = log_entry.user_agent(ua);
     }
 
-    state.action_logger.log(log_entry.build());
+    state.log_action(log_entry.build());
+
+    state.publish_moderation_event(auth.site_id, "user_banned", serde_json::json!({
+        "user_id": user_id,
+        "moderator_id": auth.user_id,
+        "shadowban": false,
+        "comments_deleted": comments_deleted,
+    })).await;
 
     Ok(Json(BanUserResponse { comments_deleted }))
 }
@@ -512,18 +532,26 @@ pub async fn ban_user(
 )]
 pub async fn unban_user(
     State(state): State<AppState>,
-    project_id: ProjectId,
-    auth: AuthUserWithRole,
+    auth: StaffAccess,
     Path(user_id): Path<Uuid>,
 ) -> Result<StatusCode, (StatusCode, String)> {
-    auth.require_moderator()?;
+    auth.require_moderator(ApiTokenScope::Moderate)?;
 
     state
-        .redis
-        .unblock_user(project_id.0.site_id, user_id)
+        .store
+        .unblock_user(auth.site_id, user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    state.log_action(
+        auth.action(ActionType::UserUnbanned)
+            .metadata(serde_json::json!({
+                "unbanned_user_id": user_id,
+                "moderator_id": auth.user_id
+            }))
+            .build(),
+    );
+
     Ok(StatusCode::OK)
 }
 
@@ -544,21 +572,20 @@ pub async fn unban_user(
 )]
 pub async fn shadowban_user(
     State(state): State<AppState>,
-    project_id: ProjectId,
-    auth: AuthUserWithRole,
+    auth: StaffAccess,
     Path(user_id): Path<Uuid>,
 ) -> Result<StatusCode, (StatusCode, String)> {
-    auth.require_moderator()?;
+    auth.require_moderator(ApiTokenScope::Moderate)?;
 
     // Can't shadowban yourself
-    if user_id == auth.user_id {
+    if Some(user_id) == auth.user_id {
         return Err((StatusCode::BAD_REQUEST, "Cannot shadowban yourself".into()));
     }
 
     // Check target's role
     let target_role = state
-        .redis
-        .get_user_role(project_id.0.site_id, user_id)
+        .store
+        .get_user_role(auth.site_id, user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
@@ -567,11 +594,27 @@ pub async fn shadowban_user(
     }
 
     state
-        .redis
-        .shadowban_user(project_id.0.site_id, user_id)
+        .store
+        .shadowban_user(auth.site_id, user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    state.log_action(
+        auth.action(ActionType::UserShadowbanned)
+            .metadata(serde_json::json!({
+                "shadowbanned_user_id": user_id,
+                "moderator_id": auth.user_id
+            }))
+            .build(),
+    );
+
+    state.publish_moderation_event(auth.site_id, "user_banned", serde_json::json!({
+        "user_id": user_id,
+        "moderator_id": auth.user_id,
+        "shadowban": true,
+        "comments_deleted": 0,
+    })).await;
+
     Ok(StatusCode::OK)
 }
 
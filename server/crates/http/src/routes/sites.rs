@@ -0,0 +1,751 @@
+//! Site management for SaaS platform operators
+//!
+//! Creates, edits and deletes tenants' `SiteConfig` records, authenticated with
+//! `ADMIN_API_KEY` rather than a site's own keys, sets their plans and reports their usage
+//! against the plan's limits. Standalone servers manage their single site with
+//! `--create-site` / `--edit-site` instead.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use threadkit_common::{
+    auth::{generate_api_key, PUBLIC_KEY_PREFIX, SECRET_KEY_PREFIX},
+    quotas::{QuotaExceeded, QuotaMetric, QuotaUsage, COMMENTS_FIELD, MODERATION_CHECKS_FIELD},
+    redis::RedisClient,
+    types::{
+        AuthSettings, ContentModerationSettings, DisplaySettings, EditSettings, EmailSettings, ModerationMode,
+        PlanLimits, ReactionSettings, SiteConfig, SitePlan, SiteRateLimitSettings, SiteSettings,
+        TurnstileSettings,
+    },
+    Config,
+};
+
+use crate::{extractors::PlatformAdmin, routes::admin::validate_reaction_settings, state::AppState};
+
+/// Maximum length of a site name
+const MAX_SITE_NAME_LENGTH: usize = 100;
+
+/// Maximum extra origins per site
+const MAX_ALLOWED_ORIGINS: usize = 50;
+
+/// Longest Turnstile verification cache (one day)
+const MAX_TURNSTILE_CACHE_SECONDS: u32 = 24 * 60 * 60;
+
+/// How long replaced API keys keep working by default (one day)
+const DEFAULT_KEY_GRACE_SECONDS: u64 = 24 * 60 * 60;
+
+/// Longest grace period for replaced API keys (one week)
+const MAX_KEY_GRACE_SECONDS: u64 = 7 * 24 * 60 * 60;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/admin/sites", get(list_sites).post(create_site))
+        .route("/admin/sites/{id}", get(get_site).patch(update_site).delete(delete_site))
+        .route("/admin/sites/{id}/rotate-keys", post(rotate_keys))
+        .route("/admin/sites/{id}/usage", get(get_site_usage))
+}
+
+// ============================================================================
+// Request/Response Types
+// ============================================================================
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ListSitesQuery {
+    /// Number of sites to skip (default: 0)
+    #[param(default = 0)]
+    pub offset: Option<usize>,
+    /// Maximum number of sites to return (default: 50, max: 100)
+    #[param(default = 50)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SitesResponse {
+    /// Sites, oldest first
+    pub sites: Vec<SiteConfig>,
+    /// Total number of sites
+    pub total: usize,
+    /// Whether there are more sites available
+    pub has_more: bool,
+}
+
+/// Settings sections to replace (omitted sections are left unchanged)
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct SiteSettingsUpdate {
+    pub moderation_mode: Option<ModerationMode>,
+    pub auth: Option<AuthSettings>,
+    pub display: Option<DisplaySettings>,
+    /// Per-site rate limits (null fields use the server defaults)
+    pub rate_limits: Option<SiteRateLimitSettings>,
+    pub content_moderation: Option<ContentModerationSettings>,
+    pub turnstile: Option<TurnstileSettings>,
+    /// Extra host names allowed to use the public key, besides the site's domain
+    /// (`*.example.com` allows subdomains)
+    pub allowed_origins: Option<Vec<String>>,
+    /// Disable new comments site-wide
+    pub posting_disabled: Option<bool>,
+    /// Edit window (null or 0 = no limit) and revision visibility
+    pub editing: Option<EditSettings>,
+    /// Reaction emoji set (empty disables reactions)
+    pub reactions: Option<ReactionSettings>,
+    /// Email sender name (null or empty = the site name) and notification emails
+    pub email: Option<EmailSettings>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateSiteRequest {
+    /// Display name
+    pub name: String,
+    /// Primary domain (host name, e.g. `example.com`); subdomains are allowed too
+    pub domain: String,
+    /// Plan and owning account (default: no plan, no limits)
+    #[serde(default)]
+    pub plan: SitePlan,
+    /// Initial settings (defaults to email sign-in only)
+    #[serde(flatten)]
+    pub settings: SiteSettingsUpdate,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateSiteRequest {
+    pub name: Option<String>,
+    pub domain: Option<String>,
+    /// Replaces the site's plan
+    pub plan: Option<SitePlan>,
+    #[serde(flatten)]
+    pub settings: SiteSettingsUpdate,
+}
+
+/// Which API keys to replace
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RotatedKeys {
+    Public,
+    Secret,
+    #[default]
+    Both,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RotateKeysRequest {
+    /// Keys to replace (default: both)
+    #[serde(default)]
+    pub keys: RotatedKeys,
+    /// Seconds the replaced keys keep working (default: 86400, max: 604800, 0 revokes them immediately)
+    pub grace_period_seconds: Option<u64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RotateKeysResponse {
+    /// The site with its new keys
+    pub site: SiteConfig,
+    /// When the replaced keys stop working (absent if they were revoked immediately)
+    pub previous_keys_expire_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct SiteUsageQuery {
+    /// Month to report, `YYYY-MM` (default: the current month, UTC)
+    pub month: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SiteUsageResponse {
+    pub site_id: Uuid,
+    /// Month reported (`YYYY-MM`)
+    pub month: String,
+    pub plan: SitePlan,
+    /// Limits in effect (the plan's custom limits, or its tier's)
+    pub limits: PlanLimits,
+    /// Page views that month (not limited)
+    pub pageviews: u64,
+    /// Usage against each limit. Comments and moderation checks are for `month`;
+    /// media storage and sites are current totals.
+    pub quotas: Vec<QuotaUsage>,
+}
+
+// ============================================================================
+// Validation
+// ============================================================================
+
+/// Whether `host` is a DNS host name (lowercase labels of letters, digits and hyphens)
+fn is_host_name(host: &str) -> bool {
+    !host.is_empty()
+        && host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        })
+}
+
+fn validate_name(name: &str) -> Result<String, (StatusCode, String)> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Name cannot be empty".into()));
+    }
+    if name.chars().count() > MAX_SITE_NAME_LENGTH {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Name must be at most {} characters", MAX_SITE_NAME_LENGTH),
+        ));
+    }
+    Ok(name.to_string())
+}
+
+fn validate_domain(domain: &str) -> Result<String, (StatusCode, String)> {
+    let domain = domain.trim().to_lowercase();
+    if !is_host_name(&domain) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Invalid domain '{}' (expected a host name like example.com)", domain),
+        ));
+    }
+    Ok(domain)
+}
+
+fn validate_allowed_origins(origins: Vec<String>) -> Result<Vec<String>, (StatusCode, String)> {
+    if origins.len() > MAX_ALLOWED_ORIGINS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("At most {} allowed origins", MAX_ALLOWED_ORIGINS),
+        ));
+    }
+
+    let mut allowed: Vec<String> = Vec::with_capacity(origins.len());
+    for origin in origins {
+        let origin = origin.trim().to_lowercase();
+        if !is_host_name(origin.strip_prefix("*.").unwrap_or(&origin)) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Invalid origin '{}' (expected a host name like app.example.com or *.example.com)", origin),
+            ));
+        }
+        if !allowed.contains(&origin) {
+            allowed.push(origin);
+        }
+    }
+    Ok(allowed)
+}
+
+impl SiteSettingsUpdate {
+    /// Validate the given sections and write them into `settings`
+    fn apply(self, settings: &mut SiteSettings, config: &Config) -> Result<(), (StatusCode, String)> {
+        if let Some(moderation_mode) = self.moderation_mode {
+            settings.moderation_mode = moderation_mode;
+        }
+        if let Some(auth) = self.auth {
+            settings.auth = auth;
+        }
+        if let Some(display) = self.display {
+            settings.display = display;
+        }
+
+        if let Some(rate_limits) = self.rate_limits {
+            let limits = [
+                rate_limits.ip_writes_per_minute,
+                rate_limits.ip_reads_per_minute,
+                rate_limits.user_writes_per_minute,
+                rate_limits.user_reads_per_minute,
+                rate_limits.auth_attempts_per_hour,
+            ];
+            if limits.contains(&Some(0)) {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "Rate limits must be at least 1 (null uses the server default)".into(),
+                ));
+            }
+            settings.rate_limits = rate_limits;
+        }
+
+        if let Some(content_moderation) = self.content_moderation {
+            if !(0.0..=1.0).contains(&content_moderation.confidence_threshold) {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "confidence_threshold must be between 0.0 and 1.0".into(),
+                ));
+            }
+            settings.content_moderation = content_moderation;
+        }
+
+        if let Some(turnstile) = self.turnstile {
+            if turnstile.enabled && config.turnstile.secret_key.is_none() {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "Turnstile is not configured on this server (TURNSTILE_SECRET_KEY)".into(),
+                ));
+            }
+            if turnstile.cache_duration_seconds > MAX_TURNSTILE_CACHE_SECONDS {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("cache_duration_seconds must be at most {}", MAX_TURNSTILE_CACHE_SECONDS),
+                ));
+            }
+            settings.turnstile = turnstile;
+        }
+
+        if let Some(allowed_origins) = self.allowed_origins {
+            settings.allowed_origins = validate_allowed_origins(allowed_origins)?;
+        }
+        if let Some(posting_disabled) = self.posting_disabled {
+            settings.posting_disabled = posting_disabled;
+        }
+
+        if let Some(mut editing) = self.editing {
+            // Like `--edit-site edit_window_minutes 0`, a zero window removes the limit
+            editing.edit_window_minutes = editing.edit_window_minutes.filter(|minutes| *minutes > 0);
+            settings.editing = editing;
+        }
+        if let Some(reactions) = self.reactions {
+            settings.reactions = validate_reaction_settings(reactions)?;
+        }
+        if let Some(mut email) = self.email {
+            email.sender_name = email
+                .sender_name
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty());
+            if let Some(name) = &email.sender_name {
+                // Goes into the From header
+                if name.chars().any(char::is_control) || name.chars().count() > MAX_SITE_NAME_LENGTH {
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        format!(
+                            "sender_name must be at most {} characters, without control characters",
+                            MAX_SITE_NAME_LENGTH
+                        ),
+                    ));
+                }
+            }
+            settings.email = email;
+        }
+
+        Ok(())
+    }
+}
+
+fn validate_plan(mut plan: SitePlan) -> SitePlan {
+    plan.owner_id = plan
+        .owner_id
+        .map(|owner_id| owner_id.trim().to_string())
+        .filter(|owner_id| !owner_id.is_empty());
+    plan
+}
+
+fn validate_month(month: &str) -> Result<(), (StatusCode, String)> {
+    if month.len() != 7 || NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d").is_err() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Invalid month '{}' (expected YYYY-MM)", month),
+        ));
+    }
+    Ok(())
+}
+
+/// Number of sites belonging to `owner_id`, other than `except`
+///
+/// Reads every site config, like the site listing.
+async fn count_owner_sites(state: &AppState, owner_id: &str, except: Uuid) -> Result<u64, (StatusCode, String)> {
+    let ids = state
+        .redis
+        .list_site_ids()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let mut count = 0;
+    for site_id in ids.into_iter().filter(|id| *id != except) {
+        let site = state
+            .redis
+            .get_site_config(site_id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        if site.is_some_and(|site| site.plan.owner_id.as_deref() == Some(owner_id)) {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Refuse (402) a site that would take its owner over the plan's `max_sites`
+async fn check_site_limit(state: &AppState, site: &SiteConfig) -> Result<(), (StatusCode, String)> {
+    let (Some(owner_id), Some(limit)) = (
+        site.plan.owner_id.as_deref(),
+        QuotaMetric::Sites.limit(&site.plan.effective_limits()),
+    ) else {
+        return Ok(());
+    };
+
+    if count_owner_sites(state, owner_id, site.id).await? >= limit {
+        let e = QuotaExceeded {
+            metric: QuotaMetric::Sites,
+            limit,
+        };
+        return Err((StatusCode::PAYMENT_REQUIRED, e.to_string()));
+    }
+    Ok(())
+}
+
+/// Load a site's stored config, or 404
+async fn load_site(state: &AppState, site_id: Uuid) -> Result<SiteConfig, (StatusCode, String)> {
+    state
+        .redis
+        .get_site_config(site_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Site not found".into()))
+}
+
+// ============================================================================
+// Handlers (platform operators - requires ADMIN_API_KEY)
+// ============================================================================
+
+/// List all sites (platform admin, SaaS mode)
+#[utoipa::path(
+    get,
+    path = "/admin/sites",
+    tag = "sites",
+    params(ListSitesQuery),
+    responses(
+        (status = 200, description = "Sites", body = SitesResponse),
+        (status = 401, description = "Invalid admin API key"),
+        (status = 403, description = "Not in SaaS mode, or ADMIN_API_KEY not set")
+    ),
+    security(("admin_api_key" = []))
+)]
+pub async fn list_sites(
+    State(state): State<AppState>,
+    _admin: PlatformAdmin,
+    Query(query): Query<ListSitesQuery>,
+) -> Result<Json<SitesResponse>, (StatusCode, String)> {
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+
+    let ids = state
+        .redis
+        .list_site_ids()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let mut sites = Vec::new();
+    for site_id in ids.iter().skip(offset).take(limit) {
+        // Skip configs deleted since the listing
+        if let Some(site) = state
+            .redis
+            .get_site_config(*site_id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        {
+            sites.push(site);
+        }
+    }
+
+    Ok(Json(SitesResponse {
+        sites,
+        total: ids.len(),
+        has_more: offset + limit < ids.len(),
+    }))
+}
+
+/// Create a site with new API keys (platform admin, SaaS mode)
+#[utoipa::path(
+    post,
+    path = "/admin/sites",
+    tag = "sites",
+    request_body = CreateSiteRequest,
+    responses(
+        (status = 200, description = "Site created", body = SiteConfig),
+        (status = 400, description = "Invalid settings"),
+        (status = 401, description = "Invalid admin API key"),
+        (status = 402, description = "The owner's plan allows no more sites"),
+        (status = 403, description = "Not in SaaS mode, or ADMIN_API_KEY not set")
+    ),
+    security(("admin_api_key" = []))
+)]
+pub async fn create_site(
+    State(state): State<AppState>,
+    _admin: PlatformAdmin,
+    Json(req): Json<CreateSiteRequest>,
+) -> Result<Json<SiteConfig>, (StatusCode, String)> {
+    let mut settings = SiteSettings {
+        auth: AuthSettings {
+            email: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    req.settings.apply(&mut settings, &state.config)?;
+
+    let site = SiteConfig {
+        id: Uuid::now_v7(),
+        name: validate_name(&req.name)?,
+        domain: validate_domain(&req.domain)?,
+        project_id_public: generate_api_key(PUBLIC_KEY_PREFIX),
+        project_id_secret: generate_api_key(SECRET_KEY_PREFIX),
+        settings,
+        plan: validate_plan(req.plan),
+    };
+    check_site_limit(&state, &site).await?;
+
+    state
+        .redis
+        .set_site_config(&site)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tracing::info!("Created site {} ({})", site.name, site.id);
+
+    Ok(Json(site))
+}
+
+/// Get a site (platform admin, SaaS mode)
+#[utoipa::path(
+    get,
+    path = "/admin/sites/{id}",
+    tag = "sites",
+    params(
+        ("id" = Uuid, Path, description = "Site ID")
+    ),
+    responses(
+        (status = 200, description = "Site", body = SiteConfig),
+        (status = 401, description = "Invalid admin API key"),
+        (status = 403, description = "Not in SaaS mode, or ADMIN_API_KEY not set"),
+        (status = 404, description = "Site not found")
+    ),
+    security(("admin_api_key" = []))
+)]
+pub async fn get_site(
+    State(state): State<AppState>,
+    _admin: PlatformAdmin,
+    Path(site_id): Path<Uuid>,
+) -> Result<Json<SiteConfig>, (StatusCode, String)> {
+    Ok(Json(load_site(&state, site_id).await?))
+}
+
+/// Update a site's name, domain, plan or settings sections (platform admin, SaaS mode)
+///
+/// Each settings section given replaces the stored one; omitted sections are unchanged.
+#[utoipa::path(
+    patch,
+    path = "/admin/sites/{id}",
+    tag = "sites",
+    params(
+        ("id" = Uuid, Path, description = "Site ID")
+    ),
+    request_body = UpdateSiteRequest,
+    responses(
+        (status = 200, description = "Site updated", body = SiteConfig),
+        (status = 400, description = "Invalid settings"),
+        (status = 401, description = "Invalid admin API key"),
+        (status = 402, description = "The new owner's plan allows no more sites"),
+        (status = 403, description = "Not in SaaS mode, or ADMIN_API_KEY not set"),
+        (status = 404, description = "Site not found")
+    ),
+    security(("admin_api_key" = []))
+)]
+pub async fn update_site(
+    State(state): State<AppState>,
+    _admin: PlatformAdmin,
+    Path(site_id): Path<Uuid>,
+    Json(req): Json<UpdateSiteRequest>,
+) -> Result<Json<SiteConfig>, (StatusCode, String)> {
+    let mut site = load_site(&state, site_id).await?;
+
+    if let Some(name) = &req.name {
+        site.name = validate_name(name)?;
+    }
+    if let Some(domain) = &req.domain {
+        site.domain = validate_domain(domain)?;
+    }
+    req.settings.apply(&mut site.settings, &state.config)?;
+    if let Some(plan) = req.plan {
+        site.plan = validate_plan(plan);
+        check_site_limit(&state, &site).await?;
+    }
+
+    state
+        .redis
+        .set_site_config(&site)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Drop cached API key lookups so the new settings are visible right away
+    let _ = state.redis.invalidate_project_id_cache(&site.project_id_public).await;
+    let _ = state.redis.invalidate_project_id_cache(&site.project_id_secret).await;
+
+    Ok(Json(site))
+}
+
+/// Delete a site (platform admin, SaaS mode)
+///
+/// The site's API keys stop working immediately. Comments and other site data are kept;
+/// export the site first (`GET /admin/sites/{id}/owner/export`) to keep a copy.
+#[utoipa::path(
+    delete,
+    path = "/admin/sites/{id}",
+    tag = "sites",
+    params(
+        ("id" = Uuid, Path, description = "Site ID")
+    ),
+    responses(
+        (status = 204, description = "Site deleted"),
+        (status = 401, description = "Invalid admin API key"),
+        (status = 403, description = "Not in SaaS mode, or ADMIN_API_KEY not set"),
+        (status = 404, description = "Site not found")
+    ),
+    security(("admin_api_key" = []))
+)]
+pub async fn delete_site(
+    State(state): State<AppState>,
+    _admin: PlatformAdmin,
+    Path(site_id): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let site = load_site(&state, site_id).await?;
+
+    state
+        .redis
+        .delete_site_config(&site)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tracing::info!("Deleted site {} ({})", site.name, site.id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Replace a site's API keys (platform admin, SaaS mode)
+///
+/// The replaced keys keep working for `grace_period_seconds` so embeds and servers can be
+/// updated without downtime.
+#[utoipa::path(
+    post,
+    path = "/admin/sites/{id}/rotate-keys",
+    tag = "sites",
+    params(
+        ("id" = Uuid, Path, description = "Site ID")
+    ),
+    request_body = RotateKeysRequest,
+    responses(
+        (status = 200, description = "Keys rotated", body = RotateKeysResponse),
+        (status = 400, description = "Grace period too long"),
+        (status = 401, description = "Invalid admin API key"),
+        (status = 403, description = "Not in SaaS mode, or ADMIN_API_KEY not set"),
+        (status = 404, description = "Site not found")
+    ),
+    security(("admin_api_key" = []))
+)]
+pub async fn rotate_keys(
+    State(state): State<AppState>,
+    _admin: PlatformAdmin,
+    Path(site_id): Path<Uuid>,
+    Json(req): Json<RotateKeysRequest>,
+) -> Result<Json<RotateKeysResponse>, (StatusCode, String)> {
+    let grace_seconds = req.grace_period_seconds.unwrap_or(DEFAULT_KEY_GRACE_SECONDS);
+    if grace_seconds > MAX_KEY_GRACE_SECONDS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("grace_period_seconds must be at most {}", MAX_KEY_GRACE_SECONDS),
+        ));
+    }
+
+    let mut site = load_site(&state, site_id).await?;
+    let mut replaced = Vec::new();
+    if req.keys != RotatedKeys::Secret {
+        let old = std::mem::replace(&mut site.project_id_public, generate_api_key(PUBLIC_KEY_PREFIX));
+        replaced.push(old);
+    }
+    if req.keys != RotatedKeys::Public {
+        let old = std::mem::replace(&mut site.project_id_secret, generate_api_key(SECRET_KEY_PREFIX));
+        replaced.push(old);
+    }
+
+    // Index the new keys before the old ones start expiring
+    state
+        .redis
+        .set_site_config(&site)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    for old in &replaced {
+        state
+            .redis
+            .retire_project_id(old, site_id, grace_seconds)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    tracing::info!("Rotated API keys of site {} ({})", site.name, site.id);
+
+    Ok(Json(RotateKeysResponse {
+        site,
+        previous_keys_expire_at: (grace_seconds > 0).then(|| Utc::now() + Duration::seconds(grace_seconds as i64)),
+    }))
+}
+
+
+/// Report a site's usage against its plan's limits (platform admin, SaaS mode)
+#[utoipa::path(
+    get,
+    path = "/admin/sites/{id}/usage",
+    tag = "sites",
+    params(
+        ("id" = Uuid, Path, description = "Site ID"),
+        SiteUsageQuery
+    ),
+    responses(
+        (status = 200, description = "Usage and limits", body = SiteUsageResponse),
+        (status = 400, description = "Invalid month"),
+        (status = 401, description = "Invalid admin API key"),
+        (status = 403, description = "Not in SaaS mode, or ADMIN_API_KEY not set"),
+        (status = 404, description = "Site not found")
+    ),
+    security(("admin_api_key" = []))
+)]
+pub async fn get_site_usage(
+    State(state): State<AppState>,
+    _admin: PlatformAdmin,
+    Path(site_id): Path<Uuid>,
+    Query(query): Query<SiteUsageQuery>,
+) -> Result<Json<SiteUsageResponse>, (StatusCode, String)> {
+    let month = query.month.unwrap_or_else(RedisClient::usage_month);
+    validate_month(&month)?;
+
+    let site = load_site(&state, site_id).await?;
+    let limits = site.plan.effective_limits();
+
+    let redis = &state.redis;
+    let internal = |e: threadkit_common::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    let comments = redis.get_usage(site_id, &month, COMMENTS_FIELD).await.map_err(internal)?;
+    let moderation_checks = redis
+        .get_usage(site_id, &month, MODERATION_CHECKS_FIELD)
+        .await
+        .map_err(internal)?;
+    let pageviews = redis.get_usage(site_id, &month, "pageviews").await.map_err(internal)?;
+    let media_bytes = redis.get_media_storage(site_id).await.map_err(internal)?;
+    let sites = match site.plan.owner_id.as_deref() {
+        Some(owner_id) => count_owner_sites(&state, owner_id, site_id).await? + 1,
+        None => 1,
+    };
+
+    Ok(Json(SiteUsageResponse {
+        site_id,
+        month,
+        quotas: vec![
+            QuotaUsage::new(QuotaMetric::Comments, comments, &limits),
+            QuotaUsage::new(QuotaMetric::ModerationChecks, moderation_checks, &limits),
+            QuotaUsage::new(QuotaMetric::MediaStorageBytes, media_bytes, &limits),
+            QuotaUsage::new(QuotaMetric::Sites, sites, &limits),
+        ],
+        plan: site.plan,
+        limits,
+        pageviews,
+    }))
+}
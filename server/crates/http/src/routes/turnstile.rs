@@ -8,7 +8,12 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::{extractors::ProjectId, state::AppState};
+use threadkit_common::turnstile;
+
+use crate::{
+    extractors::{ClientIp, ProjectId},
+    state::AppState,
+};
 
 pub fn router() -> Router<AppState> {
     Router::new()
@@ -268,19 +273,12 @@ pub async fn challenge_page(
 pub async fn verify_token(
     State(state): State<AppState>,
     _project_id: ProjectId,
-    headers: axum::http::HeaderMap,
+    ClientIp(client_ip): ClientIp,
     Json(req): Json<VerifyRequest>,
 ) -> Result<Json<VerifyResponse>, (StatusCode, String)> {
     let secret_key = state.config.turnstile.secret_key.as_ref()
         .ok_or((StatusCode::SERVICE_UNAVAILABLE, "Turnstile not configured".into()))?;
 
-    // Get client IP for additional verification
-    let client_ip = headers
-        .get("x-forwarded-for")
-        .and_then(|v| v.to_str().ok())
-        .and_then(|s| s.split(',').next())
-        .map(|s| s.trim().to_string());
-
     // Verify with Cloudflare
     let result = verify_with_cloudflare(secret_key, &req.token, client_ip.as_deref()).await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
@@ -289,40 +287,15 @@ pub async fn verify_token(
 }
 
 /// Verify a token with Cloudflare's siteverify API
-pub async fn verify_with_cloudflare(
+async fn verify_with_cloudflare(
     secret_key: &str,
     token: &str,
     remote_ip: Option<&str>,
 ) -> Result<VerifyResponse, String> {
-    let client = reqwest::Client::new();
-
-    let mut form = vec![
-        ("secret", secret_key),
-        ("response", token),
-    ];
-
-    if let Some(ip) = remote_ip {
-        form.push(("remoteip", ip));
-    }
-
-    let response = client
-        .post("https://challenges.cloudflare.com/turnstile/v0/siteverify")
-        .form(&form)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to contact Cloudflare: {}", e))?;
-
-    let body: serde_json::Value = response.json().await
-        .map_err(|e| format!("Failed to parse Cloudflare response: {}", e))?;
-
-    let success = body["success"].as_bool().unwrap_or(false);
-    let error_codes = body["error-codes"]
-        .as_array()
-        .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect())
-        .unwrap_or_default();
+    let result = turnstile::verify_with_cloudflare(secret_key, token, remote_ip).await?;
 
     Ok(VerifyResponse {
-        success,
-        error_codes,
+        success: result.success,
+        error_codes: result.error_codes,
     })
 }
@@ -1,14 +1,21 @@
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{header, StatusCode},
+    response::{Html, IntoResponse},
     routing::{get, post},
     Json, Router,
 };
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
-use threadkit_common::types::{DeletedAccountStats, Notification, SocialLinks, TreeComment, UserPublic};
+use threadkit_common::store;
+use threadkit_common::notification_emails::verify_unsubscribe_token;
+use threadkit_common::types::{
+    DeletedAccountStats, EmailNotificationFrequency, MediaInfo, Notification, SessionInfo,
+    SocialLinks, TreeComment, User, UserPublic, VoteDirection,
+};
 
 use crate::{
     extractors::{ProjectId, AuthUser, MaybeAuthUser},
@@ -20,12 +27,14 @@ pub fn router() -> Router<AppState> {
         .route("/users/me", get(get_me).put(update_me).delete(delete_account))
         .route("/users/me/blocked", get(get_blocked_users))
         .route("/users/me/comments", get(get_my_comments))
+        .route("/users/me/export", get(export_my_data))
         .route("/users/check-username", post(check_username))
         .route("/users/{id}", get(get_user))
         .route("/users/{id}/block", post(block_user).delete(unblock_user))
         .route("/users/{id}/comments", get(get_user_comments))
         .route("/notifications", get(get_notifications))
         .route("/notifications/read", post(mark_read))
+        .route("/notifications/unsubscribe", get(unsubscribe_page).post(unsubscribe))
 }
 
 // ============================================================================
@@ -52,6 +61,8 @@ pub struct MeResponse {
     pub username_set: bool,
     /// Social media links
     pub social_links: SocialLinks,
+    /// How reply and mention notifications are emailed
+    pub email_notifications: EmailNotificationFrequency,
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
@@ -62,6 +73,17 @@ pub struct UpdateMeRequest {
     pub avatar_url: Option<String>,
     /// Social media links
     pub social_links: Option<SocialLinks>,
+    /// How reply and mention notifications are emailed (`off`, `immediate` or `daily`)
+    pub email_notifications: Option<EmailNotificationFrequency>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UnsubscribeQuery {
+    /// User the link was sent to
+    pub user_id: Uuid,
+    /// Signature from the email link
+    pub token: String,
 }
 
 #[derive(Debug, Deserialize, IntoParams)]
@@ -121,6 +143,56 @@ pub struct UserCommentsResponse {
     pub has_more: bool,
 }
 
+/// A vote cast by the exporting user
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ExportedVote {
+    /// Page ID (absent for votes from the legacy per-comment store)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_id: Option<Uuid>,
+    /// Comment voted on
+    pub comment_id: Uuid,
+    /// Vote direction
+    pub direction: VoteDirection,
+}
+
+/// An emoji reaction by the exporting user
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ExportedReaction {
+    /// Page ID
+    pub page_id: Uuid,
+    /// Comment reacted to
+    pub comment_id: Uuid,
+    /// Emoji
+    pub emoji: String,
+}
+
+/// Everything stored about the current user
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserDataExport {
+    /// When the export was generated
+    pub exported_at: DateTime<Utc>,
+    /// Full user record, including email and login provider
+    #[schema(value_type = Object)]
+    pub user: User,
+    /// Social media links
+    pub social_links: SocialLinks,
+    /// Comments across all sites (without other users' replies)
+    pub comments: Vec<CommentItem>,
+    /// Votes across all sites
+    pub votes: Vec<ExportedVote>,
+    /// Emoji reactions across all sites
+    pub reactions: Vec<ExportedReaction>,
+    /// Notifications, newest first
+    pub notifications: Vec<Notification>,
+    /// User IDs this user has blocked
+    pub blocked_users: Vec<Uuid>,
+    /// Uploaded media metadata
+    #[schema(value_type = Vec<Object>)]
+    pub media: Vec<MediaInfo>,
+    /// Active login sessions
+    pub sessions: Vec<SessionInfo>,
+}
+
 // ============================================================================
 // Handlers
 // ============================================================================
@@ -144,8 +216,8 @@ pub async fn get_me(
 ) -> Result<Json<MeResponse>, (StatusCode, String)> {
     // Parallelize user and unread count fetches
     let (user_result, unread) = tokio::join!(
-        state.redis.get_user(auth.user_id),
-        async { state.redis.get_unread_count(auth.user_id).await.unwrap_or(0) }
+        state.store.get_user(auth.user_id),
+        async { state.store.get_unread_count(auth.user_id).await.unwrap_or(0) }
     );
 
     let user = user_result
@@ -162,6 +234,7 @@ pub async fn get_me(
         unread_notifications: unread,
         username_set: user.username_set,
         social_links: user.social_links,
+        email_notifications: user.email_notifications,
     }))
 }
 
@@ -185,7 +258,7 @@ pub async fn update_me(
     Json(req): Json<UpdateMeRequest>,
 ) -> Result<Json<MeResponse>, (StatusCode, String)> {
     let mut user = state
-        .redis
+        .store
         .get_user(auth.user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
@@ -198,7 +271,7 @@ pub async fn update_me(
 
         // Check if new username is available (excluding current user)
         if !state
-            .redis
+            .store
             .is_username_available(name, Some(auth.user_id))
             .await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
@@ -207,10 +280,10 @@ pub async fn update_me(
         }
 
         // Delete old username index
-        let _ = state.redis.delete_user_username_index(&user.name).await;
+        let _ = state.store.delete_user_username_index(&user.name).await;
         // Set new username index
         state
-            .redis
+            .store
             .set_user_username_index(name, auth.user_id)
             .await
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -227,14 +300,21 @@ pub async fn update_me(
         user.social_links = social_links;
     }
 
+    if let Some(frequency) = req.email_notifications {
+        if frequency != EmailNotificationFrequency::Off && !user.email_verified {
+            return Err((StatusCode::BAD_REQUEST, "A verified email address is required".into()));
+        }
+        user.email_notifications = frequency;
+    }
+
     state
-        .redis
+        .store
         .set_user(&user)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     let unread = state
-        .redis
+        .store
         .get_unread_count(auth.user_id)
         .await
         .unwrap_or(0);
@@ -249,6 +329,7 @@ pub async fn update_me(
         unread_notifications: unread,
         username_set: user.username_set,
         social_links: user.social_links,
+        email_notifications: user.email_notifications,
     }))
 }
 
@@ -272,7 +353,7 @@ pub async fn get_user(
     Path(user_id): Path<Uuid>,
 ) -> Result<Json<UserPublic>, (StatusCode, String)> {
     let user = state
-        .redis
+        .store
         .get_user(user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
@@ -309,7 +390,7 @@ pub async fn check_username(
     let exclude_user_id = auth.map(|a| a.user_id);
 
     let available = state
-        .redis
+        .store
         .is_username_available(&req.username, exclude_user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -340,8 +421,8 @@ pub async fn get_notifications(
 
     // Fetch notifications and unread count in parallel
     let (notifications_result, unread_count) = tokio::join!(
-        state.redis.get_notifications(auth.user_id, offset, limit),
-        async { state.redis.get_unread_count(auth.user_id).await.unwrap_or(0) }
+        state.store.get_notifications(auth.user_id, offset, limit),
+        async { state.store.get_unread_count(auth.user_id).await.unwrap_or(0) }
     );
 
     let notifications = notifications_result
@@ -350,7 +431,7 @@ pub async fn get_notifications(
     // Fetch all from_users in parallel
     let user_futures: Vec<_> = notifications
         .iter()
-        .map(|n| state.redis.get_user(n.from_user_id))
+        .map(|n| state.store.get_user(n.from_user_id))
         .collect();
 
     let user_results = futures::future::join_all(user_futures).await;
@@ -390,7 +471,7 @@ pub async fn mark_read(
     auth: AuthUser,
 ) -> Result<StatusCode, (StatusCode, String)> {
     state
-        .redis
+        .store
         .mark_notifications_read(auth.user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -398,6 +479,85 @@ pub async fn mark_read(
     Ok(StatusCode::OK)
 }
 
+/// Confirm turning off notification emails from an email's unsubscribe link
+///
+/// Only shows a button that submits the `POST`: link scanners and prefetchers
+/// follow `GET` links, so opening the link must not unsubscribe anyone.
+#[utoipa::path(
+    get,
+    path = "/notifications/unsubscribe",
+    tag = "notifications",
+    params(UnsubscribeQuery),
+    responses(
+        (status = 200, description = "Confirmation page", content_type = "text/html", body = String),
+        (status = 403, description = "Invalid unsubscribe link")
+    )
+)]
+pub async fn unsubscribe_page(
+    State(state): State<AppState>,
+    Query(query): Query<UnsubscribeQuery>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    if !verify_unsubscribe_token(&state.config.jwt_secret, query.user_id, &query.token) {
+        return Err((StatusCode::FORBIDDEN, "Invalid unsubscribe link".into()));
+    }
+
+    // The token was checked above, so it's hex and safe to put in the form action
+    Ok(Html(format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Unsubscribe</title></head>\
+         <body style=\"font-family: sans-serif; text-align: center; margin: 80px auto;\">\
+         <p>Stop getting notification emails?</p>\
+         <form method=\"post\" action=\"?user_id={}&amp;token={}\">\
+         <button type=\"submit\">Unsubscribe</button></form></body></html>",
+        query.user_id, query.token
+    )))
+}
+
+/// Turn off notification emails from an email's unsubscribe link
+///
+/// Works without logging in: the link is signed for the user. This is also the
+/// one-click `POST` mail clients send for the `List-Unsubscribe` header (RFC 8058).
+#[utoipa::path(
+    post,
+    path = "/notifications/unsubscribe",
+    tag = "notifications",
+    params(UnsubscribeQuery),
+    responses(
+        (status = 200, description = "Unsubscribed", content_type = "text/html", body = String),
+        (status = 403, description = "Invalid unsubscribe link")
+    )
+)]
+pub async fn unsubscribe(
+    State(state): State<AppState>,
+    Query(query): Query<UnsubscribeQuery>,
+) -> Result<Html<&'static str>, (StatusCode, String)> {
+    if !verify_unsubscribe_token(&state.config.jwt_secret, query.user_id, &query.token) {
+        return Err((StatusCode::FORBIDDEN, "Invalid unsubscribe link".into()));
+    }
+
+    // Deleted accounts have nothing left to unsubscribe
+    if let Some(mut user) = state
+        .store
+        .get_user(query.user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        && user.email_notifications != EmailNotificationFrequency::Off
+    {
+        user.email_notifications = EmailNotificationFrequency::Off;
+        state
+            .store
+            .set_user(&user)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let _ = state.redis.remove_notification_email(query.user_id).await;
+    }
+
+    Ok(Html(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Unsubscribed</title></head>\
+         <body style=\"font-family: sans-serif; text-align: center; margin: 80px auto;\">\
+         <p>You won't get any more notification emails.</p></body></html>",
+    ))
+}
+
 // ============================================================================
 // User Blocking
 // ============================================================================
@@ -425,7 +585,7 @@ pub async fn get_blocked_users(
     auth: AuthUser,
 ) -> Result<Json<BlockedUsersResponse>, (StatusCode, String)> {
     let blocked_ids = state
-        .redis
+        .store
         .get_blocked_users(auth.user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -433,7 +593,7 @@ pub async fn get_blocked_users(
     // Fetch all blocked users in parallel
     let user_futures: Vec<_> = blocked_ids
         .iter()
-        .map(|&user_id| state.redis.get_user(user_id))
+        .map(|&user_id| state.store.get_user(user_id))
         .collect();
 
     let user_results = futures::future::join_all(user_futures).await;
@@ -474,14 +634,14 @@ pub async fn block_user(
 
     // Verify target user exists
     let _ = state
-        .redis
+        .store
         .get_user(user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or((StatusCode::NOT_FOUND, "User not found".into()))?;
 
     state
-        .redis
+        .store
         .block_user_by_user(auth.user_id, user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -509,7 +669,7 @@ pub async fn unblock_user(
     Path(user_id): Path<Uuid>,
 ) -> Result<StatusCode, (StatusCode, String)> {
     state
-        .redis
+        .store
         .unblock_user_by_user(auth.user_id, user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -537,15 +697,131 @@ pub async fn delete_account(
     _project_id: ProjectId,
     auth: AuthUser,
 ) -> Result<Json<DeletedAccountStats>, (StatusCode, String)> {
-    let stats = state
-        .redis
-        .delete_user_account(auth.user_id)
+    let stats = store::delete_user_account(state.store.as_ref(), &state.redis, auth.user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     Ok(Json(stats))
 }
 
+/// Download all data stored about the current user (GDPR)
+#[utoipa::path(
+    get,
+    path = "/users/me/export",
+    tag = "users",
+    responses(
+        (status = 200, description = "User data archive", body = UserDataExport),
+        (status = 401, description = "Not authenticated"),
+        (status = 404, description = "User not found")
+    ),
+    security(("project_id" = []), ("bearer" = []))
+)]
+pub async fn export_my_data(
+    State(state): State<AppState>,
+    _project_id: ProjectId,
+    auth: AuthUser,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let export = build_user_export(&state, auth.user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "User not found".into()))?;
+
+    Ok((
+        [(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"threadkit-user-{}.json\"", auth.user_id),
+        )],
+        Json(export),
+    ))
+}
+
+async fn build_user_export(
+    state: &AppState,
+    user_id: Uuid,
+) -> threadkit_common::Result<Option<UserDataExport>> {
+    let (redis, store) = (&state.redis, &state.store);
+    let Some(user) = store.get_user(user_id).await? else {
+        return Ok(None);
+    };
+
+    // Comments: load each page tree once
+    let comment_refs = redis.get_user_comment_index(user_id, 0, usize::MAX).await?;
+    let mut comments_by_page: std::collections::HashMap<Uuid, Vec<Uuid>> = std::collections::HashMap::new();
+    for (page_id, comment_id) in &comment_refs {
+        comments_by_page.entry(*page_id).or_default().push(*comment_id);
+    }
+    let mut comments = Vec::with_capacity(comment_refs.len());
+    for (page_id, comment_ids) in comments_by_page {
+        let Some(tree) = store.get_page_tree(page_id).await? else {
+            continue;
+        };
+        for comment_id in comment_ids {
+            if let Some(comment) = find_comment_in_tree(&tree.comments, comment_id) {
+                let mut comment = comment.clone();
+                comment.replies.clear();
+                comments.push(CommentItem { page_id, comment });
+            }
+        }
+    }
+    comments.sort_by_key(|c| std::cmp::Reverse(c.comment.created_at));
+
+    // Votes: per-page hashes, plus any left in the legacy per-comment store
+    let mut votes: Vec<ExportedVote> = store
+        .get_all_page_votes(user_id)
+        .await?
+        .into_iter()
+        .map(|(page_id, comment_id, direction)| ExportedVote {
+            page_id: Some(page_id),
+            comment_id,
+            direction,
+        })
+        .collect();
+    for comment_id in redis.get_user_votes(user_id).await? {
+        if votes.iter().any(|v| v.comment_id == comment_id) {
+            continue;
+        }
+        if let Some(direction) = redis.get_vote(user_id, comment_id).await? {
+            votes.push(ExportedVote {
+                page_id: None,
+                comment_id,
+                direction,
+            });
+        }
+    }
+
+    let reactions = store
+        .get_all_page_reactions(user_id)
+        .await?
+        .into_iter()
+        .map(|(page_id, comment_id, emoji)| ExportedReaction {
+            page_id,
+            comment_id,
+            emoji,
+        })
+        .collect();
+
+    let mut media = Vec::new();
+    for media_id in redis.get_user_media(user_id).await? {
+        if let Some(info) = redis.get_media_info(media_id).await? {
+            media.push(info);
+        }
+    }
+    media.sort_by_key(|m| m.upload_date);
+
+    Ok(Some(UserDataExport {
+        exported_at: Utc::now(),
+        social_links: user.social_links.clone(),
+        user,
+        comments,
+        votes,
+        reactions,
+        notifications: store.get_notifications(user_id, 0, usize::MAX).await?,
+        blocked_users: store.get_blocked_users(user_id).await?,
+        media,
+        sessions: store.get_user_sessions(user_id).await?,
+    }))
+}
+
 // ============================================================================
 // User Comments
 // ============================================================================
@@ -584,7 +860,7 @@ pub async fn get_my_comments(
     // Fetch all page trees in parallel
     let tree_futures: Vec<_> = comment_refs
         .iter()
-        .map(|(page_id, _)| state.redis.get_page_tree(*page_id))
+        .map(|(page_id, _)| state.store.get_page_tree(*page_id))
         .collect();
 
     let tree_results = futures::future::join_all(tree_futures).await;
@@ -632,7 +908,7 @@ pub async fn get_user_comments(
 ) -> Result<Json<UserCommentsResponse>, (StatusCode, String)> {
     // Verify user exists
     let _ = state
-        .redis
+        .store
         .get_user(user_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
@@ -654,7 +930,7 @@ pub async fn get_user_comments(
     // Fetch all page trees in parallel
     let tree_futures: Vec<_> = comment_refs
         .iter()
-        .map(|(page_id, _)| state.redis.get_page_tree(*page_id))
+        .map(|(page_id, _)| state.store.get_page_tree(*page_id))
         .collect();
 
     let tree_results = futures::future::join_all(tree_futures).await;
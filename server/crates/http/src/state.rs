@@ -1,8 +1,16 @@
 use anyhow::Result;
+use dashmap::DashMap;
 use moka::future::Cache;
 use std::sync::Arc;
 use std::time::Duration;
-use threadkit_common::{redis::RedisClient, Config, ModerationClient, StorageClient, ActionLogger};
+use threadkit_common::{
+    config::EmailProvider,
+    messages::ServerMessage,
+    pubsub::PubSubSubscriber,
+    redis::RedisClient, store, types::AuthEvent, ActionLog, ActionLogger, CommentService, Config,
+    Mailer, ModerationClient, NotificationEmailer, Quotas, Storage, StorageClient, WebhookDispatcher,
+};
+use tokio::sync::broadcast;
 use uuid::Uuid;
 
 /// In-memory cache for page ETags (updated_at timestamps)
@@ -14,12 +22,29 @@ pub type ETagCache = Cache<Uuid, i64>;
 pub struct AppState {
     pub config: Arc<Config>,
     pub redis: Arc<RedisClient>,
+    /// Users, page trees, votes, roles, notifications, sessions and rate limits
+    /// (Redis or SQLite, see `STORAGE_BACKEND`)
+    pub store: Arc<dyn Storage>,
     pub moderation: Arc<ModerationClient>,
+    /// Outgoing email (SMTP, Resend, or the development outbox)
+    pub mailer: Arc<Mailer>,
+    /// Reply and mention emails (worker is started from main)
+    pub notification_emails: Arc<NotificationEmailer>,
     pub storage: Option<Arc<StorageClient>>,
     /// In-memory cache for page ETags - avoids Redis reads for unchanged pages
     pub etag_cache: ETagCache,
     /// Action logger for monitoring write operations
     pub action_logger: Arc<ActionLogger>,
+    /// Outbound webhook queue (worker is started from main)
+    pub webhooks: Arc<WebhookDispatcher>,
+    /// Plan limits, checked by comment writes and uploads
+    pub quotas: Arc<Quotas>,
+    /// Comment writes, shared with the WebSocket server
+    pub comments: Arc<CommentService>,
+    /// Page events relayed from Redis pub/sub for SSE streams (subscriber is started from main)
+    pub page_channels: Arc<DashMap<Uuid, broadcast::Sender<ServerMessage>>>,
+    /// Logouts and bans, for SSE streams to end revoked sessions
+    pub auth_events: broadcast::Sender<AuthEvent>,
 }
 
 impl AppState {
@@ -27,28 +52,102 @@ impl AppState {
     ///
     /// Events are published to `threadkit:page:{page_id}:events`
     pub async fn publish_event(&self, page_id: uuid::Uuid, event_type: &str, data: serde_json::Value) {
-        let channel = format!("threadkit:page:{}:events", page_id);
-        let message = serde_json::json!({
-            "type": event_type,
-            "page_id": page_id,
-            "data": data
-        });
-
-        if let Err(e) = self.redis.publish(&channel, &message.to_string()).await {
+        if let Err(e) = self.redis.publish_page_event(page_id, event_type, data).await {
             tracing::warn!("Failed to publish event to Redis: {}", e);
         }
     }
 
+    /// Publish a moderation event for moderators watching the site live
+    ///
+    /// Events are published to `threadkit:site:{site_id}:moderation`
+    pub async fn publish_moderation_event(&self, site_id: uuid::Uuid, event_type: &str, data: serde_json::Value) {
+        if let Err(e) = self.redis.publish_moderation_event(site_id, event_type, data).await {
+            tracing::warn!("Failed to publish moderation event to Redis: {}", e);
+        }
+    }
+
+    /// Tell WebSocket servers about a logout or ban so open connections drop the identity
+    pub async fn publish_auth_event(&self, event: threadkit_common::types::AuthEvent) {
+        if let Err(e) = self.redis.publish_auth_event(&event).await {
+            tracing::warn!("Failed to publish auth event to Redis: {}", e);
+        }
+    }
+
+    /// Subscribe to a page's live events for an SSE stream
+    pub fn subscribe_page_events(&self, page_id: Uuid) -> broadcast::Receiver<ServerMessage> {
+        self.page_channels
+            .entry(page_id)
+            .or_insert_with(|| {
+                let (tx, _) = broadcast::channel(1000);
+                tx
+            })
+            .subscribe()
+    }
+
+    /// Relays Redis pub/sub events to `page_channels` and `auth_events`
+    ///
+    /// The same subscriber the WebSocket server runs; site moderation events and user
+    /// notifications are dropped. Page events also drop the page's cached ETag, since
+    /// WebSocket servers and other HTTP nodes write pages without touching this one's cache.
+    pub fn pubsub_subscriber(&self) -> PubSubSubscriber {
+        let etag_cache = self.etag_cache.clone();
+        PubSubSubscriber::new(
+            self.config.redis_url.clone(),
+            self.page_channels.clone(),
+            Arc::new(DashMap::new()),
+            Arc::new(DashMap::new()),
+            self.auth_events.clone(),
+        )
+        .on_page_event(move |page_id| {
+            let etag_cache = etag_cache.clone();
+            tokio::spawn(async move {
+                etag_cache.invalidate(&page_id).await;
+            });
+        })
+    }
+
+    /// Log an action and queue webhook deliveries for it
+    ///
+    /// Webhooks are queued in the background so the request isn't slowed down
+    pub fn log_action(&self, entry: ActionLog) {
+        self.action_logger.log(entry.clone());
+        self.webhooks.enqueue_in_background(entry);
+    }
+
     pub async fn new(config: Config, action_logger: Arc<ActionLogger>) -> Result<Self> {
-        let redis = RedisClient::new(&config.redis_url).await?;
+        let redis = Arc::new(RedisClient::new(&config.redis_url).await?);
         tracing::info!("Connected to Redis");
 
+        let store = store::connect(&config.storage, redis.clone()).await?;
+        tracing::info!("Storage backend: {:?}", config.storage);
+
+        {
+            let redis = redis.clone();
+            tokio::spawn(async move {
+                match redis.delete_legacy_visitor_sets().await {
+                    Ok(0) => {}
+                    Ok(n) => tracing::info!("Deleted {} legacy monthly visitor sets", n),
+                    Err(e) => tracing::warn!("Failed to delete legacy visitor sets: {}", e),
+                }
+            });
+        }
+
+        let webhooks = Arc::new(WebhookDispatcher::new(redis.clone(), config.webhooks.clone())?);
+
         // Initialize moderation client
         let moderation = ModerationClient::new(config.content_moderation.clone())?;
         if moderation.is_enabled() {
             tracing::info!("Content moderation enabled");
         }
 
+        let mailer = Arc::new(Mailer::new(&config.email)?);
+        match &config.email.provider {
+            Some(EmailProvider::Smtp(smtp)) => tracing::info!("Email provider: SMTP ({}:{})", smtp.host, smtp.port),
+            Some(EmailProvider::Resend(_)) => tracing::info!("Email provider: Resend"),
+            Some(EmailProvider::Outbox(_)) => tracing::info!("Email provider: outbox"),
+            None => tracing::info!("Email provider not configured, emails will be logged"),
+        }
+
         // Initialize S3 storage client if enabled
         let storage = if let Some(s3_config) = &config.s3 {
             match StorageClient::new(s3_config).await {
@@ -127,13 +226,40 @@ impl AppState {
             .time_to_idle(Duration::from_secs(300))
             .build();
 
+        let config = Arc::new(config);
+        let moderation = Arc::new(moderation);
+        let notification_emails = Arc::new(NotificationEmailer::new(
+            redis.clone(),
+            store.clone(),
+            mailer.clone(),
+            config.clone(),
+        ));
+        let quotas = Arc::new(Quotas::new(redis.clone(), action_logger.clone(), webhooks.clone()));
+        let comments = Arc::new(CommentService::new(
+            config.clone(),
+            redis.clone(),
+            store.clone(),
+            moderation.clone(),
+            action_logger.clone(),
+            webhooks.clone(),
+            quotas.clone(),
+        ));
+
         Ok(AppState {
-            config: Arc::new(config),
-            redis: Arc::new(redis),
-            moderation: Arc::new(moderation),
+            config,
+            redis,
+            store,
+            moderation,
+            mailer,
+            notification_emails,
             storage,
             etag_cache,
             action_logger,
+            webhooks,
+            quotas,
+            comments,
+            page_channels: Arc::new(DashMap::new()),
+            auth_events: broadcast::channel(1000).0,
         })
     }
 }
@@ -0,0 +1,212 @@
+mod common;
+
+use axum::http::{HeaderName, HeaderValue, StatusCode};
+use common::TestContext;
+use std::collections::HashMap;
+use threadkit_common::types::AnalyticsGranularity;
+use uuid::Uuid;
+
+/// Register an admin and return their token
+async fn admin_token(ctx: &TestContext) -> String {
+    let auth = ctx.register_user("admin", "admin@example.com", "password123").await;
+    let user_id = auth["user"]["id"].as_str().unwrap();
+    ctx.set_user_role(user_id, "admin").await;
+    auth["token"].as_str().unwrap().to_string()
+}
+
+/// Load a page's comments as an anonymous visitor
+async fn view_page(ctx: &TestContext, page_url: &str, ip: &str, user_agent: &str) {
+    let (key_name, key_value) = ctx.project_id_header();
+    ctx.server
+        .get("/v1/comments")
+        .add_query_param("page_url", page_url)
+        .add_header(key_name, key_value)
+        .add_header(HeaderName::from_static("x-forwarded-for"), HeaderValue::from_str(ip).unwrap())
+        .add_header(HeaderName::from_static("user-agent"), HeaderValue::from_str(user_agent).unwrap())
+        .await
+        .assert_status_ok();
+}
+
+async fn get_analytics(ctx: &TestContext, token: &str, query: &[(&str, &str)]) -> axum_test::TestResponse {
+    let (key_name, key_value) = ctx.project_id_header();
+    let (auth_name, auth_value) = TestContext::auth_header(token);
+
+    let mut request = ctx
+        .server
+        .get(&format!("/v1/admin/sites/{}/analytics", ctx.site_id))
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value);
+    for (name, value) in query {
+        request = request.add_query_param(name, value);
+    }
+    request.await
+}
+
+/// Pageviews and comments are counted in the background - wait until `pageviews` show up
+async fn wait_for_analytics(ctx: &TestContext, token: &str, pageviews: u64) -> serde_json::Value {
+    for _ in 0..50 {
+        let response = get_analytics(ctx, token, &[("granularity", "hour")]).await;
+        response.assert_status_ok();
+        let body: serde_json::Value = response.json();
+        if body["totals"]["pageviews"].as_u64().unwrap() >= pageviews {
+            return body;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("Expected {} pageviews in analytics", pageviews);
+}
+
+/// Test that pageviews, visitors and comments show up in the hourly series
+#[tokio::test]
+async fn test_analytics_counts_activity() {
+    let ctx = TestContext::new().await;
+    let token = admin_token(&ctx).await;
+
+    // Two visitors, one of them twice
+    view_page(&ctx, "https://example.com/page1", "203.0.113.1", "Firefox").await;
+    view_page(&ctx, "https://example.com/page1", "203.0.113.1", "Firefox").await;
+    view_page(&ctx, "https://example.com/page2", "203.0.113.2", "Safari").await;
+
+    ctx.create_comment(&token, "https://example.com/page2", "First", None)
+        .await
+        .assert_status_ok();
+    ctx.create_comment(&token, "https://example.com/page2", "Second", None)
+        .await
+        .assert_status_ok();
+
+    let body = wait_for_analytics(&ctx, &token, 3).await;
+    assert_eq!(body["granularity"], "hour");
+
+    // Default range is the last 24 hours, one bucket per hour
+    let series = body["series"].as_array().unwrap();
+    assert!(series.len() >= 24 && series.len() <= 26);
+    let current = series.last().unwrap();
+    assert_eq!(current["pageviews"], 3);
+    assert_eq!(current["unique_visitors"], 2);
+
+    // Comments are counted in the background too
+    for _ in 0..50 {
+        let body = get_analytics(&ctx, &token, &[("granularity", "hour")]).await.json::<serde_json::Value>();
+        if body["totals"]["comments"] == 2 {
+            assert_eq!(body["totals"]["active_commenters"], 1);
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("Expected 2 comments in analytics");
+}
+
+/// Test that top pages are ranked by views plus weighted comments
+#[tokio::test]
+async fn test_analytics_top_pages() {
+    let ctx = TestContext::new().await;
+    let token = admin_token(&ctx).await;
+
+    for i in 0..5 {
+        view_page(&ctx, "https://example.com/popular", &format!("203.0.113.{}", i), "Firefox").await;
+    }
+    view_page(&ctx, "https://example.com/discussed", "203.0.113.9", "Firefox").await;
+    ctx.create_comment(&token, "https://example.com/discussed", "Worth talking about", None)
+        .await
+        .assert_status_ok();
+
+    wait_for_analytics(&ctx, &token, 6).await;
+
+    let mut top_pages = Vec::new();
+    for _ in 0..50 {
+        let body: serde_json::Value = get_analytics(&ctx, &token, &[]).await.json();
+        top_pages = body["top_pages"].as_array().unwrap().clone();
+        if top_pages.first().is_some_and(|p| p["comments"] == 1) {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+
+    // One comment outweighs four extra views
+    assert_eq!(top_pages.len(), 2);
+    assert_eq!(top_pages[0]["page_url"], "https://example.com/discussed");
+    assert_eq!(top_pages[0]["pageviews"], 1);
+    assert_eq!(top_pages[1]["page_url"], "https://example.com/popular");
+    assert_eq!(top_pages[1]["pageviews"], 5);
+
+    let limited: serde_json::Value = get_analytics(&ctx, &token, &[("top_pages", "1")]).await.json();
+    assert_eq!(limited["top_pages"].as_array().unwrap().len(), 1);
+}
+
+/// Test that peak connections keep the highest sample
+#[tokio::test]
+async fn test_analytics_peak_connections() {
+    let ctx = TestContext::new().await;
+    let token = admin_token(&ctx).await;
+    let redis = ctx.get_redis_client().await;
+    let site_id: Uuid = ctx.site_id;
+
+    for count in [3, 7, 2] {
+        redis
+            .record_peak_connections(&HashMap::from([(site_id, count)]))
+            .await
+            .unwrap();
+    }
+
+    let response = get_analytics(&ctx, &token, &[]).await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["series"].as_array().unwrap().last().unwrap()["peak_connections"], 7);
+    assert_eq!(body["totals"]["peak_connections"], 7);
+}
+
+/// Test that the monthly visitor sets from before HyperLogLog counting are deleted
+#[tokio::test]
+async fn test_legacy_visitor_sets_deleted() {
+    let ctx = TestContext::new().await;
+    let redis = ctx.get_redis_client().await;
+    let site_id: Uuid = ctx.site_id;
+
+    redis.add_unique_visitor(site_id, "visitor").await.unwrap();
+    let legacy_key = format!("site:{}:visitors:2025-01", site_id);
+    let client = redis::Client::open(ctx.get_redis_url().await).unwrap();
+    let mut conn = client.get_multiplexed_async_connection().await.unwrap();
+    redis::cmd("SADD").arg(&legacy_key).arg("visitor").query_async::<()>(&mut conn).await.unwrap();
+
+    redis.delete_legacy_visitor_sets().await.unwrap();
+    let exists: bool = redis::cmd("EXISTS").arg(&legacy_key).query_async(&mut conn).await.unwrap();
+    assert!(!exists);
+
+    // Current HyperLogLogs are left alone
+    let today = AnalyticsGranularity::Day.truncate(chrono::Utc::now());
+    let (visitors, _) = redis
+        .count_analytics_uniques(site_id, AnalyticsGranularity::Day, &[today])
+        .await
+        .unwrap();
+    assert_eq!(visitors, 1);
+}
+
+/// Test that analytics need an admin and a valid range
+#[tokio::test]
+async fn test_analytics_access_and_validation() {
+    let ctx = TestContext::new().await;
+    let admin = admin_token(&ctx).await;
+
+    let auth = ctx.register_user("reader", "reader@example.com", "password123").await;
+    let token = auth["token"].as_str().unwrap();
+    get_analytics(&ctx, token, &[]).await.assert_status(StatusCode::FORBIDDEN);
+
+    get_analytics(
+        &ctx,
+        &admin,
+        &[("from", "2025-02-01T00:00:00Z"), ("to", "2025-01-01T00:00:00Z")],
+    )
+    .await
+    .assert_status(StatusCode::BAD_REQUEST);
+
+    // Ranges are clamped to the retention: at most 7 days of hourly buckets
+    let response = get_analytics(
+        &ctx,
+        &admin,
+        &[("granularity", "hour"), ("from", "2020-01-01T00:00:00Z")],
+    )
+    .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert!(body["series"].as_array().unwrap().len() <= 7 * 24 + 1);
+}
@@ -0,0 +1,228 @@
+mod common;
+
+use axum::http::{HeaderName, HeaderValue, StatusCode};
+use common::TestContext;
+use serde_json::json;
+
+fn projectid(key: &str) -> (HeaderName, HeaderValue) {
+    (HeaderName::from_static("projectid"), HeaderValue::from_str(key).unwrap())
+}
+
+/// Mint a token with the secret key and return the create response
+async fn create_token(ctx: &TestContext, body: serde_json::Value) -> serde_json::Value {
+    let (key_name, key_value) = projectid(&ctx.secret_key);
+    let response = ctx
+        .server
+        .post(&format!("/v1/admin/sites/{}/tokens", ctx.site_id))
+        .add_header(key_name, key_value)
+        .json(&body)
+        .await;
+    response.assert_status_ok();
+    response.json()
+}
+
+async fn list_tokens(ctx: &TestContext) -> Vec<serde_json::Value> {
+    let (key_name, key_value) = projectid(&ctx.secret_key);
+    let response = ctx
+        .server
+        .get(&format!("/v1/admin/sites/{}/tokens", ctx.site_id))
+        .add_header(key_name, key_value)
+        .await;
+    response.assert_status_ok();
+    response.json::<serde_json::Value>()["tokens"].as_array().unwrap().clone()
+}
+
+async fn get_site_comments(ctx: &TestContext, token: &str) -> axum_test::TestResponse {
+    let (key_name, key_value) = projectid(token);
+    ctx.server
+        .get(&format!("/v1/admin/sites/{}/comments", ctx.site_id))
+        .add_header(key_name, key_value)
+        .await
+}
+
+/// Test that a token can be created, used, listed and revoked
+#[tokio::test]
+async fn test_api_token_lifecycle() {
+    let ctx = TestContext::new().await;
+
+    let created = create_token(&ctx, json!({ "name": "CMS backend", "scopes": ["read_comments"] })).await;
+    let token = created["token"].as_str().unwrap().to_string();
+    assert!(token.starts_with("tk_tok_"));
+    assert_eq!(created["name"], "CMS backend");
+    assert_eq!(created["hint"], token[token.len() - 4..]);
+
+    get_site_comments(&ctx, &token).await.assert_status_ok();
+
+    // Listed without the token itself, with its last use
+    let mut tokens = Vec::new();
+    for _ in 0..50 {
+        tokens = list_tokens(&ctx).await;
+        if tokens[0]["last_used_at"].is_string() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(tokens[0]["id"], created["id"]);
+    assert!(tokens[0].get("token").is_none());
+    assert!(tokens[0]["last_used_at"].is_string());
+
+    let (key_name, key_value) = projectid(&ctx.secret_key);
+    ctx.server
+        .delete(&format!("/v1/admin/sites/{}/tokens/{}", ctx.site_id, created["id"].as_str().unwrap()))
+        .add_header(key_name.clone(), key_value.clone())
+        .await
+        .assert_status(StatusCode::NO_CONTENT);
+
+    get_site_comments(&ctx, &token).await.assert_status(StatusCode::UNAUTHORIZED);
+    assert!(list_tokens(&ctx).await.is_empty());
+
+    ctx.server
+        .delete(&format!("/v1/admin/sites/{}/tokens/{}", ctx.site_id, created["id"].as_str().unwrap()))
+        .add_header(key_name, key_value)
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+/// Test that a token can only reach the endpoints of its scopes
+#[tokio::test]
+async fn test_api_token_scopes() {
+    let ctx = TestContext::new().await;
+
+    let created = create_token(&ctx, json!({ "name": "Analytics", "scopes": ["read_analytics"] })).await;
+    let token = created["token"].as_str().unwrap();
+    let (key_name, key_value) = projectid(token);
+
+    ctx.server
+        .get(&format!("/v1/admin/sites/{}/analytics", ctx.site_id))
+        .add_header(key_name.clone(), key_value.clone())
+        .await
+        .assert_status_ok();
+
+    get_site_comments(&ctx, token).await.assert_status(StatusCode::FORBIDDEN);
+    ctx.server
+        .get("/v1/moderation/queue")
+        .add_header(key_name.clone(), key_value.clone())
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+
+    // Tokens can't mint tokens, export the site or manage admins, whatever their scopes
+    let all = create_token(
+        &ctx,
+        json!({
+            "name": "Everything",
+            "scopes": ["read_comments", "moderate", "manage_users", "read_analytics", "manage_site"]
+        }),
+    )
+    .await;
+    let (key_name, key_value) = projectid(all["token"].as_str().unwrap());
+    ctx.server
+        .get("/v1/moderation/queue")
+        .add_header(key_name.clone(), key_value.clone())
+        .await
+        .assert_status_ok();
+    ctx.server
+        .post(&format!("/v1/admin/sites/{}/tokens", ctx.site_id))
+        .add_header(key_name.clone(), key_value.clone())
+        .json(&json!({ "name": "Sneaky", "scopes": ["manage_site"] }))
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+    ctx.server
+        .get(&format!("/v1/admin/sites/{}/owner/export", ctx.site_id))
+        .add_header(key_name.clone(), key_value.clone())
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+    ctx.server
+        .post(&format!("/v1/admin/sites/{}/admins", ctx.site_id))
+        .add_header(key_name, key_value)
+        .json(&json!({ "user_id": uuid::Uuid::now_v7() }))
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+}
+
+/// Test that tokens only work on the management API, not the public endpoints
+#[tokio::test]
+async fn test_api_token_rejected_on_public_endpoints() {
+    let ctx = TestContext::new().await;
+
+    let auth = ctx.register_user("tokenuser", "tokenuser@example.com", "password123").await;
+    let created = create_token(&ctx, json!({ "name": "Analytics", "scopes": ["read_analytics"] })).await;
+    let (key_name, key_value) = projectid(created["token"].as_str().unwrap());
+    let (auth_name, auth_value) = TestContext::auth_header(auth["token"].as_str().unwrap());
+
+    ctx.server
+        .post("/v1/comments")
+        .add_header(key_name.clone(), key_value.clone())
+        .add_header(auth_name, auth_value)
+        .json(&json!({ "page_url": "https://example.com/token", "content": "Posted with a token" }))
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+    ctx.server
+        .get("/v1/comments")
+        .add_query_param("page_url", "https://example.com/token")
+        .add_header(key_name, key_value)
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+}
+
+/// Test that a token is limited to its allowed IPs
+#[tokio::test]
+async fn test_api_token_ip_allowlist() {
+    let ctx = TestContext::new().await;
+
+    let created = create_token(
+        &ctx,
+        json!({ "name": "Office", "scopes": ["read_comments"], "allowed_ips": ["203.0.113.0/24"] }),
+    )
+    .await;
+    let (key_name, key_value) = projectid(created["token"].as_str().unwrap());
+
+    for (ip, status) in [("203.0.113.9", StatusCode::OK), ("198.51.100.1", StatusCode::FORBIDDEN)] {
+        ctx.server
+            .get(&format!("/v1/admin/sites/{}/comments", ctx.site_id))
+            .add_header(key_name.clone(), key_value.clone())
+            .add_header(HeaderName::from_static("x-forwarded-for"), HeaderValue::from_static(ip))
+            .await
+            .assert_status(status);
+    }
+}
+
+/// Test that expired tokens stop working and invalid requests are rejected
+#[tokio::test]
+async fn test_api_token_expiry_and_validation() {
+    let ctx = TestContext::new().await;
+
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(2);
+    let created = create_token(
+        &ctx,
+        json!({ "name": "Short-lived", "scopes": ["read_comments"], "expires_at": expires_at }),
+    )
+    .await;
+    let token = created["token"].as_str().unwrap();
+    get_site_comments(&ctx, token).await.assert_status_ok();
+    tokio::time::sleep(std::time::Duration::from_millis(2500)).await;
+    get_site_comments(&ctx, token).await.assert_status(StatusCode::UNAUTHORIZED);
+
+    let (key_name, key_value) = projectid(&ctx.secret_key);
+    for body in [
+        json!({ "name": " ", "scopes": ["moderate"] }),
+        json!({ "name": "No scopes", "scopes": [] }),
+        json!({ "name": "Past", "scopes": ["moderate"], "expires_at": "2020-01-01T00:00:00Z" }),
+        json!({ "name": "Bad IP", "scopes": ["moderate"], "allowed_ips": ["example.com"] }),
+    ] {
+        ctx.server
+            .post(&format!("/v1/admin/sites/{}/tokens", ctx.site_id))
+            .add_header(key_name.clone(), key_value.clone())
+            .json(&body)
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    // The public key can't manage tokens
+    let (key_name, key_value) = ctx.project_id_header();
+    ctx.server
+        .get(&format!("/v1/admin/sites/{}/tokens", ctx.site_id))
+        .add_header(key_name, key_value)
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,255 @@
+mod common;
+
+use axum::http::{HeaderName, HeaderValue, StatusCode};
+use common::TestContext;
+use serde_json::json;
+use uuid::Uuid;
+
+use threadkit_common::redis::RedisClient;
+use threadkit_common::types::{AuthProvider, SocialLinks, User};
+use threadkit_http::archive::{restore_site, Archive, RestoreOptions};
+
+/// Create a root comment with one reply on `page_url`, return the root ID
+async fn create_thread(ctx: &TestContext, token: &str, page_url: &str) -> Uuid {
+    let response = ctx.create_comment(token, page_url, "Root comment", None).await;
+    response.assert_status_ok();
+    let root_id: Uuid = response.json::<serde_json::Value>()["comment"]["i"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    let (key_name, key_value) = ctx.project_id_header();
+    let (auth_name, auth_value) = TestContext::auth_header(token);
+    ctx.server
+        .post("/v1/comments")
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value)
+        .json(&json!({
+            "page_url": page_url,
+            "content": "A reply",
+            "parent_path": [root_id]
+        }))
+        .await
+        .assert_status_ok();
+
+    root_id
+}
+
+/// Page URLs are recorded in the background - wait until `page_url` shows up
+async fn wait_for_page_url(redis: &RedisClient, site_id: Uuid, page_url: &str) {
+    for _ in 0..50 {
+        let pages = redis.get_site_pages(site_id).await.unwrap();
+        if pages.iter().any(|(_, url)| url == page_url) {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("Page URL {} was not recorded", page_url);
+}
+
+async fn export(ctx: &TestContext) -> Archive {
+    let response = ctx
+        .server
+        .get(&format!("/v1/admin/sites/{}/owner/export", ctx.site_id))
+        .add_header(
+            HeaderName::from_static("projectid"),
+            HeaderValue::from_str(&ctx.secret_key).unwrap(),
+        )
+        .await;
+    response.assert_status_ok();
+    assert_eq!(response.header("content-type"), "application/x-ndjson");
+
+    let body = response.text();
+    assert!(body.starts_with(r#"{"type":"header""#));
+    Archive::read(body.as_bytes()).unwrap()
+}
+
+/// Test that the export endpoint requires the secret key
+#[tokio::test]
+async fn test_export_requires_secret_key() {
+    let ctx = TestContext::new().await;
+    let (key_name, key_value) = ctx.project_id_header();
+
+    ctx.server
+        .get(&format!("/v1/admin/sites/{}/owner/export", ctx.site_id))
+        .add_header(key_name, key_value)
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+}
+
+/// Test that a site can be restored into a new site ID
+#[tokio::test]
+async fn test_export_restore_into_new_site() {
+    let ctx = TestContext::new().await;
+    let redis = ctx.get_redis_client().await;
+
+    let alice = ctx.register_user("alice", "alice@example.com", "password123").await;
+    let alice_token = alice["token"].as_str().unwrap();
+    let alice_id: Uuid = alice["user"]["id"].as_str().unwrap().parse().unwrap();
+    let bob = ctx.register_user("bob", "bob@example.com", "password123").await;
+    let bob_id: Uuid = bob["user"]["id"].as_str().unwrap().parse().unwrap();
+
+    let root_id = create_thread(&ctx, alice_token, "/post").await;
+    wait_for_page_url(&redis, ctx.site_id, "/post").await;
+
+    let page_id = RedisClient::generate_page_id(ctx.site_id, "/post");
+    redis.pin_comment(page_id, root_id, 1_700_000_000).await.unwrap();
+    redis.lock_page(ctx.site_id, page_id).await.unwrap();
+    redis.add_moderator(ctx.site_id, bob_id).await.unwrap();
+
+    let archive = export(&ctx).await;
+    assert_eq!(archive.header.site_id, ctx.site_id);
+    assert_eq!(archive.pages.len(), 1);
+    assert_eq!(archive.pages[0].page_url.as_deref(), Some("/post"));
+    assert_eq!(archive.roles.moderators, vec![bob_id]);
+    assert!(archive.users.iter().any(|u| u.id == alice_id));
+    // The secret key stays out of the archive
+    assert!(archive.site.project_id_secret.is_empty());
+    assert!(!archive.site.project_id_public.is_empty());
+
+    let new_site_id = Uuid::now_v7();
+    let options = RestoreOptions {
+        site_id: Some(new_site_id),
+    };
+    let report = restore_site(&redis, &redis, &archive, &options).await.unwrap();
+    assert!(report.site_created);
+    // The archived keys still belong to the original site
+    assert!(report.new_project_keys.is_some());
+    assert_eq!(report.pages, 1);
+    assert_eq!(report.comments, 2);
+    assert!(report.unmapped_pages.is_empty());
+
+    // Page ID is recomputed for the new site
+    let new_page_id = RedisClient::generate_page_id(new_site_id, "/post");
+    let tree = redis.get_page_tree(new_page_id).await.unwrap().unwrap();
+    assert_eq!(tree.comments.len(), 1);
+    assert_eq!(tree.comments[0].id, root_id);
+    assert_eq!(tree.comments[0].author_id, alice_id);
+    assert_eq!(tree.comments[0].replies[0].text, "A reply");
+
+    assert!(redis.is_page_locked(new_site_id, new_page_id).await.unwrap());
+    assert_eq!(
+        redis.get_pinned_comments(new_page_id).await.unwrap()[0].0,
+        root_id
+    );
+    assert_eq!(redis.get_moderators(new_site_id).await.unwrap(), vec![bob_id]);
+    let comments = redis.get_site_comment_index(new_site_id, 0, 10).await.unwrap();
+    assert_eq!(comments.len(), 2);
+
+    // Restoring again merges nothing new
+    let report = restore_site(&redis, &redis, &archive, &options).await.unwrap();
+    assert!(!report.site_created);
+    assert_eq!(report.comments, 0);
+    assert_eq!(report.already_present, 2);
+}
+
+/// Test that a site can be restored into an empty Redis under its own ID
+#[tokio::test]
+async fn test_export_restore_into_empty_redis() {
+    let ctx = TestContext::new().await;
+    let redis = ctx.get_redis_client().await;
+
+    let alice = ctx.register_user("alice", "alice@example.com", "password123").await;
+    let alice_token = alice["token"].as_str().unwrap();
+    let alice_id: Uuid = alice["user"]["id"].as_str().unwrap().parse().unwrap();
+
+    create_thread(&ctx, alice_token, "/post").await;
+    wait_for_page_url(&redis, ctx.site_id, "/post").await;
+
+    let archive = export(&ctx).await;
+
+    let client = redis::Client::open(ctx.get_redis_url().await).unwrap();
+    let mut conn = client.get_multiplexed_async_connection().await.unwrap();
+    redis::cmd("FLUSHALL").query_async::<()>(&mut conn).await.unwrap();
+
+    let report = restore_site(&redis, &redis, &archive, &RestoreOptions::default())
+        .await
+        .unwrap();
+    assert!(report.site_created);
+    // Same public key, and a new secret key since it wasn't exported
+    let (public, secret) = report.new_project_keys.clone().unwrap();
+    assert_eq!(public, ctx.project_id);
+    assert!(secret.starts_with("tk_sec_"));
+    assert_ne!(secret, ctx.secret_key);
+    assert_eq!(report.users_created, 1);
+    assert_eq!(report.comments, 2);
+
+    // Same keys and page IDs, so the embed keeps working
+    let (key_name, key_value) = ctx.project_id_header();
+    let response = ctx
+        .server
+        .get("/v1/comments")
+        .add_query_param("page_url", "/post")
+        .add_header(key_name, key_value)
+        .await;
+    response.assert_status_ok();
+    assert_eq!(response.json::<serde_json::Value>()["total"], 2);
+
+    assert_eq!(
+        redis.get_user_by_email("alice@example.com").await.unwrap(),
+        Some(alice_id)
+    );
+    let alice = redis.get_user(alice_id).await.unwrap().unwrap();
+    assert_eq!(alice.total_comments, 2);
+}
+
+/// Test that archived users aren't merged into accounts that never verified the email
+#[tokio::test]
+async fn test_restore_skips_unverified_email_match() {
+    let ctx = TestContext::new().await;
+    let redis = ctx.get_redis_client().await;
+
+    let alice = ctx.register_user("alice", "alice@example.com", "password123").await;
+    let alice_token = alice["token"].as_str().unwrap();
+    let alice_id: Uuid = alice["user"]["id"].as_str().unwrap().parse().unwrap();
+
+    create_thread(&ctx, alice_token, "/post").await;
+    wait_for_page_url(&redis, ctx.site_id, "/post").await;
+
+    let archive = export(&ctx).await;
+
+    let client = redis::Client::open(ctx.get_redis_url().await).unwrap();
+    let mut conn = client.get_multiplexed_async_connection().await.unwrap();
+    redis::cmd("FLUSHALL").query_async::<()>(&mut conn).await.unwrap();
+
+    // Someone signed up with Alice's address before the restore without proving it's theirs
+    let squatter = User {
+        id: Uuid::now_v7(),
+        name: "squatter".to_string(),
+        email: Some("alice@example.com".to_string()),
+        avatar_url: None,
+        provider: AuthProvider::Email,
+        provider_id: None,
+        email_verified: false,
+        karma: 0,
+        global_banned: false,
+        shadow_banned: false,
+        created_at: chrono::Utc::now(),
+        username_set: true,
+        social_links: SocialLinks::default(),
+        total_comments: 0,
+        email_notifications: Default::default(),
+    };
+    redis.set_user(&squatter).await.unwrap();
+    redis.set_user_email_index("alice@example.com", squatter.id).await.unwrap();
+
+    let report = restore_site(&redis, &redis, &archive, &RestoreOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(report.users_matched_by_email, 0);
+    assert_eq!(report.users_created, 1);
+
+    // Alice's comments stay with her restored user, which doesn't take over the email
+    let page_id = RedisClient::generate_page_id(ctx.site_id, "/post");
+    let tree = redis.get_page_tree(page_id).await.unwrap().unwrap();
+    assert_eq!(tree.comments[0].author_id, alice_id);
+    let restored = redis.get_user(alice_id).await.unwrap().unwrap();
+    assert!(restored.email.is_none());
+    assert_eq!(
+        redis.get_user_by_email("alice@example.com").await.unwrap(),
+        Some(squatter.id)
+    );
+    let squatter = redis.get_user(squatter.id).await.unwrap().unwrap();
+    assert_eq!(squatter.total_comments, 0);
+}
@@ -76,7 +76,44 @@ async fn test_send_otp_email() {
         }))
         .await;
 
-    // Should succeed (email provider not configured, so just logs)
+    response.assert_status(StatusCode::OK);
+
+    // The code is emailed from the site
+    let emails = ctx.sent_emails();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0]["to"], "otp-test@example.com");
+    let from = emails[0]["from"].as_str().unwrap();
+    assert!(from.contains("Test Site") && from.ends_with("<noreply@localhost>"));
+    assert!(emails[0]["subject"].as_str().unwrap().ends_with("Sign in to Test Site"));
+}
+
+#[tokio::test]
+async fn test_verify_otp_with_emailed_code() {
+    let ctx = TestContext::new().await;
+    let (key_name, key_value) = project_id_header(&ctx.project_id);
+
+    ctx.server
+        .post("/v1/auth/send-otp")
+        .add_header(key_name.clone(), key_value.clone())
+        .json(&json!({ "email": "emailed@example.com" }))
+        .await
+        .assert_status(StatusCode::OK);
+
+    let emails = ctx.sent_emails();
+    let code = emails[0]["subject"].as_str().unwrap().split(' ').next().unwrap().to_string();
+    assert!(emails[0]["text"].as_str().unwrap().contains(&code));
+    assert!(emails[0]["html"].as_str().unwrap().contains(&code));
+
+    let response = ctx
+        .server
+        .post("/v1/auth/verify-otp")
+        .add_header(key_name, key_value)
+        .json(&json!({
+            "email": "emailed@example.com",
+            "code": code,
+            "name": "emaileduser"
+        }))
+        .await;
     response.assert_status(StatusCode::OK);
 }
 
@@ -269,6 +306,152 @@ async fn test_logout() {
     response.assert_status(StatusCode::OK);
 }
 
+/// Sign in with an emailed code and return the auth response (with a refresh token)
+async fn sign_in(ctx: &TestContext, email: &str, name: &str) -> serde_json::Value {
+    let (key_name, key_value) = project_id_header(&ctx.project_id);
+
+    ctx.server
+        .post("/v1/auth/send-otp")
+        .add_header(key_name.clone(), key_value.clone())
+        .json(&json!({ "email": email }))
+        .await
+        .assert_status(StatusCode::OK);
+
+    let emails = ctx.sent_emails();
+    let code = emails.last().unwrap()["subject"].as_str().unwrap().split(' ').next().unwrap().to_string();
+
+    let response = ctx
+        .server
+        .post("/v1/auth/verify-otp")
+        .add_header(key_name, key_value)
+        .json(&json!({ "email": email, "code": code, "name": name }))
+        .await;
+    response.assert_status(StatusCode::OK);
+    response.json()
+}
+
+async fn refresh(ctx: &TestContext, refresh_token: &str) -> axum_test::TestResponse {
+    let (key_name, key_value) = project_id_header(&ctx.project_id);
+    ctx.server
+        .post("/v1/auth/refresh")
+        .add_header(key_name, key_value)
+        .json(&json!({ "refresh_token": refresh_token }))
+        .await
+}
+
+async fn get_me(ctx: &TestContext, token: &str) -> axum_test::TestResponse {
+    let (key_name, key_value) = project_id_header(&ctx.project_id);
+    let (auth_name, auth_value) = auth_header(token);
+    ctx.server
+        .get("/v1/users/me")
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value)
+        .await
+}
+
+#[tokio::test]
+async fn test_refresh_token_rotation() {
+    let ctx = TestContext::new().await;
+    let auth = sign_in(&ctx, "rotate@example.com", "rotateuser").await;
+    let first = auth["refresh_token"].as_str().unwrap();
+
+    let response = refresh(&ctx, first).await;
+    response.assert_status(StatusCode::OK);
+    let body: serde_json::Value = response.json();
+    let second = body["refresh_token"].as_str().unwrap();
+    assert_ne!(second, first);
+    assert_eq!(body["user"]["name"], "rotateuser");
+
+    get_me(&ctx, body["token"].as_str().unwrap()).await.assert_status(StatusCode::OK);
+
+    // The new refresh token works in turn
+    refresh(&ctx, second).await.assert_status(StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_access_token_rejected_as_refresh_token() {
+    let ctx = TestContext::new().await;
+    let auth = sign_in(&ctx, "access@example.com", "accessuser").await;
+
+    refresh(&ctx, auth["token"].as_str().unwrap())
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+
+    // Nor is a refresh token an access token
+    get_me(&ctx, auth["refresh_token"].as_str().unwrap())
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+    get_me(&ctx, auth["token"].as_str().unwrap()).await.assert_status(StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_refresh_token_reuse_revokes_session() {
+    let ctx = TestContext::new().await;
+    let auth = sign_in(&ctx, "reuse@example.com", "reuseuser").await;
+    let first = auth["refresh_token"].as_str().unwrap();
+
+    let rotated: serde_json::Value = refresh(&ctx, first).await.json();
+    let second = rotated["refresh_token"].as_str().unwrap();
+    let token = rotated["token"].as_str().unwrap();
+    get_me(&ctx, token).await.assert_status(StatusCode::OK);
+
+    // Replaying the used token revokes the whole session
+    refresh(&ctx, first).await.assert_status(StatusCode::UNAUTHORIZED);
+    refresh(&ctx, second).await.assert_status(StatusCode::UNAUTHORIZED);
+    get_me(&ctx, token).await.assert_status(StatusCode::UNAUTHORIZED);
+    get_me(&ctx, auth["token"].as_str().unwrap())
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+
+    // Other sessions of the user are unaffected
+    let other = sign_in(&ctx, "reuse@example.com", "reuseuser").await;
+    refresh(&ctx, other["refresh_token"].as_str().unwrap())
+        .await
+        .assert_status(StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_refresh_token_expired() {
+    let ctx = TestContext::new().await;
+    let auth = sign_in(&ctx, "expired@example.com", "expireduser").await;
+    let refresh_token = auth["refresh_token"].as_str().unwrap();
+
+    // Backdate the session's refresh token
+    let session_id = threadkit_common::auth::parse_refresh_token(refresh_token).unwrap();
+    ctx.get_redis_client()
+        .await
+        .set_refresh_token(
+            session_id,
+            &threadkit_common::api_tokens::hash_token(refresh_token),
+            chrono::Utc::now() - chrono::Duration::seconds(1),
+        )
+        .await
+        .unwrap();
+
+    let response = refresh(&ctx, refresh_token).await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+    assert!(response.text().contains("expired"));
+}
+
+#[tokio::test]
+async fn test_logout_revokes_refresh_token() {
+    let ctx = TestContext::new().await;
+    let auth = sign_in(&ctx, "logoutrefresh@example.com", "logoutrefresh").await;
+    let (key_name, key_value) = project_id_header(&ctx.project_id);
+    let (auth_name, auth_value) = auth_header(auth["token"].as_str().unwrap());
+
+    ctx.server
+        .post("/v1/auth/logout")
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value)
+        .await
+        .assert_status(StatusCode::OK);
+
+    refresh(&ctx, auth["refresh_token"].as_str().unwrap())
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+}
+
 // ============================================================================
 // Anonymous Auth Tests
 // Note: Anonymous auth tests are not included because it requires enabling
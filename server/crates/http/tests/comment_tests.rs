@@ -200,6 +200,203 @@ async fn test_update_comment_not_owner() {
     response.assert_status(StatusCode::FORBIDDEN);
 }
 
+/// Edit a comment as the given user
+async fn edit_comment(
+    ctx: &TestContext,
+    token: &str,
+    page_url: &str,
+    comment_id: &str,
+    content: &str,
+) -> axum_test::TestResponse {
+    let (key_name, key_value) = project_id_header(&ctx.project_id);
+    let (auth_name, auth_value) = auth_header(token);
+    ctx.server
+        .put(&format!("/v1/comments/{}", comment_id))
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value)
+        .json(&json!({
+            "page_url": page_url,
+            "content": content,
+            "path": [comment_id]
+        }))
+        .await
+}
+
+/// Fetch a comment's revisions, optionally signed in
+async fn get_revisions(
+    ctx: &TestContext,
+    token: Option<&str>,
+    page_url: &str,
+    comment_id: &str,
+) -> axum_test::TestResponse {
+    let (key_name, key_value) = project_id_header(&ctx.project_id);
+    let mut request = ctx
+        .server
+        .get(&format!("/v1/comments/{}/revisions", comment_id))
+        .add_query_param("page_url", page_url)
+        .add_query_param("path", comment_id)
+        .add_header(key_name, key_value);
+    if let Some(token) = token {
+        let (auth_name, auth_value) = auth_header(token);
+        request = request.add_header(auth_name, auth_value);
+    }
+    request.await
+}
+
+#[tokio::test]
+async fn test_edit_records_revision() {
+    let ctx = TestContext::new().await;
+    let page_url = "https://example.com/revisions";
+
+    let auth = ctx
+        .register_user("reviser", "reviser@example.com", "password123")
+        .await;
+    let token = auth["token"].as_str().unwrap();
+    let user_id = auth["user"]["id"].as_str().unwrap();
+
+    let mod_auth = ctx
+        .register_user("revmod", "revmod@example.com", "password123")
+        .await;
+    let mod_token = mod_auth["token"].as_str().unwrap();
+    ctx.set_user_role(mod_auth["user"]["id"].as_str().unwrap(), "Moderator").await;
+
+    let response = ctx.create_comment(token, page_url, "First version", None).await;
+    let comment: serde_json::Value = response.json();
+    let comment_id = comment["comment"]["i"].as_str().unwrap();
+
+    edit_comment(&ctx, token, page_url, comment_id, "Second version")
+        .await
+        .assert_status(StatusCode::OK);
+    edit_comment(&ctx, token, page_url, comment_id, "Third version")
+        .await
+        .assert_status(StatusCode::OK);
+
+    let response = get_revisions(&ctx, Some(mod_token), page_url, comment_id).await;
+    response.assert_status(StatusCode::OK);
+    let body: serde_json::Value = response.json();
+    let revisions = body["revisions"].as_array().unwrap();
+    assert_eq!(revisions.len(), 2);
+    assert_eq!(revisions[0]["text"], "First version");
+    assert_eq!(revisions[1]["text"], "Second version");
+    assert_eq!(revisions[0]["editor_id"], user_id);
+}
+
+#[tokio::test]
+async fn test_revisions_hidden_from_users_unless_public() {
+    let ctx = TestContext::new().await;
+    let page_url = "https://example.com/revisions-visibility";
+
+    let auth = ctx
+        .register_user("hider", "hider@example.com", "password123")
+        .await;
+    let token = auth["token"].as_str().unwrap();
+
+    let response = ctx.create_comment(token, page_url, "Original", None).await;
+    let comment: serde_json::Value = response.json();
+    let comment_id = comment["comment"]["i"].as_str().unwrap();
+    edit_comment(&ctx, token, page_url, comment_id, "Edited")
+        .await
+        .assert_status(StatusCode::OK);
+
+    get_revisions(&ctx, Some(token), page_url, comment_id)
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+
+    ctx.update_site_settings(json!({ "editing": { "public_revisions": true } }))
+        .await;
+
+    let response = get_revisions(&ctx, None, page_url, comment_id).await;
+    response.assert_status(StatusCode::OK);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["revisions"][0]["text"], "Original");
+}
+
+#[tokio::test]
+async fn test_moderator_can_edit_any_comment() {
+    let ctx = TestContext::new().await;
+    let page_url = "https://example.com/mod-edit";
+
+    let auth = ctx
+        .register_user("rude", "rude@example.com", "password123")
+        .await;
+    let token = auth["token"].as_str().unwrap();
+
+    let mod_auth = ctx
+        .register_user("cleaner", "cleaner@example.com", "password123")
+        .await;
+    let mod_token = mod_auth["token"].as_str().unwrap();
+    let mod_user_id = mod_auth["user"]["id"].as_str().unwrap();
+    ctx.set_user_role(mod_user_id, "Moderator").await;
+
+    let response = ctx.create_comment(token, page_url, "Something abusive", None).await;
+    let comment: serde_json::Value = response.json();
+    let comment_id = comment["comment"]["i"].as_str().unwrap();
+
+    let response = edit_comment(&ctx, mod_token, page_url, comment_id, "[removed by moderator]").await;
+    response.assert_status(StatusCode::OK);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["t"], "[removed by moderator]");
+
+    let response = get_revisions(&ctx, Some(mod_token), page_url, comment_id).await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["revisions"][0]["text"], "Something abusive");
+    assert_eq!(body["revisions"][0]["editor_id"], mod_user_id);
+}
+
+#[tokio::test]
+async fn test_edit_window() {
+    use threadkit_common::redis::RedisClient;
+
+    let ctx = TestContext::new().await;
+    let page_url = "https://example.com/edit-window";
+
+    let auth = ctx
+        .register_user("latecomer", "latecomer@example.com", "password123")
+        .await;
+    let token = auth["token"].as_str().unwrap();
+
+    let mod_auth = ctx
+        .register_user("windowmod", "windowmod@example.com", "password123")
+        .await;
+    let mod_token = mod_auth["token"].as_str().unwrap();
+    ctx.set_user_role(mod_auth["user"]["id"].as_str().unwrap(), "Moderator").await;
+
+    ctx.update_site_settings(json!({ "editing": { "edit_window_minutes": 15 } }))
+        .await;
+
+    let response = ctx.create_comment(token, page_url, "Fresh comment", None).await;
+    let comment: serde_json::Value = response.json();
+    let comment_id = comment["comment"]["i"].as_str().unwrap();
+
+    // Inside the window
+    edit_comment(&ctx, token, page_url, comment_id, "Quick fix")
+        .await
+        .assert_status(StatusCode::OK);
+
+    // Backdate the comment past the window
+    let redis = ctx.get_redis_client().await;
+    let page_id = RedisClient::generate_page_id(ctx.site_id, page_url);
+    let id = comment_id.parse().unwrap();
+    redis
+        .update_page_tree(page_id, |tree| {
+            let comment = tree.find_by_path_mut(&[id]).ok_or(())?;
+            comment.created_at -= 16 * 60;
+            Ok::<_, ()>(())
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    edit_comment(&ctx, token, page_url, comment_id, "Too late")
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+
+    // Moderators are not limited by the window
+    edit_comment(&ctx, mod_token, page_url, comment_id, "Moderator fix")
+        .await
+        .assert_status(StatusCode::OK);
+}
+
 #[tokio::test]
 async fn test_delete_comment() {
     let ctx = TestContext::new().await;
@@ -354,6 +551,151 @@ async fn test_vote_toggle() {
     assert!(body["user_vote"].is_null());
 }
 
+/// Toggle a reaction on a top-level comment
+async fn react(
+    ctx: &TestContext,
+    token: &str,
+    page_url: &str,
+    comment_id: &str,
+    emoji: &str,
+) -> axum_test::TestResponse {
+    let (key_name, key_value) = project_id_header(&ctx.project_id);
+    let (auth_name, auth_value) = auth_header(token);
+    ctx.server
+        .post(&format!("/v1/comments/{}/reactions", comment_id))
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value)
+        .json(&json!({
+            "page_url": page_url,
+            "emoji": emoji,
+            "path": [comment_id]
+        }))
+        .await
+}
+
+#[tokio::test]
+async fn test_reaction_toggle() {
+    let ctx = TestContext::new().await;
+    let page_url = "https://example.com/reactions";
+
+    let auth1 = ctx
+        .register_user("reactor1", "reactor1@example.com", "password123")
+        .await;
+    let token1 = auth1["token"].as_str().unwrap();
+    let auth2 = ctx
+        .register_user("reactor2", "reactor2@example.com", "password123")
+        .await;
+    let token2 = auth2["token"].as_str().unwrap();
+
+    let response = ctx.create_comment(token1, page_url, "React to me", None).await;
+    let comment: serde_json::Value = response.json();
+    let comment_id = comment["comment"]["i"].as_str().unwrap();
+
+    let response = react(&ctx, token1, page_url, comment_id, "👍").await;
+    response.assert_status(StatusCode::OK);
+    let response = react(&ctx, token2, page_url, comment_id, "👍").await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["reacted"], true);
+    assert_eq!(body["reactions"]["👍"], 2);
+
+    // Counts are carried on the comment
+    let (key_name, key_value) = project_id_header(&ctx.project_id);
+    let response = ctx
+        .server
+        .get("/v1/comments")
+        .add_query_param("page_url", page_url)
+        .add_header(key_name, key_value)
+        .await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["tree"]["c"][0]["z"]["👍"], 2);
+
+    // Reacting again removes it
+    let response = react(&ctx, token2, page_url, comment_id, "👍").await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["reacted"], false);
+    assert_eq!(body["reactions"]["👍"], 1);
+}
+
+#[tokio::test]
+async fn test_reaction_emoji_must_be_allowed() {
+    let ctx = TestContext::new().await;
+    let page_url = "https://example.com/reactions-allowed";
+
+    let auth = ctx
+        .register_user("picky", "picky@example.com", "password123")
+        .await;
+    let token = auth["token"].as_str().unwrap();
+
+    let response = ctx.create_comment(token, page_url, "Only some emojis", None).await;
+    let comment: serde_json::Value = response.json();
+    let comment_id = comment["comment"]["i"].as_str().unwrap();
+
+    let response = react(&ctx, token, page_url, comment_id, "🦀").await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    ctx.update_site_settings(json!({ "reactions": { "emojis": ["🦀"] } }))
+        .await;
+    let response = react(&ctx, token, page_url, comment_id, "🦀").await;
+    response.assert_status(StatusCode::OK);
+
+    // An empty set turns reactions off
+    ctx.update_site_settings(json!({ "reactions": { "emojis": [] } }))
+        .await;
+    let response = react(&ctx, token, page_url, comment_id, "🦀").await;
+    response.assert_status(StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_reaction_to_missing_comment() {
+    let ctx = TestContext::new().await;
+    let page_url = "https://example.com/reactions-missing";
+
+    let auth = ctx
+        .register_user("latecomer", "latecomer@example.com", "password123")
+        .await;
+    let token = auth["token"].as_str().unwrap();
+
+    // No comments on the page yet
+    let missing_id = uuid::Uuid::now_v7().to_string();
+    let response = react(&ctx, token, page_url, &missing_id, "👍").await;
+    response.assert_status(StatusCode::NOT_FOUND);
+
+    // Page exists, comment doesn't
+    ctx.create_comment(token, page_url, "Someone else's thread", None).await;
+    let response = react(&ctx, token, page_url, &missing_id, "👍").await;
+    response.assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_my_votes_includes_reactions() {
+    let ctx = TestContext::new().await;
+    let page_url = "https://example.com/reactions-mine";
+
+    let auth = ctx
+        .register_user("myreacts", "myreacts@example.com", "password123")
+        .await;
+    let token = auth["token"].as_str().unwrap();
+
+    let response = ctx.create_comment(token, page_url, "Mine", None).await;
+    let comment: serde_json::Value = response.json();
+    let comment_id = comment["comment"]["i"].as_str().unwrap();
+
+    react(&ctx, token, page_url, comment_id, "🎉").await;
+
+    let (key_name, key_value) = project_id_header(&ctx.project_id);
+    let (auth_name, auth_value) = auth_header(token);
+    let response = ctx
+        .server
+        .get("/v1/pages/my_votes")
+        .add_query_param("page_url", page_url)
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value)
+        .await;
+    response.assert_status(StatusCode::OK);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["reactions"][comment_id], json!(["🎉"]));
+}
+
 #[tokio::test]
 async fn test_report_comment() {
     let ctx = TestContext::new().await;
@@ -1307,3 +1649,163 @@ async fn test_tree_raw_after_vote() {
         }
     }
 }
+
+#[tokio::test]
+async fn test_get_comments_paginated() {
+    let ctx = TestContext::new().await;
+
+    let auth = ctx
+        .register_user("pager", "pager@example.com", "password123")
+        .await;
+    let token = auth["token"].as_str().unwrap();
+
+    for i in 1..=5 {
+        ctx.create_comment(token, "https://example.com/paged", &format!("Comment {}", i), None)
+            .await
+            .assert_status(StatusCode::OK);
+    }
+
+    // Walk the pages oldest-first, two at a time
+    let mut seen = vec![];
+    let mut cursor: Option<String> = None;
+    loop {
+        let (key_name, key_value) = project_id_header(&ctx.project_id);
+        let mut request = ctx
+            .server
+            .get("/v1/comments")
+            .add_query_param("page_url", "https://example.com/paged")
+            .add_query_param("sort", "old")
+            .add_query_param("limit", 2)
+            .add_header(key_name, key_value);
+        if let Some(c) = &cursor {
+            request = request.add_query_param("cursor", c);
+        }
+        let response = request.await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+
+        // Total always reflects the whole page
+        assert_eq!(body["total"], 5);
+        let page = body["tree"]["c"].as_array().unwrap();
+        assert!(page.len() <= 2);
+        seen.extend(page.iter().map(|c| c["t"].as_str().unwrap().to_string()));
+
+        match body["next_cursor"].as_str() {
+            Some(next) => cursor = Some(next.to_string()),
+            None => break,
+        }
+    }
+
+    let expected: Vec<String> = (1..=5).map(|i| format!("Comment {}", i)).collect();
+    assert_eq!(seen, expected);
+
+    // Unknown cursor is rejected
+    let (key_name, key_value) = project_id_header(&ctx.project_id);
+    let response = ctx
+        .server
+        .get("/v1/comments")
+        .add_query_param("page_url", "https://example.com/paged")
+        .add_query_param("limit", 2)
+        .add_query_param("cursor", uuid::Uuid::now_v7().to_string())
+        .add_header(key_name, key_value)
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_get_comments_truncated_replies_and_subtree() {
+    let ctx = TestContext::new().await;
+
+    let auth = ctx
+        .register_user("threader", "threader@example.com", "password123")
+        .await;
+    let token = auth["token"].as_str().unwrap();
+    let page_url = "https://example.com/threaded";
+
+    let response = ctx.create_comment(token, page_url, "Root", None).await;
+    response.assert_status(StatusCode::OK);
+    let root: serde_json::Value = response.json();
+    let root_id = root["comment"]["i"].as_str().unwrap().to_string();
+
+    // Three direct replies to the root
+    let mut reply_ids = vec![];
+    for i in 1..=3 {
+        let (key_name, key_value) = project_id_header(&ctx.project_id);
+        let (auth_name, auth_value) = auth_header(token);
+        let response = ctx
+            .server
+            .post("/v1/comments")
+            .add_header(key_name, key_value)
+            .add_header(auth_name, auth_value)
+            .json(&json!({
+                "page_url": page_url,
+                "content": format!("Reply {}", i),
+                "parent_path": [root_id]
+            }))
+            .await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        reply_ids.push(body["comment"]["i"].as_str().unwrap().to_string());
+    }
+
+    // Only one reply per comment: the other two become a stub count
+    let (key_name, key_value) = project_id_header(&ctx.project_id);
+    let response = ctx
+        .server
+        .get("/v1/comments")
+        .add_query_param("page_url", page_url)
+        .add_query_param("sort", "old")
+        .add_query_param("max_replies", 1)
+        .add_header(key_name, key_value)
+        .await;
+    response.assert_status(StatusCode::OK);
+    let body: serde_json::Value = response.json();
+    let root = &body["tree"]["c"][0];
+    assert_eq!(root["r"].as_array().unwrap().len(), 1);
+    assert_eq!(root["o"], 2);
+
+    // Depth 0: no replies at all, stub carries the full count
+    let (key_name, key_value) = project_id_header(&ctx.project_id);
+    let response = ctx
+        .server
+        .get("/v1/comments")
+        .add_query_param("page_url", page_url)
+        .add_query_param("max_depth", 0)
+        .add_header(key_name, key_value)
+        .await;
+    let body: serde_json::Value = response.json();
+    assert!(body["tree"]["c"][0].get("r").is_none());
+    assert_eq!(body["tree"]["c"][0]["o"], 3);
+
+    // Expand the rest via the subtree endpoint, continuing after the first reply
+    let (key_name, key_value) = project_id_header(&ctx.project_id);
+    let response = ctx
+        .server
+        .get("/v1/comments/subtree")
+        .add_query_param("page_url", page_url)
+        .add_query_param("parent_path", &root_id)
+        .add_query_param("sort", "old")
+        .add_query_param("limit", 10)
+        .add_query_param("cursor", &reply_ids[0])
+        .add_header(key_name, key_value)
+        .await;
+    response.assert_status(StatusCode::OK);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["total"], 3);
+    let replies = body["replies"].as_array().unwrap();
+    assert_eq!(replies.len(), 2);
+    assert_eq!(replies[0]["t"], "Reply 2");
+    assert_eq!(replies[1]["t"], "Reply 3");
+    assert!(body.get("next_cursor").is_none());
+
+    // Unknown parent
+    let (key_name, key_value) = project_id_header(&ctx.project_id);
+    let response = ctx
+        .server
+        .get("/v1/comments/subtree")
+        .add_query_param("page_url", page_url)
+        .add_query_param("parent_path", uuid::Uuid::now_v7().to_string())
+        .add_header(key_name, key_value)
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+}
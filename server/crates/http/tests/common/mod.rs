@@ -1,4 +1,7 @@
-use axum::{http::HeaderName, http::HeaderValue, middleware, Router};
+use axum::{
+    extract::ConnectInfo, http::HeaderName, http::HeaderValue, middleware, Extension, Router,
+};
+use std::net::SocketAddr;
 use axum_test::TestServer;
 use redis::AsyncCommands;
 use serde_json::json;
@@ -8,13 +11,17 @@ use uuid::Uuid;
 
 use threadkit_common::{
     config::{
-        ContentModerationConfig, EmailConfig, RateLimitConfig, S3Config,
-        StandaloneConfig, TurnstileConfig,
+        ContentModerationConfig, EmailConfig, EmailProvider, Mode, OutboxConfig, RateLimitConfig, S3Config,
+        SaasConfig, StandaloneConfig, StorageBackend, TurnstileConfig, WebhookConfig,
     },
     Config,
 };
 use threadkit_http::{middleware::rate_limit, routes, state::AppState};
 
+/// `ADMIN_API_KEY` of servers started with `TestContext::new_saas`
+#[allow(dead_code)]
+pub const ADMIN_API_KEY: &str = "test_admin_api_key";
+
 pub struct TestContext {
     pub server: TestServer,
     pub project_id: String,
@@ -25,6 +32,8 @@ pub struct TestContext {
     #[allow(dead_code)]
     pub minio_container: Option<ContainerAsync<MinIO>>,
     pub s3_config: Option<S3Config>,
+    /// Where emails sent by the server are written
+    pub outbox_dir: std::path::PathBuf,
 }
 
 impl TestContext {
@@ -33,6 +42,22 @@ impl TestContext {
     }
 
     pub async fn new_with_s3(enable_s3: bool) -> Self {
+        Self::build(enable_s3, false, false).await
+    }
+
+    /// Serve over a real HTTP port, for tests that read streaming responses (SSE)
+    #[allow(dead_code)]
+    pub async fn new_with_http_transport() -> Self {
+        Self::build(false, true, false).await
+    }
+
+    /// Run in SaaS mode with the site management API enabled (`ADMIN_API_KEY`)
+    #[allow(dead_code)]
+    pub async fn new_saas() -> Self {
+        Self::build(false, false, true).await
+    }
+
+    async fn build(enable_s3: bool, http_transport: bool, saas: bool) -> Self {
         // Start Redis container
         let redis_container = Redis::default()
             .with_tag("7-alpine")
@@ -90,6 +115,8 @@ impl TestContext {
             Uuid::now_v7().to_string().replace('-', "")[..32].to_lowercase()
         );
 
+        let outbox_dir = std::env::temp_dir().join(format!("threadkit-outbox-{}", Uuid::now_v7()));
+
         let redis_url = format!("redis://{}:{}", host, port);
         let redis_client = threadkit_common::redis::RedisClient::new(&redis_url)
             .await
@@ -107,6 +134,7 @@ impl TestContext {
             project_id_public: project_id.clone(),
             project_id_secret: secret_key.clone(),
             settings,
+            plan: Default::default(),
         };
         redis_client
             .set_site_config(&site_config)
@@ -115,17 +143,26 @@ impl TestContext {
 
         let config = Config {
             redis_url,
+            storage: StorageBackend::Redis,
             http_host: "127.0.0.1".to_string(),
             http_port: 8080,
             ws_host: "127.0.0.1".to_string(),
             ws_port: 8081,
             jwt_secret: "test_jwt_secret_for_testing".to_string(),
             jwt_expiry_hours: 24,
-            mode: threadkit_common::config::Mode::Standalone(StandaloneConfig {
-                project_id_public: project_id.clone(),
-                project_id_secret: secret_key.clone(),
-                allowed_origins: vec![],
-            }),
+            refresh_token_expiry_days: 30,
+            refresh_reuse_grace_secs: 0,
+            mode: if saas {
+                Mode::Saas(SaasConfig {
+                    admin_api_key: Some(ADMIN_API_KEY.to_string()),
+                })
+            } else {
+                Mode::Standalone(StandaloneConfig {
+                    project_id_public: project_id.clone(),
+                    project_id_secret: secret_key.clone(),
+                    allowed_origins: vec![],
+                })
+            },
             oauth: Default::default(),
             rate_limit: RateLimitConfig {
                 enabled: true,
@@ -142,9 +179,22 @@ impl TestContext {
                 trusted_proxies: vec!["127.0.0.1".to_string()],
             },
             content_moderation: ContentModerationConfig::default(),
-            email: EmailConfig::default(),
+            email: EmailConfig {
+                provider: Some(EmailProvider::Outbox(OutboxConfig {
+                    dir: Some(outbox_dir.to_string_lossy().into_owned()),
+                })),
+                from_address: "noreply@localhost".to_string(),
+                public_api_url: "http://localhost:8080".to_string(),
+                // Send notification emails as soon as the worker polls
+                notification_delay_seconds: 0,
+            },
             turnstile: TurnstileConfig::default(),
             s3: s3_config.clone(),
+            webhooks: WebhookConfig {
+                // Test endpoints listen on 127.0.0.1
+                allow_private_targets: true,
+                ..WebhookConfig::default()
+            },
             max_comment_length: 10_000,
             allow_localhost_origin: true,
         };
@@ -160,15 +210,27 @@ impl TestContext {
             .await
             .expect("Failed to create app state");
 
+        // Relay published page events to SSE streams and send notification emails,
+        // as the server binary does
+        let _pubsub_handle = state.pubsub_subscriber().start();
+        let _emails_handle = state.notification_emails.clone().start();
+
         // Build router
         let app = Router::new()
             .nest(
                 "/v1",
                 routes::router().layer(middleware::from_fn_with_state(state.clone(), rate_limit)),
             )
-            .with_state(state);
+            .with_state(state)
+            // Requests come from a trusted local proxy, so tests can set X-Forwarded-For
+            .layer(Extension(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0)))));
 
-        let server = TestServer::new(app).expect("Failed to create test server");
+        let server = if http_transport {
+            TestServer::builder().http_transport().build(app)
+        } else {
+            TestServer::new(app)
+        }
+        .expect("Failed to create test server");
 
         Self {
             server,
@@ -178,9 +240,24 @@ impl TestContext {
             redis_container,
             minio_container,
             s3_config,
+            outbox_dir,
         }
     }
 
+    /// Emails the server has sent, oldest first
+    #[allow(dead_code)]
+    pub fn sent_emails(&self) -> Vec<serde_json::Value> {
+        let Ok(entries) = std::fs::read_dir(&self.outbox_dir) else {
+            return Vec::new();
+        };
+        let mut paths: Vec<_> = entries.filter_map(|e| e.ok().map(|e| e.path())).collect();
+        paths.sort();
+        paths
+            .iter()
+            .map(|path| serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap())
+            .collect()
+    }
+
     pub fn project_id_header(&self) -> (HeaderName, HeaderValue) {
         (
             HeaderName::from_static("projectid"),
@@ -390,6 +467,18 @@ impl TestContext {
             config.settings.turnstile = serde_json::from_value::<TurnstileSettings>(turnstile_obj.clone())
                 .expect("Failed to parse turnstile settings");
         }
+        if let Some(editing_obj) = partial_settings.get("editing") {
+            config.settings.editing = serde_json::from_value(editing_obj.clone())
+                .expect("Failed to parse editing settings");
+        }
+        if let Some(reactions_obj) = partial_settings.get("reactions") {
+            config.settings.reactions = serde_json::from_value(reactions_obj.clone())
+                .expect("Failed to parse reaction settings");
+        }
+        if let Some(email_obj) = partial_settings.get("email") {
+            config.settings.email = serde_json::from_value(email_obj.clone())
+                .expect("Failed to parse email settings");
+        }
 
         // Save updated config
         redis
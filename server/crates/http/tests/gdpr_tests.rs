@@ -476,3 +476,213 @@ async fn test_delete_account_with_nested_replies() {
         }
     }
 }
+
+#[tokio::test]
+async fn test_export_includes_user_data() {
+    let ctx = TestContext::new().await;
+
+    let user1_response = ctx.register_user("User 1", "user1@example.com", "").await;
+    let user1_id = user1_response["user"]["id"].as_str().unwrap();
+    let user1_token = user1_response["token"].as_str().unwrap();
+
+    let user2_response = ctx.register_user("User 2", "user2@example.com", "").await;
+    let user2_id = user2_response["user"]["id"].as_str().unwrap();
+    let user2_token = user2_response["token"].as_str().unwrap();
+
+    // User 1 comments, User 2 replies and votes on it
+    let comment_response = ctx
+        .create_comment(user1_token, "https://example.com/page1", "My comment", None)
+        .await;
+    let comment_body: serde_json::Value = comment_response.json();
+    let comment_id = comment_body["comment"]["i"].as_str().unwrap();
+    ctx.index_comment(user1_id, "https://example.com/page1", comment_id).await;
+
+    let reply_response = ctx
+        .create_comment(
+            user2_token,
+            "https://example.com/page1",
+            "Someone else's reply",
+            Some(uuid::Uuid::parse_str(comment_id).unwrap()),
+        )
+        .await;
+    assert_eq!(reply_response.status_code(), StatusCode::OK);
+
+    // User 1 votes and reacts on their own comment and blocks User 2
+    let vote_response = ctx
+        .server
+        .post(&format!("/v1/comments/{}/vote", comment_id))
+        .add_header("projectid", &ctx.project_id)
+        .add_header("Authorization", &format!("Bearer {}", user1_token))
+        .json(&json!({
+            "direction": "down",
+            "page_url": "https://example.com/page1",
+            "path": [comment_id]
+        }))
+        .await;
+    assert_eq!(vote_response.status_code(), StatusCode::OK);
+
+    let reaction_response = ctx
+        .server
+        .post(&format!("/v1/comments/{}/reactions", comment_id))
+        .add_header("projectid", &ctx.project_id)
+        .add_header("Authorization", &format!("Bearer {}", user1_token))
+        .json(&json!({
+            "page_url": "https://example.com/page1",
+            "emoji": "👍",
+            "path": [comment_id]
+        }))
+        .await;
+    assert_eq!(reaction_response.status_code(), StatusCode::OK);
+
+    ctx.server
+        .post(&format!("/v1/users/{}/block", user2_id))
+        .add_header("projectid", &ctx.project_id)
+        .add_header("Authorization", &format!("Bearer {}", user1_token))
+        .await;
+
+    let response = ctx
+        .server
+        .get("/v1/users/me/export")
+        .add_header("projectid", &ctx.project_id)
+        .add_header("Authorization", &format!("Bearer {}", user1_token))
+        .await;
+
+    assert_eq!(response.status_code(), StatusCode::OK);
+    assert!(response
+        .header("content-disposition")
+        .to_str()
+        .unwrap()
+        .starts_with("attachment;"));
+    let export: serde_json::Value = response.json();
+
+    assert_eq!(export["user"]["id"], user1_id);
+    assert_eq!(export["user"]["email"], "user1@example.com");
+
+    // Only the user's own comment, without other users' replies
+    let comments = export["comments"].as_array().unwrap();
+    assert_eq!(comments.len(), 1);
+    assert_eq!(comments[0]["comment"]["i"], comment_id);
+    assert_eq!(comments[0]["comment"]["t"], "My comment");
+    assert!(comments[0]["comment"]["r"].as_array().is_none_or(|r| r.is_empty()));
+
+    let votes = export["votes"].as_array().unwrap();
+    assert_eq!(votes.len(), 1);
+    assert_eq!(votes[0]["comment_id"], comment_id);
+    assert_eq!(votes[0]["direction"], "down");
+
+    let reactions = export["reactions"].as_array().unwrap();
+    assert_eq!(reactions.len(), 1);
+    assert_eq!(reactions[0]["comment_id"], comment_id);
+    assert_eq!(reactions[0]["emoji"], "👍");
+
+    assert_eq!(export["blocked_users"], json!([user2_id]));
+    // Other users' blocks stay private to them
+    assert!(export.get("blocked_by").is_none());
+    assert_eq!(export["media"], json!([]));
+
+    // The session created at registration
+    assert_eq!(export["sessions"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_export_includes_notifications() {
+    let ctx = TestContext::new().await;
+
+    let user1_response = ctx.register_user("User 1", "user1@example.com", "").await;
+    let user1_id = user1_response["user"]["id"].as_str().unwrap();
+    let user1_token = user1_response["token"].as_str().unwrap();
+
+    let redis = ctx.get_redis_client().await;
+    let notification = threadkit_common::types::Notification {
+        id: uuid::Uuid::now_v7(),
+        notification_type: threadkit_common::types::NotificationType::Reply,
+        comment_id: uuid::Uuid::now_v7(),
+        from_user_id: uuid::Uuid::now_v7(),
+        read: false,
+        site_id: None,
+        page_url: None,
+        excerpt: None,
+        created_at: chrono::Utc::now(),
+    };
+    redis
+        .add_notification(user1_id.parse().unwrap(), &notification)
+        .await
+        .unwrap();
+
+    let response = ctx
+        .server
+        .get("/v1/users/me/export")
+        .add_header("projectid", &ctx.project_id)
+        .add_header("Authorization", &format!("Bearer {}", user1_token))
+        .await;
+
+    assert_eq!(response.status_code(), StatusCode::OK);
+    let export: serde_json::Value = response.json();
+    let notifications = export["notifications"].as_array().unwrap();
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0]["id"], notification.id.to_string());
+    assert_eq!(notifications[0]["notification_type"], "reply");
+}
+
+#[tokio::test]
+async fn test_export_requires_auth() {
+    let ctx = TestContext::new().await;
+
+    let response = ctx
+        .server
+        .get("/v1/users/me/export")
+        .add_header("projectid", &ctx.project_id)
+        .await;
+
+    assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+}
+
+/// Test that revisions of the user's comments don't point back at the deleted account
+#[tokio::test]
+async fn test_delete_account_anonymizes_revisions() {
+    let ctx = TestContext::new().await;
+    let page_url = "https://example.com/page1";
+
+    let user_response = ctx.register_user("Editor", "editor@example.com", "").await;
+    let user_id = user_response["user"]["id"].as_str().unwrap();
+    let token = user_response["token"].as_str().unwrap();
+
+    let response = ctx.create_comment(token, page_url, "First version", None).await;
+    assert_eq!(response.status_code(), StatusCode::OK);
+    let body: serde_json::Value = response.json();
+    let comment_id = body["comment"]["i"].as_str().unwrap();
+    ctx.index_comment(user_id, page_url, comment_id).await;
+
+    let edit_response = ctx
+        .server
+        .put(&format!("/v1/comments/{}", comment_id))
+        .add_header("projectid", &ctx.project_id)
+        .add_header("Authorization", &format!("Bearer {}", token))
+        .json(&json!({
+            "page_url": page_url,
+            "content": "Second version",
+            "path": [comment_id]
+        }))
+        .await;
+    assert_eq!(edit_response.status_code(), StatusCode::OK);
+
+    let delete_response = ctx
+        .server
+        .delete("/v1/users/me")
+        .add_header("projectid", &ctx.project_id)
+        .add_header("Authorization", &format!("Bearer {}", token))
+        .await;
+    assert_eq!(delete_response.status_code(), StatusCode::OK);
+
+    let redis = ctx.get_redis_client().await;
+    let revisions = redis
+        .get_comment_revisions(comment_id.parse().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(revisions.len(), 1);
+    assert_eq!(revisions[0].text, "First version");
+    assert_eq!(
+        revisions[0].editor_id.to_string(),
+        "d0000000-0000-0000-0000-000000000000"
+    );
+}
@@ -0,0 +1,318 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::TestContext;
+
+use threadkit_common::types::{AuthProvider, SocialLinks, User};
+use threadkit_common::ActionLogger;
+use threadkit_http::import::{run_import, ImportFormat, ImportOptions, ImportReport};
+use uuid::Uuid;
+
+const DISQUS_EXPORT: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<disqus xmlns="http://disqus.com" xmlns:dsq="http://disqus.com/disqus-internals">
+  <thread dsq:id="100">
+    <link>https://example.com/blog/hello</link>
+    <title>Hello</title>
+  </thread>
+  <thread dsq:id="200">
+    <title>No link</title>
+  </thread>
+  <post dsq:id="1">
+    <message><![CDATA[<p>First post</p>]]></message>
+    <createdAt>2019-01-01T10:00:00Z</createdAt>
+    <isDeleted>false</isDeleted>
+    <isSpam>false</isSpam>
+    <author><email>alice@example.com</email><name>Alice</name></author>
+    <thread dsq:id="100" />
+  </post>
+  <post dsq:id="2">
+    <message><![CDATA[<p>A reply</p>]]></message>
+    <createdAt>2019-01-02T10:00:00Z</createdAt>
+    <isDeleted>false</isDeleted>
+    <isSpam>false</isSpam>
+    <author><email>bob@example.com</email><name>Bob Smith</name></author>
+    <thread dsq:id="100" />
+    <parent dsq:id="1" />
+  </post>
+  <post dsq:id="3">
+    <message><![CDATA[<p>Second root<script>alert(1)</script></p>]]></message>
+    <createdAt>2019-01-03T10:00:00Z</createdAt>
+    <isDeleted>false</isDeleted>
+    <isSpam>false</isSpam>
+    <author><name>Guest</name></author>
+    <thread dsq:id="100" />
+  </post>
+  <post dsq:id="4">
+    <message><![CDATA[<p>Lost</p>]]></message>
+    <createdAt>2019-01-04T10:00:00Z</createdAt>
+    <isDeleted>false</isDeleted>
+    <isSpam>false</isSpam>
+    <author><name>Guest</name></author>
+    <thread dsq:id="200" />
+  </post>
+</disqus>"#;
+
+async fn import(ctx: &TestContext, dry_run: bool) -> ImportReport {
+    import_export(ctx, DISQUS_EXPORT, dry_run).await
+}
+
+async fn import_export(ctx: &TestContext, export: &str, dry_run: bool) -> ImportReport {
+    let options = ImportOptions {
+        dry_run,
+        ..Default::default()
+    };
+    import_with(ctx, export, &options).await
+}
+
+/// Create an account holding `email` that never verified it
+async fn create_unverified_user(ctx: &TestContext, email: &str) -> User {
+    let redis = ctx.get_redis_client().await;
+    let user = User {
+        id: Uuid::now_v7(),
+        name: "squatter".to_string(),
+        email: Some(email.to_string()),
+        avatar_url: None,
+        provider: AuthProvider::Email,
+        provider_id: None,
+        email_verified: false,
+        karma: 0,
+        global_banned: false,
+        shadow_banned: false,
+        created_at: chrono::Utc::now(),
+        username_set: true,
+        social_links: SocialLinks::default(),
+        total_comments: 0,
+        email_notifications: Default::default(),
+    };
+    redis.set_user(&user).await.unwrap();
+    redis.set_user_email_index(email, user.id).await.unwrap();
+    user
+}
+
+async fn import_with(ctx: &TestContext, export: &str, options: &ImportOptions) -> ImportReport {
+    let redis = ctx.get_redis_client().await;
+    let site = redis.get_site_config(ctx.site_id).await.unwrap().unwrap();
+    let data = ImportFormat::Disqus.parse(export).unwrap();
+    let action_logger = ActionLogger::new(None).unwrap();
+    run_import(&redis, &redis, &site, &data, ImportFormat::Disqus, options, &action_logger)
+        .await
+        .unwrap()
+}
+
+async fn get_tree(ctx: &TestContext, page_url: &str) -> serde_json::Value {
+    let (key_name, key_value) = ctx.project_id_header();
+    let response = ctx
+        .server
+        .get("/v1/comments")
+        .add_query_param("page_url", page_url)
+        .add_header(key_name, key_value)
+        .await;
+    response.assert_status(StatusCode::OK);
+    response.json()
+}
+
+/// Test that a Disqus export is imported with nesting, timestamps and authors
+#[tokio::test]
+async fn test_import_disqus() {
+    let ctx = TestContext::new().await;
+    let alice = ctx.register_user("alice", "alice@example.com", "password123").await;
+    let alice_id = alice["user"]["id"].as_str().unwrap();
+
+    let report = import(&ctx, false).await;
+    assert_eq!(report.pages, 1);
+    assert_eq!(report.comments, 3);
+    assert_eq!(report.replies, 1);
+    assert_eq!(report.users_matched, 1);
+    assert_eq!(report.users_created, 1);
+    assert_eq!(report.anonymous_comments, 1);
+    assert_eq!(report.unmapped_comments, 1);
+    assert_eq!(report.unmapped_threads[0].key, "200");
+
+    // Thread URL is mapped to its path, like the embed's page_url
+    let body = get_tree(&ctx, "/blog/hello").await;
+    assert_eq!(body["total"], 3);
+    let roots = body["tree"]["c"].as_array().unwrap();
+    assert_eq!(roots.len(), 2);
+
+    let first = roots
+        .iter()
+        .find(|c| c["t"] == "First post")
+        .expect("First post imported");
+    assert_eq!(first["a"], alice_id);
+    assert_eq!(first["x"], 1546336800); // 2019-01-01T10:00:00Z
+    assert_eq!(first["r"][0]["t"], "A reply");
+    assert_eq!(first["r"][0]["x"], 1546423200);
+
+    // Imported HTML is sanitized
+    let second = roots.iter().find(|c| c["n"] == "Guest").unwrap();
+    assert!(!second["h"].as_str().unwrap().contains("script"));
+
+    // Unknown author gets a placeholder account matched by email
+    let redis = ctx.get_redis_client().await;
+    let bob_id = redis.get_user_by_email("bob@example.com").await.unwrap().unwrap();
+    let bob = redis.get_user(bob_id).await.unwrap().unwrap();
+    assert_eq!(bob.name, "bob-smith");
+    assert!(!bob.username_set);
+    assert!(!bob.email_verified);
+    assert_eq!(bob.total_comments, 1);
+    assert_eq!(first["r"][0]["a"], bob_id.to_string());
+}
+
+/// Test that re-running an import doesn't duplicate comments
+#[tokio::test]
+async fn test_import_is_idempotent() {
+    let ctx = TestContext::new().await;
+
+    let first = import(&ctx, false).await;
+    assert_eq!(first.comments, 3);
+    assert_eq!(first.users_created, 2);
+
+    let second = import(&ctx, false).await;
+    assert_eq!(second.comments, 0);
+    assert_eq!(second.already_imported, 3);
+    assert_eq!(second.users_matched, 2);
+    assert_eq!(second.users_created, 0);
+
+    let body = get_tree(&ctx, "/blog/hello").await;
+    assert_eq!(body["total"], 3);
+}
+
+/// Test that a newer export adds replies below threads imported before
+#[tokio::test]
+async fn test_reimport_adds_new_replies() {
+    let ctx = TestContext::new().await;
+    import(&ctx, false).await;
+
+    let newer = DISQUS_EXPORT.replace(
+        "</disqus>",
+        r#"<post dsq:id="5">
+    <message><![CDATA[<p>A later reply</p>]]></message>
+    <createdAt>2019-02-01T10:00:00Z</createdAt>
+    <isDeleted>false</isDeleted>
+    <isSpam>false</isSpam>
+    <author><name>Guest</name></author>
+    <thread dsq:id="100" />
+    <parent dsq:id="1" />
+  </post>
+  <post dsq:id="6">
+    <message><![CDATA[<p>A nested reply</p>]]></message>
+    <createdAt>2019-02-02T10:00:00Z</createdAt>
+    <isDeleted>false</isDeleted>
+    <isSpam>false</isSpam>
+    <author><name>Guest</name></author>
+    <thread dsq:id="100" />
+    <parent dsq:id="2" />
+  </post>
+</disqus>"#,
+    );
+
+    let dry_run = import_export(&ctx, &newer, true).await;
+    assert_eq!(dry_run.comments, 2);
+    assert_eq!(dry_run.already_imported, 3);
+
+    let report = import_export(&ctx, &newer, false).await;
+    assert_eq!(report.comments, 2);
+    assert_eq!(report.already_imported, 3);
+
+    let body = get_tree(&ctx, "/blog/hello").await;
+    assert_eq!(body["total"], 5);
+    let roots = body["tree"]["c"].as_array().unwrap();
+    assert_eq!(roots.len(), 2);
+    let first = roots.iter().find(|c| c["t"] == "First post").unwrap();
+    let replies = first["r"].as_array().unwrap();
+    assert_eq!(replies.len(), 2);
+    assert_eq!(replies[0]["r"][0]["t"], "A nested reply");
+    assert_eq!(replies[1]["t"], "A later reply");
+}
+
+/// Test that a dry run reports counts without writing
+#[tokio::test]
+async fn test_import_dry_run() {
+    let ctx = TestContext::new().await;
+
+    let report = import(&ctx, true).await;
+    assert!(report.dry_run);
+    assert_eq!(report.comments, 3);
+    assert_eq!(report.users_created, 2);
+    assert_eq!(report.unmapped_threads.len(), 1);
+    assert_eq!(report.unmapped_threads[0].title.as_deref(), Some("No link"));
+
+    let body = get_tree(&ctx, "/blog/hello").await;
+    assert_eq!(body["total"], 0);
+
+    let redis = ctx.get_redis_client().await;
+    assert!(redis.get_user_by_email("bob@example.com").await.unwrap().is_none());
+}
+
+/// Test that authors aren't matched to accounts that never verified the email
+#[tokio::test]
+async fn test_import_skips_unverified_accounts() {
+    let ctx = TestContext::new().await;
+    let redis = ctx.get_redis_client().await;
+
+    // Someone signed up with Bob's address without proving it's theirs
+    let squatter = create_unverified_user(&ctx, "bob@example.com").await;
+
+    let report = import(&ctx, false).await;
+    assert_eq!(report.users_matched, 0);
+    assert_eq!(report.unverified_matched, 0);
+    assert_eq!(report.users_created, 2);
+
+    // Bob's reply went to a placeholder that doesn't take over the email
+    let body = get_tree(&ctx, "/blog/hello").await;
+    let roots = body["tree"]["c"].as_array().unwrap();
+    let first = roots.iter().find(|c| c["t"] == "First post").unwrap();
+    assert_ne!(first["r"][0]["a"], squatter.id.to_string());
+    assert_eq!(
+        redis.get_user_by_email("bob@example.com").await.unwrap(),
+        Some(squatter.id)
+    );
+
+    // Matching them anyway takes an explicit option
+    let newer = DISQUS_EXPORT.replace("dsq:id=\"2\"", "dsq:id=\"7\"");
+    let options = ImportOptions {
+        match_unverified_emails: true,
+        ..Default::default()
+    };
+    let report = import_with(&ctx, &newer, &options).await;
+    assert_eq!(report.comments, 1);
+    assert_eq!(report.unverified_matched, 1);
+    let body = get_tree(&ctx, "/blog/hello").await;
+    let roots = body["tree"]["c"].as_array().unwrap();
+    let first = roots.iter().find(|c| c["t"] == "First post").unwrap();
+    let replies = first["r"].as_array().unwrap();
+    assert!(replies.iter().any(|r| r["a"] == squatter.id.to_string()));
+}
+
+/// Test that re-running an import reuses the placeholder of an author whose email
+/// belongs to an unverified account
+#[tokio::test]
+async fn test_import_is_idempotent_with_unverified_accounts() {
+    let ctx = TestContext::new().await;
+    let squatter = create_unverified_user(&ctx, "bob@example.com").await;
+
+    let first = import(&ctx, false).await;
+    assert_eq!(first.users_created, 2);
+    let body = get_tree(&ctx, "/blog/hello").await;
+    let roots = body["tree"]["c"].as_array().unwrap();
+    let first_post = roots.iter().find(|c| c["t"] == "First post").unwrap();
+    let bob_id = first_post["r"][0]["a"].clone();
+    assert_ne!(bob_id, squatter.id.to_string());
+
+    let second = import(&ctx, false).await;
+    assert_eq!(second.comments, 0);
+    assert_eq!(second.users_matched, 2);
+    assert_eq!(second.users_created, 0);
+
+    // New comments by Bob land on the same placeholder
+    let newer = DISQUS_EXPORT.replace("dsq:id=\"2\"", "dsq:id=\"7\"");
+    let third = import_export(&ctx, &newer, false).await;
+    assert_eq!(third.comments, 1);
+    assert_eq!(third.users_created, 0);
+    let body = get_tree(&ctx, "/blog/hello").await;
+    let roots = body["tree"]["c"].as_array().unwrap();
+    let first_post = roots.iter().find(|c| c["t"] == "First post").unwrap();
+    let replies = first_post["r"].as_array().unwrap();
+    assert_eq!(replies.len(), 2);
+    assert!(replies.iter().all(|r| r["a"] == bob_id));
+}
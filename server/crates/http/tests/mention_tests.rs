@@ -0,0 +1,162 @@
+mod common;
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use common::TestContext;
+use serde_json::json;
+
+const PAGE_URL: &str = "https://example.com/mention-page";
+
+/// Post a comment, optionally as a reply, and return the response body
+async fn post_comment(ctx: &TestContext, token: &str, content: &str, parent_id: Option<&str>) -> serde_json::Value {
+    let (key_name, key_value) = ctx.project_id_header();
+    let (auth_name, auth_value) = TestContext::auth_header(token);
+    let mut payload = json!({ "page_url": PAGE_URL, "content": content });
+    if let Some(parent_id) = parent_id {
+        payload["parent_path"] = json!([parent_id]);
+    }
+
+    let response = ctx
+        .server
+        .post("/v1/comments")
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value)
+        .json(&payload)
+        .await;
+    response.assert_status(StatusCode::OK);
+    response.json()
+}
+
+/// Notifications of a user, after giving the background writes time to land
+async fn notifications(ctx: &TestContext, token: &str) -> Vec<serde_json::Value> {
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let (key_name, key_value) = ctx.project_id_header();
+    let (auth_name, auth_value) = TestContext::auth_header(token);
+    let response = ctx
+        .server
+        .get("/v1/notifications")
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value)
+        .await;
+    response.assert_status(StatusCode::OK);
+    response.json::<serde_json::Value>()["notifications"]
+        .as_array()
+        .unwrap()
+        .clone()
+}
+
+#[tokio::test]
+async fn test_mention_notifies_and_links_user() {
+    let ctx = TestContext::new().await;
+    let alice = ctx.register_user("alice", "alice@example.com", "").await;
+    let bob = ctx.register_user("bob", "bob@example.com", "").await;
+    let bob_token = bob["token"].as_str().unwrap();
+    let alice_id = alice["user"]["id"].as_str().unwrap();
+
+    let body = post_comment(&ctx, bob_token, "What do you think, @Alice? cc @nobody", None).await;
+    let html = body["comment"]["h"].as_str().unwrap();
+    assert!(html.contains(&format!(r#"/v1/users/{}" class="threadkit-mention""#, alice_id)));
+    assert!(html.contains(">@Alice</a>"));
+    assert!(!html.contains(">@nobody</a>"));
+
+    let notifications = notifications(&ctx, alice["token"].as_str().unwrap()).await;
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0]["notification_type"], "mention");
+    assert_eq!(notifications[0]["from_user"]["name"], "bob");
+    assert_eq!(notifications[0]["comment_id"], body["comment"]["i"]);
+}
+
+#[tokio::test]
+async fn test_mention_of_replied_to_author_is_not_duplicated() {
+    let ctx = TestContext::new().await;
+    let alice = ctx.register_user("alice", "alice@example.com", "").await;
+    let alice_token = alice["token"].as_str().unwrap();
+    let bob = ctx.register_user("bob", "bob@example.com", "").await;
+    let bob_token = bob["token"].as_str().unwrap();
+
+    let parent = post_comment(&ctx, alice_token, "Original thought", None).await;
+    let parent_id = parent["comment"]["i"].as_str().unwrap();
+    post_comment(&ctx, bob_token, "@alice @alice I agree, and so do I (@bob)", Some(parent_id)).await;
+
+    let alice_notifications = notifications(&ctx, alice_token).await;
+    assert_eq!(alice_notifications.len(), 1);
+    assert_eq!(alice_notifications[0]["notification_type"], "reply");
+
+    // Mentioning yourself does nothing
+    assert!(notifications(&ctx, bob_token).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_mentions_are_capped_per_comment() {
+    let ctx = TestContext::new().await;
+    let bob = ctx.register_user("bob", "bob@example.com", "").await;
+    let bob_token = bob["token"].as_str().unwrap();
+
+    let mut tokens = Vec::new();
+    for i in 0..7 {
+        let user = ctx.register_user(&format!("user{}", i), &format!("user{}@example.com", i), "").await;
+        tokens.push(user["token"].as_str().unwrap().to_string());
+    }
+
+    let body = post_comment(&ctx, bob_token, "@user0 @user1 @user2 @user3 @user4 @user5 @user6", None).await;
+    let html = body["comment"]["h"].as_str().unwrap();
+    assert_eq!(html.matches("threadkit-mention").count(), 5);
+
+    let mut notified = 0;
+    for token in &tokens {
+        notified += notifications(&ctx, token).await.len();
+    }
+    assert_eq!(notified, 5);
+}
+
+#[tokio::test]
+async fn test_unknown_names_count_towards_the_cap() {
+    let ctx = TestContext::new().await;
+    let alice = ctx.register_user("alice", "alice@example.com", "").await;
+    let bob = ctx.register_user("bob", "bob@example.com", "").await;
+    let bob_token = bob["token"].as_str().unwrap();
+
+    let body = post_comment(&ctx, bob_token, "@nobody1 @nobody2 @nobody3 @nobody4 @nobody5 @alice", None).await;
+    let html = body["comment"]["h"].as_str().unwrap();
+    assert!(!html.contains("threadkit-mention"));
+
+    assert!(notifications(&ctx, alice["token"].as_str().unwrap()).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_mention_does_not_notify_users_who_blocked_the_author() {
+    let ctx = TestContext::new().await;
+    let alice = ctx.register_user("alice", "alice@example.com", "").await;
+    let alice_token = alice["token"].as_str().unwrap();
+    let bob = ctx.register_user("bob", "bob@example.com", "").await;
+    let bob_token = bob["token"].as_str().unwrap();
+    let bob_id = bob["user"]["id"].as_str().unwrap();
+
+    let (key_name, key_value) = ctx.project_id_header();
+    let (auth_name, auth_value) = TestContext::auth_header(alice_token);
+    ctx.server
+        .post(&format!("/v1/users/{}/block", bob_id))
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value)
+        .await
+        .assert_status(StatusCode::OK);
+
+    post_comment(&ctx, bob_token, "What do you think, @alice?", None).await;
+
+    assert!(notifications(&ctx, alice_token).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_mention_in_pending_comment_does_not_notify() {
+    let ctx = TestContext::new().await;
+    let alice = ctx.register_user("alice", "alice@example.com", "").await;
+    let bob = ctx.register_user("bob", "bob@example.com", "").await;
+    let bob_token = bob["token"].as_str().unwrap();
+
+    ctx.set_moderation_mode("pre_moderation").await;
+    post_comment(&ctx, bob_token, "Held for review, @alice", None).await;
+
+    assert!(notifications(&ctx, alice["token"].as_str().unwrap()).await.is_empty());
+}
@@ -0,0 +1,223 @@
+mod common;
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use common::TestContext;
+use serde_json::json;
+
+const PAGE_URL: &str = "https://example.com/notify-page";
+
+/// Post a comment, optionally as a reply, and return its ID
+async fn post_comment(ctx: &TestContext, token: &str, content: &str, parent_id: Option<&str>) -> String {
+    let (key_name, key_value) = ctx.project_id_header();
+    let (auth_name, auth_value) = TestContext::auth_header(token);
+    let mut payload = json!({ "page_url": PAGE_URL, "content": content });
+    if let Some(parent_id) = parent_id {
+        payload["parent_path"] = json!([parent_id]);
+    }
+
+    let response = ctx
+        .server
+        .post("/v1/comments")
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value)
+        .json(&payload)
+        .await;
+    response.assert_status(StatusCode::OK);
+    let body: serde_json::Value = response.json();
+    body["comment"]["i"].as_str().unwrap().to_string()
+}
+
+async fn set_email_notifications(ctx: &TestContext, token: &str, frequency: &str) -> axum_test::TestResponse {
+    let (key_name, key_value) = ctx.project_id_header();
+    let (auth_name, auth_value) = TestContext::auth_header(token);
+    ctx.server
+        .put("/v1/users/me")
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value)
+        .json(&json!({ "email_notifications": frequency }))
+        .await
+}
+
+/// Wait for the worker to write `count` emails to the outbox
+async fn wait_for_emails(ctx: &TestContext, count: usize) -> Vec<serde_json::Value> {
+    for _ in 0..50 {
+        let emails = ctx.sent_emails();
+        if emails.len() >= count {
+            return emails;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("timed out waiting for {} emails, got {:?}", count, ctx.sent_emails());
+}
+
+#[tokio::test]
+async fn test_reply_is_emailed_when_opted_in() {
+    let ctx = TestContext::new().await;
+    let author = ctx.register_user("author", "author@example.com", "").await;
+    let author_token = author["token"].as_str().unwrap();
+    let replier = ctx.register_user("replier", "replier@example.com", "").await;
+    let replier_token = replier["token"].as_str().unwrap();
+
+    let response = set_email_notifications(&ctx, author_token, "immediate").await;
+    response.assert_status(StatusCode::OK);
+    assert_eq!(response.json::<serde_json::Value>()["email_notifications"], "immediate");
+
+    let parent_id = post_comment(&ctx, author_token, "Original thought", None).await;
+    post_comment(&ctx, replier_token, "I disagree <strongly>", Some(&parent_id)).await;
+
+    let emails = wait_for_emails(&ctx, 1).await;
+    let email = &emails[0];
+    assert_eq!(email["to"], "author@example.com");
+    assert_eq!(email["subject"], "replier replied to your comment on Test Site");
+    assert!(email["text"].as_str().unwrap().contains("I disagree <strongly>"));
+    assert!(email["text"].as_str().unwrap().contains(PAGE_URL));
+    assert!(email["html"].as_str().unwrap().contains("I disagree &lt;strongly&gt;"));
+    assert!(email["unsubscribe_url"]
+        .as_str()
+        .unwrap()
+        .starts_with("http://localhost:8080/v1/notifications/unsubscribe?"));
+
+    // Already emailed notifications aren't sent again
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(ctx.sent_emails().len(), 1);
+}
+
+#[tokio::test]
+async fn test_replies_are_not_emailed_by_default() {
+    let ctx = TestContext::new().await;
+    let author = ctx.register_user("author", "author@example.com", "").await;
+    let author_token = author["token"].as_str().unwrap();
+    let replier = ctx.register_user("replier", "replier@example.com", "").await;
+    let replier_token = replier["token"].as_str().unwrap();
+
+    let parent_id = post_comment(&ctx, author_token, "Original thought", None).await;
+    post_comment(&ctx, replier_token, "A reply", Some(&parent_id)).await;
+
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(ctx.sent_emails().is_empty());
+}
+
+#[tokio::test]
+async fn test_pending_reply_is_emailed_once_approved() {
+    let ctx = TestContext::new().await;
+    let author = ctx.register_user("author", "author@example.com", "").await;
+    let author_token = author["token"].as_str().unwrap();
+    let replier = ctx.register_user("replier", "replier@example.com", "").await;
+    let replier_token = replier["token"].as_str().unwrap();
+    set_email_notifications(&ctx, author_token, "immediate").await.assert_status(StatusCode::OK);
+
+    let parent_id = post_comment(&ctx, author_token, "Original thought", None).await;
+    ctx.set_moderation_mode("pre_moderation").await;
+    let reply_id = post_comment(&ctx, replier_token, "Held for review", Some(&parent_id)).await;
+
+    // Nothing while the reply waits for a moderator
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(ctx.sent_emails().is_empty());
+    let (key_name, key_value) = ctx.project_id_header();
+    let (auth_name, auth_value) = TestContext::auth_header(author_token);
+    let notifications: serde_json::Value = ctx
+        .server
+        .get("/v1/notifications")
+        .add_header(key_name.clone(), key_value.clone())
+        .add_header(auth_name, auth_value)
+        .await
+        .json();
+    assert_eq!(notifications["notifications"], json!([]));
+
+    let moderator = ctx.register_user("moderator", "moderator@example.com", "").await;
+    ctx.set_user_role(moderator["user"]["id"].as_str().unwrap(), "moderator").await;
+    let (auth_name, auth_value) = TestContext::auth_header(moderator["token"].as_str().unwrap());
+    let page_id = threadkit_common::redis::RedisClient::generate_page_id(ctx.site_id, PAGE_URL);
+    ctx.server
+        .post(&format!("/v1/moderation/approve/{}", reply_id))
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value)
+        .json(&json!({ "page_id": page_id, "path": [parent_id, reply_id] }))
+        .await
+        .assert_status(StatusCode::OK);
+
+    let emails = wait_for_emails(&ctx, 1).await;
+    assert_eq!(emails[0]["to"], "author@example.com");
+    assert!(emails[0]["text"].as_str().unwrap().contains("Held for review"));
+}
+
+#[tokio::test]
+async fn test_site_can_turn_off_reply_emails() {
+    let ctx = TestContext::new().await;
+    ctx.update_site_settings(json!({
+        "email": { "reply_notifications": false }
+    }))
+    .await;
+
+    let author = ctx.register_user("author", "author@example.com", "").await;
+    let author_token = author["token"].as_str().unwrap();
+    let replier = ctx.register_user("replier", "replier@example.com", "").await;
+    let replier_token = replier["token"].as_str().unwrap();
+    set_email_notifications(&ctx, author_token, "immediate").await.assert_status(StatusCode::OK);
+
+    let parent_id = post_comment(&ctx, author_token, "Original thought", None).await;
+    post_comment(&ctx, replier_token, "A reply", Some(&parent_id)).await;
+
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(ctx.sent_emails().is_empty());
+}
+
+#[tokio::test]
+async fn test_unsubscribe_link() {
+    let ctx = TestContext::new().await;
+    let author = ctx.register_user("author", "author@example.com", "").await;
+    let author_token = author["token"].as_str().unwrap();
+    let author_id = author["user"]["id"].as_str().unwrap();
+    let replier = ctx.register_user("replier", "replier@example.com", "").await;
+    let replier_token = replier["token"].as_str().unwrap();
+    set_email_notifications(&ctx, author_token, "immediate").await.assert_status(StatusCode::OK);
+
+    let parent_id = post_comment(&ctx, author_token, "Original thought", None).await;
+    post_comment(&ctx, replier_token, "A reply", Some(&parent_id)).await;
+    let emails = wait_for_emails(&ctx, 1).await;
+    let url = emails[0]["unsubscribe_url"].as_str().unwrap();
+    let path = url.strip_prefix("http://localhost:8080").unwrap();
+
+    // A tampered link is refused
+    let response = ctx
+        .server
+        .get("/v1/notifications/unsubscribe")
+        .add_query_param("user_id", author_id)
+        .add_query_param("token", "00")
+        .await;
+    response.assert_status(StatusCode::FORBIDDEN);
+
+    // Opening the link only asks for confirmation
+    let response = ctx.server.get(path).await;
+    response.assert_status(StatusCode::OK);
+    assert!(response.text().contains("method=\"post\""));
+
+    let (key_name, key_value) = ctx.project_id_header();
+    let (auth_name, auth_value) = TestContext::auth_header(author_token);
+    let me: serde_json::Value = ctx
+        .server
+        .get("/v1/users/me")
+        .add_header(key_name.clone(), key_value.clone())
+        .add_header(auth_name.clone(), auth_value.clone())
+        .await
+        .json();
+    assert_eq!(me["email_notifications"], "immediate");
+
+    // The confirmation's (or a mail client's one-click) POST works without logging in
+    ctx.server.post(path).await.assert_status(StatusCode::OK);
+    let me: serde_json::Value = ctx
+        .server
+        .get("/v1/users/me")
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value)
+        .await
+        .json();
+    assert_eq!(me["email_notifications"], "off");
+
+    // No more emails after unsubscribing
+    post_comment(&ctx, replier_token, "Another reply", Some(&parent_id)).await;
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(ctx.sent_emails().len(), 1);
+}
@@ -0,0 +1,418 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::{TestContext, ADMIN_API_KEY};
+use serde_json::json;
+
+fn admin_header() -> (axum::http::HeaderName, axum::http::HeaderValue) {
+    TestContext::auth_header(ADMIN_API_KEY)
+}
+
+/// Create a site through the API and return it
+async fn create_site(ctx: &TestContext, body: serde_json::Value) -> serde_json::Value {
+    let (name, value) = admin_header();
+    let response = ctx.server.post("/v1/admin/sites").add_header(name, value).json(&body).await;
+    response.assert_status(StatusCode::OK);
+    response.json()
+}
+
+/// Status of a comment listing made with the given API key
+async fn key_status(ctx: &TestContext, key: &str) -> StatusCode {
+    ctx.server
+        .get("/v1/comments?page_url=test")
+        .add_header("projectid", key)
+        .await
+        .status_code()
+}
+
+#[tokio::test]
+async fn test_site_management_requires_admin_key() {
+    let ctx = TestContext::new_saas().await;
+
+    let response = ctx.server.get("/v1/admin/sites").await;
+    assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+
+    let (name, value) = TestContext::auth_header("wrong_key");
+    let response = ctx.server.get("/v1/admin/sites").add_header(name, value).await;
+    assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+
+    // A site's own secret key doesn't grant access either
+    let (name, value) = TestContext::auth_header(&ctx.secret_key);
+    let response = ctx.server.get("/v1/admin/sites").add_header(name, value).await;
+    assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_site_management_unavailable_in_standalone_mode() {
+    let ctx = TestContext::new().await;
+
+    let (name, value) = admin_header();
+    let response = ctx.server.get("/v1/admin/sites").add_header(name, value).await;
+    assert_eq!(response.status_code(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_create_list_get_and_delete_site() {
+    let ctx = TestContext::new_saas().await;
+
+    let site = create_site(
+        &ctx,
+        json!({ "name": "  Blog ", "domain": "Blog.Example.com", "moderation_mode": "pre" }),
+    )
+    .await;
+    let site_id = site["id"].as_str().unwrap();
+    assert_eq!(site["name"], "Blog");
+    assert_eq!(site["domain"], "blog.example.com");
+    assert_eq!(site["settings"]["moderation_mode"], "pre");
+    assert_eq!(site["settings"]["auth"]["email"], true);
+    let public_key = site["project_id_public"].as_str().unwrap();
+    assert!(public_key.starts_with("tk_pub_"));
+    assert!(site["project_id_secret"].as_str().unwrap().starts_with("tk_sec_"));
+
+    // The new keys work right away
+    assert_eq!(key_status(&ctx, public_key).await, StatusCode::OK);
+
+    // Listed next to the test site
+    let (name, value) = admin_header();
+    let response = ctx.server.get("/v1/admin/sites").add_header(name, value).await;
+    response.assert_status(StatusCode::OK);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["total"], 2);
+    assert_eq!(body["has_more"], false);
+    assert!(body["sites"].as_array().unwrap().iter().any(|s| s["id"] == site_id));
+
+    let (name, value) = admin_header();
+    let response = ctx.server.get("/v1/admin/sites?limit=1").add_header(name, value).await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["sites"].as_array().unwrap().len(), 1);
+    assert_eq!(body["has_more"], true);
+
+    let (name, value) = admin_header();
+    let response = ctx.server.get(&format!("/v1/admin/sites/{}", site_id)).add_header(name, value).await;
+    response.assert_status(StatusCode::OK);
+    assert_eq!(response.json::<serde_json::Value>()["project_id_public"], public_key);
+
+    // Deleting revokes the keys
+    let (name, value) = admin_header();
+    let response = ctx.server.delete(&format!("/v1/admin/sites/{}", site_id)).add_header(name, value).await;
+    assert_eq!(response.status_code(), StatusCode::NO_CONTENT);
+    assert_eq!(key_status(&ctx, public_key).await, StatusCode::UNAUTHORIZED);
+
+    let (name, value) = admin_header();
+    let response = ctx.server.get(&format!("/v1/admin/sites/{}", site_id)).add_header(name, value).await;
+    assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_create_site_validation() {
+    let ctx = TestContext::new_saas().await;
+
+    for body in [
+        json!({ "name": " ", "domain": "example.com" }),
+        json!({ "name": "Blog", "domain": "https://example.com/" }),
+        json!({ "name": "Blog", "domain": "example.com", "allowed_origins": ["http://app.example.com"] }),
+    ] {
+        let (name, value) = admin_header();
+        let response = ctx.server.post("/v1/admin/sites").add_header(name, value).json(&body).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST, "{}", body);
+    }
+}
+
+#[tokio::test]
+async fn test_update_site_settings_sections() {
+    let ctx = TestContext::new_saas().await;
+
+    // Cache the test site's key lookup, so the update has to invalidate it
+    assert_eq!(key_status(&ctx, &ctx.project_id).await, StatusCode::OK);
+
+    let (name, value) = admin_header();
+    let response = ctx
+        .server
+        .patch(&format!("/v1/admin/sites/{}", ctx.site_id))
+        .add_header(name, value)
+        .json(&json!({
+            "name": "Renamed",
+            "auth": { "google": false, "github": false, "email": true, "anonymous": true, "ethereum": false, "solana": false },
+            "rate_limits": { "ip_writes_per_minute": 20 },
+            "content_moderation": {
+                "enabled": true,
+                "confidence_threshold": 0.9,
+                "blocked_categories": {
+                    "hate_speech": true, "harassment": true, "sexual_content": false, "violence": true,
+                    "self_harm": true, "spam": true, "illegal_activity": true
+                },
+                "action": "queue"
+            },
+            "allowed_origins": ["*.Partner.org"],
+            "editing": { "edit_window_minutes": 0, "public_revisions": true },
+            "reactions": { "emojis": [" 👍 ", "🎉", "👍"] },
+            "email": { "sender_name": "  Partner Blog ", "reply_notifications": false, "mention_notifications": true }
+        }))
+        .await;
+    response.assert_status(StatusCode::OK);
+    let site: serde_json::Value = response.json();
+    assert_eq!(site["name"], "Renamed");
+    assert_eq!(site["domain"], "localhost");
+    assert_eq!(site["settings"]["auth"]["anonymous"], true);
+    assert_eq!(site["settings"]["rate_limits"]["ip_writes_per_minute"], 20);
+    assert_eq!(site["settings"]["content_moderation"]["action"], "queue");
+    assert_eq!(site["settings"]["allowed_origins"], json!(["*.partner.org"]));
+    // A zero edit window means no limit, like `--edit-site`
+    assert!(site["settings"]["editing"]["edit_window_minutes"].is_null());
+    assert_eq!(site["settings"]["editing"]["public_revisions"], true);
+    assert_eq!(site["settings"]["reactions"]["emojis"], json!(["👍", "🎉"]));
+    assert_eq!(site["settings"]["email"]["sender_name"], "Partner Blog");
+    assert_eq!(site["settings"]["email"]["reply_notifications"], false);
+    // Sections that weren't sent are unchanged
+    assert_eq!(site["settings"]["display"]["show_vote_counts"], true);
+
+    // The new origins apply right away
+    let response = ctx
+        .server
+        .get("/v1/comments?page_url=test")
+        .add_header("projectid", &ctx.project_id)
+        .add_header("Origin", "https://app.partner.org")
+        .await;
+    assert_eq!(response.status_code(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_update_site_rejects_invalid_settings() {
+    let ctx = TestContext::new_saas().await;
+
+    for body in [
+        json!({ "rate_limits": { "user_writes_per_minute": 0 } }),
+        json!({ "content_moderation": {
+            "enabled": true,
+            "confidence_threshold": 1.5,
+            "blocked_categories": {
+                "hate_speech": true, "harassment": true, "sexual_content": true, "violence": true,
+                "self_harm": true, "spam": true, "illegal_activity": true
+            },
+            "action": "reject"
+        } }),
+        // No TURNSTILE_SECRET_KEY on the test server
+        json!({ "turnstile": { "enabled": true, "enforce_on": "all" } }),
+        json!({ "domain": "" }),
+        json!({ "reactions": { "emojis": ["👍", " "] } }),
+        json!({ "reactions": { "emojis": (0..21).map(|i| i.to_string()).collect::<Vec<_>>() } }),
+        json!({ "email": { "sender_name": "Blog\r\nBcc: x@example.com" } }),
+    ] {
+        let (name, value) = admin_header();
+        let response = ctx
+            .server
+            .patch(&format!("/v1/admin/sites/{}", ctx.site_id))
+            .add_header(name, value)
+            .json(&body)
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST, "{}", body);
+    }
+
+    let (name, value) = admin_header();
+    let response = ctx
+        .server
+        .patch(&format!("/v1/admin/sites/{}", uuid::Uuid::now_v7()))
+        .add_header(name, value)
+        .json(&json!({ "name": "Nope" }))
+        .await;
+    assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_rotate_keys_with_grace_period() {
+    let ctx = TestContext::new_saas().await;
+    let old_public = ctx.project_id.clone();
+    let old_secret = ctx.secret_key.clone();
+
+    // Cache the old key lookups
+    assert_eq!(key_status(&ctx, &old_public).await, StatusCode::OK);
+
+    let (name, value) = admin_header();
+    let response = ctx
+        .server
+        .post(&format!("/v1/admin/sites/{}/rotate-keys", ctx.site_id))
+        .add_header(name, value)
+        .json(&json!({ "grace_period_seconds": 3600 }))
+        .await;
+    response.assert_status(StatusCode::OK);
+    let body: serde_json::Value = response.json();
+    assert!(body["previous_keys_expire_at"].is_string());
+    let new_public = body["site"]["project_id_public"].as_str().unwrap();
+    let new_secret = body["site"]["project_id_secret"].as_str().unwrap();
+    assert_ne!(new_public, old_public);
+    assert_ne!(new_secret, old_secret);
+
+    // Old and new keys both work during the grace period, and keep their type
+    assert_eq!(key_status(&ctx, new_public).await, StatusCode::OK);
+    assert_eq!(key_status(&ctx, &old_public).await, StatusCode::OK);
+
+    let response = ctx
+        .server
+        .get(&format!("/v1/admin/sites/{}/owner/comments", ctx.site_id))
+        .add_header("projectid", &old_public)
+        .await;
+    assert_eq!(response.status_code(), StatusCode::FORBIDDEN);
+    let response = ctx
+        .server
+        .get(&format!("/v1/admin/sites/{}/owner/comments", ctx.site_id))
+        .add_header("projectid", &old_secret)
+        .await;
+    assert_eq!(response.status_code(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_rotate_keys_without_grace_period() {
+    let ctx = TestContext::new_saas().await;
+    assert_eq!(key_status(&ctx, &ctx.project_id).await, StatusCode::OK);
+
+    let (name, value) = admin_header();
+    let response = ctx
+        .server
+        .post(&format!("/v1/admin/sites/{}/rotate-keys", ctx.site_id))
+        .add_header(name, value)
+        .json(&json!({ "keys": "public", "grace_period_seconds": 0 }))
+        .await;
+    response.assert_status(StatusCode::OK);
+    let body: serde_json::Value = response.json();
+    assert!(body["previous_keys_expire_at"].is_null());
+    assert_eq!(body["site"]["project_id_secret"], ctx.secret_key.as_str());
+
+    // The old public key is revoked even though its lookup was cached
+    assert_eq!(key_status(&ctx, &ctx.project_id).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(key_status(&ctx, &ctx.secret_key).await, StatusCode::OK);
+
+    let (name, value) = admin_header();
+    let response = ctx
+        .server
+        .post(&format!("/v1/admin/sites/{}/rotate-keys", ctx.site_id))
+        .add_header(name, value)
+        .json(&json!({ "grace_period_seconds": 30 * 24 * 3600 }))
+        .await;
+    assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+}
+
+/// Set the test site's plan through the API
+async fn set_plan(ctx: &TestContext, plan: serde_json::Value) {
+    let (name, value) = admin_header();
+    let response = ctx
+        .server
+        .patch(&format!("/v1/admin/sites/{}", ctx.site_id))
+        .add_header(name, value)
+        .json(&json!({ "plan": plan }))
+        .await;
+    response.assert_status(StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_monthly_comment_quota() {
+    let ctx = TestContext::new_saas().await;
+    let user = ctx.register_user("quota", "quota@example.com", "").await;
+    let token = user["token"].as_str().unwrap();
+    set_plan(&ctx, json!({ "tier": "free", "limits": { "monthly_comments": 10 } })).await;
+
+    // 8 of 10 used: the next comment is allowed and logs the soft-limit warning
+    let redis = ctx.get_redis_client().await;
+    redis.increment_usage(ctx.site_id, "comments", 8).await.unwrap();
+    let response = ctx.create_comment(token, "quota-page", "Still room", None).await;
+    response.assert_status(StatusCode::OK);
+
+    let month = threadkit_common::redis::RedisClient::usage_month();
+    let notice = format!("site:{}:quota_notice:{}:quota_warning:comments", ctx.site_id, month);
+    assert!(redis.exists(&notice).await.unwrap());
+
+    // At the limit the comment is refused
+    redis.increment_usage(ctx.site_id, "comments", 10).await.unwrap();
+    let response = ctx.create_comment(token, "quota-page", "Over the limit", None).await;
+    assert_eq!(response.status_code(), StatusCode::PAYMENT_REQUIRED);
+    assert!(response.text().contains("10 comments"));
+
+    // Sites without a tier have no limits
+    set_plan(&ctx, json!({})).await;
+    let response = ctx.create_comment(token, "quota-page", "Unlimited", None).await;
+    response.assert_status(StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_site_usage_report() {
+    let ctx = TestContext::new_saas().await;
+    set_plan(&ctx, json!({ "tier": "free", "owner_id": " acct_1 " })).await;
+
+    let redis = ctx.get_redis_client().await;
+    redis.increment_usage(ctx.site_id, "comments", 850).await.unwrap();
+    redis.increment_usage(ctx.site_id, "pageviews", 42).await.unwrap();
+    redis.increment_media_storage(ctx.site_id, 1024).await.unwrap();
+
+    let (name, value) = admin_header();
+    let response = ctx
+        .server
+        .get(&format!("/v1/admin/sites/{}/usage", ctx.site_id))
+        .add_header(name, value)
+        .await;
+    response.assert_status(StatusCode::OK);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["month"], threadkit_common::redis::RedisClient::usage_month());
+    assert_eq!(body["plan"]["owner_id"], "acct_1");
+    assert_eq!(body["limits"]["monthly_comments"], 1000);
+    assert_eq!(body["pageviews"], 42);
+    assert_eq!(
+        body["quotas"],
+        json!([
+            { "metric": "comments", "used": 850, "limit": 1000, "status": "warning" },
+            { "metric": "moderation_checks", "used": 0, "limit": 1000, "status": "ok" },
+            { "metric": "media_storage_bytes", "used": 1024, "limit": 104857600, "status": "ok" },
+            { "metric": "sites", "used": 1, "limit": 1, "status": "limit_reached" },
+        ])
+    );
+
+    // Other months have their own counters
+    let (name, value) = admin_header();
+    let response = ctx
+        .server
+        .get(&format!("/v1/admin/sites/{}/usage?month=2020-01", ctx.site_id))
+        .add_header(name, value)
+        .await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["quotas"][0]["used"], 0);
+
+    let (name, value) = admin_header();
+    let response = ctx
+        .server
+        .get(&format!("/v1/admin/sites/{}/usage?month=2020-13", ctx.site_id))
+        .add_header(name, value)
+        .await;
+    assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_max_sites_per_owner() {
+    let ctx = TestContext::new_saas().await;
+    let plan = json!({ "tier": "free", "owner_id": "acct_1" });
+
+    create_site(&ctx, json!({ "name": "First", "domain": "first.example.com", "plan": plan })).await;
+
+    // The free tier allows one site per owner
+    let (name, value) = admin_header();
+    let response = ctx
+        .server
+        .post("/v1/admin/sites")
+        .add_header(name, value)
+        .json(&json!({ "name": "Second", "domain": "second.example.com", "plan": plan }))
+        .await;
+    assert_eq!(response.status_code(), StatusCode::PAYMENT_REQUIRED);
+
+    // Moving the test site to that owner is refused too, other owners are fine
+    let (name, value) = admin_header();
+    let response = ctx
+        .server
+        .patch(&format!("/v1/admin/sites/{}", ctx.site_id))
+        .add_header(name, value)
+        .json(&json!({ "plan": plan }))
+        .await;
+    assert_eq!(response.status_code(), StatusCode::PAYMENT_REQUIRED);
+
+    create_site(
+        &ctx,
+        json!({ "name": "Second", "domain": "second.example.com", "plan": { "tier": "pro", "owner_id": "acct_2" } }),
+    )
+    .await;
+}
@@ -0,0 +1,280 @@
+mod common;
+
+use std::time::Duration;
+
+use common::TestContext;
+use threadkit_common::redis::RedisClient;
+use uuid::Uuid;
+
+const PAGE_URL: &str = "https://example.com/sse-page";
+
+/// A Server-Sent Events stream read over a real HTTP connection
+struct SseStream {
+    response: reqwest::Response,
+    buffer: String,
+}
+
+impl SseStream {
+    async fn open(ctx: &TestContext, page_id: Uuid, headers: &[(&str, &str)]) -> reqwest::Response {
+        let url = ctx
+            .server
+            .server_url(&format!("/v1/pages/{}/events?project_id={}", page_id, ctx.project_id))
+            .expect("server url");
+        let mut request = reqwest::Client::new().get(url);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.send().await.expect("open event stream")
+    }
+
+    async fn connect(ctx: &TestContext, page_id: Uuid, headers: &[(&str, &str)]) -> Self {
+        let response = Self::open(ctx, page_id, headers).await;
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert!(response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/event-stream"));
+        Self { response, buffer: String::new() }
+    }
+
+    /// Next event's id and JSON data, skipping keep-alive comments
+    async fn next_event(&mut self) -> (Option<String>, serde_json::Value) {
+        loop {
+            if let Some(end) = self.buffer.find("\n\n") {
+                let block: String = self.buffer.drain(..end + 2).collect();
+                let mut id = None;
+                let mut data = None;
+                for line in block.lines() {
+                    if let Some(v) = line.strip_prefix("id:") {
+                        id = Some(v.trim().to_string());
+                    } else if let Some(v) = line.strip_prefix("data:") {
+                        data = Some(v.trim().to_string());
+                    }
+                }
+                if let Some(data) = data {
+                    return (id, serde_json::from_str(&data).expect("event data is JSON"));
+                }
+                continue;
+            }
+
+            let chunk = tokio::time::timeout(Duration::from_secs(5), self.response.chunk())
+                .await
+                .expect("timed out waiting for event")
+                .expect("read event stream")
+                .expect("event stream ended");
+            self.buffer.push_str(&String::from_utf8_lossy(&chunk));
+        }
+    }
+
+    /// Skip events until one with the given method
+    async fn wait_for_method(&mut self, method: &str) -> (Option<String>, serde_json::Value) {
+        loop {
+            let (id, event) = self.next_event().await;
+            if event["method"] == method {
+                return (id, event);
+            }
+        }
+    }
+}
+
+/// Record the test page as the site's, as its first comment would
+async fn create_page(ctx: &TestContext) -> Uuid {
+    let page_id = RedisClient::generate_page_id(ctx.site_id, PAGE_URL);
+    ctx.get_redis_client()
+        .await
+        .set_page_url(ctx.site_id, page_id, PAGE_URL)
+        .await
+        .expect("register page");
+    page_id
+}
+
+#[tokio::test]
+async fn test_sse_streams_new_comments() {
+    let ctx = TestContext::new_with_http_transport().await;
+    let page_id = create_page(&ctx).await;
+
+    let user = ctx.register_user("Streamer", "streamer@example.com", "").await;
+    let token = user["token"].as_str().unwrap();
+
+    let mut stream = SseStream::connect(&ctx, page_id, &[]).await;
+    let (_, connected) = stream.wait_for_method("connected").await;
+    assert!(connected["params"]["user_id"].is_null());
+
+    // Give the subscriber a moment before publishing
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let response = ctx.create_comment(token, PAGE_URL, "Hello over SSE", None).await;
+    assert_eq!(response.status_code(), 200);
+
+    let (id, event) = stream.wait_for_method("new_comment").await;
+    assert_eq!(event["jsonrpc"], "2.0");
+    assert_eq!(event["params"]["page_id"], page_id.to_string());
+    assert_eq!(event["params"]["seq"], 1);
+    assert_eq!(id.as_deref(), Some("1"));
+}
+
+#[tokio::test]
+async fn test_sse_resumes_from_last_event_id() {
+    let ctx = TestContext::new_with_http_transport().await;
+    let page_id = RedisClient::generate_page_id(ctx.site_id, PAGE_URL);
+
+    let user = ctx.register_user("Writer", "writer@example.com", "").await;
+    let token = user["token"].as_str().unwrap();
+    for content in ["First", "Second", "Third"] {
+        let response = ctx.create_comment(token, PAGE_URL, content, None).await;
+        assert_eq!(response.status_code(), 200);
+    }
+
+    // Missed events after the last one the client saw are replayed in order
+    let mut stream = SseStream::connect(&ctx, page_id, &[("Last-Event-ID", "1")]).await;
+    stream.wait_for_method("connected").await;
+    let (id, event) = stream.next_event().await;
+    assert_eq!(id.as_deref(), Some("2"));
+    assert_eq!(event["method"], "new_comment");
+    let (id, _) = stream.next_event().await;
+    assert_eq!(id.as_deref(), Some("3"));
+}
+
+#[tokio::test]
+async fn test_sse_authenticated_stream() {
+    let ctx = TestContext::new_with_http_transport().await;
+    let page_id = create_page(&ctx).await;
+
+    let user = ctx.register_user("Reader", "reader@example.com", "").await;
+    let token = user["token"].as_str().unwrap();
+    let auth = format!("Bearer {}", token);
+
+    let mut stream = SseStream::connect(&ctx, page_id, &[("Authorization", &auth)]).await;
+    let (_, connected) = stream.wait_for_method("connected").await;
+    assert_eq!(connected["params"]["user_id"], user["user"]["id"]);
+
+    // Invalid tokens are rejected rather than streamed anonymously
+    let response = SseStream::open(&ctx, page_id, &[("Authorization", "Bearer not-a-token")]).await;
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_sse_stream_ticket() {
+    let ctx = TestContext::new_with_http_transport().await;
+    let page_id = create_page(&ctx).await;
+
+    let user = ctx.register_user("Ticketed", "ticketed@example.com", "").await;
+    let token = user["token"].as_str().unwrap();
+    let (auth_name, auth_value) = TestContext::auth_header(token);
+    let response = ctx.server.post("/v1/events/ticket").add_header(auth_name, auth_value).await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["expires_in"], 60);
+    let ticket = body["ticket"].as_str().unwrap();
+
+    let url = ctx
+        .server
+        .server_url(&format!("/v1/pages/{}/events?project_id={}&ticket={}", page_id, ctx.project_id, ticket))
+        .expect("server url");
+    let response = reqwest::get(url.clone()).await.expect("open event stream");
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let mut stream = SseStream { response, buffer: String::new() };
+    let (_, connected) = stream.wait_for_method("connected").await;
+    assert_eq!(connected["params"]["user_id"], user["user"]["id"]);
+
+    // Tickets only work once
+    let response = reqwest::get(url).await.expect("request");
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    // Access tokens in the URL are ignored
+    let url = ctx
+        .server
+        .server_url(&format!("/v1/pages/{}/events?project_id={}&token={}", page_id, ctx.project_id, token))
+        .expect("server url");
+    let response = reqwest::get(url).await.expect("open event stream");
+    let mut stream = SseStream { response, buffer: String::new() };
+    let (_, connected) = stream.wait_for_method("connected").await;
+    assert!(connected["params"]["user_id"].is_null());
+}
+
+#[tokio::test]
+async fn test_sse_validates_project_id_and_origin() {
+    let ctx = TestContext::new_with_http_transport().await;
+    let page_id = create_page(&ctx).await;
+
+    let url = ctx
+        .server
+        .server_url(&format!("/v1/pages/{}/events", page_id))
+        .expect("server url");
+    let response = reqwest::get(url.clone()).await.expect("request");
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let response = reqwest::get(format!("{}?project_id=tk_pub_invalid", url))
+        .await
+        .expect("request");
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let response = SseStream::open(&ctx, page_id, &[("Origin", "https://evil.example.org")]).await;
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+
+    let response = SseStream::open(&ctx, page_id, &[("Origin", "http://localhost:3000")]).await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_sse_rejects_other_sites_pages() {
+    let ctx = TestContext::new_with_http_transport().await;
+
+    // Never loaded on this site
+    let response = SseStream::open(&ctx, Uuid::now_v7(), &[]).await;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    // Another site's page
+    let other_site = Uuid::now_v7();
+    let page_id = RedisClient::generate_page_id(other_site, PAGE_URL);
+    ctx.get_redis_client()
+        .await
+        .set_page_url(other_site, page_id, PAGE_URL)
+        .await
+        .expect("register page");
+    let response = SseStream::open(&ctx, page_id, &[]).await;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+/// Load a page's comments, return its ID once the page is registered (in the background)
+async fn view_page(ctx: &TestContext, page_url: &str) -> Uuid {
+    let (key_name, key_value) = ctx.project_id_header();
+    ctx.server
+        .get("/v1/comments")
+        .add_query_param("page_url", page_url)
+        .add_header(key_name, key_value)
+        .await
+        .assert_status_ok();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    RedisClient::generate_page_id(ctx.site_id, page_url)
+}
+
+#[tokio::test]
+async fn test_sse_page_without_comments() {
+    let ctx = TestContext::new_with_http_transport().await;
+
+    // Loading a page without comments lets it be subscribed to, so its first comment arrives live
+    let page_id = view_page(&ctx, PAGE_URL).await;
+    let mut stream = SseStream::connect(&ctx, page_id, &[]).await;
+    stream.wait_for_method("connected").await;
+
+    let user = ctx.register_user("First", "first@example.com", "").await;
+    let token = user["token"].as_str().unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let response = ctx.create_comment(token, PAGE_URL, "First!", None).await;
+    assert_eq!(response.status_code(), 200);
+    let (_, event) = stream.wait_for_method("new_comment").await;
+    assert_eq!(event["params"]["page_id"], page_id.to_string());
+
+    // Reads stop registering pages once the site has its fill of them
+    let client = redis::Client::open(ctx.get_redis_url().await).unwrap();
+    let mut conn = client.get_multiplexed_async_connection().await.unwrap();
+    redis::cmd("SET")
+        .arg(format!("site:{}:viewed_pages", ctx.site_id))
+        .arg(10_000)
+        .query_async::<()>(&mut conn)
+        .await
+        .unwrap();
+    let page_id = view_page(&ctx, "https://example.com/one-too-many").await;
+    let response = SseStream::open(&ctx, page_id, &[]).await;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,358 @@
+mod common;
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::post,
+    Router,
+};
+use common::TestContext;
+use serde_json::json;
+use std::sync::{
+    atomic::{AtomicU16, Ordering},
+    Arc, Mutex,
+};
+use tokio::net::TcpListener;
+
+use threadkit_common::{config::WebhookConfig, webhooks, WebhookDispatcher};
+
+/// A request received by the stand-in endpoint
+#[derive(Clone)]
+struct Received {
+    headers: HeaderMap,
+    body: String,
+}
+
+/// Local HTTP server standing in for a customer's webhook endpoint
+#[derive(Clone)]
+struct StandIn {
+    received: Arc<Mutex<Vec<Received>>>,
+    /// Status code returned to the dispatcher
+    status: Arc<AtomicU16>,
+}
+
+impl StandIn {
+    /// Start the stand-in and return it with its URL
+    async fn start() -> (Self, String) {
+        let stand_in = StandIn {
+            received: Arc::new(Mutex::new(vec![])),
+            status: Arc::new(AtomicU16::new(200)),
+        };
+
+        async fn receive(
+            State(stand_in): State<StandIn>,
+            headers: HeaderMap,
+            body: Bytes,
+        ) -> StatusCode {
+            stand_in.received.lock().unwrap().push(Received {
+                headers,
+                body: String::from_utf8_lossy(&body).to_string(),
+            });
+            StatusCode::from_u16(stand_in.status.load(Ordering::SeqCst)).unwrap()
+        }
+
+        let app = Router::new()
+            .route("/hook", post(receive))
+            .with_state(stand_in.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        (stand_in, format!("http://{}/hook", addr))
+    }
+
+    fn received(&self) -> Vec<Received> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+/// Register an admin and return their token
+async fn admin_token(ctx: &TestContext) -> String {
+    let auth = ctx.register_user("admin", "admin@example.com", "password123").await;
+    let user_id = auth["user"]["id"].as_str().unwrap();
+    ctx.set_user_role(user_id, "admin").await;
+    auth["token"].as_str().unwrap().to_string()
+}
+
+async fn set_webhooks(ctx: &TestContext, token: &str, webhooks: serde_json::Value) {
+    let (key_name, key_value) = ctx.project_id_header();
+    let (auth_name, auth_value) = TestContext::auth_header(token);
+
+    ctx.server
+        .put(&format!("/v1/admin/sites/{}/webhooks", ctx.site_id))
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value)
+        .json(&json!({ "webhooks": webhooks }))
+        .await
+        .assert_status_ok();
+}
+
+async fn list_deliveries(ctx: &TestContext, token: &str) -> Vec<serde_json::Value> {
+    let (key_name, key_value) = ctx.project_id_header();
+    let (auth_name, auth_value) = TestContext::auth_header(token);
+
+    let response = ctx
+        .server
+        .get(&format!("/v1/admin/sites/{}/webhooks/deliveries", ctx.site_id))
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value)
+        .await;
+    response.assert_status_ok();
+    response.json::<serde_json::Value>()["deliveries"]
+        .as_array()
+        .unwrap()
+        .clone()
+}
+
+/// Deliveries are queued in the background - wait until `count` show up
+async fn wait_for_deliveries(ctx: &TestContext, token: &str, count: usize) -> Vec<serde_json::Value> {
+    for _ in 0..50 {
+        let deliveries = list_deliveries(ctx, token).await;
+        if deliveries.len() >= count {
+            return deliveries;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("Expected {} webhook deliveries", count);
+}
+
+async fn dispatcher(ctx: &TestContext, max_attempts: u32) -> WebhookDispatcher {
+    let redis = Arc::new(ctx.get_redis_client().await);
+    WebhookDispatcher::new(
+        redis,
+        WebhookConfig {
+            max_attempts,
+            base_backoff_seconds: 0, // Retry immediately
+            allow_private_targets: true,
+            ..WebhookConfig::default()
+        },
+    )
+    .unwrap()
+}
+
+/// Test that a comment is delivered with a valid signature
+#[tokio::test]
+async fn test_webhook_delivered_with_signature() {
+    let ctx = TestContext::new().await;
+    let (stand_in, url) = StandIn::start().await;
+    let token = admin_token(&ctx).await;
+
+    set_webhooks(&ctx, &token, json!([{ "url": url, "events": ["comment_created"] }])).await;
+
+    ctx.create_comment(&token, "https://example.com/page1", "Hello webhooks", None)
+        .await
+        .assert_status_ok();
+
+    wait_for_deliveries(&ctx, &token, 1).await;
+    let attempted = dispatcher(&ctx, 3).await.process_due().await.unwrap();
+    assert_eq!(attempted, 1);
+
+    let received = stand_in.received();
+    assert_eq!(received.len(), 1);
+    let request = &received[0];
+
+    assert_eq!(request.headers[webhooks::EVENT_HEADER], "comment_created");
+
+    // Signature header is `t=...,v1=...` over "{t}.{body}" with the site secret
+    let signature = request.headers[webhooks::SIGNATURE_HEADER].to_str().unwrap();
+    let (t, v1) = signature.split_once(',').unwrap();
+    let timestamp: i64 = t.strip_prefix("t=").unwrap().parse().unwrap();
+    assert_eq!(
+        v1.strip_prefix("v1=").unwrap(),
+        webhooks::sign_payload(&ctx.secret_key, timestamp, &request.body)
+    );
+
+    let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+    assert_eq!(body["event"], "comment_created");
+    assert_eq!(body["data"]["content_preview"], "Hello webhooks");
+    assert_eq!(
+        body["id"].as_str().unwrap(),
+        request.headers[webhooks::DELIVERY_HEADER].to_str().unwrap()
+    );
+    // Private fields are not sent
+    assert!(!request.body.contains("admin@example.com"));
+
+    let deliveries = list_deliveries(&ctx, &token).await;
+    assert_eq!(deliveries[0]["status"], "succeeded");
+    assert_eq!(deliveries[0]["attempts"], 1);
+    assert_eq!(deliveries[0]["last_status_code"], 200);
+}
+
+/// Test that endpoints only receive the events they subscribed to
+#[tokio::test]
+async fn test_webhook_event_filter() {
+    let ctx = TestContext::new().await;
+    let (stand_in, url) = StandIn::start().await;
+    let token = admin_token(&ctx).await;
+
+    set_webhooks(&ctx, &token, json!([{ "url": url, "events": ["user_banned"] }])).await;
+
+    ctx.create_comment(&token, "https://example.com/page1", "Not delivered", None)
+        .await
+        .assert_status_ok();
+
+    // Ban a user - this one is delivered
+    let user = ctx.register_user("user1", "user1@example.com", "password123").await;
+    let user_id = user["user"]["id"].as_str().unwrap();
+    let (key_name, key_value) = ctx.project_id_header();
+    let (auth_name, auth_value) = TestContext::auth_header(&token);
+    ctx.server
+        .post(&format!("/v1/moderation/ban/{}", user_id))
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value)
+        .json(&json!({}))
+        .await
+        .assert_status_ok();
+
+    let deliveries = wait_for_deliveries(&ctx, &token, 1).await;
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0]["event"], "user_banned");
+
+    dispatcher(&ctx, 3).await.process_due().await.unwrap();
+    let received = stand_in.received();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].headers[webhooks::EVENT_HEADER], "user_banned");
+}
+
+/// Test that failing deliveries are retried, marked failed, and can be replayed
+#[tokio::test]
+async fn test_webhook_retry_and_replay() {
+    let ctx = TestContext::new().await;
+    let (stand_in, url) = StandIn::start().await;
+    let token = admin_token(&ctx).await;
+
+    set_webhooks(&ctx, &token, json!([{ "url": url }])).await;
+    stand_in.status.store(500, Ordering::SeqCst);
+
+    ctx.create_comment(&token, "https://example.com/page1", "Flaky endpoint", None)
+        .await
+        .assert_status_ok();
+    wait_for_deliveries(&ctx, &token, 1).await;
+
+    // Two attempts, both fail
+    let dispatcher = dispatcher(&ctx, 2).await;
+    assert_eq!(dispatcher.process_due().await.unwrap(), 1);
+    let deliveries = list_deliveries(&ctx, &token).await;
+    assert_eq!(deliveries[0]["status"], "pending");
+    assert_eq!(deliveries[0]["last_status_code"], 500);
+
+    assert_eq!(dispatcher.process_due().await.unwrap(), 1);
+    let deliveries = list_deliveries(&ctx, &token).await;
+    assert_eq!(deliveries[0]["status"], "failed");
+    assert_eq!(deliveries[0]["attempts"], 2);
+
+    // Nothing left in the queue
+    assert_eq!(dispatcher.process_due().await.unwrap(), 0);
+    assert_eq!(stand_in.received().len(), 2);
+
+    // Endpoint recovers, replay the failed delivery
+    stand_in.status.store(200, Ordering::SeqCst);
+    let delivery_id = deliveries[0]["id"].as_str().unwrap();
+    let (key_name, key_value) = ctx.project_id_header();
+    let (auth_name, auth_value) = TestContext::auth_header(&token);
+    let response = ctx
+        .server
+        .post(&format!(
+            "/v1/admin/sites/{}/webhooks/deliveries/{}/replay",
+            ctx.site_id, delivery_id
+        ))
+        .add_header(key_name.clone(), key_value.clone())
+        .add_header(auth_name.clone(), auth_value.clone())
+        .await;
+    response.assert_status_ok();
+    assert_eq!(response.json::<serde_json::Value>()["status"], "pending");
+
+    // Replaying a queued delivery is rejected
+    ctx.server
+        .post(&format!(
+            "/v1/admin/sites/{}/webhooks/deliveries/{}/replay",
+            ctx.site_id, delivery_id
+        ))
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value)
+        .await
+        .assert_status(StatusCode::CONFLICT);
+
+    assert_eq!(dispatcher.process_due().await.unwrap(), 1);
+    let deliveries = list_deliveries(&ctx, &token).await;
+    assert_eq!(deliveries[0]["status"], "succeeded");
+
+    // Same delivery ID on every attempt
+    let received = stand_in.received();
+    assert_eq!(received.len(), 3);
+    assert!(received
+        .iter()
+        .all(|r| r.headers[webhooks::DELIVERY_HEADER] == delivery_id));
+}
+
+/// Test that deliveries to private addresses are refused unless allowed
+#[tokio::test]
+async fn test_webhook_private_target_refused() {
+    let ctx = TestContext::new().await;
+    let (stand_in, url) = StandIn::start().await;
+    let token = admin_token(&ctx).await;
+
+    // The test context allows private targets, so the endpoint can be saved
+    set_webhooks(&ctx, &token, json!([{ "url": url }])).await;
+    ctx.create_comment(&token, "https://example.com/page1", "Internal", None)
+        .await
+        .assert_status_ok();
+    wait_for_deliveries(&ctx, &token, 1).await;
+
+    let dispatcher = WebhookDispatcher::new(
+        Arc::new(ctx.get_redis_client().await),
+        WebhookConfig { max_attempts: 1, ..WebhookConfig::default() },
+    )
+    .unwrap();
+    assert_eq!(dispatcher.process_due().await.unwrap(), 1);
+
+    assert!(stand_in.received().is_empty());
+    let deliveries = list_deliveries(&ctx, &token).await;
+    assert_eq!(deliveries[0]["status"], "failed");
+    assert!(deliveries[0]["last_error"].as_str().unwrap().contains("private address"));
+}
+
+/// Test that only admins can manage webhooks and URLs are validated
+#[tokio::test]
+async fn test_webhook_admin_access() {
+    let ctx = TestContext::new().await;
+    let admin = admin_token(&ctx).await;
+    let user = ctx.register_user("user1", "user1@example.com", "password123").await;
+    let user_token = user["token"].as_str().unwrap();
+
+    let (key_name, key_value) = ctx.project_id_header();
+
+    let (auth_name, auth_value) = TestContext::auth_header(user_token);
+    ctx.server
+        .get(&format!("/v1/admin/sites/{}/webhooks/deliveries", ctx.site_id))
+        .add_header(key_name.clone(), key_value.clone())
+        .add_header(auth_name, auth_value)
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+
+    let (auth_name, auth_value) = TestContext::auth_header(&admin);
+    ctx.server
+        .put(&format!("/v1/admin/sites/{}/webhooks", ctx.site_id))
+        .add_header(key_name.clone(), key_value.clone())
+        .add_header(auth_name.clone(), auth_value.clone())
+        .json(&json!({ "webhooks": [{ "url": "ftp://example.com/hook" }] }))
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+
+    set_webhooks(&ctx, &admin, json!([{ "url": "https://example.com/hook" }])).await;
+
+    let response = ctx
+        .server
+        .get(&format!("/v1/admin/sites/{}/webhooks", ctx.site_id))
+        .add_header(key_name, key_value)
+        .add_header(auth_name, auth_value)
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["webhooks"][0]["url"], "https://example.com/hook");
+    assert_eq!(body["webhooks"][0]["enabled"], true);
+    assert!(body["webhooks"][0]["id"].is_string());
+}
@@ -14,6 +14,7 @@ use uuid::Uuid;
 
 use threadkit_common::redis::RedisClient;
 use threadkit_common::types::{ProjectIdInfo, User};
+use threadkit_common::Storage;
 
 /// Pending read request with response channel
 struct PendingRead<T> {
@@ -26,6 +27,10 @@ struct PendingRead<T> {
 /// This dramatically reduces Redis round-trips while adding minimal latency.
 pub struct RedisBatcher {
     redis: Arc<RedisClient>,
+    /// User lookups go through the configured storage backend
+    store: Arc<dyn Storage>,
+    /// This server's node ID, presence entries are owned by it
+    node_id: Uuid,
     flush_interval_ms: u64,
 
     // === WRITES (fire and forget) ===
@@ -60,9 +65,16 @@ pub struct RedisBatcher {
 
 impl RedisBatcher {
     /// Create a new batcher with the given flush interval
-    pub fn new(redis: Arc<RedisClient>, flush_interval_ms: u64) -> Arc<Self> {
+    pub fn new(
+        redis: Arc<RedisClient>,
+        store: Arc<dyn Storage>,
+        node_id: Uuid,
+        flush_interval_ms: u64,
+    ) -> Arc<Self> {
         Arc::new(Self {
             redis,
+            store,
+            node_id,
             flush_interval_ms,
             presence_add: DashMap::new(),
             presence_remove: DashMap::new(),
@@ -164,7 +176,7 @@ impl RedisBatcher {
         rx.await.ok().flatten()
     }
 
-    /// Get presence for a page (batched read)
+    /// Get presence for a page across all live nodes (batched read)
     pub async fn get_presence(&self, page_id: Uuid) -> HashSet<Uuid> {
         let (tx, rx) = oneshot::channel();
 
@@ -350,7 +362,7 @@ impl RedisBatcher {
         // Execute presence adds
         for (page_id, user_ids) in presence_adds {
             for user_id in user_ids {
-                if let Err(e) = self.redis.add_presence(page_id, user_id).await {
+                if let Err(e) = self.redis.add_presence(page_id, user_id, self.node_id).await {
                     tracing::warn!("Failed to add presence: {}", e);
                 }
             }
@@ -359,7 +371,7 @@ impl RedisBatcher {
         // Execute presence removes
         for (page_id, user_ids) in presence_removes {
             for user_id in user_ids {
-                if let Err(e) = self.redis.remove_presence(page_id, user_id).await {
+                if let Err(e) = self.redis.remove_presence(page_id, user_id, self.node_id).await {
                     tracing::warn!("Failed to remove presence: {}", e);
                 }
             }
@@ -381,20 +393,22 @@ impl RedisBatcher {
             }
         }
 
-        // Execute analytics - message counts
-        for ((site_id, hour_key), count) in message_counts {
-            let key = format!("threadkit:stats:{}:messages:{}", site_id, hour_key);
-            if let Err(e) = self.redis.increment_usage(site_id, &key, count as i64).await {
+        // Execute analytics - message counts (the batch is flushed within the hour it was queued in)
+        let mut site_messages: HashMap<Uuid, u64> = HashMap::new();
+        for ((site_id, _hour_key), count) in message_counts {
+            *site_messages.entry(site_id).or_default() += count;
+        }
+        for (site_id, count) in site_messages {
+            if let Err(e) = self.redis.increment_analytics(site_id, "websocket_messages", count as i64).await {
                 tracing::warn!("Failed to increment message count: {}", e);
             }
         }
 
-        // Execute analytics - unique users (using sets for now, could use HyperLogLog)
-        for ((site_id, hour_key), user_ids) in unique_users {
+        // Execute analytics - unique users (counted as visitors)
+        for ((site_id, _hour_key), user_ids) in unique_users {
             for user_id in user_ids {
-                let key = format!("threadkit:stats:{}:users:{}", site_id, hour_key);
                 if let Err(e) = self.redis.add_unique_visitor(site_id, &user_id.to_string()).await {
-                    tracing::warn!("Failed to add unique user to {}: {}", key, e);
+                    tracing::warn!("Failed to add unique visitor: {}", e);
                 }
             }
         }
@@ -427,7 +441,7 @@ impl RedisBatcher {
 
         // Execute user lookups
         for (user_id, pending) in user_requests {
-            let result = self.redis.get_user(user_id).await.ok().flatten();
+            let result = self.store.get_user(user_id).await.ok().flatten();
             for p in pending {
                 let _ = p.tx.send(result.clone());
             }
@@ -0,0 +1,90 @@
+//! Coordination between WebSocket nodes sharing a Redis instance.
+//!
+//! Each node sends a heartbeat every `HEARTBEAT_INTERVAL` with its per-site
+//! connection counts and metrics. Presence entries are owned by the node that
+//! added them, so when a node stops sending heartbeats (crash, lost network)
+//! the other nodes reap its entries and tell the page's subscribers its users
+//! left. Connection counts and metrics are summed over the live nodes, and the
+//! summed counts are sampled into each site's peak connections analytics.
+
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::state::WsState;
+
+/// How often each node sends a heartbeat and looks for dead nodes
+///
+/// Well under `WS_NODE_TTL_MS`, so a few missed heartbeats don't get a live node reaped.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Start the heartbeat and reaper loop
+///
+/// The first heartbeat is sent immediately so the node's presence entries count right away.
+pub fn start(state: WsState) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let mut registered = false;
+        loop {
+            interval.tick().await;
+            registered = heartbeat(&state, registered).await;
+            reap(&state).await;
+            record_peak_connections(&state).await;
+        }
+    })
+}
+
+/// Send this node's heartbeat, returns whether the node is registered afterwards
+async fn heartbeat(state: &WsState, registered: bool) -> bool {
+    let connections: HashMap<Uuid, u64> = state
+        .connections_per_site
+        .iter()
+        .map(|entry| (*entry.key(), entry.value().load(Ordering::Relaxed)))
+        .filter(|(_, count)| *count > 0)
+        .collect();
+    let metrics = state.get_metrics().counters();
+
+    match state.redis.ws_node_heartbeat(state.node_id, &connections, &metrics).await {
+        Ok(was_registered) => {
+            if registered && !was_registered {
+                tracing::warn!(
+                    "WebSocket node {} missed its heartbeats and was reaped, open connections show up in presence again when they resubscribe",
+                    state.node_id
+                );
+            }
+            true
+        }
+        Err(e) => {
+            tracing::warn!("Failed to send WebSocket node heartbeat: {}", e);
+            registered
+        }
+    }
+}
+
+/// Raise the sites' peak concurrent connections in their analytics to the cluster-wide counts
+///
+/// Every node does this, so the peak is sampled once per heartbeat interval per node.
+async fn record_peak_connections(state: &WsState) {
+    let result = match state.redis.get_cluster_connections().await {
+        Ok(connections) => state.redis.record_peak_connections(&connections).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        tracing::warn!("Failed to record peak WebSocket connections: {}", e);
+    }
+}
+
+/// Clean up after nodes that stopped sending heartbeats
+async fn reap(state: &WsState) {
+    match state.redis.reap_ws_nodes().await {
+        Ok(reaped) => {
+            for node_id in reaped {
+                tracing::info!("Reaped presence of dead WebSocket node {}", node_id);
+            }
+        }
+        Err(e) => tracing::warn!("Failed to reap dead WebSocket nodes: {}", e),
+    }
+}
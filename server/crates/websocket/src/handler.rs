@@ -1,22 +1,80 @@
 //! WebSocket connection handler.
 
 use axum::extract::ws::{Message, WebSocket};
+use axum::http::{header::USER_AGENT, HeaderMap};
 use chrono::Utc;
 use futures_util::{SinkExt, StreamExt};
 use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
 use std::time::{Duration, Instant};
-use tokio::sync::broadcast;
+use tokio::sync::broadcast::{self, error::TryRecvError};
 use uuid::Uuid;
 
 use threadkit_common::{
     auth,
-    types::{ProjectIdInfo, ProjectIdType, UserPublic},
+    comments::{CommentActor, CommentError},
+    messages::{ClientMessage, ClientRequest, ClientRpcMessage, RpcError, ServerMessage, ServerResponse},
+    pubsub, rate_limit,
+    types::{AuthEvent, ProjectIdInfo, ProjectIdType, Role, UserPublic},
+    visibility::Viewer,
 };
 
-use crate::{
-    messages::{ClientMessage, ClientRpcMessage, ServerMessage},
-    state::WsState,
-};
+use crate::state::WsState;
+
+/// Where a connection comes from, captured at upgrade
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    /// Read the client IP (through trusted proxies only) and user agent of the upgrade request
+    pub fn from_request(headers: &HeaderMap, peer: Option<IpAddr>, trusted_proxies: &[String]) -> Self {
+        Self {
+            ip: rate_limit::client_ip(headers, peer, trusted_proxies),
+            user_agent: headers
+                .get(USER_AGENT)
+                .and_then(|v| v.to_str().ok())
+                .map(|s| s.to_string()),
+        }
+    }
+}
+
+/// The login session a connection was opened with
+#[derive(Debug, Clone, Copy)]
+struct Session {
+    user_id: Uuid,
+    session_id: Uuid,
+}
+
+/// Whether a connection's session may still act as its user
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SessionStatus {
+    Active,
+    /// Logged out (or the session expired)
+    Revoked,
+    /// Banned from the site
+    Blocked,
+}
+
+/// A connection's live event receivers
+#[derive(Default)]
+struct Receivers {
+    pages: Vec<PageSubscription>,
+    /// Site moderation events, once subscribed as a moderator
+    moderation: Option<broadcast::Receiver<ServerMessage>>,
+    /// The signed in user's notifications on this site
+    notifications: Option<broadcast::Receiver<ServerMessage>>,
+}
+
+/// A subscribed page's live event receiver
+struct PageSubscription {
+    page_id: Uuid,
+    rx: broadcast::Receiver<ServerMessage>,
+    /// Events up to this sequence number were already sent by a replay
+    replayed_through: u64,
+}
 
 /// Configuration for connection handling
 struct ConnectionConfig {
@@ -30,6 +88,8 @@ struct ConnectionConfig {
     typing_debounce_ms: u64,
     /// Max pages a client can subscribe to
     max_subscriptions: usize,
+    /// How often an authenticated connection re-checks its session, ban status and viewer
+    session_check_secs: u64,
 }
 
 impl ConnectionConfig {
@@ -40,6 +100,7 @@ impl ConnectionConfig {
             idle_timeout_secs: 300, // 5 minutes
             typing_debounce_ms: 500,
             max_subscriptions: 10,
+            session_check_secs: 60,
         }
     }
 }
@@ -50,6 +111,7 @@ pub async fn handle_socket(
     state: WsState,
     project_id: String,
     token: Option<String>,
+    client: ClientInfo,
 ) {
     // Validate API key
     let site_info = match validate_project_id(&state, &project_id).await {
@@ -65,15 +127,21 @@ pub async fn handle_socket(
     // Track connection
     state.connection_opened(site_id);
 
-    // Validate JWT token if provided
-    let user_id = if let Some(ref token) = token {
-        match auth::verify_token(token, &state.config.jwt_secret) {
-            Ok(claims) if claims.site_id == site_info.site_id => Some(claims.sub),
-            _ => None,
+    // Validate JWT token if provided, and that its session is live and the user isn't banned
+    let session = match token.as_deref().map(|t| auth::verify_token(t, &state.config.jwt_secret)) {
+        Some(Ok(claims)) if claims.site_id == site_info.site_id => {
+            let session = Session {
+                user_id: claims.sub,
+                session_id: claims.session_id,
+            };
+            match check_session(&state, site_id, session).await {
+                SessionStatus::Active => Some(session),
+                _ => None,
+            }
         }
-    } else {
-        None
+        _ => None,
     };
+    let user_id = session.map(|s| s.user_id);
 
     // Get user info for presence (via batcher)
     let user_public: Option<UserPublic> = if let Some(uid) = user_id {
@@ -83,7 +151,7 @@ pub async fn handle_socket(
     };
 
     // Run the connection loop
-    run_connection(socket, state.clone(), site_id, user_id, user_public).await;
+    run_connection(socket, state.clone(), site_id, &project_id, &client, session, user_public).await;
 
     // Track disconnection
     state.connection_closed(site_id);
@@ -93,10 +161,13 @@ async fn run_connection(
     socket: WebSocket,
     state: WsState,
     site_id: Uuid,
-    user_id: Option<Uuid>,
-    user_public: Option<UserPublic>,
+    project_id: &str,
+    client: &ClientInfo,
+    mut session: Option<Session>,
+    mut user_public: Option<UserPublic>,
 ) {
     let config = ConnectionConfig::from_state(&state);
+    let mut user_id = session.map(|s| s.user_id);
     let (mut sender, mut receiver) = socket.split();
 
     // Send connected message
@@ -105,9 +176,19 @@ async fn run_connection(
         state.message_sent();
     }
 
+    // Who is watching, to filter page events per connection
+    let mut viewer = Viewer::load(state.store.as_ref(), site_id, user_id).await;
+
+    // Logouts and bans published while the connection is open
+    let mut auth_rx = session.map(|_| state.auth_events.subscribe());
+    let mut last_session_check = Instant::now();
+
     // Connection state
     let mut subscribed_pages: HashSet<Uuid> = HashSet::new();
-    let mut page_receivers: Vec<(Uuid, broadcast::Receiver<ServerMessage>)> = Vec::new();
+    let mut receivers = Receivers::default();
+    if let Some(uid) = user_id {
+        receivers.notifications = Some(state.subscribe_notifications(site_id, uid));
+    }
     let mut last_activity = Instant::now();
     let mut last_typing: HashMap<Uuid, Instant> = HashMap::new();
     let mut messages_this_second = 0u32;
@@ -150,26 +231,38 @@ async fn run_connection(
 
                         // Parse and handle message
                         if let Ok(rpc) = serde_json::from_str::<ClientRpcMessage>(&text) {
-                            if let Ok(client_msg) = ClientMessage::from_rpc(rpc) {
-                                // Track message for analytics
-                                state.batcher.queue_message_count(site_id, hour_key.clone());
-
-                                handle_client_message(
-                                    &state,
-                                    &mut sender,
-                                    &mut subscribed_pages,
-                                    &mut page_receivers,
-                                    &mut last_typing,
-                                    client_msg,
-                                    user_id,
-                                    user_public.as_ref(),
-                                    site_id,
-                                    &config,
-                                ).await;
-                            } else {
-                                if let Ok(json) = ServerMessage::error("invalid_method", "Unknown method").to_json() {
-                                    let _ = sender.send(Message::Text(json.into())).await;
-                                    state.message_sent();
+                            let request_id = rpc.id.clone();
+                            match ClientMessage::from_rpc(rpc) {
+                                Ok(client_msg) => {
+                                    // Track message for analytics
+                                    state.batcher.queue_message_count(site_id, hour_key.clone());
+
+                                    handle_client_message(
+                                        &state,
+                                        &mut sender,
+                                        &mut subscribed_pages,
+                                        &mut receivers,
+                                        &mut last_typing,
+                                        client_msg,
+                                        user_id,
+                                        user_public.as_ref(),
+                                        &viewer,
+                                        site_id,
+                                        project_id,
+                                        client,
+                                        &config,
+                                    ).await;
+                                }
+                                Err(e) => {
+                                    // Requests get an error response, notifications an error event
+                                    let json = match request_id {
+                                        Some(id) => ServerResponse::error(id, e).to_json(),
+                                        None => ServerMessage::error("invalid_method", &e.message).to_json(),
+                                    };
+                                    if let Ok(json) = json {
+                                        let _ = sender.send(Message::Text(json.into())).await;
+                                        state.message_sent();
+                                    }
                                 }
                             }
                         } else {
@@ -192,7 +285,41 @@ async fn run_connection(
 
             // Handle broadcast messages from subscribed pages (check every 10ms)
             _ = tokio::time::sleep(Duration::from_millis(10)) => {
-                for (_page_id, rx) in page_receivers.iter_mut() {
+                for sub in receivers.pages.iter_mut() {
+                    loop {
+                        let msg = match sub.rx.try_recv() {
+                            Ok(msg) => msg,
+                            Err(TryRecvError::Lagged(skipped)) => {
+                                // The client missed events, have it refetch the page
+                                tracing::debug!("WebSocket client lagged, skipped {} events", skipped);
+                                let latest_seq = state.redis.get_page_event_seq(sub.page_id).await.unwrap_or(0);
+                                sub.replayed_through = sub.replayed_through.max(latest_seq);
+                                if let Ok(json) = ServerMessage::resync_required(sub.page_id, latest_seq).to_json() {
+                                    let _ = sender.send(Message::Text(json.into())).await;
+                                    state.message_sent();
+                                }
+                                continue;
+                            }
+                            Err(_) => break,
+                        };
+                        // Skip events the client already got from a replay
+                        if msg.seq().is_some_and(|seq| seq <= sub.replayed_through) {
+                            continue;
+                        }
+                        if !viewer.can_see(&msg) {
+                            continue;
+                        }
+                        if let Ok(json) = msg.to_json() {
+                            let _ = sender.send(Message::Text(json.into())).await;
+                            state.message_sent();
+                        }
+                    }
+                }
+
+                for rx in [receivers.moderation.as_mut(), receivers.notifications.as_mut()]
+                    .into_iter()
+                    .flatten()
+                {
                     while let Ok(msg) = rx.try_recv() {
                         if let Ok(json) = msg.to_json() {
                             let _ = sender.send(Message::Text(json.into())).await;
@@ -201,6 +328,78 @@ async fn run_connection(
                     }
                 }
 
+                // Apply logouts and bans to this connection's identity
+                if let (Some(current), Some(rx)) = (session, auth_rx.as_mut()) {
+                    let mut status = SessionStatus::Active;
+                    while let Ok(event) = rx.try_recv() {
+                        match event {
+                            AuthEvent::SessionRevoked { session_id, .. }
+                                if session_id == current.session_id =>
+                            {
+                                status = SessionStatus::Revoked;
+                            }
+                            AuthEvent::UserBlocked { site_id: blocked_site, user_id: blocked_user }
+                                if blocked_site == site_id && blocked_user == current.user_id =>
+                            {
+                                status = SessionStatus::Blocked;
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    // Periodic re-check catches anything the events missed, and picks up
+                    // role and block list changes
+                    if status == SessionStatus::Active
+                        && last_session_check.elapsed() >= Duration::from_secs(config.session_check_secs)
+                    {
+                        last_session_check = Instant::now();
+                        status = check_session(&state, site_id, current).await;
+                        if status == SessionStatus::Active {
+                            viewer = Viewer::load(state.store.as_ref(), site_id, user_id).await;
+
+                            // Demoted moderators stop receiving the site's moderation events
+                            if receivers.moderation.is_some() && !viewer.is_moderator() {
+                                receivers.moderation = None;
+                                let msg = ServerMessage::error("forbidden", "Moderator role required");
+                                if let Ok(json) = msg.to_json() {
+                                    let _ = sender.send(Message::Text(json.into())).await;
+                                    state.message_sent();
+                                }
+                            }
+                        }
+                    }
+
+                    match status {
+                        SessionStatus::Active => {}
+                        SessionStatus::Revoked => {
+                            // The token is dead, the client must reconnect
+                            if let Ok(json) = ServerMessage::signed_out("session_revoked").to_json() {
+                                let _ = sender.send(Message::Text(json.into())).await;
+                                state.message_sent();
+                            }
+                            break;
+                        }
+                        SessionStatus::Blocked => {
+                            // Banned users can still read, but lose their identity on the socket
+                            remove_presence(&state, &subscribed_pages, current.user_id);
+                            session = None;
+                            user_id = None;
+                            user_public = None;
+                            auth_rx = None;
+                            receivers.moderation = None;
+                            receivers.notifications = None;
+                            state.release_notifications(site_id, current.user_id);
+                            last_typing.clear();
+                            viewer = Viewer::load(state.store.as_ref(), site_id, None).await;
+
+                            if let Ok(json) = ServerMessage::signed_out("banned").to_json() {
+                                let _ = sender.send(Message::Text(json.into())).await;
+                                state.message_sent();
+                            }
+                        }
+                    }
+                }
+
                 // Check idle timeout
                 if last_activity.elapsed() > Duration::from_secs(config.idle_timeout_secs) {
                     tracing::debug!("Connection timed out due to inactivity");
@@ -212,34 +411,60 @@ async fn run_connection(
 
     // Cleanup: remove presence from all subscribed pages
     if let Some(uid) = user_id {
-        for page_id in subscribed_pages {
-            state.batcher.queue_presence_remove(page_id, uid);
-
-            // Broadcast user left
-            state.broadcast(
-                page_id,
-                ServerMessage::user_left(page_id, uid),
-            );
-        }
+        remove_presence(&state, &subscribed_pages, uid);
+        drop(receivers);
+        state.release_notifications(site_id, uid);
     }
 
     tracing::debug!("WebSocket connection closed");
 }
 
+/// Remove a user's presence from pages and tell the other subscribers
+fn remove_presence(state: &WsState, pages: &HashSet<Uuid>, user_id: Uuid) {
+    for &page_id in pages {
+        state.batcher.queue_presence_remove(page_id, user_id);
+
+        // Tell subscribers on every node the user left
+        state.publish_activity(page_id, "user_left", serde_json::json!({ "user_id": user_id }));
+    }
+}
+
+/// Check that a session is still logged in and its user isn't banned from the site
+///
+/// Storage errors leave the session active, so a brief outage doesn't sign everyone out.
+async fn check_session(state: &WsState, site_id: Uuid, session: Session) -> SessionStatus {
+    match state.store.get_session_user(session.session_id).await {
+        Ok(Some(uid)) if uid == session.user_id => {}
+        Ok(_) => return SessionStatus::Revoked,
+        Err(e) => {
+            tracing::warn!("Failed to check session {}: {}", session.session_id, e);
+            return SessionStatus::Active;
+        }
+    }
+
+    match state.store.get_user_role(site_id, session.user_id).await {
+        Ok(Role::Blocked) => SessionStatus::Blocked,
+        _ => SessionStatus::Active,
+    }
+}
+
 async fn handle_client_message(
     state: &WsState,
     sender: &mut futures_util::stream::SplitSink<WebSocket, Message>,
     subscribed_pages: &mut HashSet<Uuid>,
-    page_receivers: &mut Vec<(Uuid, broadcast::Receiver<ServerMessage>)>,
+    receivers: &mut Receivers,
     last_typing: &mut HashMap<Uuid, Instant>,
     message: ClientMessage,
     user_id: Option<Uuid>,
     user_public: Option<&UserPublic>,
-    _site_id: Uuid,
+    viewer: &Viewer,
+    site_id: Uuid,
+    project_id: &str,
+    client: &ClientInfo,
     config: &ConnectionConfig,
 ) {
     match message {
-        ClientMessage::Subscribe { page_id } => {
+        ClientMessage::Subscribe { page_id, since_seq } => {
             // Check subscription limit
             if subscribed_pages.len() >= config.max_subscriptions {
                 if let Ok(json) = ServerMessage::error("subscription_limit", "Too many subscriptions").to_json() {
@@ -250,22 +475,44 @@ async fn handle_client_message(
             }
 
             if !subscribed_pages.contains(&page_id) {
+                // The viewer's role is for this site, so only its pages may be watched
+                if !matches!(state.redis.get_page_url(site_id, page_id).await, Ok(Some(_))) {
+                    if let Ok(json) = ServerMessage::error("not_found", "Page not found").to_json() {
+                        let _ = sender.send(Message::Text(json.into())).await;
+                        state.message_sent();
+                    }
+                    return;
+                }
+
                 subscribed_pages.insert(page_id);
 
-                // Subscribe to broadcast channel
+                // Subscribe to broadcast channel before reading the event log
+                // so nothing published in between is missed
                 let rx = state.subscribe(page_id);
-                page_receivers.push((page_id, rx));
+                let mut seq = state.redis.get_page_event_seq(page_id).await.unwrap_or(0);
+                let mut replayed_through = 0;
+
+                if let Some(since_seq) = since_seq {
+                    let (messages, through) = pubsub::replay_events(&state.redis, page_id, since_seq).await;
+                    for msg in messages.iter().filter(|m| viewer.can_see(m)) {
+                        if let Ok(json) = msg.to_json() {
+                            let _ = sender.send(Message::Text(json.into())).await;
+                            state.message_sent();
+                        }
+                    }
+                    replayed_through = through;
+                    seq = seq.max(replayed_through);
+                }
+
+                receivers.pages.push(PageSubscription { page_id, rx, replayed_through });
 
                 // Add presence if authenticated (via batcher)
                 if let Some(uid) = user_id {
                     state.batcher.queue_presence_add(page_id, uid);
 
-                    // Broadcast user joined
+                    // Tell subscribers on every node the user joined
                     if let Some(user) = user_public {
-                        state.broadcast(
-                            page_id,
-                            ServerMessage::user_joined(page_id, user.clone()),
-                        );
+                        state.publish_activity(page_id, "user_joined", serde_json::json!({ "user": user }));
                     }
                 }
 
@@ -278,7 +525,7 @@ async fn handle_client_message(
                     }
                 }
 
-                let msg = ServerMessage::presence(page_id, users);
+                let msg = ServerMessage::presence(page_id, viewer.filter_users(users)).with_seq(seq);
                 if let Ok(json) = msg.to_json() {
                     let _ = sender.send(Message::Text(json.into())).await;
                     state.message_sent();
@@ -288,19 +535,36 @@ async fn handle_client_message(
 
         ClientMessage::Unsubscribe { page_id } => {
             subscribed_pages.remove(&page_id);
-            page_receivers.retain(|(pid, _)| *pid != page_id);
+            receivers.pages.retain(|sub| sub.page_id != page_id);
 
             // Remove presence if authenticated (via batcher)
             if let Some(uid) = user_id {
                 state.batcher.queue_presence_remove(page_id, uid);
 
-                state.broadcast(
-                    page_id,
-                    ServerMessage::user_left(page_id, uid),
-                );
+                state.publish_activity(page_id, "user_left", serde_json::json!({ "user_id": uid }));
+            }
+        }
+
+        ClientMessage::SubscribeModeration => {
+            let msg = match moderator_site(state, project_id, user_id).await {
+                Ok(site_id) => {
+                    if receivers.moderation.is_none() {
+                        receivers.moderation = Some(state.subscribe_moderation(site_id));
+                    }
+                    ServerMessage::moderation_subscribed(site_id)
+                }
+                Err(message) => ServerMessage::error("forbidden", &message),
+            };
+            if let Ok(json) = msg.to_json() {
+                let _ = sender.send(Message::Text(json.into())).await;
+                state.message_sent();
             }
         }
 
+        ClientMessage::UnsubscribeModeration => {
+            receivers.moderation = None;
+        }
+
         ClientMessage::Typing { page_id, reply_to } => {
             if let (Some(uid), Some(user)) = (user_id, user_public) {
                 if subscribed_pages.contains(&page_id) {
@@ -317,10 +581,11 @@ async fn handle_client_message(
                         let timestamp = Utc::now().timestamp_millis();
                         state.batcher.queue_typing(page_id, uid, reply_to, timestamp);
 
-                        // Broadcast to local subscribers immediately
-                        state.broadcast(
+                        // Tell subscribers on every node
+                        state.publish_activity(
                             page_id,
-                            ServerMessage::typing(page_id, user.clone(), reply_to),
+                            "typing",
+                            serde_json::json!({ "user": user, "reply_to": reply_to }),
                         );
                     }
                 }
@@ -334,7 +599,122 @@ async fn handle_client_message(
                 state.message_sent();
             }
         }
+
+        ClientMessage::Request { id, request } => {
+            let response = match handle_request(state, project_id, client, user_id, request).await {
+                Ok(result) => ServerResponse::result(id, result),
+                Err(e) => ServerResponse::error(id, e),
+            };
+            if let Ok(json) = response.to_json() {
+                let _ = sender.send(Message::Text(json.into())).await;
+                state.message_sent();
+            }
+        }
+    }
+}
+
+/// The connection's site, if its user may watch the site's moderation events
+async fn moderator_site(state: &WsState, project_id: &str, user_id: Option<Uuid>) -> Result<Uuid, String> {
+    let user_id = user_id.ok_or("Authentication required")?;
+    let site = validate_project_id(state, project_id).await?;
+
+    let role = state
+        .store
+        .get_user_role(site.site_id, user_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    if role < Role::Moderator {
+        return Err("Moderator role required".into());
+    }
+
+    Ok(site.site_id)
+}
+
+/// Run a comment write through the shared comment service
+///
+/// Site settings and the user's role are looked up per request, like the HTTP API does,
+/// so changes (posting disabled, new bans) apply to connections that are already open.
+/// Every request is a write and counts against the same rate limits as HTTP writes.
+async fn handle_request(
+    state: &WsState,
+    project_id: &str,
+    client: &ClientInfo,
+    user_id: Option<Uuid>,
+    request: ClientRequest,
+) -> Result<serde_json::Value, RpcError> {
+    let site = validate_project_id(state, project_id)
+        .await
+        .map_err(|e| RpcError::from(CommentError::Unauthorized(e)))?;
+
+    if state.config.rate_limit.enabled {
+        let refused = rate_limit::check_write_limits(
+            state.store.as_ref(),
+            &state.config.rate_limit,
+            &site.settings.rate_limits,
+            site.site_id,
+            client.ip.as_deref().unwrap_or("unknown"),
+            user_id,
+        )
+        .await
+        .map_err(CommentError::from)?;
+        if let Some((layer, _)) = refused {
+            return Err(CommentError::TooManyRequests(format!("Rate limit exceeded ({})", layer)).into());
+        }
     }
+
+    let (role, username_set) = match user_id {
+        Some(uid) => {
+            let role = state
+                .store
+                .get_user_role(site.site_id, uid)
+                .await
+                .map_err(CommentError::from)?;
+            if role == Role::Blocked {
+                return Err(CommentError::Forbidden("User is blocked".into()).into());
+            }
+            let username_set = state
+                .store
+                .get_user(uid)
+                .await
+                .map_err(CommentError::from)?
+                .map(|u| u.username_set)
+                .unwrap_or(true);
+            (role, username_set)
+        }
+        None => (Role::User, true),
+    };
+
+    let mut actor = CommentActor {
+        user_id,
+        role,
+        username_set,
+        ip: client.ip.clone(),
+        user_agent: client.user_agent.clone(),
+        turnstile_token: None,
+    };
+
+    let result = match request {
+        ClientRequest::PostComment { req, turnstile_token } => {
+            actor.turnstile_token = turnstile_token;
+            let written = state.comments.create(&site, &actor, req).await?;
+            serde_json::json!({ "comment": written.value })
+        }
+        ClientRequest::EditComment { comment_id, req } => {
+            let written = state.comments.edit(&site, &actor, comment_id, req).await?;
+            serde_json::json!({ "comment": written.value })
+        }
+        ClientRequest::DeleteComment { comment_id, req } => {
+            state.comments.delete(&site, &actor, comment_id, req).await?;
+            serde_json::json!({ "comment_id": comment_id, "deleted": true })
+        }
+        ClientRequest::Vote { comment_id, req } => {
+            let written = state.comments.vote(&site, &actor, comment_id, req).await?;
+            serde_json::to_value(written.value)
+                .map_err(|e| CommentError::Internal(e.to_string()))?
+        }
+    };
+
+    Ok(result)
 }
 
 /// Validate an API key
@@ -366,6 +746,8 @@ async fn validate_project_id(state: &WsState, project_id: &str) -> Result<Projec
             key_type,
             settings: site_config.settings,
             domain: site_config.domain,
+            limits: site_config.plan.effective_limits(),
+            api_token: None,
         };
 
         // Cache for future requests
@@ -1,5 +1,4 @@
 pub mod batcher;
+pub mod cluster;
 pub mod handler;
-pub mod messages;
-pub mod pubsub;
 pub mod state;
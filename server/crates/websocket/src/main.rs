@@ -1,6 +1,7 @@
 use anyhow::Result;
 use axum::{
-    extract::{ws::WebSocketUpgrade, Query, State},
+    extract::{ws::WebSocketUpgrade, ConnectInfo, Query, State},
+    http::HeaderMap,
     response::{Json, Response},
     routing::get,
     Router,
@@ -8,14 +9,18 @@ use axum::{
 use clap::Parser;
 use serde::Deserialize;
 use std::net::SocketAddr;
-use std::sync::atomic::Ordering;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::net::TcpListener;
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use threadkit_common::Config;
-use threadkit_websocket::{handler::handle_socket, pubsub::PubSubSubscriber, state::WsState};
+use threadkit_common::{pubsub::PubSubSubscriber, ActionLogger, Config};
+use threadkit_websocket::{
+    cluster,
+    handler::{handle_socket, ClientInfo},
+    state::WsState,
+};
 
 #[derive(Parser)]
 #[command(name = "threadkit-websocket")]
@@ -46,6 +51,10 @@ struct Args {
     #[arg(long)]
     no_rate_limit: bool,
 
+    /// Path to JSON action log file (overrides THREADKIT_ACTION_LOG env var)
+    #[arg(long)]
+    action_log: Option<String>,
+
 }
 
 #[tokio::main]
@@ -103,8 +112,19 @@ async fn main() -> Result<()> {
         tracing::warn!("Rate limiting is DISABLED");
     }
 
+    // Comment writes over the socket are logged like the HTTP server's
+    let action_log_path = args.action_log
+        .or_else(|| std::env::var("THREADKIT_ACTION_LOG").ok())
+        .map(std::path::PathBuf::from);
+
+    if let Some(ref path) = action_log_path {
+        tracing::info!("Action logging enabled: {}", path.display());
+    }
+
+    let action_logger = Arc::new(ActionLogger::new(action_log_path)?);
+
     // Initialize state
-    let state = WsState::new(config.clone()).await?;
+    let state = WsState::new(config.clone(), action_logger).await?;
 
     // Start the batcher flush loop
     let batcher_handle = state.batcher.start();
@@ -114,10 +134,17 @@ async fn main() -> Result<()> {
     let pubsub_subscriber = PubSubSubscriber::new(
         config.redis_url.clone(),
         state.page_channels.clone(),
+        state.moderation_channels.clone(),
+        state.notification_channels.clone(),
+        state.auth_events.clone(),
     );
     let _pubsub_handle = pubsub_subscriber.start();
     tracing::info!("Redis pub/sub subscriber started");
 
+    // Heartbeats keep this node's presence entries alive and reap dead nodes'
+    let cluster_handle = cluster::start(state.clone());
+    tracing::info!("WebSocket node {} registered", state.node_id);
+
     // Start metrics logging task
     let metrics_state = state.clone();
     tokio::spawn(async move {
@@ -153,7 +180,7 @@ async fn main() -> Result<()> {
         .route("/health", get(|| async { "ok" }))
         .route("/metrics", get(metrics_handler))
         .layer(TraceLayer::new_for_http())
-        .with_state(state);
+        .with_state(state.clone());
 
     // Start server
     let host: std::net::IpAddr = config.ws_host.parse().unwrap_or_else(|_| {
@@ -164,11 +191,16 @@ async fn main() -> Result<()> {
     tracing::info!("WebSocket server listening on {}", addr);
 
     let listener = TcpListener::bind(addr).await?;
-    axum::serve(listener, app)
+    // Peer addresses decide whether X-Forwarded-For can be trusted
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
         .with_graceful_shutdown(shutdown_signal())
         .await?;
 
     // Cleanup
+    cluster_handle.abort();
+    if let Err(e) = state.redis.remove_ws_node(state.node_id).await {
+        tracing::warn!("Failed to deregister WebSocket node: {}", e);
+    }
     batcher_handle.abort();
     tracing::info!("WebSocket server shut down");
 
@@ -184,23 +216,43 @@ struct WsQuery {
 async fn ws_handler(
     ws: WebSocketUpgrade,
     State(state): State<WsState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Query(query): Query<WsQuery>,
 ) -> Response {
-    ws.on_upgrade(move |socket| handle_socket(socket, state, query.project_id, query.token))
+    let client = ClientInfo::from_request(
+        &headers,
+        Some(peer.ip().to_canonical()),
+        &state.config.rate_limit.trusted_proxies,
+    );
+    ws.on_upgrade(move |socket| handle_socket(socket, state, query.project_id, query.token, client))
 }
 
+/// This node's metrics, plus the same metrics summed across all live nodes
 async fn metrics_handler(State(state): State<WsState>) -> Json<serde_json::Value> {
-    let metrics = state.get_metrics();
-    Json(serde_json::json!({
-        "active_connections": metrics.active_connections,
-        "total_connections": metrics.total_connections,
-        "messages_received": metrics.total_messages_received,
-        "messages_sent": metrics.total_messages_sent,
-        "batcher_flushes": metrics.batcher_flushes,
-        "batcher_writes_batched": metrics.batcher_writes_batched,
-        "batcher_reads_batched": metrics.batcher_reads_batched,
-        "page_channels": metrics.page_channels_count,
-    }))
+    let mut metrics = serde_json::Map::new();
+    metrics.insert("node_id".to_string(), state.node_id.to_string().into());
+    for (name, value) in state.get_metrics().counters() {
+        metrics.insert(name.to_string(), value.into());
+    }
+
+    let cluster = match state.redis.get_cluster_metrics().await {
+        Ok((nodes, totals)) => {
+            let mut cluster = serde_json::Map::new();
+            cluster.insert("nodes".to_string(), nodes.into());
+            for (name, value) in totals {
+                cluster.insert(name, value.into());
+            }
+            serde_json::Value::Object(cluster)
+        }
+        Err(e) => {
+            tracing::warn!("Failed to get cluster metrics: {}", e);
+            serde_json::Value::Null
+        }
+    };
+    metrics.insert("cluster".to_string(), cluster);
+
+    Json(serde_json::Value::Object(metrics))
 }
 
 async fn shutdown_signal() {
@@ -214,14 +266,21 @@ async fn shutdown_signal() {
 }
 
 /// Snapshot current connection counts to Redis for analytics
+///
+/// Counts are summed across all WebSocket nodes, so every node writes the same values.
 async fn snapshot_connections(state: &WsState) {
     let hour_key = chrono::Utc::now().format("%Y%m%d%H").to_string();
     let minute = chrono::Utc::now().format("%M").to_string().parse::<u32>().unwrap_or(0);
 
-    for entry in state.connections_per_site.iter() {
-        let site_id = *entry.key();
-        let count = entry.value().load(Ordering::Relaxed);
+    let connections = match state.redis.get_cluster_connections().await {
+        Ok(connections) => connections,
+        Err(e) => {
+            tracing::warn!("Failed to get cluster connection counts: {}", e);
+            return;
+        }
+    };
 
+    for (site_id, count) in connections {
         if count > 0 {
             // Store in Redis: ZADD threadkit:stats:{site_id}:connections:{hour} minute count
             let key = format!("threadkit:stats:{}:connections:{}", site_id, hour_key);
@@ -2,25 +2,40 @@
 
 use anyhow::Result;
 use dashmap::DashMap;
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::broadcast;
 use uuid::Uuid;
 
-use threadkit_common::{redis::RedisClient, Config};
+use threadkit_common::{
+    messages::ServerMessage, redis::RedisClient, store, types::AuthEvent, ActionLogger,
+    CommentService, Config, ModerationClient, Quotas, Storage, WebhookDispatcher,
+};
 
 use crate::batcher::RedisBatcher;
-use crate::messages::ServerMessage;
 
 /// WebSocket server state
 #[derive(Clone)]
 pub struct WsState {
+    /// Identifies this server among the WebSocket nodes sharing the Redis instance
+    pub node_id: Uuid,
     pub config: Arc<Config>,
     pub redis: Arc<RedisClient>,
+    /// Users, sessions and the other data kinds behind `STORAGE_BACKEND`
+    pub store: Arc<dyn Storage>,
     pub batcher: Arc<RedisBatcher>,
+    /// Comment writes made over the socket (same rules as the HTTP API)
+    pub comments: Arc<CommentService>,
     /// Broadcast channels per page for real-time events
     pub page_channels: Arc<DashMap<Uuid, broadcast::Sender<ServerMessage>>>,
-    /// Active connections per site (for analytics)
+    /// Broadcast channels per site for moderation events
+    pub moderation_channels: Arc<DashMap<Uuid, broadcast::Sender<ServerMessage>>>,
+    /// Broadcast channels per (site, user) for the user's notifications
+    pub notification_channels: Arc<DashMap<(Uuid, Uuid), broadcast::Sender<ServerMessage>>>,
+    /// Logouts and bans, for connections to drop revoked identities
+    pub auth_events: broadcast::Sender<AuthEvent>,
+    /// Active connections per site on this node (summed across nodes via heartbeats)
     pub connections_per_site: Arc<DashMap<Uuid, AtomicU64>>,
 
     // === Metrics ===
@@ -32,17 +47,40 @@ pub struct WsState {
 
 impl WsState {
     /// Create a new WebSocket server state
-    pub async fn new(config: Config) -> Result<Self> {
+    pub async fn new(config: Config, action_logger: Arc<ActionLogger>) -> Result<Self> {
         let redis = Arc::new(RedisClient::new(&config.redis_url).await?);
         tracing::info!("WebSocket server connected to Redis");
 
-        let batcher = RedisBatcher::new(Arc::clone(&redis), 20); // 20ms flush interval
+        let store = store::connect(&config.storage, redis.clone()).await?;
+
+        // A fresh ID per process: a restarted node's old entries are reaped like a crashed one's
+        let node_id = Uuid::now_v7();
+        let batcher = RedisBatcher::new(Arc::clone(&redis), Arc::clone(&store), node_id, 20); // 20ms flush interval
+
+        // Webhooks are delivered by the HTTP server's worker
+        let config = Arc::new(config);
+        let webhooks = Arc::new(WebhookDispatcher::new(Arc::clone(&redis), config.webhooks.clone())?);
+        let comments = Arc::new(CommentService::new(
+            Arc::clone(&config),
+            Arc::clone(&redis),
+            Arc::clone(&store),
+            Arc::new(ModerationClient::new(config.content_moderation.clone())?),
+            Arc::clone(&action_logger),
+            Arc::clone(&webhooks),
+            Arc::new(Quotas::new(Arc::clone(&redis), action_logger, webhooks)),
+        ));
 
         Ok(WsState {
-            config: Arc::new(config),
+            node_id,
+            config,
             redis,
+            store,
             batcher,
+            comments,
             page_channels: Arc::new(DashMap::new()),
+            moderation_channels: Arc::new(DashMap::new()),
+            notification_channels: Arc::new(DashMap::new()),
+            auth_events: broadcast::channel(1000).0,
             connections_per_site: Arc::new(DashMap::new()),
             active_connections: Arc::new(AtomicU64::new(0)),
             total_connections: Arc::new(AtomicU64::new(0)),
@@ -67,6 +105,34 @@ impl WsState {
         self.get_or_create_channel(page_id).subscribe()
     }
 
+    /// Subscribe to a site's moderation events
+    pub fn subscribe_moderation(&self, site_id: Uuid) -> broadcast::Receiver<ServerMessage> {
+        self.moderation_channels
+            .entry(site_id)
+            .or_insert_with(|| {
+                let (tx, _) = broadcast::channel(1000);
+                tx
+            })
+            .subscribe()
+    }
+
+    /// Subscribe to a user's notifications on a site
+    pub fn subscribe_notifications(&self, site_id: Uuid, user_id: Uuid) -> broadcast::Receiver<ServerMessage> {
+        self.notification_channels
+            .entry((site_id, user_id))
+            .or_insert_with(|| {
+                let (tx, _) = broadcast::channel(100);
+                tx
+            })
+            .subscribe()
+    }
+
+    /// Drop a user's notification channel once none of their connections listen to it
+    pub fn release_notifications(&self, site_id: Uuid, user_id: Uuid) {
+        self.notification_channels
+            .remove_if(&(site_id, user_id), |_, tx| tx.receiver_count() == 0);
+    }
+
     /// Broadcast a message to all subscribers of a page
     pub fn broadcast(&self, page_id: Uuid, message: ServerMessage) {
         if let Some(tx) = self.page_channels.get(&page_id) {
@@ -74,6 +140,22 @@ impl WsState {
         }
     }
 
+    /// Publish presence or typing activity to a page's subscribers on every node (via batcher)
+    ///
+    /// Activity isn't sequenced or logged for replay, it reaches this node's subscribers
+    /// through the pub/sub subscriber like any other node's.
+    pub fn publish_activity(&self, page_id: Uuid, event_type: &str, data: serde_json::Value) {
+        let message = serde_json::json!({
+            "type": event_type,
+            "page_id": page_id,
+            "data": data
+        });
+        self.batcher.queue_publish(
+            format!("threadkit:page:{}:events", page_id),
+            message.to_string(),
+        );
+    }
+
     // === Connection Tracking ===
 
     /// Increment active connection count
@@ -133,3 +215,19 @@ pub struct Metrics {
     pub batcher_reads_batched: u64,
     pub page_channels_count: u64,
 }
+
+impl Metrics {
+    /// Metrics by name, as reported by `/metrics` and summed across nodes
+    pub fn counters(&self) -> HashMap<&'static str, u64> {
+        HashMap::from([
+            ("active_connections", self.active_connections),
+            ("total_connections", self.total_connections),
+            ("messages_received", self.total_messages_received),
+            ("messages_sent", self.total_messages_sent),
+            ("batcher_flushes", self.batcher_flushes),
+            ("batcher_writes_batched", self.batcher_writes_batched),
+            ("batcher_reads_batched", self.batcher_reads_batched),
+            ("page_channels", self.page_channels_count),
+        ])
+    }
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::TestContext;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn test_presence_ignores_unknown_nodes() {
+    let ctx = TestContext::new().await;
+    let page_id = Uuid::now_v7();
+    let (user_id, _) = ctx.create_test_user().await;
+
+    // An entry from a node that never sent a heartbeat
+    ctx.redis_client
+        .add_presence(page_id, user_id, Uuid::now_v7())
+        .await
+        .expect("add presence");
+
+    let present = ctx.redis_client.get_presence(page_id).await.expect("get presence");
+    assert!(present.is_empty());
+
+    // The same user through this node counts
+    ctx.redis_client
+        .add_presence(page_id, user_id, ctx.ws_state.node_id)
+        .await
+        .expect("add presence");
+    let present = ctx.redis_client.get_presence(page_id).await.expect("get presence");
+    assert_eq!(present, vec![user_id]);
+}
+
+#[tokio::test]
+async fn test_dead_node_presence_reaped() {
+    let ctx = TestContext::new().await;
+    let page_id = ctx.create_page().await;
+
+    // Another node with a user on the page
+    let other_node = Uuid::now_v7();
+    let (ghost_id, _) = ctx.create_test_user().await;
+    ctx.redis_client
+        .ws_node_heartbeat(other_node, &HashMap::new(), &HashMap::new())
+        .await
+        .expect("heartbeat");
+    ctx.redis_client
+        .add_presence(page_id, ghost_id, other_node)
+        .await
+        .expect("add presence");
+
+    let mut client = ctx.connect().await;
+    let _ = client.recv_message().await;
+    client.subscribe(page_id).await;
+    let msg = client.wait_for_method("presence").await;
+    let users = msg["params"]["users"].as_array().expect("users");
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0]["id"], ghost_id.to_string());
+
+    // The other node dies
+    ctx.redis_client
+        .remove_ws_node(other_node)
+        .await
+        .expect("remove node");
+
+    let msg = client.wait_for_method("user_left").await;
+    assert_eq!(msg["params"]["user_id"], ghost_id.to_string());
+
+    let present = ctx.redis_client.get_presence(page_id).await.expect("get presence");
+    assert!(present.is_empty());
+    let nodes = ctx.redis_client.get_live_ws_nodes().await.expect("live nodes");
+    assert!(!nodes.contains(&other_node));
+    assert!(nodes.contains(&ctx.ws_state.node_id));
+
+    client.close().await;
+}
+
+#[tokio::test]
+async fn test_cluster_connection_counts_and_metrics() {
+    let ctx = TestContext::new().await;
+    let other_site = Uuid::now_v7();
+
+    let node_a = Uuid::now_v7();
+    let node_b = Uuid::now_v7();
+    ctx.redis_client
+        .ws_node_heartbeat(
+            node_a,
+            &HashMap::from([(ctx.site_id, 2), (other_site, 1)]),
+            &HashMap::from([("active_connections", 3), ("messages_sent", 10)]),
+        )
+        .await
+        .expect("heartbeat");
+    ctx.redis_client
+        .ws_node_heartbeat(
+            node_b,
+            &HashMap::from([(ctx.site_id, 3)]),
+            &HashMap::from([("active_connections", 3), ("messages_sent", 5)]),
+        )
+        .await
+        .expect("heartbeat");
+
+    let connections = ctx
+        .redis_client
+        .get_cluster_connections()
+        .await
+        .expect("cluster connections");
+    assert_eq!(connections.get(&ctx.site_id), Some(&5));
+    assert_eq!(connections.get(&other_site), Some(&1));
+
+    // This node counts too (no connections open, so it adds nothing)
+    let (nodes, metrics) = ctx.redis_client.get_cluster_metrics().await.expect("cluster metrics");
+    assert_eq!(nodes, 3);
+    assert_eq!(metrics.get("active_connections"), Some(&6));
+    assert_eq!(metrics.get("messages_sent"), Some(&15));
+
+    // Reaped nodes drop out of the totals
+    ctx.redis_client.remove_ws_node(node_b).await.expect("remove node");
+    let connections = ctx
+        .redis_client
+        .get_cluster_connections()
+        .await
+        .expect("cluster connections");
+    assert_eq!(connections.get(&ctx.site_id), Some(&2));
+}
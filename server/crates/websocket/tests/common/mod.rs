@@ -1,5 +1,6 @@
 use axum::{
-    extract::{ws::WebSocketUpgrade, Query, State},
+    extract::{ws::WebSocketUpgrade, ConnectInfo, Query, State},
+    http::HeaderMap,
     routing::get,
     Router,
 };
@@ -7,6 +8,7 @@ use futures_util::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use testcontainers::{runners::AsyncRunner, ContainerAsync, ImageExt};
 use testcontainers_modules::redis::Redis;
@@ -14,11 +16,16 @@ use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, Web
 use uuid::Uuid;
 
 use threadkit_common::{
-    config::{RateLimitConfig, StandaloneConfig},
+    config::{RateLimitConfig, StandaloneConfig, StorageBackend, WebhookConfig},
+    pubsub::PubSubSubscriber,
     redis::RedisClient,
-    Config,
+    ActionLogger, Config,
+};
+use threadkit_websocket::{
+    cluster,
+    handler::{handle_socket, ClientInfo},
+    state::WsState,
 };
-use threadkit_websocket::{handler::handle_socket, state::WsState};
 
 #[derive(Debug, Deserialize)]
 struct WsQuery {
@@ -82,6 +89,7 @@ impl TestContext {
             project_id_public: project_id.clone(),
             project_id_secret: secret_key.clone(),
             settings,
+            plan: Default::default(),
         };
         redis_client
             .set_site_config(&site_config)
@@ -91,12 +99,15 @@ impl TestContext {
         // Create config
         let config = Config {
             redis_url: redis_url.clone(),
+            storage: StorageBackend::Redis,
             http_host: "127.0.0.1".to_string(),
             http_port: 8080,
             ws_host: "127.0.0.1".to_string(),
             ws_port: 8081,
             jwt_secret: "test_jwt_secret_for_testing".to_string(),
             jwt_expiry_hours: 24,
+            refresh_token_expiry_days: 30,
+            refresh_reuse_grace_secs: 0,
             mode: threadkit_common::config::Mode::Standalone(StandaloneConfig {
                 project_id_public: project_id.clone(),
                 project_id_secret: secret_key.clone(),
@@ -121,16 +132,33 @@ impl TestContext {
             email: Default::default(),
             turnstile: Default::default(),
             s3: None,
+            webhooks: WebhookConfig::default(),
             max_comment_length: 10_000,
             allow_localhost_origin: true,
         };
 
         // Create WebSocket state
-        let ws_state = WsState::new(config).await.expect("Failed to create WsState");
+        let action_logger = Arc::new(ActionLogger::new(None).expect("Failed to create action logger"));
+        let ws_state = WsState::new(config, action_logger)
+            .await
+            .expect("Failed to create WsState");
 
         // Start the batcher flush loop (in background)
         let _batcher_handle = ws_state.batcher.start();
 
+        // Relay published page events to subscribers, as the server binary does
+        let _pubsub_handle = PubSubSubscriber::new(
+            redis_url.clone(),
+            ws_state.page_channels.clone(),
+            ws_state.moderation_channels.clone(),
+            ws_state.notification_channels.clone(),
+            ws_state.auth_events.clone(),
+        )
+        .start();
+
+        // Register the node so its presence entries count
+        let _cluster_handle = cluster::start(ws_state.clone());
+
         // Create Axum app
         let state_clone = ws_state.clone();
         let app = Router::new()
@@ -139,9 +167,16 @@ impl TestContext {
                 get(
                     |ws: WebSocketUpgrade,
                      State(state): State<WsState>,
+                     ConnectInfo(peer): ConnectInfo<SocketAddr>,
+                     headers: HeaderMap,
                      Query(query): Query<WsQuery>| async move {
+                        let client = ClientInfo::from_request(
+                            &headers,
+                            Some(peer.ip()),
+                            &state.config.rate_limit.trusted_proxies,
+                        );
                         ws.on_upgrade(move |socket| {
-                            handle_socket(socket, state, query.project_id, query.token)
+                            handle_socket(socket, state, query.project_id, query.token, client)
                         })
                     },
                 ),
@@ -155,7 +190,9 @@ impl TestContext {
         let server_addr = format!("127.0.0.1:{}", listener.local_addr().unwrap().port());
 
         tokio::spawn(async move {
-            axum::serve(listener, app).await.unwrap();
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+                .await
+                .unwrap();
         });
 
         // Give server time to start
@@ -199,6 +236,12 @@ impl TestContext {
 
     /// Create a test user and return JWT token
     pub async fn create_test_user(&self) -> (Uuid, String) {
+        let (user_id, token, _) = self.create_test_user_with_session().await;
+        (user_id, token)
+    }
+
+    /// Create a test user with a login session, returning (user_id, token, session_id)
+    pub async fn create_test_user_with_session(&self) -> (Uuid, String, Uuid) {
         let user_id = Uuid::now_v7();
         let username = format!("testuser_{}", &user_id.to_string()[..8]);
 
@@ -218,6 +261,7 @@ impl TestContext {
             username_set: true,
             social_links: threadkit_common::types::SocialLinks::default(),
             total_comments: 0,
+            email_notifications: Default::default(),
         };
 
         self.redis_client
@@ -225,17 +269,41 @@ impl TestContext {
             .await
             .expect("Failed to save user");
 
+        // Sessions are checked on connect, like the HTTP API does
+        let session_id = Uuid::now_v7();
+        self.redis_client
+            .create_session(
+                session_id,
+                user_id,
+                "test-agent",
+                "127.0.0.1",
+                chrono::Utc::now() + chrono::Duration::days(30),
+            )
+            .await
+            .expect("Failed to create session");
+
         // Generate JWT token (valid for 24 hours)
         let token = threadkit_common::auth::create_token(
             user_id,
             self.site_id,
-            Uuid::now_v7(), // session_id
+            session_id,
             &"test_jwt_secret_for_testing".to_string(),
             24, // expiry_hours
         )
         .expect("Failed to create token");
 
-        (user_id, token)
+        (user_id, token, session_id)
+    }
+
+    /// Register a new page on the test site and return its ID
+    pub async fn create_page(&self) -> Uuid {
+        let page_url = format!("https://example.com/{}", Uuid::now_v7());
+        let page_id = RedisClient::generate_page_id(self.site_id, &page_url);
+        self.redis_client
+            .set_page_url(self.site_id, page_id, &page_url)
+            .await
+            .expect("Failed to register page");
+        page_id
     }
 }
 
@@ -258,6 +326,29 @@ impl TestWebSocketClient {
             .expect("Failed to send message");
     }
 
+    /// Send a JSON-RPC request and wait for its response
+    pub async fn request(&mut self, id: u64, method: &str, params: serde_json::Value) -> serde_json::Value {
+        let msg = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params
+        });
+
+        self.ws_stream
+            .send(Message::Text(msg.to_string()))
+            .await
+            .expect("Failed to send request");
+
+        // Skip notifications until the response arrives
+        loop {
+            let msg = self.recv_message().await;
+            if msg["id"] == id {
+                return msg;
+            }
+        }
+    }
+
     /// Receive the next message (with timeout)
     pub async fn recv_message(&mut self) -> serde_json::Value {
         let timeout = tokio::time::Duration::from_secs(5);
@@ -292,6 +383,15 @@ impl TestWebSocketClient {
             .await;
     }
 
+    /// Subscribe to a page, replaying events after `since_seq`
+    pub async fn subscribe_since(&mut self, page_id: Uuid, since_seq: u64) {
+        self.send_message(
+            "subscribe",
+            json!({ "page_id": page_id.to_string(), "since_seq": since_seq }),
+        )
+        .await;
+    }
+
     /// Unsubscribe from a page
     pub async fn unsubscribe(&mut self, page_id: Uuid) {
         self.send_message("unsubscribe", json!({ "page_id": page_id.to_string() }))
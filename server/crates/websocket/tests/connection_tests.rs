@@ -2,6 +2,9 @@ mod common;
 
 use common::TestContext;
 use futures_util::StreamExt;
+use serde_json::json;
+use threadkit_common::types::AuthEvent;
+use tokio_tungstenite::tungstenite::Message;
 use uuid::Uuid;
 
 #[tokio::test]
@@ -271,3 +274,94 @@ async fn test_message_metrics() {
 
     client.close().await;
 }
+
+#[tokio::test]
+async fn test_revoked_session_connects_anonymously() {
+    let ctx = TestContext::new().await;
+    let (_, token, session_id) = ctx.create_test_user_with_session().await;
+
+    ctx.redis_client
+        .delete_session(session_id)
+        .await
+        .expect("delete session");
+
+    // The token is still valid, but its session is gone
+    let mut client = ctx.connect_with_token(Some(token)).await;
+    let msg = client.recv_message().await;
+    assert_eq!(msg["method"], "connected");
+    assert!(msg["params"]["user_id"].is_null());
+
+    client.close().await;
+}
+
+#[tokio::test]
+async fn test_logout_closes_open_connection() {
+    let ctx = TestContext::new().await;
+    let (user_id, token, session_id) = ctx.create_test_user_with_session().await;
+
+    let mut client = ctx.connect_with_token(Some(token)).await;
+    let msg = client.recv_message().await;
+    assert_eq!(msg["params"]["user_id"], user_id.to_string());
+
+    // What the HTTP logout endpoint does
+    ctx.redis_client
+        .delete_session(session_id)
+        .await
+        .expect("delete session");
+    ctx.redis_client
+        .publish_auth_event(&AuthEvent::SessionRevoked { session_id, user_id })
+        .await
+        .expect("publish");
+
+    let msg = client.wait_for_method("signed_out").await;
+    assert_eq!(msg["params"]["reason"], "session_revoked");
+
+    // The server closes the socket afterwards
+    let timeout = tokio::time::Duration::from_secs(2);
+    loop {
+        match tokio::time::timeout(timeout, client.ws_stream.next()).await {
+            Ok(Some(Ok(Message::Close(_)))) | Ok(Some(Err(_))) | Ok(None) => break,
+            Ok(Some(Ok(_))) => {}
+            Err(_) => panic!("Connection still open after signed_out"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_ban_downgrades_open_connection() {
+    let ctx = TestContext::new_with_rate_limit(false).await;
+    let (user_id, token) = ctx.create_test_user().await;
+
+    let mut client = ctx.connect_with_token(Some(token)).await;
+    let msg = client.recv_message().await;
+    assert_eq!(msg["params"]["user_id"], user_id.to_string());
+
+    // What the HTTP ban endpoint does
+    ctx.redis_client
+        .block_user(ctx.site_id, user_id)
+        .await
+        .expect("block user");
+    ctx.redis_client
+        .publish_auth_event(&AuthEvent::UserBlocked { site_id: ctx.site_id, user_id })
+        .await
+        .expect("publish");
+
+    let msg = client.wait_for_method("signed_out").await;
+    assert_eq!(msg["params"]["reason"], "banned");
+
+    // Still connected, but writes are rejected as anonymous
+    let response = client
+        .request(
+            1,
+            "post_comment",
+            json!({ "page_url": "https://example.com/banned", "content": "Still here" }),
+        )
+        .await;
+    assert_eq!(response["error"]["code"], 401);
+
+    client.send_ping().await;
+    let msg = client.wait_for_method("pong").await;
+    assert_eq!(msg["method"], "pong");
+
+    client.close().await;
+}
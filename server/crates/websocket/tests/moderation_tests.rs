@@ -0,0 +1,116 @@
+mod common;
+
+use common::TestContext;
+use serde_json::json;
+use threadkit_common::types::ModerationMode;
+use uuid::Uuid;
+
+const PAGE_URL: &str = "https://example.com/moderation";
+
+#[tokio::test]
+async fn test_subscribe_moderation_requires_moderator() {
+    let ctx = TestContext::new().await;
+
+    // Anonymous
+    let mut client = ctx.connect().await;
+    let _ = client.recv_message().await;
+    client.send_message("subscribe_moderation", json!({})).await;
+    let msg = client.recv_message().await;
+    assert_eq!(msg["method"], "error");
+    assert_eq!(msg["params"]["code"], "forbidden");
+    client.close().await;
+
+    // Signed in, but not a moderator
+    let (_, token) = ctx.create_test_user().await;
+    let mut client = ctx.connect_with_token(Some(token)).await;
+    let _ = client.recv_message().await;
+    client.send_message("subscribe_moderation", json!({})).await;
+    let msg = client.recv_message().await;
+    assert_eq!(msg["method"], "error");
+    assert_eq!(msg["params"]["code"], "forbidden");
+    client.close().await;
+}
+
+#[tokio::test]
+async fn test_moderator_receives_site_events() {
+    let ctx = TestContext::new().await;
+    let (moderator_id, token) = ctx.create_test_user().await;
+    ctx.redis_client
+        .add_moderator(ctx.site_id, moderator_id)
+        .await
+        .expect("add moderator");
+
+    let mut client = ctx.connect_with_token(Some(token)).await;
+    let _ = client.recv_message().await;
+
+    client.send_message("subscribe_moderation", json!({})).await;
+    let msg = client.recv_message().await;
+    assert_eq!(msg["method"], "moderation_subscribed");
+    assert_eq!(msg["params"]["site_id"], ctx.site_id.to_string());
+
+    let banned_id = Uuid::now_v7();
+    ctx.redis_client
+        .publish_moderation_event(
+            ctx.site_id,
+            "user_banned",
+            json!({ "user_id": banned_id, "moderator_id": moderator_id, "shadowban": false }),
+        )
+        .await
+        .expect("publish");
+
+    let msg = client.wait_for_method("user_banned").await;
+    assert_eq!(msg["params"]["site_id"], ctx.site_id.to_string());
+    assert_eq!(msg["params"]["user_id"], banned_id.to_string());
+
+    // Other sites' events are not relayed
+    ctx.redis_client
+        .publish_moderation_event(Uuid::now_v7(), "user_banned", json!({ "user_id": banned_id }))
+        .await
+        .expect("publish");
+    let msg = client
+        .recv_message_timeout(tokio::time::Duration::from_millis(500))
+        .await;
+    assert!(msg.is_none(), "unexpected message: {:?}", msg);
+
+    client.close().await;
+}
+
+#[tokio::test]
+async fn test_pending_comment_pushed_to_moderators() {
+    let ctx = TestContext::new_with_rate_limit(false).await;
+
+    let mut site = ctx
+        .redis_client
+        .get_site_config(ctx.site_id)
+        .await
+        .expect("get site")
+        .expect("site exists");
+    site.settings.moderation_mode = ModerationMode::Pre;
+    ctx.redis_client.set_site_config(&site).await.expect("set site");
+
+    let (moderator_id, moderator_token) = ctx.create_test_user().await;
+    ctx.redis_client
+        .add_moderator(ctx.site_id, moderator_id)
+        .await
+        .expect("add moderator");
+    let (_, author_token) = ctx.create_test_user().await;
+
+    let mut moderator = ctx.connect_with_token(Some(moderator_token)).await;
+    let _ = moderator.recv_message().await;
+    moderator.send_message("subscribe_moderation", json!({})).await;
+    let _ = moderator.wait_for_method("moderation_subscribed").await;
+
+    let mut author = ctx.connect_with_token(Some(author_token)).await;
+    let _ = author.recv_message().await;
+    let response = author
+        .request(1, "post_comment", json!({ "page_url": PAGE_URL, "content": "Please review" }))
+        .await;
+    assert!(response["error"].is_null(), "unexpected error: {}", response);
+
+    let msg = moderator.wait_for_method("modqueue_added").await;
+    assert_eq!(msg["params"]["comment"]["t"], "Please review");
+    assert_eq!(msg["params"]["comment"]["s"], "pending");
+
+    author.close().await;
+    moderator.close().await;
+}
@@ -0,0 +1,198 @@
+mod common;
+
+use common::TestContext;
+use serde_json::json;
+use threadkit_common::redis::RedisClient;
+
+const PAGE_URL: &str = "https://example.com/chat";
+
+#[tokio::test]
+async fn test_post_comment_over_websocket() {
+    let ctx = TestContext::new().await;
+    let (user_id, token) = ctx.create_test_user().await;
+    let mut client = ctx.connect_with_token(Some(token)).await;
+    let _ = client.recv_message().await; // connected
+
+    let response = client
+        .request(1, "post_comment", json!({ "page_url": PAGE_URL, "content": "Hello from the socket" }))
+        .await;
+
+    assert_eq!(response["jsonrpc"], "2.0");
+    assert!(response["error"].is_null(), "unexpected error: {}", response);
+    assert_eq!(response["result"]["comment"]["t"], "Hello from the socket");
+    assert_eq!(response["result"]["comment"]["a"], user_id.to_string());
+
+    // Saved to the page tree like an HTTP post
+    let page_id = RedisClient::generate_page_id(ctx.site_id, PAGE_URL);
+    let tree = ctx
+        .redis_client
+        .get_page_tree(page_id)
+        .await
+        .expect("get tree")
+        .expect("tree exists");
+    assert_eq!(tree.comments.len(), 1);
+
+    client.close().await;
+}
+
+#[tokio::test]
+async fn test_anonymous_post_rejected_when_disabled() {
+    let ctx = TestContext::new().await;
+    let mut client = ctx.connect().await;
+    let _ = client.recv_message().await;
+
+    let response = client
+        .request(
+            1,
+            "post_comment",
+            json!({ "page_url": PAGE_URL, "content": "Hi", "author_name": "Guest" }),
+        )
+        .await;
+
+    assert!(response["result"].is_null());
+    assert_eq!(response["error"]["code"], 401);
+
+    client.close().await;
+}
+
+#[tokio::test]
+async fn test_vote_edit_and_delete_over_websocket() {
+    let ctx = TestContext::new_with_rate_limit(false).await;
+    let (_, author_token) = ctx.create_test_user().await;
+    let (_, voter_token) = ctx.create_test_user().await;
+
+    let mut author = ctx.connect_with_token(Some(author_token)).await;
+    let _ = author.recv_message().await;
+    let mut voter = ctx.connect_with_token(Some(voter_token)).await;
+    let _ = voter.recv_message().await;
+
+    let response = author
+        .request(1, "post_comment", json!({ "page_url": PAGE_URL, "content": "Original" }))
+        .await;
+    let comment_id = response["result"]["comment"]["i"].as_str().unwrap().to_string();
+    let path = json!([comment_id]);
+
+    let response = voter
+        .request(
+            1,
+            "vote",
+            json!({ "comment_id": comment_id, "page_url": PAGE_URL, "direction": "up", "path": path }),
+        )
+        .await;
+    assert_eq!(response["result"]["upvotes"], 1);
+    assert_eq!(response["result"]["user_vote"], "up");
+
+    // Only the author (or a moderator) can edit
+    let response = voter
+        .request(
+            2,
+            "edit_comment",
+            json!({ "comment_id": comment_id, "page_url": PAGE_URL, "content": "Hijacked", "path": path }),
+        )
+        .await;
+    assert_eq!(response["error"]["code"], 403);
+
+    let response = author
+        .request(
+            2,
+            "edit_comment",
+            json!({ "comment_id": comment_id, "page_url": PAGE_URL, "content": "Edited", "path": path }),
+        )
+        .await;
+    assert_eq!(response["result"]["comment"]["t"], "Edited");
+
+    let response = author
+        .request(
+            3,
+            "delete_comment",
+            json!({ "comment_id": comment_id, "page_url": PAGE_URL, "path": path }),
+        )
+        .await;
+    assert_eq!(response["result"]["deleted"], true);
+
+    author.close().await;
+    voter.close().await;
+}
+
+#[tokio::test]
+async fn test_request_with_invalid_params() {
+    let ctx = TestContext::new().await;
+    let (_, token) = ctx.create_test_user().await;
+    let mut client = ctx.connect_with_token(Some(token)).await;
+    let _ = client.recv_message().await;
+
+    let response = client
+        .request(9, "vote", json!({ "page_url": PAGE_URL, "direction": "up" }))
+        .await;
+
+    assert_eq!(response["id"], 9);
+    assert_eq!(response["error"]["code"], -32602);
+
+    client.close().await;
+}
+
+#[tokio::test]
+async fn test_mention_sends_notification_event() {
+    let ctx = TestContext::new_with_rate_limit(false).await;
+    let (mentioned_id, mentioned_token) = ctx.create_test_user().await;
+    let (author_id, author_token) = ctx.create_test_user().await;
+
+    let mentioned = ctx.redis_client.get_user(mentioned_id).await.unwrap().unwrap();
+    ctx.redis_client
+        .set_user_username_index(&mentioned.name, mentioned_id)
+        .await
+        .unwrap();
+
+    let mut listener = ctx.connect_with_token(Some(mentioned_token)).await;
+    let _ = listener.recv_message().await;
+    let mut author = ctx.connect_with_token(Some(author_token)).await;
+    let _ = author.recv_message().await;
+
+    let content = format!("Hey @{}, look at this", mentioned.name);
+    let response = author
+        .request(1, "post_comment", json!({ "page_url": PAGE_URL, "content": content }))
+        .await;
+    let comment_id = response["result"]["comment"]["i"].clone();
+    assert!(response["result"]["comment"]["h"].as_str().unwrap().contains("threadkit-mention"));
+
+    let msg = listener.wait_for_method("notification").await;
+    assert_eq!(msg["params"]["type"], "mention");
+    assert_eq!(msg["params"]["comment_id"], comment_id);
+    assert_eq!(msg["params"]["from_user"]["id"], author_id.to_string());
+    assert_eq!(msg["params"]["page_url"], PAGE_URL);
+
+    listener.close().await;
+    author.close().await;
+}
+
+#[tokio::test]
+async fn test_writes_count_against_http_rate_limits() {
+    let ctx = TestContext::new().await;
+
+    // Two writes per minute per user on this site
+    let mut site = ctx.redis_client.get_site_config(ctx.site_id).await.unwrap().unwrap();
+    site.settings.rate_limits.user_writes_per_minute = Some(2);
+    ctx.redis_client
+        .update_site_settings(ctx.site_id, &site.settings)
+        .await
+        .unwrap();
+
+    let (_, token) = ctx.create_test_user().await;
+    let mut client = ctx.connect_with_token(Some(token)).await;
+    let _ = client.recv_message().await;
+
+    for id in 1..=2 {
+        let response = client
+            .request(id, "post_comment", json!({ "page_url": PAGE_URL, "content": "Within limits" }))
+            .await;
+        assert!(response["error"].is_null(), "unexpected error: {}", response);
+    }
+
+    let response = client
+        .request(3, "post_comment", json!({ "page_url": PAGE_URL, "content": "One too many" }))
+        .await;
+    assert!(response["result"].is_null());
+    assert_eq!(response["error"]["code"], 429);
+
+    client.close().await;
+}
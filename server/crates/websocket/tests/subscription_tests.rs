@@ -3,6 +3,7 @@ mod common;
 use common::TestContext;
 use futures_util::StreamExt;
 use serde_json::json;
+use threadkit_common::messages::ServerMessage;
 use uuid::Uuid;
 
 #[tokio::test]
@@ -14,7 +15,7 @@ async fn test_subscribe_to_page() {
     let _ = client.recv_message().await;
 
     // Subscribe to a page
-    let page_id = Uuid::now_v7();
+    let page_id = ctx.create_page().await;
     client.subscribe(page_id).await;
 
     // Should receive presence message with empty user list
@@ -41,7 +42,7 @@ async fn test_subscribe_with_presence() {
     assert_eq!(msg["params"]["user_id"], user_id.to_string());
 
     // Subscribe to a page
-    let page_id = Uuid::now_v7();
+    let page_id = ctx.create_page().await;
     client.subscribe(page_id).await;
 
     // Should receive presence message with the user in it
@@ -70,7 +71,7 @@ async fn test_multiple_users_presence() {
     let _ = client1.recv_message().await;
     let _ = client2.recv_message().await;
 
-    let page_id = Uuid::now_v7();
+    let page_id = ctx.create_page().await;
 
     // Client 1 subscribes first
     client1.subscribe(page_id).await;
@@ -126,7 +127,7 @@ async fn test_unsubscribe_from_page() {
     let mut client = ctx.connect_with_token(Some(token)).await;
     let _ = client.recv_message().await;
 
-    let page_id = Uuid::now_v7();
+    let page_id = ctx.create_page().await;
 
     // Subscribe
     client.subscribe(page_id).await;
@@ -157,7 +158,7 @@ async fn test_user_left_notification() {
     let _ = client1.recv_message().await;
     let _ = client2.recv_message().await;
 
-    let page_id = Uuid::now_v7();
+    let page_id = ctx.create_page().await;
 
     // Both subscribe
     client1.subscribe(page_id).await;
@@ -189,13 +190,13 @@ async fn test_max_subscriptions_limit() {
 
     // Subscribe to 10 pages (the default max)
     for _ in 0..10 {
-        let page_id = Uuid::now_v7();
+        let page_id = ctx.create_page().await;
         client.subscribe(page_id).await;
         let _ = client.recv_message().await; // presence
     }
 
     // Try to subscribe to 11th page
-    let page_id = Uuid::now_v7();
+    let page_id = ctx.create_page().await;
     client.subscribe(page_id).await;
 
     // Should receive error
@@ -210,13 +211,41 @@ async fn test_max_subscriptions_limit() {
     client.close().await;
 }
 
+#[tokio::test]
+async fn test_subscribe_to_unknown_page() {
+    let ctx = TestContext::new().await;
+    let mut client = ctx.connect().await;
+    let _ = client.recv_message().await;
+
+    // A page that was never registered
+    client.subscribe(Uuid::now_v7()).await;
+    let msg = client.recv_message().await;
+    assert_eq!(msg["method"], "error");
+    assert_eq!(msg["params"]["code"], "not_found");
+
+    // A page that belongs to another site
+    let other_site = Uuid::now_v7();
+    let page_url = "https://other.example.com/page";
+    let page_id = threadkit_common::redis::RedisClient::generate_page_id(other_site, page_url);
+    ctx.redis_client
+        .set_page_url(other_site, page_id, page_url)
+        .await
+        .expect("register page");
+    client.subscribe(page_id).await;
+    let msg = client.recv_message().await;
+    assert_eq!(msg["method"], "error");
+    assert_eq!(msg["params"]["code"], "not_found");
+
+    client.close().await;
+}
+
 #[tokio::test]
 async fn test_duplicate_subscription() {
     let ctx = TestContext::new().await;
     let mut client = ctx.connect().await;
     let _ = client.recv_message().await;
 
-    let page_id = Uuid::now_v7();
+    let page_id = ctx.create_page().await;
 
     // Subscribe once
     client.subscribe(page_id).await;
@@ -240,9 +269,9 @@ async fn test_subscribe_multiple_pages() {
     let mut client = ctx.connect().await;
     let _ = client.recv_message().await;
 
-    let page_id_1 = Uuid::now_v7();
-    let page_id_2 = Uuid::now_v7();
-    let page_id_3 = Uuid::now_v7();
+    let page_id_1 = ctx.create_page().await;
+    let page_id_2 = ctx.create_page().await;
+    let page_id_3 = ctx.create_page().await;
 
     // Subscribe to multiple pages
     client.subscribe(page_id_1).await;
@@ -273,7 +302,7 @@ async fn test_presence_cleanup_on_disconnect() {
     let _ = client1.recv_message().await;
     let _ = client2.recv_message().await;
 
-    let page_id = Uuid::now_v7();
+    let page_id = ctx.create_page().await;
 
     // Both subscribe
     client1.subscribe(page_id).await;
@@ -311,7 +340,7 @@ async fn test_typing_indicator() {
     let _ = client1.recv_message().await;
     let _ = client2.recv_message().await;
 
-    let page_id = Uuid::now_v7();
+    let page_id = ctx.create_page().await;
 
     // Both subscribe
     client1.subscribe(page_id).await;
@@ -366,7 +395,7 @@ async fn test_typing_indicator_with_reply_to() {
     let _ = client1.recv_message().await;
     let _ = client2.recv_message().await;
 
-    let page_id = Uuid::now_v7();
+    let page_id = ctx.create_page().await;
     let comment_id = Uuid::now_v7();
 
     // Both subscribe
@@ -419,7 +448,7 @@ async fn test_typing_debounce() {
     let _ = client1.recv_message().await;
     let _ = client2.recv_message().await;
 
-    let page_id = Uuid::now_v7();
+    let page_id = ctx.create_page().await;
 
     // Both subscribe
     client1.subscribe(page_id).await;
@@ -482,7 +511,7 @@ async fn test_unauthenticated_typing_ignored() {
     let mut client = ctx.connect().await;
     let _ = client.recv_message().await;
 
-    let page_id = Uuid::now_v7();
+    let page_id = ctx.create_page().await;
 
     // Subscribe
     client.subscribe(page_id).await;
@@ -508,8 +537,8 @@ async fn test_typing_only_on_subscribed_page() {
     let mut client = ctx.connect_with_token(Some(token)).await;
     let _ = client.recv_message().await;
 
-    let page_id_1 = Uuid::now_v7();
-    let page_id_2 = Uuid::now_v7();
+    let page_id_1 = ctx.create_page().await;
+    let page_id_2 = ctx.create_page().await;
 
     // Subscribe to page 1 only
     client.subscribe(page_id_1).await;
@@ -531,3 +560,196 @@ async fn test_typing_only_on_subscribed_page() {
 
     client.close().await;
 }
+
+#[tokio::test]
+async fn test_presence_includes_event_seq() {
+    let ctx = TestContext::new().await;
+    let page_id = ctx.create_page().await;
+
+    for upvotes in 1..=3 {
+        ctx.redis_client
+            .publish_page_event(
+                page_id,
+                "vote_update",
+                json!({ "comment_id": Uuid::now_v7(), "upvotes": upvotes, "downvotes": 0 }),
+            )
+            .await
+            .expect("publish");
+    }
+
+    let mut client = ctx.connect().await;
+    let _ = client.recv_message().await;
+
+    client.subscribe(page_id).await;
+    let msg = client.recv_message().await;
+    assert_eq!(msg["method"], "presence");
+    assert_eq!(msg["params"]["seq"], 3);
+
+    client.close().await;
+}
+
+#[tokio::test]
+async fn test_subscribe_replays_missed_events() {
+    let ctx = TestContext::new().await;
+    let page_id = ctx.create_page().await;
+    let comment_id = Uuid::now_v7();
+
+    for upvotes in 1..=3 {
+        ctx.redis_client
+            .publish_page_event(
+                page_id,
+                "vote_update",
+                json!({ "comment_id": comment_id, "upvotes": upvotes, "downvotes": 0 }),
+            )
+            .await
+            .expect("publish");
+    }
+
+    let mut client = ctx.connect().await;
+    let _ = client.recv_message().await;
+
+    // The client last saw seq 1, so events 2 and 3 are replayed before presence
+    client.subscribe_since(page_id, 1).await;
+
+    let msg = client.recv_message().await;
+    assert_eq!(msg["method"], "vote_update");
+    assert_eq!(msg["params"]["seq"], 2);
+    assert_eq!(msg["params"]["upvotes"], 2);
+
+    let msg = client.recv_message().await;
+    assert_eq!(msg["method"], "vote_update");
+    assert_eq!(msg["params"]["seq"], 3);
+
+    let msg = client.recv_message().await;
+    assert_eq!(msg["method"], "presence");
+    assert_eq!(msg["params"]["seq"], 3);
+
+    // Live events continue the sequence
+    ctx.redis_client
+        .publish_page_event(
+            page_id,
+            "vote_update",
+            json!({ "comment_id": comment_id, "upvotes": 4, "downvotes": 0 }),
+        )
+        .await
+        .expect("publish");
+
+    let msg = client.wait_for_method("vote_update").await;
+    assert_eq!(msg["params"]["seq"], 4);
+
+    client.close().await;
+}
+
+#[tokio::test]
+async fn test_subscribe_resync_required_when_events_trimmed() {
+    let ctx = TestContext::new().await;
+    let page_id = ctx.create_page().await;
+    let comment_id = Uuid::now_v7();
+
+    // Publish past the event log's cap so the oldest events are trimmed
+    for upvotes in 1..=1200 {
+        ctx.redis_client
+            .publish_page_event(
+                page_id,
+                "vote_update",
+                json!({ "comment_id": comment_id, "upvotes": upvotes, "downvotes": 0 }),
+            )
+            .await
+            .expect("publish");
+    }
+
+    let mut client = ctx.connect().await;
+    let _ = client.recv_message().await;
+
+    client.subscribe_since(page_id, 1).await;
+
+    let msg = client.recv_message().await;
+    assert_eq!(msg["method"], "resync_required");
+    assert_eq!(msg["params"]["page_id"], page_id.to_string());
+    assert_eq!(msg["params"]["seq"], 1200);
+
+    let msg = client.recv_message().await;
+    assert_eq!(msg["method"], "presence");
+
+    client.close().await;
+}
+
+#[tokio::test]
+async fn test_subscribe_resync_required_when_ahead_of_page() {
+    let ctx = TestContext::new().await;
+    let page_id = ctx.create_page().await;
+    let comment_id = Uuid::now_v7();
+
+    // The page's sequence restarted (e.g. its event log expired) below the client's
+    ctx.redis_client
+        .publish_page_event(
+            page_id,
+            "vote_update",
+            json!({ "comment_id": comment_id, "upvotes": 1, "downvotes": 0 }),
+        )
+        .await
+        .expect("publish");
+
+    let mut client = ctx.connect().await;
+    let _ = client.recv_message().await;
+
+    client.subscribe_since(page_id, 40).await;
+
+    let msg = client.recv_message().await;
+    assert_eq!(msg["method"], "resync_required");
+    assert_eq!(msg["params"]["seq"], 1);
+
+    let msg = client.recv_message().await;
+    assert_eq!(msg["method"], "presence");
+
+    // Live events aren't mistaken for ones already replayed
+    ctx.redis_client
+        .publish_page_event(
+            page_id,
+            "vote_update",
+            json!({ "comment_id": comment_id, "upvotes": 2, "downvotes": 0 }),
+        )
+        .await
+        .expect("publish");
+
+    let msg = client.wait_for_method("vote_update").await;
+    assert_eq!(msg["params"]["seq"], 2);
+
+    client.close().await;
+}
+
+#[tokio::test]
+async fn test_lagged_subscription_requires_resync() {
+    let ctx = TestContext::new().await;
+    let page_id = ctx.create_page().await;
+    let comment_id = Uuid::now_v7();
+
+    let mut client = ctx.connect().await;
+    let _ = client.recv_message().await;
+    client.subscribe(page_id).await;
+    let _ = client.wait_for_method("presence").await;
+
+    ctx.redis_client
+        .publish_page_event(
+            page_id,
+            "vote_update",
+            json!({ "comment_id": comment_id, "upvotes": 1, "downvotes": 0 }),
+        )
+        .await
+        .expect("publish");
+    let msg = client.wait_for_method("vote_update").await;
+    assert_eq!(msg["params"]["seq"], 1);
+
+    // Overflow the page's channel before the connection gets a chance to drain it
+    let tx = ctx.ws_state.get_or_create_channel(page_id);
+    for upvotes in 2..=1500 {
+        let _ = tx.send(ServerMessage::vote_update(page_id, comment_id, upvotes, 0).with_seq(upvotes as u64));
+    }
+
+    let msg = client.recv_message().await;
+    assert_eq!(msg["method"], "resync_required");
+    assert_eq!(msg["params"]["page_id"], page_id.to_string());
+    assert_eq!(msg["params"]["seq"], 1);
+
+    client.close().await;
+}
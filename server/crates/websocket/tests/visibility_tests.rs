@@ -0,0 +1,123 @@
+mod common;
+
+use common::TestContext;
+use serde_json::json;
+use threadkit_common::redis::RedisClient;
+use threadkit_common::types::ModerationMode;
+
+const PAGE_URL: &str = "https://example.com/visibility";
+
+#[tokio::test]
+async fn test_blocked_author_comments_not_relayed() {
+    let ctx = TestContext::new_with_rate_limit(false).await;
+    let page_id = RedisClient::generate_page_id(ctx.site_id, PAGE_URL);
+    ctx.redis_client
+        .set_page_url(ctx.site_id, page_id, PAGE_URL)
+        .await
+        .expect("register page");
+
+    let (author_id, author_token) = ctx.create_test_user().await;
+    let (blocker_id, blocker_token) = ctx.create_test_user().await;
+    let (_, reader_token) = ctx.create_test_user().await;
+
+    ctx.redis_client
+        .block_user_by_user(blocker_id, author_id)
+        .await
+        .expect("block user");
+
+    let mut author = ctx.connect_with_token(Some(author_token)).await;
+    let _ = author.recv_message().await;
+    let mut blocker = ctx.connect_with_token(Some(blocker_token)).await;
+    let _ = blocker.recv_message().await;
+    let mut reader = ctx.connect_with_token(Some(reader_token)).await;
+    let _ = reader.recv_message().await;
+
+    blocker.subscribe(page_id).await;
+    let _ = blocker.wait_for_method("presence").await;
+    reader.subscribe(page_id).await;
+    let _ = reader.wait_for_method("presence").await;
+
+    let response = author
+        .request(1, "post_comment", json!({ "page_url": PAGE_URL, "content": "Hello" }))
+        .await;
+    assert!(response["error"].is_null(), "unexpected error: {}", response);
+
+    let msg = reader.wait_for_method("new_comment").await;
+    assert_eq!(msg["params"]["comment"]["a"], author_id.to_string());
+
+    // The blocker gets nothing from the author: no comment, no presence
+    while let Some(msg) = blocker
+        .recv_message_timeout(tokio::time::Duration::from_millis(500))
+        .await
+    {
+        assert_ne!(msg["method"], "new_comment", "blocked author's comment relayed");
+        assert_ne!(msg["method"], "user_joined", "blocked author's presence relayed");
+    }
+
+    author.close().await;
+    blocker.close().await;
+    reader.close().await;
+}
+
+#[tokio::test]
+async fn test_pending_comments_only_relayed_to_author_and_moderators() {
+    let ctx = TestContext::new_with_rate_limit(false).await;
+    let page_id = RedisClient::generate_page_id(ctx.site_id, PAGE_URL);
+    ctx.redis_client
+        .set_page_url(ctx.site_id, page_id, PAGE_URL)
+        .await
+        .expect("register page");
+
+    // Pre-moderation: every new comment starts pending
+    let mut site = ctx
+        .redis_client
+        .get_site_config(ctx.site_id)
+        .await
+        .expect("get site")
+        .expect("site exists");
+    site.settings.moderation_mode = ModerationMode::Pre;
+    ctx.redis_client.set_site_config(&site).await.expect("set site");
+
+    let (_, author_token) = ctx.create_test_user().await;
+    let (moderator_id, moderator_token) = ctx.create_test_user().await;
+    let (_, reader_token) = ctx.create_test_user().await;
+    ctx.redis_client
+        .add_moderator(ctx.site_id, moderator_id)
+        .await
+        .expect("add moderator");
+
+    let mut author = ctx.connect_with_token(Some(author_token)).await;
+    let _ = author.recv_message().await;
+    let mut moderator = ctx.connect_with_token(Some(moderator_token)).await;
+    let _ = moderator.recv_message().await;
+    let mut reader = ctx.connect_with_token(Some(reader_token)).await;
+    let _ = reader.recv_message().await;
+
+    author.subscribe(page_id).await;
+    let _ = author.wait_for_method("presence").await;
+    moderator.subscribe(page_id).await;
+    let _ = moderator.wait_for_method("presence").await;
+    reader.subscribe(page_id).await;
+    let _ = reader.wait_for_method("presence").await;
+
+    let response = author
+        .request(1, "post_comment", json!({ "page_url": PAGE_URL, "content": "Awaiting review" }))
+        .await;
+    assert!(response["error"].is_null(), "unexpected error: {}", response);
+
+    let msg = author.wait_for_method("new_comment").await;
+    assert_eq!(msg["params"]["comment"]["t"], "Awaiting review");
+    let msg = moderator.wait_for_method("new_comment").await;
+    assert_eq!(msg["params"]["comment"]["t"], "Awaiting review");
+
+    while let Some(msg) = reader
+        .recv_message_timeout(tokio::time::Duration::from_millis(500))
+        .await
+    {
+        assert_ne!(msg["method"], "new_comment", "pending comment relayed to reader");
+    }
+
+    author.close().await;
+    moderator.close().await;
+    reader.close().await;
+}
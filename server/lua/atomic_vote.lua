@@ -2,15 +2,20 @@
 -- This script handles vote transitions and updates the comment tree in a single atomic operation
 --
 -- KEYS[1]: vote_key (votes:{user_id}:{page_id})
--- KEYS[2]: tree_key (page:{page_id})
+-- KEYS[2]: tree_key (page:{page_id}:tree)
+-- KEYS[3]: version_key (page:{page_id}:tree:version)
+-- KEYS[4]: vote_pages_key (user:{user_id}:vote_pages)
 -- ARGV[1]: comment_id (UUID)
 -- ARGV[2]: new_direction ("1" for up, "-1" for down)
 -- ARGV[3]: path_json (JSON array of UUIDs in path)
+-- ARGV[4]: page_id (UUID)
 --
 -- Returns: {final_vote, upvotes, downvotes, upvote_delta, downvote_delta}
 
 local vote_key = KEYS[1]
 local tree_key = KEYS[2]
+local version_key = KEYS[3]
+local vote_pages_key = KEYS[4]
 local comment_id = ARGV[1]
 local new_direction = ARGV[2]
 local path_json = ARGV[3]
@@ -107,10 +112,12 @@ tree.u = tonumber(redis.call('TIME')[1])
 
 -- Save updated tree
 redis.call('SET', tree_key, cjson.encode(tree))
+redis.call('INCR', version_key)
 
 -- Update vote
 if final_vote then
     redis.call('HSET', vote_key, comment_id, final_vote)
+    redis.call('SADD', vote_pages_key, ARGV[4])
 else
     redis.call('HDEL', vote_key, comment_id)
 end
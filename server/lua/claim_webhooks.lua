@@ -0,0 +1,22 @@
+-- Atomically claim due webhook deliveries from the retry queue
+-- Claimed deliveries are pushed forward to the lease expiry instead of being removed,
+-- so a delivery is picked up again if the worker dies before finishing it.
+--
+-- KEYS[1]: queue_key (webhooks:queue)
+-- ARGV[1]: now (unix millis)
+-- ARGV[2]: lease_until (unix millis)
+-- ARGV[3]: max number of deliveries to claim
+--
+-- Returns: array of claimed delivery IDs
+
+local queue_key = KEYS[1]
+local now = ARGV[1]
+local lease_until = ARGV[2]
+local limit = tonumber(ARGV[3])
+
+local due = redis.call('ZRANGEBYSCORE', queue_key, '-inf', now, 'LIMIT', 0, limit)
+for _, id in ipairs(due) do
+    redis.call('ZADD', queue_key, 'XX', lease_until, id)
+end
+
+return due
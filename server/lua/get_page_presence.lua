@@ -0,0 +1,32 @@
+-- Get the users present on a page, ignoring entries from WebSocket nodes
+-- that stopped sending heartbeats (they are cleaned up by reap_ws_nodes)
+--
+-- KEYS[1]: presence_key (page:{page_id}:presence, members "{user_id}:{node_id}")
+-- KEYS[2]: nodes_key (ws:nodes, node_id -> last heartbeat in unix millis)
+-- ARGV[1]: cutoff (unix millis; nodes with an older heartbeat are dead)
+--
+-- Returns: array of unique user IDs
+
+local presence_key = KEYS[1]
+local nodes_key = KEYS[2]
+local cutoff = tonumber(ARGV[1])
+
+local live = {}
+local seen = {}
+local users = {}
+
+for _, member in ipairs(redis.call('SMEMBERS', presence_key)) do
+    local user_id, node_id = string.match(member, '^([^:]+):([^:]+)$')
+    if user_id then
+        if live[node_id] == nil then
+            local heartbeat = redis.call('ZSCORE', nodes_key, node_id)
+            live[node_id] = heartbeat ~= false and tonumber(heartbeat) >= cutoff
+        end
+        if live[node_id] and not seen[user_id] then
+            seen[user_id] = true
+            table.insert(users, user_id)
+        end
+    end
+end
+
+return users
@@ -0,0 +1,39 @@
+-- Assign the next sequence number to a page event, append it to the page's
+-- capped event log and publish it to WebSocket servers
+--
+-- KEYS[1]: seq_key (page:{page_id}:event_seq)
+-- KEYS[2]: log_key (page:{page_id}:event_log)
+-- ARGV[1]: channel (threadkit:page:{page_id}:events)
+-- ARGV[2]: event_type
+-- ARGV[3]: page_id
+-- ARGV[4]: data_json
+-- ARGV[5]: max_len (approximate number of events kept in the log)
+-- ARGV[6]: ttl (seconds the log is kept after the last event)
+--
+-- Returns: the event's sequence number
+
+local seq_key = KEYS[1]
+local log_key = KEYS[2]
+local channel = ARGV[1]
+local event_type = ARGV[2]
+local page_id = ARGV[3]
+local data_json = ARGV[4]
+local max_len = ARGV[5]
+local ttl = tonumber(ARGV[6])
+
+local seq = redis.call('INCR', seq_key)
+
+-- Build the message by hand so data_json is passed through untouched
+-- (a cjson round trip would turn empty arrays into objects)
+local message = '{"type":' .. cjson.encode(event_type)
+    .. ',"page_id":' .. cjson.encode(page_id)
+    .. ',"seq":' .. seq
+    .. ',"data":' .. data_json .. '}'
+
+-- Stream IDs are the sequence numbers so replay can range from any seq
+redis.call('XADD', log_key, 'MAXLEN', '~', max_len, seq .. '-0', 'event', message)
+redis.call('EXPIRE', log_key, ttl)
+
+redis.call('PUBLISH', channel, message)
+
+return seq
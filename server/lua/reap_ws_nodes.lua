@@ -0,0 +1,54 @@
+-- Remove the presence entries, connection counts and metrics of WebSocket
+-- nodes that stopped sending heartbeats, and tell the remaining nodes that
+-- their users left
+--
+-- Safe to run from every node at once: each dead node is reaped exactly once.
+--
+-- KEYS[1]: nodes_key (ws:nodes, node_id -> last heartbeat in unix millis)
+-- ARGV[1]: cutoff (unix millis; nodes with an older heartbeat are dead)
+--
+-- Returns: array of reaped node IDs
+
+local nodes_key = KEYS[1]
+local cutoff = tonumber(ARGV[1])
+
+local dead = redis.call('ZRANGEBYSCORE', nodes_key, '-inf', '(' .. cutoff)
+
+-- Whether a user is still on a page through another live node
+local function still_present(presence_key, user_id)
+    local prefix = user_id .. ':'
+    for _, member in ipairs(redis.call('SMEMBERS', presence_key)) do
+        if string.sub(member, 1, #prefix) == prefix then
+            local node_id = string.sub(member, #prefix + 1)
+            local heartbeat = redis.call('ZSCORE', nodes_key, node_id)
+            if heartbeat and tonumber(heartbeat) >= cutoff then
+                return true
+            end
+        end
+    end
+    return false
+end
+
+for _, node_id in ipairs(dead) do
+    local node_presence_key = 'ws:node:' .. node_id .. ':presence'
+
+    -- Entries are "{page_id}:{user_id}"
+    for _, entry in ipairs(redis.call('SMEMBERS', node_presence_key)) do
+        local page_id, user_id = string.match(entry, '^([^:]+):([^:]+)$')
+        if page_id then
+            local presence_key = 'page:' .. page_id .. ':presence'
+            redis.call('SREM', presence_key, user_id .. ':' .. node_id)
+
+            if not still_present(presence_key, user_id) then
+                local message = '{"type":"user_left","page_id":' .. cjson.encode(page_id)
+                    .. ',"data":{"user_id":' .. cjson.encode(user_id) .. '}}'
+                redis.call('PUBLISH', 'threadkit:page:' .. page_id .. ':events', message)
+            end
+        end
+    end
+
+    redis.call('DEL', node_presence_key, 'ws:node:' .. node_id .. ':connections', 'ws:node:' .. node_id .. ':metrics')
+    redis.call('ZREM', nodes_key, node_id)
+end
+
+return dead
@@ -0,0 +1,25 @@
+-- Raise the peak concurrent WebSocket connections in sites' analytics buckets
+--
+-- KEYS[2i-1]: hour stats key of site i (site:{site_id}:stats:hour:{YYYYMMDDHH})
+-- KEYS[2i]: day stats key of site i (site:{site_id}:stats:day:{YYYYMMDD})
+-- ARGV[1]: TTL of hour buckets (seconds)
+-- ARGV[2]: TTL of day buckets (seconds)
+-- ARGV[2+i]: current connections of site i
+--
+-- Returns: empty array
+
+local ttls = { tonumber(ARGV[1]), tonumber(ARGV[2]) }
+
+for i = 1, #KEYS / 2 do
+    local connections = tonumber(ARGV[2 + i])
+    for j = 1, 2 do
+        local key = KEYS[2 * (i - 1) + j]
+        local peak = tonumber(redis.call('HGET', key, 'peak_connections') or '0')
+        if connections > peak then
+            redis.call('HSET', key, 'peak_connections', connections)
+        end
+        redis.call('EXPIRE', key, ttls[j])
+    end
+end
+
+return {}
@@ -0,0 +1,56 @@
+-- Atomically exchange a session's refresh token for a new one
+--
+-- KEYS[1]: session_key (session:{session_id})
+-- KEYS[2]: used_key (session:{session_id}:refresh_used)
+-- ARGV[1]: hash of the presented token
+-- ARGV[2]: hash of the new token
+-- ARGV[3]: now (unix seconds)
+-- ARGV[4]: expiry of the new token (unix seconds)
+-- ARGV[5]: now (RFC 3339), recorded as the session's last use
+-- ARGV[6]: grace period for the previous token (seconds)
+-- ARGV[7]: when the session and its used tokens expire (unix seconds)
+--
+-- Returns: {status, user_id} where status is rotated, superseded, reused, expired or invalid
+
+local session_key = KEYS[1]
+local used_key = KEYS[2]
+local token_hash = ARGV[1]
+local now = tonumber(ARGV[3])
+
+local fields = redis.call('HMGET', session_key, 'user_id', 'refresh_hash', 'refresh_expires_at', 'previous_hash', 'rotated_at')
+local user_id, current_hash, expires_at = fields[1], fields[2], fields[3]
+local previous_hash, rotated_at = fields[4], fields[5]
+
+if not user_id then
+    return {'invalid', ''}
+end
+
+if current_hash ~= token_hash then
+    -- The token was exchanged moments ago, most likely by a concurrent refresh from the same client
+    if token_hash == previous_hash and now - tonumber(rotated_at or '0') < tonumber(ARGV[6]) then
+        return {'superseded', user_id}
+    end
+    -- A rotated-out token coming back means two parties hold the session's tokens
+    if redis.call('SISMEMBER', used_key, token_hash) == 1 then
+        return {'reused', user_id}
+    end
+    return {'invalid', ''}
+end
+
+if tonumber(expires_at or '0') <= now then
+    return {'expired', ''}
+end
+
+redis.call('HSET', session_key,
+    'refresh_hash', ARGV[2],
+    'refresh_expires_at', ARGV[4],
+    'previous_hash', token_hash,
+    'rotated_at', ARGV[3],
+    'last_used', ARGV[5])
+redis.call('SADD', used_key, token_hash)
+
+-- Once the new token expires nothing can refresh the session, so neither needs to outlive it
+redis.call('EXPIREAT', session_key, ARGV[7])
+redis.call('EXPIREAT', used_key, ARGV[7])
+
+return {'rotated', user_id}
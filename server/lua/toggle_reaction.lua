@@ -0,0 +1,102 @@
+-- Atomically toggle an emoji reaction and update the comment's reaction counts
+-- Same navigation and tree handling as atomic_vote.lua
+--
+-- KEYS[1]: reaction_key (reactions:{user_id}:{page_id})
+-- KEYS[2]: tree_key (page:{page_id}:tree)
+-- KEYS[3]: version_key (page:{page_id}:tree:version)
+-- KEYS[4]: reaction_pages_key (user:{user_id}:reaction_pages)
+-- ARGV[1]: comment_id (UUID)
+-- ARGV[2]: emoji
+-- ARGV[3]: path_json (JSON array of UUIDs in path)
+-- ARGV[4]: page_id (UUID)
+--
+-- Returns: {added (1 or 0), reactions_json (emoji -> count for the comment)}
+
+local reaction_key = KEYS[1]
+local tree_key = KEYS[2]
+local version_key = KEYS[3]
+local reaction_pages_key = KEYS[4]
+local comment_id = ARGV[1]
+local emoji = ARGV[2]
+local path_json = ARGV[3]
+
+-- Reactions are stored per user and page as "{comment_id}:{emoji}" fields
+local field = comment_id .. ":" .. emoji
+local existing = redis.call('HEXISTS', reaction_key, field) == 1
+
+-- Get tree
+local tree_json = redis.call('GET', tree_key)
+if not tree_json then
+    return redis.error_reply("Page not found")
+end
+
+local tree = cjson.decode(tree_json)
+
+-- Navigate to comment using path (tree.c for roots, comment.r for replies)
+local comment = nil
+local path = cjson.decode(path_json)
+
+for i, pid in ipairs(path) do
+    local found = false
+    local search_array = nil
+
+    if i == 1 then
+        search_array = tree.c
+    else
+        search_array = comment and comment.r
+    end
+
+    if search_array then
+        for j, child in ipairs(search_array) do
+            if child.i == pid then
+                comment = child
+                found = true
+                break
+            end
+        end
+    end
+
+    if not found then
+        return redis.error_reply("Comment not found in path")
+    end
+end
+
+if not comment then
+    return redis.error_reply("Comment not found")
+end
+
+-- Update counts (z = emoji -> count)
+local reactions = comment.z or {}
+local count = reactions[emoji] or 0
+if existing then
+    count = math.max(0, count - 1)
+else
+    count = count + 1
+end
+
+if count > 0 then
+    reactions[emoji] = count
+else
+    reactions[emoji] = nil
+end
+
+if next(reactions) == nil then
+    comment.z = nil
+else
+    comment.z = reactions
+end
+
+-- Update tree timestamp (u=updated_at)
+tree.u = tonumber(redis.call('TIME')[1])
+
+redis.call('SET', tree_key, cjson.encode(tree))
+redis.call('INCR', version_key)
+
+if existing then
+    redis.call('HDEL', reaction_key, field)
+else
+    redis.call('HSET', reaction_key, field, "1")
+    redis.call('SADD', reaction_pages_key, ARGV[4])
+end
+
+return {existing and 0 or 1, cjson.encode(comment.z or {})}
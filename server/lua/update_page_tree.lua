@@ -0,0 +1,23 @@
+-- Compare-and-set a page tree using its version counter
+-- The caller reads the tree and version, mutates the tree, and submits it back.
+-- The write only succeeds if nobody else wrote the tree in the meantime.
+--
+-- KEYS[1]: tree_key (page:{page_id}:tree)
+-- KEYS[2]: version_key (page:{page_id}:tree:version)
+-- ARGV[1]: expected version ("0" if the tree has never been written)
+-- ARGV[2]: tree_json (new tree)
+--
+-- Returns: new version on success, -1 if the version changed (caller should retry)
+
+local tree_key = KEYS[1]
+local version_key = KEYS[2]
+local expected_version = ARGV[1]
+local tree_json = ARGV[2]
+
+local current_version = redis.call('GET', version_key) or "0"
+if current_version ~= expected_version then
+    return -1
+end
+
+redis.call('SET', tree_key, tree_json)
+return redis.call('INCR', version_key)